@@ -0,0 +1,509 @@
+use crate::{
+    layers::{
+        auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta},
+        eap_layer::SessionKeys,
+        mux::TupleElement,
+        MethodType,
+    },
+    message::MessageCode,
+    util::{constant_time_eq, OwnedSlice},
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Identity,
+    Challenge,
+    Reauthentication,
+    Done,
+}
+
+/// Server side of EAP-AKA, or EAP-AKA' with [`Self::with_aka_prime`].
+///
+/// Vectors come from the [`AuthenticationCentre`], pseudonyms and
+/// re-authentication identities are kept in the [`IdentityStore`].
+pub struct AuthAkaMethod<A, S> {
+    variant: Variant,
+    network_name: OwnedSlice<64>,
+    auc: A,
+    store: S,
+    pseudonyms: bool,
+    fast_reauth: bool,
+    state: State,
+    /// AT_*_ID_REQ of the last AKA-Identity request
+    id_req: u8,
+    checkcode: Checkcode,
+    /// Identity of the last AT_IDENTITY, the keys are bound to it
+    identity: Identity,
+    permanent_identity: Identity,
+    vector: Option<AuthenticationVector>,
+    resynchronized: bool,
+    keys: Option<Keys>,
+    next_pseudonym: Option<Identity>,
+    next_reauth_id: Option<Identity>,
+    /// Context of a re-authentication, with the counter that was sent
+    reauth: Option<ReauthContext>,
+    nonce_s: [u8; NONCE_LEN],
+    /// AT_MAC of the Re-authentication Request, part of the Session-ID
+    reauth_mac: [u8; MAC_LEN],
+    session_keys: Option<SessionKeys>,
+}
+
+impl<A, S> TupleElement for AuthAkaMethod<A, S>
+where
+    A: AuthenticationCentre + 'static,
+    S: IdentityStore + 'static,
+{
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<A, S> AuthAkaMethod<A, S>
+where
+    A: AuthenticationCentre,
+    S: IdentityStore,
+{
+    pub fn new(auc: A, store: S) -> Self {
+        Self {
+            variant: Variant::Aka,
+            network_name: OwnedSlice::new(),
+            auc,
+            store,
+            pseudonyms: false,
+            fast_reauth: false,
+            state: State::Identity,
+            id_req: AT_PERMANENT_ID_REQ,
+            checkcode: Checkcode::new(Variant::Aka),
+            identity: Identity::new(),
+            permanent_identity: Identity::new(),
+            vector: None,
+            resynchronized: false,
+            keys: None,
+            next_pseudonym: None,
+            next_reauth_id: None,
+            reauth: None,
+            nonce_s: [0; NONCE_LEN],
+            reauth_mac: [0; MAC_LEN],
+            session_keys: None,
+        }
+    }
+
+    /// Runs EAP-AKA' (type 50) instead, the keys are bound to the access network
+    /// name, e.g. "WLAN"
+    pub fn with_aka_prime(mut self, network_name: &[u8]) -> Self {
+        self.variant = Variant::AkaPrime;
+        self.network_name = network_name
+            .try_into()
+            .expect("network name too long for nostd");
+        self.checkcode = Checkcode::new(self.variant);
+        self
+    }
+
+    /// Sends a new pseudonym with every full authentication
+    pub fn with_pseudonyms(mut self) -> Self {
+        self.pseudonyms = true;
+        self
+    }
+
+    /// Sends a new re-authentication identity with every authentication
+    pub fn with_fast_reauth(mut self) -> Self {
+        self.fast_reauth = true;
+        self
+    }
+
+    fn identity_request(&self) -> Writer {
+        let mut writer = Writer::new(SUBTYPE_IDENTITY);
+        writer.reserved(self.id_req, &[]);
+        writer
+    }
+
+    fn new_identity(&self, prefix: u8, env: &dyn EapEnvironment) -> Identity {
+        let mut random = [0u8; 8];
+        env.fill_random(&mut random);
+        temporary_identity(prefix, self.permanent_identity.as_ref(), &random)
+    }
+
+    fn recv_identity<'a>(
+        &mut self,
+        attributes: &Attributes,
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        // The request had the identifier of its response
+        let message = &meta.message;
+        let request = self.identity_request();
+        let method_type = [self.variant.method_type()];
+        self.checkcode.update(&[
+            &packet_header(
+                MessageCode::Request as u8,
+                message.identifier,
+                1 + request.as_slice().len(),
+            ),
+            &method_type,
+            request.as_slice(),
+        ]);
+        self.checkcode.update(&[
+            &packet_header(message.code as u8, message.identifier, message.body.len()),
+            message.body,
+        ]);
+
+        let Some(identity) = attributes
+            .identity
+            .filter(|identity| !identity.is_empty() && identity.len() <= MAX_IDENTITY_LEN)
+        else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+        self.identity = Identity::from(identity);
+
+        let identifier = message.identifier.wrapping_add(1);
+        let prefixes = self.variant.prefixes();
+        match identity[0] {
+            prefix if prefix == prefixes.reauth => {
+                let context = self
+                    .store
+                    .load_reauth(identity)
+                    .filter(|_| self.fast_reauth && self.id_req == AT_ANY_ID_REQ);
+                if let Some(context) = context {
+                    return self.reauthentication_request(context, identifier, env);
+                }
+            }
+            prefix if prefix == prefixes.pseudonym => {
+                let permanent_identity = self
+                    .store
+                    .permanent_identity(identity)
+                    .filter(|_| self.pseudonyms && self.id_req != AT_PERMANENT_ID_REQ);
+                if let Some(permanent_identity) = permanent_identity {
+                    self.permanent_identity = permanent_identity;
+                    return self.challenge_request(identifier, env);
+                }
+            }
+            prefix if prefix == prefixes.permanent => {
+                self.permanent_identity = self.identity.clone();
+                return self.challenge_request(identifier, env);
+            }
+            _ => {}
+        }
+
+        // Unknown identity, ask for one that is less private
+        self.id_req = match self.id_req {
+            AT_ANY_ID_REQ => AT_FULLAUTH_ID_REQ,
+            AT_FULLAUTH_ID_REQ => AT_PERMANENT_ID_REQ,
+            _ => return AuthMethodLayerResult::Failed(env),
+        };
+        AuthMethodLayerResult::Send(env.respond().write(self.identity_request().as_slice()))
+    }
+
+    fn challenge_request<'a>(
+        &mut self,
+        identifier: u8,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let mut rand = [0u8; RAND_LEN];
+        env.fill_random(&mut rand);
+
+        let amf = match self.variant {
+            Variant::Aka => [0, 0],
+            Variant::AkaPrime => [AMF_SEPARATION_BIT, 0],
+        };
+        let imsi = &username(self.permanent_identity.as_ref())[1..];
+        let Some(vector) = self.auc.vector(imsi, &rand, amf) else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+
+        let (ck, ik) = match self.variant {
+            Variant::Aka => (vector.ck, vector.ik),
+            Variant::AkaPrime => derive_ck_ik_prime(
+                &vector.ck,
+                &vector.ik,
+                self.network_name.as_ref(),
+                &vector.autn,
+            ),
+        };
+        let keys = self
+            .variant
+            .keys(self.identity.as_ref(), &ck, &ik, &vector.rand, &vector.autn);
+
+        let mut writer = Writer::new(SUBTYPE_CHALLENGE);
+        writer
+            .reserved(AT_RAND, &vector.rand)
+            .reserved(AT_AUTN, &vector.autn);
+        if self.variant == Variant::AkaPrime {
+            writer
+                .u16(AT_KDF, KDF_AKA_PRIME)
+                .prefixed(AT_KDF_INPUT, self.network_name.as_ref());
+        }
+        writer.reserved(AT_CHECKCODE, self.checkcode.value().as_ref());
+
+        let prefixes = self.variant.prefixes();
+        self.next_pseudonym = self
+            .pseudonyms
+            .then(|| self.new_identity(prefixes.pseudonym, env));
+        self.next_reauth_id = self
+            .fast_reauth
+            .then(|| self.new_identity(prefixes.reauth, env));
+
+        let mut plaintext = Writer::nested();
+        if let Some(pseudonym) = &self.next_pseudonym {
+            plaintext.prefixed(AT_NEXT_PSEUDONYM, pseudonym.as_ref());
+        }
+        if let Some(reauth_id) = &self.next_reauth_id {
+            plaintext.prefixed(AT_NEXT_REAUTH_ID, reauth_id.as_ref());
+        }
+        if !plaintext.as_slice().is_empty() {
+            let mut iv = [0u8; 16];
+            env.fill_random(&mut iv);
+            writer.encrypted(&keys.k_encr, &iv, plaintext);
+        }
+
+        writer.mac().sign(
+            self.variant.mac_algorithm(),
+            &keys.k_aut,
+            MessageCode::Request,
+            identifier,
+            self.variant.method_type(),
+            &[],
+        );
+
+        self.vector = Some(vector);
+        self.keys = Some(keys);
+        self.state = State::Challenge;
+        AuthMethodLayerResult::Send(env.respond().write(writer.as_slice()))
+    }
+
+    fn recv_challenge<'a>(
+        &mut self,
+        attributes: &Attributes,
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let (Some(keys), Some(vector)) = (&self.keys, &self.vector) else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+
+        let authentic =
+            self.variant
+                .mac_algorithm()
+                .verify(&keys.k_aut, &meta.message, attributes, &[])
+                && attributes
+                    .res
+                    .is_some_and(|res| constant_time_eq(res, vector.xres.as_ref()))
+                && attributes.checkcode == Some(self.checkcode.value().as_ref());
+        if !authentic {
+            return AuthMethodLayerResult::Failed(env);
+        }
+
+        let permanent_identity = self.permanent_identity.as_ref();
+        if let Some(pseudonym) = &self.next_pseudonym {
+            self.store
+                .store_pseudonym(pseudonym.as_ref(), permanent_identity);
+        }
+        if let Some(reauth_id) = &self.next_reauth_id {
+            let context = ReauthContext::new(reauth_id.as_ref(), permanent_identity, keys);
+            self.store.store_reauth(&context);
+        }
+
+        env.set_name(permanent_identity);
+        self.session_keys = Some(keys.session_keys.clone());
+        self.state = State::Done;
+        AuthMethodLayerResult::Finished(env)
+    }
+
+    fn recv_synchronization_failure<'a>(
+        &mut self,
+        attributes: &Attributes,
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let (Some(vector), Some(auts)) = (&self.vector, attributes.auts) else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+
+        // Only one resynchronization per conversation
+        let imsi = &username(self.permanent_identity.as_ref())[1..];
+        if self.resynchronized || !self.auc.resynchronize(imsi, &vector.rand, auts) {
+            return AuthMethodLayerResult::Failed(env);
+        }
+
+        self.resynchronized = true;
+        self.challenge_request(meta.message.identifier.wrapping_add(1), env)
+    }
+
+    fn reauthentication_request<'a>(
+        &mut self,
+        mut context: ReauthContext,
+        identifier: u8,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        context.counter = context.counter.wrapping_add(1);
+        self.permanent_identity = context.permanent_identity.clone();
+        env.fill_random(&mut self.nonce_s);
+
+        self.next_reauth_id = Some(self.new_identity(self.variant.prefixes().reauth, env));
+        let mut plaintext = Writer::nested();
+        plaintext
+            .u16(AT_COUNTER, context.counter)
+            .reserved(AT_NONCE_S, &self.nonce_s)
+            .prefixed(
+                AT_NEXT_REAUTH_ID,
+                self.next_reauth_id.as_ref().unwrap().as_ref(),
+            );
+
+        let mut iv = [0u8; 16];
+        env.fill_random(&mut iv);
+
+        let mut writer = Writer::new(SUBTYPE_REAUTHENTICATION);
+        writer
+            .encrypted(&context.k_encr, &iv, plaintext)
+            .reserved(AT_CHECKCODE, self.checkcode.value().as_ref())
+            .mac()
+            .sign(
+                self.variant.mac_algorithm(),
+                &context.k_aut,
+                MessageCode::Request,
+                identifier,
+                self.variant.method_type(),
+                &[],
+            );
+
+        self.reauth_mac = writer.mac_value();
+        self.reauth = Some(context);
+        self.state = State::Reauthentication;
+        AuthMethodLayerResult::Send(env.respond().write(writer.as_slice()))
+    }
+
+    fn recv_reauthentication<'a>(
+        &mut self,
+        attributes: &Attributes,
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let Some(context) = self.reauth.take() else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+
+        // The response is authenticated together with NONCE_S
+        let authentic = self.variant.mac_algorithm().verify(
+            &context.k_aut,
+            &meta.message,
+            attributes,
+            &self.nonce_s,
+        ) && attributes.checkcode == Some(self.checkcode.value().as_ref());
+        let decrypted = Decrypted::new(&context.k_encr, attributes);
+        let Some(encrypted) = decrypted.as_ref().and_then(Decrypted::attributes) else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+        if !authentic || encrypted.counter != Some(context.counter) {
+            return AuthMethodLayerResult::Failed(env);
+        }
+
+        self.store.remove_reauth(context.identity());
+        if encrypted.counter_too_small {
+            // The peer saw the counter before, fall back to full authentication
+            return self.challenge_request(meta.message.identifier.wrapping_add(1), env);
+        }
+
+        let session_keys = self.variant.reauth_session_keys(
+            self.identity.as_ref(),
+            context.counter,
+            &self.nonce_s,
+            &self.reauth_mac,
+            &context.reauth_key,
+        );
+
+        let context = ReauthContext {
+            identity: self.next_reauth_id.take().unwrap(),
+            ..context
+        };
+        self.store.store_reauth(&context);
+
+        env.set_name(context.permanent_identity.as_ref());
+        self.session_keys = Some(session_keys);
+        self.state = State::Done;
+        AuthMethodLayerResult::Finished(env)
+    }
+}
+
+impl<A, S> AuthMethodLayer for AuthAkaMethod<A, S>
+where
+    A: AuthenticationCentre,
+    S: IdentityStore,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(self.variant.method_type())
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_AKA
+    }
+
+    // AT_MAC covers the header of the next Request
+    fn binds_identifier(&self) -> bool {
+        true
+    }
+
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.state = State::Identity;
+        self.id_req = if self.fast_reauth {
+            AT_ANY_ID_REQ
+        } else if self.pseudonyms {
+            AT_FULLAUTH_ID_REQ
+        } else {
+            AT_PERMANENT_ID_REQ
+        };
+        self.checkcode = Checkcode::new(self.variant);
+        self.vector = None;
+        self.resynchronized = false;
+        self.keys = None;
+        self.reauth = None;
+        self.session_keys = None;
+
+        AuthMethodLayerResult::Send(env.respond().write(self.identity_request().as_slice()))
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let Some(attributes) = msg.get(3..).and_then(|data| Attributes::parse(data, 3)) else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+
+        match (self.state, msg[0]) {
+            (State::Identity, SUBTYPE_IDENTITY) => self.recv_identity(&attributes, meta, env),
+            (State::Challenge, SUBTYPE_CHALLENGE) => self.recv_challenge(&attributes, meta, env),
+            (State::Challenge, SUBTYPE_SYNCHRONIZATION_FAILURE) => {
+                self.recv_synchronization_failure(&attributes, meta, env)
+            }
+            (State::Reauthentication, SUBTYPE_REAUTHENTICATION) => {
+                self.recv_reauthentication(&attributes, meta, env)
+            }
+            // Also covers Authentication-Reject and Client-Error
+            _ => {
+                self.state = State::Done;
+                AuthMethodLayerResult::Failed(env)
+            }
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        match self.state {
+            State::Done => self.session_keys.as_ref(),
+            _ => None,
+        }
+    }
+}
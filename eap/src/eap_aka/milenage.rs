@@ -0,0 +1,358 @@
+//! Milenage, 3GPP TS 35.206, and a USIM and authentication centre built on it
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes128,
+};
+
+use crate::util::{constant_time_eq, OwnedSlice};
+
+use super::*;
+
+/// Highest sequence number the USIM accepts ahead of the last one, a simplified
+/// version of the freshness check of 3GPP TS 33.102 C.2
+const SQN_DELTA: u64 = 1 << 28;
+const SQN_MAX: u64 = (1 << 48) - 1;
+
+fn sqn_bytes(sqn: u64) -> [u8; 6] {
+    sqn.to_be_bytes()[2..].try_into().unwrap()
+}
+
+fn sqn_value(bytes: &[u8; 6]) -> u64 {
+    bytes.iter().fold(0, |sqn, &b| sqn << 8 | b as u64)
+}
+
+fn xor<const N: usize>(mut a: [u8; N], b: &[u8]) -> [u8; N] {
+    a.iter_mut().zip(b).for_each(|(a, b)| *a ^= b);
+    a
+}
+
+/// The Milenage algorithm set with the subscriber key K and OPc
+#[derive(Clone)]
+pub struct Milenage {
+    cipher: Aes128,
+    opc: Block,
+}
+
+impl Milenage {
+    /// Derives OPc from the operator variant configuration field OP
+    pub fn new(k: &Block, op: &Block) -> Self {
+        let cipher = Aes128::new(GenericArray::from_slice(k));
+        let mut opc = GenericArray::clone_from_slice(op);
+        cipher.encrypt_block(&mut opc);
+        let opc = xor(opc.into(), op);
+        Self { cipher, opc }
+    }
+
+    pub fn with_opc(k: &Block, opc: &Block) -> Self {
+        Self {
+            cipher: Aes128::new(GenericArray::from_slice(k)),
+            opc: *opc,
+        }
+    }
+
+    pub fn opc(&self) -> &Block {
+        &self.opc
+    }
+
+    fn encrypt(&self, block: Block) -> Block {
+        let mut block = GenericArray::from(block);
+        self.cipher.encrypt_block(&mut block);
+        block.into()
+    }
+
+    fn temp(&self, rand: &Block) -> Block {
+        self.encrypt(xor(*rand, &self.opc))
+    }
+
+    /// OUT2 to OUT5: E_K(rot(TEMP xor OPc, r) xor c) xor OPc
+    fn out(&self, temp: &Block, rotation: usize, constant: u8) -> Block {
+        let input = xor(*temp, &self.opc);
+        let mut block: Block = core::array::from_fn(|i| input[(i + rotation / 8) % 16]);
+        block[15] ^= constant;
+        xor(self.encrypt(block), &self.opc)
+    }
+
+    /// Network authentication code MAC-A (f1) and resynchronization code MAC-S (f1*)
+    pub fn f1(&self, rand: &Block, sqn: &[u8; 6], amf: &[u8; 2]) -> ([u8; 8], [u8; 8]) {
+        let temp = self.temp(rand);
+
+        let mut in1 = [0u8; 16];
+        for half in in1.chunks_mut(8) {
+            half[..6].copy_from_slice(sqn);
+            half[6..].copy_from_slice(amf);
+        }
+
+        // OUT1 = E_K(TEMP xor rot(IN1 xor OPc, r1) xor c1) xor OPc, r1 = 64, c1 = 0
+        let input = xor(in1, &self.opc);
+        let rotated: Block = core::array::from_fn(|i| input[(i + 8) % 16]);
+        let out1 = xor(self.encrypt(xor(temp, &rotated)), &self.opc);
+
+        (out1[..8].try_into().unwrap(), out1[8..].try_into().unwrap())
+    }
+
+    /// RES (f2), CK (f3), IK (f4) and the anonymity key AK (f5)
+    pub fn f2345(&self, rand: &Block) -> ([u8; 8], Block, Block, [u8; 6]) {
+        let temp = self.temp(rand);
+        let out2 = self.out(&temp, 0, 1);
+        let ck = self.out(&temp, 32, 2);
+        let ik = self.out(&temp, 64, 4);
+
+        (
+            out2[8..].try_into().unwrap(),
+            ck,
+            ik,
+            out2[..6].try_into().unwrap(),
+        )
+    }
+
+    /// Anonymity key for resynchronization (f5*)
+    pub fn f5_star(&self, rand: &Block) -> [u8; 6] {
+        let out5 = self.out(&self.temp(rand), 96, 8);
+        out5[..6].try_into().unwrap()
+    }
+
+    /// SRES and Kc of GSM-Milenage, 3GPP TS 55.205. These are A3 and A8 of the
+    /// GSM security context, derived with the conversion functions c2 and c3.
+    pub fn gsm(&self, rand: &Block) -> ([u8; 4], [u8; 8]) {
+        let (res, ck, ik, _) = self.f2345(rand);
+        let sres = core::array::from_fn(|i| res[i] ^ res[i + 4]);
+        let kc = core::array::from_fn(|i| ck[i] ^ ck[i + 8] ^ ik[i] ^ ik[i + 8]);
+        (sres, kc)
+    }
+}
+
+/// A USIM in software, e.g. for tests without a card reader
+#[derive(Clone)]
+pub struct SoftUsim {
+    imsi: OwnedSlice<16>,
+    milenage: Milenage,
+    /// Highest accepted sequence number
+    sqn: u64,
+    identities: PeerIdentities,
+}
+
+impl SoftUsim {
+    pub fn new(imsi: &[u8], milenage: Milenage) -> Self {
+        Self {
+            imsi: imsi.try_into().expect("IMSI has at most 15 digits"),
+            milenage,
+            sqn: 0,
+            identities: PeerIdentities::default(),
+        }
+    }
+
+    /// Starts at the given highest accepted sequence number
+    pub fn with_sqn(mut self, sqn: u64) -> Self {
+        self.sqn = sqn & SQN_MAX;
+        self
+    }
+
+    pub fn sqn(&self) -> u64 {
+        self.sqn
+    }
+}
+
+impl PeerIdentityStore for SoftUsim {
+    fn load_identities(&mut self) -> PeerIdentities {
+        self.identities.clone()
+    }
+
+    fn store_identities(&mut self, identities: &PeerIdentities) {
+        self.identities = identities.clone();
+    }
+}
+
+impl Usim for SoftUsim {
+    fn imsi(&self) -> &[u8] {
+        self.imsi.as_ref()
+    }
+
+    fn authenticate(
+        &mut self,
+        rand: &[u8; RAND_LEN],
+        autn: &[u8; AUTN_LEN],
+    ) -> Result<UsimResponse, UsimError> {
+        // AUTN = SQN xor AK | AMF | MAC-A
+        let (res, ck, ik, ak) = self.milenage.f2345(rand);
+        let sqn = xor(autn[..6].try_into().unwrap(), &ak);
+        let amf = autn[6..8].try_into().unwrap();
+
+        let (mac_a, _) = self.milenage.f1(rand, &sqn, &amf);
+        if !constant_time_eq(&mac_a, &autn[8..]) {
+            return Err(UsimError::MacFailure);
+        }
+
+        let sqn = sqn_value(&sqn);
+        if sqn <= self.sqn || sqn - self.sqn > SQN_DELTA {
+            // AUTS = SQN_MS xor AK* | MAC-S, the AMF of MAC-S is zero
+            let sqn_ms = sqn_bytes(self.sqn);
+            let (_, mac_s) = self.milenage.f1(rand, &sqn_ms, &[0, 0]);
+
+            let mut auts = [0u8; AUTS_LEN];
+            auts[..6].copy_from_slice(&xor(sqn_ms, &self.milenage.f5_star(rand)));
+            auts[6..].copy_from_slice(&mac_s);
+            return Err(UsimError::SyncFailure(auts));
+        }
+
+        self.sqn = sqn;
+        Ok(UsimResponse {
+            res: OwnedSlice::from(&res),
+            ck,
+            ik,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone)]
+struct Subscriber {
+    milenage: Milenage,
+    /// Sequence number of the last vector
+    sqn: u64,
+}
+
+/// An authentication centre in software, subscribers are looked up by IMSI
+#[cfg(feature = "std")]
+#[derive(Clone, Default)]
+pub struct SoftAuc {
+    subscribers: std::collections::HashMap<Vec<u8>, Subscriber>,
+}
+
+#[cfg(feature = "std")]
+impl SoftAuc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_subscriber(mut self, imsi: &[u8], milenage: Milenage) -> Self {
+        self.subscribers
+            .insert(imsi.to_vec(), Subscriber { milenage, sqn: 0 });
+        self
+    }
+
+    pub fn sqn(&self, imsi: &[u8]) -> Option<u64> {
+        self.subscribers.get(imsi).map(|subscriber| subscriber.sqn)
+    }
+}
+
+#[cfg(feature = "std")]
+impl AuthenticationCentre for SoftAuc {
+    fn vector(
+        &mut self,
+        imsi: &[u8],
+        rand: &[u8; RAND_LEN],
+        amf: [u8; 2],
+    ) -> Option<AuthenticationVector> {
+        let subscriber = self.subscribers.get_mut(imsi)?;
+        subscriber.sqn = (subscriber.sqn + 1) & SQN_MAX;
+        let sqn = sqn_bytes(subscriber.sqn);
+
+        let milenage = &subscriber.milenage;
+        let (mac_a, _) = milenage.f1(rand, &sqn, &amf);
+        let (xres, ck, ik, ak) = milenage.f2345(rand);
+
+        let mut autn = [0u8; AUTN_LEN];
+        autn[..6].copy_from_slice(&xor(sqn, &ak));
+        autn[6..8].copy_from_slice(&amf);
+        autn[8..].copy_from_slice(&mac_a);
+
+        Some(AuthenticationVector {
+            rand: *rand,
+            xres: OwnedSlice::from(&xres),
+            ck,
+            ik,
+            autn,
+        })
+    }
+
+    fn resynchronize(&mut self, imsi: &[u8], rand: &[u8; RAND_LEN], auts: &[u8; AUTS_LEN]) -> bool {
+        let Some(subscriber) = self.subscribers.get_mut(imsi) else {
+            return false;
+        };
+
+        let milenage = &subscriber.milenage;
+        let sqn_ms = xor(auts[..6].try_into().unwrap(), &milenage.f5_star(rand));
+        let (_, mac_s) = milenage.f1(rand, &sqn_ms, &[0, 0]);
+        if !constant_time_eq(&mac_s, &auts[6..]) {
+            return false;
+        }
+
+        subscriber.sqn = sqn_value(&sqn_ms);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex_to_vec;
+
+    fn array<const N: usize>(hex: &str) -> [u8; N] {
+        hex_to_vec(hex).try_into().unwrap()
+    }
+
+    #[test]
+    fn milenage_test_set_1() {
+        // 3GPP TS 35.207 4.3, Test Set 1
+        let k = array("46 5b 5c e8 b1 99 b4 9f aa 5f 0a 2e e2 38 a6 bc");
+        let op = array("cd c2 02 d5 12 3e 20 f6 2b 6d 67 6a c7 2c b3 18");
+        let rand = array("23 55 3c be 96 37 a8 9d 21 8a e6 4d ae 47 bf 35");
+        let sqn = array("ff 9b b4 d0 b6 07");
+        let amf = array("b9 b9");
+
+        let milenage = Milenage::new(&k, &op);
+        assert_eq!(
+            milenage.opc(),
+            &array("cd 63 cb 71 95 4a 9f 4e 48 a5 99 4e 37 a0 2b af")
+        );
+
+        let (mac_a, mac_s) = milenage.f1(&rand, &sqn, &amf);
+        assert_eq!(mac_a, array("4a 9f fa c3 54 df af b3"));
+        assert_eq!(mac_s, array("01 cf af 9e c4 e8 71 e9"));
+
+        let (res, ck, ik, ak) = milenage.f2345(&rand);
+        assert_eq!(res, array("a5 42 11 d5 e3 ba 50 bf"));
+        assert_eq!(ck, array("b4 0b a9 a3 c5 8b 2a 05 bb f0 d9 87 b2 1b f8 cb"));
+        assert_eq!(ik, array("f7 69 bc d7 51 04 46 04 12 76 72 71 1c 6d 34 41"));
+        assert_eq!(ak, array("aa 68 9c 64 83 70"));
+        assert_eq!(milenage.f5_star(&rand), array("45 1e 8b ec a4 3b"));
+
+        // 3GPP TS 55.205 4.3, Test Set 1
+        let (sres, kc) = milenage.gsm(&rand);
+        assert_eq!(sres, array("46 f8 41 6a"));
+        assert_eq!(kc, array("ea e4 be 82 3a f9 a0 8b"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn usim_resynchronization() {
+        let milenage = Milenage::with_opc(&[1; 16], &[2; 16]);
+        let mut auc = SoftAuc::new().with_subscriber(b"001010000000001", milenage.clone());
+        let mut usim = SoftUsim::new(b"001010000000001", milenage).with_sqn(100);
+
+        let rand = [3; RAND_LEN];
+        let vector = auc.vector(b"001010000000001", &rand, [0, 0]).unwrap();
+        let Err(UsimError::SyncFailure(auts)) = usim.authenticate(&rand, &vector.autn) else {
+            panic!("stale sequence number was accepted");
+        };
+        assert!(auc.resynchronize(b"001010000000001", &rand, &auts));
+        assert_eq!(auc.sqn(b"001010000000001"), Some(100));
+
+        let vector = auc.vector(b"001010000000001", &rand, [0, 0]).unwrap();
+        let response = usim.authenticate(&rand, &vector.autn).unwrap();
+        assert_eq!(response.res, vector.xres);
+        assert_eq!((response.ck, response.ik), (vector.ck, vector.ik));
+        assert_eq!(usim.sqn(), 101);
+
+        // Replayed vector
+        assert!(matches!(
+            usim.authenticate(&rand, &vector.autn),
+            Err(UsimError::SyncFailure(_))
+        ));
+
+        let mut autn = vector.autn;
+        autn[15] ^= 1;
+        assert_eq!(usim.authenticate(&rand, &autn), Err(UsimError::MacFailure));
+    }
+}
@@ -0,0 +1,369 @@
+//! EAP-AKA and EAP-AKA', see https://www.rfc-editor.org/rfc/rfc4187 and
+//! https://www.rfc-editor.org/rfc/rfc5448
+//!
+//! The server always starts with an AKA-Identity request, so every request that
+//! carries AT_MAC answers a response and its identifier is known. AT_CHECKCODE
+//! protects the identity exchange of the challenge and re-authentication.
+//! Credentials come from a [`Usim`] on the peer and an [`AuthenticationCentre`]
+//! on the server, [`SoftUsim`] and [`SoftAuc`] implement both in software with
+//! Milenage.
+
+mod auth;
+mod milenage;
+mod peer;
+
+pub use auth::AuthAkaMethod;
+#[cfg(feature = "std")]
+pub use milenage::SoftAuc;
+pub use milenage::{Milenage, SoftUsim};
+pub use peer::PeerAkaMethod;
+
+#[cfg(feature = "std")]
+pub use crate::sim_aka::MemoryIdentityStore;
+pub use crate::sim_aka::{
+    IdentityStore, PeerIdentities, PeerIdentityStore, ReauthContext, MAX_IDENTITY_LEN,
+};
+
+use hmac::{Hmac, Mac};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::{
+    layers::{eap_layer::SessionKeys, SecurityClaims},
+    sim_aka::*,
+    util::OwnedSlice,
+};
+
+const METHOD_AKA: u8 = 23;
+const METHOD_AKA_PRIME: u8 = 50;
+
+const SUBTYPE_CHALLENGE: u8 = 1;
+const SUBTYPE_AUTHENTICATION_REJECT: u8 = 2;
+const SUBTYPE_SYNCHRONIZATION_FAILURE: u8 = 4;
+const SUBTYPE_IDENTITY: u8 = 5;
+
+/// The only key derivation function of EAP-AKA', RFC 5448 3.2
+const KDF_AKA_PRIME: u16 = 1;
+
+/// Set in the AMF of vectors for EAP-AKA', 3GPP TS 33.402 6.1
+const AMF_SEPARATION_BIT: u8 = 0x80;
+
+pub const RAND_LEN: usize = 16;
+pub const AUTN_LEN: usize = 16;
+pub const AUTS_LEN: usize = 14;
+
+type Checksum = OwnedSlice<32>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Variant {
+    Aka,
+    AkaPrime,
+}
+
+impl Variant {
+    fn method_type(self) -> u8 {
+        match self {
+            Self::Aka => METHOD_AKA,
+            Self::AkaPrime => METHOD_AKA_PRIME,
+        }
+    }
+
+    fn mac_algorithm(self) -> MacAlgorithm {
+        match self {
+            Self::Aka => MacAlgorithm::HmacSha1,
+            Self::AkaPrime => MacAlgorithm::HmacSha256,
+        }
+    }
+
+    /// RFC 4187 4.1.1.6 and RFC 5448 3.1, the temporary identity prefixes are
+    /// the ones commonly used
+    fn prefixes(self) -> IdentityPrefixes {
+        match self {
+            Self::Aka => IdentityPrefixes {
+                permanent: b'0',
+                pseudonym: b'2',
+                reauth: b'4',
+            },
+            Self::AkaPrime => IdentityPrefixes {
+                permanent: b'6',
+                pseudonym: b'7',
+                reauth: b'8',
+            },
+        }
+    }
+
+    /// Keys of a full authentication, `ck` and `ik` are CK' and IK' for
+    /// EAP-AKA'. The Session-ID is the Type, RAND and AUTN, RFC 5247
+    /// Appendix A and RFC 5448 3.
+    fn keys(self, identity: &[u8], ck: &Block, ik: &Block, rand: &Block, autn: &Block) -> Keys {
+        let session_id = SessionKeys::session_id(self.method_type(), &[rand, autn]);
+        match self {
+            Self::Aka => {
+                // MK = SHA1(Identity|IK|CK)
+                let mk: [u8; MK_LEN] = Sha1::new()
+                    .chain_update(identity)
+                    .chain_update(ik)
+                    .chain_update(ck)
+                    .finalize()
+                    .into();
+                Keys::from_master_key(&mk, session_id)
+            }
+            Self::AkaPrime => {
+                // MK = PRF'(IK'|CK', "EAP-AKA'"|Identity)
+                let mut key = [0u8; 32];
+                key[..16].copy_from_slice(ik);
+                key[16..].copy_from_slice(ck);
+                let mut mk = [0u8; 208];
+                prf_prime(&key, &[b"EAP-AKA'", identity], &mut mk);
+
+                Keys {
+                    k_encr: mk[..16].try_into().unwrap(),
+                    k_aut: mk[16..48].try_into().unwrap(),
+                    reauth_key: mk[48..80].try_into().unwrap(),
+                    session_keys: SessionKeys {
+                        msk: mk[80..144].try_into().unwrap(),
+                        emsk: mk[144..208].try_into().unwrap(),
+                        session_id,
+                    },
+                }
+            }
+        }
+    }
+
+    /// `mac` is AT_MAC of the Re-authentication Request
+    fn reauth_session_keys(
+        self,
+        identity: &[u8],
+        counter: u16,
+        nonce_s: &[u8; NONCE_LEN],
+        mac: &[u8; MAC_LEN],
+        reauth_key: &[u8; 32],
+    ) -> SessionKeys {
+        let session_id = reauth_session_id(self.method_type(), nonce_s, mac);
+        match self {
+            Self::Aka => reauth_session_keys(
+                identity,
+                counter,
+                nonce_s,
+                reauth_key[..MK_LEN].try_into().unwrap(),
+                session_id,
+            ),
+            Self::AkaPrime => {
+                // MK = PRF'(K_re, "EAP-AKA' re-auth"|Identity|counter|NONCE_S)
+                let mut mk = [0u8; 128];
+                prf_prime(
+                    reauth_key,
+                    &[
+                        b"EAP-AKA' re-auth",
+                        identity,
+                        &counter.to_be_bytes(),
+                        nonce_s,
+                    ],
+                    &mut mk,
+                );
+                SessionKeys {
+                    msk: mk[..64].try_into().unwrap(),
+                    emsk: mk[64..].try_into().unwrap(),
+                    session_id,
+                }
+            }
+        }
+    }
+}
+
+/// PRF' of RFC 5448 3.4, `s` is the concatenation of its parts
+fn prf_prime(key: &[u8], s: &[&[u8]], out: &mut [u8]) {
+    let mut previous = [0u8; 32];
+    for (i, chunk) in out.chunks_mut(32).enumerate() {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+        if i > 0 {
+            mac.update(&previous);
+        }
+        s.iter().for_each(|part| mac.update(part));
+        mac.update(&[i as u8 + 1]);
+
+        previous = mac.finalize().into_bytes().into();
+        chunk.copy_from_slice(&previous[..chunk.len()]);
+    }
+}
+
+/// CK' and IK' of EAP-AKA', RFC 5448 3.3 and 3GPP TS 33.402 A.2
+fn derive_ck_ik_prime(ck: &Block, ik: &Block, network_name: &[u8], autn: &Block) -> (Block, Block) {
+    const FC: u8 = 0x20;
+    let sqn_xor_ak = &autn[..6];
+
+    let mut key = [0u8; 32];
+    key[..16].copy_from_slice(ck);
+    key[16..].copy_from_slice(ik);
+
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key).unwrap();
+    mac.update(&[FC]);
+    mac.update(network_name);
+    mac.update(&(network_name.len() as u16).to_be_bytes());
+    mac.update(sqn_xor_ak);
+    mac.update(&(sqn_xor_ak.len() as u16).to_be_bytes());
+
+    let out = mac.finalize().into_bytes();
+    (out[..16].try_into().unwrap(), out[16..].try_into().unwrap())
+}
+
+/// Hash of the AKA-Identity messages for AT_CHECKCODE, RFC 4187 10.13
+#[derive(Clone)]
+struct Checkcode {
+    hash: CheckcodeHash,
+    used: bool,
+}
+
+#[derive(Clone)]
+enum CheckcodeHash {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl Checkcode {
+    fn new(variant: Variant) -> Self {
+        let hash = match variant {
+            Variant::Aka => CheckcodeHash::Sha1(Sha1::new()),
+            Variant::AkaPrime => CheckcodeHash::Sha256(Sha256::new()),
+        };
+        Self { hash, used: false }
+    }
+
+    /// Adds the EAP packet made of `parts`
+    fn update(&mut self, parts: &[&[u8]]) {
+        self.used = true;
+        for part in parts {
+            match &mut self.hash {
+                CheckcodeHash::Sha1(hash) => hash.update(part),
+                CheckcodeHash::Sha256(hash) => hash.update(part),
+            }
+        }
+    }
+
+    /// Value of AT_CHECKCODE, empty if there were no AKA-Identity messages
+    fn value(&self) -> Checksum {
+        match (self.used, &self.hash) {
+            (false, _) => Checksum::new(),
+            (true, CheckcodeHash::Sha1(hash)) => Checksum::from(&hash.clone().finalize()[..]),
+            (true, CheckcodeHash::Sha256(hash)) => Checksum::from(&hash.clone().finalize()[..]),
+        }
+    }
+}
+
+/// Challenge of the home network for one authentication
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthenticationVector {
+    pub rand: [u8; RAND_LEN],
+    pub xres: OwnedSlice<16>,
+    pub ck: Block,
+    pub ik: Block,
+    pub autn: [u8; AUTN_LEN],
+}
+
+/// The authentication centre of the home network, it holds the subscriber keys
+pub trait AuthenticationCentre {
+    /// Generates a vector for the subscriber, `None` if the IMSI is unknown
+    fn vector(
+        &mut self,
+        imsi: &[u8],
+        rand: &[u8; RAND_LEN],
+        amf: [u8; 2],
+    ) -> Option<AuthenticationVector>;
+
+    /// Handles the AUTS of a synchronization failure, returns `false` if it is not
+    /// authentic
+    fn resynchronize(&mut self, imsi: &[u8], rand: &[u8; RAND_LEN], auts: &[u8; AUTS_LEN]) -> bool;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UsimResponse {
+    pub res: OwnedSlice<16>,
+    pub ck: Block,
+    pub ik: Block,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UsimError {
+    /// AUTN was not generated by the home network
+    MacFailure,
+    /// The sequence number is not fresh, the home network has to resynchronize
+    SyncFailure([u8; AUTS_LEN]),
+}
+
+/// The USIM application of the peer. It also keeps the temporary identities.
+pub trait Usim: PeerIdentityStore {
+    fn imsi(&self) -> &[u8];
+
+    /// Verifies AUTN and computes the response, 3GPP TS 33.102 6.3.3
+    fn authenticate(
+        &mut self,
+        rand: &[u8; RAND_LEN],
+        autn: &[u8; AUTN_LEN],
+    ) -> Result<UsimResponse, UsimError>;
+}
+
+/// Permanent identity of a subscriber, RFC 4187 4.1.1.6
+fn permanent_identity(variant: Variant, imsi: &[u8]) -> Identity {
+    let mut buf = [0u8; MAX_IDENTITY_LEN];
+    buf[0] = variant.prefixes().permanent;
+    buf[1..1 + imsi.len()].copy_from_slice(imsi);
+    Identity::from(&buf[..1 + imsi.len()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex_to_vec;
+
+    fn block(hex: &str) -> Block {
+        hex_to_vec(hex).try_into().unwrap()
+    }
+
+    #[test]
+    fn aka_prime_keys() {
+        // RFC 5448 Appendix C, Test Case 1
+        let ck = block("53 49 fb e0 98 64 9f 94 8f 5d 2e 97 3a 81 c0 0f");
+        let ik = block("97 44 87 1a d3 2b f9 bb d1 dd 5c e5 4e 3e 2e 5a");
+        let rand = block("81 e9 2b 6c 0e e0 e1 2e bc eb a8 d9 2a 99 df a5");
+        let autn = block("bb 52 e9 1c 74 7a c3 ab 2a 5c 23 d1 5e e3 51 d5");
+
+        let (ck_prime, ik_prime) = derive_ck_ik_prime(&ck, &ik, b"WLAN", &autn);
+        assert_eq!(
+            ck_prime,
+            block("00 93 96 2d 0d d8 4a a5 68 4b 04 5c 9e df fa 04")
+        );
+        assert_eq!(
+            ik_prime,
+            block("cc fc 23 0c a7 4f cc 96 c0 a5 d6 11 64 f5 a7 6c")
+        );
+
+        let keys = Variant::AkaPrime.keys(b"0555444333222111", &ck_prime, &ik_prime, &rand, &autn);
+        assert_eq!(
+            keys.k_encr,
+            block("76 6f a0 a6 c3 17 17 4b 81 2d 52 fb cd 11 a1 79")
+        );
+        assert_eq!(
+            keys.k_aut.to_vec(),
+            hex_to_vec(
+                "08 42 ea 72 2f f6 83 5b fa 20 32 49 9f c3 ec 23 \
+                 c2 f0 e3 88 b4 f0 75 43 ff c6 77 f1 69 6d 71 ea"
+            )
+        );
+        assert_eq!(
+            keys.session_keys.session_id.as_ref(),
+            [&[METHOD_AKA_PRIME][..], &rand, &autn].concat()
+        );
+    }
+
+    #[test]
+    fn checkcode() {
+        let mut checkcode = Checkcode::new(Variant::Aka);
+        assert!(checkcode.value().as_ref().is_empty());
+        checkcode.update(&[b"abc"]);
+        assert_eq!(checkcode.value().as_ref().len(), 20);
+
+        let mut checkcode = Checkcode::new(Variant::AkaPrime);
+        checkcode.update(&[b"abc"]);
+        assert_eq!(checkcode.value().as_ref().len(), 32);
+    }
+}
@@ -0,0 +1,425 @@
+use crate::{
+    layers::{
+        eap_layer::{Decision, MethodState, SessionKeys},
+        mux::TupleElement,
+        peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta},
+        MethodType,
+    },
+    message::MessageCode,
+    util::OwnedSlice,
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+/// Rank of the identity requests, each one may only be followed by a stricter one
+fn id_req_rank(id_req: u8) -> u8 {
+    match id_req {
+        AT_ANY_ID_REQ => 0,
+        AT_FULLAUTH_ID_REQ => 1,
+        _ => 2,
+    }
+}
+
+/// Peer side of EAP-AKA, or EAP-AKA' with [`Self::with_aka_prime`].
+///
+/// Temporary identities sent by the server are kept in the [`Usim`] and used
+/// whenever the server allows it.
+pub struct PeerAkaMethod<U> {
+    variant: Variant,
+    usim: U,
+    network_name: Option<OwnedSlice<64>>,
+    /// Loaded from the USIM on the first message of a conversation
+    identities: Option<PeerIdentities>,
+    id_req: Option<u8>,
+    checkcode: Checkcode,
+    /// Identity of the last AT_IDENTITY, the keys are bound to it
+    identity: Identity,
+    session_keys: Option<SessionKeys>,
+}
+
+impl<U: Usim + 'static> TupleElement for PeerAkaMethod<U> {
+    type Target = dyn PeerMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<U: Usim> PeerAkaMethod<U> {
+    pub fn new(usim: U) -> Self {
+        Self {
+            variant: Variant::Aka,
+            usim,
+            network_name: None,
+            identities: None,
+            id_req: None,
+            checkcode: Checkcode::new(Variant::Aka),
+            identity: Identity::new(),
+            session_keys: None,
+        }
+    }
+
+    /// Runs EAP-AKA' (type 50) instead
+    pub fn with_aka_prime(mut self) -> Self {
+        self.variant = Variant::AkaPrime;
+        self.checkcode = Checkcode::new(self.variant);
+        self
+    }
+
+    /// Rejects EAP-AKA' challenges bound to another access network name
+    pub fn with_network_name(mut self, network_name: &[u8]) -> Self {
+        self.network_name = Some(
+            network_name
+                .try_into()
+                .expect("network name too long for nostd"),
+        );
+        self
+    }
+
+    fn identities(&mut self) -> &mut PeerIdentities {
+        let usim = &mut self.usim;
+        self.identities
+            .get_or_insert_with(|| usim.load_identities())
+    }
+
+    fn store_identities(&mut self) {
+        let identities = self.identities().clone();
+        self.usim.store_identities(&identities);
+    }
+
+    fn permanent_identity(&self) -> Identity {
+        permanent_identity(self.variant, self.usim.imsi())
+    }
+
+    fn respond<'a>(
+        &self,
+        writer: &Writer,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        PeerMethodLayerResult::Send(env.respond().write(writer.as_slice()))
+    }
+
+    fn client_error<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> PeerMethodLayerResult<'a> {
+        self.session_keys = None;
+        let mut writer = Writer::new(SUBTYPE_CLIENT_ERROR);
+        writer.u16(AT_CLIENT_ERROR_CODE, CLIENT_ERROR_UNABLE_TO_PROCESS);
+        self.respond(&writer, env)
+    }
+
+    fn authentication_reject<'a>(
+        &mut self,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        self.session_keys = None;
+        self.respond(&Writer::new(SUBTYPE_AUTHENTICATION_REJECT), env)
+    }
+
+    /// Verifies AT_CHECKCODE if the server sent one, returns the value to answer
+    /// with
+    fn checkcode(&self, attributes: &Attributes) -> Result<Option<Checksum>, ()> {
+        let value = self.checkcode.value();
+        match attributes.checkcode {
+            Some(checkcode) if checkcode == value.as_ref() => Ok(Some(value)),
+            Some(_) => Err(()),
+            None => Ok(None),
+        }
+    }
+
+    fn recv_identity<'a>(
+        &mut self,
+        attributes: &Attributes,
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let Some(id_req) = attributes.id_req else {
+            return self.client_error(env);
+        };
+        if self
+            .id_req
+            .is_some_and(|last| id_req_rank(id_req) <= id_req_rank(last))
+        {
+            return self.client_error(env);
+        }
+        self.id_req = Some(id_req);
+
+        let permanent_identity = self.permanent_identity();
+        let identities = self.identities();
+        let reauth_id = identities
+            .reauth
+            .as_ref()
+            .map(|context| context.identity.clone());
+        let pseudonym = identities.pseudonym.clone();
+        self.identity = match id_req {
+            AT_ANY_ID_REQ => reauth_id.or(pseudonym),
+            AT_FULLAUTH_ID_REQ => pseudonym,
+            _ => None,
+        }
+        .unwrap_or(permanent_identity);
+
+        let mut writer = Writer::new(SUBTYPE_IDENTITY);
+        writer.prefixed(AT_IDENTITY, self.identity.as_ref());
+
+        let message = &meta.message;
+        self.checkcode.update(&[
+            &packet_header(message.code as u8, message.identifier, message.body.len()),
+            message.body,
+        ]);
+        self.checkcode.update(&[
+            &packet_header(
+                MessageCode::Response as u8,
+                message.identifier,
+                1 + writer.as_slice().len(),
+            ),
+            &[self.variant.method_type()],
+            writer.as_slice(),
+        ]);
+
+        self.respond(&writer, env)
+    }
+
+    fn recv_challenge<'a>(
+        &mut self,
+        attributes: &Attributes,
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let (Some(rand), Some(autn)) = (attributes.rand, attributes.autn) else {
+            return self.client_error(env);
+        };
+        let Ok(rand) = rand.try_into() else {
+            return self.client_error(env);
+        };
+
+        let mut network_name: &[u8] = &[];
+        if self.variant == Variant::AkaPrime {
+            let (Some(KDF_AKA_PRIME), Some(kdf_input)) = (attributes.kdf, attributes.kdf_input)
+            else {
+                return self.authentication_reject(env);
+            };
+            let expected = self.network_name.as_ref().map(AsRef::as_ref);
+            if kdf_input.is_empty()
+                || expected.is_some_and(|name| name != kdf_input)
+                || autn[6] & AMF_SEPARATION_BIT == 0
+            {
+                return self.authentication_reject(env);
+            }
+            network_name = kdf_input;
+        }
+
+        let response = match self.usim.authenticate(rand, autn) {
+            Ok(response) => response,
+            Err(UsimError::MacFailure) => return self.authentication_reject(env),
+            Err(UsimError::SyncFailure(auts)) => {
+                let mut writer = Writer::new(SUBTYPE_SYNCHRONIZATION_FAILURE);
+                writer.attribute(AT_AUTS, &[&auts]);
+                return self.respond(&writer, env);
+            }
+        };
+
+        let (ck, ik) = match self.variant {
+            Variant::Aka => (response.ck, response.ik),
+            Variant::AkaPrime => derive_ck_ik_prime(&response.ck, &response.ik, network_name, autn),
+        };
+        if self.identity.as_ref().is_empty() {
+            self.identity = self.permanent_identity();
+        }
+        let keys = self
+            .variant
+            .keys(self.identity.as_ref(), &ck, &ik, rand, autn);
+
+        let mac_algorithm = self.variant.mac_algorithm();
+        if !mac_algorithm.verify(&keys.k_aut, &meta.message, attributes, &[]) {
+            return self.client_error(env);
+        }
+        let Ok(checkcode) = self.checkcode(attributes) else {
+            return self.client_error(env);
+        };
+
+        if attributes.encr_data.is_some() {
+            let decrypted = Decrypted::new(&keys.k_encr, attributes);
+            let Some(encrypted) = decrypted.as_ref().and_then(Decrypted::attributes) else {
+                return self.client_error(env);
+            };
+
+            let permanent_identity = self.permanent_identity();
+            let identities = self.identities();
+            if let Some(pseudonym) = encrypted.next_pseudonym {
+                identities.pseudonym = Some(Identity::try_from(pseudonym).unwrap_or_default())
+                    .filter(|pseudonym| !pseudonym.as_ref().is_empty());
+            }
+            identities.reauth = encrypted.next_reauth_id.and_then(|reauth_id| {
+                (!reauth_id.is_empty() && reauth_id.len() <= MAX_IDENTITY_LEN)
+                    .then(|| ReauthContext::new(reauth_id, permanent_identity.as_ref(), &keys))
+            });
+        } else {
+            self.identities().reauth = None;
+        }
+        self.store_identities();
+
+        let res = response.res.as_ref();
+        let mut writer = Writer::new(SUBTYPE_CHALLENGE);
+        writer.attribute(AT_RES, &[&(res.len() as u16 * 8).to_be_bytes(), res]);
+        if let Some(checkcode) = checkcode {
+            writer.reserved(AT_CHECKCODE, checkcode.as_ref());
+        }
+        writer.mac().sign(
+            mac_algorithm,
+            &keys.k_aut,
+            MessageCode::Response,
+            meta.message.identifier,
+            self.variant.method_type(),
+            &[],
+        );
+
+        self.session_keys = Some(keys.session_keys);
+        self.respond(&writer, env)
+    }
+
+    fn recv_reauthentication<'a>(
+        &mut self,
+        attributes: &Attributes,
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        // Only possible if the re-authentication identity was sent
+        let identity = self.identity.clone();
+        let Some(context) = self
+            .identities()
+            .reauth
+            .clone()
+            .filter(|context| context.identity == identity)
+        else {
+            return self.client_error(env);
+        };
+
+        let mac_algorithm = self.variant.mac_algorithm();
+        if !mac_algorithm.verify(&context.k_aut, &meta.message, attributes, &[]) {
+            return self.client_error(env);
+        }
+        let Some(mac) = attributes.mac_value(&meta.message) else {
+            return self.client_error(env);
+        };
+        let Ok(checkcode) = self.checkcode(attributes) else {
+            return self.client_error(env);
+        };
+        let decrypted = Decrypted::new(&context.k_encr, attributes);
+        let Some(encrypted) = decrypted.as_ref().and_then(Decrypted::attributes) else {
+            return self.client_error(env);
+        };
+        let (Some(counter), Some(nonce_s)) = (encrypted.counter, encrypted.nonce_s) else {
+            return self.client_error(env);
+        };
+
+        let mut plaintext = Writer::nested();
+        plaintext.u16(AT_COUNTER, counter);
+
+        if counter <= context.counter {
+            // Replayed request, the server falls back to full authentication
+            plaintext.reserved(AT_COUNTER_TOO_SMALL, &[]);
+            self.identities().reauth = None;
+            self.session_keys = None;
+        } else {
+            self.session_keys = Some(self.variant.reauth_session_keys(
+                identity.as_ref(),
+                counter,
+                nonce_s,
+                &mac,
+                &context.reauth_key,
+            ));
+            self.identities().reauth = encrypted
+                .next_reauth_id
+                .filter(|reauth_id| !reauth_id.is_empty() && reauth_id.len() <= MAX_IDENTITY_LEN)
+                .map(|reauth_id| ReauthContext {
+                    identity: Identity::from(reauth_id),
+                    counter,
+                    ..context.clone()
+                });
+        }
+        self.store_identities();
+
+        let mut iv = [0u8; 16];
+        env.fill_random(&mut iv);
+
+        let mut writer = Writer::new(SUBTYPE_REAUTHENTICATION);
+        writer.encrypted(&context.k_encr, &iv, plaintext);
+        if let Some(checkcode) = checkcode {
+            writer.reserved(AT_CHECKCODE, checkcode.as_ref());
+        }
+        writer.mac().sign(
+            mac_algorithm,
+            &context.k_aut,
+            MessageCode::Response,
+            meta.message.identifier,
+            self.variant.method_type(),
+            nonce_s,
+        );
+
+        self.respond(&writer, env)
+    }
+}
+
+impl<U: Usim> PeerMethodLayer for PeerAkaMethod<U> {
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(self.variant.method_type())
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_AKA
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let Some(attributes) = msg.get(3..).and_then(|data| Attributes::parse(data, 3)) else {
+            return self.client_error(env);
+        };
+
+        match msg[0] {
+            SUBTYPE_IDENTITY => self.recv_identity(&attributes, meta, env),
+            SUBTYPE_CHALLENGE => self.recv_challenge(&attributes, meta, env),
+            SUBTYPE_REAUTHENTICATION => self.recv_reauthentication(&attributes, meta, env),
+            // Notifications are not supported
+            _ => self.client_error(env),
+        }
+    }
+
+    fn method_state(&self) -> MethodState {
+        if self.session_keys.is_some() {
+            MethodState::Done
+        } else {
+            MethodState::MayCont
+        }
+    }
+
+    fn decision(&self) -> Decision {
+        if self.session_keys.is_some() {
+            Decision::CondSucc
+        } else {
+            Decision::Fail
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.session_keys.as_ref()
+    }
+
+    fn reset(&mut self) {
+        self.identities = None;
+        self.id_req = None;
+        self.checkcode = Checkcode::new(self.variant);
+        self.identity = Identity::new();
+        self.session_keys = None;
+    }
+}
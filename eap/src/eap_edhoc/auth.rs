@@ -0,0 +1,278 @@
+use crate::layers::{
+    auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta},
+    mux::TupleElement,
+    MethodType,
+};
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Start,
+    WaitMessage1,
+    WaitMessage3,
+    /// message_4 was sent, waiting for the empty response
+    WaitAck,
+    /// An EDHOC error was sent, waiting for the answer of the peer
+    Failing,
+    Done,
+}
+
+/// Server side of EAP-EDHOC, the EDHOC Responder.
+///
+/// `credential_lookup` returns the credential of the peer for the kid in
+/// ID_CRED_I.
+#[derive(Clone)]
+pub struct AuthEdhocMethod<F> {
+    credential: OwnCredential,
+    credential_lookup: F,
+    state: State,
+    /// METHOD of message_1
+    method: i8,
+    /// Ephemeral X25519 key
+    y: [u8; KE_LEN],
+    prk_3e2m: Hash,
+    th_3: Hash,
+    session_keys: Option<SessionKeys>,
+}
+
+impl<F> TupleElement for AuthEdhocMethod<F>
+where
+    F: FnMut(&[u8]) -> Option<Credential> + 'static,
+{
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<F> AuthEdhocMethod<F>
+where
+    F: FnMut(&[u8]) -> Option<Credential>,
+{
+    pub fn new(credential: OwnCredential, credential_lookup: F) -> Self {
+        Self {
+            credential,
+            credential_lookup,
+            state: State::Start,
+            method: 0,
+            y: [0; KE_LEN],
+            prk_3e2m: [0; HASH_LEN],
+            th_3: [0; HASH_LEN],
+            session_keys: None,
+        }
+    }
+
+    fn fail<'a>(&mut self, code: i8, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.state = State::Failing;
+        self.session_keys = None;
+        AuthMethodLayerResult::Send(error_message(env, code))
+    }
+
+    /// The peer answered our error or sent its own
+    fn failed<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.state = State::Failing;
+        self.session_keys = None;
+        AuthMethodLayerResult::Failed(env)
+    }
+
+    /// message_1 = (METHOD, SUITES_I, G_X, C_I, ? EAD_1), answered by
+    /// message_2
+    fn recv_message_1<'a>(
+        &mut self,
+        message: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let mut reader = ByteReader::new(message);
+        let Some(method) = cbor::int(&mut reader).filter(|method| (0..=3).contains(method)) else {
+            return self.fail(ERR_UNSPECIFIED, env);
+        };
+        if selected_suite(&mut reader) != Some(SUITE_0 as i32) {
+            return self.fail(ERR_WRONG_SUITE, env);
+        }
+        let (Some(g_x), Some(_c_i), Some(())) = (
+            cbor::bstr(&mut reader).and_then(|g_x| <[u8; KE_LEN]>::try_from(g_x).ok()),
+            cbor::int_or_bstr(&mut reader),
+            skip_ead(&mut reader),
+        ) else {
+            return self.fail(ERR_UNSPECIFIED, env);
+        };
+        // The own authentication key has to match the METHOD of the peer
+        let method = method as i8;
+        let own = self.credential.credential();
+        if method & 1 != own.is_static_dh() as i8 {
+            return self.fail(ERR_UNSPECIFIED, env);
+        }
+
+        env.fill_random(&mut self.y);
+        let g_y = dh_public_key(&self.y);
+        let (Some(g_xy), Some(g_rx)) = (
+            dh_shared_secret(&self.y, &g_x),
+            self.credential.shared_secret(&g_x),
+        ) else {
+            return self.fail(ERR_UNSPECIFIED, env);
+        };
+
+        // TH_2 = H(G_Y, H(message_1)), PRK_2e = EDHOC_Extract(TH_2, G_XY)
+        let th_2 = hash(&[&BSTR_32, &g_y, &BSTR_32, &hash(&[message])]);
+        let prk_2e = extract(&th_2, &g_xy);
+        let prk_3e2m = static_dh_prk(&prk_2e, LABEL_SALT_3E2M, &th_2, g_rx);
+        let signature_or_mac_2 = self.credential.signature_or_mac(&MacContext {
+            prk: &prk_3e2m,
+            label: LABEL_MAC_2,
+            c_r: &[C_R],
+            th: &th_2,
+        });
+
+        // PLAINTEXT_2 = (C_R, ID_CRED_R, Signature_or_MAC_2)
+        let mut plaintext_2 = cbor::Writer::<MAX_PLAINTEXT_LEN>::new();
+        plaintext_2
+            .raw(&[C_R])
+            .raw(own.compact_id_cred().as_slice())
+            .bstr(signature_or_mac_2.as_slice());
+        // TH_3 = H(TH_2, PLAINTEXT_2, CRED_R)
+        self.th_3 = hash(&[&BSTR_32, &th_2, plaintext_2.as_slice(), own.cred.as_ref()]);
+
+        let mut message_2 = cbor::Writer::<MAX_MESSAGE_LEN>::new();
+        message_2
+            .head(MAJOR_BSTR, KE_LEN + plaintext_2.as_slice().len())
+            .raw(&g_y)
+            .raw(plaintext_2.as_slice());
+        let ciphertext_2 = &mut message_2.as_mut_slice()[2 + KE_LEN..];
+        let mut keystream_2 = [0u8; MAX_PLAINTEXT_LEN];
+        let keystream_2 = &mut keystream_2[..ciphertext_2.len()];
+        kdf(&prk_2e, LABEL_KEYSTREAM_2, &[&th_2], keystream_2);
+        ciphertext_2
+            .iter_mut()
+            .zip(keystream_2)
+            .for_each(|(c, k)| *c ^= *k);
+
+        self.method = method;
+        self.prk_3e2m = prk_3e2m;
+        self.state = State::WaitMessage3;
+        AuthMethodLayerResult::Send(env.respond().write(&[0]).write(message_2.as_slice()))
+    }
+
+    /// message_3 = bstr(CIPHERTEXT_3), answered by message_4
+    fn recv_message_3<'a>(
+        &mut self,
+        message: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let mut reader = ByteReader::new(message);
+        let mut ciphertext_3 = [0u8; MAX_PLAINTEXT_LEN + TAG_LEN];
+        let Some(ciphertext_3) = cbor::bstr(&mut reader)
+            .filter(|data| reader.is_empty() && data.len() <= MAX_PLAINTEXT_LEN + TAG_LEN)
+            .map(|data| {
+                ciphertext_3[..data.len()].copy_from_slice(data);
+                &mut ciphertext_3[..data.len()]
+            })
+        else {
+            return self.fail(ERR_UNSPECIFIED, env);
+        };
+        let (k_3, iv_3) = aead_key(&self.prk_3e2m, LABEL_K_3, &self.th_3);
+        let Some(plaintext_3) =
+            crypto::decrypt(&k_3, &iv_3, &enc_structure(&self.th_3), ciphertext_3)
+        else {
+            return self.fail(ERR_UNSPECIFIED, env);
+        };
+
+        // PLAINTEXT_3 = (ID_CRED_I, Signature_or_MAC_3, ? EAD_3)
+        let mut reader = ByteReader::new(plaintext_3);
+        let (Some(id_cred_i), Some(signature_or_mac_3), Some(())) = (
+            cbor::int_or_bstr(&mut reader).and_then(compact_kid),
+            cbor::bstr(&mut reader),
+            skip_ead(&mut reader),
+        ) else {
+            return self.fail(ERR_UNSPECIFIED, env);
+        };
+        let Some(peer) = (self.credential_lookup)(id_cred_i)
+            .filter(|peer| method(peer, self.credential.credential()) == self.method)
+        else {
+            return self.fail(ERR_UNSPECIFIED, env);
+        };
+        let Some(g_iy) = peer.static_dh(&self.y) else {
+            return self.fail(ERR_UNSPECIFIED, env);
+        };
+        let prk_4e3m = static_dh_prk(&self.prk_3e2m, LABEL_SALT_4E3M, &self.th_3, g_iy);
+        let mac_3 = MacContext {
+            prk: &prk_4e3m,
+            label: LABEL_MAC_3,
+            c_r: &[],
+            th: &self.th_3,
+        };
+        if !peer.verify_signature_or_mac(&mac_3, signature_or_mac_3) {
+            return self.fail(ERR_UNSPECIFIED, env);
+        }
+
+        // TH_4 = H(TH_3, PLAINTEXT_3, CRED_I), message_4 has no EAD
+        let th_4 = hash(&[&BSTR_32, &self.th_3, plaintext_3, peer.cred.as_ref()]);
+        let (k_4, iv_4) = aead_key(&prk_4e3m, LABEL_K_4, &th_4);
+        let mut ciphertext_4 = [0u8; TAG_LEN];
+        crypto::encrypt(&k_4, &iv_4, &enc_structure(&th_4), &mut ciphertext_4);
+
+        self.session_keys = Some(session_keys(&prk_4e3m, &th_4));
+        self.state = State::WaitAck;
+        AuthMethodLayerResult::Send(
+            env.respond()
+                .write(&[0, MAJOR_BSTR | TAG_LEN as u8])
+                .write(&ciphertext_4),
+        )
+    }
+}
+
+impl<F> AuthMethodLayer for AuthEdhocMethod<F>
+where
+    F: FnMut(&[u8]) -> Option<Credential>,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_EDHOC)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_EDHOC
+    }
+
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.session_keys = None;
+        self.state = State::WaitMessage1;
+        AuthMethodLayerResult::Send(env.respond().write(&[FLAG_START]))
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let Some((_, message)) = parse_packet(msg).filter(|_| self.state != State::Failing) else {
+            return self.failed(env);
+        };
+        match self.state {
+            State::WaitMessage3 | State::WaitAck if is_error(message) => self.failed(env),
+            State::WaitMessage1 => self.recv_message_1(message, env),
+            State::WaitMessage3 => self.recv_message_3(message, env),
+            State::WaitAck if message.is_empty() => {
+                self.state = State::Done;
+                AuthMethodLayerResult::Finished(env)
+            }
+            _ => self.fail(ERR_UNSPECIFIED, env),
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        match self.state {
+            State::Done => self.session_keys.as_ref(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Minimal CBOR support for EDHOC messages.
+//!
+//! Only the data items EDHOC uses are supported: integers, byte and text
+//! strings, and arrays of integers in SUITES_I. Lengths are limited to 16 bits.
+
+use crate::util::ByteReader;
+
+pub(crate) const MAJOR_UINT: u8 = 0x00;
+pub(crate) const MAJOR_NINT: u8 = 0x20;
+pub(crate) const MAJOR_BSTR: u8 = 0x40;
+pub(crate) const MAJOR_TSTR: u8 = 0x60;
+pub(crate) const MAJOR_ARRAY: u8 = 0x80;
+pub(crate) const MAJOR_MAP: u8 = 0xa0;
+
+/// Initial byte and argument of a data item, returns the buffer and its length
+pub(crate) fn header(major: u8, value: usize) -> ([u8; 3], usize) {
+    match value {
+        0..=23 => ([major | value as u8, 0, 0], 1),
+        24..=0xff => ([major | 24, value as u8, 0], 2),
+        _ => {
+            let [high, low] = (value as u16).to_be_bytes();
+            ([major | 25, high, low], 3)
+        }
+    }
+}
+
+/// Encodes integers from -24 to 23, which fit the initial byte
+pub(crate) const fn small_int(value: i8) -> u8 {
+    if value >= 0 {
+        MAJOR_UINT | value as u8
+    } else {
+        MAJOR_NINT | (-1 - value) as u8
+    }
+}
+
+/// Whether `data` is the single byte encoding of an integer, RFC 9528 3.3.2
+pub(crate) fn is_small_int(data: &[u8]) -> bool {
+    matches!(data, [byte] if byte & 0x1f < 24 && byte & 0xe0 <= MAJOR_NINT)
+}
+
+/// Writes CBOR sequences into a fixed buffer
+#[derive(Clone)]
+pub(crate) struct Writer<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Writer<N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Already encoded data
+    pub(crate) fn raw(&mut self, data: &[u8]) -> &mut Self {
+        self.buffer[self.len..self.len + data.len()].copy_from_slice(data);
+        self.len += data.len();
+        self
+    }
+
+    pub(crate) fn head(&mut self, major: u8, value: usize) -> &mut Self {
+        let (header, len) = header(major, value);
+        self.raw(&header[..len])
+    }
+
+    pub(crate) fn int(&mut self, value: i8) -> &mut Self {
+        self.raw(&[small_int(value)])
+    }
+
+    pub(crate) fn bstr(&mut self, data: &[u8]) -> &mut Self {
+        self.head(MAJOR_BSTR, data.len()).raw(data)
+    }
+
+    pub(crate) fn tstr(&mut self, text: &str) -> &mut Self {
+        self.head(MAJOR_TSTR, text.len()).raw(text.as_bytes())
+    }
+
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.buffer[..self.len]
+    }
+}
+
+/// Reads the argument of a data item of type `major`
+fn argument(reader: &mut ByteReader, major: u8) -> Option<usize> {
+    let initial = reader.u8()?;
+    if initial & 0xe0 != major {
+        return None;
+    }
+    match initial & 0x1f {
+        value @ 0..=23 => Some(value as usize),
+        24 => reader.u8().map(usize::from),
+        25 => reader.u16().map(usize::from),
+        _ => None,
+    }
+}
+
+/// Integers in the 16 bit range of the argument
+pub(crate) fn int(reader: &mut ByteReader) -> Option<i32> {
+    match reader.remaining().first()? & 0xe0 {
+        MAJOR_UINT => argument(reader, MAJOR_UINT).map(|value| value as i32),
+        MAJOR_NINT => argument(reader, MAJOR_NINT).map(|value| -1 - value as i32),
+        _ => None,
+    }
+}
+
+pub(crate) fn bstr<'a>(reader: &mut ByteReader<'a>) -> Option<&'a [u8]> {
+    let len = argument(reader, MAJOR_BSTR)?;
+    reader.take(len)
+}
+
+pub(crate) fn array_len(reader: &mut ByteReader) -> Option<usize> {
+    argument(reader, MAJOR_ARRAY)
+}
+
+/// An integer or a byte string, returns its encoding. Used for connection
+/// identifiers and compact ID_CRED_x, RFC 9528 3.3.2.
+pub(crate) fn int_or_bstr<'a>(reader: &mut ByteReader<'a>) -> Option<&'a [u8]> {
+    let data = reader.remaining();
+    match data.first()? & 0xe0 {
+        MAJOR_UINT | MAJOR_NINT => int(reader)?,
+        MAJOR_BSTR => bstr(reader)?.len() as i32,
+        _ => return None,
+    };
+    Some(&data[..data.len() - reader.remaining().len()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn items() {
+        let mut writer = Writer::<64>::new();
+        writer.int(3).int(-24).bstr(&[0xab; 30]).tstr("ok");
+        let encoded = writer.as_slice();
+        assert_eq!(&encoded[..4], [0x03, 0x37, 0x58, 30]);
+        assert_eq!(&encoded[34..], [0x62, b'o', b'k']);
+
+        let mut reader = ByteReader::new(encoded);
+        assert_eq!(int(&mut reader), Some(3));
+        assert_eq!(int_or_bstr(&mut reader), Some(&[0x37][..]));
+        assert_eq!(bstr(&mut reader), Some(&[0xab; 30][..]));
+        assert_eq!(bstr(&mut reader), None);
+
+        assert!(is_small_int(&[0x17]) && is_small_int(&[0x37]));
+        assert!(!is_small_int(&[0x18]) && !is_small_int(&[0x41]));
+    }
+}
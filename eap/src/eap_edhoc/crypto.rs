@@ -0,0 +1,255 @@
+//! AES-CCM-16-64-128 and Ed25519 of cipher suite 0
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use curve25519_dalek::{
+    edwards::{CompressedEdwardsY, EdwardsPoint},
+    scalar::Scalar,
+};
+use sha2::{Digest, Sha512};
+
+use crate::util::constant_time_eq;
+
+pub(crate) const KEY_LEN: usize = 16;
+pub(crate) const IV_LEN: usize = 13;
+pub(crate) const TAG_LEN: usize = 8;
+pub(crate) const SIGNATURE_LEN: usize = 64;
+
+const BLOCK_LEN: usize = 16;
+
+/// AES-CCM with a 2 byte length field and an 8 byte tag, RFC 3610
+struct Ccm {
+    cipher: Aes128,
+    nonce: [u8; IV_LEN],
+}
+
+impl Ccm {
+    fn new(key: &[u8; KEY_LEN], nonce: &[u8; IV_LEN]) -> Self {
+        Self {
+            cipher: Aes128::new(GenericArray::from_slice(key)),
+            nonce: *nonce,
+        }
+    }
+
+    fn block(&self, flags: u8, value: usize) -> [u8; BLOCK_LEN] {
+        let mut block = [0u8; BLOCK_LEN];
+        block[0] = flags;
+        block[1..1 + IV_LEN].copy_from_slice(&self.nonce);
+        block[14..].copy_from_slice(&(value as u16).to_be_bytes());
+        block
+    }
+
+    fn encrypt_block(&self, mut block: [u8; BLOCK_LEN]) -> [u8; BLOCK_LEN] {
+        self.cipher
+            .encrypt_block(GenericArray::from_mut_slice(&mut block));
+        block
+    }
+
+    /// CBC-MAC over B_0, the length prefixed AAD and the plaintext
+    fn mac(&self, aad: &[u8], plaintext: &[u8]) -> [u8; TAG_LEN] {
+        let adata = if aad.is_empty() { 0 } else { 0x40 };
+        let flags = adata | ((TAG_LEN as u8 - 2) / 2) << 3 | 1;
+        let mut mac = self.encrypt_block(self.block(flags, plaintext.len()));
+
+        let aad_len = (aad.len() as u16).to_be_bytes();
+        let mut absorb = |data: &mut dyn Iterator<Item = &u8>| {
+            let mut block = [0u8; BLOCK_LEN];
+            let mut filled = 0;
+            for &byte in data {
+                block[filled] = byte;
+                filled += 1;
+                if filled == BLOCK_LEN {
+                    mac.iter_mut().zip(block).for_each(|(m, b)| *m ^= b);
+                    mac = self.encrypt_block(mac);
+                    (block, filled) = ([0; BLOCK_LEN], 0);
+                }
+            }
+            if filled > 0 {
+                mac.iter_mut().zip(block).for_each(|(m, b)| *m ^= b);
+                mac = self.encrypt_block(mac);
+            }
+        };
+        if !aad.is_empty() {
+            absorb(&mut aad_len.iter().chain(aad));
+        }
+        absorb(&mut plaintext.iter());
+
+        let s_0 = self.encrypt_block(self.block(1, 0));
+        core::array::from_fn(|i| mac[i] ^ s_0[i])
+    }
+
+    fn ctr(&self, data: &mut [u8]) {
+        for (i, chunk) in data.chunks_mut(BLOCK_LEN).enumerate() {
+            let keystream = self.encrypt_block(self.block(1, i + 1));
+            chunk.iter_mut().zip(keystream).for_each(|(d, k)| *d ^= k);
+        }
+    }
+}
+
+/// Encrypts `data` in place, the tag goes into the last [`TAG_LEN`] bytes
+pub(crate) fn encrypt(key: &[u8; KEY_LEN], iv: &[u8; IV_LEN], aad: &[u8], data: &mut [u8]) {
+    let ccm = Ccm::new(key, iv);
+    let (plaintext, tag) = data.split_at_mut(data.len() - TAG_LEN);
+    tag.copy_from_slice(&ccm.mac(aad, plaintext));
+    ccm.ctr(plaintext);
+}
+
+/// Decrypts `data` in place, returns the plaintext without the tag
+pub(crate) fn decrypt<'d>(
+    key: &[u8; KEY_LEN],
+    iv: &[u8; IV_LEN],
+    aad: &[u8],
+    data: &'d mut [u8],
+) -> Option<&'d [u8]> {
+    let ccm = Ccm::new(key, iv);
+    let (plaintext, tag) = data.split_at_mut(data.len().checked_sub(TAG_LEN)?);
+    ccm.ctr(plaintext);
+    constant_time_eq(&ccm.mac(aad, plaintext), tag).then_some(plaintext)
+}
+
+/// Ed25519 with the message in parts, RFC 8032 5.1
+pub(crate) struct Ed25519 {
+    scalar: Scalar,
+    prefix: [u8; 32],
+    public_key: [u8; 32],
+}
+
+impl Ed25519 {
+    pub(crate) fn new(private_key: &[u8; 32]) -> Self {
+        let hash: [u8; 64] = Sha512::digest(private_key).into();
+        let mut scalar: [u8; 32] = hash[..32].try_into().unwrap();
+        scalar[0] &= 248;
+        scalar[31] &= 127;
+        scalar[31] |= 64;
+        let scalar = Scalar::from_bytes_mod_order(scalar);
+
+        Self {
+            scalar,
+            prefix: hash[32..].try_into().unwrap(),
+            public_key: EdwardsPoint::mul_base(&scalar).compress().to_bytes(),
+        }
+    }
+
+    pub(crate) fn public_key(&self) -> [u8; 32] {
+        self.public_key
+    }
+
+    pub(crate) fn sign(&self, message: &[&[u8]]) -> [u8; SIGNATURE_LEN] {
+        let mut hash = Sha512::new_with_prefix(self.prefix);
+        message.iter().for_each(|part| hash.update(part));
+        let r = Scalar::from_bytes_mod_order_wide(&hash.finalize().into());
+        let big_r = EdwardsPoint::mul_base(&r).compress().to_bytes();
+
+        let k = challenge(&big_r, &self.public_key, message);
+        let s = r + k * self.scalar;
+
+        let mut signature = [0u8; SIGNATURE_LEN];
+        signature[..32].copy_from_slice(&big_r);
+        signature[32..].copy_from_slice(s.as_bytes());
+        signature
+    }
+}
+
+fn challenge(big_r: &[u8; 32], public_key: &[u8; 32], message: &[&[u8]]) -> Scalar {
+    let mut hash = Sha512::new_with_prefix(big_r);
+    hash.update(public_key);
+    message.iter().for_each(|part| hash.update(part));
+    Scalar::from_bytes_mod_order_wide(&hash.finalize().into())
+}
+
+pub(crate) fn ed25519_verify(public_key: &[u8; 32], message: &[&[u8]], signature: &[u8]) -> bool {
+    let Ok(signature) = <[u8; SIGNATURE_LEN]>::try_from(signature) else {
+        return false;
+    };
+    let big_r: [u8; 32] = signature[..32].try_into().unwrap();
+    let (Some(a), Some(s)) = (
+        CompressedEdwardsY(*public_key).decompress(),
+        Option::<Scalar>::from(Scalar::from_canonical_bytes(
+            signature[32..].try_into().unwrap(),
+        )),
+    ) else {
+        return false;
+    };
+
+    // [S]B - [k]A == R
+    let k = challenge(&big_r, public_key, message);
+    EdwardsPoint::vartime_double_scalar_mul_basepoint(&k, &-a, &s)
+        .compress()
+        .to_bytes()
+        == big_r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex_to_vec;
+
+    #[test]
+    fn ccm() {
+        let key = core::array::from_fn(|i| i as u8);
+        let iv = core::array::from_fn(|i| 16 + i as u8);
+        let aad = b"Encrypt0 aad";
+        let plaintext = b"EDHOC plaintext, 33 bytes long!!!";
+
+        let mut data = [plaintext.as_slice(), &[0; TAG_LEN]].concat();
+        encrypt(&key, &iv, aad, &mut data);
+        assert_eq!(
+            data,
+            hex_to_vec(
+                "39 a5 38 0e fb 7c 9e b8 da 30 26 e5 4a 7f 18 ad \
+                 0a e0 66 59 1d ee ea 4f 4e 6d 76 bc e0 df a4 62 \
+                 24 c8 5a a8 e5 06 4b 01 1c"
+            )
+        );
+        assert_eq!(decrypt(&key, &iv, aad, &mut data), Some(&plaintext[..]));
+
+        let mut tag = [0u8; TAG_LEN];
+        encrypt(&key, &iv, aad, &mut tag);
+        assert_eq!(tag.to_vec(), hex_to_vec("8c e6 73 05 f7 84 8c 6a"));
+        tag[0] ^= 1;
+        assert_eq!(decrypt(&key, &iv, aad, &mut tag), None);
+    }
+
+    #[test]
+    fn ed25519() {
+        // RFC 8032 7.1, test 1
+        let private_key = hex_to_vec(
+            "9d 61 b1 9d ef fd 5a 60 ba 84 4a f4 92 ec 2c c4 \
+             44 49 c5 69 7b 32 69 19 70 3b ac 03 1c ae 7f 60",
+        );
+        let key = Ed25519::new(&private_key.try_into().unwrap());
+        assert_eq!(
+            key.public_key().to_vec(),
+            hex_to_vec(
+                "d7 5a 98 01 82 b1 0a b7 d5 4b fe d3 c9 64 07 3a \
+                 0e e1 72 f3 da a6 23 25 af 02 1a 68 f7 07 51 1a"
+            )
+        );
+        let signature = key.sign(&[]);
+        assert_eq!(
+            signature.to_vec(),
+            hex_to_vec(
+                "e5 56 43 00 c3 60 ac 72 90 86 e2 cc 80 6e 82 8a \
+                 84 87 7f 1e b8 e5 d9 74 d8 73 e0 65 22 49 01 55 \
+                 5f b8 82 15 90 a3 3b ac c6 1e 39 70 1c f9 b4 6b \
+                 d2 5b f5 f0 59 5b be 24 65 51 41 43 8e 7a 10 0b"
+            )
+        );
+        assert!(ed25519_verify(&key.public_key(), &[], &signature));
+
+        let signature = key.sign(&[b"a", b"bc"]);
+        assert_eq!(
+            signature.to_vec(),
+            hex_to_vec(
+                "80 d7 24 b0 1e 7c a2 60 f4 cc 7f 8d e7 c9 5f 73 \
+                 cf ac 61 5b ab 1f 76 2b 64 35 b6 ec 26 c8 cf 6d \
+                 2c 75 8d ae 2f 87 39 9a 8e ed a1 cb cd 28 35 ac \
+                 5b a6 6d 6e ca a3 ab a5 e5 67 a7 51 05 3d c2 07"
+            )
+        );
+        assert!(ed25519_verify(&key.public_key(), &[b"abc"], &signature));
+        assert!(!ed25519_verify(&key.public_key(), &[b"abd"], &signature));
+    }
+}
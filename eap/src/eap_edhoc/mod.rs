@@ -0,0 +1,540 @@
+//! EAP-EDHOC, see https://datatracker.ietf.org/doc/draft-ietf-emu-eap-edhoc/
+//!
+//! Runs EDHOC (RFC 9528) with cipher suite 0: AES-CCM-16-64-128, SHA-256,
+//! X25519 and EdDSA. The peer is the EDHOC Initiator, the server the Responder.
+//! Either side authenticates with a signature key or a static DH key, so all
+//! four EDHOC methods are supported.
+//!
+//! Credentials are identified by a kid and never sent by value, which keeps all
+//! messages far below the EAP MTU. Fragmented messages are not supported.
+
+mod auth;
+pub(crate) mod cbor;
+mod crypto;
+mod peer;
+
+pub use auth::AuthEdhocMethod;
+pub use peer::PeerEdhocMethod;
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    layers::{eap_layer::SessionKeys, SecurityClaims},
+    util::{constant_time_eq, ByteReader, OwnedSlice},
+    EapEnvironment, EapEnvironmentResponse, MessageBuilder,
+};
+use cbor::{MAJOR_BSTR, MAJOR_MAP, MAJOR_UINT};
+use crypto::{Ed25519, IV_LEN, KEY_LEN, SIGNATURE_LEN, TAG_LEN};
+
+/// The draft leaves the method type to IANA, this is its suggested value
+const METHOD_EDHOC: u8 = 111;
+
+/// EAP-EDHOC flags, the same as in EAP-TLS
+const FLAG_LENGTH: u8 = 0x80;
+const FLAG_MORE_FRAGMENTS: u8 = 0x40;
+const FLAG_START: u8 = 0x20;
+
+/// AES-CCM-16-64-128, SHA-256, 8, X25519, EdDSA, ...
+const SUITE_0: i8 = 0;
+
+const HASH_LEN: usize = 32;
+/// EDHOC MAC length of cipher suite 0
+const MAC_LEN: usize = 8;
+const KE_LEN: usize = 32;
+
+/// Connection identifiers. EAP has its own demultiplexing, so both sides use
+/// fixed one byte identifiers.
+const C_I: u8 = cbor::small_int(-24);
+const C_R: u8 = cbor::small_int(-8);
+
+/// ERR_CODE values, RFC 9528 6.1
+const ERR_UNSPECIFIED: i8 = 1;
+const ERR_WRONG_SUITE: i8 = 2;
+
+/// Labels of EDHOC_KDF, RFC 9528 4.1.2
+const LABEL_KEYSTREAM_2: u8 = 0;
+const LABEL_SALT_3E2M: u8 = 1;
+const LABEL_MAC_2: u8 = 2;
+const LABEL_K_3: u8 = 3;
+const LABEL_SALT_4E3M: u8 = 5;
+const LABEL_MAC_3: u8 = 6;
+const LABEL_PRK_OUT: u8 = 7;
+const LABEL_K_4: u8 = 8;
+const LABEL_PRK_EXPORTER: u8 = 10;
+
+/// EDHOC_Exporter labels of the MSK and the EMSK
+const EXPORTER_MSK: u8 = 26;
+const EXPORTER_EMSK: u8 = 27;
+const EXPORTER_METHOD_ID: u8 = 28;
+
+pub const MAX_KID_LEN: usize = 16;
+pub const MAX_CRED_LEN: usize = 128;
+
+/// PLAINTEXT_2: C_R, ID_CRED_R and Signature_or_MAC_2, EAD is not sent
+const MAX_PLAINTEXT_LEN: usize = 1 + 2 + MAX_KID_LEN + 2 + SIGNATURE_LEN;
+/// Byte string of G_Y and CIPHERTEXT_2, message_3 is shorter
+const MAX_MESSAGE_LEN: usize = 2 + KE_LEN + MAX_PLAINTEXT_LEN;
+
+/// CBOR header of a 32 byte byte string, for G_X, G_Y and the transcript hashes
+const BSTR_32: [u8; 2] = [MAJOR_BSTR | 24, 32];
+/// ID_CRED_x = {4: kid}
+const KID_LABEL: u8 = 4;
+
+type Hash = [u8; HASH_LEN];
+
+/// The public key a [`Credential`] authenticates with
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AuthenticationKey {
+    /// Ed25519 public key
+    Signature([u8; 32]),
+    /// X25519 public key
+    StaticDh([u8; 32]),
+}
+
+/// Credential of the other side. ID_CRED_x is `{4: kid}`, CRED_x the encoded
+/// credential, for example a CWT Claims Set.
+#[derive(Clone)]
+pub struct Credential {
+    kid: OwnedSlice<MAX_KID_LEN>,
+    cred: OwnedSlice<MAX_CRED_LEN>,
+    key: AuthenticationKey,
+}
+
+impl Credential {
+    /// `cred` has to contain `key`, it is not parsed
+    pub fn new(kid: &[u8], cred: &[u8], key: AuthenticationKey) -> Self {
+        Self {
+            kid: kid.try_into().expect("kid too long for nostd"),
+            cred: cred.try_into().expect("cred too long for nostd"),
+            key,
+        }
+    }
+
+    /// CWT Claims Set with `subject` and the public key as COSE_Key, RFC 9528
+    /// 3.5.2
+    pub fn ccs(kid: &[u8], subject: &str, key: AuthenticationKey) -> Self {
+        // crv Ed25519 or X25519
+        let (crv, x) = match key {
+            AuthenticationKey::Signature(x) => (6, x),
+            AuthenticationKey::StaticDh(x) => (4, x),
+        };
+        assert!(subject.len() < 64, "subject too long for nostd");
+        let mut cred = cbor::Writer::<MAX_CRED_LEN>::new();
+        // {2: subject, 8: {1: {1: OKP, 2: kid, -1: crv, -2: x}}}
+        cred.head(MAJOR_MAP, 2)
+            .int(2)
+            .tstr(subject)
+            .int(8)
+            .head(MAJOR_MAP, 1)
+            .int(1)
+            .head(MAJOR_MAP, 4)
+            .int(1)
+            .int(1)
+            .int(2)
+            .bstr(kid)
+            .int(-1)
+            .int(crv)
+            .int(-2)
+            .bstr(&x);
+        Self::new(kid, cred.as_slice(), key)
+    }
+
+    pub fn kid(&self) -> &[u8] {
+        self.kid.as_ref()
+    }
+
+    fn is_static_dh(&self) -> bool {
+        matches!(self.key, AuthenticationKey::StaticDh(_))
+    }
+
+    /// ID_CRED_x as map, covered by the MAC
+    fn id_cred(&self) -> cbor::Writer<{ 4 + MAX_KID_LEN }> {
+        let mut id_cred = cbor::Writer::new();
+        id_cred
+            .head(MAJOR_MAP, 1)
+            .head(MAJOR_UINT, KID_LABEL as usize)
+            .bstr(self.kid());
+        id_cred
+    }
+
+    /// ID_CRED_x in PLAINTEXT_x: only the kid, as integer if it encodes one,
+    /// RFC 9528 3.5.3.2
+    fn compact_id_cred(&self) -> cbor::Writer<{ 2 + MAX_KID_LEN }> {
+        let mut compact = cbor::Writer::new();
+        if cbor::is_small_int(self.kid()) {
+            compact.raw(self.kid());
+        } else {
+            compact.bstr(self.kid());
+        }
+        compact
+    }
+
+    /// Shared secret of the static DH key of this credential with an ephemeral
+    /// key, `Some(None)` for signature keys
+    fn static_dh(&self, ephemeral_secret: &[u8; KE_LEN]) -> Option<Option<Hash>> {
+        match self.key {
+            AuthenticationKey::StaticDh(public_key) => {
+                dh_shared_secret(ephemeral_secret, &public_key).map(Some)
+            }
+            AuthenticationKey::Signature(_) => Some(None),
+        }
+    }
+
+    /// Checks Signature_or_MAC_x of the owner of this credential
+    fn verify_signature_or_mac(&self, context: &MacContext, received: &[u8]) -> bool {
+        let mac = context.compute(self);
+        match self.key {
+            AuthenticationKey::StaticDh(_) => constant_time_eq(received, mac.as_slice()),
+            AuthenticationKey::Signature(public_key) => {
+                sig_structure(self, context.th, mac.as_slice(), |parts| {
+                    crypto::ed25519_verify(&public_key, parts, received)
+                })
+            }
+        }
+    }
+}
+
+/// Kid of a compact ID_CRED_x, see [`Credential::compact_id_cred`]
+fn compact_kid(encoded: &[u8]) -> Option<&[u8]> {
+    if cbor::is_small_int(encoded) {
+        return Some(encoded);
+    }
+    let kid = cbor::bstr(&mut ByteReader::new(encoded))?;
+    // Integer kids have to use the integer encoding
+    (!cbor::is_small_int(kid)).then_some(kid)
+}
+
+/// Own credential with the private key
+#[derive(Clone)]
+pub struct OwnCredential {
+    credential: Credential,
+    private_key: [u8; 32],
+}
+
+impl OwnCredential {
+    /// `private_key` is the Ed25519 or X25519 key of `credential`
+    pub fn new(credential: Credential, private_key: [u8; 32]) -> Self {
+        Self {
+            credential,
+            private_key,
+        }
+    }
+
+    /// CCS credential with an Ed25519 key, see [`Credential::ccs`]
+    pub fn signature(kid: &[u8], subject: &str, private_key: [u8; 32]) -> Self {
+        let public_key = Ed25519::new(&private_key).public_key();
+        let credential = Credential::ccs(kid, subject, AuthenticationKey::Signature(public_key));
+        Self::new(credential, private_key)
+    }
+
+    /// CCS credential with an X25519 key, see [`Credential::ccs`]
+    pub fn static_dh(kid: &[u8], subject: &str, private_key: [u8; 32]) -> Self {
+        let public_key = dh_public_key(&private_key);
+        let credential = Credential::ccs(kid, subject, AuthenticationKey::StaticDh(public_key));
+        Self::new(credential, private_key)
+    }
+
+    /// The credential the other side has to know
+    pub fn credential(&self) -> &Credential {
+        &self.credential
+    }
+
+    /// Signature_or_MAC_x, RFC 9528 5.3.2
+    fn signature_or_mac(&self, context: &MacContext) -> cbor::Writer<SIGNATURE_LEN> {
+        let credential = &self.credential;
+        let mac = context.compute(credential);
+        let mut out = cbor::Writer::new();
+        match credential.key {
+            AuthenticationKey::StaticDh(_) => out.raw(mac.as_slice()),
+            AuthenticationKey::Signature(_) => {
+                let key = Ed25519::new(&self.private_key);
+                out.raw(&sig_structure(
+                    credential,
+                    context.th,
+                    mac.as_slice(),
+                    |parts| key.sign(parts),
+                ))
+            }
+        };
+        out
+    }
+
+    /// Shared secret of the own static DH key with an ephemeral public key,
+    /// `Some(None)` for signature keys
+    fn shared_secret(&self, public_key: &[u8; KE_LEN]) -> Option<Option<Hash>> {
+        match self.credential.key {
+            AuthenticationKey::StaticDh(_) => {
+                dh_shared_secret(&self.private_key, public_key).map(Some)
+            }
+            AuthenticationKey::Signature(_) => Some(None),
+        }
+    }
+}
+
+fn dh_public_key(secret: &[u8; KE_LEN]) -> [u8; KE_LEN] {
+    x25519_dalek::x25519(*secret, x25519_dalek::X25519_BASEPOINT_BYTES)
+}
+
+fn dh_shared_secret(secret: &[u8; KE_LEN], public_key: &[u8; KE_LEN]) -> Option<[u8; KE_LEN]> {
+    let shared_secret = x25519_dalek::x25519(*secret, *public_key);
+    (shared_secret != [0; KE_LEN]).then_some(shared_secret)
+}
+
+/// METHOD of message_1, RFC 9528 3.2
+fn method(initiator: &Credential, responder: &Credential) -> i8 {
+    2 * initiator.is_static_dh() as i8 + responder.is_static_dh() as i8
+}
+
+fn hmac(key: &[u8]) -> Hmac<Sha256> {
+    <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap()
+}
+
+fn hash(parts: &[&[u8]]) -> Hash {
+    let mut hash = Sha256::new();
+    parts.iter().for_each(|part| hash.update(part));
+    hash.finalize().into()
+}
+
+/// EDHOC_Extract, HKDF-Extract with SHA-256
+fn extract(salt: &[u8], ikm: &[u8]) -> Hash {
+    let mut mac = hmac(salt);
+    mac.update(ikm);
+    mac.finalize().into_bytes().into()
+}
+
+/// EDHOC_KDF(PRK, label, context, length) with the context in parts, the info
+/// is the CBOR sequence (label, bstr context, length), RFC 9528 4.1.2
+fn kdf(prk: &Hash, label: u8, context: &[&[u8]], out: &mut [u8]) {
+    let (label_head, label_len) = cbor::header(MAJOR_UINT, label as usize);
+    let context_len = context.iter().map(|part| part.len()).sum();
+    let (context_head, context_head_len) = cbor::header(MAJOR_BSTR, context_len);
+    let (length_head, length_len) = cbor::header(MAJOR_UINT, out.len());
+
+    let mut previous: Option<Hash> = None;
+    for (i, chunk) in out.chunks_mut(HASH_LEN).enumerate() {
+        let mut mac = hmac(prk);
+        if let Some(previous) = &previous {
+            mac.update(previous);
+        }
+        mac.update(&label_head[..label_len]);
+        mac.update(&context_head[..context_head_len]);
+        context.iter().for_each(|part| mac.update(part));
+        mac.update(&length_head[..length_len]);
+        mac.update(&[i as u8 + 1]);
+        let block: Hash = mac.finalize().into_bytes().into();
+        chunk.copy_from_slice(&block[..chunk.len()]);
+        previous = Some(block);
+    }
+}
+
+fn kdf_array<const N: usize>(prk: &Hash, label: u8, context: &[&[u8]]) -> [u8; N] {
+    let mut out = [0u8; N];
+    kdf(prk, label, context, &mut out);
+    out
+}
+
+/// PRK_3e2m from PRK_2e or PRK_4e3m from PRK_3e2m. The shared secret with the
+/// static DH key of the authenticating side goes into the next key, RFC 9528
+/// 4.1.1.
+fn static_dh_prk(prk: &Hash, salt_label: u8, th: &Hash, shared_secret: Option<Hash>) -> Hash {
+    match shared_secret {
+        Some(shared_secret) => extract(
+            &kdf_array::<HASH_LEN>(prk, salt_label, &[th]),
+            &shared_secret,
+        ),
+        None => *prk,
+    }
+}
+
+/// Everything MAC_2 or MAC_3 covers besides ID_CRED_x and CRED_x
+struct MacContext<'a> {
+    prk: &'a Hash,
+    label: u8,
+    /// C_R for MAC_2, empty for MAC_3
+    c_r: &'a [u8],
+    th: &'a Hash,
+}
+
+impl MacContext<'_> {
+    /// MAC_x = EDHOC_KDF(PRK, label, C_R | ID_CRED_x | TH_x | CRED_x, length),
+    /// the length is that of a hash for signature keys
+    fn compute(&self, credential: &Credential) -> cbor::Writer<HASH_LEN> {
+        let mut mac = cbor::Writer::new();
+        let len = match credential.key {
+            AuthenticationKey::StaticDh(_) => MAC_LEN,
+            AuthenticationKey::Signature(_) => HASH_LEN,
+        };
+        mac.raw(&[0; HASH_LEN][..len]);
+        kdf(
+            self.prk,
+            self.label,
+            &[
+                self.c_r,
+                credential.id_cred().as_slice(),
+                &BSTR_32,
+                self.th,
+                credential.cred.as_ref(),
+            ],
+            mac.as_mut_slice(),
+        );
+        mac
+    }
+}
+
+/// Sig_structure = ["Signature1", << ID_CRED_x >>, << TH_x, CRED_x >>, MAC_x],
+/// RFC 9528 5.3.2, passed to `f` in parts
+fn sig_structure<R>(
+    credential: &Credential,
+    th: &Hash,
+    mac: &[u8],
+    f: impl FnOnce(&[&[u8]]) -> R,
+) -> R {
+    let id_cred = credential.id_cred();
+    let cred = credential.cred.as_ref();
+    let mut heads = cbor::Writer::<32>::new();
+    heads
+        .raw(&[cbor::MAJOR_ARRAY | 4])
+        .tstr("Signature1")
+        .head(MAJOR_BSTR, id_cred.as_slice().len());
+    let id_cred_end = heads.as_slice().len();
+    heads.head(MAJOR_BSTR, BSTR_32.len() + HASH_LEN + cred.len());
+    let external_end = heads.as_slice().len();
+    heads.head(MAJOR_BSTR, mac.len());
+    let heads = heads.as_slice();
+
+    f(&[
+        &heads[..id_cred_end],
+        id_cred.as_slice(),
+        &heads[id_cred_end..external_end],
+        &BSTR_32,
+        th,
+        cred,
+        &heads[external_end..],
+        mac,
+    ])
+}
+
+/// A_3 / A_4 = ["Encrypt0", h'', TH_x], RFC 9528 5.4.2
+fn enc_structure(th: &Hash) -> [u8; 13 + HASH_LEN] {
+    let mut aad = [0u8; 13 + HASH_LEN];
+    aad[..11].copy_from_slice(b"\x83\x68Encrypt0\x40");
+    aad[11..13].copy_from_slice(&BSTR_32);
+    aad[13..].copy_from_slice(th);
+    aad
+}
+
+/// K_3 and IV_3 from PRK_3e2m and TH_3, or K_4 and IV_4 from PRK_4e3m and TH_4.
+/// The label of the IV follows that of the key.
+fn aead_key(prk: &Hash, key_label: u8, th: &Hash) -> ([u8; KEY_LEN], [u8; IV_LEN]) {
+    (
+        kdf_array(prk, key_label, &[th]),
+        kdf_array(prk, key_label + 1, &[th]),
+    )
+}
+
+/// MSK, EMSK and Method-Id from PRK_out with EDHOC_Exporter, RFC 9528
+/// 4.2.1. The Session-ID is the Type and the Method-Id.
+fn session_keys(prk_4e3m: &Hash, th_4: &Hash) -> SessionKeys {
+    let prk_out: Hash = kdf_array(prk_4e3m, LABEL_PRK_OUT, &[th_4]);
+    let prk_exporter: Hash = kdf_array(&prk_out, LABEL_PRK_EXPORTER, &[]);
+    let method_id: [u8; 64] = kdf_array(&prk_exporter, EXPORTER_METHOD_ID, &[]);
+    SessionKeys {
+        msk: kdf_array(&prk_exporter, EXPORTER_MSK, &[]),
+        emsk: kdf_array(&prk_exporter, EXPORTER_EMSK, &[]),
+        session_id: SessionKeys::session_id(METHOD_EDHOC, &[&method_id]),
+    }
+}
+
+/// EDHOC message of an EAP-EDHOC packet. The length field is accepted for a
+/// message in one packet.
+fn parse_packet(msg: &[u8]) -> Option<(u8, &[u8])> {
+    let (&flags, data) = msg.split_first()?;
+    if flags & FLAG_MORE_FRAGMENTS != 0 {
+        return None;
+    }
+    if flags & FLAG_LENGTH == 0 {
+        return Some((flags, data));
+    }
+    let (length, data) = data.split_at_checked(4)?;
+    (u32::from_be_bytes(length.try_into().unwrap()) as usize == data.len()).then_some((flags, data))
+}
+
+/// EDHOC error message in place of message_2, message_3 or message_4, which
+/// are byte strings, RFC 9528 6
+fn is_error(message: &[u8]) -> bool {
+    matches!(message.first(), Some(initial) if initial & 0xe0 <= cbor::MAJOR_NINT)
+}
+
+/// SUITES_I of message_1, the selected suite is the last one of an array
+fn selected_suite(reader: &mut ByteReader) -> Option<i32> {
+    if reader.remaining().first()? & 0xe0 != cbor::MAJOR_ARRAY {
+        return cbor::int(reader);
+    }
+    let mut selected = None;
+    for _ in 0..cbor::array_len(reader)? {
+        selected = Some(cbor::int(reader)?);
+    }
+    selected
+}
+
+/// Skips EAD items, `None` if one of them is critical, RFC 9528 3.8
+fn skip_ead(reader: &mut ByteReader) -> Option<()> {
+    while !reader.is_empty() {
+        if cbor::int(reader)? < 0 {
+            return None;
+        }
+        if reader.remaining().first().map(|initial| initial & 0xe0) == Some(MAJOR_BSTR) {
+            cbor::bstr(reader)?;
+        }
+    }
+    Some(())
+}
+
+/// EAP-EDHOC packet with an error message, ERR_CODE 1 with a text or ERR_CODE
+/// 2 with the supported suite
+fn error_message(env: &mut dyn EapEnvironment, code: i8) -> MessageBuilder<'_> {
+    let mut message = cbor::Writer::<32>::new();
+    message.int(code);
+    match code {
+        ERR_WRONG_SUITE => message.int(SUITE_0),
+        _ => message.tstr("EAP-EDHOC failure"),
+    };
+    env.respond().write(&[0]).write(message.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex_to_vec;
+
+    #[test]
+    fn kdf_expand() {
+        // HKDF-Expand with the CBOR info of RFC 9528 4.1.2
+        let prk = core::array::from_fn(|i| i as u8);
+        let mut out = [0u8; 40];
+        kdf(&prk, 2, &[b"con", b"text"], &mut out);
+        assert_eq!(
+            out.to_vec(),
+            hex_to_vec(
+                "62 9d dc b8 88 61 5f 57 e9 7e 2d 14 bb a0 73 6a \
+                 45 95 d6 9c 85 5f af f2 a7 41 9d f8 19 5d 84 05 \
+                 d0 6f 8e 2c 4f 45 84 df"
+            )
+        );
+    }
+
+    #[test]
+    fn compact_id_cred() {
+        let key = AuthenticationKey::StaticDh([0; 32]);
+        let credential = Credential::new(&[0x2b], &[], key);
+        assert_eq!(credential.compact_id_cred().as_slice(), [0x2b]);
+        assert_eq!(credential.id_cred().as_slice(), [0xa1, 0x04, 0x41, 0x2b]);
+        assert_eq!(compact_kid(&[0x2b]), Some(&[0x2b][..]));
+
+        let credential = Credential::new(b"kid", &[], key);
+        assert_eq!(credential.compact_id_cred().as_slice(), b"\x43kid");
+        assert_eq!(compact_kid(b"\x43kid"), Some(&b"kid"[..]));
+        assert_eq!(compact_kid(&[0x41, 0x2b]), None);
+    }
+}
@@ -0,0 +1,312 @@
+use crate::layers::{
+    eap_layer::{Decision, MethodState},
+    mux::TupleElement,
+    peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta},
+    MethodType,
+};
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    WaitStart,
+    WaitMessage2,
+    WaitMessage4,
+    Done,
+}
+
+/// Peer side of EAP-EDHOC, the EDHOC Initiator.
+///
+/// `server` is the credential the server has to authenticate with.
+#[derive(Clone)]
+pub struct PeerEdhocMethod {
+    credential: OwnCredential,
+    server: Credential,
+    state: State,
+    /// Ephemeral X25519 key
+    x: [u8; KE_LEN],
+    h_message_1: Hash,
+    prk_4e3m: Hash,
+    th_4: Hash,
+    session_keys: Option<SessionKeys>,
+}
+
+impl TupleElement for PeerEdhocMethod {
+    type Target = dyn PeerMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl PeerEdhocMethod {
+    pub fn new(credential: OwnCredential, server: Credential) -> Self {
+        Self {
+            credential,
+            server,
+            state: State::WaitStart,
+            x: [0; KE_LEN],
+            h_message_1: [0; HASH_LEN],
+            prk_4e3m: [0; HASH_LEN],
+            th_4: [0; HASH_LEN],
+            session_keys: None,
+        }
+    }
+
+    fn fail<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> PeerMethodLayerResult<'a> {
+        self.state = State::Done;
+        self.session_keys = None;
+        PeerMethodLayerResult::Send(error_message(env, ERR_UNSPECIFIED))
+    }
+
+    /// message_1 = (METHOD, SUITES_I, G_X, C_I)
+    fn recv_start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> PeerMethodLayerResult<'a> {
+        self.session_keys = None;
+        env.fill_random(&mut self.x);
+
+        let mut message_1 = cbor::Writer::<{ 4 + 2 + KE_LEN }>::new();
+        message_1
+            .int(method(self.credential.credential(), &self.server))
+            .int(SUITE_0)
+            .bstr(&dh_public_key(&self.x))
+            .raw(&[C_I]);
+        self.h_message_1 = hash(&[message_1.as_slice()]);
+
+        self.state = State::WaitMessage2;
+        PeerMethodLayerResult::Send(env.respond().write(&[0]).write(message_1.as_slice()))
+    }
+
+    /// message_2 = bstr(G_Y | CIPHERTEXT_2), answered by message_3
+    fn recv_message_2<'a>(
+        &mut self,
+        message: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let mut reader = ByteReader::new(message);
+        let Some((g_y, ciphertext_2)) = cbor::bstr(&mut reader)
+            .filter(|_| reader.is_empty())
+            .and_then(|data| data.split_at_checked(KE_LEN))
+            .filter(|(_, ciphertext_2)| ciphertext_2.len() <= MAX_PLAINTEXT_LEN)
+        else {
+            return self.fail(env);
+        };
+        let g_y: [u8; KE_LEN] = g_y.try_into().unwrap();
+        let Some(g_xy) = dh_shared_secret(&self.x, &g_y) else {
+            return self.fail(env);
+        };
+
+        // TH_2 = H(G_Y, H(message_1)), PRK_2e = EDHOC_Extract(TH_2, G_XY)
+        let th_2 = hash(&[&BSTR_32, &g_y, &BSTR_32, &self.h_message_1]);
+        let prk_2e = extract(&th_2, &g_xy);
+        let mut plaintext_2 = [0u8; MAX_PLAINTEXT_LEN];
+        let plaintext_2 = &mut plaintext_2[..ciphertext_2.len()];
+        kdf(&prk_2e, LABEL_KEYSTREAM_2, &[&th_2], plaintext_2);
+        plaintext_2
+            .iter_mut()
+            .zip(ciphertext_2)
+            .for_each(|(p, c)| *p ^= c);
+        let plaintext_2 = &*plaintext_2;
+
+        // PLAINTEXT_2 = (C_R, ID_CRED_R, Signature_or_MAC_2, ? EAD_2)
+        let mut reader = ByteReader::new(plaintext_2);
+        let (Some(c_r), Some(id_cred_r), Some(signature_or_mac_2), Some(())) = (
+            cbor::int_or_bstr(&mut reader),
+            cbor::int_or_bstr(&mut reader).and_then(compact_kid),
+            cbor::bstr(&mut reader),
+            skip_ead(&mut reader),
+        ) else {
+            return self.fail(env);
+        };
+        if id_cred_r != self.server.kid() {
+            return self.fail(env);
+        }
+        let Some(g_rx) = self.server.static_dh(&self.x) else {
+            return self.fail(env);
+        };
+        let prk_3e2m = static_dh_prk(&prk_2e, LABEL_SALT_3E2M, &th_2, g_rx);
+        let mac_2 = MacContext {
+            prk: &prk_3e2m,
+            label: LABEL_MAC_2,
+            c_r,
+            th: &th_2,
+        };
+        if !self
+            .server
+            .verify_signature_or_mac(&mac_2, signature_or_mac_2)
+        {
+            return self.fail(env);
+        }
+
+        // TH_3 = H(TH_2, PLAINTEXT_2, CRED_R)
+        let th_3 = hash(&[&BSTR_32, &th_2, plaintext_2, self.server.cred.as_ref()]);
+        let Some(g_iy) = self.credential.shared_secret(&g_y) else {
+            return self.fail(env);
+        };
+        let prk_4e3m = static_dh_prk(&prk_3e2m, LABEL_SALT_4E3M, &th_3, g_iy);
+        let signature_or_mac_3 = self.credential.signature_or_mac(&MacContext {
+            prk: &prk_4e3m,
+            label: LABEL_MAC_3,
+            c_r: &[],
+            th: &th_3,
+        });
+
+        // PLAINTEXT_3 = (ID_CRED_I, Signature_or_MAC_3), encrypted with K_3
+        let credential = self.credential.credential();
+        let mut ciphertext_3 = cbor::Writer::<{ MAX_PLAINTEXT_LEN + TAG_LEN }>::new();
+        ciphertext_3
+            .raw(credential.compact_id_cred().as_slice())
+            .bstr(signature_or_mac_3.as_slice());
+        self.th_4 = hash(&[
+            &BSTR_32,
+            &th_3,
+            ciphertext_3.as_slice(),
+            credential.cred.as_ref(),
+        ]);
+        ciphertext_3.raw(&[0; TAG_LEN]);
+        let (k_3, iv_3) = aead_key(&prk_3e2m, LABEL_K_3, &th_3);
+        crypto::encrypt(
+            &k_3,
+            &iv_3,
+            &enc_structure(&th_3),
+            ciphertext_3.as_mut_slice(),
+        );
+
+        self.prk_4e3m = prk_4e3m;
+        self.state = State::WaitMessage4;
+        let (header, header_len) = cbor::header(MAJOR_BSTR, ciphertext_3.as_slice().len());
+        PeerMethodLayerResult::Send(
+            env.respond()
+                .write(&[0])
+                .write(&header[..header_len])
+                .write(ciphertext_3.as_slice()),
+        )
+    }
+
+    /// message_4 = bstr(CIPHERTEXT_4), answered by an empty response
+    fn recv_message_4<'a>(
+        &mut self,
+        message: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let mut reader = ByteReader::new(message);
+        let mut ciphertext_4 = [0u8; MAX_PLAINTEXT_LEN];
+        let Some(ciphertext_4) = cbor::bstr(&mut reader)
+            .filter(|data| reader.is_empty() && data.len() <= MAX_PLAINTEXT_LEN)
+            .map(|data| {
+                ciphertext_4[..data.len()].copy_from_slice(data);
+                &mut ciphertext_4[..data.len()]
+            })
+        else {
+            return self.fail(env);
+        };
+
+        let (k_4, iv_4) = aead_key(&self.prk_4e3m, LABEL_K_4, &self.th_4);
+        let Some(plaintext_4) =
+            crypto::decrypt(&k_4, &iv_4, &enc_structure(&self.th_4), ciphertext_4)
+        else {
+            return self.fail(env);
+        };
+        if skip_ead(&mut ByteReader::new(plaintext_4)).is_none() {
+            return self.fail(env);
+        }
+
+        self.session_keys = Some(session_keys(&self.prk_4e3m, &self.th_4));
+        self.state = State::Done;
+        PeerMethodLayerResult::Send(env.respond().write(&[0]))
+    }
+}
+
+impl PeerMethodLayer for PeerEdhocMethod {
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_EDHOC)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_EDHOC
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let Some((flags, message)) = parse_packet(msg) else {
+            return self.fail(env);
+        };
+        match self.state {
+            _ if flags & FLAG_START != 0 => self.recv_start(env),
+            // The server gave up
+            State::WaitMessage2 | State::WaitMessage4 if is_error(message) => {
+                self.state = State::Done;
+                PeerMethodLayerResult::Failed(env)
+            }
+            State::WaitMessage2 => self.recv_message_2(message, env),
+            State::WaitMessage4 => self.recv_message_4(message, env),
+            _ => self.fail(env),
+        }
+    }
+
+    fn method_state(&self) -> MethodState {
+        if self.session_keys.is_some() {
+            MethodState::Done
+        } else {
+            MethodState::MayCont
+        }
+    }
+
+    fn decision(&self) -> Decision {
+        if self.session_keys.is_some() {
+            Decision::CondSucc
+        } else {
+            Decision::Fail
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.session_keys.as_ref()
+    }
+
+    fn reset(&mut self) {
+        self.state = State::WaitStart;
+        self.session_keys = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layers::{
+        auth::AuthIdentityMethod, peer::PeerIdentityMethod, AuthLayer, EapLayer, PeerLayer,
+    };
+    use crate::test_util::assert_static_conversation;
+
+    use super::*;
+
+    #[test]
+    fn edhoc_static_environment() {
+        let server = OwnCredential::static_dh(&[0x21], "server", [2; 32]);
+        let sensor = OwnCredential::signature(&[0x05], "sensor-1", [3; 32]);
+        let sensor_credential = sensor.credential().clone();
+
+        let peer = EapLayer::new(
+            PeerLayer::new()
+                .with(PeerIdentityMethod::new(b"sensor-1"))
+                .with(PeerEdhocMethod::new(sensor, server.credential().clone())),
+        );
+        let auth = EapLayer::new(AuthLayer::new().with(AuthIdentityMethod::new()).with(
+            AuthEdhocMethod::new(server, move |kid: &[u8]| {
+                (kid == sensor_credential.kid()).then(|| sensor_credential.clone())
+            }),
+        ));
+
+        assert_static_conversation(peer, auth, 14);
+    }
+}
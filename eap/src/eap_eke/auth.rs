@@ -0,0 +1,278 @@
+use crate::{
+    layers::{
+        auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta},
+        eap_layer::SessionKeys,
+        mux::TupleElement,
+        MethodType,
+    },
+    message::MessageCode,
+    util::{constant_time_eq, OwnedSlice},
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Start,
+    WaitId,
+    WaitCommit,
+    WaitConfirm,
+    /// EAP-EKE-Failure was sent, waiting for the answer of the peer
+    Failing,
+    Done,
+}
+
+/// Server side of EAP-EKE.
+///
+/// `password_lookup` returns the password for the peer identity (ID_P).
+#[derive(Clone)]
+pub struct AuthEkeMethod<F> {
+    id_s: OwnedSlice<MAX_IDENTITY_LEN>,
+    password_lookup: F,
+    state: State,
+    id_p: OwnedSlice<MAX_IDENTITY_LEN>,
+    /// Protects the DH components, derived from the password
+    password_key: Block,
+    dh_key: DhKey,
+    keys: Option<Keys>,
+    nonce_s: Block,
+    msgs: Transcript,
+    /// Auth_P the peer has to answer with
+    expected_auth_p: [u8; PRF_LEN],
+    session_keys: Option<SessionKeys>,
+}
+
+impl<F> TupleElement for AuthEkeMethod<F>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<MAX_PASSWORD_LEN>> + 'static,
+{
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<F> AuthEkeMethod<F>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<MAX_PASSWORD_LEN>>,
+{
+    pub fn new(id_s: &[u8], password_lookup: F) -> Self {
+        Self {
+            id_s: id_s.try_into().expect("id_s too long for nostd"),
+            password_lookup,
+            state: State::Start,
+            id_p: OwnedSlice::new(),
+            password_key: [0; KEY_LEN],
+            dh_key: DhKey::new([0; EXPONENT_LEN]),
+            keys: None,
+            nonce_s: [0; NONCE_LEN],
+            msgs: Transcript::new(),
+            expected_auth_p: [0; PRF_LEN],
+            session_keys: None,
+        }
+    }
+
+    fn fail<'a>(
+        &mut self,
+        code: u32,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        self.state = State::Failing;
+        self.session_keys = None;
+        AuthMethodLayerResult::Send(
+            env.respond()
+                .write(&[EXCH_FAILURE])
+                .write(&code.to_be_bytes()),
+        )
+    }
+
+    fn recv_id<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        // The peer selects exactly one of the offered proposals
+        if msg.get(1) != Some(&1) {
+            return self.fail(FAILURE_PROTOCOL_ERROR, env);
+        }
+        let Some(id_p) = parse_id(msg, |proposal| proposal == PROPOSAL) else {
+            return self.fail(FAILURE_NO_PROPOSAL_CHOSEN, env);
+        };
+        self.id_p = id_p.try_into().unwrap();
+
+        let Some(password) = (self.password_lookup)(id_p) else {
+            return self.fail(FAILURE_PASSWORD_NOT_FOUND, env);
+        };
+        self.password_key = password_key(password.as_ref(), self.id_s.as_ref(), id_p);
+
+        let mut exponent = [0u8; EXPONENT_LEN];
+        env.fill_random(&mut exponent);
+        self.dh_key = DhKey::new(exponent);
+
+        let mut iv = [0u8; BLOCK_LEN];
+        env.fill_random(&mut iv);
+        let dh_component = self.dh_key.dh_component(&self.password_key, &iv);
+
+        // The ID request was sent with the identifier of its response
+        let identifier = meta.message.identifier;
+        self.msgs = Transcript::new();
+        self.msgs.push(
+            MessageCode::Request,
+            identifier,
+            &[&ID_HEADER, self.id_s.as_ref()],
+        );
+        self.msgs.push(MessageCode::Response, identifier, &[msg]);
+        self.msgs.push(
+            MessageCode::Request,
+            identifier.wrapping_add(1),
+            &[&[EXCH_COMMIT], &dh_component],
+        );
+
+        self.state = State::WaitCommit;
+        AuthMethodLayerResult::Send(env.respond().write(&[EXCH_COMMIT]).write(&dh_component))
+    }
+
+    fn recv_commit<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        // DHComponent_P | PNonce_P
+        if msg.len() != 1 + DH_COMPONENT_LEN + protected_len(NONCE_LEN) {
+            return self.fail(FAILURE_PROTOCOL_ERROR, env);
+        }
+        let (dh_component, pnonce_p) = msg[1..].split_at(DH_COMPONENT_LEN);
+
+        let y_p = decrypt_dh_component(&self.password_key, dh_component);
+        let Some(shared_secret) = self.dh_key.shared_secret(&y_p) else {
+            return self.fail(FAILURE_AUTHENTICATION, env);
+        };
+        let keys = Keys::derive(shared_secret, self.id_s.as_ref(), self.id_p.as_ref());
+
+        // A wrong password shows here
+        let mut nonce_p = [0u8; NONCE_LEN];
+        if !keys.unprotect(pnonce_p, &mut nonce_p) {
+            return self.fail(FAILURE_AUTHENTICATION, env);
+        }
+        self.msgs
+            .push(MessageCode::Response, meta.message.identifier, &[msg]);
+
+        env.fill_random(&mut self.nonce_s);
+        let mut iv = [0u8; BLOCK_LEN];
+        env.fill_random(&mut iv);
+
+        let mut nonces = [0u8; 2 * NONCE_LEN];
+        nonces[..NONCE_LEN].copy_from_slice(&nonce_p);
+        nonces[NONCE_LEN..].copy_from_slice(&self.nonce_s);
+        let mut pnonce_ps = [0u8; protected_len(2 * NONCE_LEN)];
+        keys.protect(&iv, &nonces, &mut pnonce_ps);
+
+        let (id_s, id_p) = (self.id_s.as_ref(), self.id_p.as_ref());
+        let ka = keys.ka(id_s, id_p, &nonce_p, &self.nonce_s);
+        let auth_s = self.msgs.auth(&ka, b"EAP-EKE server");
+
+        self.expected_auth_p = self.msgs.auth(&ka, b"EAP-EKE peer");
+        self.session_keys = Some(keys.session_keys(id_s, id_p, &nonce_p, &self.nonce_s));
+        self.keys = Some(keys);
+
+        self.state = State::WaitConfirm;
+        AuthMethodLayerResult::Send(
+            env.respond()
+                .write(&[EXCH_CONFIRM])
+                .write(&pnonce_ps)
+                .write(&auth_s),
+        )
+    }
+
+    fn recv_confirm<'a>(
+        &mut self,
+        msg: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        // PNonce_S | Auth_P
+        let Some(keys) = &self.keys else {
+            return self.fail(FAILURE_PROTOCOL_ERROR, env);
+        };
+        if msg.len() != 1 + protected_len(NONCE_LEN) + PRF_LEN {
+            return self.fail(FAILURE_PROTOCOL_ERROR, env);
+        }
+        let (pnonce_s, auth_p) = msg[1..].split_at(protected_len(NONCE_LEN));
+
+        let mut nonce_s = [0u8; NONCE_LEN];
+        if !keys.unprotect(pnonce_s, &mut nonce_s)
+            || nonce_s != self.nonce_s
+            || !constant_time_eq(auth_p, &self.expected_auth_p)
+        {
+            return self.fail(FAILURE_AUTHENTICATION, env);
+        }
+
+        env.set_name(self.id_p.as_ref());
+        self.state = State::Done;
+        AuthMethodLayerResult::Finished(env)
+    }
+}
+
+impl<F> AuthMethodLayer for AuthEkeMethod<F>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<MAX_PASSWORD_LEN>>,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_EKE)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_EKE
+    }
+
+    // The transcript includes the Identifiers of the Requests
+    fn binds_identifier(&self) -> bool {
+        true
+    }
+
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.keys = None;
+        self.session_keys = None;
+        self.state = State::WaitId;
+
+        AuthMethodLayerResult::Send(env.respond().write(&ID_HEADER).write(self.id_s.as_ref()))
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        match (self.state, msg.first()) {
+            (State::WaitId, Some(&EXCH_ID)) => self.recv_id(msg, meta, env),
+            (State::WaitCommit, Some(&EXCH_COMMIT)) => self.recv_commit(msg, meta, env),
+            (State::WaitConfirm, Some(&EXCH_CONFIRM)) => self.recv_confirm(msg, env),
+            // The peer answered our EAP-EKE-Failure or sent its own
+            (_, Some(&EXCH_FAILURE)) | (State::Failing, _) => {
+                self.state = State::Failing;
+                self.session_keys = None;
+                AuthMethodLayerResult::Failed(env)
+            }
+            _ => self.fail(FAILURE_PROTOCOL_ERROR, env),
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        match self.state {
+            State::Done => self.session_keys.as_ref(),
+            _ => None,
+        }
+    }
+}
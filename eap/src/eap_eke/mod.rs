@@ -0,0 +1,403 @@
+//! EAP-EKE, see https://www.rfc-editor.org/rfc/rfc6124
+//!
+//! Only the DH group 14 / AES-128-CBC / HMAC-SHA256 / HMAC-SHA256 proposal is
+//! implemented.
+
+mod auth;
+mod peer;
+
+pub use auth::AuthEkeMethod;
+pub use peer::PeerEkeMethod;
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use crypto_bigint::{
+    modular::runtime_mod::{DynResidue, DynResidueParams},
+    Encoding, U2048, U256,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    layers::{eap_layer::SessionKeys, SecurityClaims},
+    message::MessageCode,
+    util::constant_time_eq,
+};
+
+const METHOD_EKE: u8 = 53;
+
+const EXCH_ID: u8 = 1;
+const EXCH_COMMIT: u8 = 2;
+const EXCH_CONFIRM: u8 = 3;
+const EXCH_FAILURE: u8 = 4;
+
+const FAILURE_NO_ERROR: u32 = 1;
+const FAILURE_PROTOCOL_ERROR: u32 = 2;
+const FAILURE_PASSWORD_NOT_FOUND: u32 = 3;
+const FAILURE_AUTHENTICATION: u32 = 4;
+const FAILURE_NO_PROPOSAL_CHOSEN: u32 = 6;
+
+const GROUP_EKE_14: u8 = 3;
+const ENCR_AES128_CBC: u8 = 1;
+const PRF_HMAC_SHA256: u8 = 2;
+const MAC_HMAC_SHA256: u8 = 2;
+
+const PROPOSAL: [u8; 4] = [
+    GROUP_EKE_14,
+    ENCR_AES128_CBC,
+    PRF_HMAC_SHA256,
+    MAC_HMAC_SHA256,
+];
+const PROPOSAL_LEN: usize = 4;
+
+const ID_NAI: u8 = 1;
+
+/// EAP-EKE-ID payload up to the identity: one proposal, reserved byte and the
+/// ID type. The server offers only [`PROPOSAL`], so the request and the response
+/// share it.
+const ID_HEADER: [u8; 8] = [
+    EXCH_ID,
+    1,
+    0,
+    PROPOSAL[0],
+    PROPOSAL[1],
+    PROPOSAL[2],
+    PROPOSAL[3],
+    ID_NAI,
+];
+
+pub const MAX_IDENTITY_LEN: usize = 64;
+pub const MAX_PASSWORD_LEN: usize = 64;
+
+/// RFC 3526 group 14. EKE uses 11 as generator, a primitive root of the group,
+/// RFC 6124 5.1.
+const PRIME: U2048 = U2048::from_be_hex(concat!(
+    "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74",
+    "020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437",
+    "4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED",
+    "EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05",
+    "98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB",
+    "9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B",
+    "E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF695581718",
+    "3995497CEA956AE515D2261898FA051015728E5A8AACAA68FFFFFFFFFFFFFFFF",
+));
+const GENERATOR: u8 = 11;
+const PRIME_LEN: usize = 256;
+
+/// Private exponents are 256 bits, twice the security level of the group
+const EXPONENT_LEN: usize = 32;
+
+const BLOCK_LEN: usize = 16;
+const KEY_LEN: usize = 16;
+const PRF_LEN: usize = 32;
+const MAC_LEN: usize = 32;
+const NONCE_LEN: usize = 16;
+
+/// IV | Encr(key, y)
+const DH_COMPONENT_LEN: usize = BLOCK_LEN + PRIME_LEN;
+
+/// IV | Encr(Ke, data) | ICV
+const fn protected_len(data_len: usize) -> usize {
+    BLOCK_LEN + data_len + MAC_LEN
+}
+
+/// Concatenation of the exchanged messages up to the Commit response (Msgs).
+/// Identities are limited to [`MAX_IDENTITY_LEN`], so this fits every
+/// conversation.
+const MAX_MSGS_LEN: usize = 1024;
+
+type Block = [u8; BLOCK_LEN];
+
+fn prf(key: &[u8], parts: &[&[u8]]) -> [u8; PRF_LEN] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+    parts.iter().for_each(|part| mac.update(part));
+    mac.finalize().into_bytes().into()
+}
+
+/// prf+(K, S), RFC 6124 5.1, the construction of IKEv2
+fn prf_plus(key: &[u8], parts: &[&[u8]], out: &mut [u8]) {
+    let mut previous = [0u8; PRF_LEN];
+    for (i, chunk) in out.chunks_mut(PRF_LEN).enumerate() {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+        if i > 0 {
+            mac.update(&previous);
+        }
+        parts.iter().for_each(|part| mac.update(part));
+        mac.update(&[i as u8 + 1]);
+        previous = mac.finalize().into_bytes().into();
+        chunk.copy_from_slice(&previous[..chunk.len()]);
+    }
+}
+
+fn encrypt_cbc(key: &Block, iv: &Block, data: &mut [u8]) {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    let mut previous = *iv;
+    for block in data.chunks_exact_mut(BLOCK_LEN) {
+        block.iter_mut().zip(previous).for_each(|(b, p)| *b ^= p);
+        cipher.encrypt_block(GenericArray::from_mut_slice(block));
+        previous.copy_from_slice(block);
+    }
+}
+
+fn decrypt_cbc(key: &Block, iv: &Block, data: &mut [u8]) {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    let mut previous = *iv;
+    for block in data.chunks_exact_mut(BLOCK_LEN) {
+        let ciphertext: Block = (*block).try_into().unwrap();
+        cipher.decrypt_block(GenericArray::from_mut_slice(block));
+        block.iter_mut().zip(previous).for_each(|(b, p)| *b ^= p);
+        previous = ciphertext;
+    }
+}
+
+/// Key protecting the DH components, RFC 6124 5.2:
+/// temp = prf(0+, password), key = prf+(temp, ID_S | ID_P)
+fn password_key(password: &[u8], id_s: &[u8], id_p: &[u8]) -> Block {
+    let temp = prf(&[0; PRF_LEN], &[password]);
+    let mut key = [0u8; KEY_LEN];
+    prf_plus(&temp, &[id_s, id_p], &mut key);
+    key
+}
+
+/// Private exponent and public value (y = g ^ x mod p) of one side
+#[derive(Clone)]
+struct DhKey {
+    exponent: [u8; EXPONENT_LEN],
+}
+
+impl DhKey {
+    fn new(exponent: [u8; EXPONENT_LEN]) -> Self {
+        Self { exponent }
+    }
+
+    fn pow(&self, base: &U2048) -> [u8; PRIME_LEN] {
+        let params = DynResidueParams::new(&PRIME);
+        let exponent = U256::from_be_slice(&self.exponent);
+        DynResidue::new(base, params)
+            .pow(&exponent)
+            .retrieve()
+            .to_be_bytes()
+    }
+
+    fn public_value(&self) -> [u8; PRIME_LEN] {
+        self.pow(&U2048::from_u8(GENERATOR))
+    }
+
+    /// SharedSecret = prf(0+, g ^ (x_s * x_p) mod p), `None` if the public value
+    /// of the other side is not in (1, p - 1)
+    fn shared_secret(&self, public_value: &[u8; PRIME_LEN]) -> Option<[u8; PRF_LEN]> {
+        let y = U2048::from_be_slice(public_value);
+        if y <= U2048::ONE || y >= PRIME.wrapping_sub(&U2048::ONE) {
+            return None;
+        }
+        Some(prf(&[0; PRF_LEN], &[&self.pow(&y)]))
+    }
+
+    /// DHComponent = IV | Encr(key, y)
+    fn dh_component(&self, key: &Block, iv: &Block) -> [u8; DH_COMPONENT_LEN] {
+        let mut out = [0u8; DH_COMPONENT_LEN];
+        out[..BLOCK_LEN].copy_from_slice(iv);
+        out[BLOCK_LEN..].copy_from_slice(&self.public_value());
+        encrypt_cbc(key, iv, &mut out[BLOCK_LEN..]);
+        out
+    }
+}
+
+/// Public value of the other side
+fn decrypt_dh_component(key: &Block, dh_component: &[u8]) -> [u8; PRIME_LEN] {
+    let iv: Block = dh_component[..BLOCK_LEN].try_into().unwrap();
+    let mut y: [u8; PRIME_LEN] = dh_component[BLOCK_LEN..].try_into().unwrap();
+    decrypt_cbc(key, &iv, &mut y);
+    y
+}
+
+#[derive(Clone)]
+struct Keys {
+    shared_secret: [u8; PRF_LEN],
+    ke: Block,
+    ki: [u8; MAC_LEN],
+}
+
+impl Keys {
+    /// Ke | Ki = prf+(SharedSecret, "EAP-EKE Keys" | ID_S | ID_P)
+    fn derive(shared_secret: [u8; PRF_LEN], id_s: &[u8], id_p: &[u8]) -> Self {
+        let mut out = [0u8; KEY_LEN + MAC_LEN];
+        prf_plus(&shared_secret, &[b"EAP-EKE Keys", id_s, id_p], &mut out);
+        Self {
+            shared_secret,
+            ke: out[..KEY_LEN].try_into().unwrap(),
+            ki: out[KEY_LEN..].try_into().unwrap(),
+        }
+    }
+
+    /// Prot(data) = IV | Encr(Ke, data) | MAC(Ki, IV | Encr(Ke, data)), `out`
+    /// must have the length of [`protected_len`]
+    fn protect(&self, iv: &Block, data: &[u8], out: &mut [u8]) {
+        let (ciphertext, icv) = out.split_at_mut(BLOCK_LEN + data.len());
+        ciphertext[..BLOCK_LEN].copy_from_slice(iv);
+        ciphertext[BLOCK_LEN..].copy_from_slice(data);
+        encrypt_cbc(&self.ke, iv, &mut ciphertext[BLOCK_LEN..]);
+        icv.copy_from_slice(&prf(&self.ki, &[ciphertext]));
+    }
+
+    /// Reverses [`Self::protect`], `out` must have the length of the data
+    fn unprotect(&self, protected: &[u8], out: &mut [u8]) -> bool {
+        if protected.len() != protected_len(out.len()) {
+            return false;
+        }
+        let (ciphertext, icv) = protected.split_at(BLOCK_LEN + out.len());
+        if !constant_time_eq(&prf(&self.ki, &[ciphertext]), icv) {
+            return false;
+        }
+        out.copy_from_slice(&ciphertext[BLOCK_LEN..]);
+        decrypt_cbc(&self.ke, ciphertext[..BLOCK_LEN].try_into().unwrap(), out);
+        true
+    }
+
+    /// Ka = prf+(SharedSecret, "EAP-EKE Ka" | ID_S | ID_P | Nonce_P | Nonce_S)
+    fn ka(&self, id_s: &[u8], id_p: &[u8], nonce_p: &Block, nonce_s: &Block) -> [u8; PRF_LEN] {
+        let mut ka = [0u8; PRF_LEN];
+        prf_plus(
+            &self.shared_secret,
+            &[b"EAP-EKE Ka", id_s, id_p, nonce_p, nonce_s],
+            &mut ka,
+        );
+        ka
+    }
+
+    /// MSK | EMSK = prf+(SharedSecret, "EAP-EKE Exported Keys" | ID_S | ID_P |
+    /// Nonce_P | Nonce_S), the Session-ID is Type | Nonce_P | Nonce_S, RFC 6124
+    /// 6.1
+    fn session_keys(
+        &self,
+        id_s: &[u8],
+        id_p: &[u8],
+        nonce_p: &Block,
+        nonce_s: &Block,
+    ) -> SessionKeys {
+        let mut out = [0u8; 128];
+        prf_plus(
+            &self.shared_secret,
+            &[b"EAP-EKE Exported Keys", id_s, id_p, nonce_p, nonce_s],
+            &mut out,
+        );
+        SessionKeys {
+            msk: out[..64].try_into().unwrap(),
+            emsk: out[64..].try_into().unwrap(),
+            session_id: SessionKeys::session_id(METHOD_EKE, &[nonce_p, nonce_s]),
+        }
+    }
+}
+
+/// Msgs, the EAP-EKE-ID and EAP-EKE-Commit messages of both sides including the
+/// EAP header. Auth_S and Auth_P authenticate them.
+#[derive(Clone)]
+struct Transcript {
+    buffer: [u8; MAX_MSGS_LEN],
+    len: usize,
+}
+
+impl Transcript {
+    fn new() -> Self {
+        Self {
+            buffer: [0; MAX_MSGS_LEN],
+            len: 0,
+        }
+    }
+
+    /// Appends a message, `parts` is the method data following the type
+    fn push(&mut self, code: MessageCode, identifier: u8, parts: &[&[u8]]) {
+        let data_len: usize = parts.iter().map(|part| part.len()).sum();
+        let length = (4 + 1 + data_len) as u16;
+        let header = [code as u8, identifier, (length >> 8) as u8, length as u8];
+
+        for part in [&header[..], &[METHOD_EKE]].iter().chain(parts) {
+            self.buffer[self.len..self.len + part.len()].copy_from_slice(part);
+            self.len += part.len();
+        }
+    }
+
+    /// Auth = prf(Ka, label | Msgs)
+    fn auth(&self, ka: &[u8; PRF_LEN], label: &[u8]) -> [u8; PRF_LEN] {
+        prf(ka, &[label, &self.buffer[..self.len]])
+    }
+}
+
+/// Identity of an EAP-EKE-ID payload if it contains one proposal `selected`
+/// accepts
+fn parse_id(msg: &[u8], selected: impl Fn(&[u8]) -> bool) -> Option<&[u8]> {
+    let count = *msg.get(1)? as usize;
+    let proposals = msg.get(3..3 + count * PROPOSAL_LEN)?;
+    let identity = msg.get(3 + count * PROPOSAL_LEN + 1..)?;
+    (proposals.chunks(PROPOSAL_LEN).any(selected)
+        && !identity.is_empty()
+        && identity.len() <= MAX_IDENTITY_LEN)
+        .then_some(identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex_to_vec;
+
+    #[test]
+    fn diffie_hellman() {
+        let mut exponent = [0u8; EXPONENT_LEN];
+        exponent[EXPONENT_LEN - 1] = 3;
+        let key = DhKey::new(exponent);
+
+        // 11 ^ 3
+        let public_value = key.public_value();
+        assert_eq!(public_value[..PRIME_LEN - 2], [0; PRIME_LEN - 2]);
+        assert_eq!(public_value[PRIME_LEN - 2..], [0x05, 0x33]);
+
+        let other = DhKey::new([0x5a; EXPONENT_LEN]);
+        let other_value = other.public_value();
+        assert_eq!(
+            other_value[..16],
+            hex_to_vec("f1 11 f4 40 f3 d7 70 b6 05 a7 3e 24 18 f6 05 e4")[..]
+        );
+        assert_eq!(
+            other_value[PRIME_LEN - 16..],
+            hex_to_vec("ff 69 93 9a c7 0b 7e 34 28 f7 89 2b d7 cb dc 43")[..]
+        );
+        assert_eq!(
+            key.shared_secret(&other.public_value()),
+            other.shared_secret(&public_value)
+        );
+
+        // Values outside of (1, p - 1)
+        let mut value = [0u8; PRIME_LEN];
+        value[PRIME_LEN - 1] = 1;
+        assert!(key.shared_secret(&value).is_none());
+        assert!(key.shared_secret(&PRIME.to_be_bytes()).is_none());
+
+        let key = password_key(b"password", b"server", b"peer");
+        let iv = [7; BLOCK_LEN];
+        let dh_component = other.dh_component(&key, &iv);
+        assert_eq!(dh_component[..BLOCK_LEN], iv);
+        assert_eq!(
+            decrypt_dh_component(&key, &dh_component),
+            other.public_value()
+        );
+    }
+
+    #[test]
+    fn protect() {
+        let keys = Keys::derive([1; PRF_LEN], b"server", b"peer");
+        let data = hex_to_vec("00 11 22 33 44 55 66 77 88 99 aa bb cc dd ee ff");
+
+        let mut protected = [0u8; protected_len(NONCE_LEN)];
+        keys.protect(&[9; BLOCK_LEN], &data, &mut protected);
+        assert_ne!(protected[BLOCK_LEN..][..NONCE_LEN], data[..]);
+
+        let mut out = [0u8; NONCE_LEN];
+        assert!(keys.unprotect(&protected, &mut out));
+        assert_eq!(out[..], data[..]);
+
+        protected[BLOCK_LEN] ^= 1;
+        assert!(!keys.unprotect(&protected, &mut out));
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{
+    layers::{
+        eap_layer::{Decision, MethodState, SessionKeys},
+        mux::TupleElement,
+        peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta},
+        MethodType,
+    },
+    message::MessageCode,
+    util::{constant_time_eq, OwnedSlice},
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    WaitId,
+    WaitCommit,
+    WaitConfirm,
+    Done,
+}
+
+/// Peer side of EAP-EKE
+#[derive(Clone)]
+pub struct PeerEkeMethod {
+    id_p: OwnedSlice<MAX_IDENTITY_LEN>,
+    password: OwnedSlice<MAX_PASSWORD_LEN>,
+    state: State,
+    id_s: OwnedSlice<MAX_IDENTITY_LEN>,
+    keys: Option<Keys>,
+    nonce_p: Block,
+    msgs: Transcript,
+    session_keys: Option<SessionKeys>,
+}
+
+impl TupleElement for PeerEkeMethod {
+    type Target = dyn PeerMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl PeerEkeMethod {
+    pub fn new(id_p: &[u8], password: &[u8]) -> Self {
+        Self {
+            id_p: id_p.try_into().expect("id_p too long for nostd"),
+            password: password.try_into().expect("password too long for nostd"),
+            state: State::WaitId,
+            id_s: OwnedSlice::new(),
+            keys: None,
+            nonce_p: [0; NONCE_LEN],
+            msgs: Transcript::new(),
+            session_keys: None,
+        }
+    }
+
+    /// Identity of the server (ID_S), available after the first message.
+    pub fn server_identity(&self) -> &[u8] {
+        self.id_s.as_ref()
+    }
+
+    fn fail<'a>(
+        &mut self,
+        code: u32,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        self.state = State::Done;
+        self.session_keys = None;
+        PeerMethodLayerResult::Send(
+            env.respond()
+                .write(&[EXCH_FAILURE])
+                .write(&code.to_be_bytes()),
+        )
+    }
+
+    fn recv_id<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let Some(id_s) = parse_id(msg, |proposal| proposal == PROPOSAL) else {
+            return self.fail(FAILURE_NO_PROPOSAL_CHOSEN, env);
+        };
+        self.id_s = id_s.try_into().unwrap();
+
+        let identifier = meta.message.identifier;
+        self.msgs = Transcript::new();
+        self.msgs.push(MessageCode::Request, identifier, &[msg]);
+        self.msgs.push(
+            MessageCode::Response,
+            identifier,
+            &[&ID_HEADER, self.id_p.as_ref()],
+        );
+
+        self.state = State::WaitCommit;
+        PeerMethodLayerResult::Send(env.respond().write(&ID_HEADER).write(self.id_p.as_ref()))
+    }
+
+    fn recv_commit<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        // DHComponent_S
+        if msg.len() != 1 + DH_COMPONENT_LEN {
+            return self.fail(FAILURE_PROTOCOL_ERROR, env);
+        }
+        let (id_s, id_p) = (self.id_s.as_ref(), self.id_p.as_ref());
+        let password_key = password_key(self.password.as_ref(), id_s, id_p);
+        let y_s = decrypt_dh_component(&password_key, &msg[1..]);
+
+        let mut exponent = [0u8; EXPONENT_LEN];
+        env.fill_random(&mut exponent);
+        let dh_key = DhKey::new(exponent);
+        let Some(shared_secret) = dh_key.shared_secret(&y_s) else {
+            return self.fail(FAILURE_AUTHENTICATION, env);
+        };
+        let keys = Keys::derive(shared_secret, id_s, id_p);
+
+        let mut iv = [0u8; BLOCK_LEN];
+        env.fill_random(&mut iv);
+        let dh_component = dh_key.dh_component(&password_key, &iv);
+
+        env.fill_random(&mut self.nonce_p);
+        env.fill_random(&mut iv);
+        let mut pnonce_p = [0u8; protected_len(NONCE_LEN)];
+        keys.protect(&iv, &self.nonce_p, &mut pnonce_p);
+
+        let identifier = meta.message.identifier;
+        self.msgs.push(MessageCode::Request, identifier, &[msg]);
+        self.msgs.push(
+            MessageCode::Response,
+            identifier,
+            &[&[EXCH_COMMIT], &dh_component, &pnonce_p],
+        );
+
+        self.keys = Some(keys);
+        self.state = State::WaitConfirm;
+        PeerMethodLayerResult::Send(
+            env.respond()
+                .write(&[EXCH_COMMIT])
+                .write(&dh_component)
+                .write(&pnonce_p),
+        )
+    }
+
+    fn recv_confirm<'a>(
+        &mut self,
+        msg: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        // PNonce_PS | Auth_S
+        let Some(keys) = self.keys.take() else {
+            return self.fail(FAILURE_PROTOCOL_ERROR, env);
+        };
+        if msg.len() != 1 + protected_len(2 * NONCE_LEN) + PRF_LEN {
+            return self.fail(FAILURE_PROTOCOL_ERROR, env);
+        }
+        let (pnonce_ps, auth_s) = msg[1..].split_at(protected_len(2 * NONCE_LEN));
+
+        // Only the server knowing the password can return Nonce_P
+        let mut nonces = [0u8; 2 * NONCE_LEN];
+        if !keys.unprotect(pnonce_ps, &mut nonces) || nonces[..NONCE_LEN] != self.nonce_p {
+            return self.fail(FAILURE_AUTHENTICATION, env);
+        }
+        let nonce_s: Block = nonces[NONCE_LEN..].try_into().unwrap();
+
+        let (id_s, id_p) = (self.id_s.as_ref(), self.id_p.as_ref());
+        let ka = keys.ka(id_s, id_p, &self.nonce_p, &nonce_s);
+        if !constant_time_eq(auth_s, &self.msgs.auth(&ka, b"EAP-EKE server")) {
+            return self.fail(FAILURE_AUTHENTICATION, env);
+        }
+
+        let mut iv = [0u8; BLOCK_LEN];
+        env.fill_random(&mut iv);
+        let mut pnonce_s = [0u8; protected_len(NONCE_LEN)];
+        keys.protect(&iv, &nonce_s, &mut pnonce_s);
+        let auth_p = self.msgs.auth(&ka, b"EAP-EKE peer");
+
+        self.session_keys = Some(keys.session_keys(id_s, id_p, &self.nonce_p, &nonce_s));
+        self.state = State::Done;
+        PeerMethodLayerResult::Send(
+            env.respond()
+                .write(&[EXCH_CONFIRM])
+                .write(&pnonce_s)
+                .write(&auth_p),
+        )
+    }
+}
+
+impl PeerMethodLayer for PeerEkeMethod {
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_EKE)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_EKE
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        match (self.state, msg.first()) {
+            (State::WaitId, Some(&EXCH_ID)) => self.recv_id(msg, meta, env),
+            (State::WaitCommit, Some(&EXCH_COMMIT)) => self.recv_commit(msg, meta, env),
+            (State::WaitConfirm, Some(&EXCH_CONFIRM)) => self.recv_confirm(msg, env),
+            // The server gave up, the failure is acknowledged
+            (_, Some(&EXCH_FAILURE)) => self.fail(FAILURE_NO_ERROR, env),
+            _ => self.fail(FAILURE_PROTOCOL_ERROR, env),
+        }
+    }
+
+    fn method_state(&self) -> MethodState {
+        if self.session_keys.is_some() {
+            MethodState::Done
+        } else {
+            MethodState::MayCont
+        }
+    }
+
+    fn decision(&self) -> Decision {
+        if self.session_keys.is_some() {
+            Decision::CondSucc
+        } else {
+            Decision::Fail
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.session_keys.as_ref()
+    }
+
+    fn reset(&mut self) {
+        self.state = State::WaitId;
+        self.id_s = OwnedSlice::new();
+        self.keys = None;
+        self.msgs = Transcript::new();
+        self.session_keys = None;
+    }
+}
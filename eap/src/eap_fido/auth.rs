@@ -0,0 +1,245 @@
+use dummycert::TlsConfig;
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1};
+use rustls::ServerConnection;
+
+use crate::{
+    eap_rustls::{tunnel::TunnelInput, AuthTlsMethod, CommonTLS},
+    layers::{
+        auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta},
+        eap_layer::SessionKeys,
+        mux::TupleElement,
+        MethodType,
+    },
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Handshake,
+    WaitResponse,
+    /// The protected success indication was sent
+    WaitAck,
+}
+
+/// Server side of EAP-FIDO.
+///
+/// Assertions are accepted for credentials of `store` that are scoped to
+/// `rp_id`. The user handle of the credential becomes the name of the peer.
+pub struct AuthFidoMethod<S> {
+    config: TlsConfig,
+    rp_id: String,
+    store: S,
+    user_verification: bool,
+    tls: Option<CommonTLS<ServerConnection>>,
+    state: State,
+    additional_data: [u8; ADDITIONAL_DATA_LEN],
+    allow_list: Vec<Vec<u8>>,
+    session_keys: Option<SessionKeys>,
+}
+
+impl<S> TupleElement for AuthFidoMethod<S>
+where
+    S: CredentialStore + 'static,
+{
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<S> AuthFidoMethod<S>
+where
+    S: CredentialStore,
+{
+    pub fn new(config: TlsConfig, rp_id: &str, store: S) -> Self {
+        Self {
+            config,
+            rp_id: rp_id.to_string(),
+            store,
+            user_verification: false,
+            tls: None,
+            state: State::Handshake,
+            additional_data: [0; ADDITIONAL_DATA_LEN],
+            allow_list: Vec::new(),
+            session_keys: None,
+        }
+    }
+
+    /// Requires the authenticator to verify the user, e.g. by PIN or biometrics
+    pub fn with_user_verification(mut self) -> Self {
+        self.user_verification = true;
+        self
+    }
+
+    /// Answers the first message of the peer with the authentication request
+    fn process_hello(&mut self, data: &[u8], env: &mut dyn EapEnvironment) -> Option<Vec<u8>> {
+        let fields = Fields::parse(data)?;
+        self.allow_list = match fields.identity {
+            Some(identity) => {
+                Some(self.store.credential_ids(identity)).filter(|ids| !ids.is_empty())?
+            }
+            None => Vec::new(),
+        };
+        env.fill_random(&mut self.additional_data);
+
+        let allow_list = self
+            .allow_list
+            .iter()
+            .map(Vec::as_slice)
+            .collect::<Vec<_>>();
+        let mut entries = vec![(KEY_ADDITIONAL_DATA, Value::Bytes(&self.additional_data))];
+        if !allow_list.is_empty() {
+            entries.push((KEY_ALLOW_LIST, Value::Array(&allow_list)));
+        }
+        if self.user_verification {
+            entries.push((KEY_USER_VERIFICATION, Value::Bool(true)));
+        }
+        Some(write_message(&entries))
+    }
+
+    /// Checks the assertion of the peer, returns the credential it used
+    fn verify_response(&mut self, data: &[u8]) -> Option<RegisteredCredential> {
+        let fields = Fields::parse(data)?;
+        let (Some(credential_id), Some(authenticator_data), Some(signature)) = (
+            fields.credential_id,
+            fields.authenticator_data,
+            fields.signature,
+        ) else {
+            return None;
+        };
+
+        if !self.allow_list.is_empty() && !self.allow_list.iter().any(|id| id == credential_id) {
+            return None;
+        }
+        let mut credential = self.store.load(credential_id)?;
+        if fields
+            .user_handle
+            .is_some_and(|user_handle| user_handle != credential.user_handle)
+        {
+            return None;
+        }
+
+        let (rp_id_hash, flags, sign_count) = parse_authenticator_data(authenticator_data)?;
+        let user_verified = flags & FLAG_USER_VERIFIED != 0;
+        // A counter that does not increase hints at a cloned authenticator
+        let counter_valid =
+            (sign_count == 0 && credential.sign_count == 0) || sign_count > credential.sign_count;
+        if rp_id_hash[..] != Sha256::digest(self.rp_id.as_bytes())[..]
+            || flags & FLAG_USER_PRESENT == 0
+            || (self.user_verification && !user_verified)
+            || !counter_valid
+        {
+            return None;
+        }
+
+        let client_data_hash = client_data_hash(self.tls.as_ref()?, &self.additional_data)?;
+        let mut message = authenticator_data.to_vec();
+        message.extend_from_slice(&client_data_hash);
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, &credential.public_key)
+            .verify(&message, signature)
+            .ok()?;
+
+        credential.sign_count = sign_count;
+        Some(credential)
+    }
+}
+
+impl<S> AuthMethodLayer for AuthFidoMethod<S>
+where
+    S: CredentialStore,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_FIDO)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_FIDO
+    }
+
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.state = State::Handshake;
+        self.session_keys = None;
+        let tls = self
+            .tls
+            .insert(AuthTlsMethod::create_common_tls(&self.config, false));
+
+        AuthMethodLayerResult::Send(env.respond().write(&tls.start_packet()))
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let Some(tls) = self.tls.as_mut() else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+
+        let out = match (tls.receive_tunnel(msg), self.state) {
+            (Ok(TunnelInput::Fragment), _) => {
+                return AuthMethodLayerResult::Send(env.respond().write(&tls.ack_packet()));
+            }
+            (Ok(TunnelInput::Handshake), _) => Vec::new(),
+            (Ok(TunnelInput::Data(data)), State::Handshake) => {
+                if !negotiated_tls13(tls) {
+                    return AuthMethodLayerResult::Failed(env);
+                }
+                let Some(request) = self.process_hello(&data, env) else {
+                    return AuthMethodLayerResult::Failed(env);
+                };
+                self.state = State::WaitResponse;
+                request
+            }
+            // The peer acknowledged the success indication
+            (Ok(TunnelInput::Data(data)), State::WaitAck) if data.is_empty() => {
+                return AuthMethodLayerResult::Finished(env);
+            }
+            // Acknowledgement of a fragment
+            (Ok(TunnelInput::Data(data)), _) if data.is_empty() => Vec::new(),
+            (Ok(TunnelInput::Data(data)), State::WaitResponse) => {
+                let Some(credential) = self.verify_response(&data) else {
+                    return AuthMethodLayerResult::Failed(env);
+                };
+                self.store
+                    .update_sign_count(&credential.credential_id, credential.sign_count);
+                env.set_name(&credential.user_handle);
+
+                self.session_keys = session_keys(self.tls.as_ref().unwrap());
+                self.state = State::WaitAck;
+                PROTECTED_SUCCESS.to_vec()
+            }
+            (Ok(TunnelInput::Data(_)), _) | (Err(_), _) => {
+                return AuthMethodLayerResult::Failed(env)
+            }
+        };
+
+        let tls = self.tls.as_mut().unwrap();
+        if out.is_empty() && !tls.con.wants_write() {
+            return AuthMethodLayerResult::Failed(env);
+        }
+        match tls.send_tunnel(&out) {
+            Ok(data) => AuthMethodLayerResult::Send(env.respond().write(&data)),
+            Err(_) => AuthMethodLayerResult::Failed(env),
+        }
+    }
+
+    fn selectable_by_nak(&self) -> bool {
+        false
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.session_keys.as_ref()
+    }
+}
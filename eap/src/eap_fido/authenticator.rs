@@ -0,0 +1,155 @@
+//! The FIDO authenticator of the peer, modelled after authenticatorGetAssertion
+//! of CTAP 2.1 (6.2)
+
+use ring::{
+    rand::{SecureRandom, SystemRandom},
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+};
+
+use super::{authenticator_data, RegisteredCredential, FLAG_USER_PRESENT, FLAG_USER_VERIFIED};
+
+const CREDENTIAL_ID_LEN: usize = 16;
+
+/// Parameters of authenticatorGetAssertion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssertionRequest<'a> {
+    pub rp_id: &'a str,
+    pub client_data_hash: &'a [u8; 32],
+    /// Acceptable credentials, empty if the server accepts any discoverable
+    /// credential of the relying party
+    pub allow_list: &'a [&'a [u8]],
+    pub user_verification: bool,
+}
+
+/// Response of authenticatorGetAssertion, the signature covers the
+/// authenticator data and the client data hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assertion {
+    pub credential_id: Vec<u8>,
+    pub authenticator_data: Vec<u8>,
+    pub signature: Vec<u8>,
+    pub user_handle: Option<Vec<u8>>,
+}
+
+/// CTAP2 status codes of a failed request, they are sent to the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CtapError {
+    NoCredentials,
+    OperationDenied,
+    UnsupportedOption,
+}
+
+impl CtapError {
+    pub(crate) const fn code(self) -> u8 {
+        match self {
+            CtapError::NoCredentials => 0x2e,
+            CtapError::OperationDenied => 0x27,
+            CtapError::UnsupportedOption => 0x2b,
+        }
+    }
+}
+
+/// A FIDO2 authenticator, e.g. a security key reached over CTAP
+pub trait FidoAuthenticator {
+    fn get_assertion(&mut self, request: &AssertionRequest) -> Result<Assertion, CtapError>;
+}
+
+struct StoredKey {
+    credential_id: Vec<u8>,
+    rp_id: String,
+    user_handle: Vec<u8>,
+    /// PKCS#8 document of the P-256 key
+    private_key: Vec<u8>,
+    sign_count: u32,
+}
+
+/// Authenticator with the keys in memory, for tests and prototypes.
+///
+/// Every credential is discoverable. User presence is always asserted, user
+/// verification only if enabled.
+#[derive(Default)]
+pub struct SoftwareAuthenticator {
+    keys: Vec<StoredKey>,
+    user_verification: bool,
+}
+
+impl SoftwareAuthenticator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asserts user verification, as if a PIN was entered
+    pub fn with_user_verification(mut self) -> Self {
+        self.user_verification = true;
+        self
+    }
+
+    /// Creates an ES256 credential, the result is registered with the server
+    pub fn make_credential(&mut self, rp_id: &str, user_handle: &[u8]) -> RegisteredCredential {
+        let random = SystemRandom::new();
+        let mut credential_id = vec![0; CREDENTIAL_ID_LEN];
+        random.fill(&mut credential_id).unwrap();
+
+        let private_key = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &random)
+            .unwrap()
+            .as_ref()
+            .to_vec();
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &private_key).unwrap();
+
+        self.keys.push(StoredKey {
+            credential_id: credential_id.clone(),
+            rp_id: rp_id.to_string(),
+            user_handle: user_handle.to_vec(),
+            private_key,
+            sign_count: 0,
+        });
+
+        RegisteredCredential {
+            credential_id,
+            public_key: key_pair.public_key().as_ref().to_vec(),
+            user_handle: user_handle.to_vec(),
+            sign_count: 0,
+        }
+    }
+}
+
+impl FidoAuthenticator for SoftwareAuthenticator {
+    fn get_assertion(&mut self, request: &AssertionRequest) -> Result<Assertion, CtapError> {
+        if request.user_verification && !self.user_verification {
+            return Err(CtapError::UnsupportedOption);
+        }
+
+        let key = self
+            .keys
+            .iter_mut()
+            .find(|key| {
+                key.rp_id == request.rp_id
+                    && (request.allow_list.is_empty()
+                        || request.allow_list.contains(&&key.credential_id[..]))
+            })
+            .ok_or(CtapError::NoCredentials)?;
+
+        let mut flags = FLAG_USER_PRESENT;
+        if self.user_verification {
+            flags |= FLAG_USER_VERIFIED;
+        }
+        key.sign_count += 1;
+        let authenticator_data = authenticator_data(request.rp_id, flags, key.sign_count);
+
+        let mut message = authenticator_data.to_vec();
+        message.extend_from_slice(request.client_data_hash);
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &key.private_key)
+            .map_err(|_| CtapError::OperationDenied)?;
+        let signature = key_pair
+            .sign(&SystemRandom::new(), &message)
+            .map_err(|_| CtapError::OperationDenied)?;
+
+        Ok(Assertion {
+            credential_id: key.credential_id.clone(),
+            authenticator_data: authenticator_data.to_vec(),
+            signature: signature.as_ref().to_vec(),
+            user_handle: Some(key.user_handle.clone()),
+        })
+    }
+}
@@ -0,0 +1,369 @@
+//! EAP-FIDO, passwordless authentication with FIDO2 credentials inside a TLS 1.3
+//! tunnel, see https://datatracker.ietf.org/doc/draft-ietf-emu-eap-fido/
+//!
+//! The server is authenticated by its certificate during the handshake, the
+//! peer by a WebAuthn style assertion inside the tunnel. The peer may name a
+//! user, the server answers with an authentication request and the peer lets
+//! its [`FidoAuthenticator`] sign it. The client data hash covers a challenge
+//! exported from the TLS session, which binds the assertion to the tunnel. The
+//! server checks the assertion against its [`CredentialStore`] and concludes
+//! with the protected success indication of RFC 9190 2.5.
+//!
+//! Messages in the tunnel are CBOR maps with small integer keys. Packets use
+//! the EAP-TLS format, the keys are derived as for EAP-TLS 1.3. The draft has
+//! no type assigned yet, the experimental type is used.
+
+mod auth;
+mod authenticator;
+mod peer;
+
+pub use auth::AuthFidoMethod;
+pub use authenticator::{
+    Assertion, AssertionRequest, CtapError, FidoAuthenticator, SoftwareAuthenticator,
+};
+pub use peer::PeerFidoMethod;
+
+use std::ops::DerefMut;
+
+use rustls::{ConnectionCommon, ProtocolVersion};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    eap_edhoc::cbor::{self, MAJOR_ARRAY, MAJOR_BSTR, MAJOR_MAP, MAJOR_UINT},
+    eap_rustls::CommonTLS,
+    layers::{eap_layer::SessionKeys, SecurityClaims},
+    util::ByteReader,
+};
+
+/// Experimental type, RFC 3748 5.8
+const METHOD_FIDO: u8 = 255;
+
+const CHALLENGE_LABEL: &[u8] = b"EXPORTER_EAP_FIDO_Challenge";
+const KEY_MATERIAL_LABEL: &[u8] = b"EXPORTER_EAP_TLS_Key_Material";
+const METHOD_ID_LABEL: &[u8] = b"EXPORTER_EAP_TLS_Method-Id";
+/// Application data of the protected success indication
+const PROTECTED_SUCCESS: &[u8] = &[0x00];
+
+const CHALLENGE_LEN: usize = 32;
+const ADDITIONAL_DATA_LEN: usize = 32;
+
+/// Keys of the message maps
+const KEY_IDENTITY: i32 = 1;
+const KEY_ADDITIONAL_DATA: i32 = 2;
+const KEY_ALLOW_LIST: i32 = 3;
+const KEY_USER_VERIFICATION: i32 = 4;
+const KEY_CREDENTIAL_ID: i32 = 5;
+const KEY_AUTHENTICATOR_DATA: i32 = 6;
+const KEY_SIGNATURE: i32 = 7;
+const KEY_USER_HANDLE: i32 = 8;
+const KEY_ERROR: i32 = 9;
+
+const CBOR_FALSE: u8 = 0xf4;
+const CBOR_TRUE: u8 = 0xf5;
+
+/// Authenticator data flags, WebAuthn 6.1
+pub(crate) const FLAG_USER_PRESENT: u8 = 0x01;
+pub(crate) const FLAG_USER_VERIFIED: u8 = 0x04;
+/// RP ID hash, flags and signature counter
+const AUTHENTICATOR_DATA_LEN: usize = 37;
+
+/// A credential registered with the server, the public key is an uncompressed
+/// P-256 point (ES256)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisteredCredential {
+    pub credential_id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    /// Reported as the name of the peer after a successful authentication
+    pub user_handle: Vec<u8>,
+    /// Signature counter of the last assertion, zero if the authenticator has
+    /// no counter
+    pub sign_count: u32,
+}
+
+/// Credentials known to the server
+pub trait CredentialStore {
+    /// Credentials of the user the peer named
+    fn credential_ids(&mut self, user_handle: &[u8]) -> Vec<Vec<u8>>;
+    fn load(&mut self, credential_id: &[u8]) -> Option<RegisteredCredential>;
+    /// Called after a successful authentication
+    fn update_sign_count(&mut self, credential_id: &[u8], sign_count: u32);
+}
+
+impl CredentialStore for std::collections::HashMap<Vec<u8>, RegisteredCredential> {
+    fn credential_ids(&mut self, user_handle: &[u8]) -> Vec<Vec<u8>> {
+        self.values()
+            .filter(|credential| credential.user_handle == user_handle)
+            .map(|credential| credential.credential_id.clone())
+            .collect()
+    }
+
+    fn load(&mut self, credential_id: &[u8]) -> Option<RegisteredCredential> {
+        self.get(credential_id).cloned()
+    }
+
+    fn update_sign_count(&mut self, credential_id: &[u8], sign_count: u32) {
+        if let Some(credential) = self.get_mut(credential_id) {
+            credential.sign_count = sign_count;
+        }
+    }
+}
+
+pub(crate) fn authenticator_data(
+    rp_id: &str,
+    flags: u8,
+    sign_count: u32,
+) -> [u8; AUTHENTICATOR_DATA_LEN] {
+    let mut data = [0u8; AUTHENTICATOR_DATA_LEN];
+    data[..32].copy_from_slice(&Sha256::digest(rp_id.as_bytes()));
+    data[32] = flags;
+    data[33..].copy_from_slice(&sign_count.to_be_bytes());
+    data
+}
+
+/// RP ID hash, flags and signature counter. Extensions are covered by the
+/// signature but ignored.
+fn parse_authenticator_data(data: &[u8]) -> Option<([u8; 32], u8, u32)> {
+    let mut reader = ByteReader::new(data);
+    Some((reader.take_array()?, reader.u8()?, reader.u32()?))
+}
+
+/// A value of a message map
+enum Value<'a> {
+    Bytes(&'a [u8]),
+    Array(&'a [&'a [u8]]),
+    Bool(bool),
+    Uint(u8),
+}
+
+fn write_head(out: &mut Vec<u8>, major: u8, value: usize) {
+    let (header, len) = cbor::header(major, value);
+    out.extend_from_slice(&header[..len]);
+}
+
+fn write_bstr(out: &mut Vec<u8>, data: &[u8]) {
+    write_head(out, MAJOR_BSTR, data.len());
+    out.extend_from_slice(data);
+}
+
+fn write_message(entries: &[(i32, Value)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_head(&mut out, MAJOR_MAP, entries.len());
+    for (key, value) in entries {
+        write_head(&mut out, MAJOR_UINT, *key as usize);
+        match value {
+            Value::Bytes(data) => write_bstr(&mut out, data),
+            Value::Array(items) => {
+                write_head(&mut out, MAJOR_ARRAY, items.len());
+                for item in items.iter() {
+                    write_bstr(&mut out, item);
+                }
+            }
+            Value::Bool(value) => out.push(if *value { CBOR_TRUE } else { CBOR_FALSE }),
+            Value::Uint(value) => write_head(&mut out, MAJOR_UINT, *value as usize),
+        }
+    }
+    out
+}
+
+/// Fields of a received message, each key may appear once
+#[derive(Default)]
+struct Fields<'a> {
+    identity: Option<&'a [u8]>,
+    additional_data: Option<&'a [u8]>,
+    allow_list: Option<Vec<&'a [u8]>>,
+    user_verification: Option<bool>,
+    credential_id: Option<&'a [u8]>,
+    authenticator_data: Option<&'a [u8]>,
+    signature: Option<&'a [u8]>,
+    user_handle: Option<&'a [u8]>,
+    error: Option<i32>,
+}
+
+impl<'a> Fields<'a> {
+    /// `None` for malformed messages and unknown keys
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let mut reader = ByteReader::new(data);
+        let mut result = Fields::default();
+
+        // The maps have less than 24 entries, the count is in the initial byte
+        let initial = reader.u8()?;
+        if initial & 0xe0 != MAJOR_MAP || initial & 0x1f >= 24 {
+            return None;
+        }
+
+        for _ in 0..initial & 0x1f {
+            let bstr = |reader: &mut ByteReader<'a>| cbor::bstr(reader);
+            let duplicate = match cbor::int(&mut reader)? {
+                KEY_IDENTITY => result.identity.replace(bstr(&mut reader)?).is_some(),
+                KEY_ADDITIONAL_DATA => result.additional_data.replace(bstr(&mut reader)?).is_some(),
+                KEY_ALLOW_LIST => {
+                    let len = cbor::array_len(&mut reader)?;
+                    let items = (0..len)
+                        .map(|_| bstr(&mut reader))
+                        .collect::<Option<Vec<_>>>()?;
+                    result.allow_list.replace(items).is_some()
+                }
+                KEY_USER_VERIFICATION => {
+                    let value = match reader.u8()? {
+                        CBOR_FALSE => false,
+                        CBOR_TRUE => true,
+                        _ => return None,
+                    };
+                    result.user_verification.replace(value).is_some()
+                }
+                KEY_CREDENTIAL_ID => result.credential_id.replace(bstr(&mut reader)?).is_some(),
+                KEY_AUTHENTICATOR_DATA => result
+                    .authenticator_data
+                    .replace(bstr(&mut reader)?)
+                    .is_some(),
+                KEY_SIGNATURE => result.signature.replace(bstr(&mut reader)?).is_some(),
+                KEY_USER_HANDLE => result.user_handle.replace(bstr(&mut reader)?).is_some(),
+                KEY_ERROR => result
+                    .error
+                    .replace(cbor::int(&mut reader).filter(|code| *code >= 0)?)
+                    .is_some(),
+                _ => return None,
+            };
+
+            if duplicate {
+                return None;
+            }
+        }
+
+        reader.is_empty().then_some(result)
+    }
+}
+
+/// EAP-FIDO requires TLS 1.3
+fn negotiated_tls13<C, T>(tls: &CommonTLS<C>) -> bool
+where
+    C: DerefMut<Target = ConnectionCommon<T>>,
+{
+    tls.con.protocol_version() == Some(ProtocolVersion::TLSv1_3)
+}
+
+/// SHA-256 over the challenge exported from the tunnel and the additional
+/// data of the server
+fn client_data_hash<C, T>(tls: &CommonTLS<C>, additional_data: &[u8]) -> Option<[u8; 32]>
+where
+    C: DerefMut<Target = ConnectionCommon<T>>,
+{
+    let mut challenge = [0u8; CHALLENGE_LEN];
+    tls.export_keying_material(&mut challenge, CHALLENGE_LABEL)
+        .ok()?;
+
+    let mut hash = Sha256::new();
+    hash.update(challenge);
+    hash.update(additional_data);
+    Some(hash.finalize().into())
+}
+
+/// MSK, EMSK and the Session-ID, RFC 9190 2.3 with the type of EAP-FIDO as
+/// context
+fn session_keys<C, T>(tls: &CommonTLS<C>) -> Option<SessionKeys>
+where
+    C: DerefMut<Target = ConnectionCommon<T>>,
+{
+    let mut key_material = [0u8; 128];
+    tls.export_keying_material_with_context(&mut key_material, KEY_MATERIAL_LABEL, &[METHOD_FIDO])
+        .ok()?;
+    let mut method_id = [0u8; 64];
+    tls.export_keying_material_with_context(&mut method_id, METHOD_ID_LABEL, &[METHOD_FIDO])
+        .ok()?;
+
+    let mut keys = SessionKeys {
+        msk: [0; 64],
+        emsk: [0; 64],
+        session_id: SessionKeys::session_id(METHOD_FIDO, &[&method_id]),
+    };
+    keys.msk.copy_from_slice(&key_material[..64]);
+    keys.emsk.copy_from_slice(&key_material[64..]);
+    Some(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_roundtrip() {
+        let allow_list: [&[u8]; 2] = [b"first", b"second"];
+        let message = write_message(&[
+            (KEY_ADDITIONAL_DATA, Value::Bytes(&[7; ADDITIONAL_DATA_LEN])),
+            (KEY_ALLOW_LIST, Value::Array(&allow_list)),
+            (KEY_USER_VERIFICATION, Value::Bool(true)),
+        ]);
+        assert_eq!(&message[..4], [0xa3, 0x02, 0x58, 32]);
+
+        let fields = Fields::parse(&message).unwrap();
+        assert_eq!(fields.additional_data, Some(&[7; ADDITIONAL_DATA_LEN][..]));
+        assert_eq!(fields.allow_list, Some(allow_list.to_vec()));
+        assert_eq!(fields.user_verification, Some(true));
+        assert_eq!(fields.credential_id, None);
+
+        let error = write_message(&[(KEY_ERROR, Value::Uint(CtapError::NoCredentials.code()))]);
+        assert_eq!(error, [0xa1, 0x09, 0x18, 0x2e]);
+        assert_eq!(Fields::parse(&error).unwrap().error, Some(0x2e));
+
+        // Truncated messages, duplicate and unknown keys are rejected
+        assert!(Fields::parse(&message[..message.len() - 1]).is_none());
+        assert!(Fields::parse(&[0xa2, 0x01, 0x40, 0x01, 0x40]).is_none());
+        assert!(Fields::parse(&[0xa1, 0x0a, 0x40]).is_none());
+    }
+
+    #[test]
+    fn software_authenticator() {
+        let mut authenticator = SoftwareAuthenticator::new();
+        let credential = authenticator.make_credential("example.com", b"hans");
+        assert_eq!(credential.public_key.len(), 65);
+
+        let request = AssertionRequest {
+            rp_id: "example.com",
+            client_data_hash: &[3; 32],
+            allow_list: &[],
+            user_verification: false,
+        };
+        let assertion = authenticator.get_assertion(&request).unwrap();
+        assert_eq!(assertion.credential_id, credential.credential_id);
+        assert_eq!(
+            parse_authenticator_data(&assertion.authenticator_data),
+            Some((Sha256::digest(b"example.com").into(), FLAG_USER_PRESENT, 1))
+        );
+
+        let mut message = assertion.authenticator_data.clone();
+        message.extend_from_slice(&[3; 32]);
+        ring::signature::UnparsedPublicKey::new(
+            &ring::signature::ECDSA_P256_SHA256_ASN1,
+            &credential.public_key,
+        )
+        .verify(&message, &assertion.signature)
+        .unwrap();
+
+        // Other relying party, unknown credential and missing user verification
+        let other = AssertionRequest {
+            rp_id: "example.org",
+            ..request
+        };
+        assert_eq!(
+            authenticator.get_assertion(&other),
+            Err(CtapError::NoCredentials)
+        );
+        let allow_list: [&[u8]; 1] = [b"unknown"];
+        let unknown = AssertionRequest {
+            allow_list: &allow_list,
+            ..request
+        };
+        assert_eq!(
+            authenticator.get_assertion(&unknown),
+            Err(CtapError::NoCredentials)
+        );
+        let verified = AssertionRequest {
+            user_verification: true,
+            ..request
+        };
+        assert_eq!(
+            authenticator.get_assertion(&verified),
+            Err(CtapError::UnsupportedOption)
+        );
+    }
+}
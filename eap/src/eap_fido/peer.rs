@@ -0,0 +1,204 @@
+use dummycert::TlsConfig;
+use rustls::ClientConnection;
+
+use crate::{
+    eap_rustls::{tunnel::TunnelInput, CommonTLS, PeerTlsMethod},
+    layers::{
+        eap_layer::{Decision, MethodState, SessionKeys},
+        mux::TupleElement,
+        peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta},
+        MethodType,
+    },
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Handshake,
+    WaitRequest,
+    WaitResult,
+    Done,
+}
+
+/// Peer side of EAP-FIDO.
+///
+/// `rp_id` is the relying party of the credentials, the domain of the server
+/// certificate. Without an identity the authenticator chooses a discoverable
+/// credential.
+pub struct PeerFidoMethod<A> {
+    config: TlsConfig,
+    rp_id: String,
+    identity: Option<Vec<u8>>,
+    authenticator: A,
+    tls: Option<CommonTLS<ClientConnection>>,
+    state: State,
+    session_keys: Option<SessionKeys>,
+}
+
+impl<A> TupleElement for PeerFidoMethod<A>
+where
+    A: FidoAuthenticator + 'static,
+{
+    type Target = dyn PeerMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<A> PeerFidoMethod<A>
+where
+    A: FidoAuthenticator,
+{
+    pub fn new(config: TlsConfig, rp_id: &str, authenticator: A) -> Self {
+        Self {
+            config,
+            rp_id: rp_id.to_string(),
+            identity: None,
+            authenticator,
+            tls: None,
+            state: State::Handshake,
+            session_keys: None,
+        }
+    }
+
+    /// Names the user, the server then restricts the request to their
+    /// credentials
+    pub fn with_identity(mut self, user_handle: &[u8]) -> Self {
+        self.identity = Some(user_handle.to_vec());
+        self
+    }
+
+    /// Signs the authentication request, or reports why the authenticator
+    /// refused
+    fn process_request(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        let fields = Fields::parse(data)?;
+        let additional_data = fields.additional_data?;
+        let client_data_hash = client_data_hash(self.tls.as_ref()?, additional_data)?;
+
+        let allow_list = fields.allow_list.unwrap_or_default();
+        let request = AssertionRequest {
+            rp_id: &self.rp_id,
+            client_data_hash: &client_data_hash,
+            allow_list: &allow_list,
+            user_verification: fields.user_verification.unwrap_or(false),
+        };
+
+        Some(match self.authenticator.get_assertion(&request) {
+            Ok(assertion) => {
+                let mut entries = vec![
+                    (KEY_CREDENTIAL_ID, Value::Bytes(&assertion.credential_id)),
+                    (
+                        KEY_AUTHENTICATOR_DATA,
+                        Value::Bytes(&assertion.authenticator_data),
+                    ),
+                    (KEY_SIGNATURE, Value::Bytes(&assertion.signature)),
+                ];
+                if let Some(user_handle) = &assertion.user_handle {
+                    entries.push((KEY_USER_HANDLE, Value::Bytes(user_handle)));
+                }
+                write_message(&entries)
+            }
+            Err(error) => write_message(&[(KEY_ERROR, Value::Uint(error.code()))]),
+        })
+    }
+}
+
+impl<A> PeerMethodLayer for PeerFidoMethod<A>
+where
+    A: FidoAuthenticator,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_FIDO)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_FIDO
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let tls = self
+            .tls
+            .get_or_insert_with(|| PeerTlsMethod::create_common_tls(&self.config));
+
+        let out = match (tls.receive_tunnel(msg), self.state) {
+            (Ok(TunnelInput::Fragment), _) => {
+                return PeerMethodLayerResult::Send(env.respond().write(&tls.ack_packet()));
+            }
+            (Ok(TunnelInput::Handshake), _) => Vec::new(),
+            // Our Finished goes out together with the first message
+            (Ok(TunnelInput::Data(data)), State::Handshake) => {
+                if !data.is_empty() || !negotiated_tls13(tls) {
+                    return PeerMethodLayerResult::Failed(env);
+                }
+                self.state = State::WaitRequest;
+                match &self.identity {
+                    Some(identity) => write_message(&[(KEY_IDENTITY, Value::Bytes(identity))]),
+                    None => write_message(&[]),
+                }
+            }
+            (Ok(TunnelInput::Data(data)), _) if data.is_empty() => Vec::new(),
+            (Ok(TunnelInput::Data(data)), State::WaitRequest) => {
+                let Some(response) = self.process_request(&data) else {
+                    return PeerMethodLayerResult::Failed(env);
+                };
+                self.state = State::WaitResult;
+                response
+            }
+            (Ok(TunnelInput::Data(data)), State::WaitResult) if data == PROTECTED_SUCCESS => {
+                self.session_keys = session_keys(tls);
+                self.state = State::Done;
+                Vec::new()
+            }
+            (Ok(TunnelInput::Data(_)), _) | (Err(_), _) => {
+                return PeerMethodLayerResult::Failed(env)
+            }
+        };
+
+        match self.tls.as_mut().unwrap().send_tunnel(&out) {
+            Ok(data) => PeerMethodLayerResult::Send(env.respond().write(&data)),
+            Err(_) => PeerMethodLayerResult::Failed(env),
+        }
+    }
+
+    fn method_state(&self) -> MethodState {
+        if self.session_keys.is_some() {
+            MethodState::Done
+        } else {
+            MethodState::MayCont
+        }
+    }
+
+    fn decision(&self) -> Decision {
+        if self.session_keys.is_some() {
+            Decision::UncondSucc
+        } else {
+            Decision::Fail
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.session_keys.as_ref()
+    }
+
+    fn reset(&mut self) {
+        self.tls = None;
+        self.state = State::Handshake;
+        self.session_keys = None;
+    }
+}
@@ -0,0 +1,290 @@
+use crate::{
+    layers::{
+        auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta},
+        eap_layer::SessionKeys,
+        mux::TupleElement,
+        MethodType,
+    },
+    util::{ByteReader, OwnedSlice},
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+#[derive(Clone)]
+enum State {
+    Start,
+    WaitGpsk2,
+    WaitGpsk4 { keys: Keys },
+    Failing,
+    Done { keys: Keys },
+}
+
+/// Server side of EAP-GPSK.
+///
+/// `psk_lookup` returns the PSK for the peer identity (ID_Peer). The ciphersuites are
+/// offered in the configured order, the peer picks one of them.
+#[derive(Clone)]
+pub struct AuthGpskMethod<F> {
+    id_server: OwnedSlice<64>,
+    psk_lookup: F,
+    ciphersuites: &'static [Ciphersuite],
+    protected_data: OwnedSlice<MAX_PROTECTED_DATA_LEN>,
+    peer_protected_data: OwnedSlice<MAX_PROTECTED_DATA_LEN>,
+    rand_server: [u8; RAND_LEN],
+    state: State,
+}
+
+impl<F> TupleElement for AuthGpskMethod<F>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<64>> + 'static,
+{
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<F> AuthGpskMethod<F>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<64>>,
+{
+    pub fn new(id_server: &[u8], psk_lookup: F) -> Self {
+        Self {
+            id_server: id_server.try_into().expect("id_server too long for nostd"),
+            psk_lookup,
+            ciphersuites: DEFAULT_CIPHERSUITES,
+            protected_data: OwnedSlice::new(),
+            peer_protected_data: OwnedSlice::new(),
+            rand_server: [0; RAND_LEN],
+            state: State::Start,
+        }
+    }
+
+    pub fn with_ciphersuites(mut self, ciphersuites: &'static [Ciphersuite]) -> Self {
+        assert!(!ciphersuites.is_empty());
+        self.ciphersuites = ciphersuites;
+        self
+    }
+
+    /// Data sent to the peer in GPSK-3, encrypted if the ciphersuite allows it.
+    pub fn with_protected_data(mut self, data: &[u8]) -> Self {
+        assert!(data.len() <= MAX_PROTECTED_DATA_LEN);
+        self.protected_data = data.try_into().unwrap();
+        self
+    }
+
+    /// Protected data the peer has sent in GPSK-2
+    pub fn peer_protected_data(&self) -> &[u8] {
+        self.peer_protected_data.as_ref()
+    }
+
+    /// Negotiated ciphersuite
+    pub fn ciphersuite(&self) -> Option<Ciphersuite> {
+        match &self.state {
+            State::WaitGpsk4 { keys } | State::Done { keys } => Some(keys.suite),
+            _ => None,
+        }
+    }
+
+    fn is_offered_list(&self, csuite_list: &[u8]) -> bool {
+        csuite_list.len() == self.ciphersuites.len() * CSUITE_LEN
+            && csuite_list
+                .chunks(CSUITE_LEN)
+                .zip(self.ciphersuites)
+                .all(|(a, b)| a == b.to_bytes())
+    }
+
+    fn fail<'a>(
+        &mut self,
+        code: u32,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        self.state = State::Failing;
+        AuthMethodLayerResult::Send(
+            env.respond()
+                .write(&[OP_GPSK_FAIL])
+                .write(&code.to_be_bytes()),
+        )
+    }
+
+    fn recv_gpsk_2<'a>(
+        &mut self,
+        payload: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let mut reader = ByteReader::new(payload);
+
+        let (id_peer, id_server, rand_peer, rand_server, csuite_list, csuite_sel, pd) = match (
+            reader.u16_prefixed(),
+            reader.u16_prefixed(),
+            reader.take(RAND_LEN),
+            reader.take(RAND_LEN),
+            reader.u16_prefixed(),
+            reader.take(CSUITE_LEN),
+            reader.u16_prefixed(),
+        ) {
+            (Some(a), Some(b), Some(c), Some(d), Some(e), Some(f), Some(g)) => {
+                (a, b, c, d, e, f, g)
+            }
+            _ => return AuthMethodLayerResult::Failed(env),
+        };
+        let mac = reader.remaining();
+        let mac_input = &payload[..payload.len() - mac.len()];
+
+        // The peer echoes our values, a mismatch is a sign of tampering
+        if id_server != self.id_server.as_ref()
+            || rand_server != self.rand_server
+            || !self.is_offered_list(csuite_list)
+        {
+            return AuthMethodLayerResult::Failed(env);
+        }
+
+        let suite = match Ciphersuite::from_bytes(csuite_sel) {
+            Some(suite) if self.ciphersuites.contains(&suite) => suite,
+            _ => return AuthMethodLayerResult::Failed(env),
+        };
+
+        let psk = match (self.psk_lookup)(id_peer) {
+            Some(psk) => psk,
+            None => return self.fail(FAILURE_PSK_NOT_FOUND, env),
+        };
+
+        let keys = Keys::derive(
+            suite,
+            psk.as_ref(),
+            rand_peer,
+            id_peer,
+            &self.rand_server,
+            self.id_server.as_ref(),
+        );
+
+        if !keys.verify_mac(mac_input, mac) {
+            return self.fail(FAILURE_AUTHENTICATION, env);
+        }
+
+        self.peer_protected_data = match keys.open_protected_data(pd) {
+            Some(data) => data,
+            None => return self.fail(FAILURE_AUTHENTICATION, env),
+        };
+
+        env.set_name(id_peer);
+
+        let mut iv = [0u8; IV_LEN];
+        env.fill_random(&mut iv);
+        let mut pd_buffer = [0u8; PD_BLOCK_LEN];
+        let pd = keys.seal_protected_data(self.protected_data.as_ref(), iv, &mut pd_buffer);
+
+        let msg = env
+            .respond()
+            .write(&[OP_GPSK_3])
+            .write(rand_peer)
+            .write(&self.rand_server)
+            .write(&(self.id_server.as_ref().len() as u16).to_be_bytes())
+            .write(self.id_server.as_ref())
+            .write(&suite.to_bytes())
+            .write(&(pd.len() as u16).to_be_bytes())
+            .write(pd);
+        let mac = keys.mac(&msg.slice()[1..]);
+
+        self.state = State::WaitGpsk4 { keys };
+
+        AuthMethodLayerResult::Send(msg.write(mac.as_ref()))
+    }
+
+    fn recv_gpsk_4<'a>(
+        &mut self,
+        payload: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let keys = match &self.state {
+            State::WaitGpsk4 { keys } => keys.clone(),
+            _ => return AuthMethodLayerResult::Failed(env),
+        };
+
+        let mut reader = ByteReader::new(payload);
+        let pd = match reader.u16_prefixed() {
+            Some(pd) => pd,
+            None => return AuthMethodLayerResult::Failed(env),
+        };
+        let mac = reader.remaining();
+        let mac_input = &payload[..payload.len() - mac.len()];
+
+        if !keys.verify_mac(mac_input, mac) || keys.open_protected_data(pd).is_none() {
+            return AuthMethodLayerResult::Failed(env);
+        }
+
+        self.state = State::Done { keys };
+        AuthMethodLayerResult::Finished(env)
+    }
+}
+
+impl<F> AuthMethodLayer for AuthGpskMethod<F>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<64>>,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_GPSK)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_GPSK
+    }
+
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        env.fill_random(&mut self.rand_server);
+        self.state = State::WaitGpsk2;
+
+        let mut msg = env
+            .respond()
+            .write(&[OP_GPSK_1])
+            .write(&(self.id_server.as_ref().len() as u16).to_be_bytes())
+            .write(self.id_server.as_ref())
+            .write(&self.rand_server)
+            .write(&((self.ciphersuites.len() * CSUITE_LEN) as u16).to_be_bytes());
+
+        for suite in self.ciphersuites {
+            msg = msg.write(&suite.to_bytes());
+        }
+
+        AuthMethodLayerResult::Send(msg)
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let (op_code, payload) = match msg.split_first() {
+            Some((op_code, payload)) => (*op_code, payload),
+            None => return AuthMethodLayerResult::Failed(env),
+        };
+
+        match (&self.state, op_code) {
+            (State::WaitGpsk2, OP_GPSK_2) => self.recv_gpsk_2(payload, env),
+            (State::WaitGpsk4 { .. }, OP_GPSK_4) => self.recv_gpsk_4(payload, env),
+            _ => {
+                // Includes GPSK-Fail and GPSK-Protected-Fail from the peer
+                self.state = State::Failing;
+                AuthMethodLayerResult::Failed(env)
+            }
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        match &self.state {
+            State::Done { keys } => Some(&keys.session),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,497 @@
+//! EAP-GPSK, see https://www.rfc-editor.org/rfc/rfc5433
+
+mod auth;
+mod peer;
+
+pub use auth::AuthGpskMethod;
+pub use peer::PeerGpskMethod;
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use cmac::{Cmac, Mac};
+use hmac::Hmac;
+use sha2::Sha256;
+
+use crate::{
+    layers::{eap_layer::SessionKeys, SecurityClaims},
+    util::{constant_time_eq, OwnedSlice},
+};
+
+const METHOD_GPSK: u8 = 51;
+
+const OP_GPSK_1: u8 = 1;
+const OP_GPSK_2: u8 = 2;
+const OP_GPSK_3: u8 = 3;
+const OP_GPSK_4: u8 = 4;
+const OP_GPSK_FAIL: u8 = 5;
+const OP_GPSK_PROTECTED_FAIL: u8 = 6;
+
+const FAILURE_PSK_NOT_FOUND: u32 = 1;
+const FAILURE_AUTHENTICATION: u32 = 2;
+#[allow(unused)]
+const FAILURE_AUTHORIZATION: u32 = 3;
+
+const RAND_LEN: usize = 32;
+const CSUITE_LEN: usize = 6;
+
+/// Protected data is limited so it can be handled without allocations
+pub const MAX_PROTECTED_DATA_LEN: usize = 64;
+
+const MAX_MAC_LEN: usize = 32;
+const MAX_SK_LEN: usize = 32;
+const PK_LEN: usize = 16;
+const IV_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ciphersuite {
+    /// AES-CBC-128 for protected data, AES-CMAC-128 as MAC and KDF
+    AesCmac128,
+    /// No encryption of protected data, HMAC-SHA256 as MAC and KDF
+    HmacSha256,
+}
+
+/// Ciphersuites in order of preference
+pub const DEFAULT_CIPHERSUITES: &[Ciphersuite] =
+    &[Ciphersuite::AesCmac128, Ciphersuite::HmacSha256];
+
+impl Ciphersuite {
+    const fn specifier(self) -> u16 {
+        match self {
+            Ciphersuite::AesCmac128 => 1,
+            Ciphersuite::HmacSha256 => 2,
+        }
+    }
+
+    /// CSuite field, 4 byte IETF vendor id followed by the specifier
+    fn to_bytes(self) -> [u8; CSUITE_LEN] {
+        let mut out = [0; CSUITE_LEN];
+        out[4..].copy_from_slice(&self.specifier().to_be_bytes());
+        out
+    }
+
+    fn from_bytes(data: &[u8]) -> Option<Self> {
+        match data {
+            [0, 0, 0, 0, 0, 1] => Some(Ciphersuite::AesCmac128),
+            [0, 0, 0, 0, 0, 2] => Some(Ciphersuite::HmacSha256),
+            _ => None,
+        }
+    }
+
+    /// Key size (KS)
+    fn key_size(self) -> usize {
+        match self {
+            Ciphersuite::AesCmac128 => 16,
+            Ciphersuite::HmacSha256 => 32,
+        }
+    }
+
+    fn mac_len(self) -> usize {
+        self.key_size()
+    }
+
+    fn pk_len(self) -> usize {
+        match self {
+            Ciphersuite::AesCmac128 => PK_LEN,
+            Ciphersuite::HmacSha256 => 0,
+        }
+    }
+
+    fn encrypts(self) -> bool {
+        self.pk_len() != 0
+    }
+
+    fn mac(self, key: &[u8], parts: &[&[u8]]) -> Mac32 {
+        let mut out = Mac32 {
+            buffer: [0; MAX_MAC_LEN],
+            len: self.mac_len(),
+        };
+
+        match self {
+            Ciphersuite::AesCmac128 => {
+                let mut mac = <Cmac<Aes128> as KeyInit>::new_from_slice(key).unwrap();
+                for part in parts {
+                    mac.update(part);
+                }
+                out.buffer[..16].copy_from_slice(&mac.finalize().into_bytes());
+            }
+            Ciphersuite::HmacSha256 => {
+                let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(key).unwrap();
+                for part in parts {
+                    mac.update(part);
+                }
+                out.buffer.copy_from_slice(&mac.finalize().into_bytes());
+            }
+        }
+
+        out
+    }
+
+    /// GKDF-X(Y, Z), RFC 5433 4.
+    fn gkdf(self, key: &[u8], z: &[&[u8]], out: &mut [u8]) {
+        for (i, chunk) in out.chunks_mut(self.key_size()).enumerate() {
+            let counter = (i as u16 + 1).to_be_bytes();
+
+            let mut parts: [&[u8]; 8] = [&[]; 8];
+            parts[0] = &counter;
+            parts[1..=z.len()].copy_from_slice(z);
+
+            let m = self.mac(key, &parts[..=z.len()]);
+            chunk.copy_from_slice(&m.as_ref()[..chunk.len()]);
+        }
+    }
+}
+
+/// MAC value of up to 32 bytes
+struct Mac32 {
+    buffer: [u8; MAX_MAC_LEN],
+    len: usize,
+}
+
+impl AsRef<[u8]> for Mac32 {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+#[derive(Clone)]
+struct Keys {
+    suite: Ciphersuite,
+    sk: [u8; MAX_SK_LEN],
+    pk: [u8; PK_LEN],
+    session: SessionKeys,
+}
+
+impl Keys {
+    /// RFC 5433 4. and 7.
+    fn derive(
+        suite: Ciphersuite,
+        psk: &[u8],
+        rand_peer: &[u8],
+        id_peer: &[u8],
+        rand_server: &[u8],
+        id_server: &[u8],
+    ) -> Self {
+        let ks = suite.key_size();
+        let psk_len = (psk.len() as u16).to_be_bytes();
+        let csuite = suite.to_bytes();
+
+        // MK = GKDF-KS(0x00, PL || PSK || CSuite_Sel || inputString)
+        let mut mk = [0u8; MAX_SK_LEN];
+        suite.gkdf(
+            &[0u8; MAX_SK_LEN][..ks],
+            &[
+                &psk_len,
+                psk,
+                &csuite,
+                rand_peer,
+                id_peer,
+                rand_server,
+                id_server,
+            ],
+            &mut mk[..ks],
+        );
+
+        // MSK || EMSK || SK || PK = GKDF(MK, inputString)
+        let mut out = [0u8; 64 + 64 + MAX_SK_LEN + PK_LEN];
+        let out_len = 64 + 64 + ks + suite.pk_len();
+        suite.gkdf(
+            &mk[..ks],
+            &[rand_peer, id_peer, rand_server, id_server],
+            &mut out[..out_len],
+        );
+
+        // Method-ID = GKDF-16(zero, "Method ID" || EAP_Method_Type ||
+        // CSuite_Sel || inputString), Session-ID = EAP_Method_Type || Method-ID
+        let mut method_id = [0u8; 16];
+        suite.gkdf(
+            &[0u8; MAX_SK_LEN][..ks],
+            &[
+                b"Method ID",
+                &[METHOD_GPSK],
+                &csuite,
+                rand_peer,
+                id_peer,
+                rand_server,
+                id_server,
+            ],
+            &mut method_id,
+        );
+
+        let mut keys = Keys {
+            suite,
+            sk: [0; MAX_SK_LEN],
+            pk: [0; PK_LEN],
+            session: SessionKeys {
+                msk: out[..64].try_into().unwrap(),
+                emsk: out[64..128].try_into().unwrap(),
+                session_id: SessionKeys::session_id(METHOD_GPSK, &[&method_id]),
+            },
+        };
+        keys.sk[..ks].copy_from_slice(&out[128..128 + ks]);
+        keys.pk[..suite.pk_len()].copy_from_slice(&out[128 + ks..out_len]);
+        keys
+    }
+
+    fn sk(&self) -> &[u8] {
+        &self.sk[..self.suite.key_size()]
+    }
+
+    fn mac(&self, data: &[u8]) -> Mac32 {
+        self.suite.mac(self.sk(), &[data])
+    }
+
+    fn verify_mac(&self, data: &[u8], mac: &[u8]) -> bool {
+        constant_time_eq(self.mac(data).as_ref(), mac)
+    }
+
+    /// Encodes the PD_Payload_Block, encrypted if the ciphersuite supports it.
+    /// The encrypted block is `IV || AES-CBC-128(PK, data || padding)`.
+    fn seal_protected_data<'b>(
+        &self,
+        data: &[u8],
+        iv: [u8; IV_LEN],
+        out: &'b mut [u8; PD_BLOCK_LEN],
+    ) -> &'b [u8] {
+        assert!(data.len() <= MAX_PROTECTED_DATA_LEN);
+
+        if data.is_empty() {
+            return &[];
+        }
+
+        if !self.suite.encrypts() {
+            out[..data.len()].copy_from_slice(data);
+            return &out[..data.len()];
+        }
+
+        let cipher = Aes128::new(GenericArray::from_slice(&self.pk));
+
+        // PKCS#7 padding
+        let padded_len = (data.len() / 16 + 1) * 16;
+        let pad = (padded_len - data.len()) as u8;
+        out[..IV_LEN].copy_from_slice(&iv);
+        out[IV_LEN..IV_LEN + data.len()].copy_from_slice(data);
+        out[IV_LEN + data.len()..IV_LEN + padded_len].fill(pad);
+
+        let mut previous = iv;
+        for block in out[IV_LEN..IV_LEN + padded_len].chunks_mut(16) {
+            for (b, p) in block.iter_mut().zip(previous) {
+                *b ^= p;
+            }
+            cipher.encrypt_block(GenericArray::from_mut_slice(block));
+            previous.copy_from_slice(block);
+        }
+
+        &out[..IV_LEN + padded_len]
+    }
+
+    fn open_protected_data(&self, block: &[u8]) -> Option<OwnedSlice<MAX_PROTECTED_DATA_LEN>> {
+        if block.is_empty() {
+            return Some(OwnedSlice::new());
+        }
+
+        if !self.suite.encrypts() {
+            return block.try_into().ok();
+        }
+
+        if block.len() < IV_LEN + 16
+            || !block.len().is_multiple_of(16)
+            || block.len() > PD_BLOCK_LEN
+        {
+            return None;
+        }
+
+        let cipher = Aes128::new(GenericArray::from_slice(&self.pk));
+
+        let mut buffer = [0u8; PD_BLOCK_LEN];
+        let ciphertext = &block[IV_LEN..];
+        let plaintext = &mut buffer[..ciphertext.len()];
+        plaintext.copy_from_slice(ciphertext);
+
+        let mut previous: [u8; 16] = block[..IV_LEN].try_into().unwrap();
+        for (block, ciphertext) in plaintext.chunks_mut(16).zip(ciphertext.chunks(16)) {
+            cipher.decrypt_block(GenericArray::from_mut_slice(block));
+            for (b, p) in block.iter_mut().zip(previous) {
+                *b ^= p;
+            }
+            previous.copy_from_slice(ciphertext);
+        }
+
+        let pad = *plaintext.last()? as usize;
+        if pad == 0
+            || pad > 16
+            || plaintext[plaintext.len() - pad..]
+                .iter()
+                .any(|p| *p as usize != pad)
+        {
+            return None;
+        }
+
+        plaintext[..plaintext.len() - pad].try_into().ok()
+    }
+}
+
+const PD_BLOCK_LEN: usize = IV_LEN + MAX_PROTECTED_DATA_LEN + 16;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ciphersuite_encoding() {
+        for suite in DEFAULT_CIPHERSUITES {
+            assert_eq!(Ciphersuite::from_bytes(&suite.to_bytes()), Some(*suite));
+        }
+        assert_eq!(Ciphersuite::from_bytes(&[0, 0, 0, 1, 0, 1]), None);
+    }
+
+    #[test]
+    fn protected_data_roundtrip() {
+        for suite in DEFAULT_CIPHERSUITES {
+            let keys = Keys::derive(*suite, &[1; 16], &[2; 32], b"peer", &[3; 32], b"server");
+
+            for len in [0, 1, 15, 16, 17, MAX_PROTECTED_DATA_LEN] {
+                let data = [0x5a; MAX_PROTECTED_DATA_LEN];
+                let mut buffer = [0u8; PD_BLOCK_LEN];
+                let sealed = keys.seal_protected_data(&data[..len], [9; 16], &mut buffer);
+                assert_eq!(sealed.len() != len, suite.encrypts() && len != 0);
+
+                let opened = keys.open_protected_data(sealed).unwrap();
+                assert_eq!(opened.as_ref(), &data[..len]);
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn gpsk_exchange() {
+        use crate::layers::{
+            auth::auth_layer::{self, AuthMethodLayer, AuthMethodLayerResult},
+            eap_layer::Decision,
+            peer::peer_layer::{self, PeerMethodLayer, PeerMethodLayerResult},
+        };
+        use crate::message::{Message, MessageCode};
+
+        let configs: &[(&'static [Ciphersuite], &'static [Ciphersuite], Ciphersuite)] = &[
+            (
+                DEFAULT_CIPHERSUITES,
+                DEFAULT_CIPHERSUITES,
+                Ciphersuite::AesCmac128,
+            ),
+            (
+                DEFAULT_CIPHERSUITES,
+                &[Ciphersuite::HmacSha256, Ciphersuite::AesCmac128],
+                Ciphersuite::HmacSha256,
+            ),
+            (
+                &[Ciphersuite::HmacSha256],
+                DEFAULT_CIPHERSUITES,
+                Ciphersuite::HmacSha256,
+            ),
+        ];
+
+        for (server_suites, peer_suites, expected) in configs {
+            let mut auth_env = crate::DefaultEnvironment::new();
+            let mut peer_env = crate::DefaultEnvironment::new();
+
+            let mut auth = AuthGpskMethod::new(b"server", |id: &[u8]| {
+                (id == b"peer").then(|| OwnedSlice::from(&[7; 16]))
+            })
+            .with_ciphersuites(server_suites)
+            .with_protected_data(b"to peer");
+            let mut peer = PeerGpskMethod::new(b"peer", &[7; 16])
+                .with_ciphersuites(peer_suites)
+                .with_protected_data(b"to server");
+
+            let dummy = Message::new(MessageCode::Request, 0, &[]);
+
+            let gpsk_1 = match auth.start(&mut auth_env) {
+                AuthMethodLayerResult::Send(msg) => msg.slice().to_vec(),
+                _ => panic!("expected GPSK-1"),
+            };
+            let gpsk_2 = match peer.recv(
+                &gpsk_1,
+                &peer_layer::RecvMeta { message: dummy },
+                &mut peer_env,
+            ) {
+                PeerMethodLayerResult::Send(msg) => msg.slice().to_vec(),
+                _ => panic!("expected GPSK-2"),
+            };
+            let gpsk_3 = match auth.recv(
+                &gpsk_2,
+                &auth_layer::RecvMeta { message: dummy },
+                &mut auth_env,
+            ) {
+                AuthMethodLayerResult::Send(msg) => msg.slice().to_vec(),
+                _ => panic!("expected GPSK-3"),
+            };
+            assert_eq!(gpsk_3[0], OP_GPSK_3);
+            let gpsk_4 = match peer.recv(
+                &gpsk_3,
+                &peer_layer::RecvMeta { message: dummy },
+                &mut peer_env,
+            ) {
+                PeerMethodLayerResult::Send(msg) => msg.slice().to_vec(),
+                _ => panic!("expected GPSK-4"),
+            };
+            assert!(matches!(
+                auth.recv(
+                    &gpsk_4,
+                    &auth_layer::RecvMeta { message: dummy },
+                    &mut auth_env
+                ),
+                AuthMethodLayerResult::Finished(_)
+            ));
+
+            assert_eq!(auth.ciphersuite(), Some(*expected));
+            assert_eq!(peer.ciphersuite(), Some(*expected));
+            assert_eq!(auth.peer_protected_data(), b"to server");
+            assert_eq!(peer.server_protected_data(), b"to peer");
+            assert_eq!(peer.decision(), Decision::CondSucc);
+            assert!(auth.session_keys().is_some());
+            assert_eq!(
+                auth.session_keys().map(|k| k.msk),
+                peer.session_keys().map(|k| k.msk)
+            );
+
+            // Modified GPSK-3 is rejected with a GPSK-Fail
+            let mut peer = peer.clone();
+            peer.reset();
+            let _ = peer.recv(
+                &gpsk_1,
+                &peer_layer::RecvMeta { message: dummy },
+                &mut peer_env,
+            );
+            let mut tampered = gpsk_3.clone();
+            *tampered.last_mut().unwrap() ^= 1;
+            assert!(matches!(
+                peer.recv(&tampered, &peer_layer::RecvMeta { message: dummy }, &mut peer_env),
+                PeerMethodLayerResult::Send(msg) if msg.slice()[0] == OP_GPSK_FAIL
+            ));
+            assert_eq!(peer.decision(), Decision::Fail);
+        }
+    }
+
+    #[test]
+    fn keys_depend_on_suite() {
+        let a = Keys::derive(
+            Ciphersuite::AesCmac128,
+            &[1; 16],
+            &[2; 32],
+            b"peer",
+            &[3; 32],
+            b"server",
+        );
+        let b = Keys::derive(
+            Ciphersuite::HmacSha256,
+            &[1; 16],
+            &[2; 32],
+            b"peer",
+            &[3; 32],
+            b"server",
+        );
+        assert_ne!(a.session.msk, b.session.msk);
+        assert_ne!(a.session.msk, a.session.emsk);
+    }
+}
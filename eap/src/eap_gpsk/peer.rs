@@ -0,0 +1,292 @@
+use crate::{
+    layers::{
+        eap_layer::{Decision, MethodState, SessionKeys},
+        mux::TupleElement,
+        peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta},
+        MethodType,
+    },
+    util::{ByteReader, OwnedSlice},
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+#[derive(Clone)]
+enum State {
+    WaitGpsk1,
+    WaitGpsk3 {
+        keys: Keys,
+        rand_peer: [u8; RAND_LEN],
+        rand_server: [u8; RAND_LEN],
+        id_server: OwnedSlice<64>,
+    },
+    Done {
+        keys: Option<Keys>,
+    },
+}
+
+/// Peer side of EAP-GPSK.
+///
+/// The peer selects the first ciphersuite of its own list that is offered by the server.
+#[derive(Clone)]
+pub struct PeerGpskMethod {
+    id_peer: OwnedSlice<64>,
+    psk: OwnedSlice<64>,
+    ciphersuites: &'static [Ciphersuite],
+    protected_data: OwnedSlice<MAX_PROTECTED_DATA_LEN>,
+    server_protected_data: OwnedSlice<MAX_PROTECTED_DATA_LEN>,
+    state: State,
+}
+
+impl TupleElement for PeerGpskMethod {
+    type Target = dyn PeerMethodLayer;
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl PeerGpskMethod {
+    pub fn new(id_peer: &[u8], psk: &[u8]) -> Self {
+        // RFC 5433 8.: the PSK MUST be at least 16 bytes long
+        assert!(psk.len() >= 16, "PSK too short");
+
+        Self {
+            id_peer: id_peer.try_into().expect("id_peer too long for nostd"),
+            psk: psk.try_into().expect("psk too long for nostd"),
+            ciphersuites: DEFAULT_CIPHERSUITES,
+            protected_data: OwnedSlice::new(),
+            server_protected_data: OwnedSlice::new(),
+            state: State::WaitGpsk1,
+        }
+    }
+
+    pub fn with_ciphersuites(mut self, ciphersuites: &'static [Ciphersuite]) -> Self {
+        assert!(!ciphersuites.is_empty());
+        self.ciphersuites = ciphersuites;
+        self
+    }
+
+    /// Data sent to the server in GPSK-2, encrypted if the ciphersuite allows it.
+    pub fn with_protected_data(mut self, data: &[u8]) -> Self {
+        assert!(data.len() <= MAX_PROTECTED_DATA_LEN);
+        self.protected_data = data.try_into().unwrap();
+        self
+    }
+
+    /// Protected data the server has sent in GPSK-3
+    pub fn server_protected_data(&self) -> &[u8] {
+        self.server_protected_data.as_ref()
+    }
+
+    /// Negotiated ciphersuite
+    pub fn ciphersuite(&self) -> Option<Ciphersuite> {
+        match &self.state {
+            State::WaitGpsk3 { keys, .. } => Some(keys.suite),
+            State::Done { keys } => keys.as_ref().map(|keys| keys.suite),
+            State::WaitGpsk1 => None,
+        }
+    }
+
+    fn recv_gpsk_1<'a>(
+        &mut self,
+        payload: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let mut reader = ByteReader::new(payload);
+        let (id_server, rand_server, csuite_list) = match (
+            reader.u16_prefixed(),
+            reader.take_array::<RAND_LEN>(),
+            reader.u16_prefixed(),
+        ) {
+            (Some(a), Some(b), Some(c)) if reader.is_empty() => (a, b, c),
+            _ => return PeerMethodLayerResult::Failed(env),
+        };
+
+        if csuite_list.is_empty() || !csuite_list.len().is_multiple_of(CSUITE_LEN) {
+            return PeerMethodLayerResult::Failed(env);
+        }
+
+        let suite = self.ciphersuites.iter().find(|suite| {
+            csuite_list
+                .chunks(CSUITE_LEN)
+                .any(|offered| offered == suite.to_bytes())
+        });
+        let suite = match suite {
+            Some(suite) => *suite,
+            None => return PeerMethodLayerResult::Failed(env),
+        };
+
+        let id_server: OwnedSlice<64> = match id_server.try_into() {
+            Ok(id_server) => id_server,
+            Err(_) => return PeerMethodLayerResult::Failed(env),
+        };
+
+        let mut rand_peer = [0u8; RAND_LEN];
+        env.fill_random(&mut rand_peer);
+
+        let keys = Keys::derive(
+            suite,
+            self.psk.as_ref(),
+            &rand_peer,
+            self.id_peer.as_ref(),
+            &rand_server,
+            id_server.as_ref(),
+        );
+
+        let mut iv = [0u8; IV_LEN];
+        env.fill_random(&mut iv);
+        let mut pd_buffer = [0u8; PD_BLOCK_LEN];
+        let pd = keys.seal_protected_data(self.protected_data.as_ref(), iv, &mut pd_buffer);
+
+        let msg = env
+            .respond()
+            .write(&[OP_GPSK_2])
+            .write(&(self.id_peer.as_ref().len() as u16).to_be_bytes())
+            .write(self.id_peer.as_ref())
+            .write(&(id_server.as_ref().len() as u16).to_be_bytes())
+            .write(id_server.as_ref())
+            .write(&rand_peer)
+            .write(&rand_server)
+            .write(&(csuite_list.len() as u16).to_be_bytes())
+            .write(csuite_list)
+            .write(&suite.to_bytes())
+            .write(&(pd.len() as u16).to_be_bytes())
+            .write(pd);
+        let mac = keys.mac(&msg.slice()[1..]);
+
+        self.state = State::WaitGpsk3 {
+            keys,
+            rand_peer,
+            rand_server,
+            id_server,
+        };
+
+        PeerMethodLayerResult::Send(msg.write(mac.as_ref()))
+    }
+
+    fn recv_gpsk_3<'a>(
+        &mut self,
+        payload: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let (keys, expected_rand_peer, expected_rand_server, expected_id_server) = match &self.state
+        {
+            State::WaitGpsk3 {
+                keys,
+                rand_peer,
+                rand_server,
+                id_server,
+            } => (keys.clone(), *rand_peer, *rand_server, id_server.clone()),
+            _ => return PeerMethodLayerResult::Failed(env),
+        };
+
+        let mut reader = ByteReader::new(payload);
+        let (rand_peer, rand_server, id_server, csuite_sel, pd) = match (
+            reader.take(RAND_LEN),
+            reader.take(RAND_LEN),
+            reader.u16_prefixed(),
+            reader.take(CSUITE_LEN),
+            reader.u16_prefixed(),
+        ) {
+            (Some(a), Some(b), Some(c), Some(d), Some(e)) => (a, b, c, d, e),
+            _ => return PeerMethodLayerResult::Failed(env),
+        };
+        let mac = reader.remaining();
+        let mac_input = &payload[..payload.len() - mac.len()];
+
+        let server_protected_data = if rand_peer != expected_rand_peer
+            || rand_server != expected_rand_server
+            || id_server != expected_id_server.as_ref()
+            || csuite_sel != keys.suite.to_bytes()
+            || !keys.verify_mac(mac_input, mac)
+        {
+            None
+        } else {
+            keys.open_protected_data(pd)
+        };
+
+        let server_protected_data = match server_protected_data {
+            Some(data) => data,
+            None => {
+                self.state = State::Done { keys: None };
+                return PeerMethodLayerResult::Send(
+                    env.respond()
+                        .write(&[OP_GPSK_FAIL])
+                        .write(&FAILURE_AUTHENTICATION.to_be_bytes()),
+                );
+            }
+        };
+        self.server_protected_data = server_protected_data;
+
+        // No protected data in GPSK-4
+        let msg = env.respond().write(&[OP_GPSK_4]).write(&0u16.to_be_bytes());
+        let mac = keys.mac(&msg.slice()[1..]);
+
+        self.state = State::Done { keys: Some(keys) };
+
+        PeerMethodLayerResult::Send(msg.write(mac.as_ref()))
+    }
+}
+
+impl PeerMethodLayer for PeerGpskMethod {
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_GPSK)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_GPSK
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        match msg.split_first() {
+            Some((&OP_GPSK_1, payload)) => self.recv_gpsk_1(payload, env),
+            Some((&OP_GPSK_3, payload)) => self.recv_gpsk_3(payload, env),
+            Some((&OP_GPSK_FAIL, _)) | Some((&OP_GPSK_PROTECTED_FAIL, _)) => {
+                self.state = State::Done { keys: None };
+                PeerMethodLayerResult::Failed(env)
+            }
+            _ => PeerMethodLayerResult::Failed(env),
+        }
+    }
+
+    fn method_state(&self) -> MethodState {
+        if matches!(self.state, State::Done { .. }) {
+            MethodState::Done
+        } else {
+            MethodState::MayCont
+        }
+    }
+
+    fn decision(&self) -> Decision {
+        if matches!(self.state, State::Done { keys: Some(_) }) {
+            Decision::CondSucc
+        } else {
+            Decision::Fail
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        match &self.state {
+            State::Done { keys: Some(keys) } => Some(&keys.session),
+            _ => None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = State::WaitGpsk1;
+        self.server_protected_data = OwnedSlice::new();
+    }
+}
@@ -0,0 +1,356 @@
+use crate::{
+    layers::{
+        auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta},
+        eap_layer::SessionKeys,
+        mux::TupleElement,
+        MethodType,
+    },
+    message::MessageCode,
+    util::OwnedSlice,
+    EapEnvironment,
+};
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Start,
+    WaitInitResponse,
+    WaitAuthResponse,
+    Done,
+    Failed,
+}
+
+/// Server side of EAP-IKEv2, the IKEv2 initiator.
+///
+/// `secret_lookup` returns the shared secret for the EAP identity of the peer.
+/// It may return `None` if the peer authenticates with a certificate.
+#[derive(Clone)]
+pub struct AuthIkev2Method<F> {
+    id_i: OwnedSlice<MAX_IDENTITY_LEN>,
+    secret_lookup: F,
+    credentials: Credentials,
+    state: State,
+    spi_i: Spi,
+    spi_r: Spi,
+    nonce_i: [u8; NONCE_LEN],
+    nonce_r: OwnedSlice<MAX_NONCE_LEN>,
+    dh_secret: [u8; KE_LEN],
+    keys: Option<Keys>,
+    /// The IKE_SA_INIT messages, signed by the AUTH payloads
+    init_request: Buffer<MAX_INIT_MESSAGE_LEN>,
+    init_response: Buffer<MAX_INIT_MESSAGE_LEN>,
+    outgoing: Outgoing,
+    incoming: Incoming,
+    session_keys: Option<SessionKeys>,
+}
+
+impl<F> TupleElement for AuthIkev2Method<F>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<MAX_SECRET_LEN>> + 'static,
+{
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<F> AuthIkev2Method<F>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<MAX_SECRET_LEN>>,
+{
+    pub fn new(id_i: &[u8], secret_lookup: F) -> Self {
+        Self {
+            id_i: id_i.try_into().expect("id_i too long for nostd"),
+            secret_lookup,
+            credentials: Credentials::default(),
+            state: State::Start,
+            spi_i: [0; SPI_LEN],
+            spi_r: [0; SPI_LEN],
+            nonce_i: [0; NONCE_LEN],
+            nonce_r: OwnedSlice::new(),
+            dh_secret: [0; KE_LEN],
+            keys: None,
+            init_request: Buffer::new(),
+            init_response: Buffer::new(),
+            outgoing: Outgoing::new(DEFAULT_FRAGMENT_SIZE),
+            incoming: Incoming::default(),
+            session_keys: None,
+        }
+    }
+
+    /// Authenticate with a certificate instead of the shared secret and accept
+    /// peer certificates issued by the CA of `config`.
+    #[cfg(feature = "tls")]
+    pub fn with_certificate(mut self, config: dummycert::TlsConfig) -> Self {
+        self.credentials.certificate = Some(Certificate::new(&config));
+        self
+    }
+
+    /// Largest IKE message fragment in a request, defaults to
+    /// [`DEFAULT_FRAGMENT_SIZE`]
+    pub fn with_fragment_size(mut self, fragment_size: usize) -> Self {
+        self.outgoing = Outgoing::new(fragment_size);
+        self
+    }
+
+    fn fail<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.state = State::Failed;
+        self.keys = None;
+        self.session_keys = None;
+        AuthMethodLayerResult::Failed(env)
+    }
+
+    fn recv_init_response<'a>(
+        &mut self,
+        message: &[u8],
+        identifier: u8,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let Some(response) = IkeMessage::parse(message) else {
+            return self.fail(env);
+        };
+        let header = response.header;
+        if header.exchange != EXCHANGE_IKE_SA_INIT
+            || header.flags & (FLAG_INITIATOR | FLAG_RESPONSE) != FLAG_RESPONSE
+            || header.spi_i != self.spi_i
+            || header.message_id != 0
+        {
+            return self.fail(env);
+        }
+
+        // The peer rejected the proposal or the key exchange
+        let payloads = &response.payloads;
+        if payloads.error.is_some() || payloads.sa != Some(&PROPOSAL[..]) {
+            return self.fail(env);
+        }
+        let Some(ke_r) = payloads.ke.and_then(parse_ke) else {
+            return self.fail(env);
+        };
+        let Some(nonce_r) = payloads
+            .nonce
+            .filter(|nonce| (MIN_NONCE_LEN..=MAX_NONCE_LEN).contains(&nonce.len()))
+        else {
+            return self.fail(env);
+        };
+        let (Some(shared_secret), Some(init_response)) = (
+            dh_shared_secret(&self.dh_secret, &ke_r),
+            Buffer::from_slice(message),
+        ) else {
+            return self.fail(env);
+        };
+
+        self.spi_r = header.spi_r;
+        self.nonce_r = nonce_r.try_into().unwrap();
+        self.init_response = init_response;
+        let keys = Keys::derive(
+            &shared_secret,
+            &self.nonce_i,
+            nonce_r,
+            &self.spi_i,
+            &self.spi_r,
+        );
+
+        // IKE_AUTH request: SK { IDi, [CERT,] AUTH }
+        let id_i = self.id_i.as_ref();
+        let mut inner = Buffer::<MAX_MESSAGE_LEN>::new();
+        let mut writer = PayloadWriter::chain(&mut inner);
+        writer.payload(PAYLOAD_IDI, &[&ID_HEADER, id_i]);
+        let signed = self.credentials.write_auth(
+            &mut writer,
+            &keys,
+            Role::Initiator,
+            self.init_request.as_slice(),
+            nonce_r,
+            &[&ID_HEADER, id_i],
+        );
+        let Some(first_payload) = writer.finish_chain().filter(|_| signed) else {
+            return self.fail(env);
+        };
+
+        let mut iv = [0u8; BLOCK_LEN];
+        env.fill_random(&mut iv);
+        let header = Header {
+            spi_i: self.spi_i,
+            spi_r: self.spi_r,
+            exchange: EXCHANGE_IKE_AUTH,
+            flags: FLAG_INITIATOR,
+            message_id: 1,
+        };
+        let inner = (first_payload, inner.as_slice());
+        if !self
+            .outgoing
+            .start()
+            .encrypted(&header, &keys, Role::Initiator, &iv, inner)
+        {
+            return self.fail(env);
+        }
+
+        self.state = State::WaitAuthResponse;
+        let keys = self.keys.insert(keys);
+        AuthMethodLayerResult::Send(self.outgoing.next_fragment(
+            env,
+            MessageCode::Request,
+            identifier,
+            Some((keys, Role::Initiator)),
+        ))
+    }
+
+    fn recv_auth_response<'a>(
+        &mut self,
+        message: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let (Some(response), Some(keys)) = (IkeMessage::parse(message), &self.keys) else {
+            return self.fail(env);
+        };
+        let header = response.header;
+        if header.exchange != EXCHANGE_IKE_AUTH
+            || header.flags & (FLAG_INITIATOR | FLAG_RESPONSE) != FLAG_RESPONSE
+            || header.spi_i != self.spi_i
+            || header.spi_r != self.spi_r
+            || header.message_id != 1
+        {
+            return self.fail(env);
+        }
+
+        let mut plaintext = Buffer::<MAX_MESSAGE_LEN>::new();
+        let Some(payloads) = response.decrypt(keys, Role::Responder, &mut plaintext) else {
+            return self.fail(env);
+        };
+        // AUTHENTICATION_FAILED if the peer didn't accept our AUTH payload
+        if payloads.error.is_some()
+            || !self.credentials.verify_auth(
+                &payloads,
+                keys,
+                Role::Responder,
+                self.init_response.as_slice(),
+                &self.nonce_i,
+            )
+        {
+            return self.fail(env);
+        }
+        let Some(id_r) = payloads.id_r.and_then(identification) else {
+            return self.fail(env);
+        };
+
+        self.session_keys = Some(keys.session_keys(&self.nonce_i, self.nonce_r.as_ref()));
+        env.set_name(id_r);
+        self.state = State::Done;
+        AuthMethodLayerResult::Finished(env)
+    }
+}
+
+impl<F> AuthMethodLayer for AuthIkev2Method<F>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<MAX_SECRET_LEN>>,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_IKEV2)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        // The peer is always accepted with a shared secret
+        SecurityClaims::eap_ikev2(true)
+    }
+
+    // The ICV covers the header of the next Request
+    fn binds_identifier(&self) -> bool {
+        true
+    }
+
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.keys = None;
+        self.session_keys = None;
+        self.incoming = Incoming::default();
+        self.credentials.secret = (self.secret_lookup)(env.name().unwrap_or_default());
+
+        env.fill_random(&mut self.spi_i);
+        env.fill_random(&mut self.nonce_i);
+        env.fill_random(&mut self.dh_secret);
+        let ke_i = dh_public_key(&self.dh_secret);
+
+        // IKE_SA_INIT request: SA, KEi, Ni
+        let header = Header {
+            spi_i: self.spi_i,
+            spi_r: [0; SPI_LEN],
+            exchange: EXCHANGE_IKE_SA_INIT,
+            flags: FLAG_INITIATOR,
+            message_id: 0,
+        };
+        let mut writer = self.outgoing.start();
+        writer
+            .payload(PAYLOAD_SA, &[&PROPOSAL])
+            .payload(PAYLOAD_KE, &[&KE_HEADER, &ke_i])
+            .payload(PAYLOAD_NONCE, &[&self.nonce_i]);
+        assert!(writer.finish(&header));
+        self.init_request = Buffer::from_slice(self.outgoing.message.as_slice()).unwrap();
+
+        self.state = State::WaitInitResponse;
+        // Without an ICV the EAP header of the request doesn't matter
+        AuthMethodLayerResult::Send(
+            self.outgoing
+                .next_fragment(env, MessageCode::Request, 0, None),
+        )
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let identifier = meta.message.identifier.wrapping_add(1);
+        // All messages of the IKE_AUTH exchange carry an ICV
+        let keys = match self.state {
+            State::WaitAuthResponse => self.keys.as_ref(),
+            _ => None,
+        };
+
+        let received =
+            self.incoming
+                .receive(msg, &meta.message, keys.map(|keys| (keys, Role::Responder)));
+        match (received, self.outgoing.pending()) {
+            (Some(Received::Ack), true) => {
+                AuthMethodLayerResult::Send(self.outgoing.next_fragment(
+                    env,
+                    MessageCode::Request,
+                    identifier,
+                    keys.map(|keys| (keys, Role::Initiator)),
+                ))
+            }
+            (Some(Received::Fragment), false) => AuthMethodLayerResult::Send(ack(
+                env,
+                MessageCode::Request,
+                identifier,
+                keys.map(|keys| (keys, Role::Initiator)),
+            )),
+            (Some(Received::Message), false) => {
+                let incoming = core::mem::take(&mut self.incoming);
+                let message = incoming.message.as_slice();
+                match self.state {
+                    State::WaitInitResponse => self.recv_init_response(message, identifier, env),
+                    State::WaitAuthResponse => self.recv_auth_response(message, env),
+                    _ => self.fail(env),
+                }
+            }
+            _ => self.fail(env),
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        match self.state {
+            State::Done => self.session_keys.as_ref(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::SystemTime;
+
+use dummycert::TlsConfig;
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, RsaKeyPair, RSA_PKCS1_SHA256},
+};
+use webpki::{
+    EndEntityCert, SignatureAlgorithm, Time, TlsClientTrustAnchors, TlsServerTrustAnchors,
+    TrustAnchor,
+};
+
+use super::Role;
+
+/// AlgorithmIdentifier of the signature in the AUTH payload, RFC 7427 3
+const ALGORITHM_ED25519: &[u8] = &[0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70];
+const ALGORITHM_SHA256_WITH_RSA: &[u8] = &[
+    0x30, 0x0d, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b, 0x05, 0x00,
+];
+
+/// Algorithms accepted in the certificate chain
+static CHAIN_ALGORITHMS: &[&SignatureAlgorithm] = &[
+    &webpki::ED25519,
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+];
+
+/// The own certificate and private key, and the CA certificate the other side
+/// has to chain to.
+#[derive(Clone)]
+pub struct Certificate {
+    cert: Vec<u8>,
+    key: Vec<u8>,
+    ca_cert: Vec<u8>,
+}
+
+impl Certificate {
+    /// Ed25519 and RSA keys are supported
+    pub fn new(config: &TlsConfig) -> Self {
+        let first_cert = |pem: &[u8]| {
+            rustls_pemfile::read_all(&mut &pem[..])
+                .unwrap()
+                .into_iter()
+                .find_map(|item| match item {
+                    rustls_pemfile::Item::X509Certificate(cert) => Some(cert),
+                    _ => None,
+                })
+                .expect("no certificate")
+        };
+
+        let key = rustls_pemfile::read_one(&mut config.server_key.as_ref())
+            .unwrap()
+            .and_then(|key| match key {
+                rustls_pemfile::Item::PKCS8Key(key) => Some(key),
+                _ => None,
+            })
+            .expect("no PKCS#8 key");
+
+        Self {
+            cert: first_cert(&config.server_cert),
+            key,
+            ca_cert: first_cert(&config.ca_cert),
+        }
+    }
+
+    pub fn der(&self) -> &[u8] {
+        &self.cert
+    }
+
+    /// Signature Authentication Data: the length of the AlgorithmIdentifier,
+    /// the AlgorithmIdentifier and the signature of `octets`
+    pub fn sign(&self, octets: &[u8]) -> Vec<u8> {
+        if let Ok(key) = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&self.key) {
+            let signature = key.sign(octets);
+            return [
+                &[ALGORITHM_ED25519.len() as u8],
+                ALGORITHM_ED25519,
+                signature.as_ref(),
+            ]
+            .concat();
+        }
+
+        let key = RsaKeyPair::from_pkcs8(&self.key).expect("unsupported private key");
+        let mut signature = vec![0; key.public_modulus_len()];
+        key.sign(
+            &RSA_PKCS1_SHA256,
+            &SystemRandom::new(),
+            octets,
+            &mut signature,
+        )
+        .expect("RSA signature failed");
+        [
+            &[ALGORITHM_SHA256_WITH_RSA.len() as u8],
+            ALGORITHM_SHA256_WITH_RSA,
+            &signature,
+        ]
+        .concat()
+    }
+
+    /// Checks the certificate of `sender` against the CA and the signature of
+    /// `octets` in `auth_data`, see [`Self::sign`]
+    pub fn verify(&self, sender: Role, cert: &[u8], octets: &[u8], auth_data: &[u8]) -> bool {
+        let Some((&len, rest)) = auth_data.split_first() else {
+            return false;
+        };
+        let Some((algorithm, signature)) = rest.split_at_checked(len as usize) else {
+            return false;
+        };
+        let algorithm = match algorithm {
+            ALGORITHM_ED25519 => &webpki::ED25519,
+            ALGORITHM_SHA256_WITH_RSA => &webpki::RSA_PKCS1_2048_8192_SHA256,
+            _ => return false,
+        };
+
+        let (Ok(cert), Ok(anchor), Ok(time)) = (
+            EndEntityCert::try_from(cert),
+            TrustAnchor::try_from_cert_der(&self.ca_cert),
+            Time::try_from(SystemTime::now()),
+        ) else {
+            return false;
+        };
+        let anchors = [anchor];
+        // The server needs a server certificate, the peer a client certificate
+        let valid = match sender {
+            Role::Initiator => cert.verify_is_valid_tls_server_cert(
+                CHAIN_ALGORITHMS,
+                &TlsServerTrustAnchors(&anchors),
+                &[],
+                time,
+            ),
+            Role::Responder => cert.verify_is_valid_tls_client_cert(
+                CHAIN_ALGORITHMS,
+                &TlsClientTrustAnchors(&anchors),
+                &[],
+                time,
+            ),
+        };
+
+        valid.is_ok() && cert.verify_signature(algorithm, octets, signature).is_ok()
+    }
+}
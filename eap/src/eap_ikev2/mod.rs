@@ -0,0 +1,968 @@
+//! EAP-IKEv2, see https://www.rfc-editor.org/rfc/rfc5106
+//!
+//! The server is the IKEv2 initiator. A single proposal is offered and
+//! accepted: AES-128-CBC, HMAC-SHA256 as PRF, HMAC-SHA256-128 for integrity and
+//! Curve25519 (group 31). Both sides authenticate with a shared secret or, with
+//! the `tls` feature, with a certificate and a digital signature (RFC 7427).
+
+mod auth;
+#[cfg(feature = "tls")]
+mod certificate;
+mod peer;
+
+pub use auth::AuthIkev2Method;
+pub use peer::PeerIkev2Method;
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    layers::{eap_layer::SessionKeys, SecurityClaims},
+    message::{Message, MessageCode},
+    util::{constant_time_eq, ByteReader, OwnedSlice},
+    EapEnvironment, EapEnvironmentResponse, MessageBuilder,
+};
+
+#[cfg(feature = "tls")]
+use certificate::Certificate;
+
+const METHOD_IKEV2: u8 = 49;
+
+/// EAP-IKEv2 header flags, RFC 5106 8.1
+const FLAG_LENGTH: u8 = 0x80;
+const FLAG_MORE_FRAGMENTS: u8 = 0x40;
+const FLAG_ICV: u8 = 0x20;
+
+const IKE_HEADER_LEN: usize = 28;
+const IKE_VERSION: u8 = 0x20;
+const EXCHANGE_IKE_SA_INIT: u8 = 34;
+const EXCHANGE_IKE_AUTH: u8 = 35;
+const FLAG_INITIATOR: u8 = 0x08;
+const FLAG_RESPONSE: u8 = 0x20;
+
+const PAYLOAD_NONE: u8 = 0;
+const PAYLOAD_SA: u8 = 33;
+const PAYLOAD_KE: u8 = 34;
+const PAYLOAD_IDI: u8 = 35;
+const PAYLOAD_IDR: u8 = 36;
+const PAYLOAD_CERT: u8 = 37;
+const PAYLOAD_AUTH: u8 = 39;
+const PAYLOAD_NONCE: u8 = 40;
+const PAYLOAD_NOTIFY: u8 = 41;
+const PAYLOAD_SK: u8 = 46;
+const PAYLOAD_CRITICAL: u8 = 0x80;
+
+const PROTOCOL_IKE: u8 = 1;
+const TRANSFORM_ENCR: u8 = 1;
+const TRANSFORM_PRF: u8 = 2;
+const TRANSFORM_INTEG: u8 = 3;
+const TRANSFORM_DH: u8 = 4;
+const ENCR_AES_CBC: u16 = 12;
+const PRF_HMAC_SHA2_256: u16 = 5;
+const AUTH_HMAC_SHA2_256_128: u16 = 12;
+const DH_CURVE25519: u16 = 31;
+const ATTRIBUTE_KEY_LENGTH: u16 = 0x800e;
+
+/// The single proposal of the server, RFC 7296 3.3. The peer answers with
+/// the same bytes.
+#[rustfmt::skip]
+const PROPOSAL: [u8; 44] = [
+    0, 0, 0, 44, 1, PROTOCOL_IKE, 0, 4,
+    // AES-CBC with a 128 bit key
+    3, 0, 0, 12, TRANSFORM_ENCR, 0, 0, ENCR_AES_CBC as u8, 0x80, 0x0e, 0, 128,
+    3, 0, 0, 8, TRANSFORM_PRF, 0, 0, PRF_HMAC_SHA2_256 as u8,
+    3, 0, 0, 8, TRANSFORM_INTEG, 0, 0, AUTH_HMAC_SHA2_256_128 as u8,
+    0, 0, 0, 8, TRANSFORM_DH, 0, 0, DH_CURVE25519 as u8,
+];
+
+/// KE payload up to the key exchange data: DH group and reserved bytes
+const KE_HEADER: [u8; 4] = [0, DH_CURVE25519 as u8, 0, 0];
+
+/// Both sides identify with an opaque ID_KEY_ID
+const ID_KEY_ID: u8 = 11;
+const ID_HEADER: [u8; 4] = [ID_KEY_ID, 0, 0, 0];
+
+const AUTH_SHARED_KEY: u8 = 2;
+#[cfg(feature = "tls")]
+const AUTH_DIGITAL_SIGNATURE: u8 = 14;
+#[cfg(feature = "tls")]
+const CERT_X509_SIGNATURE: u8 = 4;
+
+const NOTIFY_INVALID_SYNTAX: u16 = 7;
+const NOTIFY_NO_PROPOSAL_CHOSEN: u16 = 14;
+const NOTIFY_INVALID_KE_PAYLOAD: u16 = 17;
+const NOTIFY_AUTHENTICATION_FAILED: u16 = 24;
+/// Notify types below this value are errors
+const NOTIFY_STATUS_TYPES: u16 = 16384;
+
+const KEY_PAD: &[u8] = b"Key Pad for IKEv2";
+
+const SPI_LEN: usize = 8;
+const BLOCK_LEN: usize = 16;
+const KEY_LEN: usize = 16;
+const PRF_LEN: usize = 32;
+const ICV_LEN: usize = 16;
+const NONCE_LEN: usize = 32;
+const MIN_NONCE_LEN: usize = 16;
+const MAX_NONCE_LEN: usize = 64;
+const KE_LEN: usize = 32;
+
+pub const MAX_IDENTITY_LEN: usize = 64;
+pub const MAX_SECRET_LEN: usize = 64;
+
+/// Largest reassembled IKE message, enough for an IKE_AUTH message with a
+/// 4096 bit RSA certificate.
+const MAX_MESSAGE_LEN: usize = 3072;
+/// IKE_SA_INIT messages are kept for the AUTH payloads
+const MAX_INIT_MESSAGE_LEN: usize = 512;
+
+/// Fits a fragment with the EAP and EAP-IKEv2 headers and the ICV into the
+/// 1020 byte default response buffer
+pub const DEFAULT_FRAGMENT_SIZE: usize = 980;
+
+type Block = [u8; BLOCK_LEN];
+type Spi = [u8; SPI_LEN];
+
+fn prf(key: &[u8], parts: &[&[u8]]) -> [u8; PRF_LEN] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+    parts.iter().for_each(|part| mac.update(part));
+    mac.finalize().into_bytes().into()
+}
+
+/// prf+(K, S), RFC 7296 2.13
+fn prf_plus(key: &[u8], parts: &[&[u8]], out: &mut [u8]) {
+    let mut previous = [0u8; PRF_LEN];
+    for (i, chunk) in out.chunks_mut(PRF_LEN).enumerate() {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+        if i > 0 {
+            mac.update(&previous);
+        }
+        parts.iter().for_each(|part| mac.update(part));
+        mac.update(&[i as u8 + 1]);
+        previous = mac.finalize().into_bytes().into();
+        chunk.copy_from_slice(&previous[..chunk.len()]);
+    }
+}
+
+fn encrypt_cbc(key: &Block, iv: &Block, data: &mut [u8]) {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    let mut previous = *iv;
+    for block in data.chunks_exact_mut(BLOCK_LEN) {
+        block.iter_mut().zip(previous).for_each(|(b, p)| *b ^= p);
+        cipher.encrypt_block(GenericArray::from_mut_slice(block));
+        previous.copy_from_slice(block);
+    }
+}
+
+fn decrypt_cbc(key: &Block, iv: &Block, data: &mut [u8]) {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    let mut previous = *iv;
+    for block in data.chunks_exact_mut(BLOCK_LEN) {
+        let ciphertext: Block = block.try_into().unwrap();
+        cipher.decrypt_block(GenericArray::from_mut_slice(block));
+        block.iter_mut().zip(previous).for_each(|(b, p)| *b ^= p);
+        previous = ciphertext;
+    }
+}
+
+/// Fixed capacity byte buffer, IKE messages don't fit an OwnedSlice
+#[derive(Clone)]
+struct Buffer<const N: usize> {
+    data: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Buffer<N> {
+    const fn new() -> Self {
+        Self {
+            data: [0; N],
+            len: 0,
+        }
+    }
+
+    fn from_slice(data: &[u8]) -> Option<Self> {
+        let mut buffer = Self::new();
+        buffer.extend(data).then_some(buffer)
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Appends `data`, returns false if it doesn't fit
+    #[must_use]
+    fn extend(&mut self, data: &[u8]) -> bool {
+        let Some(target) = self.data.get_mut(self.len..self.len + data.len()) else {
+            return false;
+        };
+        target.copy_from_slice(data);
+        self.len += data.len();
+        true
+    }
+}
+
+impl<const N: usize> Default for Buffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The server is the initiator of the IKE SA, the peer the responder
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Role {
+    Initiator,
+    Responder,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Header {
+    spi_i: Spi,
+    spi_r: Spi,
+    exchange: u8,
+    flags: u8,
+    message_id: u32,
+}
+
+impl Header {
+    fn bytes(&self, first_payload: u8, len: usize) -> [u8; IKE_HEADER_LEN] {
+        let mut header = [0u8; IKE_HEADER_LEN];
+        header[..8].copy_from_slice(&self.spi_i);
+        header[8..16].copy_from_slice(&self.spi_r);
+        header[16] = first_payload;
+        header[17] = IKE_VERSION;
+        header[18] = self.exchange;
+        header[19] = self.flags;
+        header[20..24].copy_from_slice(&self.message_id.to_be_bytes());
+        header[24..].copy_from_slice(&(len as u32).to_be_bytes());
+        header
+    }
+
+    /// Header of the response to this request
+    fn response(&self) -> Self {
+        Self {
+            flags: FLAG_RESPONSE,
+            ..*self
+        }
+    }
+}
+
+/// A received IKE message
+struct IkeMessage<'a> {
+    header: Header,
+    payloads: Payloads<'a>,
+    raw: &'a [u8],
+}
+
+impl<'a> IkeMessage<'a> {
+    fn parse(raw: &'a [u8]) -> Option<Self> {
+        let mut reader = ByteReader::new(raw);
+        let spi_i = reader.take_array()?;
+        let spi_r = reader.take_array()?;
+        let first_payload = reader.u8()?;
+        // Only the major version has to match
+        if reader.u8()? >> 4 != IKE_VERSION >> 4 {
+            return None;
+        }
+        let exchange = reader.u8()?;
+        let flags = reader.u8()?;
+        let message_id = reader.u32()?;
+        if reader.u32()? as usize != raw.len() {
+            return None;
+        }
+
+        Some(Self {
+            header: Header {
+                spi_i,
+                spi_r,
+                exchange,
+                flags,
+                message_id,
+            },
+            payloads: Payloads::parse(first_payload, reader.remaining(), IKE_HEADER_LEN)?,
+            raw,
+        })
+    }
+
+    /// Verifies and decrypts the SK payload sent by `sender`, the inner payloads
+    /// borrow from `out`.
+    fn decrypt<'b, const N: usize>(
+        &self,
+        keys: &Keys,
+        sender: Role,
+        out: &'b mut Buffer<N>,
+    ) -> Option<Payloads<'b>> {
+        let (first_payload, offset) = self.payloads.encrypted?;
+        let (authenticated, icv) = self.raw.split_at(self.raw.len().checked_sub(ICV_LEN)?);
+        if offset > authenticated.len()
+            || !constant_time_eq(&keys.icv(sender, &[authenticated]), icv)
+        {
+            return None;
+        }
+
+        let (iv, ciphertext) = authenticated[offset..].split_first_chunk::<BLOCK_LEN>()?;
+        if ciphertext.is_empty() || ciphertext.len() % BLOCK_LEN != 0 {
+            return None;
+        }
+        *out = Buffer::from_slice(ciphertext)?;
+        decrypt_cbc(&keys.sk_e[sender as usize], iv, &mut out.data[..out.len]);
+
+        let pad_len = *out.as_slice().last()? as usize;
+        out.len = out.len.checked_sub(pad_len + 1)?;
+        Payloads::parse(first_payload, out.as_slice(), 0)
+    }
+}
+
+/// The payloads of a message this implementation looks at, others are skipped
+#[derive(Default)]
+struct Payloads<'a> {
+    sa: Option<&'a [u8]>,
+    ke: Option<&'a [u8]>,
+    nonce: Option<&'a [u8]>,
+    id_i: Option<&'a [u8]>,
+    id_r: Option<&'a [u8]>,
+    cert: Option<&'a [u8]>,
+    auth: Option<&'a [u8]>,
+    /// Type of the first error notification
+    error: Option<u16>,
+    /// First inner payload and offset of the SK payload body in the message
+    encrypted: Option<(u8, usize)>,
+}
+
+impl<'a> Payloads<'a> {
+    /// Parses a payload chain, `base` is the offset of `data` in its message
+    fn parse(first_payload: u8, data: &'a [u8], base: usize) -> Option<Self> {
+        let mut payloads = Self::default();
+        let mut reader = ByteReader::new(data);
+        let mut next = first_payload;
+
+        while next != PAYLOAD_NONE {
+            let current = next;
+            next = reader.u8()?;
+            let critical = reader.u8()? & PAYLOAD_CRITICAL != 0;
+            let len = (reader.u16()? as usize).checked_sub(4)?;
+            let offset = base + data.len() - reader.remaining().len();
+            let body = reader.take(len)?;
+
+            match current {
+                PAYLOAD_SA => payloads.sa = Some(body),
+                PAYLOAD_KE => payloads.ke = Some(body),
+                PAYLOAD_NONCE => payloads.nonce = Some(body),
+                PAYLOAD_IDI => payloads.id_i = Some(body),
+                PAYLOAD_IDR => payloads.id_r = Some(body),
+                PAYLOAD_CERT => payloads.cert = payloads.cert.or(Some(body)),
+                PAYLOAD_AUTH => payloads.auth = Some(body),
+                PAYLOAD_NOTIFY => {
+                    // Protocol ID and SPI size precede the type
+                    let notify_type = ByteReader::new(body.get(2..)?).u16()?;
+                    if notify_type < NOTIFY_STATUS_TYPES {
+                        payloads.error = payloads.error.or(Some(notify_type));
+                    }
+                }
+                // The encrypted payload is always the last one, its next
+                // payload is the first inner one
+                PAYLOAD_SK => {
+                    payloads.encrypted = Some((next, offset));
+                    return reader.is_empty().then_some(payloads);
+                }
+                _ if critical => return None,
+                _ => {}
+            }
+        }
+
+        reader.is_empty().then_some(payloads)
+    }
+}
+
+/// Checks the single transform of each type of the offered proposals, returns
+/// the number of the first acceptable one.
+fn select_proposal(sa: &[u8]) -> Option<u8> {
+    let mut proposals = ByteReader::new(sa);
+    while !proposals.is_empty() {
+        let _last = proposals.u8()?;
+        let _reserved = proposals.u8()?;
+        let len = (proposals.u16()? as usize).checked_sub(4)?;
+        let mut proposal = ByteReader::new(proposals.take(len)?);
+
+        let number = proposal.u8()?;
+        let protocol = proposal.u8()?;
+        let spi_size = proposal.u8()?;
+        let transforms = proposal.u8()?;
+        proposal.take(spi_size as usize)?;
+
+        let mut found = [false; 4];
+        for _ in 0..transforms {
+            let _last = proposal.u8()?;
+            let _reserved = proposal.u8()?;
+            let len = (proposal.u16()? as usize).checked_sub(4)?;
+            let mut transform = ByteReader::new(proposal.take(len)?);
+            let transform_type = transform.u8()?;
+            let _reserved = transform.u8()?;
+            let id = transform.u16()?;
+            let attributes = transform.remaining();
+
+            let acceptable = match transform_type {
+                TRANSFORM_ENCR => {
+                    let [key_length_type @ .., 0, 128] = attributes else {
+                        continue;
+                    };
+                    id == ENCR_AES_CBC && *key_length_type == ATTRIBUTE_KEY_LENGTH.to_be_bytes()
+                }
+                TRANSFORM_PRF => id == PRF_HMAC_SHA2_256,
+                TRANSFORM_INTEG => id == AUTH_HMAC_SHA2_256_128,
+                TRANSFORM_DH => id == DH_CURVE25519,
+                _ => false,
+            };
+            if acceptable {
+                found[transform_type as usize - 1] = true;
+            }
+        }
+
+        if protocol == PROTOCOL_IKE && spi_size == 0 && found == [true; 4] {
+            return Some(number);
+        }
+    }
+
+    None
+}
+
+/// Curve25519 public key of a KE payload
+fn parse_ke(body: &[u8]) -> Option<[u8; KE_LEN]> {
+    body.strip_prefix(&KE_HEADER)?.try_into().ok()
+}
+
+/// Identification Data of an ID payload
+fn identification(id: &[u8]) -> Option<&[u8]> {
+    id.get(ID_HEADER.len()..)
+}
+
+fn dh_public_key(secret: &[u8; KE_LEN]) -> [u8; KE_LEN] {
+    x25519_dalek::x25519(*secret, x25519_dalek::X25519_BASEPOINT_BYTES)
+}
+
+fn dh_shared_secret(secret: &[u8; KE_LEN], public_key: &[u8; KE_LEN]) -> Option<[u8; KE_LEN]> {
+    let shared_secret = x25519_dalek::x25519(*secret, *public_key);
+    // Low order points, RFC 8031 2.3
+    (shared_secret != [0; KE_LEN]).then_some(shared_secret)
+}
+
+/// Keys of the IKE SA, RFC 7296 2.14
+#[derive(Clone)]
+struct Keys {
+    sk_d: [u8; PRF_LEN],
+    /// The keys below are indexed by the sending [`Role`]
+    sk_a: [[u8; PRF_LEN]; 2],
+    sk_e: [Block; 2],
+    sk_p: [[u8; PRF_LEN]; 2],
+}
+
+impl Keys {
+    fn derive(
+        shared_secret: &[u8; KE_LEN],
+        nonce_i: &[u8],
+        nonce_r: &[u8],
+        spi_i: &Spi,
+        spi_r: &Spi,
+    ) -> Self {
+        let mut nonces = Buffer::<{ 2 * MAX_NONCE_LEN }>::new();
+        assert!(nonces.extend(nonce_i) && nonces.extend(nonce_r));
+        let skeyseed = prf(nonces.as_slice(), &[shared_secret]);
+
+        let mut material = [0u8; 5 * PRF_LEN + 2 * KEY_LEN];
+        prf_plus(&skeyseed, &[nonce_i, nonce_r, spi_i, spi_r], &mut material);
+
+        let mut reader = ByteReader::new(&material);
+        let sk_d = reader.take_array().unwrap();
+        let sk_a = [reader.take_array().unwrap(), reader.take_array().unwrap()];
+        let sk_e = [reader.take_array().unwrap(), reader.take_array().unwrap()];
+        let sk_p = [reader.take_array().unwrap(), reader.take_array().unwrap()];
+
+        Self {
+            sk_d,
+            sk_a,
+            sk_e,
+            sk_p,
+        }
+    }
+
+    /// MSK | EMSK = prf+(SK_d, Ni | Nr), the Session-ID is Type | Ni | Nr,
+    /// RFC 5106 5
+    fn session_keys(&self, nonce_i: &[u8], nonce_r: &[u8]) -> SessionKeys {
+        let mut material = [0u8; 128];
+        prf_plus(&self.sk_d, &[nonce_i, nonce_r], &mut material);
+
+        SessionKeys {
+            msk: material[..64].try_into().unwrap(),
+            emsk: material[64..].try_into().unwrap(),
+            session_id: SessionKeys::session_id(METHOD_IKEV2, &[nonce_i, nonce_r]),
+        }
+    }
+
+    /// HMAC-SHA256-128 with SK_a of `sender`, for the SK payload and the
+    /// EAP-IKEv2 ICV
+    fn icv(&self, sender: Role, parts: &[&[u8]]) -> [u8; ICV_LEN] {
+        prf(&self.sk_a[sender as usize], parts)[..ICV_LEN]
+            .try_into()
+            .unwrap()
+    }
+
+    /// prf(SK_p, IDx') over the ID payload body of `sender`
+    fn id_mac(&self, sender: Role, id: &[&[u8]]) -> [u8; PRF_LEN] {
+        prf(&self.sk_p[sender as usize], id)
+    }
+}
+
+/// AUTH data with a shared secret, RFC 7296 2.15. The signed octets are the
+/// IKE_SA_INIT message of the sender, the nonce of the other side and the MAC
+/// of the sender's ID.
+fn shared_key_auth(
+    secret: &[u8],
+    message: &[u8],
+    nonce: &[u8],
+    id_mac: &[u8; PRF_LEN],
+) -> [u8; PRF_LEN] {
+    prf(&prf(secret, &[KEY_PAD]), &[message, nonce, id_mac])
+}
+
+/// Writes a chain of payloads. The type of the first payload goes into the
+/// IKE header or the SK payload containing the chain.
+struct PayloadWriter<'b, const N: usize> {
+    buffer: &'b mut Buffer<N>,
+    first_payload: u8,
+    /// Offset of the next payload field of the last written payload
+    next: Option<usize>,
+    complete: bool,
+}
+
+impl<'b, const N: usize> PayloadWriter<'b, N> {
+    fn chain(buffer: &'b mut Buffer<N>) -> Self {
+        buffer.clear();
+        Self {
+            buffer,
+            first_payload: PAYLOAD_NONE,
+            next: None,
+            complete: true,
+        }
+    }
+
+    /// Leaves room for the IKE header, written by [`Self::finish`]
+    fn message(buffer: &'b mut Buffer<N>) -> Self {
+        let mut writer = Self::chain(buffer);
+        writer.complete = writer.buffer.extend(&[0; IKE_HEADER_LEN]);
+        writer
+    }
+
+    fn payload(&mut self, payload_type: u8, parts: &[&[u8]]) -> &mut Self {
+        let len = 4 + parts.iter().map(|part| part.len()).sum::<usize>();
+        let start = self.buffer.len;
+        if !self.complete || len > u16::MAX as usize {
+            self.complete = false;
+            return self;
+        }
+
+        self.complete = self.buffer.extend(&[PAYLOAD_NONE, 0])
+            && self.buffer.extend(&(len as u16).to_be_bytes())
+            && parts.iter().all(|part| self.buffer.extend(part));
+        if self.complete {
+            match self.next {
+                Some(offset) => self.buffer.data[offset] = payload_type,
+                None => self.first_payload = payload_type,
+            }
+            self.next = Some(start);
+        }
+        self
+    }
+
+    /// The type of the first payload, if everything fit
+    fn finish_chain(self) -> Option<u8> {
+        self.complete.then_some(self.first_payload)
+    }
+
+    fn finish(self, header: &Header) -> bool {
+        if self.complete {
+            let header = header.bytes(self.first_payload, self.buffer.len);
+            self.buffer.data[..IKE_HEADER_LEN].copy_from_slice(&header);
+        }
+        self.complete
+    }
+
+    /// Appends the SK payload with the encrypted payload chain `inner` and
+    /// finishes the message, RFC 7296 3.14
+    fn encrypted(
+        mut self,
+        header: &Header,
+        keys: &Keys,
+        sender: Role,
+        iv: &Block,
+        inner: (u8, &[u8]),
+    ) -> bool {
+        let (first_inner, inner) = inner;
+        let pad_len = BLOCK_LEN - 1 - inner.len() % BLOCK_LEN;
+        self.payload(
+            PAYLOAD_SK,
+            &[
+                iv,
+                inner,
+                &[0; BLOCK_LEN][..pad_len],
+                &[pad_len as u8],
+                &[0; ICV_LEN],
+            ],
+        );
+        let Some(sk) = self.next.filter(|_| self.complete) else {
+            return false;
+        };
+        // The next payload field of SK holds the first inner payload
+        self.buffer.data[sk] = first_inner;
+
+        let end = self.buffer.len - ICV_LEN;
+        let plaintext = sk + 4 + BLOCK_LEN..end;
+        encrypt_cbc(
+            &keys.sk_e[sender as usize],
+            iv,
+            &mut self.buffer.data[plaintext],
+        );
+
+        let buffer = &mut *self.buffer;
+        let first_payload = self.first_payload;
+        buffer.data[..IKE_HEADER_LEN].copy_from_slice(&header.bytes(first_payload, buffer.len));
+        let icv = keys.icv(sender, &[&buffer.data[..end]]);
+        buffer.data[end..buffer.len].copy_from_slice(&icv);
+        true
+    }
+}
+
+/// Writes an EAP-IKEv2 message. Once the IKE SA keys exist, the ICV of
+/// `sender` covers the whole EAP packet.
+fn respond<'a>(
+    env: &'a mut dyn EapEnvironment,
+    code: MessageCode,
+    identifier: u8,
+    flags: u8,
+    length: &[u8],
+    data: &[u8],
+    icv: Option<(&Keys, Role)>,
+) -> MessageBuilder<'a> {
+    let Some((keys, sender)) = icv else {
+        return env.respond().write(&[flags]).write(length).write(data);
+    };
+
+    let flags = [flags | FLAG_ICV];
+    let len = 4 + 1 + flags.len() + length.len() + data.len() + ICV_LEN;
+    let header = [code as u8, identifier, (len >> 8) as u8, len as u8];
+    let icv = keys.icv(sender, &[&header, &[METHOD_IKEV2], &flags, length, data]);
+
+    env.respond()
+        .write(&flags)
+        .write(length)
+        .write(data)
+        .write(&icv)
+}
+
+/// Acknowledges a received fragment
+fn ack<'a>(
+    env: &'a mut dyn EapEnvironment,
+    code: MessageCode,
+    identifier: u8,
+    icv: Option<(&Keys, Role)>,
+) -> MessageBuilder<'a> {
+    respond(env, code, identifier, 0, &[], &[], icv)
+}
+
+/// The IKE message being sent, in fragments of at most `fragment_size` bytes,
+/// RFC 5106 8.3
+#[derive(Clone)]
+struct Outgoing {
+    message: Buffer<MAX_MESSAGE_LEN>,
+    sent: usize,
+    fragment_size: usize,
+}
+
+impl Outgoing {
+    fn new(fragment_size: usize) -> Self {
+        assert!(fragment_size > 0);
+        Self {
+            message: Buffer::new(),
+            sent: 0,
+            fragment_size,
+        }
+    }
+
+    /// Writer for the next message
+    fn start(&mut self) -> PayloadWriter<'_, MAX_MESSAGE_LEN> {
+        self.sent = 0;
+        PayloadWriter::message(&mut self.message)
+    }
+
+    fn pending(&self) -> bool {
+        self.sent < self.message.len
+    }
+
+    fn clear(&mut self) {
+        self.message.clear();
+        self.sent = 0;
+    }
+
+    /// The first fragment of a fragmented message carries its total length
+    fn next_fragment<'a>(
+        &mut self,
+        env: &'a mut dyn EapEnvironment,
+        code: MessageCode,
+        identifier: u8,
+        icv: Option<(&Keys, Role)>,
+    ) -> MessageBuilder<'a> {
+        let message = self.message.as_slice();
+        let end = message.len().min(self.sent + self.fragment_size);
+        let fragment = &message[self.sent..end];
+
+        let mut flags = 0;
+        let total_length = (message.len() as u32).to_be_bytes();
+        let mut length = &[][..];
+        if self.sent == 0 && end < message.len() {
+            flags |= FLAG_LENGTH;
+            length = &total_length;
+        }
+        if end < message.len() {
+            flags |= FLAG_MORE_FRAGMENTS;
+        }
+
+        self.sent = end;
+        respond(env, code, identifier, flags, length, fragment, icv)
+    }
+}
+
+enum Received {
+    /// Acknowledgement of a sent fragment
+    Ack,
+    /// A fragment which has to be acknowledged
+    Fragment,
+    /// A complete IKE message, in [`Incoming::message`]
+    Message,
+}
+
+/// Reassembly of received fragments
+#[derive(Clone, Default)]
+struct Incoming {
+    message: Buffer<MAX_MESSAGE_LEN>,
+    /// Total length announced by the first fragment
+    expected: Option<usize>,
+}
+
+impl Incoming {
+    /// Checks the flags and the ICV of `sender` if keys are given. `msg` is the
+    /// data of `message` after the EAP type.
+    fn receive(
+        &mut self,
+        msg: &[u8],
+        message: &Message,
+        icv: Option<(&Keys, Role)>,
+    ) -> Option<Received> {
+        let (&flags, mut data) = msg.split_first()?;
+
+        if let Some((keys, sender)) = icv {
+            data = &data[..data.len().checked_sub(ICV_LEN)?];
+            let (authenticated, received_icv) = message.body.split_at(message.body.len() - ICV_LEN);
+            let header = [
+                message.code as u8,
+                message.identifier,
+                (message.total_length >> 8) as u8,
+                message.total_length as u8,
+            ];
+            if flags & FLAG_ICV == 0
+                || !constant_time_eq(&keys.icv(sender, &[&header, authenticated]), received_icv)
+            {
+                return None;
+            }
+        } else if flags & FLAG_ICV != 0 {
+            return None;
+        }
+
+        if flags & FLAG_LENGTH != 0 {
+            let mut reader = ByteReader::new(data);
+            let total_length = reader.u32()? as usize;
+            // Only the first fragment has the length
+            if self.message.len != 0 || total_length > MAX_MESSAGE_LEN {
+                return None;
+            }
+            self.expected = Some(total_length);
+            data = reader.remaining();
+        } else if data.is_empty() && flags & FLAG_MORE_FRAGMENTS == 0 {
+            return Some(Received::Ack);
+        }
+
+        if !self.message.extend(data) {
+            return None;
+        }
+        if flags & FLAG_MORE_FRAGMENTS != 0 {
+            return self.expected.map(|_| Received::Fragment);
+        }
+        match self.expected {
+            Some(expected) if expected != self.message.len => None,
+            _ => Some(Received::Message),
+        }
+    }
+}
+
+/// The own credentials and what is accepted from the other side
+#[derive(Clone, Default)]
+struct Credentials {
+    secret: Option<OwnedSlice<MAX_SECRET_LEN>>,
+    #[cfg(feature = "tls")]
+    certificate: Option<Certificate>,
+}
+
+impl Credentials {
+    /// Writes the CERT and AUTH payloads of `sender`: a signature if a
+    /// certificate is configured, otherwise the MAC with the shared secret.
+    /// `message` and `nonce` are the IKE_SA_INIT message of the sender and the
+    /// nonce of the other side.
+    fn write_auth<const N: usize>(
+        &self,
+        writer: &mut PayloadWriter<N>,
+        keys: &Keys,
+        sender: Role,
+        message: &[u8],
+        nonce: &[u8],
+        id: &[&[u8]],
+    ) -> bool {
+        let id_mac = keys.id_mac(sender, id);
+
+        #[cfg(feature = "tls")]
+        if let Some(certificate) = &self.certificate {
+            let signature = certificate.sign(&[message, nonce, &id_mac].concat());
+            writer
+                .payload(PAYLOAD_CERT, &[&[CERT_X509_SIGNATURE], certificate.der()])
+                .payload(
+                    PAYLOAD_AUTH,
+                    &[&[AUTH_DIGITAL_SIGNATURE, 0, 0, 0], &signature],
+                );
+            return true;
+        }
+
+        let Some(secret) = &self.secret else {
+            return false;
+        };
+        let auth = shared_key_auth(secret.as_ref(), message, nonce, &id_mac);
+        writer.payload(PAYLOAD_AUTH, &[&[AUTH_SHARED_KEY, 0, 0, 0], &auth]);
+        true
+    }
+
+    /// Checks the AUTH payload of `sender`, see [`Self::write_auth`]
+    fn verify_auth(
+        &self,
+        payloads: &Payloads,
+        keys: &Keys,
+        sender: Role,
+        message: &[u8],
+        nonce: &[u8],
+    ) -> bool {
+        let id = match sender {
+            Role::Initiator => payloads.id_i,
+            Role::Responder => payloads.id_r,
+        };
+        let (Some(id), Some(auth)) = (id, payloads.auth) else {
+            return false;
+        };
+        let id_mac = keys.id_mac(sender, &[id]);
+
+        match auth {
+            [AUTH_SHARED_KEY, _, _, _, data @ ..] => self.secret.as_ref().is_some_and(|secret| {
+                constant_time_eq(
+                    data,
+                    &shared_key_auth(secret.as_ref(), message, nonce, &id_mac),
+                )
+            }),
+            #[cfg(feature = "tls")]
+            [AUTH_DIGITAL_SIGNATURE, _, _, _, data @ ..] => {
+                match (&self.certificate, payloads.cert) {
+                    (Some(certificate), Some([CERT_X509_SIGNATURE, cert @ ..])) => {
+                        certificate.verify(sender, cert, &[message, nonce, &id_mac].concat(), data)
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proposal() {
+        assert_eq!(select_proposal(&PROPOSAL), Some(1));
+
+        // A second proposal with a different number is selected
+        let mut offer = PROPOSAL;
+        offer[0] = 2;
+        offer[4] = 1;
+        offer[19] = 0; // 0 bit AES key
+        let mut second = PROPOSAL;
+        second[4] = 2;
+        assert_eq!(select_proposal(&[offer, second].concat()), Some(2));
+        assert_eq!(select_proposal(&offer), None);
+
+        let mut group = PROPOSAL;
+        group[43] = 19;
+        assert_eq!(select_proposal(&group), None);
+    }
+
+    #[test]
+    fn encrypted_payload() {
+        let keys = Keys::derive(&[7; KE_LEN], &[1; 32], &[2; 32], &[3; 8], &[4; 8]);
+        let header = Header {
+            spi_i: [3; 8],
+            spi_r: [4; 8],
+            exchange: EXCHANGE_IKE_AUTH,
+            flags: FLAG_INITIATOR,
+            message_id: 1,
+        };
+
+        let mut inner = Buffer::<256>::new();
+        let mut writer = PayloadWriter::chain(&mut inner);
+        writer
+            .payload(PAYLOAD_IDI, &[&ID_HEADER, b"server"])
+            .payload(PAYLOAD_AUTH, &[&[AUTH_SHARED_KEY, 0, 0, 0], &[9; PRF_LEN]]);
+        let first = writer.finish_chain().unwrap();
+        assert_eq!(first, PAYLOAD_IDI);
+
+        let mut message = Buffer::<512>::new();
+        assert!(PayloadWriter::message(&mut message).encrypted(
+            &header,
+            &keys,
+            Role::Initiator,
+            &[5; BLOCK_LEN],
+            (first, inner.as_slice()),
+        ));
+
+        let parsed = IkeMessage::parse(message.as_slice()).unwrap();
+        assert_eq!(parsed.header, header);
+        let mut plaintext = Buffer::<512>::new();
+        let payloads = parsed
+            .decrypt(&keys, Role::Initiator, &mut plaintext)
+            .unwrap();
+        assert_eq!(identification(payloads.id_i.unwrap()), Some(&b"server"[..]));
+        assert_eq!(payloads.auth.unwrap().len(), 4 + PRF_LEN);
+
+        // The responder keys don't verify it
+        let mut plaintext = Buffer::<512>::new();
+        assert!(parsed
+            .decrypt(&keys, Role::Responder, &mut plaintext)
+            .is_none());
+
+        let mut tampered = Buffer::<512>::from_slice(message.as_slice()).unwrap();
+        tampered.data[IKE_HEADER_LEN + 20] ^= 1;
+        let parsed = IkeMessage::parse(tampered.as_slice()).unwrap();
+        assert!(parsed
+            .decrypt(&keys, Role::Initiator, &mut plaintext)
+            .is_none());
+    }
+}
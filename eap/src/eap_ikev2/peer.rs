@@ -0,0 +1,404 @@
+use crate::{
+    layers::{
+        eap_layer::{Decision, MethodState, SessionKeys},
+        mux::TupleElement,
+        peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta},
+        MethodType,
+    },
+    message::MessageCode,
+    util::OwnedSlice,
+    EapEnvironment,
+};
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    WaitInitRequest,
+    WaitAuthRequest,
+    Done,
+}
+
+/// Peer side of EAP-IKEv2, the IKEv2 responder
+#[derive(Clone)]
+pub struct PeerIkev2Method {
+    id_r: OwnedSlice<MAX_IDENTITY_LEN>,
+    credentials: Credentials,
+    state: State,
+    spi_i: Spi,
+    spi_r: Spi,
+    nonce_i: OwnedSlice<MAX_NONCE_LEN>,
+    nonce_r: [u8; NONCE_LEN],
+    keys: Option<Keys>,
+    /// The IKE_SA_INIT messages, signed by the AUTH payloads
+    init_request: Buffer<MAX_INIT_MESSAGE_LEN>,
+    init_response: Buffer<MAX_INIT_MESSAGE_LEN>,
+    /// Set once the IKE_AUTH exchange started, its messages carry an ICV
+    icv: bool,
+    outgoing: Outgoing,
+    incoming: Incoming,
+    session_keys: Option<SessionKeys>,
+}
+
+impl TupleElement for PeerIkev2Method {
+    type Target = dyn PeerMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl PeerIkev2Method {
+    /// The peer needs either a shared secret or a certificate
+    pub fn new(id_r: &[u8]) -> Self {
+        Self {
+            id_r: id_r.try_into().expect("id_r too long for nostd"),
+            credentials: Credentials::default(),
+            state: State::WaitInitRequest,
+            spi_i: [0; SPI_LEN],
+            spi_r: [0; SPI_LEN],
+            nonce_i: OwnedSlice::new(),
+            nonce_r: [0; NONCE_LEN],
+            keys: None,
+            init_request: Buffer::new(),
+            init_response: Buffer::new(),
+            icv: false,
+            outgoing: Outgoing::new(DEFAULT_FRAGMENT_SIZE),
+            incoming: Incoming::default(),
+            session_keys: None,
+        }
+    }
+
+    /// Secret shared with the server, also used to verify a server which
+    /// authenticates with it.
+    pub fn with_shared_secret(mut self, secret: &[u8]) -> Self {
+        self.credentials.secret = Some(secret.try_into().expect("secret too long for nostd"));
+        self
+    }
+
+    /// Authenticate with a certificate instead of the shared secret and accept
+    /// server certificates issued by the CA of `config`.
+    #[cfg(feature = "tls")]
+    pub fn with_certificate(mut self, config: dummycert::TlsConfig) -> Self {
+        self.credentials.certificate = Some(Certificate::new(&config));
+        self
+    }
+
+    /// Largest IKE message fragment in a response, defaults to
+    /// [`DEFAULT_FRAGMENT_SIZE`]
+    pub fn with_fragment_size(mut self, fragment_size: usize) -> Self {
+        self.outgoing = Outgoing::new(fragment_size);
+        self
+    }
+
+    fn fail<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> PeerMethodLayerResult<'a> {
+        self.state = State::Done;
+        self.keys = None;
+        self.session_keys = None;
+        PeerMethodLayerResult::Failed(env)
+    }
+
+    /// Answers the IKE_SA_INIT request with an error notification
+    fn reject_init<'a>(
+        &mut self,
+        request: &Header,
+        notify_type: u16,
+        data: &[u8],
+        identifier: u8,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let mut writer = self.outgoing.start();
+        writer.payload(PAYLOAD_NOTIFY, &[&[0, 0], &notify_type.to_be_bytes(), data]);
+        assert!(writer.finish(&request.response()));
+
+        self.state = State::Done;
+        PeerMethodLayerResult::Send(self.outgoing.next_fragment(
+            env,
+            MessageCode::Response,
+            identifier,
+            None,
+        ))
+    }
+
+    /// Answers the IKE_AUTH request with an encrypted error notification
+    fn reject_auth<'a>(
+        &mut self,
+        request: &Header,
+        notify_type: u16,
+        identifier: u8,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let Some(keys) = &self.keys else {
+            return self.fail(env);
+        };
+        let mut inner = Buffer::<64>::new();
+        let mut writer = PayloadWriter::chain(&mut inner);
+        writer.payload(PAYLOAD_NOTIFY, &[&[0, 0], &notify_type.to_be_bytes()]);
+        let first_payload = writer.finish_chain().unwrap();
+
+        let mut iv = [0u8; BLOCK_LEN];
+        env.fill_random(&mut iv);
+        let inner = (first_payload, inner.as_slice());
+        assert!(self.outgoing.start().encrypted(
+            &request.response(),
+            keys,
+            Role::Responder,
+            &iv,
+            inner,
+        ));
+
+        self.state = State::Done;
+        self.session_keys = None;
+        PeerMethodLayerResult::Send(self.outgoing.next_fragment(
+            env,
+            MessageCode::Response,
+            identifier,
+            Some((keys, Role::Responder)),
+        ))
+    }
+
+    fn recv_init_request<'a>(
+        &mut self,
+        message: &[u8],
+        identifier: u8,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let Some(request) = IkeMessage::parse(message) else {
+            return self.fail(env);
+        };
+        let header = request.header;
+        if header.exchange != EXCHANGE_IKE_SA_INIT
+            || header.flags & (FLAG_INITIATOR | FLAG_RESPONSE) != FLAG_INITIATOR
+            || header.spi_r != [0; SPI_LEN]
+            || header.message_id != 0
+        {
+            return self.fail(env);
+        }
+
+        let payloads = &request.payloads;
+        let (Some(sa), Some(nonce_i)) = (
+            payloads.sa,
+            payloads
+                .nonce
+                .filter(|nonce| (MIN_NONCE_LEN..=MAX_NONCE_LEN).contains(&nonce.len())),
+        ) else {
+            return self.reject_init(&header, NOTIFY_INVALID_SYNTAX, &[], identifier, env);
+        };
+        let Some(proposal) = select_proposal(sa) else {
+            return self.reject_init(&header, NOTIFY_NO_PROPOSAL_CHOSEN, &[], identifier, env);
+        };
+
+        env.fill_random(&mut self.spi_r);
+        env.fill_random(&mut self.nonce_r);
+        let mut dh_secret = [0u8; KE_LEN];
+        env.fill_random(&mut dh_secret);
+        // The notification names the group we accept
+        let Some(shared_secret) = payloads
+            .ke
+            .and_then(parse_ke)
+            .and_then(|ke_i| dh_shared_secret(&dh_secret, &ke_i))
+        else {
+            let group = &KE_HEADER[..2];
+            return self.reject_init(&header, NOTIFY_INVALID_KE_PAYLOAD, group, identifier, env);
+        };
+        let Some(init_request) = Buffer::from_slice(message) else {
+            return self.fail(env);
+        };
+
+        // IKE_SA_INIT response: SA, KEr, Nr
+        let mut sa = PROPOSAL;
+        sa[4] = proposal;
+        let response = Header {
+            spi_r: self.spi_r,
+            ..header.response()
+        };
+        let mut writer = self.outgoing.start();
+        writer
+            .payload(PAYLOAD_SA, &[&sa])
+            .payload(PAYLOAD_KE, &[&KE_HEADER, &dh_public_key(&dh_secret)])
+            .payload(PAYLOAD_NONCE, &[&self.nonce_r]);
+        assert!(writer.finish(&response));
+
+        self.spi_i = header.spi_i;
+        self.nonce_i = nonce_i.try_into().unwrap();
+        self.init_request = init_request;
+        self.init_response = Buffer::from_slice(self.outgoing.message.as_slice()).unwrap();
+        self.keys = Some(Keys::derive(
+            &shared_secret,
+            nonce_i,
+            &self.nonce_r,
+            &self.spi_i,
+            &self.spi_r,
+        ));
+
+        self.state = State::WaitAuthRequest;
+        PeerMethodLayerResult::Send(self.outgoing.next_fragment(
+            env,
+            MessageCode::Response,
+            identifier,
+            None,
+        ))
+    }
+
+    fn recv_auth_request<'a>(
+        &mut self,
+        message: &[u8],
+        identifier: u8,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let (Some(request), Some(keys)) = (IkeMessage::parse(message), &self.keys) else {
+            return self.fail(env);
+        };
+        let header = request.header;
+        if header.exchange != EXCHANGE_IKE_AUTH
+            || header.flags & (FLAG_INITIATOR | FLAG_RESPONSE) != FLAG_INITIATOR
+            || header.spi_i != self.spi_i
+            || header.spi_r != self.spi_r
+            || header.message_id != 1
+        {
+            return self.fail(env);
+        }
+
+        let mut plaintext = Buffer::<MAX_MESSAGE_LEN>::new();
+        let Some(payloads) = request.decrypt(keys, Role::Initiator, &mut plaintext) else {
+            return self.fail(env);
+        };
+        if !self.credentials.verify_auth(
+            &payloads,
+            keys,
+            Role::Initiator,
+            self.init_request.as_slice(),
+            &self.nonce_r,
+        ) {
+            return self.reject_auth(&header, NOTIFY_AUTHENTICATION_FAILED, identifier, env);
+        }
+
+        // IKE_AUTH response: SK { IDr, [CERT,] AUTH }
+        let id_r = self.id_r.as_ref();
+        let mut inner = Buffer::<MAX_MESSAGE_LEN>::new();
+        let mut writer = PayloadWriter::chain(&mut inner);
+        writer.payload(PAYLOAD_IDR, &[&ID_HEADER, id_r]);
+        let signed = self.credentials.write_auth(
+            &mut writer,
+            keys,
+            Role::Responder,
+            self.init_response.as_slice(),
+            self.nonce_i.as_ref(),
+            &[&ID_HEADER, id_r],
+        );
+        let Some(first_payload) = writer.finish_chain().filter(|_| signed) else {
+            return self.reject_auth(&header, NOTIFY_AUTHENTICATION_FAILED, identifier, env);
+        };
+
+        let mut iv = [0u8; BLOCK_LEN];
+        env.fill_random(&mut iv);
+        let inner = (first_payload, inner.as_slice());
+        if !self
+            .outgoing
+            .start()
+            .encrypted(&header.response(), keys, Role::Responder, &iv, inner)
+        {
+            return self.fail(env);
+        }
+
+        self.session_keys = Some(keys.session_keys(self.nonce_i.as_ref(), &self.nonce_r));
+        self.state = State::Done;
+        PeerMethodLayerResult::Send(self.outgoing.next_fragment(
+            env,
+            MessageCode::Response,
+            identifier,
+            Some((keys, Role::Responder)),
+        ))
+    }
+}
+
+impl PeerMethodLayer for PeerIkev2Method {
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_IKEV2)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::eap_ikev2(self.credentials.secret.is_some())
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let identifier = meta.message.identifier;
+        // The IKE_AUTH exchange starts once the IKE_SA_INIT response is sent
+        if self.state == State::WaitAuthRequest && !self.outgoing.pending() {
+            self.icv = true;
+        }
+        let keys = self.keys.as_ref().filter(|_| self.icv);
+
+        let received =
+            self.incoming
+                .receive(msg, &meta.message, keys.map(|keys| (keys, Role::Initiator)));
+        match (received, self.outgoing.pending()) {
+            (Some(Received::Ack), true) => {
+                PeerMethodLayerResult::Send(self.outgoing.next_fragment(
+                    env,
+                    MessageCode::Response,
+                    identifier,
+                    keys.map(|keys| (keys, Role::Responder)),
+                ))
+            }
+            (Some(Received::Fragment), false) => PeerMethodLayerResult::Send(ack(
+                env,
+                MessageCode::Response,
+                identifier,
+                keys.map(|keys| (keys, Role::Responder)),
+            )),
+            (Some(Received::Message), false) => {
+                let incoming = core::mem::take(&mut self.incoming);
+                let message = incoming.message.as_slice();
+                match self.state {
+                    State::WaitInitRequest => self.recv_init_request(message, identifier, env),
+                    State::WaitAuthRequest => self.recv_auth_request(message, identifier, env),
+                    State::Done => self.fail(env),
+                }
+            }
+            _ => self.fail(env),
+        }
+    }
+
+    fn method_state(&self) -> MethodState {
+        if self.session_keys.is_some() {
+            MethodState::Done
+        } else {
+            MethodState::MayCont
+        }
+    }
+
+    fn decision(&self) -> Decision {
+        if self.session_keys.is_some() {
+            Decision::CondSucc
+        } else {
+            Decision::Fail
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.session_keys.as_ref()
+    }
+
+    fn reset(&mut self) {
+        self.state = State::WaitInitRequest;
+        self.keys = None;
+        self.icv = false;
+        self.outgoing.clear();
+        self.incoming = Incoming::default();
+        self.session_keys = None;
+    }
+}
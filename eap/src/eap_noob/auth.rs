@@ -0,0 +1,600 @@
+use crate::{
+    layers::{
+        auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta},
+        eap_layer::SessionKeys,
+        mux::TupleElement,
+        MethodType,
+    },
+    util::OwnedSlice,
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::json::{Object, ObjectWriter};
+use super::*;
+
+#[derive(Clone)]
+enum State {
+    Start,
+    WaitPeerIdDiscovery,
+    WaitNegotiation {
+        association: Association,
+    },
+    WaitEcdhe {
+        association: Association,
+        secret: [u8; 32],
+    },
+    WaitWaiting,
+    WaitCompletion {
+        association: Association,
+        keys: Keys,
+    },
+    WaitReconnectNegotiation {
+        association: Association,
+    },
+    WaitReconnectNonces {
+        association: Association,
+        n_s2: [u8; NONCE_LEN],
+    },
+    WaitReconnectCompletion {
+        association: Association,
+        keys: Keys,
+        n_s2: [u8; NONCE_LEN],
+        n_p2: [u8; NONCE_LEN],
+    },
+    Failed,
+    Done {
+        keys: Keys,
+    },
+}
+
+/// Server side of EAP-NOOB.
+///
+/// Associations are kept in `store`, OOB messages are exchanged through `oob`.
+#[derive(Clone)]
+pub struct AuthNoobMethod<S, O> {
+    store: S,
+    oob: O,
+    server_info: OwnedSlice<MAX_INFO_LEN>,
+    dirs: u8,
+    sleep_time: u32,
+    state: State,
+}
+
+impl<S, O> TupleElement for AuthNoobMethod<S, O>
+where
+    S: ServerStore + 'static,
+    O: OobChannel + 'static,
+{
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<S, O> AuthNoobMethod<S, O>
+where
+    S: ServerStore,
+    O: OobChannel,
+{
+    pub fn new(store: S, oob: O) -> Self {
+        Self {
+            store,
+            oob,
+            server_info: OwnedSlice::from(b"{}"),
+            dirs: OobDirection::Both as u8,
+            sleep_time: 60,
+            state: State::Start,
+        }
+    }
+
+    /// ServerInfo object, must be valid JSON
+    pub fn with_server_info(mut self, server_info: &[u8]) -> Self {
+        assert!(
+            Object::parse(server_info).is_some(),
+            "ServerInfo is not a JSON object"
+        );
+        self.server_info = server_info
+            .try_into()
+            .expect("ServerInfo too long for nostd");
+        self
+    }
+
+    /// OOB directions supported by the server
+    pub fn with_directions(mut self, directions: OobDirection) -> Self {
+        self.dirs = directions as u8;
+        self
+    }
+
+    /// Seconds the peer should wait before it retries while waiting for the OOB message
+    pub fn with_sleep_time(mut self, seconds: u32) -> Self {
+        self.sleep_time = seconds;
+        self
+    }
+
+    fn fail<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.state = State::Failed;
+        AuthMethodLayerResult::Failed(env)
+    }
+
+    fn request<'a>(
+        env: &'a mut dyn EapEnvironment,
+        message_type: u32,
+        peer_id: &[u8],
+    ) -> ObjectWriter<'a> {
+        ObjectWriter::new(env.respond())
+            .number("Type", message_type)
+            .string("PeerId", peer_id)
+    }
+
+    fn recv_peer_id_discovery<'a>(
+        &mut self,
+        msg: &Object,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let peer_state = match msg
+            .get("PeerState")
+            .and_then(|v| v.as_u32())
+            .and_then(NoobState::from_u32)
+        {
+            Some(peer_state) => peer_state,
+            None => return self.fail(env),
+        };
+
+        if peer_state == NoobState::Unregistered {
+            return self.start_initial_exchange(env);
+        }
+
+        let mut association = match msg
+            .get("PeerId")
+            .and_then(|v| v.as_str())
+            .and_then(|peer_id| self.store.load(peer_id))
+        {
+            Some(association) => association,
+            None => return self.fail(env),
+        };
+
+        match (association.state, peer_state) {
+            (
+                NoobState::WaitingForOob | NoobState::OobReceived,
+                NoobState::WaitingForOob | NoobState::OobReceived,
+            ) => {}
+            (NoobState::Registered, NoobState::Reconnecting | NoobState::Registered) => {
+                self.state = State::WaitReconnectNegotiation {
+                    association: association.clone(),
+                };
+                return AuthMethodLayerResult::Send(
+                    Self::request(env, TYPE_RECONNECT_NEGOTIATION, association.peer_id())
+                        .raw("Vers", b"[1]")
+                        .raw("Cryptosuites", b"[1]")
+                        .raw("ServerInfo", self.server_info.as_ref())
+                        .finish(),
+                );
+            }
+            _ => return self.fail(env),
+        }
+
+        if association.peer_outputs_oob() {
+            if let Some(oob) = self.oob.receive(association.peer_id()) {
+                if association.accept_oob_message(&oob) {
+                    association.state = NoobState::OobReceived;
+                    self.store.store(&association);
+                }
+            }
+        }
+
+        // Without NoobId discovery the server only completes with a Noob the peer knows
+        let noob = match association.noob {
+            Some(noob)
+                if association.peer_outputs_oob() || peer_state == NoobState::OobReceived =>
+            {
+                noob
+            }
+            _ => {
+                self.state = State::WaitWaiting;
+                return AuthMethodLayerResult::Send(
+                    Self::request(env, TYPE_WAITING, association.peer_id())
+                        .number("SleepTime", self.sleep_time)
+                        .finish(),
+                );
+            }
+        };
+
+        let keys = Keys::completion(&association, &noob);
+        let macs = mac(
+            &keys.kms,
+            &association,
+            DIR_MAC_SERVER,
+            KEYING_MODE_COMPLETION,
+            None,
+            Some(&noob),
+        );
+
+        let msg = Self::request(env, TYPE_COMPLETION, association.peer_id())
+            .base64("NoobId", &noob_id(&noob))
+            .base64("MACs", &macs)
+            .finish();
+
+        self.state = State::WaitCompletion { association, keys };
+        AuthMethodLayerResult::Send(msg)
+    }
+
+    fn start_initial_exchange<'a>(
+        &mut self,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let mut raw_peer_id = [0u8; 16];
+        env.fill_random(&mut raw_peer_id);
+        let mut peer_id = [0u8; PEER_ID_LEN];
+        base64url_encode(&raw_peer_id, &mut peer_id);
+
+        let mut association = Association::new(&peer_id);
+        association.nai = match env.name().unwrap_or(DEFAULT_NAI).try_into() {
+            Ok(nai) => nai,
+            Err(_) => return self.fail(env),
+        };
+        association.vers = OwnedSlice::from(b"[1]");
+        association.cryptosuites = OwnedSlice::from(b"[1]");
+        association.dirs = self.dirs;
+        association.server_info = self.server_info.clone();
+
+        self.state = State::WaitNegotiation { association };
+
+        AuthMethodLayerResult::Send(
+            ObjectWriter::new(env.respond())
+                .number("Type", TYPE_NEGOTIATION)
+                .raw("Vers", b"[1]")
+                .string("PeerId", &peer_id)
+                .raw("Cryptosuites", b"[1]")
+                .number("Dirs", self.dirs as u32)
+                .raw("ServerInfo", self.server_info.as_ref())
+                .finish(),
+        )
+    }
+
+    fn recv_negotiation<'a>(
+        &mut self,
+        msg: &Object,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let mut association = match &self.state {
+            State::WaitNegotiation { association } => association.clone(),
+            _ => return self.fail(env),
+        };
+
+        let (verp, cryptosuitep, dirp, peer_info) = match (
+            msg.get("Verp").and_then(|v| v.as_u32()),
+            msg.get("Cryptosuitep").and_then(|v| v.as_u32()),
+            msg.get("Dirp").and_then(|v| v.as_u32()),
+            msg.get("PeerInfo").and_then(|v| v.as_object()),
+        ) {
+            (Some(VERSION), Some(CRYPTOSUITE), Some(dirp @ 1..=3), Some(peer_info)) => {
+                (VERSION, CRYPTOSUITE, dirp, peer_info)
+            }
+            _ => return self.fail(env),
+        };
+
+        association.verp = verp as u8;
+        association.cryptosuitep = cryptosuitep as u8;
+        association.dirp = dirp as u8;
+        association.peer_info = match peer_info.raw().try_into() {
+            Ok(peer_info) => peer_info,
+            Err(_) => return self.fail(env),
+        };
+
+        if association.direction() == 0 {
+            return self.fail(env);
+        }
+
+        let mut secret = [0u8; 32];
+        env.fill_random(&mut secret);
+        let mut jwk = [0u8; MAX_JWK_LEN];
+        association.pk_s = OwnedSlice::from(write_jwk(&ecdhe_public_key(&secret), &mut jwk));
+        env.fill_random(&mut association.n_s);
+
+        let msg = Self::request(env, TYPE_ECDHE, association.peer_id())
+            .raw("PKs", association.pk_s.as_ref())
+            .base64("Ns", &association.n_s)
+            .number("SleepTime", self.sleep_time)
+            .finish();
+
+        self.state = State::WaitEcdhe {
+            association,
+            secret,
+        };
+        AuthMethodLayerResult::Send(msg)
+    }
+
+    fn recv_ecdhe<'a>(
+        &mut self,
+        msg: &Object,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let (mut association, secret) = match &self.state {
+            State::WaitEcdhe {
+                association,
+                secret,
+            } => (association.clone(), *secret),
+            _ => return self.fail(env),
+        };
+
+        let (pk_p, public_key, n_p) = match (
+            msg.get("PKp").and_then(|v| v.as_object()),
+            msg.get("Np")
+                .and_then(|v| v.as_str())
+                .and_then(base64url_decode_array),
+        ) {
+            (Some(pk_p), Some(n_p)) => match read_jwk(&pk_p) {
+                Some(public_key) => (pk_p, public_key, n_p),
+                None => return self.fail(env),
+            },
+            _ => return self.fail(env),
+        };
+
+        association.z = match ecdhe_shared_secret(&secret, &public_key) {
+            Some(z) => z,
+            None => return self.fail(env),
+        };
+        association.pk_p = match pk_p.raw().try_into() {
+            Ok(pk_p) => pk_p,
+            Err(_) => return self.fail(env),
+        };
+        association.n_p = n_p;
+        association.state = NoobState::WaitingForOob;
+
+        if !association.peer_outputs_oob() {
+            let mut noob = [0u8; NOOB_LEN];
+            env.fill_random(&mut noob);
+            let oob = association.new_oob_message(noob);
+            self.oob.send(&oob);
+        }
+
+        self.store.store(&association);
+
+        // The Initial Exchange always ends with an EAP-Failure
+        self.fail(env)
+    }
+
+    fn recv_completion<'a>(
+        &mut self,
+        msg: &Object,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let (mut association, keys) = match &self.state {
+            State::WaitCompletion { association, keys } => (association.clone(), keys.clone()),
+            _ => return self.fail(env),
+        };
+
+        let macp = mac(
+            &keys.kmp,
+            &association,
+            DIR_MAC_PEER,
+            KEYING_MODE_COMPLETION,
+            None,
+            association.noob.as_ref(),
+        );
+        if !verify_mac(msg.get("MACp"), &macp) {
+            return self.fail(env);
+        }
+
+        association.register(keys.kz);
+        self.store.store(&association);
+        env.set_name(association.peer_id());
+
+        self.state = State::Done { keys };
+        AuthMethodLayerResult::Finished(env)
+    }
+
+    fn recv_reconnect_negotiation<'a>(
+        &mut self,
+        msg: &Object,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let mut association = match &self.state {
+            State::WaitReconnectNegotiation { association } => association.clone(),
+            _ => return self.fail(env),
+        };
+
+        let peer_info = match (
+            msg.get("Verp").and_then(|v| v.as_u32()),
+            msg.get("Cryptosuitep").and_then(|v| v.as_u32()),
+            msg.get("PeerInfo").and_then(|v| v.as_object()),
+        ) {
+            (Some(VERSION), Some(CRYPTOSUITE), Some(peer_info)) => peer_info,
+            _ => return self.fail(env),
+        };
+
+        association.vers = OwnedSlice::from(b"[1]");
+        association.cryptosuites = OwnedSlice::from(b"[1]");
+        association.server_info = self.server_info.clone();
+        association.verp = VERSION as u8;
+        association.cryptosuitep = CRYPTOSUITE as u8;
+        association.peer_info = match peer_info.raw().try_into() {
+            Ok(peer_info) => peer_info,
+            Err(_) => return self.fail(env),
+        };
+
+        let mut n_s2 = [0u8; NONCE_LEN];
+        env.fill_random(&mut n_s2);
+
+        let msg = Self::request(env, TYPE_RECONNECT_NONCES, association.peer_id())
+            .number("KeyingMode", KEYING_MODE_RECONNECT)
+            .base64("Ns2", &n_s2)
+            .finish();
+
+        self.state = State::WaitReconnectNonces { association, n_s2 };
+        AuthMethodLayerResult::Send(msg)
+    }
+
+    fn recv_reconnect_nonces<'a>(
+        &mut self,
+        msg: &Object,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let (association, n_s2) = match &self.state {
+            State::WaitReconnectNonces { association, n_s2 } => (association.clone(), *n_s2),
+            _ => return self.fail(env),
+        };
+
+        let n_p2: [u8; NONCE_LEN] = match msg
+            .get("Np2")
+            .and_then(|v| v.as_str())
+            .and_then(base64url_decode_array)
+        {
+            Some(n_p2) => n_p2,
+            None => return self.fail(env),
+        };
+
+        let keys = Keys::reconnect(&association.kz, &n_p2, &n_s2);
+        let macs2 = mac(
+            &keys.kms,
+            &association,
+            DIR_MAC_SERVER,
+            KEYING_MODE_RECONNECT,
+            Some((&n_s2, &n_p2)),
+            None,
+        );
+
+        let msg = Self::request(env, TYPE_RECONNECT_COMPLETION, association.peer_id())
+            .base64("MACs2", &macs2)
+            .finish();
+
+        self.state = State::WaitReconnectCompletion {
+            association,
+            keys,
+            n_s2,
+            n_p2,
+        };
+        AuthMethodLayerResult::Send(msg)
+    }
+
+    fn recv_reconnect_completion<'a>(
+        &mut self,
+        msg: &Object,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let (association, keys, n_s2, n_p2) = match &self.state {
+            State::WaitReconnectCompletion {
+                association,
+                keys,
+                n_s2,
+                n_p2,
+            } => (association.clone(), keys.clone(), *n_s2, *n_p2),
+            _ => return self.fail(env),
+        };
+
+        let macp2 = mac(
+            &keys.kmp,
+            &association,
+            DIR_MAC_PEER,
+            KEYING_MODE_RECONNECT,
+            Some((&n_s2, &n_p2)),
+            None,
+        );
+        if !verify_mac(msg.get("MACp2"), &macp2) {
+            return self.fail(env);
+        }
+
+        // Keep the negotiated values for the next Reconnect Exchange
+        self.store.store(&association);
+        env.set_name(association.peer_id());
+
+        self.state = State::Done { keys };
+        AuthMethodLayerResult::Finished(env)
+    }
+
+    /// Checks that a response belongs to the current association
+    fn peer_id_matches(&self, msg: &Object) -> bool {
+        let association = match &self.state {
+            State::WaitNegotiation { association }
+            | State::WaitEcdhe { association, .. }
+            | State::WaitCompletion { association, .. }
+            | State::WaitReconnectNegotiation { association }
+            | State::WaitReconnectNonces { association, .. }
+            | State::WaitReconnectCompletion { association, .. } => association,
+            _ => return true,
+        };
+
+        msg.get("PeerId").and_then(|v| v.as_str()) == Some(association.peer_id())
+    }
+}
+
+impl<S, O> AuthMethodLayer for AuthNoobMethod<S, O>
+where
+    S: ServerStore,
+    O: OobChannel,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_NOOB)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_NOOB
+    }
+
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.state = State::WaitPeerIdDiscovery;
+        AuthMethodLayerResult::Send(
+            ObjectWriter::new(env.respond())
+                .number("Type", TYPE_PEER_ID_DISCOVERY)
+                .finish(),
+        )
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let msg = match Object::parse(msg) {
+            Some(msg) => msg,
+            None => return self.fail(env),
+        };
+
+        if !self.peer_id_matches(&msg) {
+            return self.fail(env);
+        }
+
+        let message_type = match msg.get("Type").and_then(|v| v.as_u32()) {
+            Some(message_type) => message_type,
+            None => return self.fail(env),
+        };
+
+        match (&self.state, message_type) {
+            (State::WaitPeerIdDiscovery, TYPE_PEER_ID_DISCOVERY) => {
+                self.recv_peer_id_discovery(&msg, env)
+            }
+            (State::WaitNegotiation { .. }, TYPE_NEGOTIATION) => self.recv_negotiation(&msg, env),
+            (State::WaitEcdhe { .. }, TYPE_ECDHE) => self.recv_ecdhe(&msg, env),
+            (State::WaitCompletion { .. }, TYPE_COMPLETION) => self.recv_completion(&msg, env),
+            (State::WaitReconnectNegotiation { .. }, TYPE_RECONNECT_NEGOTIATION) => {
+                self.recv_reconnect_negotiation(&msg, env)
+            }
+            (State::WaitReconnectNonces { .. }, TYPE_RECONNECT_NONCES) => {
+                self.recv_reconnect_nonces(&msg, env)
+            }
+            (State::WaitReconnectCompletion { .. }, TYPE_RECONNECT_COMPLETION) => {
+                self.recv_reconnect_completion(&msg, env)
+            }
+            // The peer acknowledged that it keeps waiting for the OOB message,
+            // or reported an error.
+            _ => self.fail(env),
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        match &self.state {
+            State::Done { keys } => Some(&keys.session),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Minimal JSON support for EAP-NOOB messages.
+//!
+//! Only what the protocol needs is supported: objects are scanned for top level
+//! members without allocating, strings with escape sequences are rejected.
+
+use crate::MessageBuilder;
+
+use super::base64url_encode;
+
+/// A JSON object, the members are looked up on demand.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Object<'a> {
+    data: &'a [u8],
+}
+
+/// Raw JSON text of a single value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Value<'a>(pub &'a [u8]);
+
+fn skip_whitespace(data: &[u8], mut i: usize) -> usize {
+    while i < data.len() && matches!(data[i], b' ' | b'\t' | b'\r' | b'\n') {
+        i += 1;
+    }
+    i
+}
+
+/// Returns the index after the string starting at `i`
+fn string_end(data: &[u8], i: usize) -> Option<usize> {
+    if data.get(i) != Some(&b'"') {
+        return None;
+    }
+
+    let mut j = i + 1;
+    while j < data.len() {
+        match data[j] {
+            b'"' => return Some(j + 1),
+            b'\\' => j += 2,
+            _ => j += 1,
+        }
+    }
+    None
+}
+
+/// Returns the index after the value starting at `i`
+fn value_end(data: &[u8], i: usize) -> Option<usize> {
+    match data.get(i)? {
+        b'"' => string_end(data, i),
+        b'{' | b'[' => {
+            let mut depth = 0usize;
+            let mut j = i;
+            while j < data.len() {
+                match data[j] {
+                    b'"' => {
+                        j = string_end(data, j)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(j + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                j += 1;
+            }
+            None
+        }
+        _ => {
+            let mut j = i;
+            while j < data.len()
+                && !matches!(data[j], b',' | b'}' | b']' | b' ' | b'\t' | b'\r' | b'\n')
+            {
+                j += 1;
+            }
+            (j > i).then_some(j)
+        }
+    }
+}
+
+impl<'a> Object<'a> {
+    /// Parses `data` as a single JSON object, surrounding whitespace is allowed.
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let start = skip_whitespace(data, 0);
+        let end = value_end(data, start)?;
+        if data.get(start) != Some(&b'{') || skip_whitespace(data, end) != data.len() {
+            return None;
+        }
+
+        let object = Self {
+            data: &data[start..end],
+        };
+
+        // Validate the structure once, so lookups can't fail later on
+        let mut i = 1;
+        while let Some(next) = object.member(i)? {
+            i = next.2;
+        }
+
+        Some(object)
+    }
+
+    /// Parses the member starting at `i`. Returns `Some(None)` at the end of the object.
+    #[allow(clippy::type_complexity)]
+    fn member(&self, i: usize) -> Option<Option<(&'a [u8], Value<'a>, usize)>> {
+        let data = self.data;
+        let mut i = skip_whitespace(data, i);
+        if data.get(i) == Some(&b',') {
+            i = skip_whitespace(data, i + 1);
+        }
+        if data.get(i) == Some(&b'}') {
+            return Some(None);
+        }
+
+        let key_end = string_end(data, i)?;
+        let key = &data[i + 1..key_end - 1];
+
+        let i = skip_whitespace(data, key_end);
+        if data.get(i) != Some(&b':') {
+            return None;
+        }
+        let value_start = skip_whitespace(data, i + 1);
+        let end = value_end(data, value_start)?;
+
+        let next = skip_whitespace(data, end);
+        if !matches!(data.get(next), Some(b',') | Some(b'}')) {
+            return None;
+        }
+
+        Some(Some((key, Value(&data[value_start..end]), next)))
+    }
+
+    pub fn get(&self, key: &str) -> Option<Value<'a>> {
+        let mut i = 1;
+        while let Some((k, value, next)) = self.member(i)? {
+            if k == key.as_bytes() {
+                return Some(value);
+            }
+            i = next;
+        }
+        None
+    }
+
+    pub fn raw(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl<'a> Value<'a> {
+    pub fn as_u32(&self) -> Option<u32> {
+        if self.0.is_empty() || self.0.len() > 9 || !self.0.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        Some(self.0.iter().fold(0, |acc, d| acc * 10 + (d - b'0') as u32))
+    }
+
+    /// Content of a string without escape sequences
+    pub fn as_str(&self) -> Option<&'a [u8]> {
+        match self.0 {
+            [b'"', content @ .., b'"'] if !content.contains(&b'\\') => Some(content),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<Object<'a>> {
+        Object::parse(self.0)
+    }
+
+    /// Checks if the value is an array of numbers containing `number`
+    pub fn array_contains(&self, number: u32) -> bool {
+        match self.0 {
+            [b'[', content @ .., b']'] => content
+                .split(|b| *b == b',')
+                .map(|item| {
+                    let start = skip_whitespace(item, 0);
+                    let end = item[start..]
+                        .iter()
+                        .position(|b| !b.is_ascii_digit())
+                        .map_or(item.len(), |len| start + len);
+                    Value(&item[start..end]).as_u32()
+                })
+                .any(|item| item == Some(number)),
+            _ => false,
+        }
+    }
+}
+
+/// Writes a decimal number without allocating
+pub(crate) fn format_u32(mut number: u32, buffer: &mut [u8; 10]) -> &[u8] {
+    let mut i = buffer.len();
+    loop {
+        i -= 1;
+        buffer[i] = b'0' + (number % 10) as u8;
+        number /= 10;
+        if number == 0 {
+            return &buffer[i..];
+        }
+    }
+}
+
+/// Writes a JSON object into a message
+pub(crate) struct ObjectWriter<'a> {
+    builder: MessageBuilder<'a>,
+    first: bool,
+}
+
+impl<'a> ObjectWriter<'a> {
+    pub fn new(builder: MessageBuilder<'a>) -> Self {
+        Self {
+            builder: builder.write(b"{"),
+            first: true,
+        }
+    }
+
+    fn key(mut self, key: &str) -> Self {
+        if !self.first {
+            self.builder = self.builder.write(b",");
+        }
+        self.first = false;
+        self.builder = self
+            .builder
+            .write(b"\"")
+            .write(key.as_bytes())
+            .write(b"\":");
+        self
+    }
+
+    pub fn number(self, key: &str, number: u32) -> Self {
+        let mut this = self.key(key);
+        let mut buffer = [0u8; 10];
+        this.builder = this.builder.write(format_u32(number, &mut buffer));
+        this
+    }
+
+    /// String value, the caller has to make sure it needs no escaping
+    pub fn string(self, key: &str, value: &[u8]) -> Self {
+        let mut this = self.key(key);
+        this.builder = this.builder.write(b"\"").write(value).write(b"\"");
+        this
+    }
+
+    /// Binary value as base64url string
+    pub fn base64(self, key: &str, value: &[u8]) -> Self {
+        let mut buffer = [0u8; 44];
+        self.string(key, base64url_encode(value, &mut buffer))
+    }
+
+    /// Any JSON value
+    pub fn raw(self, key: &str, value: &[u8]) -> Self {
+        let mut this = self.key(key);
+        this.builder = this.builder.write(value);
+        this
+    }
+
+    pub fn finish(self) -> MessageBuilder<'a> {
+        self.builder.write(b"}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_object() {
+        let object = Object::parse(
+            br#" {"Type":2, "Vers" : [1, 2],"PeerId":"abc","ServerInfo":{"Name":"a}\"b","Url":[]}} "#,
+        )
+        .unwrap();
+
+        assert_eq!(object.get("Type").and_then(|v| v.as_u32()), Some(2));
+        assert!(object.get("Vers").unwrap().array_contains(1));
+        assert!(object.get("Vers").unwrap().array_contains(2));
+        assert!(!object.get("Vers").unwrap().array_contains(3));
+        assert_eq!(
+            object.get("PeerId").and_then(|v| v.as_str()),
+            Some(&b"abc"[..])
+        );
+        assert_eq!(
+            object.get("ServerInfo").unwrap().0,
+            br#"{"Name":"a}\"b","Url":[]}"#
+        );
+        assert!(object.get("ServerInfo").unwrap().as_object().is_some());
+        assert!(object.get("Missing").is_none());
+        assert!(object.get("Type").unwrap().as_str().is_none());
+    }
+
+    #[test]
+    fn reject_invalid() {
+        assert!(Object::parse(b"").is_none());
+        assert!(Object::parse(b"[1]").is_none());
+        assert!(Object::parse(br#"{"Type":1"#).is_none());
+        assert!(Object::parse(br#"{"Type" 1}"#).is_none());
+        assert!(Object::parse(br#"{"Type":1 "PeerId":"a"}"#).is_none());
+        assert!(Object::parse(br#"{"Type":1} x"#).is_none());
+        assert!(Object::parse(br#"{}"#).is_some());
+    }
+
+    #[test]
+    fn numbers() {
+        let mut buffer = [0u8; 10];
+        assert_eq!(format_u32(0, &mut buffer), b"0");
+        assert_eq!(format_u32(4001, &mut buffer), b"4001");
+        assert_eq!(format_u32(u32::MAX, &mut buffer), b"4294967295");
+        assert_eq!(Value(b"4001").as_u32(), Some(4001));
+        assert_eq!(Value(b"-1").as_u32(), None);
+        assert_eq!(Value(b"1.5").as_u32(), None);
+    }
+}
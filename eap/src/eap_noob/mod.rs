@@ -0,0 +1,803 @@
+//! EAP-NOOB, see https://www.rfc-editor.org/rfc/rfc9140
+//!
+//! Nimble out-of-band authentication for devices without preconfigured credentials.
+//! A new peer first runs the Initial Exchange, which always ends with an EAP-Failure.
+//! The user then transfers an OOB message (e.g. a QR code) between the peer and the
+//! server. The next Completion Exchange authenticates both sides and registers the peer.
+//! A registered peer uses the Reconnect Exchange afterwards.
+//!
+//! Only cryptosuite 1 (X25519, SHA-256) and Reconnect without rekeying
+//! (KeyingMode 1) are supported.
+
+mod auth;
+pub(crate) mod json;
+mod peer;
+
+pub use auth::AuthNoobMethod;
+pub use peer::PeerNoobMethod;
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    layers::{eap_layer::SessionKeys, SecurityClaims},
+    util::{constant_time_eq, ByteReader, OwnedSlice},
+};
+
+use json::{format_u32, Object};
+
+const METHOD_NOOB: u8 = 56;
+
+const TYPE_ERROR: u32 = 0;
+const TYPE_PEER_ID_DISCOVERY: u32 = 1;
+const TYPE_NEGOTIATION: u32 = 2;
+const TYPE_ECDHE: u32 = 3;
+const TYPE_WAITING: u32 = 4;
+const TYPE_NOOB_ID_DISCOVERY: u32 = 5;
+const TYPE_COMPLETION: u32 = 6;
+const TYPE_RECONNECT_NEGOTIATION: u32 = 7;
+const TYPE_RECONNECT_NONCES: u32 = 8;
+const TYPE_RECONNECT_COMPLETION: u32 = 9;
+
+const ERROR_INVALID_DATA: u32 = 1002;
+const ERROR_UNEXPECTED_TYPE: u32 = 1003;
+const ERROR_INVALID_KEY: u32 = 1007;
+const ERROR_STATE_MISMATCH: u32 = 2002;
+const ERROR_UNKNOWN_NOOB_ID: u32 = 2003;
+const ERROR_NO_VERSION: u32 = 3001;
+const ERROR_NO_CRYPTOSUITE: u32 = 3002;
+const ERROR_NO_DIRECTION: u32 = 3003;
+const ERROR_MAC: u32 = 4001;
+
+const VERSION: u32 = 1;
+const CRYPTOSUITE: u32 = 1;
+
+const KEYING_MODE_COMPLETION: u32 = 0;
+const KEYING_MODE_RECONNECT: u32 = 1;
+
+/// Value of the Dir field of the MAC input for MACs and MACp
+const DIR_MAC_SERVER: u32 = 2;
+const DIR_MAC_PEER: u32 = 1;
+
+const NONCE_LEN: usize = 32;
+const NOOB_LEN: usize = 16;
+const HOOB_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const PEER_ID_LEN: usize = 22;
+
+/// Server and peer info objects are limited so they can be handled without allocations
+pub const MAX_INFO_LEN: usize = 128;
+const MAX_JWK_LEN: usize = 128;
+
+/// The default peer NAI, used by unregistered peers
+pub const DEFAULT_NAI: &[u8] = b"noob@eap-noob.arpa";
+
+/// Directions the OOB message may take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OobDirection {
+    /// The peer outputs the OOB message, e.g. shows a QR code
+    PeerToServer = 1,
+    /// The peer reads the OOB message
+    ServerToPeer = 2,
+    Both = 3,
+}
+
+/// Association state of a peer, RFC 9140 3.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoobState {
+    Unregistered = 0,
+    WaitingForOob = 1,
+    OobReceived = 2,
+    Reconnecting = 3,
+    Registered = 4,
+}
+
+impl NoobState {
+    fn from_u32(state: u32) -> Option<Self> {
+        match state {
+            0 => Some(NoobState::Unregistered),
+            1 => Some(NoobState::WaitingForOob),
+            2 => Some(NoobState::OobReceived),
+            3 => Some(NoobState::Reconnecting),
+            4 => Some(NoobState::Registered),
+            _ => None,
+        }
+    }
+}
+
+/// The OOB message, transferred by the user between the peer and the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OobMessage {
+    pub peer_id: OwnedSlice<32>,
+    pub noob: [u8; NOOB_LEN],
+    pub hoob: [u8; HOOB_LEN],
+}
+
+impl OobMessage {
+    /// Encodes the message as URL query (`P=<PeerId>&N=<Noob>&H=<Hoob>`),
+    /// suitable for a QR code.
+    pub fn to_query<'b>(&self, out: &'b mut [u8; 96]) -> &'b [u8] {
+        let mut noob = [0u8; 24];
+        let mut hoob = [0u8; 24];
+        let parts: [&[u8]; 6] = [
+            b"P=",
+            self.peer_id.as_ref(),
+            b"&N=",
+            base64url_encode(&self.noob, &mut noob),
+            b"&H=",
+            base64url_encode(&self.hoob, &mut hoob),
+        ];
+
+        let mut len = 0;
+        for part in parts {
+            out[len..len + part.len()].copy_from_slice(part);
+            len += part.len();
+        }
+        &out[..len]
+    }
+
+    pub fn from_query(query: &[u8]) -> Option<Self> {
+        let (mut peer_id, mut noob, mut hoob) = (None, None, None);
+
+        for pair in query.split(|b| *b == b'&') {
+            match pair {
+                [b'P', b'=', value @ ..] => peer_id = OwnedSlice::try_from(value).ok(),
+                [b'N', b'=', value @ ..] => noob = base64url_decode_array(value),
+                [b'H', b'=', value @ ..] => hoob = base64url_decode_array(value),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            peer_id: peer_id?,
+            noob: noob?,
+            hoob: hoob?,
+        })
+    }
+}
+
+/// Persistent state shared by the peer and the server.
+///
+/// Use [`Association::encode`] and [`Association::decode`] to store it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Association {
+    peer_id: OwnedSlice<32>,
+    state: NoobState,
+    nai: OwnedSlice<64>,
+    vers: OwnedSlice<16>,
+    cryptosuites: OwnedSlice<16>,
+    dirs: u8,
+    server_info: OwnedSlice<MAX_INFO_LEN>,
+    verp: u8,
+    cryptosuitep: u8,
+    dirp: u8,
+    peer_info: OwnedSlice<MAX_INFO_LEN>,
+    pk_s: OwnedSlice<MAX_JWK_LEN>,
+    pk_p: OwnedSlice<MAX_JWK_LEN>,
+    n_s: [u8; NONCE_LEN],
+    n_p: [u8; NONCE_LEN],
+    /// ECDHE shared secret, only kept until the Completion Exchange
+    z: [u8; KEY_LEN],
+    noob: Option<[u8; NOOB_LEN]>,
+    kz: [u8; KEY_LEN],
+}
+
+/// Upper bound for the length of an encoded [`Association`]
+pub const MAX_ENCODED_ASSOCIATION_LEN: usize = 1024;
+
+impl Association {
+    fn new(peer_id: &[u8]) -> Self {
+        Self {
+            peer_id: OwnedSlice::from(peer_id),
+            state: NoobState::Unregistered,
+            nai: OwnedSlice::new(),
+            vers: OwnedSlice::new(),
+            cryptosuites: OwnedSlice::new(),
+            dirs: 0,
+            server_info: OwnedSlice::new(),
+            verp: 0,
+            cryptosuitep: 0,
+            dirp: 0,
+            peer_info: OwnedSlice::new(),
+            pk_s: OwnedSlice::new(),
+            pk_p: OwnedSlice::new(),
+            n_s: [0; NONCE_LEN],
+            n_p: [0; NONCE_LEN],
+            z: [0; KEY_LEN],
+            noob: None,
+            kz: [0; KEY_LEN],
+        }
+    }
+
+    pub fn peer_id(&self) -> &[u8] {
+        self.peer_id.as_ref()
+    }
+
+    pub fn state(&self) -> NoobState {
+        self.state
+    }
+
+    /// Negotiated OOB direction
+    fn direction(&self) -> u8 {
+        self.dirs & self.dirp
+    }
+
+    /// If set, the peer generates the OOB message, otherwise the server
+    fn peer_outputs_oob(&self) -> bool {
+        self.direction() & OobDirection::PeerToServer as u8 != 0
+    }
+
+    /// Value of the Dir field in the Hoob input
+    fn oob_dir(&self) -> u32 {
+        if self.peer_outputs_oob() {
+            OobDirection::PeerToServer as u32
+        } else {
+            OobDirection::ServerToPeer as u32
+        }
+    }
+
+    fn hoob(&self, noob: &[u8; NOOB_LEN]) -> [u8; HOOB_LEN] {
+        let mut hash = Sha256::new();
+        write_mac_input(
+            self,
+            self.oob_dir(),
+            KEYING_MODE_COMPLETION,
+            None,
+            Some(noob),
+            &mut |data| hash.update(data),
+        );
+        hash.finalize()[..HOOB_LEN].try_into().unwrap()
+    }
+
+    /// Creates a new OOB message and remembers its Noob
+    fn new_oob_message(&mut self, noob: [u8; NOOB_LEN]) -> OobMessage {
+        self.noob = Some(noob);
+        OobMessage {
+            peer_id: self.peer_id.clone(),
+            noob,
+            hoob: self.hoob(&noob),
+        }
+    }
+
+    /// Accepts an OOB message generated by the other side if it belongs to this association
+    fn accept_oob_message(&mut self, oob: &OobMessage) -> bool {
+        if oob.peer_id != self.peer_id || self.hoob(&oob.noob) != oob.hoob {
+            return false;
+        }
+
+        self.noob = Some(oob.noob);
+        true
+    }
+
+    /// Moves to Registered, the ECDHE secret and the Noob are no longer needed
+    fn register(&mut self, kz: [u8; KEY_LEN]) {
+        self.state = NoobState::Registered;
+        self.kz = kz;
+        self.z = [0; KEY_LEN];
+        self.noob = None;
+    }
+
+    pub fn encode<'b>(&self, out: &'b mut [u8; MAX_ENCODED_ASSOCIATION_LEN]) -> &'b [u8] {
+        let noob = self.noob.unwrap_or_default();
+        let parts: [&[u8]; 17] = [
+            self.peer_id.as_ref(),
+            &[self.state as u8],
+            self.nai.as_ref(),
+            self.vers.as_ref(),
+            self.cryptosuites.as_ref(),
+            &[self.dirs],
+            self.server_info.as_ref(),
+            &[self.verp, self.cryptosuitep, self.dirp],
+            self.peer_info.as_ref(),
+            self.pk_s.as_ref(),
+            self.pk_p.as_ref(),
+            &self.n_s,
+            &self.n_p,
+            &self.z,
+            &[self.noob.is_some() as u8],
+            &noob,
+            &self.kz,
+        ];
+
+        let mut len = 0;
+        for part in parts {
+            out[len..len + 2].copy_from_slice(&(part.len() as u16).to_be_bytes());
+            out[len + 2..len + 2 + part.len()].copy_from_slice(part);
+            len += 2 + part.len();
+        }
+        &out[..len]
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let mut reader = ByteReader::new(data);
+        let mut next = || reader.u16_prefixed();
+
+        let peer_id = next()?.try_into().ok()?;
+        let state = NoobState::from_u32(*next()?.first()? as u32)?;
+        let nai = next()?.try_into().ok()?;
+        let vers = next()?.try_into().ok()?;
+        let cryptosuites = next()?.try_into().ok()?;
+        let dirs = *next()?.first()?;
+        let server_info = next()?.try_into().ok()?;
+        let [verp, cryptosuitep, dirp]: [u8; 3] = next()?.try_into().ok()?;
+        let peer_info = next()?.try_into().ok()?;
+        let pk_s = next()?.try_into().ok()?;
+        let pk_p = next()?.try_into().ok()?;
+        let n_s = next()?.try_into().ok()?;
+        let n_p = next()?.try_into().ok()?;
+        let z = next()?.try_into().ok()?;
+        let has_noob = *next()?.first()? != 0;
+        let noob: [u8; NOOB_LEN] = next()?.try_into().ok()?;
+        let kz = next()?.try_into().ok()?;
+
+        Some(Self {
+            peer_id,
+            state,
+            nai,
+            vers,
+            cryptosuites,
+            dirs,
+            server_info,
+            verp,
+            cryptosuitep,
+            dirp,
+            peer_info,
+            pk_s,
+            pk_p,
+            n_s,
+            n_p,
+            z,
+            noob: has_noob.then_some(noob),
+            kz,
+        })
+    }
+}
+
+/// Persistent storage of the peer, it holds at most one association.
+pub trait PeerStore {
+    fn load(&mut self) -> Option<Association>;
+    fn store(&mut self, association: &Association);
+}
+
+/// Persistent storage of the server, associations are looked up by PeerId.
+pub trait ServerStore {
+    fn load(&mut self, peer_id: &[u8]) -> Option<Association>;
+    fn store(&mut self, association: &Association);
+}
+
+/// Volatile storage, the association is lost on restart
+impl PeerStore for Option<Association> {
+    fn load(&mut self) -> Option<Association> {
+        self.clone()
+    }
+
+    fn store(&mut self, association: &Association) {
+        *self = Some(association.clone());
+    }
+}
+
+#[cfg(feature = "std")]
+impl ServerStore for std::collections::HashMap<Vec<u8>, Association> {
+    fn load(&mut self, peer_id: &[u8]) -> Option<Association> {
+        self.get(peer_id).cloned()
+    }
+
+    fn store(&mut self, association: &Association) {
+        self.insert(association.peer_id().to_vec(), association.clone());
+    }
+}
+
+/// The out-of-band channel, usually a display or a QR code and the user.
+pub trait OobChannel {
+    /// Called with the OOB message generated by this side
+    fn send(&mut self, oob: &OobMessage);
+
+    /// Polled for an OOB message generated by the other side
+    fn receive(&mut self, peer_id: &[u8]) -> Option<OobMessage>;
+}
+
+#[derive(Clone)]
+struct Keys {
+    session: SessionKeys,
+    kms: [u8; KEY_LEN],
+    kmp: [u8; KEY_LEN],
+    kz: [u8; KEY_LEN],
+}
+
+impl Keys {
+    /// Keys of the Completion Exchange, RFC 9140 3.5.
+    fn completion(association: &Association, noob: &[u8; NOOB_LEN]) -> Self {
+        let mut out = [0u8; 64 * 3 + KEY_LEN * 4];
+        kdf(
+            &association.z,
+            &association.n_p,
+            &association.n_s,
+            noob,
+            &mut out,
+        );
+        Self::from_kdf_output(&out, out[320 - KEY_LEN..].try_into().unwrap())
+    }
+
+    /// Keys of the Reconnect Exchange without rekeying
+    fn reconnect(kz: &[u8; KEY_LEN], n_p2: &[u8], n_s2: &[u8]) -> Self {
+        let mut out = [0u8; 64 * 3 + KEY_LEN * 3];
+        kdf(kz, n_p2, n_s2, &[], &mut out);
+        Self::from_kdf_output(&out, *kz)
+    }
+
+    /// MSK || EMSK || AMSK || MethodId || Kms || Kmp, the Session-ID is the
+    /// Type and the MethodId
+    fn from_kdf_output(out: &[u8], kz: [u8; KEY_LEN]) -> Self {
+        Self {
+            session: SessionKeys {
+                msk: out[..64].try_into().unwrap(),
+                emsk: out[64..128].try_into().unwrap(),
+                session_id: SessionKeys::session_id(METHOD_NOOB, &[&out[192..224]]),
+            },
+            kms: out[224..256].try_into().unwrap(),
+            kmp: out[256..288].try_into().unwrap(),
+            kz,
+        }
+    }
+}
+
+/// One-step KDF of NIST SP 800-56Ar3 with SHA-256, AlgorithmId "EAP-NOOB"
+fn kdf(z: &[u8], party_u: &[u8], party_v: &[u8], supp_priv: &[u8], out: &mut [u8]) {
+    for (i, chunk) in out.chunks_mut(32).enumerate() {
+        let hash = Sha256::new()
+            .chain_update((i as u32 + 1).to_be_bytes())
+            .chain_update(z)
+            .chain_update(b"EAP-NOOB")
+            .chain_update(party_u)
+            .chain_update(party_v)
+            .chain_update(supp_priv)
+            .finalize();
+        chunk.copy_from_slice(&hash[..chunk.len()]);
+    }
+}
+
+fn noob_id(noob: &[u8; NOOB_LEN]) -> [u8; 16] {
+    let hash = Sha256::new()
+        .chain_update(b"NoobId")
+        .chain_update(noob)
+        .finalize();
+    hash[..16].try_into().unwrap()
+}
+
+/// Writes the JSON array used as input of Hoob and the MACs:
+/// `[Dir, Vers, Verp, PeerId, Cryptosuites, Dirs, ServerInfo, Cryptosuitep, Dirp,
+/// NAI, PeerInfo, KeyingMode, PKs, Ns, PKp, Np, Noob]`
+///
+/// The Reconnect Exchange passes its nonces, and has no public keys and no Noob.
+fn write_mac_input(
+    association: &Association,
+    dir: u32,
+    keying_mode: u32,
+    reconnect_nonces: Option<(&[u8], &[u8])>,
+    noob: Option<&[u8; NOOB_LEN]>,
+    sink: &mut dyn FnMut(&[u8]),
+) {
+    let mut numbers = [[0u8; 10]; 6];
+    let [dir_buf, verp_buf, dirs_buf, cryptosuitep_buf, dirp_buf, keying_mode_buf] = &mut numbers;
+
+    let (n_s, n_p) = reconnect_nonces.unwrap_or((&association.n_s, &association.n_p));
+    let (pk_s, pk_p): (&[u8], &[u8]) = match reconnect_nonces {
+        Some(_) => (b"\"\"", b"\"\""),
+        None => (association.pk_s.as_ref(), association.pk_p.as_ref()),
+    };
+
+    let mut n_s_buf = [0u8; 44];
+    let mut n_p_buf = [0u8; 44];
+    let mut noob_buf = [0u8; 24];
+    let noob = match noob {
+        Some(noob) => base64url_encode(noob, &mut noob_buf),
+        None => &[],
+    };
+
+    let parts: [&[u8]; 35] = [
+        b"[",
+        format_u32(dir, dir_buf),
+        b",",
+        association.vers.as_ref(),
+        b",",
+        format_u32(association.verp as u32, verp_buf),
+        b",\"",
+        association.peer_id.as_ref(),
+        b"\",",
+        association.cryptosuites.as_ref(),
+        b",",
+        format_u32(association.dirs as u32, dirs_buf),
+        b",",
+        association.server_info.as_ref(),
+        b",",
+        format_u32(association.cryptosuitep as u32, cryptosuitep_buf),
+        b",",
+        format_u32(association.dirp as u32, dirp_buf),
+        b",\"",
+        association.nai.as_ref(),
+        b"\",",
+        association.peer_info.as_ref(),
+        b",",
+        format_u32(keying_mode, keying_mode_buf),
+        b",",
+        pk_s,
+        b",\"",
+        base64url_encode(n_s, &mut n_s_buf),
+        b"\",",
+        pk_p,
+        b",\"",
+        base64url_encode(n_p, &mut n_p_buf),
+        b"\",\"",
+        noob,
+        b"\"]",
+    ];
+
+    for part in parts {
+        sink(part);
+    }
+}
+
+fn mac(
+    key: &[u8; KEY_LEN],
+    association: &Association,
+    dir: u32,
+    keying_mode: u32,
+    reconnect_nonces: Option<(&[u8], &[u8])>,
+    noob: Option<&[u8; NOOB_LEN]>,
+) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+    write_mac_input(
+        association,
+        dir,
+        keying_mode,
+        reconnect_nonces,
+        noob,
+        &mut |data| mac.update(data),
+    );
+    mac.finalize().into_bytes().into()
+}
+
+/// Compares a base64url encoded MAC from a message
+fn verify_mac(received: Option<json::Value>, expected: &[u8; 32]) -> bool {
+    received
+        .and_then(|v| v.as_str())
+        .and_then(base64url_decode_array::<32>)
+        .is_some_and(|received| constant_time_eq(&received, expected))
+}
+
+/// Writes an X25519 public key as JWK
+fn write_jwk<'b>(public_key: &[u8; 32], out: &'b mut [u8; MAX_JWK_LEN]) -> &'b [u8] {
+    let mut x = [0u8; 44];
+    let parts: [&[u8]; 3] = [
+        br#"{"kty":"OKP","crv":"X25519","x":""#,
+        base64url_encode(public_key, &mut x),
+        br#""}"#,
+    ];
+
+    let mut len = 0;
+    for part in parts {
+        out[len..len + part.len()].copy_from_slice(part);
+        len += part.len();
+    }
+    &out[..len]
+}
+
+fn read_jwk(jwk: &Object) -> Option<[u8; 32]> {
+    if jwk.get("kty")?.as_str()? != b"OKP" || jwk.get("crv")?.as_str()? != b"X25519" {
+        return None;
+    }
+    base64url_decode_array(jwk.get("x")?.as_str()?)
+}
+
+/// X25519 public key and shared secret
+fn ecdhe_public_key(secret: &[u8; 32]) -> [u8; 32] {
+    x25519_dalek::x25519(*secret, x25519_dalek::X25519_BASEPOINT_BYTES)
+}
+
+fn ecdhe_shared_secret(secret: &[u8; 32], public_key: &[u8; 32]) -> Option<[u8; 32]> {
+    let z = x25519_dalek::x25519(*secret, *public_key);
+    // Low order points result in an all-zero secret
+    (z != [0; 32]).then_some(z)
+}
+
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// base64url without padding
+fn base64url_encode<'b>(data: &[u8], out: &'b mut [u8]) -> &'b [u8] {
+    let mut len = 0;
+    for chunk in data.chunks(3) {
+        let mut block = [0u8; 3];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let n = u32::from_be_bytes([0, block[0], block[1], block[2]]);
+
+        for i in 0..chunk.len() + 1 {
+            out[len] = BASE64URL[(n >> (18 - 6 * i) & 0x3f) as usize];
+            len += 1;
+        }
+    }
+    &out[..len]
+}
+
+fn base64url_decode<'b>(data: &[u8], out: &'b mut [u8]) -> Option<&'b [u8]> {
+    if data.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut len = 0;
+    for chunk in data.chunks(4) {
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let value = BASE64URL.iter().position(|b| b == c)? as u32;
+            n |= value << (18 - 6 * i);
+        }
+
+        let bytes = n.to_be_bytes();
+        let count = chunk.len() - 1;
+        if len + count > out.len() {
+            return None;
+        }
+        out[len..len + count].copy_from_slice(&bytes[1..1 + count]);
+        len += count;
+    }
+    Some(&out[..len])
+}
+
+fn base64url_decode_array<const N: usize>(data: &[u8]) -> Option<[u8; N]> {
+    let mut out = [0u8; N];
+    let len = base64url_decode(data, &mut out)?.len();
+    (len == N).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn association() -> Association {
+        let mut association = Association::new(b"07KRU6OgqX0HIeRFldnbSW");
+        association.nai = OwnedSlice::from(DEFAULT_NAI);
+        association.vers = OwnedSlice::from(b"[1]");
+        association.cryptosuites = OwnedSlice::from(b"[1]");
+        association.dirs = 3;
+        association.server_info = OwnedSlice::from(br#"{"Name":"Example"}"#);
+        association.verp = 1;
+        association.cryptosuitep = 1;
+        association.dirp = 1;
+        association.peer_info = OwnedSlice::from(br#"{"Make":"Acme"}"#);
+        association.pk_s = OwnedSlice::from(br#"{"kty":"OKP"}"#);
+        association.pk_p = OwnedSlice::from(br#"{"kty":"OKP"}"#);
+        association
+    }
+
+    #[test]
+    fn base64url() {
+        let mut buffer = [0u8; 44];
+        assert_eq!(base64url_encode(b"", &mut buffer), b"");
+        assert_eq!(base64url_encode(b"f", &mut buffer), b"Zg");
+        assert_eq!(base64url_encode(b"fo", &mut buffer), b"Zm8");
+        assert_eq!(base64url_encode(b"foo", &mut buffer), b"Zm9v");
+        assert_eq!(base64url_encode(&[0xfb, 0xff], &mut buffer), b"-_8");
+
+        let mut out = [0u8; 32];
+        assert_eq!(base64url_decode(b"Zm9vYg", &mut out), Some(&b"foob"[..]));
+        assert_eq!(base64url_decode(b"-_8", &mut out), Some(&[0xfb, 0xff][..]));
+        assert_eq!(base64url_decode(b"Zm9vY", &mut out), None);
+        assert_eq!(base64url_decode(b"Zm+v", &mut out), None);
+
+        let data: [u8; 32] = core::array::from_fn(|i| (i * 37) as u8);
+        let encoded = base64url_encode(&data, &mut buffer);
+        assert_eq!(encoded.len(), 43);
+        assert_eq!(base64url_decode_array::<32>(encoded), Some(data));
+        assert_eq!(base64url_decode_array::<16>(encoded), None);
+    }
+
+    #[test]
+    fn mac_input() {
+        let mut association = association();
+        association.n_s = [1; 32];
+        association.n_p = [2; 32];
+
+        let mut input = Vec::new();
+        write_mac_input(
+            &association,
+            DIR_MAC_SERVER,
+            KEYING_MODE_COMPLETION,
+            None,
+            Some(&[3; 16]),
+            &mut |data| input.extend_from_slice(data),
+        );
+
+        let expected = concat!(
+            r#"[2,[1],1,"07KRU6OgqX0HIeRFldnbSW",[1],3,{"Name":"Example"},1,1,"#,
+            r#""noob@eap-noob.arpa",{"Make":"Acme"},0,{"kty":"OKP"},"#,
+            r#""AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE",{"kty":"OKP"},"#,
+            r#""AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI","AwMDAwMDAwMDAwMDAwMDAw"]"#
+        );
+        assert_eq!(core::str::from_utf8(&input).unwrap(), expected);
+
+        input.clear();
+        write_mac_input(
+            &association,
+            DIR_MAC_PEER,
+            KEYING_MODE_RECONNECT,
+            Some((&[1; 32], &[2; 32])),
+            None,
+            &mut |data| input.extend_from_slice(data),
+        );
+        assert!(core::str::from_utf8(&input)
+            .unwrap()
+            .ends_with(r#",1,"","AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE","","AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI",""]"#));
+    }
+
+    #[test]
+    fn oob_message() {
+        let mut association = association();
+        let oob = association.new_oob_message([9; 16]);
+        assert_eq!(association.noob, Some([9; 16]));
+
+        let mut buffer = [0u8; 96];
+        let query = oob.to_query(&mut buffer);
+        assert!(query.starts_with(b"P=07KRU6OgqX0HIeRFldnbSW&N="));
+        assert_eq!(OobMessage::from_query(query), Some(oob.clone()));
+        assert_eq!(OobMessage::from_query(b"P=abc&N=AAAA"), None);
+
+        let mut other = association.clone();
+        other.noob = None;
+        assert!(other.accept_oob_message(&oob));
+
+        let mut tampered = oob.clone();
+        tampered.noob[0] ^= 1;
+        assert!(!other.accept_oob_message(&tampered));
+        assert_ne!(noob_id(&oob.noob), noob_id(&tampered.noob));
+    }
+
+    #[test]
+    fn association_encoding() {
+        let mut association = association();
+        association.z = [4; 32];
+        association.noob = Some([5; 16]);
+
+        let mut buffer = [0u8; MAX_ENCODED_ASSOCIATION_LEN];
+        let encoded = association.encode(&mut buffer);
+        assert_eq!(Association::decode(encoded), Some(association.clone()));
+        assert_eq!(Association::decode(&encoded[..encoded.len() - 3]), None);
+
+        association.register([6; 32]);
+        let encoded = association.encode(&mut buffer);
+        let decoded = Association::decode(encoded).unwrap();
+        assert_eq!(decoded.state(), NoobState::Registered);
+        assert_eq!(decoded.noob, None);
+        assert_eq!(decoded.kz, [6; 32]);
+    }
+
+    #[test]
+    fn keys() {
+        let mut association = association();
+        association.z = [7; 32];
+
+        let completion = Keys::completion(&association, &[8; 16]);
+        let other_noob = Keys::completion(&association, &[9; 16]);
+        assert_ne!(completion.session.msk, other_noob.session.msk);
+        assert_ne!(completion.kms, completion.kmp);
+        assert_ne!(completion.kz, [0; 32]);
+
+        let reconnect = Keys::reconnect(&completion.kz, &[1; 32], &[2; 32]);
+        assert_eq!(reconnect.kz, completion.kz);
+        assert_ne!(reconnect.session.msk, completion.session.msk);
+    }
+
+    #[test]
+    fn ecdhe() {
+        let a = [1u8; 32];
+        let b = [2u8; 32];
+        let z_a = ecdhe_shared_secret(&a, &ecdhe_public_key(&b));
+        let z_b = ecdhe_shared_secret(&b, &ecdhe_public_key(&a));
+        assert!(z_a.is_some());
+        assert_eq!(z_a, z_b);
+        assert_eq!(ecdhe_shared_secret(&a, &[0; 32]), None);
+
+        let mut buffer = [0u8; MAX_JWK_LEN];
+        let public_key = ecdhe_public_key(&a);
+        let jwk = write_jwk(&public_key, &mut buffer);
+        assert_eq!(read_jwk(&Object::parse(jwk).unwrap()), Some(public_key));
+    }
+}
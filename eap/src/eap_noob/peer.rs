@@ -0,0 +1,602 @@
+use crate::{
+    layers::{
+        eap_layer::{Decision, MethodState, SessionKeys},
+        mux::TupleElement,
+        peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta},
+        MethodType,
+    },
+    util::OwnedSlice,
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::json::{Object, ObjectWriter};
+use super::*;
+
+#[derive(Clone)]
+enum State {
+    Idle,
+    /// Initial Exchange in progress, the association is not stored yet
+    Initial {
+        association: Association,
+    },
+    Loaded {
+        association: Association,
+    },
+    Reconnect {
+        association: Association,
+        n_s2: [u8; NONCE_LEN],
+        n_p2: [u8; NONCE_LEN],
+    },
+    Done {
+        keys: Option<Keys>,
+    },
+}
+
+/// Peer side of EAP-NOOB.
+///
+/// The association is kept in `store`, OOB messages are exchanged through `oob`.
+/// The identity method has to use the same NAI, [`DEFAULT_NAI`] by default.
+#[derive(Clone)]
+pub struct PeerNoobMethod<S, O> {
+    store: S,
+    oob: O,
+    nai: OwnedSlice<64>,
+    peer_info: OwnedSlice<MAX_INFO_LEN>,
+    dirp: u8,
+    state: State,
+}
+
+impl<S, O> TupleElement for PeerNoobMethod<S, O>
+where
+    S: PeerStore + 'static,
+    O: OobChannel + 'static,
+{
+    type Target = dyn PeerMethodLayer;
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<S, O> PeerNoobMethod<S, O>
+where
+    S: PeerStore,
+    O: OobChannel,
+{
+    pub fn new(store: S, oob: O) -> Self {
+        Self {
+            store,
+            oob,
+            nai: OwnedSlice::from(DEFAULT_NAI),
+            peer_info: OwnedSlice::from(b"{}"),
+            dirp: OobDirection::PeerToServer as u8,
+            state: State::Idle,
+        }
+    }
+
+    pub fn with_nai(mut self, nai: &[u8]) -> Self {
+        self.nai = nai.try_into().expect("NAI too long for nostd");
+        self
+    }
+
+    /// PeerInfo object, must be valid JSON
+    pub fn with_peer_info(mut self, peer_info: &[u8]) -> Self {
+        assert!(
+            Object::parse(peer_info).is_some(),
+            "PeerInfo is not a JSON object"
+        );
+        self.peer_info = peer_info.try_into().expect("PeerInfo too long for nostd");
+        self
+    }
+
+    /// OOB directions supported by the peer
+    pub fn with_directions(mut self, directions: OobDirection) -> Self {
+        self.dirp = directions as u8;
+        self
+    }
+
+    fn response<'a>(
+        env: &'a mut dyn EapEnvironment,
+        message_type: u32,
+        peer_id: &[u8],
+    ) -> ObjectWriter<'a> {
+        ObjectWriter::new(env.respond())
+            .number("Type", message_type)
+            .string("PeerId", peer_id)
+    }
+
+    fn error<'a>(
+        &mut self,
+        code: u32,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        self.state = State::Done { keys: None };
+        PeerMethodLayerResult::Send(
+            ObjectWriter::new(env.respond())
+                .number("Type", TYPE_ERROR)
+                .number("ErrorCode", code)
+                .finish(),
+        )
+    }
+
+    fn association(&self) -> Option<&Association> {
+        match &self.state {
+            State::Initial { association }
+            | State::Loaded { association }
+            | State::Reconnect { association, .. } => Some(association),
+            State::Idle | State::Done { .. } => None,
+        }
+    }
+
+    fn recv_peer_id_discovery<'a>(
+        &mut self,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let mut association = match self.store.load() {
+            Some(association) if association.state != NoobState::Unregistered => association,
+            _ => {
+                self.state = State::Idle;
+                return PeerMethodLayerResult::Send(
+                    ObjectWriter::new(env.respond())
+                        .number("Type", TYPE_PEER_ID_DISCOVERY)
+                        .number("PeerState", NoobState::Unregistered as u32)
+                        .finish(),
+                );
+            }
+        };
+
+        if association.state == NoobState::WaitingForOob && !association.peer_outputs_oob() {
+            if let Some(oob) = self.oob.receive(association.peer_id()) {
+                if association.accept_oob_message(&oob) {
+                    association.state = NoobState::OobReceived;
+                    self.store.store(&association);
+                }
+            }
+        }
+
+        // A registered peer always reconnects when it starts a new session
+        let peer_state = match association.state {
+            NoobState::Registered => NoobState::Reconnecting,
+            state => state,
+        };
+
+        let msg = Self::response(env, TYPE_PEER_ID_DISCOVERY, association.peer_id())
+            .number("PeerState", peer_state as u32)
+            .finish();
+
+        self.state = State::Loaded { association };
+        PeerMethodLayerResult::Send(msg)
+    }
+
+    fn recv_negotiation<'a>(
+        &mut self,
+        msg: &Object,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let (peer_id, vers, cryptosuites, dirs, server_info) = match (
+            msg.get("PeerId").and_then(|v| v.as_str()),
+            msg.get("Vers"),
+            msg.get("Cryptosuites"),
+            msg.get("Dirs").and_then(|v| v.as_u32()),
+            msg.get("ServerInfo").and_then(|v| v.as_object()),
+        ) {
+            (Some(a), Some(b), Some(c), Some(d @ 1..=3), Some(e)) => (a, b, c, d as u8, e),
+            _ => return self.error(ERROR_INVALID_DATA, env),
+        };
+
+        if !vers.array_contains(VERSION) {
+            return self.error(ERROR_NO_VERSION, env);
+        }
+        if !cryptosuites.array_contains(CRYPTOSUITE) {
+            return self.error(ERROR_NO_CRYPTOSUITE, env);
+        }
+        if dirs & self.dirp == 0 {
+            return self.error(ERROR_NO_DIRECTION, env);
+        }
+
+        let mut association = Association::new(&[]);
+        match (
+            peer_id.try_into(),
+            vers.0.try_into(),
+            cryptosuites.0.try_into(),
+            server_info.raw().try_into(),
+        ) {
+            (Ok(a), Ok(b), Ok(c), Ok(d)) => {
+                association.peer_id = a;
+                association.vers = b;
+                association.cryptosuites = c;
+                association.server_info = d;
+            }
+            _ => return self.error(ERROR_INVALID_DATA, env),
+        }
+        association.dirs = dirs;
+        association.nai = self.nai.clone();
+        association.verp = VERSION as u8;
+        association.cryptosuitep = CRYPTOSUITE as u8;
+        association.dirp = self.dirp;
+        association.peer_info = self.peer_info.clone();
+
+        let msg = ObjectWriter::new(env.respond())
+            .number("Type", TYPE_NEGOTIATION)
+            .number("Verp", VERSION)
+            .string("PeerId", association.peer_id())
+            .number("Cryptosuitep", CRYPTOSUITE)
+            .number("Dirp", self.dirp as u32)
+            .raw("PeerInfo", self.peer_info.as_ref())
+            .finish();
+
+        self.state = State::Initial { association };
+        PeerMethodLayerResult::Send(msg)
+    }
+
+    fn recv_ecdhe<'a>(
+        &mut self,
+        msg: &Object,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let mut association = match &self.state {
+            State::Initial { association } => association.clone(),
+            _ => return self.error(ERROR_UNEXPECTED_TYPE, env),
+        };
+
+        let (pk_s, n_s) = match (
+            msg.get("PKs").and_then(|v| v.as_object()),
+            msg.get("Ns")
+                .and_then(|v| v.as_str())
+                .and_then(base64url_decode_array),
+        ) {
+            (Some(pk_s), Some(n_s)) => (pk_s, n_s),
+            _ => return self.error(ERROR_INVALID_DATA, env),
+        };
+
+        let mut secret = [0u8; 32];
+        env.fill_random(&mut secret);
+        let z = match read_jwk(&pk_s)
+            .and_then(|public_key| ecdhe_shared_secret(&secret, &public_key))
+        {
+            Some(z) => z,
+            None => return self.error(ERROR_INVALID_KEY, env),
+        };
+
+        association.pk_s = match pk_s.raw().try_into() {
+            Ok(pk_s) => pk_s,
+            Err(_) => return self.error(ERROR_INVALID_DATA, env),
+        };
+        let mut jwk = [0u8; MAX_JWK_LEN];
+        association.pk_p = OwnedSlice::from(write_jwk(&ecdhe_public_key(&secret), &mut jwk));
+        association.n_s = n_s;
+        env.fill_random(&mut association.n_p);
+        association.z = z;
+        association.state = NoobState::WaitingForOob;
+
+        if association.peer_outputs_oob() {
+            let mut noob = [0u8; NOOB_LEN];
+            env.fill_random(&mut noob);
+            let oob = association.new_oob_message(noob);
+            self.oob.send(&oob);
+        }
+
+        self.store.store(&association);
+
+        let msg = Self::response(env, TYPE_ECDHE, association.peer_id())
+            .raw("PKp", association.pk_p.as_ref())
+            .base64("Np", &association.n_p)
+            .finish();
+
+        // The server ends the Initial Exchange with an EAP-Failure
+        self.state = State::Done { keys: None };
+        PeerMethodLayerResult::Send(msg)
+    }
+
+    fn recv_completion<'a>(
+        &mut self,
+        msg: &Object,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let mut association = match &self.state {
+            State::Loaded { association }
+                if matches!(
+                    association.state,
+                    NoobState::WaitingForOob | NoobState::OobReceived
+                ) =>
+            {
+                association.clone()
+            }
+            _ => return self.error(ERROR_UNEXPECTED_TYPE, env),
+        };
+
+        let noob = match (association.noob, Self::noob_id_of(msg)) {
+            (Some(noob), Some(id)) if noob_id(&noob) == id => noob,
+            _ => return self.error(ERROR_UNKNOWN_NOOB_ID, env),
+        };
+
+        let keys = Keys::completion(&association, &noob);
+        let macs = mac(
+            &keys.kms,
+            &association,
+            DIR_MAC_SERVER,
+            KEYING_MODE_COMPLETION,
+            None,
+            Some(&noob),
+        );
+        if !verify_mac(msg.get("MACs"), &macs) {
+            return self.error(ERROR_MAC, env);
+        }
+
+        let macp = mac(
+            &keys.kmp,
+            &association,
+            DIR_MAC_PEER,
+            KEYING_MODE_COMPLETION,
+            None,
+            Some(&noob),
+        );
+
+        association.register(keys.kz);
+        self.store.store(&association);
+
+        let msg = Self::response(env, TYPE_COMPLETION, association.peer_id())
+            .base64("MACp", &macp)
+            .finish();
+
+        self.state = State::Done { keys: Some(keys) };
+        PeerMethodLayerResult::Send(msg)
+    }
+
+    fn recv_reconnect_negotiation<'a>(
+        &mut self,
+        msg: &Object,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let mut association = match &self.state {
+            State::Loaded { association } if association.state == NoobState::Registered => {
+                association.clone()
+            }
+            _ => return self.error(ERROR_STATE_MISMATCH, env),
+        };
+
+        let (vers, cryptosuites, server_info) = match (
+            msg.get("Vers"),
+            msg.get("Cryptosuites"),
+            msg.get("ServerInfo").and_then(|v| v.as_object()),
+        ) {
+            (Some(a), Some(b), Some(c)) => (a, b, c),
+            _ => return self.error(ERROR_INVALID_DATA, env),
+        };
+
+        if !vers.array_contains(VERSION) {
+            return self.error(ERROR_NO_VERSION, env);
+        }
+        if !cryptosuites.array_contains(CRYPTOSUITE) {
+            return self.error(ERROR_NO_CRYPTOSUITE, env);
+        }
+
+        match (
+            vers.0.try_into(),
+            cryptosuites.0.try_into(),
+            server_info.raw().try_into(),
+        ) {
+            (Ok(a), Ok(b), Ok(c)) => {
+                association.vers = a;
+                association.cryptosuites = b;
+                association.server_info = c;
+            }
+            _ => return self.error(ERROR_INVALID_DATA, env),
+        }
+        association.verp = VERSION as u8;
+        association.cryptosuitep = CRYPTOSUITE as u8;
+        association.peer_info = self.peer_info.clone();
+
+        let msg = ObjectWriter::new(env.respond())
+            .number("Type", TYPE_RECONNECT_NEGOTIATION)
+            .number("Verp", VERSION)
+            .string("PeerId", association.peer_id())
+            .number("Cryptosuitep", CRYPTOSUITE)
+            .raw("PeerInfo", self.peer_info.as_ref())
+            .finish();
+
+        self.state = State::Reconnect {
+            association,
+            n_s2: [0; NONCE_LEN],
+            n_p2: [0; NONCE_LEN],
+        };
+        PeerMethodLayerResult::Send(msg)
+    }
+
+    fn recv_reconnect_nonces<'a>(
+        &mut self,
+        msg: &Object,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let association = match &self.state {
+            State::Reconnect { association, .. } => association.clone(),
+            _ => return self.error(ERROR_UNEXPECTED_TYPE, env),
+        };
+
+        let n_s2 = match (
+            msg.get("KeyingMode").and_then(|v| v.as_u32()),
+            msg.get("Ns2")
+                .and_then(|v| v.as_str())
+                .and_then(base64url_decode_array),
+        ) {
+            (Some(KEYING_MODE_RECONNECT), Some(n_s2)) => n_s2,
+            // Rekeying with a new ECDHE exchange is not supported
+            (Some(_), Some(_)) => return self.error(ERROR_NO_CRYPTOSUITE, env),
+            _ => return self.error(ERROR_INVALID_DATA, env),
+        };
+
+        let mut n_p2 = [0u8; NONCE_LEN];
+        env.fill_random(&mut n_p2);
+
+        let msg = Self::response(env, TYPE_RECONNECT_NONCES, association.peer_id())
+            .base64("Np2", &n_p2)
+            .finish();
+
+        self.state = State::Reconnect {
+            association,
+            n_s2,
+            n_p2,
+        };
+        PeerMethodLayerResult::Send(msg)
+    }
+
+    fn recv_reconnect_completion<'a>(
+        &mut self,
+        msg: &Object,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let (association, n_s2, n_p2) = match &self.state {
+            State::Reconnect {
+                association,
+                n_s2,
+                n_p2,
+            } if *n_s2 != [0; NONCE_LEN] => (association.clone(), *n_s2, *n_p2),
+            _ => return self.error(ERROR_UNEXPECTED_TYPE, env),
+        };
+
+        let keys = Keys::reconnect(&association.kz, &n_p2, &n_s2);
+        let macs2 = mac(
+            &keys.kms,
+            &association,
+            DIR_MAC_SERVER,
+            KEYING_MODE_RECONNECT,
+            Some((&n_s2, &n_p2)),
+            None,
+        );
+        if !verify_mac(msg.get("MACs2"), &macs2) {
+            return self.error(ERROR_MAC, env);
+        }
+
+        let macp2 = mac(
+            &keys.kmp,
+            &association,
+            DIR_MAC_PEER,
+            KEYING_MODE_RECONNECT,
+            Some((&n_s2, &n_p2)),
+            None,
+        );
+
+        self.store.store(&association);
+
+        let msg = Self::response(env, TYPE_RECONNECT_COMPLETION, association.peer_id())
+            .base64("MACp2", &macp2)
+            .finish();
+
+        self.state = State::Done { keys: Some(keys) };
+        PeerMethodLayerResult::Send(msg)
+    }
+
+    fn noob_id_of(msg: &Object) -> Option<[u8; 16]> {
+        msg.get("NoobId")
+            .and_then(|v| v.as_str())
+            .and_then(base64url_decode_array)
+    }
+}
+
+impl<S, O> PeerMethodLayer for PeerNoobMethod<S, O>
+where
+    S: PeerStore,
+    O: OobChannel,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_NOOB)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_NOOB
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let msg = match Object::parse(msg) {
+            Some(msg) => msg,
+            None => return self.error(ERROR_INVALID_DATA, env),
+        };
+
+        let message_type = match msg.get("Type").and_then(|v| v.as_u32()) {
+            Some(message_type) => message_type,
+            None => return self.error(ERROR_INVALID_DATA, env),
+        };
+
+        // Apart from the first two messages, requests carry the PeerId of the association
+        if !matches!(
+            message_type,
+            TYPE_PEER_ID_DISCOVERY | TYPE_NEGOTIATION | TYPE_ERROR
+        ) {
+            let peer_id = self.association().map(|a| a.peer_id());
+            if peer_id.is_none() || msg.get("PeerId").and_then(|v| v.as_str()) != peer_id {
+                return self.error(ERROR_STATE_MISMATCH, env);
+            }
+        }
+
+        match message_type {
+            TYPE_ERROR => {
+                self.state = State::Done { keys: None };
+                PeerMethodLayerResult::Failed(env)
+            }
+            TYPE_PEER_ID_DISCOVERY => self.recv_peer_id_discovery(env),
+            TYPE_NEGOTIATION => self.recv_negotiation(&msg, env),
+            TYPE_ECDHE => self.recv_ecdhe(&msg, env),
+            TYPE_WAITING => {
+                let association = self.association().unwrap().clone();
+                PeerMethodLayerResult::Send(
+                    Self::response(env, TYPE_WAITING, association.peer_id()).finish(),
+                )
+            }
+            TYPE_NOOB_ID_DISCOVERY => match self.association().unwrap().noob {
+                Some(noob) => {
+                    let association = self.association().unwrap().clone();
+                    PeerMethodLayerResult::Send(
+                        Self::response(env, TYPE_NOOB_ID_DISCOVERY, association.peer_id())
+                            .base64("NoobId", &noob_id(&noob))
+                            .finish(),
+                    )
+                }
+                None => self.error(ERROR_UNKNOWN_NOOB_ID, env),
+            },
+            TYPE_COMPLETION => self.recv_completion(&msg, env),
+            TYPE_RECONNECT_NEGOTIATION => self.recv_reconnect_negotiation(&msg, env),
+            TYPE_RECONNECT_NONCES => self.recv_reconnect_nonces(&msg, env),
+            TYPE_RECONNECT_COMPLETION => self.recv_reconnect_completion(&msg, env),
+            _ => self.error(ERROR_UNEXPECTED_TYPE, env),
+        }
+    }
+
+    fn method_state(&self) -> MethodState {
+        if matches!(self.state, State::Done { .. }) {
+            MethodState::Done
+        } else {
+            MethodState::MayCont
+        }
+    }
+
+    fn decision(&self) -> Decision {
+        if matches!(self.state, State::Done { keys: Some(_) }) {
+            Decision::CondSucc
+        } else {
+            Decision::Fail
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        match &self.state {
+            State::Done { keys: Some(keys) } => Some(&keys.session),
+            _ => None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = State::Idle;
+    }
+}
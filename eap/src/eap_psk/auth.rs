@@ -0,0 +1,226 @@
+use crate::{
+    layers::{
+        auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta},
+        eap_layer::SessionKeys,
+        mux::TupleElement,
+        MethodType,
+    },
+    message::MessageCode,
+    util::{constant_time_eq, OwnedSlice},
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+#[derive(Clone)]
+enum State {
+    Start,
+    WaitSecondMessage,
+    WaitFourthMessage,
+    Done,
+}
+
+/// Server side of EAP-PSK.
+///
+/// `psk_lookup` returns the PSK for the peer identity (ID_P) the peer has sent.
+#[derive(Clone)]
+pub struct AuthPskMethod<F> {
+    id_s: OwnedSlice<64>,
+    psk_lookup: F,
+    state: State,
+    rand_s: Block,
+    tek: Block,
+    keys: Option<SessionKeys>,
+}
+
+impl<F> TupleElement for AuthPskMethod<F>
+where
+    F: FnMut(&[u8]) -> Option<[u8; PSK_LEN]> + 'static,
+{
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<F> AuthPskMethod<F>
+where
+    F: FnMut(&[u8]) -> Option<[u8; PSK_LEN]>,
+{
+    pub fn new(id_s: &[u8], psk_lookup: F) -> Self {
+        Self {
+            id_s: id_s.try_into().expect("id_s too long for nostd"),
+            psk_lookup,
+            state: State::Start,
+            rand_s: [0; RAND_LEN],
+            tek: [0; 16],
+            keys: None,
+        }
+    }
+
+    fn recv_second_message<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        // Flags | RAND_S | RAND_P | MAC_P | ID_P
+        if msg.len() < 1 + RAND_LEN + RAND_LEN + MAC_LEN
+            || message_number(msg[0]) != 1
+            || msg[1..17] != self.rand_s
+        {
+            return AuthMethodLayerResult::Failed(env);
+        }
+
+        let rand_p: Block = msg[17..33].try_into().unwrap();
+        let mac_p = &msg[33..49];
+        let id_p = &msg[49..];
+
+        let psk = match (self.psk_lookup)(id_p) {
+            Some(psk) => psk,
+            None => return AuthMethodLayerResult::Failed(env),
+        };
+
+        let (ak, kdk) = key_setup(&psk);
+        let expected_mac_p = cmac(&ak, &[id_p, self.id_s.as_ref(), &self.rand_s, &rand_p]);
+        if !constant_time_eq(&expected_mac_p, mac_p) {
+            return AuthMethodLayerResult::Failed(env);
+        }
+
+        let mac_s = cmac(&ak, &[self.id_s.as_ref(), &rand_p]);
+        let (tek, keys) = derive_keys(&kdk, &rand_p, &self.rand_s);
+        self.tek = tek;
+        self.keys = Some(keys);
+        env.set_name(id_p);
+
+        // The protected channel authenticates the header of the next request,
+        // the EAP layer assigns it the identifier following the current one.
+        let flags = flags(2);
+        let total_length = (4 + 1 + 1 + RAND_LEN + MAC_LEN + NONCE_LEN + TAG_LEN + 1) as u16;
+        let header = pchannel_header(
+            MessageCode::Request as u8,
+            meta.message.identifier.wrapping_add(1),
+            total_length,
+            flags,
+            &self.rand_s,
+        );
+
+        let nonce = 0u32;
+        let mut pchannel = [R_FLAG_DONE_SUCCESS << 6];
+        let tag = pchannel_seal(&self.tek, nonce, &header, &mut pchannel);
+
+        self.state = State::WaitFourthMessage;
+
+        AuthMethodLayerResult::Send(
+            env.respond()
+                .write(&[flags])
+                .write(&self.rand_s)
+                .write(&mac_s)
+                .write(&nonce.to_be_bytes())
+                .write(&tag)
+                .write(&pchannel),
+        )
+    }
+
+    fn recv_fourth_message<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        // Flags | RAND_S | PCHANNEL_P_1
+        if msg.len() < 1 + RAND_LEN + NONCE_LEN + TAG_LEN + 1
+            || message_number(msg[0]) != 3
+            || msg[1..17] != self.rand_s
+        {
+            return AuthMethodLayerResult::Failed(env);
+        }
+
+        let nonce = u32::from_be_bytes(msg[17..21].try_into().unwrap());
+        if nonce != 1 {
+            return AuthMethodLayerResult::Failed(env);
+        }
+
+        let header = pchannel_header(
+            MessageCode::Response as u8,
+            meta.message.identifier,
+            meta.message.total_length,
+            msg[0],
+            &self.rand_s,
+        );
+
+        let tag = &msg[21..37];
+        let mut pchannel = [msg[37]];
+        if msg.len() != 38 || !pchannel_open(&self.tek, nonce, &header, &mut pchannel, tag) {
+            return AuthMethodLayerResult::Failed(env);
+        }
+
+        self.state = State::Done;
+        if pchannel[0] >> 6 == R_FLAG_DONE_SUCCESS {
+            AuthMethodLayerResult::Finished(env)
+        } else {
+            self.keys = None;
+            AuthMethodLayerResult::Failed(env)
+        }
+    }
+}
+
+impl<F> AuthMethodLayer for AuthPskMethod<F>
+where
+    F: FnMut(&[u8]) -> Option<[u8; PSK_LEN]>,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_PSK)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_PSK
+    }
+
+    // The protected channel authenticates the header of the next Request
+    fn binds_identifier(&self) -> bool {
+        true
+    }
+
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        env.fill_random(&mut self.rand_s);
+        self.keys = None;
+        self.state = State::WaitSecondMessage;
+
+        AuthMethodLayerResult::Send(
+            env.respond()
+                .write(&[flags(0)])
+                .write(&self.rand_s)
+                .write(self.id_s.as_ref()),
+        )
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        match self.state {
+            State::WaitSecondMessage => self.recv_second_message(msg, meta, env),
+            State::WaitFourthMessage => self.recv_fourth_message(msg, meta, env),
+            State::Start | State::Done => AuthMethodLayerResult::Failed(env),
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        match self.state {
+            State::Done => self.keys.as_ref(),
+            _ => None,
+        }
+    }
+}
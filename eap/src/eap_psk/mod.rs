@@ -0,0 +1,195 @@
+//! EAP-PSK, see https://www.rfc-editor.org/rfc/rfc4764
+
+mod auth;
+mod peer;
+
+pub use auth::AuthPskMethod;
+pub use peer::PeerPskMethod;
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use cmac::{Cmac, Mac};
+use eax::{aead::AeadInPlace, Eax};
+
+use crate::layers::{eap_layer::SessionKeys, SecurityClaims};
+
+const METHOD_PSK: u8 = 47;
+
+pub const PSK_LEN: usize = 16;
+const RAND_LEN: usize = 16;
+const MAC_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+const NONCE_LEN: usize = 4;
+
+/// Length of the EAP header, type, flags and RAND_S. These bytes are
+/// authenticated as associated data of the protected channel.
+const PCHANNEL_HEADER_LEN: usize = 4 + 1 + 1 + RAND_LEN;
+
+/*
+      0 1 2 3 4 5 6 7
+      +-+-+-+-+-+-+-+-+
+      | T |  Reserved |
+      +-+-+-+-+-+-+-+-+
+
+      T = Message number (0..3)
+*/
+const fn flags(t: u8) -> u8 {
+    t << 6
+}
+
+const fn message_number(flags: u8) -> u8 {
+    flags >> 6
+}
+
+/*
+Protected channel flags:
+
+      0 1 2 3 4 5 6 7
+      +-+-+-+-+-+-+-+-+
+      | R |E| Reserved|
+      +-+-+-+-+-+-+-+-+
+*/
+#[allow(unused)]
+const R_FLAG_CONT: u8 = 1;
+const R_FLAG_DONE_SUCCESS: u8 = 2;
+const R_FLAG_DONE_FAILURE: u8 = 3;
+
+type Block = [u8; 16];
+
+fn encrypt_block(key: &Block, block: &Block) -> Block {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    let mut block = GenericArray::clone_from_slice(block);
+    cipher.encrypt_block(&mut block);
+    block.into()
+}
+
+/// Derives AK and KDK from the PSK, RFC 4764 3.1
+fn key_setup(psk: &Block) -> (Block, Block) {
+    let mut ak = encrypt_block(psk, &[0; 16]);
+    let mut kdk = ak;
+
+    ak[15] ^= 1;
+    kdk[15] ^= 2;
+
+    (encrypt_block(psk, &ak), encrypt_block(psk, &kdk))
+}
+
+/// Derives TEK, MSK and EMSK from the KDK, RFC 4764 3.2. The Session-ID is
+/// 0x2F || RAND_P || RAND_S, as hostap exports it.
+fn derive_keys(kdk: &Block, rand_p: &Block, rand_s: &Block) -> (Block, SessionKeys) {
+    let mut hash = encrypt_block(kdk, rand_p);
+    let mut counter = 1u8;
+
+    let mut next = || {
+        hash[15] ^= counter;
+        let block = encrypt_block(kdk, &hash);
+        hash[15] ^= counter;
+        counter += 1;
+        block
+    };
+
+    let tek = next();
+    let mut keys = SessionKeys {
+        msk: [0; 64],
+        emsk: [0; 64],
+        session_id: SessionKeys::session_id(METHOD_PSK, &[rand_p, rand_s]),
+    };
+    for chunk in keys.msk.chunks_mut(16) {
+        chunk.copy_from_slice(&next());
+    }
+    for chunk in keys.emsk.chunks_mut(16) {
+        chunk.copy_from_slice(&next());
+    }
+
+    (tek, keys)
+}
+
+fn cmac(key: &Block, parts: &[&[u8]]) -> Block {
+    let mut mac = <Cmac<Aes128> as KeyInit>::new(GenericArray::from_slice(key));
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+fn pchannel_nonce(nonce: u32) -> [u8; 16] {
+    let mut full_nonce = [0u8; 16];
+    full_nonce[12..].copy_from_slice(&nonce.to_be_bytes());
+    full_nonce
+}
+
+/// Encrypts the protected channel flags in place, returns the tag
+fn pchannel_seal(tek: &Block, nonce: u32, header: &[u8], data: &mut [u8]) -> [u8; TAG_LEN] {
+    let cipher = Eax::<Aes128>::new(GenericArray::from_slice(tek));
+    cipher
+        .encrypt_in_place_detached(
+            GenericArray::from_slice(&pchannel_nonce(nonce)),
+            header,
+            data,
+        )
+        .expect("payload is too short to exceed EAX limits")
+        .into()
+}
+
+fn pchannel_open(tek: &Block, nonce: u32, header: &[u8], data: &mut [u8], tag: &[u8]) -> bool {
+    let cipher = Eax::<Aes128>::new(GenericArray::from_slice(tek));
+    cipher
+        .decrypt_in_place_detached(
+            GenericArray::from_slice(&pchannel_nonce(nonce)),
+            header,
+            data,
+            GenericArray::from_slice(tag),
+        )
+        .is_ok()
+}
+
+/// Rebuilds the first bytes of an EAP-PSK message, as they are
+/// authenticated by the protected channel.
+fn pchannel_header(
+    code: u8,
+    identifier: u8,
+    total_length: u16,
+    flags: u8,
+    rand_s: &Block,
+) -> [u8; PCHANNEL_HEADER_LEN] {
+    let mut header = [0u8; PCHANNEL_HEADER_LEN];
+    header[0] = code;
+    header[1] = identifier;
+    header[2..4].copy_from_slice(&total_length.to_be_bytes());
+    header[4] = METHOD_PSK;
+    header[5] = flags;
+    header[6..].copy_from_slice(rand_s);
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pchannel_roundtrip() {
+        let tek = [7u8; 16];
+        let header = pchannel_header(1, 2, 59, flags(2), &[3; 16]);
+
+        let mut data = [R_FLAG_DONE_SUCCESS << 6];
+        let tag = pchannel_seal(&tek, 0, &header, &mut data);
+
+        let mut tampered = data;
+        assert!(!pchannel_open(&tek, 1, &header, &mut tampered, &tag));
+
+        assert!(pchannel_open(&tek, 0, &header, &mut data, &tag));
+        assert_eq!(data, [R_FLAG_DONE_SUCCESS << 6]);
+    }
+
+    #[test]
+    fn key_hierarchy_differs() {
+        let (ak, kdk) = key_setup(&[1; 16]);
+        assert_ne!(ak, kdk);
+
+        let (tek, keys) = derive_keys(&kdk, &[2; 16], &[3; 16]);
+        assert_ne!(tek, ak);
+        assert_ne!(keys.msk, keys.emsk);
+    }
+}
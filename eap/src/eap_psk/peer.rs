@@ -0,0 +1,328 @@
+use crate::{
+    layers::{
+        eap_layer::{Decision, MethodState, SessionKeys},
+        mux::TupleElement,
+        peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta},
+        MethodType,
+    },
+    message::MessageCode,
+    util::{constant_time_eq, OwnedSlice},
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+#[derive(Clone)]
+enum State {
+    WaitFirstMessage,
+    WaitThirdMessage {
+        ak: Block,
+        kdk: Block,
+        rand_p: Block,
+    },
+    Done {
+        success: bool,
+    },
+}
+
+/// Peer side of EAP-PSK, authenticates as `id_p` with a 128 bit pre-shared key.
+///
+/// Extensions are not supported, a server asking to continue (R=CONT) is
+/// answered with DONE_FAILURE and the method fails.
+#[derive(Clone)]
+pub struct PeerPskMethod {
+    id_p: OwnedSlice<64>,
+    id_s: OwnedSlice<64>,
+    psk: [u8; PSK_LEN],
+    state: State,
+    rand_s: Block,
+    keys: Option<SessionKeys>,
+}
+
+impl TupleElement for PeerPskMethod {
+    type Target = dyn PeerMethodLayer;
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl PeerPskMethod {
+    pub fn new(id_p: &[u8], psk: [u8; PSK_LEN]) -> Self {
+        Self {
+            id_p: id_p.try_into().expect("id_p too long for nostd"),
+            id_s: OwnedSlice::new(),
+            psk,
+            state: State::WaitFirstMessage,
+            rand_s: [0; RAND_LEN],
+            keys: None,
+        }
+    }
+
+    /// Identity of the server (ID_S), available after the first message.
+    pub fn server_identity(&self) -> &[u8] {
+        self.id_s.as_ref()
+    }
+
+    fn recv_first_message<'a>(
+        &mut self,
+        msg: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        // Flags | RAND_S | ID_S
+        if msg.len() < 1 + RAND_LEN || message_number(msg[0]) != 0 {
+            return PeerMethodLayerResult::Failed(env);
+        }
+
+        self.rand_s.copy_from_slice(&msg[1..17]);
+        self.id_s = match msg[17..].try_into() {
+            Ok(id_s) => id_s,
+            Err(_) => return PeerMethodLayerResult::Failed(env),
+        };
+
+        let mut rand_p = [0u8; RAND_LEN];
+        env.fill_random(&mut rand_p);
+
+        let (ak, kdk) = key_setup(&self.psk);
+        let mac_p = cmac(
+            &ak,
+            &[
+                self.id_p.as_ref(),
+                self.id_s.as_ref(),
+                &self.rand_s,
+                &rand_p,
+            ],
+        );
+
+        self.state = State::WaitThirdMessage { ak, kdk, rand_p };
+
+        PeerMethodLayerResult::Send(
+            env.respond()
+                .write(&[flags(1)])
+                .write(&self.rand_s)
+                .write(&rand_p)
+                .write(&mac_p)
+                .write(self.id_p.as_ref()),
+        )
+    }
+
+    fn recv_third_message<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let (ak, kdk, rand_p) = match &self.state {
+            State::WaitThirdMessage { ak, kdk, rand_p } => (*ak, *kdk, *rand_p),
+            _ => return PeerMethodLayerResult::Failed(env),
+        };
+
+        // Flags | RAND_S | MAC_S | PCHANNEL_S_0
+        if msg.len() < 1 + RAND_LEN + MAC_LEN + NONCE_LEN + TAG_LEN + 1
+            || message_number(msg[0]) != 2
+            || msg[1..17] != self.rand_s
+        {
+            return PeerMethodLayerResult::Failed(env);
+        }
+
+        let expected_mac_s = cmac(&ak, &[self.id_s.as_ref(), &rand_p]);
+        if !constant_time_eq(&msg[17..33], &expected_mac_s) {
+            return PeerMethodLayerResult::Failed(env);
+        }
+
+        let (tek, keys) = derive_keys(&kdk, &rand_p, &self.rand_s);
+
+        let header = pchannel_header(
+            MessageCode::Request as u8,
+            meta.message.identifier,
+            meta.message.total_length,
+            msg[0],
+            &self.rand_s,
+        );
+
+        let nonce = u32::from_be_bytes(msg[33..37].try_into().unwrap());
+        let tag = &msg[37..53];
+
+        // Extensions are not supported, so the payload is a single flag byte
+        let mut pchannel = [msg[53]];
+        if msg.len() != 54 || !pchannel_open(&tek, nonce, &header, &mut pchannel, tag) {
+            return PeerMethodLayerResult::Failed(env);
+        }
+
+        // CONT would need extensions, which are not supported
+        let success = pchannel[0] >> 6 == R_FLAG_DONE_SUCCESS;
+        let result = if success {
+            R_FLAG_DONE_SUCCESS
+        } else {
+            R_FLAG_DONE_FAILURE
+        };
+
+        let flags = flags(3);
+        let total_length = (4 + 1 + 1 + RAND_LEN + NONCE_LEN + TAG_LEN + 1) as u16;
+        let header = pchannel_header(
+            MessageCode::Response as u8,
+            meta.message.identifier,
+            total_length,
+            flags,
+            &self.rand_s,
+        );
+
+        let nonce = nonce.wrapping_add(1);
+        let mut pchannel = [result << 6];
+        let tag = pchannel_seal(&tek, nonce, &header, &mut pchannel);
+
+        self.keys = if success { Some(keys) } else { None };
+        self.state = State::Done { success };
+
+        PeerMethodLayerResult::Send(
+            env.respond()
+                .write(&[flags])
+                .write(&self.rand_s)
+                .write(&nonce.to_be_bytes())
+                .write(&tag)
+                .write(&pchannel),
+        )
+    }
+}
+
+impl PeerMethodLayer for PeerPskMethod {
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_PSK)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_PSK
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        match msg.first().map(|flags| message_number(*flags)) {
+            Some(0) => self.recv_first_message(msg, env),
+            Some(2) => self.recv_third_message(msg, meta, env),
+            _ => PeerMethodLayerResult::Failed(env),
+        }
+    }
+
+    fn method_state(&self) -> MethodState {
+        if matches!(self.state, State::Done { .. }) {
+            MethodState::Done
+        } else {
+            MethodState::MayCont
+        }
+    }
+
+    fn decision(&self) -> Decision {
+        if matches!(self.state, State::Done { success: true }) {
+            Decision::UncondSucc
+        } else {
+            Decision::Fail
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.keys.as_ref()
+    }
+
+    fn reset(&mut self) {
+        self.state = State::WaitFirstMessage;
+        self.keys = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::assert_static_conversation;
+    use crate::{
+        layers::{
+            auth::AuthIdentityMethod, peer::PeerIdentityMethod, AuthLayer, EapLayer, PeerLayer,
+        },
+        message::Message,
+        StaticEnvironment,
+    };
+
+    use super::*;
+
+    #[test]
+    fn psk_static_environment() {
+        const PSK: [u8; 16] = *b"0123456789abcdef";
+
+        let peer = EapLayer::new(
+            PeerLayer::new()
+                .with(PeerIdentityMethod::new(b"sensor-1"))
+                .with(PeerPskMethod::new(b"sensor-1", PSK)),
+        );
+        let auth = EapLayer::new(AuthLayer::new().with(AuthIdentityMethod::new()).with(
+            AuthPskMethod::new(b"server", |id_p: &[u8]| {
+                (id_p == b"sensor-1").then_some(PSK)
+            }),
+        ));
+
+        assert_static_conversation(peer, auth, 10);
+    }
+
+    #[test]
+    fn psk_continue_fails() {
+        const PSK: [u8; 16] = *b"0123456789abcdef";
+        const RAND_S: Block = [5; RAND_LEN];
+
+        let mut env = StaticEnvironment::<1020>::new(|buf| buf.fill(7));
+        let mut peer = PeerPskMethod::new(b"sensor-1", PSK);
+        let meta = |identifier, body: &'static [u8]| RecvMeta {
+            message: Message::new(MessageCode::Request, identifier, body),
+        };
+
+        // Flags | RAND_S | ID_S
+        let mut first = [0u8; 1 + RAND_LEN + 6];
+        first[0] = flags(0);
+        first[1..17].copy_from_slice(&RAND_S);
+        first[17..].copy_from_slice(b"server");
+        let rand_p: Block = match peer.recv(&first, &meta(0, &[]), &mut env) {
+            PeerMethodLayerResult::Send(data) => data.slice()[17..33].try_into().unwrap(),
+            _ => panic!("expected second message"),
+        };
+
+        // The server seals R=CONT into the protected channel
+        let (ak, kdk) = key_setup(&PSK);
+        let (tek, _) = derive_keys(&kdk, &rand_p, &RAND_S);
+        let mut third = [0u8; 1 + RAND_LEN + MAC_LEN + NONCE_LEN + TAG_LEN + 1];
+        third[0] = flags(2);
+        third[1..17].copy_from_slice(&RAND_S);
+        third[17..33].copy_from_slice(&cmac(&ak, &[b"server", &rand_p]));
+        let header = pchannel_header(1, 1, 4 + 1 + third.len() as u16, flags(2), &RAND_S);
+        let mut pchannel = [R_FLAG_CONT << 6];
+        let tag = pchannel_seal(&tek, 0, &header, &mut pchannel);
+        third[37..53].copy_from_slice(&tag);
+        third[53] = pchannel[0];
+
+        let mut fourth = [0u8; 1 + RAND_LEN + NONCE_LEN + TAG_LEN + 1];
+        match peer.recv(&third, &meta(1, &[0; 55]), &mut env) {
+            PeerMethodLayerResult::Send(data) => fourth.copy_from_slice(data.slice()),
+            _ => panic!("expected fourth message"),
+        }
+
+        // Flags | RAND_S | PCHANNEL_P_1
+        let header = pchannel_header(2, 1, 4 + 1 + fourth.len() as u16, flags(3), &RAND_S);
+        let mut pchannel = [fourth[37]];
+        assert!(pchannel_open(
+            &tek,
+            1,
+            &header,
+            &mut pchannel,
+            &fourth[21..37]
+        ));
+        assert_eq!(pchannel[0] >> 6, R_FLAG_DONE_FAILURE);
+        assert_eq!(peer.decision(), Decision::Fail);
+        assert!(peer.session_keys().is_none());
+    }
+}
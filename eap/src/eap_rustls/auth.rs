@@ -1,13 +1,14 @@
 use crate::{
     eap_rustls::{CommonTLS, EapCommonResult},
-    layers::mux::TupleElement,
+    layers::{mux::TupleElement, MethodType, SecurityClaims},
     EapEnvironmentResponse,
 };
 use std::sync::Arc;
 
 use dummycert::TlsConfig;
 use rustls::{
-    server::AllowAnyAuthenticatedClient, Certificate, PrivateKey, ServerConfig, ServerConnection,
+    server::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient},
+    Certificate, PrivateKey, ServerConfig, ServerConnection,
 };
 
 use crate::EapEnvironment;
@@ -24,7 +25,7 @@ pub struct AuthTlsMethod {
 impl TupleElement for AuthTlsMethod {
     type Target = dyn AuthMethodLayer;
 
-    fn id(&self) -> u8 {
+    fn id(&self) -> MethodType {
         self.method_identifier()
     }
 
@@ -51,7 +52,12 @@ impl AuthTlsMethod {
         }
     }
 
-    fn create_common_tls(server_config: &TlsConfig) -> CommonTLS<ServerConnection> {
+    /// Tunnel methods authenticate the peer inside the tunnel, they don't
+    /// require a client certificate.
+    pub(crate) fn create_common_tls(
+        server_config: &TlsConfig,
+        require_client_cert: bool,
+    ) -> CommonTLS<ServerConnection> {
         let server_cert = rustls_pemfile::read_all(&mut server_config.server_cert.as_ref())
             .unwrap()
             .into_iter()
@@ -88,12 +94,18 @@ impl AuthTlsMethod {
         }
         assert!(!root_ca_store.is_empty());
 
+        let verifier = if require_client_cert {
+            AllowAnyAuthenticatedClient::new(root_ca_store)
+        } else {
+            AllowAnyAnonymousOrAuthenticatedClient::new(root_ca_store)
+        };
+
         let config = ServerConfig::builder()
             .with_safe_default_cipher_suites()
             .with_safe_default_kx_groups()
             .with_safe_default_protocol_versions()
             .unwrap()
-            .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(root_ca_store))
+            .with_client_cert_verifier(verifier)
             .with_single_cert(server_cert, server_key)
             .expect("bad certificate/key");
 
@@ -102,16 +114,20 @@ impl AuthTlsMethod {
 }
 
 impl AuthMethodLayer for AuthTlsMethod {
-    fn method_identifier(&self) -> u8 {
-        METHOD_TLS
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_TLS)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_TLS
     }
 
     fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
         let inner = self
             .inner
-            .get_or_insert_with(|| AuthTlsMethod::create_common_tls(&self.config));
+            .get_or_insert_with(|| AuthTlsMethod::create_common_tls(&self.config, true));
 
-        AuthMethodLayerResult::Send(env.respond().write(inner.start_packet()))
+        AuthMethodLayerResult::Send(env.respond().write(&inner.start_packet()))
     }
 
     fn recv<'a>(
@@ -122,7 +138,7 @@ impl AuthMethodLayer for AuthTlsMethod {
     ) -> AuthMethodLayerResult<'a> {
         let inner = self
             .inner
-            .get_or_insert_with(|| AuthTlsMethod::create_common_tls(&self.config));
+            .get_or_insert_with(|| AuthTlsMethod::create_common_tls(&self.config, true));
 
         match inner.process(msg, true) {
             Ok(EapCommonResult::Finished) => AuthMethodLayerResult::Finished(env),
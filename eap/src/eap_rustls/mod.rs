@@ -1,18 +1,23 @@
 mod auth;
 mod peer;
+pub(crate) mod tunnel;
 
 pub use auth::AuthTlsMethod;
 pub use peer::PeerTlsMethod;
 
+use std::io::{Read, Write};
 use std::ops::DerefMut;
 
 use rustls::ConnectionCommon;
+
 const TLS_LEN_FIELD_LEN: usize = 4;
 
 pub struct CommonTLS<C> {
     pub con: Box<C>,
     pub sendbufferstate: SendBufferState,
     pub finished: bool,
+    /// Version bits of the flags field, zero for EAP-TLS
+    pub version: u8,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -33,44 +38,76 @@ impl<C> CommonTLS<C> {
             con: Box::new(con),
             sendbufferstate: SendBufferState::NewPayload { total_length: 0 },
             finished: false,
+            version: 0,
         }
     }
+
+    pub fn with_version(mut self, version: u8) -> Self {
+        assert!(version <= HEADER_FIELD_VERSION);
+        self.version = version;
+        self
+    }
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum TlsError {
     MessageEmpty,
     MessageShort,
-    NotAllDataConsumed { consumed: usize, total: usize },
+    NotAllDataConsumed {
+        consumed: usize,
+        total: usize,
+    },
     GenericTlsError,
+    /// The version bits of the peer differ from ours
+    UnsupportedVersion,
 }
 
 impl<C, T> CommonTLS<C>
 where
     C: DerefMut<Target = ConnectionCommon<T>>,
 {
-    pub fn start_packet(&self) -> &'static [u8] {
-        const START_PACKET: u8 = Header {
+    pub fn start_packet(&self) -> [u8; 1] {
+        [Header {
             length_included: false,
             more_fragments: false,
             start: true,
+            version: self.version,
         }
-        .write();
+        .write()]
+    }
+
+    pub fn process(&mut self, msg: &[u8], is_auth: bool) -> Result<EapCommonResult, TlsError> {
+        let header = self.receive(msg)?;
+        let only_ack = header.more_fragments;
+
+        if !self.con.is_handshaking()
+            && (matches!(
+                self.sendbufferstate,
+                SendBufferState::NewPayload { total_length: 0 }
+            ) || matches!(self.sendbufferstate, SendBufferState::MidPayload))
+        {
+            self.finished = true;
 
-        &[START_PACKET]
+            if is_auth {
+                return Ok(EapCommonResult::Finished);
+            }
+        }
+
+        if !only_ack {
+            self.next_fragment().map(EapCommonResult::Next)
+        } else {
+            Ok(EapCommonResult::Next(self.ack_packet()))
+        }
     }
 
-    pub fn process(
-        &mut self,
-        msg: &[u8],
-        is_auth: bool,
-    ) -> Result<EapCommonResult, TlsError> {
+    /// Feeds a received packet into the TLS connection, returns its header.
+    /// A packet with the more fragments flag set has to be acknowledged.
+    pub fn receive(&mut self, msg: &[u8]) -> Result<Header, TlsError> {
         if msg.is_empty() {
             return Err(TlsError::MessageEmpty);
         }
 
         let header = Header::parse(msg[0]);
-        let only_ack = header.more_fragments;
 
         let data_was_sent = msg.len() > 1;
         if data_was_sent || header.start {
@@ -99,81 +136,114 @@ where
                 }
             };
 
-            match self.con.process_new_packets() {
-                Ok(d) => {
-                    self.sendbufferstate = SendBufferState::NewPayload {
-                        total_length: d.tls_bytes_to_write(),
-                    }
-                }
-                Err(e) => {
-                    eprintln!("TLS Error {e}");
-                    return Err(TlsError::GenericTlsError);
-                }
-            };
+            self.update_send_buffer()?;
         }
 
-        if !self.con.is_handshaking()
-            && (matches!(
-                self.sendbufferstate,
-                SendBufferState::NewPayload { total_length: 0 }
-            ) || matches!(self.sendbufferstate, SendBufferState::MidPayload))
-        {
-            self.finished = true;
+        Ok(header)
+    }
 
-            if is_auth {
-                return Ok(EapCommonResult::Finished);
+    fn update_send_buffer(&mut self) -> Result<(), TlsError> {
+        match self.con.process_new_packets() {
+            Ok(d) => {
+                self.sendbufferstate = SendBufferState::NewPayload {
+                    total_length: d.tls_bytes_to_write(),
+                };
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("TLS Error {e}");
+                Err(TlsError::GenericTlsError)
             }
         }
+    }
 
-        if !only_ack {
-            const MTU: usize = 1000;
+    /// Next fragment of the pending TLS records
+    pub fn next_fragment(&mut self) -> Result<Vec<u8>, TlsError> {
+        const MTU: usize = 1000;
+
+        let is_first = match self.sendbufferstate {
+            SendBufferState::NewPayload { .. } => true,
+            SendBufferState::MidPayload => false,
+        };
+
+        let mut result = vec![0; MTU];
+        let (offset, mut courser) = match self.sendbufferstate {
+            SendBufferState::NewPayload { total_length } => {
+                let len = (total_length) as u32;
+                result[1..=4].copy_from_slice(&len.to_be_bytes());
+                (5, &mut result[5..])
+            }
+            SendBufferState::MidPayload => (1, &mut result[1..]),
+        };
 
-            let is_first = match self.sendbufferstate {
-                SendBufferState::NewPayload { .. } => true,
-                SendBufferState::MidPayload => false,
-            };
+        match self.con.write_tls(&mut courser) {
+            Ok(n) => {
+                result.truncate(n + offset);
+            }
+            Err(e) => {
+                eprintln!("TLS Error {e}");
+                return Err(TlsError::GenericTlsError);
+            }
+        };
 
-            let mut result = vec![0; MTU];
-            let (offset, mut courser) = match self.sendbufferstate {
-                SendBufferState::NewPayload { total_length } => {
-                    let len = (total_length) as u32;
-                    result[1..=4].copy_from_slice(&len.to_be_bytes());
-                    (5, &mut result[5..])
-                }
-                SendBufferState::MidPayload => (1, &mut result[1..]),
-            };
+        let more_fragments = self.con.wants_write();
+        let header = Header {
+            length_included: is_first,
+            more_fragments,
+            start: false,
+            version: self.version,
+        };
 
-            match self.con.write_tls(&mut courser) {
-                Ok(n) => {
-                    result.truncate(n + offset);
-                }
-                Err(e) => {
-                    eprintln!("TLS Error {e}");
-                    return Err(TlsError::GenericTlsError);
-                }
-            };
+        self.sendbufferstate = SendBufferState::MidPayload;
 
-            let more_fragments = self.con.wants_write();
-            let header = Header {
-                length_included: is_first,
-                more_fragments,
-                start: false,
-            };
+        result[0] = header.write();
+        Ok(result)
+    }
 
-            self.sendbufferstate = SendBufferState::MidPayload;
+    /// Acknowledges a received fragment
+    pub fn ack_packet(&self) -> Vec<u8> {
+        vec![Header {
+            length_included: false,
+            more_fragments: false,
+            start: false,
+            version: self.version,
+        }
+        .write()]
+    }
 
-            result[0] = header.write();
-            Ok(EapCommonResult::Next(result))
-        } else {
-            let result = vec![Header {
-                length_included: false,
-                more_fragments: false,
-                start: false,
-            }
-            .write()];
+    /// Application data received through the tunnel
+    pub fn read_plaintext(&mut self) -> Vec<u8> {
+        let mut result = Vec::new();
+        let _ = self.con.reader().read_to_end(&mut result);
+        result
+    }
 
-            Ok(EapCommonResult::Next(result))
-        }
+    /// Sends application data through the tunnel, use [`CommonTLS::next_fragment`]
+    /// to send the resulting records.
+    pub fn write_plaintext(&mut self, data: &[u8]) -> Result<(), TlsError> {
+        self.con
+            .writer()
+            .write_all(data)
+            .map_err(|_| TlsError::GenericTlsError)?;
+        self.update_send_buffer()
+    }
+
+    /// TLS keying material exporter (RFC 5705)
+    pub fn export_keying_material(&self, output: &mut [u8], label: &[u8]) -> Result<(), TlsError> {
+        self.export_keying_material_with_context(output, label, &[])
+    }
+
+    /// TLS keying material exporter with a context value, e.g. the Type-Code
+    /// of RFC 9190 2.3
+    pub fn export_keying_material_with_context(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: &[u8],
+    ) -> Result<(), TlsError> {
+        self.con
+            .export_keying_material(output, label, Some(context))
+            .map_err(|_| TlsError::GenericTlsError)
     }
 }
 
@@ -190,6 +260,8 @@ https://www.rfc-editor.org/rfc/rfc5216
       S = EAP-TLS start
       R = Reserved
 
+Tunnel methods like TEAP use the last three bits as version.
+
 e.g.
 0xC0 = 1100 0000
 0xE0 = 1110 0000
@@ -198,16 +270,19 @@ e.g.
 const HEADER_FIELD_LEN: u8 = 0b1000_0000;
 const HEADER_FIELD_MORE_FRAGMENTS: u8 = 0b0100_0000;
 const HEADER_FIELD_START: u8 = 0b0010_0000;
+const HEADER_FIELD_VERSION: u8 = 0b0000_0111;
 
-struct Header {
-    length_included: bool,
-    more_fragments: bool,
-    start: bool,
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Header {
+    pub length_included: bool,
+    pub more_fragments: bool,
+    pub start: bool,
+    pub version: u8,
 }
 
 impl Header {
     const fn write(&self) -> u8 {
-        let mut result = 0;
+        let mut result = self.version & HEADER_FIELD_VERSION;
         if self.length_included {
             result |= HEADER_FIELD_LEN;
         }
@@ -224,11 +299,13 @@ impl Header {
         let length_included = (data & HEADER_FIELD_LEN) != 0;
         let more_fragments = (data & HEADER_FIELD_MORE_FRAGMENTS) != 0;
         let start = (data & HEADER_FIELD_START) != 0;
+        let version = data & HEADER_FIELD_VERSION;
 
         Header {
             length_included,
             more_fragments,
             start,
+            version,
         }
     }
 }
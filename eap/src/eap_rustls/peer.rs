@@ -5,7 +5,11 @@ use rustls::{Certificate, ClientConfig, ClientConnection, PrivateKey};
 
 use crate::{
     eap_rustls::{CommonTLS, EapCommonResult},
-    layers::mux::TupleElement,
+    layers::{
+        eap_layer::{Decision, MethodState},
+        mux::TupleElement,
+        MethodType, SecurityClaims,
+    },
     EapEnvironmentResponse,
 };
 
@@ -18,7 +22,7 @@ pub struct PeerTlsMethod {
 
 impl TupleElement for PeerTlsMethod {
     type Target = dyn PeerMethodLayer;
-    fn id(&self) -> u8 {
+    fn id(&self) -> MethodType {
         self.method_identifier()
     }
 
@@ -41,7 +45,7 @@ impl PeerTlsMethod {
         }
     }
 
-    fn create_common_tls(config: &TlsConfig) -> CommonTLS<ClientConnection> {
+    pub(crate) fn create_common_tls(config: &TlsConfig) -> CommonTLS<ClientConnection> {
         let server_cert = rustls_pemfile::read_all(&mut config.server_cert.as_ref())
             .unwrap()
             .into_iter()
@@ -101,8 +105,12 @@ impl Clone for PeerTlsMethod {
 }
 
 impl PeerMethodLayer for PeerTlsMethod {
-    fn method_identifier(&self) -> u8 {
-        METHOD_TLS
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_TLS)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_TLS
     }
 
     fn recv<'a>(
@@ -126,10 +134,19 @@ impl PeerMethodLayer for PeerTlsMethod {
         }
     }
 
-    fn can_succeed(&self) -> Option<bool> {
-        match &self.inner {
-            Some(inner) => Some(inner.finished),
-            None => Some(false),
+    fn method_state(&self) -> MethodState {
+        if self.inner.as_ref().is_some_and(|inner| inner.finished) {
+            MethodState::Done
+        } else {
+            MethodState::MayCont
+        }
+    }
+
+    fn decision(&self) -> Decision {
+        if self.inner.as_ref().is_some_and(|inner| inner.finished) {
+            Decision::CondSucc
+        } else {
+            Decision::Fail
         }
     }
 }
@@ -0,0 +1,175 @@
+//! Shared parts of the TLS tunnel methods: driving the TLS connection, the TLV
+//! format of phase 2 and running nested EAP layers.
+//!
+//! EAP-TEAP keeps the TLV format and type numbers of RFC 4851 4.2.
+
+use crate::{
+    layers::eap_layer::{PeerAuthLayer, PeerAuthLayerResult},
+    message::{Message, MessageCode},
+    util::ByteReader,
+    EapEnvironment,
+};
+
+use super::*;
+
+pub(crate) const TLV_MANDATORY: u16 = 0x8000;
+const TLV_TYPE_MASK: u16 = 0x3fff;
+
+pub(crate) const TLV_RESULT: u16 = 3;
+pub(crate) const TLV_NAK: u16 = 4;
+pub(crate) const TLV_ERROR: u16 = 5;
+#[allow(unused)]
+pub(crate) const TLV_VENDOR_SPECIFIC: u16 = 7;
+pub(crate) const TLV_EAP_PAYLOAD: u16 = 9;
+pub(crate) const TLV_INTERMEDIATE_RESULT: u16 = 10;
+pub(crate) const TLV_CRYPTO_BINDING: u16 = 12;
+
+pub(crate) const STATUS_SUCCESS: u16 = 1;
+pub(crate) const STATUS_FAILURE: u16 = 2;
+
+/// Error-TLV code for "Tunnel Compromise Error"
+pub(crate) const ERROR_TUNNEL_COMPROMISE: u32 = 2001;
+/// Error-TLV code for "Unexpected TLVs Exchanged"
+pub(crate) const ERROR_UNEXPECTED_TLVS: u32 = 2002;
+
+/// A received packet of a tunnel method
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TunnelInput {
+    /// More fragments follow, they have to be acknowledged
+    Fragment,
+    /// The handshake is ongoing
+    Handshake,
+    /// Decrypted phase 2 data, empty if the packet only acknowledged a fragment
+    /// or completed the handshake.
+    Data(Vec<u8>),
+}
+
+impl<C, T> CommonTLS<C>
+where
+    C: DerefMut<Target = ConnectionCommon<T>>,
+{
+    pub fn receive_tunnel(&mut self, msg: &[u8]) -> Result<TunnelInput, TlsError> {
+        let header = self.receive(msg)?;
+        if header.version != self.version {
+            return Err(TlsError::UnsupportedVersion);
+        }
+
+        if header.more_fragments {
+            Ok(TunnelInput::Fragment)
+        } else if self.con.is_handshaking() {
+            Ok(TunnelInput::Handshake)
+        } else {
+            Ok(TunnelInput::Data(self.read_plaintext()))
+        }
+    }
+
+    /// Sends `data` through the tunnel, returns the next packet. That is an
+    /// acknowledgement if there is nothing left to send.
+    pub fn send_tunnel(&mut self, data: &[u8]) -> Result<Vec<u8>, TlsError> {
+        if !data.is_empty() {
+            self.write_plaintext(data)?;
+        }
+
+        if self.con.wants_write() {
+            self.next_fragment()
+        } else {
+            Ok(self.ack_packet())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Tlv<'a> {
+    pub mandatory: bool,
+    pub tlv_type: u16,
+    pub value: &'a [u8],
+}
+
+pub(crate) fn parse_tlvs(data: &[u8]) -> Option<Vec<Tlv<'_>>> {
+    let mut reader = ByteReader::new(data);
+    let mut tlvs = Vec::new();
+
+    while !reader.is_empty() {
+        let header = reader.u16()?;
+        let value = reader.u16_prefixed()?;
+        tlvs.push(Tlv {
+            mandatory: header & TLV_MANDATORY != 0,
+            tlv_type: header & TLV_TYPE_MASK,
+            value,
+        });
+    }
+
+    Some(tlvs)
+}
+
+/// Value of a Result or Intermediate-Result TLV
+pub(crate) fn parse_status(value: &[u8]) -> Option<u16> {
+    ByteReader::new(value).u16()
+}
+
+pub(crate) fn write_tlv(out: &mut Vec<u8>, tlv_type: u16, value: &[u8]) {
+    out.extend_from_slice(&(TLV_MANDATORY | tlv_type).to_be_bytes());
+    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
+pub(crate) fn write_status(out: &mut Vec<u8>, tlv_type: u16, status: u16) {
+    write_tlv(out, tlv_type, &status.to_be_bytes());
+}
+
+pub(crate) fn write_error(out: &mut Vec<u8>, code: u32) {
+    write_tlv(out, TLV_ERROR, &code.to_be_bytes());
+}
+
+/// NAK-TLV for a TLV type of the IETF namespace
+pub(crate) fn write_nak(out: &mut Vec<u8>, tlv_type: u16) {
+    let mut value = [0u8; 6];
+    value[4..].copy_from_slice(&tlv_type.to_be_bytes());
+    write_tlv(out, TLV_NAK, &value);
+}
+
+/// Wraps an inner EAP packet into an EAP-Payload TLV
+pub(crate) fn write_eap_payload(out: &mut Vec<u8>, code: MessageCode, identifier: u8, body: &[u8]) {
+    let length = (body.len() + 4) as u16;
+    let mut packet = Vec::with_capacity(length as usize);
+    packet.extend_from_slice(&[code as u8, identifier]);
+    packet.extend_from_slice(&length.to_be_bytes());
+    packet.extend_from_slice(body);
+    write_tlv(out, TLV_EAP_PAYLOAD, &packet);
+}
+
+/// Output of a nested layer, copied so the environment can be reused for the
+/// outer response.
+pub(crate) enum InnerOutput {
+    Noop,
+    Send(Vec<u8>),
+    Finished,
+    Failed,
+}
+
+fn inner_output(result: PeerAuthLayerResult<'_>) -> (InnerOutput, &mut dyn EapEnvironment) {
+    match result {
+        PeerAuthLayerResult::Noop(env) => (InnerOutput::Noop, env),
+        PeerAuthLayerResult::Send(builder) => {
+            let data = builder.slice().to_vec();
+            (InnerOutput::Send(data), builder.abort())
+        }
+        PeerAuthLayerResult::Finished(env) => (InnerOutput::Finished, env),
+        PeerAuthLayerResult::Failed(env) => (InnerOutput::Failed, env),
+    }
+}
+
+/// Starts a nested layer or passes it the EAP packet of an EAP-Payload TLV
+pub(crate) fn run_inner<'a>(
+    layer: &mut dyn PeerAuthLayer,
+    packet: Option<&[u8]>,
+    env: &'a mut dyn EapEnvironment,
+) -> (InnerOutput, &'a mut dyn EapEnvironment) {
+    match packet {
+        None => inner_output(layer.start(env)),
+        Some(packet) => match Message::parse(packet) {
+            Ok(message) => inner_output(layer.recv(&message, env)),
+            Err(_) => (InnerOutput::Failed, env),
+        },
+    }
+}
@@ -0,0 +1,431 @@
+use crate::{
+    layers::{
+        auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta},
+        eap_layer::SessionKeys,
+        mux::TupleElement,
+        MethodType,
+    },
+    message::MessageCode,
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Start,
+    Challenge,
+    Reauthentication,
+    Done,
+}
+
+/// Server side of EAP-SIM.
+///
+/// Triplets come from the [`TripletProvider`], pseudonyms and re-authentication
+/// identities are kept in the [`IdentityStore`].
+pub struct AuthSimMethod<P, S> {
+    provider: P,
+    store: S,
+    pseudonyms: bool,
+    fast_reauth: bool,
+    state: State,
+    /// AT_*_ID_REQ of the last Start request
+    id_req: u8,
+    /// Identity of the last AT_IDENTITY, the keys are bound to it
+    identity: Identity,
+    permanent_identity: Identity,
+    nonce_mt: [u8; NONCE_LEN],
+    /// SRES of the challenge, the response is authenticated with them
+    sres: [u8; TRIPLETS * SRES_LEN],
+    keys: Option<Keys>,
+    next_pseudonym: Option<Identity>,
+    next_reauth_id: Option<Identity>,
+    /// Context of a re-authentication, with the counter that was sent
+    reauth: Option<ReauthContext>,
+    nonce_s: [u8; NONCE_LEN],
+    /// AT_MAC of the Re-authentication Request, part of the Session-ID
+    reauth_mac: [u8; MAC_LEN],
+    session_keys: Option<SessionKeys>,
+}
+
+impl<P, S> TupleElement for AuthSimMethod<P, S>
+where
+    P: TripletProvider + 'static,
+    S: IdentityStore + 'static,
+{
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<P, S> AuthSimMethod<P, S>
+where
+    P: TripletProvider,
+    S: IdentityStore,
+{
+    pub fn new(provider: P, store: S) -> Self {
+        Self {
+            provider,
+            store,
+            pseudonyms: false,
+            fast_reauth: false,
+            state: State::Start,
+            id_req: AT_PERMANENT_ID_REQ,
+            identity: Identity::new(),
+            permanent_identity: Identity::new(),
+            nonce_mt: [0; NONCE_LEN],
+            sres: [0; TRIPLETS * SRES_LEN],
+            keys: None,
+            next_pseudonym: None,
+            next_reauth_id: None,
+            reauth: None,
+            nonce_s: [0; NONCE_LEN],
+            reauth_mac: [0; MAC_LEN],
+            session_keys: None,
+        }
+    }
+
+    /// Sends a new pseudonym with every full authentication
+    pub fn with_pseudonyms(mut self) -> Self {
+        self.pseudonyms = true;
+        self
+    }
+
+    /// Sends a new re-authentication identity with every authentication
+    pub fn with_fast_reauth(mut self) -> Self {
+        self.fast_reauth = true;
+        self
+    }
+
+    fn start_request<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        let mut writer = Writer::new(SUBTYPE_START);
+        writer
+            .prefixed(AT_VERSION_LIST, &VERSION.to_be_bytes())
+            .reserved(self.id_req, &[]);
+
+        self.state = State::Start;
+        AuthMethodLayerResult::Send(env.respond().write(writer.as_slice()))
+    }
+
+    fn new_identity(&self, prefix: u8, env: &dyn EapEnvironment) -> Identity {
+        let mut random = [0u8; 8];
+        env.fill_random(&mut random);
+        temporary_identity(prefix, self.permanent_identity.as_ref(), &random)
+    }
+
+    fn recv_start<'a>(
+        &mut self,
+        attributes: &Attributes,
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let Some(identity) = attributes
+            .identity
+            .filter(|identity| !identity.is_empty() && identity.len() <= MAX_IDENTITY_LEN)
+        else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+        self.identity = Identity::from(identity);
+
+        let identifier = meta.message.identifier.wrapping_add(1);
+        let permanent_identity = match identity[0] {
+            prefix if prefix == PREFIXES.reauth => {
+                let context = self
+                    .store
+                    .load_reauth(identity)
+                    .filter(|_| self.fast_reauth && self.id_req == AT_ANY_ID_REQ);
+                if let Some(context) = context {
+                    return self.reauthentication_request(context, identifier, env);
+                }
+                None
+            }
+            prefix if prefix == PREFIXES.pseudonym => self
+                .store
+                .permanent_identity(identity)
+                .filter(|_| self.pseudonyms && self.id_req != AT_PERMANENT_ID_REQ),
+            prefix if prefix == PREFIXES.permanent => Some(self.identity.clone()),
+            _ => None,
+        };
+
+        let Some(permanent_identity) = permanent_identity else {
+            // Unknown identity, ask for one that is less private
+            self.id_req = match self.id_req {
+                AT_ANY_ID_REQ => AT_FULLAUTH_ID_REQ,
+                AT_FULLAUTH_ID_REQ => AT_PERMANENT_ID_REQ,
+                _ => return AuthMethodLayerResult::Failed(env),
+            };
+            return self.start_request(env);
+        };
+
+        // Full authentication needs the nonce and the version of the peer
+        let (Some(nonce_mt), Some(VERSION)) = (attributes.nonce_mt, attributes.selected_version)
+        else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+        self.nonce_mt = *nonce_mt;
+        self.permanent_identity = permanent_identity;
+        self.challenge_request(identifier, env)
+    }
+
+    fn challenge_request<'a>(
+        &mut self,
+        identifier: u8,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let imsi = &username(self.permanent_identity.as_ref())[1..];
+
+        let mut rands = [[0u8; RAND_LEN]; TRIPLETS];
+        let mut kcs = [[0u8; KC_LEN]; TRIPLETS];
+        for (i, rand) in rands.iter_mut().enumerate() {
+            env.fill_random(rand);
+            let Some(triplet) = self.provider.triplet(imsi, rand) else {
+                return AuthMethodLayerResult::Failed(env);
+            };
+            kcs[i] = triplet.kc;
+            self.sres[i * SRES_LEN..][..SRES_LEN].copy_from_slice(&triplet.sres);
+        }
+
+        let keys = keys(
+            self.identity.as_ref(),
+            &rands,
+            &kcs,
+            &self.nonce_mt,
+            &VERSION.to_be_bytes(),
+            VERSION,
+        );
+
+        let mut writer = Writer::new(SUBTYPE_CHALLENGE);
+        writer.attribute(AT_RAND, &[&[0, 0], &rands[0], &rands[1], &rands[2]]);
+
+        self.next_pseudonym = self
+            .pseudonyms
+            .then(|| self.new_identity(PREFIXES.pseudonym, env));
+        self.next_reauth_id = self
+            .fast_reauth
+            .then(|| self.new_identity(PREFIXES.reauth, env));
+
+        let mut plaintext = Writer::nested();
+        if let Some(pseudonym) = &self.next_pseudonym {
+            plaintext.prefixed(AT_NEXT_PSEUDONYM, pseudonym.as_ref());
+        }
+        if let Some(reauth_id) = &self.next_reauth_id {
+            plaintext.prefixed(AT_NEXT_REAUTH_ID, reauth_id.as_ref());
+        }
+        if !plaintext.as_slice().is_empty() {
+            let mut iv = [0u8; 16];
+            env.fill_random(&mut iv);
+            writer.encrypted(&keys.k_encr, &iv, plaintext);
+        }
+
+        // The request is authenticated together with NONCE_MT
+        writer.mac().sign(
+            MAC_ALGORITHM,
+            &keys.k_aut,
+            MessageCode::Request,
+            identifier,
+            METHOD_SIM,
+            &self.nonce_mt,
+        );
+
+        self.keys = Some(keys);
+        self.state = State::Challenge;
+        AuthMethodLayerResult::Send(env.respond().write(writer.as_slice()))
+    }
+
+    fn recv_challenge<'a>(
+        &mut self,
+        attributes: &Attributes,
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let Some(keys) = &self.keys else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+
+        // The response is authenticated together with the SRES values
+        if !MAC_ALGORITHM.verify(&keys.k_aut, &meta.message, attributes, &self.sres) {
+            return AuthMethodLayerResult::Failed(env);
+        }
+
+        let permanent_identity = self.permanent_identity.as_ref();
+        if let Some(pseudonym) = &self.next_pseudonym {
+            self.store
+                .store_pseudonym(pseudonym.as_ref(), permanent_identity);
+        }
+        if let Some(reauth_id) = &self.next_reauth_id {
+            let context = ReauthContext::new(reauth_id.as_ref(), permanent_identity, keys);
+            self.store.store_reauth(&context);
+        }
+
+        env.set_name(permanent_identity);
+        self.session_keys = Some(keys.session_keys.clone());
+        self.state = State::Done;
+        AuthMethodLayerResult::Finished(env)
+    }
+
+    fn reauthentication_request<'a>(
+        &mut self,
+        mut context: ReauthContext,
+        identifier: u8,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        context.counter = context.counter.wrapping_add(1);
+        self.permanent_identity = context.permanent_identity.clone();
+        env.fill_random(&mut self.nonce_s);
+
+        self.next_reauth_id = Some(self.new_identity(PREFIXES.reauth, env));
+        let mut plaintext = Writer::nested();
+        plaintext
+            .u16(AT_COUNTER, context.counter)
+            .reserved(AT_NONCE_S, &self.nonce_s)
+            .prefixed(
+                AT_NEXT_REAUTH_ID,
+                self.next_reauth_id.as_ref().unwrap().as_ref(),
+            );
+
+        let mut iv = [0u8; 16];
+        env.fill_random(&mut iv);
+
+        let mut writer = Writer::new(SUBTYPE_REAUTHENTICATION);
+        writer
+            .encrypted(&context.k_encr, &iv, plaintext)
+            .mac()
+            .sign(
+                MAC_ALGORITHM,
+                &context.k_aut,
+                MessageCode::Request,
+                identifier,
+                METHOD_SIM,
+                &[],
+            );
+
+        self.reauth_mac = writer.mac_value();
+        self.reauth = Some(context);
+        self.state = State::Reauthentication;
+        AuthMethodLayerResult::Send(env.respond().write(writer.as_slice()))
+    }
+
+    fn recv_reauthentication<'a>(
+        &mut self,
+        attributes: &Attributes,
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let Some(context) = self.reauth.take() else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+
+        // The response is authenticated together with NONCE_S
+        let authentic =
+            MAC_ALGORITHM.verify(&context.k_aut, &meta.message, attributes, &self.nonce_s);
+        let decrypted = Decrypted::new(&context.k_encr, attributes);
+        let Some(encrypted) = decrypted.as_ref().and_then(Decrypted::attributes) else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+        if !authentic || encrypted.counter != Some(context.counter) {
+            return AuthMethodLayerResult::Failed(env);
+        }
+
+        self.store.remove_reauth(context.identity());
+        if encrypted.counter_too_small {
+            // The peer saw the counter before, a full authentication needs a new
+            // NONCE_MT
+            self.id_req = AT_FULLAUTH_ID_REQ;
+            return self.start_request(env);
+        }
+
+        let session_keys = reauth_session_keys(
+            self.identity.as_ref(),
+            context.counter,
+            &self.nonce_s,
+            context.reauth_key[..MK_LEN].try_into().unwrap(),
+            reauth_session_id(METHOD_SIM, &self.nonce_s, &self.reauth_mac),
+        );
+
+        let context = ReauthContext {
+            identity: self.next_reauth_id.take().unwrap(),
+            ..context
+        };
+        self.store.store_reauth(&context);
+
+        env.set_name(context.permanent_identity.as_ref());
+        self.session_keys = Some(session_keys);
+        self.state = State::Done;
+        AuthMethodLayerResult::Finished(env)
+    }
+}
+
+impl<P, S> AuthMethodLayer for AuthSimMethod<P, S>
+where
+    P: TripletProvider,
+    S: IdentityStore,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_SIM)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_SIM
+    }
+
+    // AT_MAC covers the header of the next Request
+    fn binds_identifier(&self) -> bool {
+        true
+    }
+
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.id_req = if self.fast_reauth {
+            AT_ANY_ID_REQ
+        } else if self.pseudonyms {
+            AT_FULLAUTH_ID_REQ
+        } else {
+            AT_PERMANENT_ID_REQ
+        };
+        self.keys = None;
+        self.reauth = None;
+        self.session_keys = None;
+
+        self.start_request(env)
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let Some(attributes) = msg.get(3..).and_then(|data| Attributes::parse(data, 3)) else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+
+        match (self.state, msg[0]) {
+            (State::Start, SUBTYPE_START) => self.recv_start(&attributes, meta, env),
+            (State::Challenge, SUBTYPE_CHALLENGE) => self.recv_challenge(&attributes, meta, env),
+            (State::Reauthentication, SUBTYPE_REAUTHENTICATION) => {
+                self.recv_reauthentication(&attributes, meta, env)
+            }
+            // Also covers Client-Error
+            _ => {
+                self.state = State::Done;
+                AuthMethodLayerResult::Failed(env)
+            }
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        match self.state {
+            State::Done => self.session_keys.as_ref(),
+            _ => None,
+        }
+    }
+}
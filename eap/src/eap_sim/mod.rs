@@ -0,0 +1,217 @@
+//! EAP-SIM, see https://www.rfc-editor.org/rfc/rfc4186
+//!
+//! Like EAP-AKA, the server always starts with a Start request that asks for an
+//! identity. Triplets come from a [`TripletProvider`] on the server and a [`Sim`]
+//! on the peer. [`SoftSim`] and [`SoftTripletProvider`] run GSM-Milenage as
+//! A3/A8, the COMP128 variants found on real cards are not published.
+
+mod auth;
+mod peer;
+
+pub use auth::AuthSimMethod;
+pub use peer::PeerSimMethod;
+
+#[cfg(feature = "std")]
+pub use crate::sim_aka::MemoryIdentityStore;
+pub use crate::sim_aka::{
+    IdentityStore, PeerIdentities, PeerIdentityStore, ReauthContext, MAX_IDENTITY_LEN,
+};
+
+use sha1::{Digest, Sha1};
+
+use crate::{
+    eap_aka::Milenage,
+    layers::{eap_layer::SessionKeys, SecurityClaims},
+    sim_aka::*,
+    util::OwnedSlice,
+};
+
+const METHOD_SIM: u8 = 18;
+
+const SUBTYPE_START: u8 = 10;
+const SUBTYPE_CHALLENGE: u8 = 11;
+
+/// The only version of EAP-SIM
+const VERSION: u16 = 1;
+
+/// RANDs of a challenge sent by the server, peers accept two or three
+const TRIPLETS: usize = 3;
+const MIN_TRIPLETS: usize = 2;
+
+pub const RAND_LEN: usize = 16;
+pub const SRES_LEN: usize = 4;
+pub const KC_LEN: usize = 8;
+
+const MAC_ALGORITHM: MacAlgorithm = MacAlgorithm::HmacSha1;
+
+/// RFC 4186 4.2.1.6, the temporary identity prefixes are the ones commonly used
+const PREFIXES: IdentityPrefixes = IdentityPrefixes {
+    permanent: b'1',
+    pseudonym: b'3',
+    reauth: b'5',
+};
+
+/// Keys of a full authentication, RFC 4186 7:
+/// MK = SHA1(Identity|n*Kc|NONCE_MT|Version List|Selected Version). The
+/// Session-ID is 0x12 || n*RAND || NONCE_MT, RFC 5247 Appendix A.
+fn keys(
+    identity: &[u8],
+    rands: &[[u8; RAND_LEN]],
+    kcs: &[[u8; KC_LEN]],
+    nonce_mt: &[u8; NONCE_LEN],
+    version_list: &[u8],
+    selected_version: u16,
+) -> Keys {
+    let mut hash = Sha1::new();
+    hash.update(identity);
+    kcs.iter().for_each(|kc| hash.update(kc));
+    hash.update(nonce_mt);
+    hash.update(version_list);
+    hash.update(selected_version.to_be_bytes());
+    let session_id = SessionKeys::session_id(METHOD_SIM, &[rands.as_flattened(), nonce_mt]);
+    Keys::from_master_key(&hash.finalize().into(), session_id)
+}
+
+/// Permanent identity of a subscriber
+fn permanent_identity(imsi: &[u8]) -> Identity {
+    let mut buf = [0u8; MAX_IDENTITY_LEN];
+    buf[0] = PREFIXES.permanent;
+    buf[1..1 + imsi.len()].copy_from_slice(imsi);
+    Identity::from(&buf[..1 + imsi.len()])
+}
+
+/// Result of the GSM algorithms for one RAND
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Triplet {
+    pub sres: [u8; SRES_LEN],
+    pub kc: [u8; KC_LEN],
+}
+
+/// Source of GSM triplets on the server, usually the HLR of the home network
+pub trait TripletProvider {
+    /// Runs A3/A8 for the subscriber, `None` if the IMSI is unknown
+    fn triplet(&mut self, imsi: &[u8], rand: &[u8; RAND_LEN]) -> Option<Triplet>;
+}
+
+/// The SIM of the peer. It also keeps the temporary identities.
+pub trait Sim: PeerIdentityStore {
+    fn imsi(&self) -> &[u8];
+
+    /// Runs A3/A8 of the card
+    fn run_gsm_algorithm(&mut self, rand: &[u8; RAND_LEN]) -> Triplet;
+}
+
+/// A SIM in software running GSM-Milenage
+#[derive(Clone)]
+pub struct SoftSim {
+    imsi: OwnedSlice<16>,
+    milenage: Milenage,
+    identities: PeerIdentities,
+}
+
+impl SoftSim {
+    pub fn new(imsi: &[u8], milenage: Milenage) -> Self {
+        Self {
+            imsi: imsi.try_into().expect("IMSI has at most 15 digits"),
+            milenage,
+            identities: PeerIdentities::default(),
+        }
+    }
+}
+
+impl PeerIdentityStore for SoftSim {
+    fn load_identities(&mut self) -> PeerIdentities {
+        self.identities.clone()
+    }
+
+    fn store_identities(&mut self, identities: &PeerIdentities) {
+        self.identities = identities.clone();
+    }
+}
+
+impl Sim for SoftSim {
+    fn imsi(&self) -> &[u8] {
+        self.imsi.as_ref()
+    }
+
+    fn run_gsm_algorithm(&mut self, rand: &[u8; RAND_LEN]) -> Triplet {
+        let (sres, kc) = self.milenage.gsm(rand);
+        Triplet { sres, kc }
+    }
+}
+
+/// Triplets computed with GSM-Milenage, subscribers are looked up by IMSI
+#[cfg(feature = "std")]
+#[derive(Clone, Default)]
+pub struct SoftTripletProvider {
+    subscribers: std::collections::HashMap<Vec<u8>, Milenage>,
+}
+
+#[cfg(feature = "std")]
+impl SoftTripletProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_subscriber(mut self, imsi: &[u8], milenage: Milenage) -> Self {
+        self.subscribers.insert(imsi.to_vec(), milenage);
+        self
+    }
+}
+
+#[cfg(feature = "std")]
+impl TripletProvider for SoftTripletProvider {
+    fn triplet(&mut self, imsi: &[u8], rand: &[u8; RAND_LEN]) -> Option<Triplet> {
+        let (sres, kc) = self.subscribers.get(imsi)?.gsm(rand);
+        Some(Triplet { sres, kc })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex_to_vec;
+
+    #[test]
+    fn rfc4186_keys() {
+        // RFC 4186 Appendix A
+        let rands = [
+            core::array::from_fn(|i| 0x10 + i as u8),
+            core::array::from_fn(|i| 0x20 + i as u8),
+            core::array::from_fn(|i| 0x30 + i as u8),
+        ];
+        let kcs = [
+            hex_to_vec("a0 a1 a2 a3 a4 a5 a6 a7").try_into().unwrap(),
+            hex_to_vec("b0 b1 b2 b3 b4 b5 b6 b7").try_into().unwrap(),
+            hex_to_vec("c0 c1 c2 c3 c4 c5 c6 c7").try_into().unwrap(),
+        ];
+        let nonce_mt = hex_to_vec("01 23 45 67 89 ab cd ef fe dc ba 98 76 54 32 10")
+            .try_into()
+            .unwrap();
+
+        let keys = keys(
+            b"1244070100000001@eapsim.foo",
+            &rands,
+            &kcs,
+            &nonce_mt,
+            &[0, 1],
+            VERSION,
+        );
+        assert_eq!(
+            keys.reauth_key[..MK_LEN].to_vec(),
+            hex_to_vec("e5 76 d5 ca 33 2e 99 30 01 8b f1 ba ee 27 63 c7 95 b3 c7 12")
+        );
+        assert_eq!(
+            keys.k_encr.to_vec(),
+            hex_to_vec("53 6e 5e bc 44 65 58 2a a6 a8 ec 99 86 eb b6 20")
+        );
+        assert_eq!(
+            keys.k_aut[..16].to_vec(),
+            hex_to_vec("25 af 19 42 ef cb f4 bc 72 b3 94 34 21 f2 a9 74")
+        );
+        assert_eq!(
+            keys.session_keys.session_id.as_ref(),
+            [&[METHOD_SIM], rands.as_flattened(), &nonce_mt].concat()
+        );
+    }
+}
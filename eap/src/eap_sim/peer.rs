@@ -0,0 +1,383 @@
+use crate::{
+    layers::{
+        eap_layer::{Decision, MethodState, SessionKeys},
+        mux::TupleElement,
+        peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta},
+        MethodType,
+    },
+    message::MessageCode,
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+/// Rank of the identity requests, each one may only be followed by a stricter one
+fn id_req_rank(id_req: u8) -> u8 {
+    match id_req {
+        AT_ANY_ID_REQ => 0,
+        AT_FULLAUTH_ID_REQ => 1,
+        _ => 2,
+    }
+}
+
+/// Peer side of EAP-SIM.
+///
+/// Temporary identities sent by the server are kept in the [`Sim`] and used
+/// whenever the server allows it.
+pub struct PeerSimMethod<S> {
+    sim: S,
+    /// Loaded from the SIM on the first message of a conversation
+    identities: Option<PeerIdentities>,
+    id_req: Option<u8>,
+    /// Identity of the last AT_IDENTITY, the keys are bound to it
+    identity: Identity,
+    /// Sent with the last Start response, absent when re-authenticating
+    nonce_mt: Option<[u8; NONCE_LEN]>,
+    /// AT_VERSION_LIST of the last Start request
+    version_list: OwnedSlice<32>,
+    session_keys: Option<SessionKeys>,
+}
+
+impl<S: Sim + 'static> TupleElement for PeerSimMethod<S> {
+    type Target = dyn PeerMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<S: Sim> PeerSimMethod<S> {
+    pub fn new(sim: S) -> Self {
+        Self {
+            sim,
+            identities: None,
+            id_req: None,
+            identity: Identity::new(),
+            nonce_mt: None,
+            version_list: OwnedSlice::new(),
+            session_keys: None,
+        }
+    }
+
+    fn identities(&mut self) -> &mut PeerIdentities {
+        let sim = &mut self.sim;
+        self.identities.get_or_insert_with(|| sim.load_identities())
+    }
+
+    fn store_identities(&mut self) {
+        let identities = self.identities().clone();
+        self.sim.store_identities(&identities);
+    }
+
+    fn permanent_identity(&self) -> Identity {
+        permanent_identity(self.sim.imsi())
+    }
+
+    fn respond<'a>(
+        &self,
+        writer: &Writer,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        PeerMethodLayerResult::Send(env.respond().write(writer.as_slice()))
+    }
+
+    fn client_error<'a>(
+        &mut self,
+        code: u16,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        self.session_keys = None;
+        let mut writer = Writer::new(SUBTYPE_CLIENT_ERROR);
+        writer.u16(AT_CLIENT_ERROR_CODE, code);
+        self.respond(&writer, env)
+    }
+
+    fn recv_start<'a>(
+        &mut self,
+        attributes: &Attributes,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let Some(version_list) = attributes.version_list else {
+            return self.client_error(CLIENT_ERROR_UNABLE_TO_PROCESS, env);
+        };
+        let supported = version_list
+            .chunks(2)
+            .any(|version| version == VERSION.to_be_bytes());
+        let (true, Ok(version_list)) = (supported, OwnedSlice::try_from(version_list)) else {
+            return self.client_error(CLIENT_ERROR_UNSUPPORTED_VERSION, env);
+        };
+        self.version_list = version_list;
+
+        let mut writer = Writer::new(SUBTYPE_START);
+        if let Some(id_req) = attributes.id_req {
+            if self
+                .id_req
+                .is_some_and(|last| id_req_rank(id_req) <= id_req_rank(last))
+            {
+                return self.client_error(CLIENT_ERROR_UNABLE_TO_PROCESS, env);
+            }
+            self.id_req = Some(id_req);
+
+            let permanent_identity = self.permanent_identity();
+            let identities = self.identities();
+            let reauth_id = identities
+                .reauth
+                .as_ref()
+                .map(|context| context.identity.clone());
+            let pseudonym = identities.pseudonym.clone();
+            self.identity = match id_req {
+                AT_ANY_ID_REQ => reauth_id.or(pseudonym),
+                AT_FULLAUTH_ID_REQ => pseudonym,
+                _ => None,
+            }
+            .unwrap_or(permanent_identity);
+
+            writer.prefixed(AT_IDENTITY, self.identity.as_ref());
+        }
+
+        let reauth = self
+            .identities()
+            .reauth
+            .as_ref()
+            .map(|context| context.identity.clone());
+        if reauth.is_some() && reauth == Some(self.identity.clone()) {
+            // Re-authentication does not use the nonce
+            self.nonce_mt = None;
+        } else {
+            let mut nonce_mt = [0u8; NONCE_LEN];
+            env.fill_random(&mut nonce_mt);
+            writer
+                .reserved(AT_NONCE_MT, &nonce_mt)
+                .u16(AT_SELECTED_VERSION, VERSION);
+            self.nonce_mt = Some(nonce_mt);
+        }
+
+        self.respond(&writer, env)
+    }
+
+    fn recv_challenge<'a>(
+        &mut self,
+        attributes: &Attributes,
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let (Some(nonce_mt), Some(rand)) = (self.nonce_mt, attributes.rand) else {
+            return self.client_error(CLIENT_ERROR_UNABLE_TO_PROCESS, env);
+        };
+
+        let count = rand.len() / RAND_LEN;
+        if !rand.len().is_multiple_of(RAND_LEN) || !(MIN_TRIPLETS..=TRIPLETS).contains(&count) {
+            return self.client_error(CLIENT_ERROR_INSUFFICIENT_CHALLENGES, env);
+        }
+        let mut rands = [[0u8; RAND_LEN]; TRIPLETS];
+        for (i, chunk) in rand.chunks(RAND_LEN).enumerate() {
+            rands[i].copy_from_slice(chunk);
+            if rands[..i].contains(&rands[i]) {
+                return self.client_error(CLIENT_ERROR_RANDS_NOT_FRESH, env);
+            }
+        }
+
+        let mut kcs = [[0u8; KC_LEN]; TRIPLETS];
+        let mut sres = [0u8; TRIPLETS * SRES_LEN];
+        for (i, rand) in rands[..count].iter().enumerate() {
+            let triplet = self.sim.run_gsm_algorithm(rand);
+            kcs[i] = triplet.kc;
+            sres[i * SRES_LEN..][..SRES_LEN].copy_from_slice(&triplet.sres);
+        }
+
+        if self.identity.as_ref().is_empty() {
+            self.identity = self.permanent_identity();
+        }
+        let keys = keys(
+            self.identity.as_ref(),
+            &rands[..count],
+            &kcs[..count],
+            &nonce_mt,
+            self.version_list.as_ref(),
+            VERSION,
+        );
+
+        // The request is authenticated together with NONCE_MT
+        if !MAC_ALGORITHM.verify(&keys.k_aut, &meta.message, attributes, &nonce_mt) {
+            return self.client_error(CLIENT_ERROR_UNABLE_TO_PROCESS, env);
+        }
+
+        if attributes.encr_data.is_some() {
+            let decrypted = Decrypted::new(&keys.k_encr, attributes);
+            let Some(encrypted) = decrypted.as_ref().and_then(Decrypted::attributes) else {
+                return self.client_error(CLIENT_ERROR_UNABLE_TO_PROCESS, env);
+            };
+
+            let permanent_identity = self.permanent_identity();
+            let identities = self.identities();
+            if let Some(pseudonym) = encrypted.next_pseudonym {
+                identities.pseudonym = Some(Identity::try_from(pseudonym).unwrap_or_default())
+                    .filter(|pseudonym| !pseudonym.as_ref().is_empty());
+            }
+            identities.reauth = encrypted.next_reauth_id.and_then(|reauth_id| {
+                (!reauth_id.is_empty() && reauth_id.len() <= MAX_IDENTITY_LEN)
+                    .then(|| ReauthContext::new(reauth_id, permanent_identity.as_ref(), &keys))
+            });
+        } else {
+            self.identities().reauth = None;
+        }
+        self.store_identities();
+
+        // The response is authenticated together with the SRES values
+        let mut writer = Writer::new(SUBTYPE_CHALLENGE);
+        writer.mac().sign(
+            MAC_ALGORITHM,
+            &keys.k_aut,
+            MessageCode::Response,
+            meta.message.identifier,
+            METHOD_SIM,
+            &sres[..count * SRES_LEN],
+        );
+
+        self.session_keys = Some(keys.session_keys);
+        self.respond(&writer, env)
+    }
+
+    fn recv_reauthentication<'a>(
+        &mut self,
+        attributes: &Attributes,
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        // Only possible if the re-authentication identity was sent
+        let identity = self.identity.clone();
+        let Some(context) = self
+            .identities()
+            .reauth
+            .clone()
+            .filter(|context| context.identity == identity)
+        else {
+            return self.client_error(CLIENT_ERROR_UNABLE_TO_PROCESS, env);
+        };
+
+        if !MAC_ALGORITHM.verify(&context.k_aut, &meta.message, attributes, &[]) {
+            return self.client_error(CLIENT_ERROR_UNABLE_TO_PROCESS, env);
+        }
+        let Some(mac) = attributes.mac_value(&meta.message) else {
+            return self.client_error(CLIENT_ERROR_UNABLE_TO_PROCESS, env);
+        };
+        let decrypted = Decrypted::new(&context.k_encr, attributes);
+        let Some(encrypted) = decrypted.as_ref().and_then(Decrypted::attributes) else {
+            return self.client_error(CLIENT_ERROR_UNABLE_TO_PROCESS, env);
+        };
+        let (Some(counter), Some(nonce_s)) = (encrypted.counter, encrypted.nonce_s) else {
+            return self.client_error(CLIENT_ERROR_UNABLE_TO_PROCESS, env);
+        };
+
+        let mut plaintext = Writer::nested();
+        plaintext.u16(AT_COUNTER, counter);
+
+        if counter <= context.counter {
+            // Replayed request, the server falls back to full authentication
+            plaintext.reserved(AT_COUNTER_TOO_SMALL, &[]);
+            self.identities().reauth = None;
+            self.session_keys = None;
+        } else {
+            self.session_keys = Some(reauth_session_keys(
+                identity.as_ref(),
+                counter,
+                nonce_s,
+                context.reauth_key[..MK_LEN].try_into().unwrap(),
+                reauth_session_id(METHOD_SIM, nonce_s, &mac),
+            ));
+            self.identities().reauth = encrypted
+                .next_reauth_id
+                .filter(|reauth_id| !reauth_id.is_empty() && reauth_id.len() <= MAX_IDENTITY_LEN)
+                .map(|reauth_id| ReauthContext {
+                    identity: Identity::from(reauth_id),
+                    counter,
+                    ..context.clone()
+                });
+        }
+        self.store_identities();
+
+        let mut iv = [0u8; 16];
+        env.fill_random(&mut iv);
+
+        let mut writer = Writer::new(SUBTYPE_REAUTHENTICATION);
+        writer
+            .encrypted(&context.k_encr, &iv, plaintext)
+            .mac()
+            .sign(
+                MAC_ALGORITHM,
+                &context.k_aut,
+                MessageCode::Response,
+                meta.message.identifier,
+                METHOD_SIM,
+                nonce_s,
+            );
+
+        self.respond(&writer, env)
+    }
+}
+
+impl<S: Sim> PeerMethodLayer for PeerSimMethod<S> {
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_SIM)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_SIM
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let Some(attributes) = msg.get(3..).and_then(|data| Attributes::parse(data, 3)) else {
+            return self.client_error(CLIENT_ERROR_UNABLE_TO_PROCESS, env);
+        };
+
+        match msg[0] {
+            SUBTYPE_START => self.recv_start(&attributes, env),
+            SUBTYPE_CHALLENGE => self.recv_challenge(&attributes, meta, env),
+            SUBTYPE_REAUTHENTICATION => self.recv_reauthentication(&attributes, meta, env),
+            // Notifications are not supported
+            _ => self.client_error(CLIENT_ERROR_UNABLE_TO_PROCESS, env),
+        }
+    }
+
+    fn method_state(&self) -> MethodState {
+        if self.session_keys.is_some() {
+            MethodState::Done
+        } else {
+            MethodState::MayCont
+        }
+    }
+
+    fn decision(&self) -> Decision {
+        if self.session_keys.is_some() {
+            Decision::CondSucc
+        } else {
+            Decision::Fail
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.session_keys.as_ref()
+    }
+
+    fn reset(&mut self) {
+        self.identities = None;
+        self.id_req = None;
+        self.identity = Identity::new();
+        self.nonce_mt = None;
+        self.version_list = OwnedSlice::new();
+        self.session_keys = None;
+    }
+}
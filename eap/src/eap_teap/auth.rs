@@ -0,0 +1,390 @@
+use dummycert::TlsConfig;
+use rustls::ServerConnection;
+
+use crate::{
+    eap_rustls::{tunnel::TunnelInput, AuthTlsMethod, CommonTLS},
+    layers::{
+        auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta},
+        eap_layer::{PeerAuthLayer, SessionKeys},
+        mux::TupleElement,
+        MethodType,
+    },
+    message::MessageCode,
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+type PasswordVerifier = Box<dyn FnMut(&[u8], &[u8]) -> bool>;
+type CertificateIssuer = Box<dyn FnMut(&[u8]) -> Option<Vec<u8>>>;
+
+/// Authentication performed in phase 2
+enum Authentication {
+    Eap(Box<dyn PeerAuthLayer>),
+    BasicPassword(PasswordVerifier),
+}
+
+struct Step {
+    identity_type: Option<IdentityType>,
+    authentication: Authentication,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Handshake,
+    Method,
+    CryptoBinding,
+    Result,
+    Failing,
+}
+
+/// Server side of EAP-TEAP.
+///
+/// The configured inner authentications are run one after another inside the
+/// tunnel, e.g. a machine authentication followed by a user authentication. Each one
+/// is concluded with an Intermediate-Result and a Crypto-Binding TLV. Once all of
+/// them succeeded, a PKCS#10 request of the peer is passed to the certificate issuer.
+/// Without an inner authentication, the authentication fails right away.
+pub struct AuthTeapMethod {
+    config: TlsConfig,
+    tls: Option<CommonTLS<ServerConnection>>,
+    steps: Vec<Step>,
+    step: usize,
+    state: State,
+    inner_identifier: u8,
+    keys: Option<KeySchedule>,
+    nonce: [u8; NONCE_LEN],
+    csr: Option<Vec<u8>>,
+    issuer: Option<CertificateIssuer>,
+    session_keys: Option<SessionKeys>,
+}
+
+impl TupleElement for AuthTeapMethod {
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl AuthTeapMethod {
+    pub fn new(config: TlsConfig) -> Self {
+        Self {
+            config,
+            tls: None,
+            steps: Vec::new(),
+            step: 0,
+            state: State::Handshake,
+            inner_identifier: 0,
+            keys: None,
+            nonce: [0; NONCE_LEN],
+            csr: None,
+            issuer: None,
+            session_keys: None,
+        }
+    }
+
+    /// Runs an inner EAP method, e.g. an `AuthLayer`
+    pub fn with_inner_method<L>(mut self, identity_type: Option<IdentityType>, layer: L) -> Self
+    where
+        L: PeerAuthLayer + 'static,
+    {
+        assert!(layer.is_auth());
+        self.steps.push(Step {
+            identity_type,
+            authentication: Authentication::Eap(Box::new(layer)),
+        });
+        self
+    }
+
+    /// Requests username and password, `verify` is called with both
+    pub fn with_basic_password<F>(mut self, identity_type: Option<IdentityType>, verify: F) -> Self
+    where
+        F: FnMut(&[u8], &[u8]) -> bool + 'static,
+    {
+        self.steps.push(Step {
+            identity_type,
+            authentication: Authentication::BasicPassword(Box::new(verify)),
+        });
+        self
+    }
+
+    /// Issues certificates for PKCS#10 requests, returns the PKCS#7 encoded chain
+    pub fn with_certificate_issuer<F>(mut self, issuer: F) -> Self
+    where
+        F: FnMut(&[u8]) -> Option<Vec<u8>> + 'static,
+    {
+        self.issuer = Some(Box::new(issuer));
+        self
+    }
+
+    fn reset(&mut self) {
+        self.tls = None;
+        self.step = 0;
+        self.state = State::Handshake;
+        self.keys = None;
+        self.csr = None;
+        self.session_keys = None;
+    }
+
+    /// Starts the current step, returns `false` if its inner method failed immediately
+    fn start_step(&mut self, out: &mut Vec<u8>, env: &mut dyn EapEnvironment) -> bool {
+        let step = &mut self.steps[self.step];
+
+        if let Some(identity_type) = step.identity_type {
+            write_status(out, TLV_IDENTITY_TYPE, identity_type.value());
+        }
+
+        match &mut step.authentication {
+            Authentication::Eap(layer) => match run_inner(layer.as_mut(), None, env).0 {
+                InnerOutput::Send(data) => {
+                    self.inner_identifier = self.inner_identifier.wrapping_add(1);
+                    write_eap_payload(out, MessageCode::Request, self.inner_identifier, &data);
+                }
+                _ => return false,
+            },
+            Authentication::BasicPassword(_) => {
+                write_tlv(out, TLV_BASIC_PASSWORD_AUTH_REQ, &[]);
+            }
+        }
+
+        self.state = State::Method;
+        true
+    }
+
+    /// Concludes the current step with an Intermediate-Result and a Crypto-Binding
+    fn finish_step(&mut self, out: &mut Vec<u8>, env: &mut dyn EapEnvironment) {
+        let inner_keys = match &self.steps[self.step].authentication {
+            Authentication::Eap(layer) => layer.session_keys().cloned(),
+            Authentication::BasicPassword(_) => None,
+        };
+
+        let keys = self.keys.as_mut().unwrap();
+        keys.next_method(inner_keys.as_ref());
+
+        env.fill_random(&mut self.nonce);
+        self.nonce[NONCE_LEN - 1] &= 0xfe;
+
+        write_status(out, TLV_INTERMEDIATE_RESULT, STATUS_SUCCESS);
+        keys.write_crypto_binding(out, CRYPTO_BINDING_REQUEST, &self.nonce);
+
+        self.state = State::CryptoBinding;
+    }
+
+    fn fail(&mut self, out: &mut Vec<u8>, error: Option<u32>) {
+        out.clear();
+        if let Some(code) = error {
+            write_error(out, code);
+        }
+        if self.state == State::Method {
+            write_status(out, TLV_INTERMEDIATE_RESULT, STATUS_FAILURE);
+        }
+        write_status(out, TLV_RESULT, STATUS_FAILURE);
+
+        self.state = State::Failing;
+    }
+
+    /// Handles a phase 2 message, returns the TLVs to send
+    fn process_tlvs(&mut self, data: &[u8], env: &mut dyn EapEnvironment) -> Option<Vec<u8>> {
+        if matches!(self.state, State::Handshake | State::Failing) {
+            return None;
+        }
+
+        let mut out = Vec::new();
+
+        let tlvs = match Tlvs::parse(data) {
+            Some(tlvs) if tlvs.unsupported.is_none() && !tlvs.nak => tlvs,
+            _ => {
+                self.fail(&mut out, Some(ERROR_UNEXPECTED_TLVS));
+                return Some(out);
+            }
+        };
+
+        if tlvs.result == Some(STATUS_FAILURE)
+            || tlvs.intermediate_result == Some(STATUS_FAILURE)
+            || tlvs.error.is_some()
+        {
+            self.fail(&mut out, None);
+            return Some(out);
+        }
+
+        match self.state {
+            State::Handshake | State::Failing => unreachable!(),
+            State::Method => {
+                let step = &mut self.steps[self.step];
+                let succeeded = match &mut step.authentication {
+                    Authentication::Eap(layer) => {
+                        let Some(packet) = tlvs.eap_payload else {
+                            self.fail(&mut out, Some(ERROR_UNEXPECTED_TLVS));
+                            return Some(out);
+                        };
+
+                        match run_inner(layer.as_mut(), Some(packet), env).0 {
+                            InnerOutput::Send(data) => {
+                                self.inner_identifier = self.inner_identifier.wrapping_add(1);
+                                write_eap_payload(
+                                    &mut out,
+                                    MessageCode::Request,
+                                    self.inner_identifier,
+                                    &data,
+                                );
+                                return Some(out);
+                            }
+                            InnerOutput::Finished => true,
+                            InnerOutput::Noop | InnerOutput::Failed => false,
+                        }
+                    }
+                    Authentication::BasicPassword(verify) => tlvs
+                        .basic_password_resp
+                        .and_then(parse_basic_password)
+                        .is_some_and(|(username, password)| {
+                            if verify(username, password) {
+                                env.set_name(username);
+                                true
+                            } else {
+                                false
+                            }
+                        }),
+                };
+
+                if succeeded {
+                    self.finish_step(&mut out, env);
+                } else {
+                    self.fail(&mut out, None);
+                }
+            }
+            State::CryptoBinding => {
+                let keys = self.keys.as_ref().unwrap();
+                let mut expected_nonce = self.nonce;
+                expected_nonce[NONCE_LEN - 1] |= 1;
+
+                let verified = tlvs.intermediate_result == Some(STATUS_SUCCESS)
+                    && tlvs.crypto_binding.and_then(|binding| {
+                        keys.verify_crypto_binding(binding, CRYPTO_BINDING_RESPONSE)
+                    }) == Some(expected_nonce);
+
+                if !verified {
+                    self.fail(&mut out, Some(ERROR_TUNNEL_COMPROMISE));
+                    return Some(out);
+                }
+
+                if let Some(csr) = tlvs.pkcs10 {
+                    self.csr = Some(csr.to_vec());
+                }
+
+                self.step += 1;
+                if self.step < self.steps.len() {
+                    if !self.start_step(&mut out, env) {
+                        self.fail(&mut out, None);
+                    }
+                    return Some(out);
+                }
+
+                if let (Some(csr), Some(issuer)) = (&self.csr, &mut self.issuer) {
+                    match issuer(csr) {
+                        Some(certificate) => write_tlv(&mut out, TLV_PKCS7, &certificate),
+                        None => {
+                            self.fail(&mut out, None);
+                            return Some(out);
+                        }
+                    }
+                }
+
+                write_status(&mut out, TLV_RESULT, STATUS_SUCCESS);
+                self.state = State::Result;
+            }
+            State::Result => {
+                if tlvs.result != Some(STATUS_SUCCESS) {
+                    self.fail(&mut out, Some(ERROR_UNEXPECTED_TLVS));
+                    return Some(out);
+                }
+
+                self.session_keys = self.keys.as_ref().map(KeySchedule::session_keys);
+                return None;
+            }
+        }
+
+        Some(out)
+    }
+}
+
+impl AuthMethodLayer for AuthTeapMethod {
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_TEAP)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::TEAP
+    }
+
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        if self.steps.is_empty() {
+            return AuthMethodLayerResult::Failed(env);
+        }
+
+        self.reset();
+        let tls = self.tls.insert(
+            AuthTlsMethod::create_common_tls(&self.config, false).with_version(TEAP_VERSION),
+        );
+
+        AuthMethodLayerResult::Send(env.respond().write(&tls.start_packet()))
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let Some(tls) = self.tls.as_mut() else {
+            return AuthMethodLayerResult::Failed(env);
+        };
+
+        let out = match tls.receive_tunnel(msg) {
+            Ok(TunnelInput::Fragment) => {
+                return AuthMethodLayerResult::Send(env.respond().write(&tls.ack_packet()));
+            }
+            Ok(TunnelInput::Handshake) => None,
+            Ok(TunnelInput::Data(data)) if self.state == State::Handshake => {
+                // The session key seed is taken from the completed handshake
+                let keys = KeySchedule::from_tls(tls);
+                if keys.is_none() || !data.is_empty() {
+                    return AuthMethodLayerResult::Failed(env);
+                }
+                self.keys = keys;
+
+                let mut out = Vec::new();
+                if !self.start_step(&mut out, env) {
+                    self.fail(&mut out, None);
+                }
+                Some(out)
+            }
+            // Acknowledgement of a fragment
+            Ok(TunnelInput::Data(data)) if data.is_empty() => None,
+            Ok(TunnelInput::Data(data)) => self.process_tlvs(&data, env),
+            Err(_) => return AuthMethodLayerResult::Failed(env),
+        };
+
+        let tls = self.tls.as_mut().unwrap();
+        match out {
+            None if self.session_keys.is_some() => AuthMethodLayerResult::Finished(env),
+            None if !tls.con.wants_write() => AuthMethodLayerResult::Failed(env),
+            out => match tls.send_tunnel(&out.unwrap_or_default()) {
+                Ok(data) => AuthMethodLayerResult::Send(env.respond().write(&data)),
+                Err(_) => AuthMethodLayerResult::Failed(env),
+            },
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.session_keys.as_ref()
+    }
+}
@@ -0,0 +1,382 @@
+//! EAP-TEAP, see https://www.rfc-editor.org/rfc/rfc7170
+//!
+//! Phase 1 establishes a TLS tunnel, phase 2 exchanges TLVs inside of it. Inner
+//! EAP methods are run by a nested [`crate::layers::AuthLayer`] or
+//! [`crate::layers::PeerLayer`], their EAP packets are carried in EAP-Payload TLVs.
+//! The keys are derived as updated by RFC 9427 for TLS 1.3.
+
+mod auth;
+mod peer;
+
+pub use auth::AuthTeapMethod;
+pub use peer::PeerTeapMethod;
+
+use std::ops::DerefMut;
+
+use hmac::{Hmac, Mac};
+use rustls::ConnectionCommon;
+use sha2::Sha256;
+
+use crate::{
+    eap_rustls::{tunnel::*, CommonTLS},
+    layers::{eap_layer::SessionKeys, SecurityClaims},
+    util::{constant_time_eq, ByteReader},
+};
+
+const METHOD_TEAP: u8 = 55;
+
+const TEAP_VERSION: u8 = 1;
+
+#[allow(unused)]
+const TLV_AUTHORITY_ID: u16 = 1;
+const TLV_IDENTITY_TYPE: u16 = 2;
+#[allow(unused)]
+const TLV_REQUEST_ACTION: u16 = 8;
+const TLV_BASIC_PASSWORD_AUTH_REQ: u16 = 13;
+const TLV_BASIC_PASSWORD_AUTH_RESP: u16 = 14;
+const TLV_PKCS7: u16 = 15;
+const TLV_PKCS10: u16 = 16;
+
+const SESSION_KEY_SEED_LABEL: &[u8] = b"EXPORTER: teap session key seed";
+const METHOD_ID_LABEL: &[u8] = b"EXPORTER_EAP_TLS_Method-Id";
+const IMCK_LABEL: &[u8] = b"Inner Methods Compound Keys";
+const MSK_LABEL: &[u8] = b"Session Key Generating Function";
+const EMSK_LABEL: &[u8] = b"Extended Session Key Generating Function";
+
+const S_IMCK_LEN: usize = 40;
+const METHOD_ID_LEN: usize = 64;
+const CMK_LEN: usize = 20;
+const IMSK_LEN: usize = 32;
+const NONCE_LEN: usize = 32;
+const COMPOUND_MAC_LEN: usize = 20;
+const CRYPTO_BINDING_LEN: usize = 4 + NONCE_LEN + 2 * COMPOUND_MAC_LEN;
+
+/// Only the MSK Compound MAC is present
+const CRYPTO_BINDING_FLAGS_MSK: u8 = 2;
+const CRYPTO_BINDING_REQUEST: u8 = 0;
+const CRYPTO_BINDING_RESPONSE: u8 = 1;
+
+/// Identity of the entity authenticated by an inner method (Identity-Type TLV)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentityType {
+    User,
+    Machine,
+}
+
+impl IdentityType {
+    const fn value(self) -> u16 {
+        match self {
+            IdentityType::User => 1,
+            IdentityType::Machine => 2,
+        }
+    }
+
+    fn from_value(value: u16) -> Option<Self> {
+        match value {
+            1 => Some(IdentityType::User),
+            2 => Some(IdentityType::Machine),
+            _ => None,
+        }
+    }
+}
+
+/// TLVs of a phase 2 message, at most one of each kind is accepted
+#[derive(Default)]
+struct Tlvs<'a> {
+    identity_type: Option<IdentityType>,
+    result: Option<u16>,
+    intermediate_result: Option<u16>,
+    nak: bool,
+    error: Option<u32>,
+    eap_payload: Option<&'a [u8]>,
+    crypto_binding: Option<&'a [u8]>,
+    basic_password_req: Option<&'a [u8]>,
+    basic_password_resp: Option<&'a [u8]>,
+    pkcs7: Option<&'a [u8]>,
+    pkcs10: Option<&'a [u8]>,
+    /// First mandatory TLV that is not supported
+    unsupported: Option<u16>,
+}
+
+impl<'a> Tlvs<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let mut result = Tlvs::default();
+
+        for tlv in parse_tlvs(data)? {
+            let status = || parse_status(tlv.value);
+
+            let duplicate = match tlv.tlv_type {
+                TLV_IDENTITY_TYPE => result
+                    .identity_type
+                    .replace(IdentityType::from_value(status()?)?)
+                    .is_some(),
+                TLV_RESULT => result.result.replace(status()?).is_some(),
+                TLV_INTERMEDIATE_RESULT => result.intermediate_result.replace(status()?).is_some(),
+                TLV_NAK => core::mem::replace(&mut result.nak, true),
+                TLV_ERROR => result
+                    .error
+                    .replace(ByteReader::new(tlv.value).u32()?)
+                    .is_some(),
+                TLV_EAP_PAYLOAD => result.eap_payload.replace(tlv.value).is_some(),
+                TLV_CRYPTO_BINDING => result.crypto_binding.replace(tlv.value).is_some(),
+                TLV_BASIC_PASSWORD_AUTH_REQ => {
+                    result.basic_password_req.replace(tlv.value).is_some()
+                }
+                TLV_BASIC_PASSWORD_AUTH_RESP => {
+                    result.basic_password_resp.replace(tlv.value).is_some()
+                }
+                TLV_PKCS7 => result.pkcs7.replace(tlv.value).is_some(),
+                TLV_PKCS10 => result.pkcs10.replace(tlv.value).is_some(),
+                other => {
+                    if tlv.mandatory && result.unsupported.is_none() {
+                        result.unsupported = Some(other);
+                    }
+                    false
+                }
+            };
+
+            if duplicate {
+                return None;
+            }
+        }
+
+        Some(result)
+    }
+}
+
+/// Basic-Password-Auth-Resp value: Userlen, Username, Passlen, Password
+fn write_basic_password(out: &mut Vec<u8>, username: &[u8], password: &[u8]) {
+    let mut value = Vec::with_capacity(2 + username.len() + password.len());
+    value.push(username.len() as u8);
+    value.extend_from_slice(username);
+    value.push(password.len() as u8);
+    value.extend_from_slice(password);
+    write_tlv(out, TLV_BASIC_PASSWORD_AUTH_RESP, &value);
+}
+
+fn parse_basic_password(value: &[u8]) -> Option<(&[u8], &[u8])> {
+    let mut reader = ByteReader::new(value);
+    let username_len = reader.u8()?;
+    let username = reader.take(username_len as usize)?;
+    let password_len = reader.u8()?;
+    let password = reader.take(password_len as usize)?;
+    reader.is_empty().then_some((username, password))
+}
+
+/// TLS-PRF of TLS 1.2 with SHA-256 (P_SHA256), RFC 5246 5.
+fn prf(secret: &[u8], label: &[u8], seed: &[u8], out: &mut [u8]) {
+    let hmac = |parts: &[&[u8]]| {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret).unwrap();
+        for part in parts {
+            mac.update(part);
+        }
+        mac.finalize().into_bytes()
+    };
+
+    let mut a = hmac(&[label, seed]);
+    for chunk in out.chunks_mut(32) {
+        let block = hmac(&[&a, label, seed]);
+        chunk.copy_from_slice(&block[..chunk.len()]);
+        a = hmac(&[&a]);
+    }
+}
+
+/// Inner method key chaining, RFC 7170 5.2
+#[derive(Clone)]
+struct KeySchedule {
+    s_imck: [u8; S_IMCK_LEN],
+    cmk: [u8; CMK_LEN],
+    method_id: [u8; METHOD_ID_LEN],
+}
+
+impl KeySchedule {
+    fn new(session_key_seed: [u8; S_IMCK_LEN], method_id: [u8; METHOD_ID_LEN]) -> Self {
+        Self {
+            s_imck: session_key_seed,
+            cmk: [0; CMK_LEN],
+            method_id,
+        }
+    }
+
+    /// Session key seed and Method-Id of a completed handshake, RFC 9427 2.1
+    /// and 2.4
+    fn from_tls<C, T>(tls: &CommonTLS<C>) -> Option<Self>
+    where
+        C: DerefMut<Target = ConnectionCommon<T>>,
+    {
+        let mut seed = [0u8; S_IMCK_LEN];
+        tls.export_keying_material(&mut seed, SESSION_KEY_SEED_LABEL)
+            .ok()?;
+        let mut method_id = [0u8; METHOD_ID_LEN];
+        tls.export_keying_material_with_context(&mut method_id, METHOD_ID_LABEL, &[METHOD_TEAP])
+            .ok()?;
+        Some(Self::new(seed, method_id))
+    }
+
+    /// Mixes in the keys of an inner method, methods without keys use a zero IMSK.
+    fn next_method(&mut self, inner_keys: Option<&SessionKeys>) {
+        let mut imsk = [0u8; IMSK_LEN];
+        if let Some(keys) = inner_keys {
+            imsk.copy_from_slice(&keys.msk[..IMSK_LEN]);
+        }
+
+        let mut imck = [0u8; S_IMCK_LEN + CMK_LEN];
+        prf(&self.s_imck, IMCK_LABEL, &imsk, &mut imck);
+
+        self.s_imck.copy_from_slice(&imck[..S_IMCK_LEN]);
+        self.cmk.copy_from_slice(&imck[S_IMCK_LEN..]);
+    }
+
+    /// The Session-ID is the Type and the Method-Id, RFC 9427 2.4
+    fn session_keys(&self) -> SessionKeys {
+        let mut keys = SessionKeys {
+            msk: [0; 64],
+            emsk: [0; 64],
+            session_id: SessionKeys::session_id(METHOD_TEAP, &[&self.method_id]),
+        };
+        prf(&self.s_imck, MSK_LABEL, &[], &mut keys.msk);
+        prf(&self.s_imck, EMSK_LABEL, &[], &mut keys.emsk);
+        keys
+    }
+
+    /// MSK Compound MAC over the Crypto-Binding TLV with zeroed MACs
+    fn compound_mac(&self, binding: &[u8; CRYPTO_BINDING_LEN]) -> [u8; COMPOUND_MAC_LEN] {
+        let mut zeroed = *binding;
+        zeroed[4 + NONCE_LEN..].fill(0);
+
+        let length = (CRYPTO_BINDING_LEN as u16).to_be_bytes();
+        let header = (TLV_MANDATORY | TLV_CRYPTO_BINDING).to_be_bytes();
+
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.cmk).unwrap();
+        mac.update(&header);
+        mac.update(&length);
+        mac.update(&zeroed);
+        mac.update(&[METHOD_TEAP]);
+
+        let mut result = [0u8; COMPOUND_MAC_LEN];
+        result.copy_from_slice(&mac.finalize().into_bytes()[..COMPOUND_MAC_LEN]);
+        result
+    }
+
+    fn write_crypto_binding(&self, out: &mut Vec<u8>, sub_type: u8, nonce: &[u8; NONCE_LEN]) {
+        let mut binding = [0u8; CRYPTO_BINDING_LEN];
+        binding[1] = TEAP_VERSION;
+        binding[2] = TEAP_VERSION;
+        binding[3] = (CRYPTO_BINDING_FLAGS_MSK << 4) | sub_type;
+        binding[4..4 + NONCE_LEN].copy_from_slice(nonce);
+
+        let mac = self.compound_mac(&binding);
+        binding[4 + NONCE_LEN + COMPOUND_MAC_LEN..].copy_from_slice(&mac);
+
+        write_tlv(out, TLV_CRYPTO_BINDING, &binding);
+    }
+
+    /// Checks a received Crypto-Binding TLV, returns its nonce.
+    fn verify_crypto_binding(&self, value: &[u8], sub_type: u8) -> Option<[u8; NONCE_LEN]> {
+        let binding: [u8; CRYPTO_BINDING_LEN] = value.try_into().ok()?;
+
+        let flags = binding[3] >> 4;
+        if binding[1] != TEAP_VERSION
+            || binding[2] != TEAP_VERSION
+            || binding[3] & 0x0f != sub_type
+            || flags & CRYPTO_BINDING_FLAGS_MSK == 0
+        {
+            return None;
+        }
+
+        let mac = self.compound_mac(&binding);
+        let received = &binding[4 + NONCE_LEN + COMPOUND_MAC_LEN..];
+        if !constant_time_eq(&mac, received) {
+            return None;
+        }
+
+        binding[4..4 + NONCE_LEN].try_into().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{message::MessageCode, util::OwnedSlice};
+
+    #[test]
+    fn tlv_roundtrip() {
+        let mut out = Vec::new();
+        write_status(&mut out, TLV_INTERMEDIATE_RESULT, STATUS_SUCCESS);
+        write_basic_password(&mut out, b"hans", b"1234");
+        write_eap_payload(&mut out, MessageCode::Request, 7, b"\x01");
+        // Unknown optional TLV is ignored
+        out.extend_from_slice(&[0x00, 0x42, 0x00, 0x01, 0xff]);
+
+        let tlvs = Tlvs::parse(&out).unwrap();
+        assert_eq!(tlvs.intermediate_result, Some(STATUS_SUCCESS));
+        assert_eq!(
+            tlvs.basic_password_resp.and_then(parse_basic_password),
+            Some((&b"hans"[..], &b"1234"[..]))
+        );
+        assert_eq!(tlvs.eap_payload, Some(&[1, 7, 0, 5, 1][..]));
+        assert_eq!(tlvs.unsupported, None);
+
+        // Unknown mandatory TLV is reported
+        out.extend_from_slice(&[0x80, 0x43, 0x00, 0x00]);
+        assert_eq!(Tlvs::parse(&out).unwrap().unsupported, Some(0x43));
+
+        // Duplicates and truncated TLVs are rejected
+        let mut duplicate = Vec::new();
+        write_status(&mut duplicate, TLV_RESULT, STATUS_SUCCESS);
+        write_status(&mut duplicate, TLV_RESULT, STATUS_FAILURE);
+        assert!(Tlvs::parse(&duplicate).is_none());
+        assert!(Tlvs::parse(&out[..out.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn prf_sha256() {
+        // Test vector for the TLS 1.2 PRF with SHA-256
+        let secret = crate::util::hex_to_vec("9b be 43 6b a9 40 f0 17 b1 76 52 84 9a 71 db 35");
+        let seed = crate::util::hex_to_vec("a0 ba 9f 93 6c da 31 18 27 a6 f7 96 ff d5 19 8c");
+        let mut out = [0u8; 100];
+        prf(&secret, b"test label", &seed, &mut out);
+
+        let expected = crate::util::hex_to_vec(
+            "e3 f2 29 ba 72 7b e1 7b 8d 12 26 20 55 7c d4 53 c2 aa b2 1d 07 c3 d4 95 32 9b 52 d4
+             e6 1e db 5a 6b 30 17 91 e9 0d 35 c9 c9 a4 6b 4e 14 ba f9 af 0f a0 22 f7 07 7d ef 17
+             ab fd 37 97 c0 56 4b ab 4f bc 91 66 6e 9d ef 9b 97 fc e3 4f 79 67 89 ba a4 80 82 d1
+             22 ee 42 c5 a7 2e 5a 51 10 ff f7 01 87 34 7b 66",
+        );
+        assert_eq!(out[..], expected[..]);
+    }
+
+    #[test]
+    fn crypto_binding() {
+        let mut keys = KeySchedule::new([1; S_IMCK_LEN], [3; METHOD_ID_LEN]);
+        keys.next_method(None);
+
+        let nonce = [7u8; NONCE_LEN];
+        let mut out = Vec::new();
+        keys.write_crypto_binding(&mut out, CRYPTO_BINDING_REQUEST, &nonce);
+
+        let tlvs = Tlvs::parse(&out).unwrap();
+        let binding = tlvs.crypto_binding.unwrap();
+        assert_eq!(
+            keys.verify_crypto_binding(binding, CRYPTO_BINDING_REQUEST),
+            Some(nonce)
+        );
+        assert_eq!(
+            keys.verify_crypto_binding(binding, CRYPTO_BINDING_RESPONSE),
+            None
+        );
+
+        // Different inner keys result in a different CMK
+        let mut other = KeySchedule::new([1; S_IMCK_LEN], [3; METHOD_ID_LEN]);
+        other.next_method(Some(&SessionKeys {
+            msk: [2; 64],
+            emsk: [0; 64],
+            session_id: OwnedSlice::new(),
+        }));
+        assert_eq!(
+            other.verify_crypto_binding(binding, CRYPTO_BINDING_REQUEST),
+            None
+        );
+        assert!(other.session_keys() != keys.session_keys());
+    }
+}
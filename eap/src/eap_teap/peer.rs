@@ -0,0 +1,339 @@
+use dummycert::TlsConfig;
+use rustls::ClientConnection;
+
+use crate::{
+    eap_rustls::{tunnel::TunnelInput, CommonTLS, PeerTlsMethod},
+    layers::{
+        eap_layer::{Decision, MethodState, PeerAuthLayer, SessionKeys},
+        mux::TupleElement,
+        peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta},
+        MethodType,
+    },
+    message::{Message, MessageCode},
+    EapEnvironment, EapEnvironmentResponse,
+};
+
+use super::*;
+
+type CertificateHandler = Box<dyn FnMut(&[u8])>;
+
+struct Credentials {
+    username: Vec<u8>,
+    password: Vec<u8>,
+}
+
+/// Peer side of EAP-TEAP.
+///
+/// The inner EAP method is chosen by the Identity-Type TLV of the server, user
+/// authentication is assumed if it is missing. A configured PKCS#10 request is sent
+/// along with the first Crypto-Binding response.
+pub struct PeerTeapMethod {
+    config: TlsConfig,
+    tls: Option<CommonTLS<ClientConnection>>,
+    user: Option<Box<dyn PeerAuthLayer>>,
+    machine: Option<Box<dyn PeerAuthLayer>>,
+    credentials: Option<Credentials>,
+    identity_type: IdentityType,
+    /// Inner authentication of the current step has reached success
+    inner_succeeded: bool,
+    /// The current step uses Basic-Password-Auth instead of an inner EAP method
+    password_step: bool,
+    keys: Option<KeySchedule>,
+    crypto_binding_verified: bool,
+    csr: Option<Vec<u8>>,
+    csr_sent: bool,
+    on_certificate: Option<CertificateHandler>,
+    session_keys: Option<SessionKeys>,
+}
+
+impl TupleElement for PeerTeapMethod {
+    type Target = dyn PeerMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl PeerTeapMethod {
+    pub fn new(config: TlsConfig) -> Self {
+        Self {
+            config,
+            tls: None,
+            user: None,
+            machine: None,
+            credentials: None,
+            identity_type: IdentityType::User,
+            inner_succeeded: false,
+            password_step: false,
+            keys: None,
+            crypto_binding_verified: false,
+            csr: None,
+            csr_sent: false,
+            on_certificate: None,
+            session_keys: None,
+        }
+    }
+
+    /// Inner EAP method for the given identity type, e.g. a `PeerLayer`
+    pub fn with_inner_method<L>(mut self, identity_type: IdentityType, layer: L) -> Self
+    where
+        L: PeerAuthLayer + 'static,
+    {
+        assert!(layer.is_peer());
+        match identity_type {
+            IdentityType::User => self.user = Some(Box::new(layer)),
+            IdentityType::Machine => self.machine = Some(Box::new(layer)),
+        }
+        self
+    }
+
+    /// Credentials for Basic-Password-Auth requests
+    pub fn with_basic_password(mut self, username: &[u8], password: &[u8]) -> Self {
+        assert!(username.len() <= u8::MAX as usize && password.len() <= u8::MAX as usize);
+        self.credentials = Some(Credentials {
+            username: username.to_vec(),
+            password: password.to_vec(),
+        });
+        self
+    }
+
+    /// Requests a certificate, `on_certificate` receives the PKCS#7 response
+    pub fn with_certificate_request<F>(mut self, csr: &[u8], on_certificate: F) -> Self
+    where
+        F: FnMut(&[u8]) + 'static,
+    {
+        self.csr = Some(csr.to_vec());
+        self.on_certificate = Some(Box::new(on_certificate));
+        self
+    }
+
+    fn inner_layer(&mut self) -> Option<&mut Box<dyn PeerAuthLayer>> {
+        match self.identity_type {
+            IdentityType::User => self.user.as_mut(),
+            IdentityType::Machine => self.machine.as_mut(),
+        }
+    }
+
+    fn fail(&mut self, out: &mut Vec<u8>, error: Option<u32>) {
+        out.clear();
+        if let Some(code) = error {
+            write_error(out, code);
+        }
+        write_status(out, TLV_RESULT, STATUS_FAILURE);
+        self.session_keys = None;
+    }
+
+    /// Handles a phase 2 message, returns the TLVs to send
+    fn process_tlvs(&mut self, data: &[u8], env: &mut dyn EapEnvironment) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        let Some(tlvs) = Tlvs::parse(data) else {
+            self.fail(&mut out, Some(ERROR_UNEXPECTED_TLVS));
+            return out;
+        };
+
+        if let Some(tlv_type) = tlvs.unsupported {
+            write_nak(&mut out, tlv_type);
+            return out;
+        }
+
+        if tlvs.result == Some(STATUS_FAILURE) || tlvs.intermediate_result == Some(STATUS_FAILURE) {
+            self.fail(&mut out, None);
+            return out;
+        }
+
+        if let Some(identity_type) = tlvs.identity_type {
+            self.identity_type = identity_type;
+        }
+
+        if let Some(packet) = tlvs.eap_payload {
+            let Ok(request) = Message::parse(packet) else {
+                self.fail(&mut out, Some(ERROR_UNEXPECTED_TLVS));
+                return out;
+            };
+
+            let Some(layer) = self.inner_layer() else {
+                write_nak(&mut out, TLV_EAP_PAYLOAD);
+                return out;
+            };
+
+            match request.code {
+                MessageCode::Request => match run_inner(layer.as_mut(), Some(packet), env).0 {
+                    InnerOutput::Send(data) => write_eap_payload(
+                        &mut out,
+                        MessageCode::Response,
+                        request.identifier,
+                        &data,
+                    ),
+                    InnerOutput::Noop | InnerOutput::Finished | InnerOutput::Failed => {
+                        self.fail(&mut out, None)
+                    }
+                },
+                // Only EAP-Success and EAP-Failure conclude inner methods
+                _ => self.fail(&mut out, Some(ERROR_UNEXPECTED_TLVS)),
+            }
+            return out;
+        }
+
+        if tlvs.basic_password_req.is_some() {
+            match &self.credentials {
+                Some(credentials) => {
+                    write_basic_password(&mut out, &credentials.username, &credentials.password);
+                    self.password_step = true;
+                    self.inner_succeeded = true;
+                }
+                None => write_nak(&mut out, TLV_BASIC_PASSWORD_AUTH_REQ),
+            }
+            return out;
+        }
+
+        if let Some(binding) = tlvs.crypto_binding {
+            // The server decides on success, the inner method has to agree
+            let inner_keys = if self.password_step {
+                None
+            } else if let Some(layer) = self.inner_layer() {
+                let succeeded = layer.decision() != Decision::Fail;
+                let keys = layer.session_keys().cloned();
+                self.inner_succeeded = succeeded;
+                keys
+            } else {
+                None
+            };
+
+            let keys = self.keys.as_mut().unwrap();
+            let mut candidate = keys.clone();
+            candidate.next_method(inner_keys.as_ref());
+
+            let nonce = candidate.verify_crypto_binding(binding, CRYPTO_BINDING_REQUEST);
+            let Some(mut nonce) = nonce.filter(|_| {
+                self.inner_succeeded && tlvs.intermediate_result == Some(STATUS_SUCCESS)
+            }) else {
+                self.fail(&mut out, Some(ERROR_TUNNEL_COMPROMISE));
+                return out;
+            };
+
+            *keys = candidate;
+            nonce[NONCE_LEN - 1] |= 1;
+
+            write_status(&mut out, TLV_INTERMEDIATE_RESULT, STATUS_SUCCESS);
+            keys.write_crypto_binding(&mut out, CRYPTO_BINDING_RESPONSE, &nonce);
+            self.crypto_binding_verified = true;
+            self.inner_succeeded = false;
+            self.password_step = false;
+
+            if let (Some(csr), false) = (&self.csr, self.csr_sent) {
+                write_tlv(&mut out, TLV_PKCS10, csr);
+                self.csr_sent = true;
+            }
+        }
+
+        if tlvs.result == Some(STATUS_SUCCESS) {
+            if !self.crypto_binding_verified {
+                self.fail(&mut out, Some(ERROR_UNEXPECTED_TLVS));
+                return out;
+            }
+
+            if let (Some(certificate), Some(on_certificate)) =
+                (tlvs.pkcs7, &mut self.on_certificate)
+            {
+                on_certificate(certificate);
+            }
+
+            write_status(&mut out, TLV_RESULT, STATUS_SUCCESS);
+            self.session_keys = self.keys.as_ref().map(KeySchedule::session_keys);
+        }
+
+        if out.is_empty() {
+            self.fail(&mut out, Some(ERROR_UNEXPECTED_TLVS));
+        }
+
+        out
+    }
+}
+
+impl PeerMethodLayer for PeerTeapMethod {
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_TEAP)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::TEAP
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let tls = self.tls.get_or_insert_with(|| {
+            PeerTlsMethod::create_common_tls(&self.config).with_version(TEAP_VERSION)
+        });
+
+        let out = match tls.receive_tunnel(msg) {
+            Ok(TunnelInput::Fragment) => {
+                return PeerMethodLayerResult::Send(env.respond().write(&tls.ack_packet()));
+            }
+            Ok(TunnelInput::Handshake) => Vec::new(),
+            Ok(TunnelInput::Data(data)) => {
+                if self.keys.is_none() {
+                    let Some(keys) = KeySchedule::from_tls(tls) else {
+                        return PeerMethodLayerResult::Failed(env);
+                    };
+                    self.keys = Some(keys);
+                }
+
+                if data.is_empty() {
+                    data
+                } else {
+                    self.process_tlvs(&data, env)
+                }
+            }
+            Err(_) => return PeerMethodLayerResult::Failed(env),
+        };
+
+        match self.tls.as_mut().unwrap().send_tunnel(&out) {
+            Ok(data) => PeerMethodLayerResult::Send(env.respond().write(&data)),
+            Err(_) => PeerMethodLayerResult::Failed(env),
+        }
+    }
+
+    fn method_state(&self) -> MethodState {
+        if self.session_keys.is_some() {
+            MethodState::Done
+        } else {
+            MethodState::MayCont
+        }
+    }
+
+    fn decision(&self) -> Decision {
+        if self.session_keys.is_some() {
+            Decision::UncondSucc
+        } else {
+            Decision::Fail
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.session_keys.as_ref()
+    }
+
+    fn reset(&mut self) {
+        self.tls = None;
+        self.identity_type = IdentityType::User;
+        self.inner_succeeded = false;
+        self.password_step = false;
+        self.keys = None;
+        self.crypto_binding_verified = false;
+        self.csr_sent = false;
+        self.session_keys = None;
+    }
+}
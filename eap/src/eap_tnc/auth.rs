@@ -0,0 +1,181 @@
+use crate::layers::{
+    auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta},
+    eap_layer::SessionKeys,
+    mux::TupleElement,
+    MethodType,
+};
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Start,
+    WaitBatch,
+    /// The RESULT batch was sent, waiting for the CLOSE batch of the peer
+    WaitClose,
+    /// A CLOSE batch was sent, waiting for the answer of the peer
+    Failing,
+    Done,
+}
+
+/// Server side of PT-EAP, the PB-TNC server.
+///
+/// `validator` assesses the PA messages of the peer. The method succeeds if
+/// it recommends [`AccessRecommendation::Allow`] or
+/// [`AccessRecommendation::Isolate`], the validator passes the recommendation
+/// on to the authenticator.
+#[derive(Clone)]
+pub struct AuthTncMethod<V> {
+    validator: V,
+    state: State,
+    message_id: u32,
+    recommendation: Option<AccessRecommendation>,
+}
+
+impl<V> TupleElement for AuthTncMethod<V>
+where
+    V: PostureValidator + 'static,
+{
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<V> AuthTncMethod<V>
+where
+    V: PostureValidator,
+{
+    pub fn new(validator: V) -> Self {
+        Self {
+            validator,
+            state: State::Start,
+            message_id: 0,
+            recommendation: None,
+        }
+    }
+
+    /// Access recommendation of the last assessment
+    pub fn recommendation(&self) -> Option<AccessRecommendation> {
+        self.recommendation
+    }
+
+    fn next_message_id(&mut self) -> u32 {
+        self.message_id = self.message_id.wrapping_add(1);
+        self.message_id
+    }
+
+    /// Ends the assessment with a CLOSE batch
+    fn close<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.state = State::Failing;
+        self.recommendation = None;
+        AuthMethodLayerResult::Send(Batch::new(BATCH_CLOSE, true).send(env))
+    }
+
+    fn failed<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.state = State::Failing;
+        AuthMethodLayerResult::Failed(env)
+    }
+
+    /// CDATA, answered with the requests of the validator or its result
+    fn recv_data<'a>(
+        &mut self,
+        batch: &ReceivedBatch,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let subtype = self.validator.subtype();
+        let mut out = Batch::new(BATCH_SDATA, true);
+        for message in batch.pa_messages(subtype) {
+            let Some((collector_id, _, attributes)) = message else {
+                return self.close(env);
+            };
+            let message_id = self.next_message_id();
+            out.pa_message(subtype, collector_id, POSTURE_ID, message_id, |out| {
+                self.validator.receive_message(attributes, out)
+            });
+        }
+
+        let recommendation = match self.validator.recommendation() {
+            None if out.len > BATCH_HEADER_LEN => {
+                return AuthMethodLayerResult::Send(out.send(env));
+            }
+            recommendation => recommendation.unwrap_or(AccessRecommendation::NoAccess),
+        };
+        let [high, low] = recommendation.to_u16().to_be_bytes();
+        let mut result = Batch::new(BATCH_RESULT, true);
+        result
+            .message(
+                MESSAGE_ASSESSMENT_RESULT,
+                &recommendation.assessment_result().to_be_bytes(),
+            )
+            .message(MESSAGE_ACCESS_RECOMMENDATION, &[0, 0, high, low]);
+
+        self.recommendation = Some(recommendation);
+        self.state = State::WaitClose;
+        AuthMethodLayerResult::Send(result.send(env))
+    }
+}
+
+impl<V> AuthMethodLayer for AuthTncMethod<V>
+where
+    V: PostureValidator,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_PT_EAP)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::PT_EAP
+    }
+
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.validator.begin_handshake();
+        self.recommendation = None;
+        self.state = State::WaitBatch;
+        AuthMethodLayerResult::Send(env.respond().write(&[FLAG_START | PT_EAP_VERSION]))
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let batch = ReceivedBatch::parse(msg, false);
+        match self.state {
+            State::WaitBatch => match batch {
+                Some(batch) if batch.batch_type == BATCH_CDATA && !batch.has_error() => {
+                    self.recv_data(&batch, env)
+                }
+                // The peer gave up
+                Some(batch) if batch.batch_type == BATCH_CLOSE => self.failed(env),
+                _ => self.close(env),
+            },
+            State::WaitClose => match batch {
+                Some(batch) if batch.batch_type == BATCH_CLOSE => {
+                    if self.recommendation == Some(AccessRecommendation::NoAccess) {
+                        return self.failed(env);
+                    }
+                    self.state = State::Done;
+                    AuthMethodLayerResult::Finished(env)
+                }
+                _ => self.close(env),
+            },
+            // The peer answered our CLOSE batch
+            _ => self.failed(env),
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        None
+    }
+}
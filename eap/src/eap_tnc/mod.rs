@@ -0,0 +1,344 @@
+//! PT-EAP, see https://www.rfc-editor.org/rfc/rfc7171
+//!
+//! Posture assessment after authentication, on its own or as inner method of
+//! a tunnel. PT-EAP carries PB-TNC batches (RFC 5793) between the peer, the
+//! PB-TNC client, and the server. Their PB-PA messages carry PA-TNC attributes
+//! (RFC 5792) between a [`PostureCollector`] of the peer and a
+//! [`PostureValidator`] of the server, which ends the assessment with an
+//! [`AccessRecommendation`].
+//!
+//! Batches are limited to [`MAX_BATCH_LEN`] bytes, fragmentation is not
+//! supported.
+
+mod auth;
+mod pa_tnc;
+mod peer;
+
+pub use auth::AuthTncMethod;
+pub use pa_tnc::{
+    AccessRecommendation, Attribute, AttributeWriter, Attributes, PaSubtype, PostureCollector,
+    PostureValidator,
+};
+pub use peer::PeerTncMethod;
+
+use crate::{
+    layers::SecurityClaims, util::ByteReader, EapEnvironment, EapEnvironmentResponse,
+    MessageBuilder,
+};
+
+const METHOD_PT_EAP: u8 = 54;
+
+/// PT-EAP flags and version, RFC 7171 3.1
+const FLAG_LENGTH: u8 = 0x80;
+const FLAG_MORE_FRAGMENTS: u8 = 0x40;
+const FLAG_START: u8 = 0x20;
+const VERSION_MASK: u8 = 0x07;
+const PT_EAP_VERSION: u8 = 1;
+
+const PB_TNC_VERSION: u8 = 2;
+/// Direction flag of a batch sent by the server
+const FLAG_SERVER: u8 = 0x80;
+const BATCH_HEADER_LEN: usize = 8;
+
+/// PB-TNC batch types, RFC 5793 4.1
+const BATCH_CDATA: u8 = 1;
+const BATCH_SDATA: u8 = 2;
+const BATCH_RESULT: u8 = 3;
+const BATCH_CLOSE: u8 = 6;
+
+/// PB-TNC message types, RFC 5793 4.3
+const MESSAGE_PA: u32 = 1;
+const MESSAGE_ASSESSMENT_RESULT: u32 = 2;
+const MESSAGE_ACCESS_RECOMMENDATION: u32 = 3;
+const MESSAGE_ERROR: u32 = 5;
+const MESSAGE_HEADER_LEN: usize = 12;
+const FLAG_NOSKIP: u8 = 0x80;
+const PA_HEADER_LEN: usize = 12;
+
+/// PB-Access-Recommendation values, RFC 5793 4.6
+const ACCESS_ALLOWED: u16 = 1;
+const ACCESS_NO_ACCESS: u16 = 2;
+const ACCESS_QUARANTINED: u16 = 3;
+
+/// Identifier of the single collector or validator of each side
+const POSTURE_ID: u16 = 1;
+/// Posture validator identifier of messages to any validator
+const ANY_VALIDATOR: u16 = 0xffff;
+
+/// A batch and the PT-EAP header fit into the default response buffer
+pub const MAX_BATCH_LEN: usize = 1000;
+
+impl AccessRecommendation {
+    fn to_u16(self) -> u16 {
+        match self {
+            AccessRecommendation::Allow => ACCESS_ALLOWED,
+            AccessRecommendation::Isolate => ACCESS_QUARANTINED,
+            AccessRecommendation::NoAccess => ACCESS_NO_ACCESS,
+        }
+    }
+
+    fn from_u16(value: u16) -> Option<Self> {
+        match value {
+            ACCESS_ALLOWED => Some(AccessRecommendation::Allow),
+            ACCESS_QUARANTINED => Some(AccessRecommendation::Isolate),
+            ACCESS_NO_ACCESS => Some(AccessRecommendation::NoAccess),
+            _ => None,
+        }
+    }
+
+    /// PB-Assessment-Result: compliant, minor or major non-compliance
+    fn assessment_result(self) -> u32 {
+        match self {
+            AccessRecommendation::Allow => 0,
+            AccessRecommendation::Isolate => 1,
+            AccessRecommendation::NoAccess => 2,
+        }
+    }
+}
+
+/// PB-TNC batch to send, RFC 5793 4.1
+struct Batch {
+    buffer: [u8; MAX_BATCH_LEN],
+    len: usize,
+}
+
+impl Batch {
+    fn new(batch_type: u8, from_server: bool) -> Self {
+        let mut buffer = [0u8; MAX_BATCH_LEN];
+        buffer[0] = PB_TNC_VERSION;
+        buffer[1] = if from_server { FLAG_SERVER } else { 0 };
+        buffer[3] = batch_type;
+        Self {
+            buffer,
+            len: BATCH_HEADER_LEN,
+        }
+    }
+
+    /// Appends a PB-TNC message of the IETF namespace
+    fn message(&mut self, message_type: u32, value: &[u8]) -> &mut Self {
+        let out = &mut self.buffer[self.len..self.len + MESSAGE_HEADER_LEN + value.len()];
+        out[0] = FLAG_NOSKIP;
+        out[4..8].copy_from_slice(&message_type.to_be_bytes());
+        out[8..12].copy_from_slice(&((MESSAGE_HEADER_LEN + value.len()) as u32).to_be_bytes());
+        out[12..].copy_from_slice(value);
+        self.len += out.len();
+        self
+    }
+
+    /// Appends a PB-PA message with the attributes `write` adds, nothing if it
+    /// doesn't add any
+    fn pa_message(
+        &mut self,
+        subtype: PaSubtype,
+        collector_id: u16,
+        validator_id: u16,
+        message_id: u32,
+        write: impl FnOnce(&mut AttributeWriter),
+    ) {
+        let start = self.len + MESSAGE_HEADER_LEN + PA_HEADER_LEN + pa_tnc::MESSAGE_HEADER_LEN;
+        let Some(buffer) = self.buffer.get_mut(start..) else {
+            return;
+        };
+        let mut writer = AttributeWriter::new(buffer);
+        write(&mut writer);
+        if writer.is_empty() {
+            return;
+        }
+        let attributes_len = writer.len();
+
+        let pa_len = PA_HEADER_LEN + pa_tnc::MESSAGE_HEADER_LEN + attributes_len;
+        let header = &mut self.buffer[self.len..start];
+        header[0] = FLAG_NOSKIP;
+        header[4..8].copy_from_slice(&MESSAGE_PA.to_be_bytes());
+        header[8..12].copy_from_slice(&((MESSAGE_HEADER_LEN + pa_len) as u32).to_be_bytes());
+        let pa = &mut header[MESSAGE_HEADER_LEN..];
+        pa[1..4].copy_from_slice(&subtype.vendor_id.to_be_bytes()[1..]);
+        pa[4..8].copy_from_slice(&subtype.subtype.to_be_bytes());
+        pa[8..10].copy_from_slice(&collector_id.to_be_bytes());
+        pa[10..12].copy_from_slice(&validator_id.to_be_bytes());
+        pa[12..].copy_from_slice(&pa_tnc::message_header(message_id));
+        self.len += MESSAGE_HEADER_LEN + pa_len;
+    }
+
+    /// PT-EAP packet with the batch
+    fn send(mut self, env: &mut dyn EapEnvironment) -> MessageBuilder<'_> {
+        let len = (self.len as u32).to_be_bytes();
+        self.buffer[4..8].copy_from_slice(&len);
+        env.respond()
+            .write(&[PT_EAP_VERSION])
+            .write(&self.buffer[..self.len])
+    }
+}
+
+/// PB-TNC message of a received batch
+struct Message<'a> {
+    message_type: u32,
+    value: &'a [u8],
+}
+
+/// Received PB-TNC batch, the messages are checked when it is parsed
+struct ReceivedBatch<'a> {
+    batch_type: u8,
+    messages: &'a [u8],
+}
+
+impl<'a> ReceivedBatch<'a> {
+    /// Checks the PT-EAP header and parses the batch. `None` for fragments,
+    /// batches from the wrong direction and unknown NOSKIP messages.
+    fn parse(msg: &'a [u8], from_server: bool) -> Option<Self> {
+        let (&flags, data) = msg.split_first()?;
+        if flags & VERSION_MASK != PT_EAP_VERSION || flags & FLAG_MORE_FRAGMENTS != 0 {
+            return None;
+        }
+        let mut reader = ByteReader::new(data);
+        if flags & FLAG_LENGTH != 0 && reader.u32()? as usize != reader.remaining().len() {
+            return None;
+        }
+
+        let [version, direction, _, batch_type] = reader.take_array()?;
+        let len = reader.u32()? as usize;
+        if version != PB_TNC_VERSION
+            || (direction & FLAG_SERVER != 0) != from_server
+            || len != BATCH_HEADER_LEN + reader.remaining().len()
+        {
+            return None;
+        }
+
+        let batch = Self {
+            batch_type: batch_type & 0x0f,
+            messages: reader.remaining(),
+        };
+        let mut messages = batch.messages();
+        while !messages.is_empty() {
+            let (no_skip, message) = next_message(&mut messages)?;
+            if no_skip && !message.is_some_and(|message| is_known(message.message_type)) {
+                return None;
+            }
+        }
+        Some(batch)
+    }
+
+    fn messages(&self) -> ByteReader<'a> {
+        ByteReader::new(self.messages)
+    }
+
+    /// The IETF messages of the batch
+    fn iter(&self) -> impl Iterator<Item = Message<'a>> {
+        let mut reader = self.messages();
+        core::iter::from_fn(move || loop {
+            match next_message(&mut reader)? {
+                (_, Some(message)) => return Some(message),
+                (_, None) => continue,
+            }
+        })
+    }
+
+    /// Whether the batch contains a PB-Error message
+    fn has_error(&self) -> bool {
+        self.iter()
+            .any(|message| message.message_type == MESSAGE_ERROR)
+    }
+
+    /// PB-PA messages of `subtype` with the posture collector and validator
+    /// identifiers, `None` if a message is malformed
+    fn pa_messages(
+        &self,
+        subtype: PaSubtype,
+    ) -> impl Iterator<Item = Option<(u16, u16, Attributes<'a>)>> {
+        self.iter()
+            .filter(|message| message.message_type == MESSAGE_PA)
+            .filter_map(move |message| {
+                let mut reader = ByteReader::new(message.value);
+                let (Some(_flags), Some([a, b, c]), Some(pa_subtype)) =
+                    (reader.u8(), reader.take_array(), reader.u32())
+                else {
+                    return Some(None);
+                };
+                if u32::from_be_bytes([0, a, b, c]) != subtype.vendor_id
+                    || pa_subtype != subtype.subtype
+                {
+                    return None;
+                }
+                Some(match (reader.u16(), reader.u16()) {
+                    (Some(collector_id), Some(validator_id)) => {
+                        Attributes::parse(reader.remaining())
+                            .map(|attributes| (collector_id, validator_id, attributes))
+                    }
+                    _ => None,
+                })
+            })
+    }
+}
+
+/// Message types the batches of both directions may carry
+fn is_known(message_type: u32) -> bool {
+    matches!(
+        message_type,
+        MESSAGE_PA | MESSAGE_ASSESSMENT_RESULT | MESSAGE_ACCESS_RECOMMENDATION | MESSAGE_ERROR
+    )
+}
+
+/// The next PB-TNC message with its NOSKIP flag, `Some((_, None))` if it is
+/// not in the IETF namespace
+fn next_message<'a>(reader: &mut ByteReader<'a>) -> Option<(bool, Option<Message<'a>>)> {
+    let flags = reader.u8()?;
+    let vendor_id = reader.take_array::<3>()?;
+    let message_type = reader.u32()?;
+    let len = (reader.u32()? as usize).checked_sub(MESSAGE_HEADER_LEN)?;
+    let value = reader.take(len)?;
+    let message = (vendor_id == [0; 3]).then_some(Message {
+        message_type,
+        value,
+    });
+    Some((flags & FLAG_NOSKIP != 0, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch() {
+        let mut batch = Batch::new(BATCH_CDATA, false);
+        batch.pa_message(
+            PaSubtype::OPERATING_SYSTEM,
+            POSTURE_ID,
+            ANY_VALIDATOR,
+            1,
+            |_| {},
+        );
+        batch.pa_message(
+            PaSubtype::OPERATING_SYSTEM,
+            POSTURE_ID,
+            ANY_VALIDATOR,
+            2,
+            |out| {
+                out.write(Attribute::ietf(Attribute::STRING_VERSION, b"6.1"));
+            },
+        );
+        batch.message(0x1234, &[]);
+        let len = batch.len;
+        let mut msg = [PT_EAP_VERSION; 1 + MAX_BATCH_LEN];
+        batch.buffer[4..8].copy_from_slice(&(len as u32).to_be_bytes());
+        msg[1..1 + len].copy_from_slice(&batch.buffer[..len]);
+        let msg = &msg[..1 + len];
+
+        assert!(ReceivedBatch::parse(msg, true).is_none());
+        // The made up message type has the NOSKIP flag
+        assert!(ReceivedBatch::parse(msg, false).is_none());
+
+        let mut msg = msg[..msg.len() - MESSAGE_HEADER_LEN].to_vec();
+        msg[8] -= MESSAGE_HEADER_LEN as u8;
+        let batch = ReceivedBatch::parse(&msg, false).unwrap();
+        assert_eq!(batch.batch_type, BATCH_CDATA);
+        assert!(!batch.has_error());
+        assert_eq!(batch.pa_messages(PaSubtype::FIREWALL).count(), 0);
+        let (collector_id, validator_id, attributes) = batch
+            .pa_messages(PaSubtype::OPERATING_SYSTEM)
+            .next()
+            .flatten()
+            .unwrap();
+        assert_eq!((collector_id, validator_id), (POSTURE_ID, ANY_VALIDATOR));
+        assert!(attributes.eq([Attribute::ietf(Attribute::STRING_VERSION, b"6.1")]));
+    }
+}
@@ -0,0 +1,233 @@
+//! PA-TNC messages, RFC 5792, and the IF-IMC / IF-IMV style interfaces of the
+//! posture collectors and validators exchanging them.
+
+use crate::util::ByteReader;
+
+const PA_TNC_VERSION: u8 = 1;
+pub(crate) const MESSAGE_HEADER_LEN: usize = 8;
+const ATTRIBUTE_HEADER_LEN: usize = 12;
+const FLAG_NOSKIP: u8 = 0x80;
+
+/// The component a PA message is about, RFC 5792 7.2
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PaSubtype {
+    pub vendor_id: u32,
+    pub subtype: u32,
+}
+
+impl PaSubtype {
+    pub const TESTING: Self = Self::ietf(0);
+    pub const OPERATING_SYSTEM: Self = Self::ietf(1);
+    pub const ANTI_VIRUS: Self = Self::ietf(2);
+    pub const ANTI_SPYWARE: Self = Self::ietf(3);
+    pub const ANTI_MALWARE: Self = Self::ietf(4);
+    pub const FIREWALL: Self = Self::ietf(5);
+    pub const IDPS: Self = Self::ietf(6);
+    pub const VPN: Self = Self::ietf(7);
+    pub const NEA_CLIENT: Self = Self::ietf(8);
+
+    pub const fn ietf(subtype: u32) -> Self {
+        Self {
+            vendor_id: 0,
+            subtype,
+        }
+    }
+}
+
+/// PA-TNC attribute, RFC 5792 4.2
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Attribute<'a> {
+    pub vendor_id: u32,
+    pub attribute_type: u32,
+    /// The receiver has to reject the message if it doesn't know the attribute
+    pub no_skip: bool,
+    pub value: &'a [u8],
+}
+
+impl<'a> Attribute<'a> {
+    pub const ATTRIBUTE_REQUEST: u32 = 1;
+    pub const PRODUCT_INFORMATION: u32 = 2;
+    pub const NUMERIC_VERSION: u32 = 3;
+    pub const STRING_VERSION: u32 = 4;
+    pub const OPERATIONAL_STATUS: u32 = 5;
+    pub const PORT_FILTER: u32 = 6;
+    pub const INSTALLED_PACKAGES: u32 = 7;
+    pub const PA_TNC_ERROR: u32 = 8;
+    pub const ASSESSMENT_RESULT: u32 = 9;
+    pub const REMEDIATION_INSTRUCTIONS: u32 = 10;
+    pub const FORWARDING_ENABLED: u32 = 11;
+    pub const FACTORY_DEFAULT_PASSWORD_ENABLED: u32 = 12;
+
+    /// Attribute of the IETF namespace
+    pub const fn ietf(attribute_type: u32, value: &'a [u8]) -> Self {
+        Self {
+            vendor_id: 0,
+            attribute_type,
+            no_skip: false,
+            value,
+        }
+    }
+}
+
+/// The attributes of a received PA-TNC message
+#[derive(Clone, Copy, Debug)]
+pub struct Attributes<'a> {
+    reader: ByteReader<'a>,
+}
+
+impl<'a> Attributes<'a> {
+    /// `None` if the message or one of its attributes is malformed
+    pub(crate) fn parse(message: &'a [u8]) -> Option<Self> {
+        let mut reader = ByteReader::new(message);
+        let header = reader.take(MESSAGE_HEADER_LEN)?;
+        if header[0] != PA_TNC_VERSION {
+            return None;
+        }
+
+        let attributes = Self { reader };
+        let mut check = attributes;
+        while !check.reader.is_empty() {
+            check.read()?;
+        }
+        Some(attributes)
+    }
+
+    fn read(&mut self) -> Option<Attribute<'a>> {
+        let flags = self.reader.u8()?;
+        let [a, b, c] = self.reader.take_array()?;
+        let attribute_type = self.reader.u32()?;
+        let len = self.reader.u32()? as usize;
+        Some(Attribute {
+            vendor_id: u32::from_be_bytes([0, a, b, c]),
+            attribute_type,
+            no_skip: flags & FLAG_NOSKIP != 0,
+            value: self.reader.take(len)?,
+        })
+    }
+}
+
+impl<'a> Iterator for Attributes<'a> {
+    type Item = Attribute<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read()
+    }
+}
+
+/// Writes the attributes of a PA-TNC message into the batch
+pub struct AttributeWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> AttributeWriter<'a> {
+    pub(crate) fn new(buffer: &'a mut [u8]) -> Self {
+        Self { buffer, len: 0 }
+    }
+
+    /// Returns `false` if the attribute doesn't fit into the batch
+    pub fn write(&mut self, attribute: Attribute) -> bool {
+        let end = self.len + ATTRIBUTE_HEADER_LEN + attribute.value.len();
+        let Some(out) = self.buffer.get_mut(self.len..end) else {
+            return false;
+        };
+        out[0] = if attribute.no_skip { FLAG_NOSKIP } else { 0 };
+        out[1..4].copy_from_slice(&attribute.vendor_id.to_be_bytes()[1..]);
+        out[4..8].copy_from_slice(&attribute.attribute_type.to_be_bytes());
+        out[8..12].copy_from_slice(&(attribute.value.len() as u32).to_be_bytes());
+        out[12..].copy_from_slice(attribute.value);
+        self.len = end;
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// PA-TNC message header, RFC 5792 4.1
+pub(crate) fn message_header(message_id: u32) -> [u8; MESSAGE_HEADER_LEN] {
+    let mut header = [0u8; MESSAGE_HEADER_LEN];
+    header[0] = PA_TNC_VERSION;
+    header[4..].copy_from_slice(&message_id.to_be_bytes());
+    header
+}
+
+/// Access recommendation of the server, RFC 5793 4.6
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessRecommendation {
+    Allow,
+    /// Limited access, e.g. to remediate
+    Isolate,
+    NoAccess,
+}
+
+/// Posture collector of the peer, an IF-IMC style interface
+pub trait PostureCollector {
+    /// Subtype of the PA messages of this collector
+    fn subtype(&self) -> PaSubtype;
+
+    /// Attributes sent unsolicited at the start of an assessment
+    fn begin_handshake(&mut self, out: &mut AttributeWriter);
+
+    /// A PA message of the posture validator, the attributes written to `out`
+    /// answer it
+    fn receive_message(&mut self, attributes: Attributes, out: &mut AttributeWriter);
+
+    /// The outcome of the assessment
+    fn handshake_result(&mut self, recommendation: AccessRecommendation) {
+        let _ = recommendation;
+    }
+}
+
+/// Posture validator of the server, an IF-IMV style interface
+pub trait PostureValidator {
+    /// Subtype of the PA messages this validator receives
+    fn subtype(&self) -> PaSubtype;
+
+    /// Called when a new assessment starts
+    fn begin_handshake(&mut self) {}
+
+    /// A PA message of the posture collector. The attributes written to `out`,
+    /// for example an Attribute Request, go back to it.
+    fn receive_message(&mut self, attributes: Attributes, out: &mut AttributeWriter);
+
+    /// Called after each batch of the peer. `None` continues the assessment
+    /// with the attributes written by [`Self::receive_message`], without any
+    /// the assessment ends with [`AccessRecommendation::NoAccess`].
+    fn recommendation(&mut self) -> Option<AccessRecommendation>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes() {
+        let mut message = [0u8; 64];
+        message[..MESSAGE_HEADER_LEN].copy_from_slice(&message_header(7));
+        let mut writer = AttributeWriter::new(&mut message[MESSAGE_HEADER_LEN..]);
+        assert!(writer.is_empty());
+        assert!(writer.write(Attribute::ietf(Attribute::STRING_VERSION, b"1.2.3")));
+        let vendor = Attribute {
+            vendor_id: 0x123456,
+            attribute_type: 9,
+            no_skip: true,
+            value: &[],
+        };
+        assert!(writer.write(vendor));
+        assert!(!writer.write(Attribute::ietf(1, &[0; 40])));
+        let len = MESSAGE_HEADER_LEN + writer.len();
+        assert_eq!(len, MESSAGE_HEADER_LEN + 12 + 5 + 12);
+        assert_eq!(&message[8..12], [0, 0, 0, 0]);
+        assert_eq!(&message[25..29], [0x80, 0x12, 0x34, 0x56]);
+
+        let attributes = Attributes::parse(&message[..len]).unwrap();
+        assert!(attributes.eq([Attribute::ietf(Attribute::STRING_VERSION, b"1.2.3"), vendor]));
+        assert!(Attributes::parse(&message[..len - 1]).is_none());
+    }
+}
@@ -0,0 +1,192 @@
+use crate::layers::{
+    eap_layer::{Decision, MethodState, SessionKeys},
+    mux::TupleElement,
+    peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta},
+    MethodType,
+};
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    WaitStart,
+    WaitBatch,
+    Done,
+}
+
+/// Peer side of PT-EAP, the PB-TNC client.
+///
+/// The PA messages of `collector` go to the validator of the server, its
+/// access recommendation decides whether the method can succeed.
+#[derive(Clone)]
+pub struct PeerTncMethod<C> {
+    collector: C,
+    state: State,
+    message_id: u32,
+    recommendation: Option<AccessRecommendation>,
+}
+
+impl<C> TupleElement for PeerTncMethod<C>
+where
+    C: PostureCollector + 'static,
+{
+    type Target = dyn PeerMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<C> PeerTncMethod<C>
+where
+    C: PostureCollector,
+{
+    pub fn new(collector: C) -> Self {
+        Self {
+            collector,
+            state: State::WaitStart,
+            message_id: 0,
+            recommendation: None,
+        }
+    }
+
+    /// Access recommendation of the last assessment
+    pub fn recommendation(&self) -> Option<AccessRecommendation> {
+        self.recommendation
+    }
+
+    fn next_message_id(&mut self) -> u32 {
+        self.message_id = self.message_id.wrapping_add(1);
+        self.message_id
+    }
+
+    /// Ends the assessment with a CLOSE batch
+    fn close<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> PeerMethodLayerResult<'a> {
+        self.state = State::Done;
+        PeerMethodLayerResult::Send(Batch::new(BATCH_CLOSE, false).send(env))
+    }
+
+    fn recv_start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> PeerMethodLayerResult<'a> {
+        self.recommendation = None;
+        let message_id = self.next_message_id();
+        let mut batch = Batch::new(BATCH_CDATA, false);
+        batch.pa_message(
+            self.collector.subtype(),
+            POSTURE_ID,
+            ANY_VALIDATOR,
+            message_id,
+            |out| self.collector.begin_handshake(out),
+        );
+
+        self.state = State::WaitBatch;
+        PeerMethodLayerResult::Send(batch.send(env))
+    }
+
+    /// SDATA, answered with the PA messages of the collector
+    fn recv_data<'a>(
+        &mut self,
+        batch: &ReceivedBatch,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let subtype = self.collector.subtype();
+        let mut out = Batch::new(BATCH_CDATA, false);
+        for message in batch.pa_messages(subtype) {
+            let Some((_, validator_id, attributes)) = message else {
+                return self.close(env);
+            };
+            let message_id = self.next_message_id();
+            out.pa_message(subtype, POSTURE_ID, validator_id, message_id, |out| {
+                self.collector.receive_message(attributes, out)
+            });
+        }
+        PeerMethodLayerResult::Send(out.send(env))
+    }
+
+    /// RESULT, the assessment is over
+    fn recv_result<'a>(
+        &mut self,
+        batch: &ReceivedBatch,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        self.recommendation = batch
+            .iter()
+            .find(|message| message.message_type == MESSAGE_ACCESS_RECOMMENDATION)
+            .and_then(|message| <[u8; 4]>::try_from(message.value).ok())
+            .and_then(|[_, _, high, low]| {
+                AccessRecommendation::from_u16(u16::from_be_bytes([high, low]))
+            });
+        if let Some(recommendation) = self.recommendation {
+            self.collector.handshake_result(recommendation);
+        }
+        self.close(env)
+    }
+}
+
+impl<C> PeerMethodLayer for PeerTncMethod<C>
+where
+    C: PostureCollector,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_PT_EAP)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::PT_EAP
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        if msg.first() == Some(&(FLAG_START | PT_EAP_VERSION)) {
+            return self.recv_start(env);
+        }
+        let Some(batch) = ReceivedBatch::parse(msg, true).filter(|batch| !batch.has_error()) else {
+            return self.close(env);
+        };
+        match (self.state, batch.batch_type) {
+            (State::WaitBatch, BATCH_SDATA) => self.recv_data(&batch, env),
+            (State::WaitBatch, BATCH_RESULT) => self.recv_result(&batch, env),
+            // The server gave up, or a protocol error
+            _ => self.close(env),
+        }
+    }
+
+    fn method_state(&self) -> MethodState {
+        if self.recommendation.is_some() {
+            MethodState::Done
+        } else {
+            MethodState::MayCont
+        }
+    }
+
+    fn decision(&self) -> Decision {
+        if self
+            .recommendation
+            .is_some_and(|recommendation| recommendation != AccessRecommendation::NoAccess)
+        {
+            Decision::CondSucc
+        } else {
+            Decision::Fail
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        None
+    }
+
+    fn reset(&mut self) {
+        self.state = State::WaitStart;
+        self.recommendation = None;
+    }
+}
@@ -0,0 +1,213 @@
+//! WSC attributes, type-length-value with two byte type and length, and the
+//! values the method uses. See Wi-Fi Simple Configuration 12.
+
+use crate::util::ByteReader;
+
+pub(crate) const ATTR_ASSOC_STATE: u16 = 0x1002;
+pub(crate) const ATTR_AUTH_TYPE: u16 = 0x1003;
+pub(crate) const ATTR_AUTH_TYPE_FLAGS: u16 = 0x1004;
+pub(crate) const ATTR_AUTHENTICATOR: u16 = 0x1005;
+pub(crate) const ATTR_CONFIG_METHODS: u16 = 0x1008;
+pub(crate) const ATTR_CONFIG_ERROR: u16 = 0x1009;
+pub(crate) const ATTR_CONN_TYPE_FLAGS: u16 = 0x100d;
+pub(crate) const ATTR_CREDENTIAL: u16 = 0x100e;
+pub(crate) const ATTR_ENCR_TYPE: u16 = 0x100f;
+pub(crate) const ATTR_ENCR_TYPE_FLAGS: u16 = 0x1010;
+pub(crate) const ATTR_DEVICE_NAME: u16 = 0x1011;
+pub(crate) const ATTR_DEVICE_PASSWORD_ID: u16 = 0x1012;
+pub(crate) const ATTR_E_HASH1: u16 = 0x1014;
+pub(crate) const ATTR_E_HASH2: u16 = 0x1015;
+pub(crate) const ATTR_E_SNONCE1: u16 = 0x1016;
+pub(crate) const ATTR_E_SNONCE2: u16 = 0x1017;
+pub(crate) const ATTR_ENCRYPTED_SETTINGS: u16 = 0x1018;
+pub(crate) const ATTR_ENROLLEE_NONCE: u16 = 0x101a;
+pub(crate) const ATTR_KEY_WRAP_AUTHENTICATOR: u16 = 0x101e;
+pub(crate) const ATTR_MAC_ADDRESS: u16 = 0x1020;
+pub(crate) const ATTR_MANUFACTURER: u16 = 0x1021;
+pub(crate) const ATTR_MESSAGE_TYPE: u16 = 0x1022;
+pub(crate) const ATTR_MODEL_NAME: u16 = 0x1023;
+pub(crate) const ATTR_MODEL_NUMBER: u16 = 0x1024;
+pub(crate) const ATTR_NETWORK_INDEX: u16 = 0x1026;
+pub(crate) const ATTR_NETWORK_KEY: u16 = 0x1027;
+pub(crate) const ATTR_OS_VERSION: u16 = 0x102d;
+pub(crate) const ATTR_PUBLIC_KEY: u16 = 0x1032;
+pub(crate) const ATTR_REGISTRAR_NONCE: u16 = 0x1039;
+pub(crate) const ATTR_RF_BANDS: u16 = 0x103c;
+pub(crate) const ATTR_R_HASH1: u16 = 0x103d;
+pub(crate) const ATTR_R_HASH2: u16 = 0x103e;
+pub(crate) const ATTR_R_SNONCE1: u16 = 0x103f;
+pub(crate) const ATTR_R_SNONCE2: u16 = 0x1040;
+pub(crate) const ATTR_SERIAL_NUMBER: u16 = 0x1042;
+pub(crate) const ATTR_WPS_STATE: u16 = 0x1044;
+pub(crate) const ATTR_SSID: u16 = 0x1045;
+pub(crate) const ATTR_UUID_E: u16 = 0x1047;
+pub(crate) const ATTR_UUID_R: u16 = 0x1048;
+pub(crate) const ATTR_VENDOR_EXTENSION: u16 = 0x1049;
+pub(crate) const ATTR_VERSION: u16 = 0x104a;
+pub(crate) const ATTR_PRIMARY_DEVICE_TYPE: u16 = 0x1054;
+
+/// Message Type values
+pub(crate) const MESSAGE_M1: u8 = 0x04;
+pub(crate) const MESSAGE_M2: u8 = 0x05;
+pub(crate) const MESSAGE_M2D: u8 = 0x06;
+pub(crate) const MESSAGE_M3: u8 = 0x07;
+pub(crate) const MESSAGE_M4: u8 = 0x08;
+pub(crate) const MESSAGE_M5: u8 = 0x09;
+pub(crate) const MESSAGE_M6: u8 = 0x0a;
+pub(crate) const MESSAGE_M7: u8 = 0x0b;
+pub(crate) const MESSAGE_M8: u8 = 0x0c;
+pub(crate) const MESSAGE_ACK: u8 = 0x0d;
+pub(crate) const MESSAGE_NACK: u8 = 0x0e;
+pub(crate) const MESSAGE_DONE: u8 = 0x0f;
+
+/// Version is fixed to 1.0 for compatibility, the real version is the
+/// Version2 subelement of the WFA vendor extension
+pub(crate) const VERSION: u8 = 0x10;
+/// WFA vendor extension with Version2 = 2.0
+pub(crate) const WFA_EXTENSION: [u8; 6] = [0x00, 0x37, 0x2a, 0x00, 0x01, 0x20];
+
+/// Configuration Error values
+pub(crate) const CONFIG_NO_ERROR: u16 = 0;
+pub(crate) const CONFIG_DECRYPTION_FAILURE: u16 = 2;
+pub(crate) const CONFIG_DEVICE_PASSWORD_AUTH_FAILURE: u16 = 18;
+
+/// Device Password ID values
+pub(crate) const PASSWORD_ID_DEFAULT: u16 = 0x0000;
+pub(crate) const PASSWORD_ID_USER_SPECIFIED: u16 = 0x0001;
+pub(crate) const PASSWORD_ID_PUSH_BUTTON: u16 = 0x0004;
+
+/// Config Methods of both roles: display, keypad and virtual push button
+pub(crate) const CONFIG_METHODS: u16 = 0x0008 | 0x0100 | 0x0280;
+pub(crate) const CONN_TYPE_ESS: u8 = 0x01;
+pub(crate) const WPS_STATE_NOT_CONFIGURED: u8 = 0x01;
+pub(crate) const RF_BAND_2_4_GHZ: u8 = 0x01;
+pub(crate) const ASSOC_NOT_ASSOCIATED: u16 = 0;
+
+pub(crate) const ATTRIBUTE_HEADER_LEN: usize = 4;
+pub(crate) const AUTHENTICATOR_LEN: usize = 8;
+
+/// The attributes of a received message
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Attributes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Attributes<'a> {
+    /// `None` if an attribute overruns the message
+    pub(crate) fn parse(data: &'a [u8]) -> Option<Self> {
+        let mut reader = ByteReader::new(data);
+        while !reader.is_empty() {
+            reader.u16()?;
+            reader.u16_prefixed()?;
+        }
+        Some(Self { data })
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (u16, &'a [u8])> {
+        let mut reader = ByteReader::new(self.data);
+        core::iter::from_fn(move || Some((reader.u16()?, reader.u16_prefixed()?)))
+    }
+
+    /// Value of the first attribute of `attribute_type`
+    pub(crate) fn get(&self, attribute_type: u16) -> Option<&'a [u8]> {
+        self.iter()
+            .find(|(t, _)| *t == attribute_type)
+            .map(|(_, value)| value)
+    }
+
+    pub(crate) fn array<const N: usize>(&self, attribute_type: u16) -> Option<[u8; N]> {
+        self.get(attribute_type)
+            .and_then(|value| value.try_into().ok())
+    }
+
+    pub(crate) fn u8(&self, attribute_type: u16) -> Option<u8> {
+        self.array::<1>(attribute_type).map(|[value]| value)
+    }
+
+    pub(crate) fn u16(&self, attribute_type: u16) -> Option<u16> {
+        self.array(attribute_type).map(u16::from_be_bytes)
+    }
+
+    /// Value of the last attribute if it is of `attribute_type`, and the
+    /// message in front of it. Authenticator and Key Wrap Authenticator have
+    /// to be the last attribute, they cover everything before them.
+    pub(crate) fn last(&self, attribute_type: u16) -> Option<(&'a [u8], &'a [u8])> {
+        let (t, value) = self.iter().last()?;
+        let covered = self.data.len() - ATTRIBUTE_HEADER_LEN - value.len();
+        (t == attribute_type).then(|| (&self.data[..covered], value))
+    }
+}
+
+/// Writes the attributes of a message
+pub(crate) struct Writer<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Writer<N> {
+    pub(crate) fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Messages are bounded by the limits of the device attributes and the
+    /// credential, so they always fit
+    pub(crate) fn attribute(&mut self, attribute_type: u16, value: &[u8]) -> &mut Self {
+        let out = &mut self.buffer[self.len..self.len + ATTRIBUTE_HEADER_LEN + value.len()];
+        out[..2].copy_from_slice(&attribute_type.to_be_bytes());
+        out[2..4].copy_from_slice(&(value.len() as u16).to_be_bytes());
+        out[4..].copy_from_slice(value);
+        self.len += out.len();
+        self
+    }
+
+    pub(crate) fn u8(&mut self, attribute_type: u16, value: u8) -> &mut Self {
+        self.attribute(attribute_type, &[value])
+    }
+
+    pub(crate) fn u16(&mut self, attribute_type: u16, value: u16) -> &mut Self {
+        self.attribute(attribute_type, &value.to_be_bytes())
+    }
+
+    /// Version and Message Type, the first attributes of every message
+    pub(crate) fn header(&mut self, message_type: u8) -> &mut Self {
+        self.u8(ATTR_VERSION, VERSION)
+            .u8(ATTR_MESSAGE_TYPE, message_type)
+    }
+
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes() {
+        let mut writer = Writer::<32>::new();
+        writer
+            .header(MESSAGE_M3)
+            .attribute(ATTR_E_HASH1, b"hash")
+            .attribute(ATTR_AUTHENTICATOR, &[7; AUTHENTICATOR_LEN]);
+        let message = writer.as_slice();
+        assert_eq!(
+            &message[..10],
+            [0x10, 0x4a, 0, 1, 0x10, 0x10, 0x22, 0, 1, 7]
+        );
+
+        let attributes = Attributes::parse(message).unwrap();
+        assert_eq!(attributes.u8(ATTR_MESSAGE_TYPE), Some(MESSAGE_M3));
+        assert_eq!(attributes.get(ATTR_E_HASH1), Some(&b"hash"[..]));
+        assert_eq!(attributes.u16(ATTR_E_HASH1), None);
+        assert_eq!(
+            attributes.last(ATTR_AUTHENTICATOR),
+            Some((&message[..18], &[7; AUTHENTICATOR_LEN][..]))
+        );
+        assert_eq!(attributes.last(ATTR_E_HASH1), None);
+        assert!(Attributes::parse(&message[..message.len() - 1]).is_none());
+    }
+}
@@ -0,0 +1,321 @@
+use crate::layers::{
+    auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta},
+    mux::TupleElement,
+};
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Start,
+    WaitM1,
+    /// M2D was sent, waiting for the WSC_ACK of the Enrollee
+    WaitM2dAck,
+    WaitM3,
+    WaitM5,
+    WaitM7,
+    /// M8 was sent, waiting for WSC_Done
+    WaitDone,
+    /// A WSC_NACK was sent, waiting for the answer of the Enrollee
+    Failing,
+    Done,
+}
+
+/// Server side of EAP-WSC, the Registrar.
+///
+/// An Enrollee that proves knowledge of `password` receives `credential`,
+/// issued to its MAC address. The method ends with an EAP-Failure either way.
+#[derive(Clone)]
+pub struct AuthWscMethod {
+    device: Device,
+    password: DevicePassword,
+    credential: Credential,
+    state: State,
+    session: Session,
+    enrollee_mac: [u8; ADDRESS_LEN],
+}
+
+impl TupleElement for AuthWscMethod {
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl AuthWscMethod {
+    pub fn new(device: Device, password: DevicePassword, credential: Credential) -> Self {
+        Self {
+            device,
+            password,
+            credential,
+            state: State::Start,
+            session: Session::new(false),
+            enrollee_mac: [0; ADDRESS_LEN],
+        }
+    }
+
+    fn send<'a>(
+        &mut self,
+        state: State,
+        op_code: u8,
+        out: &Writer<MAX_MESSAGE_LEN>,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        self.state = state;
+        AuthMethodLayerResult::Send(packet(env, op_code, out.as_slice()))
+    }
+
+    fn nack<'a>(
+        &mut self,
+        config_error: u16,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let out = self
+            .session
+            .status_message(MESSAGE_NACK, Some(config_error));
+        self.send(State::Failing, OP_NACK, &out, env)
+    }
+
+    fn failed<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        self.state = State::Failing;
+        AuthMethodLayerResult::Failed(env)
+    }
+
+    /// M2, or M2D without public key and Device Password ID
+    fn m2(&self, password_id: Option<u16>) -> Writer<MAX_MESSAGE_LEN> {
+        let mut out = Writer::new();
+        out.header(match password_id {
+            Some(_) => MESSAGE_M2,
+            None => MESSAGE_M2D,
+        })
+        .attribute(ATTR_ENROLLEE_NONCE, &self.session.enrollee_nonce)
+        .attribute(ATTR_REGISTRAR_NONCE, &self.session.registrar_nonce)
+        .attribute(ATTR_UUID_R, &self.device.uuid);
+        if password_id.is_some() {
+            out.attribute(ATTR_PUBLIC_KEY, self.session.own_public_key());
+        }
+        out.u16(ATTR_AUTH_TYPE_FLAGS, AUTHENTICATION_FLAGS)
+            .u16(ATTR_ENCR_TYPE_FLAGS, ENCRYPTION_FLAGS)
+            .u8(ATTR_CONN_TYPE_FLAGS, CONN_TYPE_ESS)
+            .u16(ATTR_CONFIG_METHODS, CONFIG_METHODS);
+        self.device.write(&mut out);
+        out.u8(ATTR_RF_BANDS, RF_BAND_2_4_GHZ)
+            .u16(ATTR_ASSOC_STATE, ASSOC_NOT_ASSOCIATED)
+            .u16(ATTR_CONFIG_ERROR, CONFIG_NO_ERROR);
+        if let Some(password_id) = password_id {
+            out.u16(ATTR_DEVICE_PASSWORD_ID, password_id);
+        }
+        out.attribute(ATTR_OS_VERSION, &self.device.os_version())
+            .attribute(ATTR_VENDOR_EXTENSION, &WFA_EXTENSION);
+        out
+    }
+
+    /// M1 = (UUID-E, MAC, N1, PK_E, Device Password ID), answered by M2, or
+    /// by M2D if the Enrollee uses another device password
+    fn recv_m1<'a>(
+        &mut self,
+        message: &[u8],
+        attributes: &Attributes,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        self.session.start(env);
+        let (Some(mac_address), Some(enrollee_nonce), Some(public_key), Some(password_id)) = (
+            attributes.array(ATTR_MAC_ADDRESS),
+            attributes.array(ATTR_ENROLLEE_NONCE),
+            attributes.get(ATTR_PUBLIC_KEY),
+            attributes.u16(ATTR_DEVICE_PASSWORD_ID),
+        ) else {
+            return self.nack(CONFIG_NO_ERROR, env);
+        };
+        self.session.enrollee_nonce = enrollee_nonce;
+        self.enrollee_mac = mac_address;
+        if !self.password.matches(password_id) {
+            let out = self.m2(None);
+            return self.send(State::WaitM2dAck, OP_MSG, &out, env);
+        }
+        if self
+            .session
+            .derive_keys(public_key, &mac_address, self.password.password())
+            .is_none()
+        {
+            return self.nack(CONFIG_NO_ERROR, env);
+        }
+
+        self.session.remember(message);
+        let mut out = self.m2(Some(password_id));
+        self.session.authenticate(&mut out);
+        self.send(State::WaitM3, OP_MSG, &out, env)
+    }
+
+    /// M3 = (E-Hash1, E-Hash2), answered by
+    /// M4 = (R-Hash1, R-Hash2, {R-SNonce1})
+    fn recv_m3<'a>(
+        &mut self,
+        message: &[u8],
+        attributes: &Attributes,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let (Some(e_hash1), Some(e_hash2)) = (
+            attributes.array(ATTR_E_HASH1),
+            attributes.array(ATTR_E_HASH2),
+        ) else {
+            return self.nack(CONFIG_NO_ERROR, env);
+        };
+        if !self.session.check_authenticator(message, attributes) {
+            return self.nack(CONFIG_NO_ERROR, env);
+        }
+        self.session.hash1 = e_hash1;
+        self.session.hash2 = e_hash2;
+
+        let (r_hash1, r_hash2) = self.session.own_hashes(env);
+        let mut encrypted = Writer::new();
+        encrypted.attribute(ATTR_R_SNONCE1, &self.session.secret_nonce1);
+        let mut out = Writer::new();
+        out.header(MESSAGE_M4)
+            .attribute(ATTR_ENROLLEE_NONCE, &self.session.enrollee_nonce)
+            .attribute(ATTR_R_HASH1, &r_hash1)
+            .attribute(ATTR_R_HASH2, &r_hash2);
+        self.session.encrypt_settings(&encrypted, env, &mut out);
+        out.attribute(ATTR_VENDOR_EXTENSION, &WFA_EXTENSION);
+        self.session.authenticate(&mut out);
+        self.send(State::WaitM5, OP_MSG, &out, env)
+    }
+
+    /// M5 = ({E-SNonce1}) answered by M6 = ({R-SNonce2}), and
+    /// M7 = ({E-SNonce2}) answered by M8 = ({Credential})
+    fn recv_snonce<'a>(
+        &mut self,
+        second: bool,
+        message: &[u8],
+        attributes: &Attributes,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        if !self.session.check_authenticator(message, attributes) {
+            return self.nack(CONFIG_NO_ERROR, env);
+        }
+        let mut buffer = [0u8; MAX_SETTINGS_LEN];
+        let Some(settings) = self.session.decrypt_settings(attributes, &mut buffer) else {
+            return self.nack(CONFIG_DECRYPTION_FAILURE, env);
+        };
+        let nonce_type = match second {
+            false => ATTR_E_SNONCE1,
+            true => ATTR_E_SNONCE2,
+        };
+        let valid = settings
+            .get(nonce_type)
+            .is_some_and(|nonce| self.session.check_hash(second, nonce));
+        if !valid {
+            return self.nack(CONFIG_DEVICE_PASSWORD_AUTH_FAILURE, env);
+        }
+
+        let mut encrypted = Writer::new();
+        let (message_type, state) = match second {
+            false => {
+                encrypted.attribute(ATTR_R_SNONCE2, &self.session.secret_nonce2);
+                (MESSAGE_M6, State::WaitM7)
+            }
+            true => {
+                let mut credential = Writer::new();
+                self.credential.write(&self.enrollee_mac, &mut credential);
+                encrypted.attribute(ATTR_CREDENTIAL, credential.as_slice());
+                (MESSAGE_M8, State::WaitDone)
+            }
+        };
+        let mut out = Writer::new();
+        out.header(message_type)
+            .attribute(ATTR_ENROLLEE_NONCE, &self.session.enrollee_nonce);
+        self.session.encrypt_settings(&encrypted, env, &mut out);
+        out.attribute(ATTR_VENDOR_EXTENSION, &WFA_EXTENSION);
+        self.session.authenticate(&mut out);
+        self.send(state, OP_MSG, &out, env)
+    }
+
+    fn recv_message<'a>(
+        &mut self,
+        message: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let Some(attributes) = Attributes::parse(message) else {
+            return self.nack(CONFIG_NO_ERROR, env);
+        };
+        let message_type = attributes.u8(ATTR_MESSAGE_TYPE);
+        // Every message after M1 echoes our nonce
+        if message_type != Some(MESSAGE_M1)
+            && attributes.get(ATTR_REGISTRAR_NONCE) != Some(&self.session.registrar_nonce[..])
+        {
+            return self.nack(CONFIG_NO_ERROR, env);
+        }
+        match (self.state, message_type) {
+            (State::WaitM1, Some(MESSAGE_M1)) => self.recv_m1(message, &attributes, env),
+            (State::WaitM3, Some(MESSAGE_M3)) => self.recv_m3(message, &attributes, env),
+            (State::WaitM5, Some(MESSAGE_M5)) => self.recv_snonce(false, message, &attributes, env),
+            (State::WaitM7, Some(MESSAGE_M7)) => self.recv_snonce(true, message, &attributes, env),
+            _ => self.nack(CONFIG_NO_ERROR, env),
+        }
+    }
+
+    /// WSC_Done, the Enrollee received the credential
+    fn recv_done<'a>(
+        &mut self,
+        message: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let valid = Attributes::parse(message).is_some_and(|attributes| {
+            attributes.u8(ATTR_MESSAGE_TYPE) == Some(MESSAGE_DONE)
+                && attributes.get(ATTR_ENROLLEE_NONCE) == Some(&self.session.enrollee_nonce[..])
+                && attributes.get(ATTR_REGISTRAR_NONCE) == Some(&self.session.registrar_nonce[..])
+        });
+        if !valid {
+            return self.nack(CONFIG_NO_ERROR, env);
+        }
+        self.state = State::Done;
+        AuthMethodLayerResult::Failed(env)
+    }
+}
+
+impl AuthMethodLayer for AuthWscMethod {
+    fn method_identifier(&self) -> MethodType {
+        METHOD_WSC
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::eap_wsc(matches!(self.password, DevicePassword::PushButton))
+    }
+
+    /// Only an Enrollee can register, an external Registrar is not supported
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        if env.name().is_some_and(|name| name != IDENTITY_ENROLLEE) {
+            return self.failed(env);
+        }
+        self.state = State::WaitM1;
+        AuthMethodLayerResult::Send(packet(env, OP_START, &[]))
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        let Some((op_code, message)) = parse_packet(msg).filter(|_| self.state != State::Failing)
+        else {
+            return self.failed(env);
+        };
+        match (self.state, op_code) {
+            // The Enrollee gave up, or acknowledged M2D
+            (_, OP_NACK) | (State::WaitM2dAck, OP_ACK) => self.failed(env),
+            (State::WaitDone, OP_DONE) => self.recv_done(message, env),
+            (_, OP_MSG) => self.recv_message(message, env),
+            _ => self.nack(CONFIG_NO_ERROR, env),
+        }
+    }
+}
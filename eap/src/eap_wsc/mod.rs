@@ -0,0 +1,707 @@
+//! EAP-WSC, Wi-Fi Simple Configuration (WPS) over EAP, see the Wi-Fi Simple
+//! Configuration Technical Specification v2.0
+//!
+//! The peer is the Enrollee and the authenticator the Registrar. They run the
+//! registration protocol, M1 to M8, after which the Enrollee holds the
+//! [`Credential`] of the network. Both sides prove knowledge of the
+//! [`DevicePassword`], a PIN or the fixed PIN of the push button method. A
+//! Registrar without a matching password answers with M2D.
+//!
+//! WSC is an expanded type of the Wi-Fi Alliance. The method derives no
+//! session keys and a registration always ends with an EAP-Failure, its
+//! outcome is the credential. WSC messages are not fragmented, all of them fit
+//! into one EAP packet.
+
+mod attributes;
+mod auth;
+mod peer;
+
+pub use auth::AuthWscMethod;
+pub use peer::PeerWscMethod;
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use crypto_bigint::{
+    modular::runtime_mod::{DynResidue, DynResidueParams},
+    Encoding, U1536, U256,
+};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    layers::{MethodType, SecurityClaims},
+    util::{constant_time_eq, ByteReader, OwnedSlice},
+    EapEnvironment, EapEnvironmentResponse, MessageBuilder,
+};
+use attributes::*;
+
+/// Vendor-Id of the Wi-Fi Alliance and Vendor-Type SimpleConfig
+const METHOD_WSC: MethodType = MethodType::Expanded {
+    vendor_id: 0x372a,
+    vendor_type: 1,
+};
+
+/// Op-Codes
+const OP_START: u8 = 0x01;
+const OP_ACK: u8 = 0x02;
+const OP_NACK: u8 = 0x03;
+const OP_MSG: u8 = 0x04;
+const OP_DONE: u8 = 0x05;
+
+/// Flags
+const FLAG_MORE_FRAGMENTS: u8 = 0x01;
+const FLAG_LENGTH: u8 = 0x02;
+
+/// EAP identity of an Enrollee
+pub const IDENTITY_ENROLLEE: &[u8] = b"WFA-SimpleConfig-Enrollee-1-0";
+
+/// Diffie-Hellman group 5, RFC 3526, with generator 2
+const PRIME: U1536 = U1536::from_be_hex(concat!(
+    "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74",
+    "020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437",
+    "4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED",
+    "EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05",
+    "98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB",
+    "9ED529077096966D670C354E4ABC9804F1746C08CA237327FFFFFFFFFFFFFFFF",
+));
+const GENERATOR: u8 = 2;
+const PUBLIC_KEY_LEN: usize = 192;
+
+/// Private exponents are 256 bits, more than twice the security level of the
+/// group
+const EXPONENT_LEN: usize = 32;
+
+const NONCE_LEN: usize = 16;
+const UUID_LEN: usize = 16;
+const ADDRESS_LEN: usize = 6;
+const HASH_LEN: usize = 32;
+const PSK_LEN: usize = 16;
+const BLOCK_LEN: usize = 16;
+const KEY_WRAP_AUTHENTICATOR_LEN: usize = 8;
+
+const KDF_LABEL: &[u8] = b"Wi-Fi Easy and Secure Key Derivation";
+/// AuthKey | KeyWrapKey | EMSK
+const KDF_LEN: usize = HASH_LEN + BLOCK_LEN + HASH_LEN;
+
+/// Limit of a WSC message of either side
+const MAX_MESSAGE_LEN: usize = 1000;
+/// Limit of the decrypted Encrypted Settings, a credential and the Key Wrap
+/// Authenticator fit with room to spare
+const MAX_SETTINGS_LEN: usize = 256;
+
+pub const MAX_DEVICE_NAME_LEN: usize = 32;
+pub const MAX_MANUFACTURER_LEN: usize = 64;
+pub const MAX_MODEL_LEN: usize = 32;
+pub const MAX_SERIAL_NUMBER_LEN: usize = 32;
+pub const MAX_PIN_LEN: usize = 8;
+pub const MAX_SSID_LEN: usize = 32;
+pub const MAX_NETWORK_KEY_LEN: usize = 64;
+
+/// Authentication and encryption types both roles support
+const AUTHENTICATION_FLAGS: u16 = Credential::AUTHENTICATION_OPEN
+    | Credential::AUTHENTICATION_WPA_PSK
+    | Credential::AUTHENTICATION_WPA2_PSK;
+const ENCRYPTION_FLAGS: u16 =
+    Credential::ENCRYPTION_NONE | Credential::ENCRYPTION_TKIP | Credential::ENCRYPTION_AES;
+
+/// PIN the push button method uses for the key exchange
+const PUSH_BUTTON_PIN: &[u8] = b"00000000";
+
+type Block = [u8; BLOCK_LEN];
+type Hash = [u8; HASH_LEN];
+
+/// Device attributes a side describes itself with in M1 or M2
+#[derive(Clone)]
+pub struct Device {
+    uuid: [u8; UUID_LEN],
+    mac_address: [u8; ADDRESS_LEN],
+    device_name: OwnedSlice<MAX_DEVICE_NAME_LEN>,
+    manufacturer: OwnedSlice<MAX_MANUFACTURER_LEN>,
+    model_name: OwnedSlice<MAX_MODEL_LEN>,
+    model_number: OwnedSlice<MAX_MODEL_LEN>,
+    serial_number: OwnedSlice<MAX_SERIAL_NUMBER_LEN>,
+    primary_device_type: [u8; 8],
+    os_version: u32,
+}
+
+impl Device {
+    /// Category computer, subcategory PC
+    pub const DEVICE_TYPE_COMPUTER: [u8; 8] = [0x00, 0x01, 0x00, 0x50, 0xf2, 0x04, 0x00, 0x01];
+    /// Category network infrastructure, subcategory AP
+    pub const DEVICE_TYPE_ACCESS_POINT: [u8; 8] = [0x00, 0x06, 0x00, 0x50, 0xf2, 0x04, 0x00, 0x01];
+
+    pub fn new(uuid: [u8; UUID_LEN], mac_address: [u8; ADDRESS_LEN], device_name: &str) -> Self {
+        Self {
+            uuid,
+            mac_address,
+            device_name: truncate(device_name.as_bytes()),
+            manufacturer: OwnedSlice::new(),
+            model_name: OwnedSlice::new(),
+            model_number: OwnedSlice::new(),
+            serial_number: OwnedSlice::new(),
+            primary_device_type: Self::DEVICE_TYPE_COMPUTER,
+            os_version: 0,
+        }
+    }
+
+    pub fn with_manufacturer(mut self, manufacturer: &str) -> Self {
+        self.manufacturer = truncate(manufacturer.as_bytes());
+        self
+    }
+
+    pub fn with_model(mut self, model_name: &str, model_number: &str) -> Self {
+        self.model_name = truncate(model_name.as_bytes());
+        self.model_number = truncate(model_number.as_bytes());
+        self
+    }
+
+    pub fn with_serial_number(mut self, serial_number: &str) -> Self {
+        self.serial_number = truncate(serial_number.as_bytes());
+        self
+    }
+
+    pub fn with_primary_device_type(mut self, primary_device_type: [u8; 8]) -> Self {
+        self.primary_device_type = primary_device_type;
+        self
+    }
+
+    pub fn with_os_version(mut self, os_version: u32) -> Self {
+        self.os_version = os_version;
+        self
+    }
+
+    /// Manufacturer up to Device Name. Empty strings are sent as a space,
+    /// some implementations reject empty attributes.
+    fn write(&self, out: &mut Writer<MAX_MESSAGE_LEN>) {
+        fn string(value: &[u8]) -> &[u8] {
+            if value.is_empty() {
+                b" "
+            } else {
+                value
+            }
+        }
+        out.attribute(ATTR_MANUFACTURER, string(self.manufacturer.as_ref()))
+            .attribute(ATTR_MODEL_NAME, string(self.model_name.as_ref()))
+            .attribute(ATTR_MODEL_NUMBER, string(self.model_number.as_ref()))
+            .attribute(ATTR_SERIAL_NUMBER, string(self.serial_number.as_ref()))
+            .attribute(ATTR_PRIMARY_DEVICE_TYPE, &self.primary_device_type)
+            .attribute(ATTR_DEVICE_NAME, string(self.device_name.as_ref()));
+    }
+
+    /// The most significant bit of OS Version is reserved and always set
+    fn os_version(&self) -> [u8; 4] {
+        (0x8000_0000 | self.os_version).to_be_bytes()
+    }
+}
+
+/// The informational device attributes are cut to the length WSC allows
+fn truncate<const N: usize>(value: &[u8]) -> OwnedSlice<N> {
+    OwnedSlice::from(&value[..value.len().min(N)])
+}
+
+/// The secret both sides prove knowledge of
+#[derive(Clone)]
+pub enum DevicePassword {
+    /// PIN, for example the 8 digits of a label or a display
+    Pin(OwnedSlice<MAX_PIN_LEN>),
+    /// Push button method, the buttons of both devices were pressed
+    PushButton,
+}
+
+impl DevicePassword {
+    pub fn pin(pin: &str) -> Self {
+        Self::Pin(pin.as_bytes().try_into().expect("pin too long for nostd"))
+    }
+
+    fn id(&self) -> u16 {
+        match self {
+            Self::Pin(_) => PASSWORD_ID_DEFAULT,
+            Self::PushButton => PASSWORD_ID_PUSH_BUTTON,
+        }
+    }
+
+    /// Whether the Device Password ID of the other side refers to this
+    /// password
+    fn matches(&self, id: u16) -> bool {
+        match self {
+            Self::Pin(_) => id == PASSWORD_ID_DEFAULT || id == PASSWORD_ID_USER_SPECIFIED,
+            Self::PushButton => id == PASSWORD_ID_PUSH_BUTTON,
+        }
+    }
+
+    fn password(&self) -> &[u8] {
+        match self {
+            Self::Pin(pin) => pin.as_ref(),
+            Self::PushButton => PUSH_BUTTON_PIN,
+        }
+    }
+}
+
+/// Network credential the Registrar hands to the Enrollee in M8
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Credential {
+    ssid: OwnedSlice<MAX_SSID_LEN>,
+    authentication_type: u16,
+    encryption_type: u16,
+    network_key: OwnedSlice<MAX_NETWORK_KEY_LEN>,
+    mac_address: [u8; ADDRESS_LEN],
+}
+
+impl Credential {
+    pub const AUTHENTICATION_OPEN: u16 = 0x0001;
+    pub const AUTHENTICATION_WPA_PSK: u16 = 0x0002;
+    pub const AUTHENTICATION_WPA2_PSK: u16 = 0x0020;
+
+    pub const ENCRYPTION_NONE: u16 = 0x0001;
+    pub const ENCRYPTION_TKIP: u16 = 0x0004;
+    pub const ENCRYPTION_AES: u16 = 0x0008;
+
+    /// `network_key` is the passphrase or the hex encoded PSK
+    pub fn new(
+        ssid: &[u8],
+        authentication_type: u16,
+        encryption_type: u16,
+        network_key: &[u8],
+    ) -> Self {
+        Self {
+            ssid: ssid.try_into().expect("ssid too long for nostd"),
+            authentication_type,
+            encryption_type,
+            network_key: network_key
+                .try_into()
+                .expect("network key too long for nostd"),
+            mac_address: [0; ADDRESS_LEN],
+        }
+    }
+
+    pub fn ssid(&self) -> &[u8] {
+        self.ssid.as_ref()
+    }
+
+    pub fn authentication_type(&self) -> u16 {
+        self.authentication_type
+    }
+
+    pub fn encryption_type(&self) -> u16 {
+        self.encryption_type
+    }
+
+    pub fn network_key(&self) -> &[u8] {
+        self.network_key.as_ref()
+    }
+
+    /// MAC address of the Enrollee the credential was issued to
+    pub fn mac_address(&self) -> [u8; ADDRESS_LEN] {
+        self.mac_address
+    }
+
+    /// Value of the Credential attribute, issued to `mac_address`
+    fn write(&self, mac_address: &[u8; ADDRESS_LEN], out: &mut Writer<MAX_SETTINGS_LEN>) {
+        out.u8(ATTR_NETWORK_INDEX, 1)
+            .attribute(ATTR_SSID, self.ssid.as_ref())
+            .u16(ATTR_AUTH_TYPE, self.authentication_type)
+            .u16(ATTR_ENCR_TYPE, self.encryption_type)
+            .attribute(ATTR_NETWORK_KEY, self.network_key.as_ref())
+            .attribute(ATTR_MAC_ADDRESS, mac_address);
+    }
+
+    /// The Network Key may only be missing from an open network
+    fn parse(value: &[u8]) -> Option<Self> {
+        let attributes = Attributes::parse(value)?;
+        let authentication_type = attributes.u16(ATTR_AUTH_TYPE)?;
+        let network_key = match attributes.get(ATTR_NETWORK_KEY) {
+            Some(network_key) => network_key,
+            None if authentication_type == Self::AUTHENTICATION_OPEN => &[],
+            None => return None,
+        };
+        Some(Self {
+            ssid: attributes.get(ATTR_SSID)?.try_into().ok()?,
+            authentication_type,
+            encryption_type: attributes.u16(ATTR_ENCR_TYPE)?,
+            network_key: network_key.try_into().ok()?,
+            mac_address: attributes.array(ATTR_MAC_ADDRESS)?,
+        })
+    }
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> Hash {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+    parts.iter().for_each(|part| mac.update(part));
+    mac.finalize().into_bytes().into()
+}
+
+/// kdf(key, label, bits), HMAC-SHA-256 in counter mode
+fn kdf(key: &[u8], label: &[u8], out: &mut [u8]) {
+    let bits = (out.len() as u32 * 8).to_be_bytes();
+    for (i, chunk) in out.chunks_mut(HASH_LEN).enumerate() {
+        let block = hmac(key, &[&(i as u32 + 1).to_be_bytes(), label, &bits]);
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+}
+
+fn encrypt_cbc(key: &Block, iv: &Block, data: &mut [u8]) {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    let mut previous = *iv;
+    for block in data.chunks_exact_mut(BLOCK_LEN) {
+        block.iter_mut().zip(previous).for_each(|(b, p)| *b ^= p);
+        cipher.encrypt_block(GenericArray::from_mut_slice(block));
+        previous.copy_from_slice(block);
+    }
+}
+
+fn decrypt_cbc(key: &Block, iv: &Block, data: &mut [u8]) {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    let mut previous = *iv;
+    for block in data.chunks_exact_mut(BLOCK_LEN) {
+        let ciphertext: Block = (*block).try_into().unwrap();
+        cipher.decrypt_block(GenericArray::from_mut_slice(block));
+        block.iter_mut().zip(previous).for_each(|(b, p)| *b ^= p);
+        previous = ciphertext;
+    }
+}
+
+fn dh_pow(exponent: &[u8; EXPONENT_LEN], base: &U1536) -> [u8; PUBLIC_KEY_LEN] {
+    let params = DynResidueParams::new(&PRIME);
+    DynResidue::new(base, params)
+        .pow(&U256::from_be_slice(exponent))
+        .retrieve()
+        .to_be_bytes()
+}
+
+/// Op-Code and message of a received packet, `None` for fragments and
+/// oversized messages
+fn parse_packet(msg: &[u8]) -> Option<(u8, &[u8])> {
+    let mut reader = ByteReader::new(msg);
+    let op_code = reader.u8()?;
+    let flags = reader.u8()?;
+    if flags & FLAG_MORE_FRAGMENTS != 0 {
+        return None;
+    }
+    if flags & FLAG_LENGTH != 0 {
+        reader
+            .u16()
+            .filter(|len| *len as usize == reader.remaining().len())?;
+    }
+    let message = reader.remaining();
+    (message.len() <= MAX_MESSAGE_LEN).then_some((op_code, message))
+}
+
+fn packet<'a>(env: &'a mut dyn EapEnvironment, op_code: u8, message: &[u8]) -> MessageBuilder<'a> {
+    env.respond().write(&[op_code, 0]).write(message)
+}
+
+/// State of a registration shared by both roles: the key exchange, the
+/// device password commitments and the message chain of the Authenticator.
+#[derive(Clone)]
+struct Session {
+    enrollee: bool,
+    enrollee_nonce: [u8; NONCE_LEN],
+    registrar_nonce: [u8; NONCE_LEN],
+    exponent: [u8; EXPONENT_LEN],
+    enrollee_public_key: [u8; PUBLIC_KEY_LEN],
+    registrar_public_key: [u8; PUBLIC_KEY_LEN],
+    auth_key: Hash,
+    key_wrap_key: Block,
+    psk1: [u8; PSK_LEN],
+    psk2: [u8; PSK_LEN],
+    /// Own secret nonces, revealed one by one in the Encrypted Settings
+    secret_nonce1: [u8; NONCE_LEN],
+    secret_nonce2: [u8; NONCE_LEN],
+    /// Hashes the other side committed to, checked once it reveals its secret
+    /// nonces
+    hash1: Hash,
+    hash2: Hash,
+    /// The previous message, covered by the Authenticator of the next one
+    last_message: [u8; MAX_MESSAGE_LEN],
+    last_message_len: usize,
+}
+
+impl Session {
+    fn new(enrollee: bool) -> Self {
+        Self {
+            enrollee,
+            enrollee_nonce: [0; NONCE_LEN],
+            registrar_nonce: [0; NONCE_LEN],
+            exponent: [0; EXPONENT_LEN],
+            enrollee_public_key: [0; PUBLIC_KEY_LEN],
+            registrar_public_key: [0; PUBLIC_KEY_LEN],
+            auth_key: [0; HASH_LEN],
+            key_wrap_key: [0; BLOCK_LEN],
+            psk1: [0; PSK_LEN],
+            psk2: [0; PSK_LEN],
+            secret_nonce1: [0; NONCE_LEN],
+            secret_nonce2: [0; NONCE_LEN],
+            hash1: [0; HASH_LEN],
+            hash2: [0; HASH_LEN],
+            last_message: [0; MAX_MESSAGE_LEN],
+            last_message_len: 0,
+        }
+    }
+
+    /// Fresh nonce and DH key of the own side
+    fn start(&mut self, env: &mut dyn EapEnvironment) {
+        *self = Self::new(self.enrollee);
+        env.fill_random(&mut self.exponent);
+        let public_key = dh_pow(&self.exponent, &U1536::from_u8(GENERATOR));
+        if self.enrollee {
+            env.fill_random(&mut self.enrollee_nonce);
+            self.enrollee_public_key = public_key;
+        } else {
+            env.fill_random(&mut self.registrar_nonce);
+            self.registrar_public_key = public_key;
+        }
+    }
+
+    fn own_public_key(&self) -> &[u8; PUBLIC_KEY_LEN] {
+        match self.enrollee {
+            true => &self.enrollee_public_key,
+            false => &self.registrar_public_key,
+        }
+    }
+
+    /// Stores the public key of the other side and derives the keys:
+    /// DHKey = SHA-256(g^AB mod p),
+    /// KDK = HMAC_DHKey(N1 | EnrolleeMAC | N2),
+    /// AuthKey | KeyWrapKey | EMSK = kdf(KDK, label, 640).
+    /// `None` if the public key is not in (1, p - 1).
+    fn derive_keys(
+        &mut self,
+        public_key: &[u8],
+        enrollee_mac: &[u8; ADDRESS_LEN],
+        password: &[u8],
+    ) -> Option<()> {
+        let public_key: [u8; PUBLIC_KEY_LEN] = public_key.try_into().ok()?;
+        let y = U1536::from_be_slice(&public_key);
+        if y <= U1536::ONE || y >= PRIME.wrapping_sub(&U1536::ONE) {
+            return None;
+        }
+        match self.enrollee {
+            true => self.registrar_public_key = public_key,
+            false => self.enrollee_public_key = public_key,
+        }
+
+        let dh_key: Hash = Sha256::digest(dh_pow(&self.exponent, &y)).into();
+        let kdk = hmac(
+            &dh_key,
+            &[&self.enrollee_nonce, enrollee_mac, &self.registrar_nonce],
+        );
+        let mut keys = [0u8; KDF_LEN];
+        kdf(&kdk, KDF_LABEL, &mut keys);
+        self.auth_key.copy_from_slice(&keys[..HASH_LEN]);
+        self.key_wrap_key
+            .copy_from_slice(&keys[HASH_LEN..HASH_LEN + BLOCK_LEN]);
+
+        // PSK1 and PSK2 commit to the first and the second half of the
+        // password, the first half takes the odd digit
+        let (first, second) = password.split_at(password.len().div_ceil(2));
+        self.psk1
+            .copy_from_slice(&hmac(&self.auth_key, &[first])[..PSK_LEN]);
+        self.psk2
+            .copy_from_slice(&hmac(&self.auth_key, &[second])[..PSK_LEN]);
+        Some(())
+    }
+
+    /// E-Hash or R-Hash = HMAC_AuthKey(S | PSK | PK_E | PK_R)
+    fn hash(&self, secret_nonce: &[u8], psk: &[u8; PSK_LEN]) -> Hash {
+        hmac(
+            &self.auth_key,
+            &[
+                secret_nonce,
+                psk,
+                &self.enrollee_public_key,
+                &self.registrar_public_key,
+            ],
+        )
+    }
+
+    /// Fresh secret nonces and the two hashes committing to them
+    fn own_hashes(&mut self, env: &mut dyn EapEnvironment) -> (Hash, Hash) {
+        env.fill_random(&mut self.secret_nonce1);
+        env.fill_random(&mut self.secret_nonce2);
+        (
+            self.hash(&self.secret_nonce1, &self.psk1),
+            self.hash(&self.secret_nonce2, &self.psk2),
+        )
+    }
+
+    /// Checks the first or the second half of the device password
+    fn check_hash(&self, second: bool, secret_nonce: &[u8]) -> bool {
+        let (hash, psk) = match second {
+            false => (&self.hash1, &self.psk1),
+            true => (&self.hash2, &self.psk2),
+        };
+        constant_time_eq(&self.hash(secret_nonce, psk), hash)
+    }
+
+    fn remember(&mut self, message: &[u8]) {
+        self.last_message[..message.len()].copy_from_slice(message);
+        self.last_message_len = message.len();
+    }
+
+    /// Authenticator = HMAC_AuthKey(previous message | message)
+    fn authenticator(&self, message: &[u8]) -> [u8; AUTHENTICATOR_LEN] {
+        let last_message = &self.last_message[..self.last_message_len];
+        hmac(&self.auth_key, &[last_message, message])[..AUTHENTICATOR_LEN]
+            .try_into()
+            .unwrap()
+    }
+
+    /// Appends the Authenticator, the message becomes the previous one
+    fn authenticate(&mut self, out: &mut Writer<MAX_MESSAGE_LEN>) {
+        let authenticator = self.authenticator(out.as_slice());
+        out.attribute(ATTR_AUTHENTICATOR, &authenticator);
+        self.remember(out.as_slice());
+    }
+
+    /// Checks the Authenticator of a received message, which becomes the
+    /// previous one
+    fn check_authenticator(&mut self, message: &[u8], attributes: &Attributes) -> bool {
+        let valid = attributes
+            .last(ATTR_AUTHENTICATOR)
+            .is_some_and(|(covered, authenticator)| {
+                constant_time_eq(&self.authenticator(covered), authenticator)
+            });
+        if valid {
+            self.remember(message);
+        }
+        valid
+    }
+
+    /// Appends Encrypted Settings = IV | AES-CBC(KeyWrapKey, settings | KWA),
+    /// KWA = HMAC_AuthKey(settings), padded to the block size
+    fn encrypt_settings(
+        &self,
+        settings: &Writer<MAX_SETTINGS_LEN>,
+        env: &mut dyn EapEnvironment,
+        out: &mut Writer<MAX_MESSAGE_LEN>,
+    ) {
+        let settings = settings.as_slice();
+        let kwa = hmac(&self.auth_key, &[settings]);
+        let mut plaintext = Writer::<MAX_SETTINGS_LEN>::new();
+        plaintext.attribute(
+            ATTR_KEY_WRAP_AUTHENTICATOR,
+            &kwa[..KEY_WRAP_AUTHENTICATOR_LEN],
+        );
+        let len = settings.len() + plaintext.as_slice().len();
+        let padded_len = (len / BLOCK_LEN + 1) * BLOCK_LEN;
+
+        let mut value = [0u8; BLOCK_LEN + MAX_SETTINGS_LEN];
+        env.fill_random(&mut value[..BLOCK_LEN]);
+        let (iv, data) = value.split_at_mut(BLOCK_LEN);
+        data[..settings.len()].copy_from_slice(settings);
+        data[settings.len()..len].copy_from_slice(plaintext.as_slice());
+        data[len..padded_len].fill((padded_len - len) as u8);
+        encrypt_cbc(
+            &self.key_wrap_key,
+            &(*iv).try_into().unwrap(),
+            &mut data[..padded_len],
+        );
+        out.attribute(ATTR_ENCRYPTED_SETTINGS, &value[..BLOCK_LEN + padded_len]);
+    }
+
+    /// Decrypts the Encrypted Settings of a message into `buffer`, `None` if
+    /// the padding or the Key Wrap Authenticator is wrong
+    fn decrypt_settings<'b>(
+        &self,
+        attributes: &Attributes,
+        buffer: &'b mut [u8; MAX_SETTINGS_LEN],
+    ) -> Option<Attributes<'b>> {
+        let value = attributes.get(ATTR_ENCRYPTED_SETTINGS)?;
+        if value.len() < 2 * BLOCK_LEN
+            || value.len() % BLOCK_LEN != 0
+            || value.len() > BLOCK_LEN + MAX_SETTINGS_LEN
+        {
+            return None;
+        }
+        let (iv, ciphertext) = value.split_at(BLOCK_LEN);
+        let data = &mut buffer[..ciphertext.len()];
+        data.copy_from_slice(ciphertext);
+        decrypt_cbc(&self.key_wrap_key, &iv.try_into().unwrap(), data);
+
+        let padding = *data.last()? as usize;
+        if padding == 0
+            || padding > BLOCK_LEN
+            || data[data.len() - padding..]
+                .iter()
+                .any(|b| *b as usize != padding)
+        {
+            return None;
+        }
+        let len = data.len() - padding;
+        let settings = Attributes::parse(&buffer[..len])?;
+        let (covered, kwa) = settings.last(ATTR_KEY_WRAP_AUTHENTICATOR)?;
+        constant_time_eq(
+            &hmac(&self.auth_key, &[covered])[..KEY_WRAP_AUTHENTICATOR_LEN],
+            kwa,
+        )
+        .then_some(settings)
+    }
+
+    /// WSC_ACK, WSC_NACK and WSC_Done
+    fn status_message(
+        &self,
+        message_type: u8,
+        config_error: Option<u16>,
+    ) -> Writer<MAX_MESSAGE_LEN> {
+        let mut out = Writer::new();
+        out.header(message_type)
+            .attribute(ATTR_ENROLLEE_NONCE, &self.enrollee_nonce)
+            .attribute(ATTR_REGISTRAR_NONCE, &self.registrar_nonce);
+        if let Some(config_error) = config_error {
+            out.u16(ATTR_CONFIG_ERROR, config_error);
+        }
+        out.attribute(ATTR_VENDOR_EXTENSION, &WFA_EXTENSION);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::hex_to_vec, DefaultEnvironment};
+
+    #[test]
+    fn key_derivation() {
+        let mut out = [0u8; KDF_LEN];
+        kdf(&[0x0b; HASH_LEN], KDF_LABEL, &mut out);
+        assert_eq!(
+            out.to_vec(),
+            hex_to_vec(
+                "2b c4 83 7b 1a 80 62 5b 66 60 03 4b 25 73 b7 2f \
+                 fd 66 67 49 2a 4b aa 37 06 9f 8d 18 80 89 8c 5b \
+                 05 db 74 20 1d ee db ab 47 26 61 ed f4 f1 ed d4 \
+                 d1 ee ea 50 51 e0 50 7b ec 6d d4 4a b5 10 5f d1 \
+                 80 22 e3 71 bc f3 b4 1e ef a9 58 ad 14 42 bf 46"
+            )
+        );
+    }
+
+    #[test]
+    fn encrypted_settings() {
+        let mut env = DefaultEnvironment::new();
+        let mut session = Session::new(true);
+        session.start(&mut env);
+        session.auth_key = [1; HASH_LEN];
+        session.key_wrap_key = [2; BLOCK_LEN];
+
+        let mut settings = Writer::<MAX_SETTINGS_LEN>::new();
+        settings.attribute(ATTR_E_SNONCE1, &[3; NONCE_LEN]);
+        let mut message = Writer::<MAX_MESSAGE_LEN>::new();
+        session.encrypt_settings(&settings, &mut env, &mut message);
+        // IV, nonce and KWA padded to three blocks
+        assert_eq!(
+            message.as_slice().len(),
+            ATTRIBUTE_HEADER_LEN + 4 * BLOCK_LEN
+        );
+
+        let attributes = Attributes::parse(message.as_slice()).unwrap();
+        let mut buffer = [0u8; MAX_SETTINGS_LEN];
+        let decrypted = session.decrypt_settings(&attributes, &mut buffer).unwrap();
+        assert_eq!(decrypted.get(ATTR_E_SNONCE1), Some(&[3; NONCE_LEN][..]));
+
+        session.auth_key = [9; HASH_LEN];
+        assert!(session.decrypt_settings(&attributes, &mut buffer).is_none());
+    }
+}
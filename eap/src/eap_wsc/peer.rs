@@ -0,0 +1,324 @@
+use crate::layers::{
+    eap_layer::Decision,
+    mux::TupleElement,
+    peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta},
+};
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    WaitStart,
+    WaitM2,
+    WaitM4,
+    WaitM6,
+    WaitM8,
+    /// WSC_Done was sent, the Registrar ends with an EAP-Failure
+    Done,
+    /// A WSC_NACK was sent
+    Failing,
+}
+
+/// Peer side of EAP-WSC, the Enrollee.
+///
+/// `credential_callback` receives each credential of M8. The peer has to use
+/// [`IDENTITY_ENROLLEE`] as EAP identity.
+#[derive(Clone)]
+pub struct PeerWscMethod<F> {
+    device: Device,
+    password: DevicePassword,
+    credential_callback: F,
+    state: State,
+    session: Session,
+}
+
+impl<F> TupleElement for PeerWscMethod<F>
+where
+    F: FnMut(&Credential) + 'static,
+{
+    type Target = dyn PeerMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<F> PeerWscMethod<F>
+where
+    F: FnMut(&Credential),
+{
+    pub fn new(device: Device, password: DevicePassword, credential_callback: F) -> Self {
+        Self {
+            device,
+            password,
+            credential_callback,
+            state: State::WaitStart,
+            session: Session::new(true),
+        }
+    }
+
+    fn send<'a>(
+        &mut self,
+        state: State,
+        op_code: u8,
+        out: &Writer<MAX_MESSAGE_LEN>,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        self.state = state;
+        PeerMethodLayerResult::Send(packet(env, op_code, out.as_slice()))
+    }
+
+    fn nack<'a>(
+        &mut self,
+        config_error: u16,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let out = self
+            .session
+            .status_message(MESSAGE_NACK, Some(config_error));
+        self.send(State::Failing, OP_NACK, &out, env)
+    }
+
+    /// WSC_Start, answered by M1
+    fn recv_start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> PeerMethodLayerResult<'a> {
+        self.session.start(env);
+        let mut out = Writer::new();
+        out.header(MESSAGE_M1)
+            .attribute(ATTR_UUID_E, &self.device.uuid)
+            .attribute(ATTR_MAC_ADDRESS, &self.device.mac_address)
+            .attribute(ATTR_ENROLLEE_NONCE, &self.session.enrollee_nonce)
+            .attribute(ATTR_PUBLIC_KEY, self.session.own_public_key())
+            .u16(ATTR_AUTH_TYPE_FLAGS, AUTHENTICATION_FLAGS)
+            .u16(ATTR_ENCR_TYPE_FLAGS, ENCRYPTION_FLAGS)
+            .u8(ATTR_CONN_TYPE_FLAGS, CONN_TYPE_ESS)
+            .u16(ATTR_CONFIG_METHODS, CONFIG_METHODS)
+            .u8(ATTR_WPS_STATE, WPS_STATE_NOT_CONFIGURED);
+        self.device.write(&mut out);
+        out.u8(ATTR_RF_BANDS, RF_BAND_2_4_GHZ)
+            .u16(ATTR_ASSOC_STATE, ASSOC_NOT_ASSOCIATED)
+            .u16(ATTR_DEVICE_PASSWORD_ID, self.password.id())
+            .u16(ATTR_CONFIG_ERROR, CONFIG_NO_ERROR)
+            .attribute(ATTR_OS_VERSION, &self.device.os_version())
+            .attribute(ATTR_VENDOR_EXTENSION, &WFA_EXTENSION);
+
+        self.session.remember(out.as_slice());
+        self.send(State::WaitM2, OP_MSG, &out, env)
+    }
+
+    /// M2D, the Registrar has no device password for us. It is acknowledged,
+    /// another Registrar may still send M2.
+    fn recv_m2d<'a>(
+        &mut self,
+        attributes: &Attributes,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let Some(registrar_nonce) = attributes.array(ATTR_REGISTRAR_NONCE) else {
+            return self.nack(CONFIG_NO_ERROR, env);
+        };
+        self.session.registrar_nonce = registrar_nonce;
+        let out = self.session.status_message(MESSAGE_ACK, None);
+        self.send(State::WaitM2, OP_ACK, &out, env)
+    }
+
+    /// M2 = (N1, N2, PK_R, Device Password ID), answered by
+    /// M3 = (N2, E-Hash1, E-Hash2)
+    fn recv_m2<'a>(
+        &mut self,
+        message: &[u8],
+        attributes: &Attributes,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let (Some(registrar_nonce), Some(public_key), Some(_)) = (
+            attributes.array(ATTR_REGISTRAR_NONCE),
+            attributes.get(ATTR_PUBLIC_KEY),
+            attributes
+                .u16(ATTR_DEVICE_PASSWORD_ID)
+                .filter(|id| self.password.matches(*id)),
+        ) else {
+            return self.nack(CONFIG_NO_ERROR, env);
+        };
+        self.session.registrar_nonce = registrar_nonce;
+        let mac_address = self.device.mac_address;
+        if self
+            .session
+            .derive_keys(public_key, &mac_address, self.password.password())
+            .is_none()
+            || !self.session.check_authenticator(message, attributes)
+        {
+            return self.nack(CONFIG_NO_ERROR, env);
+        }
+
+        let (e_hash1, e_hash2) = self.session.own_hashes(env);
+        let mut out = Writer::new();
+        out.header(MESSAGE_M3)
+            .attribute(ATTR_REGISTRAR_NONCE, &self.session.registrar_nonce)
+            .attribute(ATTR_E_HASH1, &e_hash1)
+            .attribute(ATTR_E_HASH2, &e_hash2)
+            .attribute(ATTR_VENDOR_EXTENSION, &WFA_EXTENSION);
+        self.session.authenticate(&mut out);
+        self.send(State::WaitM4, OP_MSG, &out, env)
+    }
+
+    /// M4 = (R-Hash1, R-Hash2, {R-SNonce1}), M6 = ({R-SNonce2}). Each reveals
+    /// a secret nonce of the Registrar, answered by M5 = ({E-SNonce1}) and
+    /// M7 = ({E-SNonce2}), which reveal ours.
+    fn recv_snonce<'a>(
+        &mut self,
+        second: bool,
+        message: &[u8],
+        attributes: &Attributes,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        if !self.session.check_authenticator(message, attributes) {
+            return self.nack(CONFIG_NO_ERROR, env);
+        }
+        if !second {
+            let (Some(r_hash1), Some(r_hash2)) = (
+                attributes.array(ATTR_R_HASH1),
+                attributes.array(ATTR_R_HASH2),
+            ) else {
+                return self.nack(CONFIG_NO_ERROR, env);
+            };
+            self.session.hash1 = r_hash1;
+            self.session.hash2 = r_hash2;
+        }
+
+        let mut buffer = [0u8; MAX_SETTINGS_LEN];
+        let Some(settings) = self.session.decrypt_settings(attributes, &mut buffer) else {
+            return self.nack(CONFIG_DECRYPTION_FAILURE, env);
+        };
+        let (nonce_type, own_nonce_type, own_nonce, message_type, state) = match second {
+            false => (
+                ATTR_R_SNONCE1,
+                ATTR_E_SNONCE1,
+                self.session.secret_nonce1,
+                MESSAGE_M5,
+                State::WaitM6,
+            ),
+            true => (
+                ATTR_R_SNONCE2,
+                ATTR_E_SNONCE2,
+                self.session.secret_nonce2,
+                MESSAGE_M7,
+                State::WaitM8,
+            ),
+        };
+        let valid = settings
+            .get(nonce_type)
+            .is_some_and(|nonce| self.session.check_hash(second, nonce));
+        if !valid {
+            return self.nack(CONFIG_DEVICE_PASSWORD_AUTH_FAILURE, env);
+        }
+
+        let mut encrypted = Writer::new();
+        encrypted.attribute(own_nonce_type, &own_nonce);
+        let mut out = Writer::new();
+        out.header(message_type)
+            .attribute(ATTR_REGISTRAR_NONCE, &self.session.registrar_nonce);
+        self.session.encrypt_settings(&encrypted, env, &mut out);
+        out.attribute(ATTR_VENDOR_EXTENSION, &WFA_EXTENSION);
+        self.session.authenticate(&mut out);
+        self.send(state, OP_MSG, &out, env)
+    }
+
+    /// M8 = ({Credential}), answered by WSC_Done
+    fn recv_m8<'a>(
+        &mut self,
+        message: &[u8],
+        attributes: &Attributes,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        if !self.session.check_authenticator(message, attributes) {
+            return self.nack(CONFIG_NO_ERROR, env);
+        }
+        let mut buffer = [0u8; MAX_SETTINGS_LEN];
+        let Some(settings) = self.session.decrypt_settings(attributes, &mut buffer) else {
+            return self.nack(CONFIG_DECRYPTION_FAILURE, env);
+        };
+
+        let mut received = false;
+        for (_, value) in settings.iter().filter(|(t, _)| *t == ATTR_CREDENTIAL) {
+            let Some(credential) = Credential::parse(value) else {
+                return self.nack(CONFIG_NO_ERROR, env);
+            };
+            (self.credential_callback)(&credential);
+            received = true;
+        }
+        if !received {
+            return self.nack(CONFIG_NO_ERROR, env);
+        }
+
+        let out = self.session.status_message(MESSAGE_DONE, None);
+        self.send(State::Done, OP_DONE, &out, env)
+    }
+
+    fn recv_message<'a>(
+        &mut self,
+        message: &[u8],
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        // Every message of the Registrar echoes our nonce
+        let Some(attributes) = Attributes::parse(message).filter(|attributes| {
+            attributes.get(ATTR_ENROLLEE_NONCE) == Some(&self.session.enrollee_nonce[..])
+        }) else {
+            return self.nack(CONFIG_NO_ERROR, env);
+        };
+        match (self.state, attributes.u8(ATTR_MESSAGE_TYPE)) {
+            (State::WaitM2, Some(MESSAGE_M2D)) => self.recv_m2d(&attributes, env),
+            (State::WaitM2, Some(MESSAGE_M2)) => self.recv_m2(message, &attributes, env),
+            (State::WaitM4, Some(MESSAGE_M4)) => self.recv_snonce(false, message, &attributes, env),
+            (State::WaitM6, Some(MESSAGE_M6)) => self.recv_snonce(true, message, &attributes, env),
+            (State::WaitM8, Some(MESSAGE_M8)) => self.recv_m8(message, &attributes, env),
+            _ => self.nack(CONFIG_NO_ERROR, env),
+        }
+    }
+}
+
+impl<F> PeerMethodLayer for PeerWscMethod<F>
+where
+    F: FnMut(&Credential),
+{
+    fn method_identifier(&self) -> MethodType {
+        METHOD_WSC
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::eap_wsc(matches!(self.password, DevicePassword::PushButton))
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        let Some((op_code, message)) = parse_packet(msg) else {
+            return PeerMethodLayerResult::Failed(env);
+        };
+        match (self.state, op_code) {
+            (_, OP_START) => self.recv_start(env),
+            (State::WaitStart, _) => PeerMethodLayerResult::Failed(env),
+            // A WSC_NACK of the Registrar is answered with our own
+            (State::Failing, _) | (_, OP_NACK) => self.nack(CONFIG_NO_ERROR, env),
+            (_, OP_MSG) => self.recv_message(message, env),
+            _ => self.nack(CONFIG_NO_ERROR, env),
+        }
+    }
+
+    /// A registration never ends with EAP-Success
+    fn decision(&self) -> Decision {
+        Decision::Fail
+    }
+
+    fn reset(&mut self) {
+        self.state = State::WaitStart;
+    }
+}
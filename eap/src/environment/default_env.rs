@@ -1,4 +1,4 @@
-use crate::util::OwnedSlice;
+use crate::{layers::Negotiation, util::OwnedSlice};
 
 use super::{EapEnvironment, ResponseBufferState};
 
@@ -7,11 +7,24 @@ pub type DefaultEnvironment = StdBoxEnvironment;
 
 const DEFAULT_RESPONSE_BUFFER_SIZE: usize = 1020;
 
+#[cfg(feature = "std")]
+type NotificationCallback = Box<dyn FnMut(&[u8])>;
+
+#[cfg(feature = "std")]
+type NegotiationCallback = Box<dyn FnMut(&Negotiation)>;
+
+#[cfg(feature = "std")]
+type Clock = Box<dyn Fn() -> u64>;
+
 #[cfg(feature = "std")]
 pub struct StdBoxEnvironment {
     name: Option<Vec<u8>>,
     response_buffer: Vec<u8>,
     response_buffer_state: ResponseBufferState,
+    notification_callback: Option<NotificationCallback>,
+    negotiation_callback: Option<NegotiationCallback>,
+    clock: Option<Clock>,
+    unix_clock: Option<Clock>,
 }
 
 #[cfg(feature = "std")]
@@ -21,6 +34,10 @@ impl Default for StdBoxEnvironment {
             name: None,
             response_buffer: vec![0; DEFAULT_RESPONSE_BUFFER_SIZE],
             response_buffer_state: ResponseBufferState::default(),
+            notification_callback: None,
+            negotiation_callback: None,
+            clock: None,
+            unix_clock: None,
         }
     }
 }
@@ -36,8 +53,35 @@ impl StdBoxEnvironment {
             response_buffer: vec![0; mtu],
             response_buffer_state: ResponseBufferState::default(),
             name: None,
+            notification_callback: None,
+            negotiation_callback: None,
+            clock: None,
+            unix_clock: None,
         }
     }
+
+    /// `callback` receives the text of each Notification Request
+    pub fn set_notification_callback(&mut self, callback: impl FnMut(&[u8]) + 'static) {
+        self.notification_callback = Some(Box::new(callback));
+    }
+
+    /// `callback` receives the outcome of each method negotiation, see
+    /// [`EapEnvironment::negotiation`]
+    pub fn set_negotiation_callback(&mut self, callback: impl FnMut(&Negotiation) + 'static) {
+        self.negotiation_callback = Some(Box::new(callback));
+    }
+
+    /// `clock` returns milliseconds of a monotonic clock, see
+    /// [`EapEnvironment::now`]
+    pub fn set_clock(&mut self, clock: impl Fn() -> u64 + 'static) {
+        self.clock = Some(Box::new(clock));
+    }
+
+    /// `unix_clock` returns seconds since the unix epoch, see
+    /// [`EapEnvironment::unix_time`]. Without one the system time is used.
+    pub fn set_unix_clock(&mut self, unix_clock: impl Fn() -> u64 + 'static) {
+        self.unix_clock = Some(Box::new(unix_clock));
+    }
 }
 
 #[cfg(feature = "std")]
@@ -54,6 +98,32 @@ impl EapEnvironment for StdBoxEnvironment {
         getrandom::getrandom(buf).unwrap();
     }
 
+    fn notification(&mut self, text: &[u8]) {
+        if let Some(callback) = &mut self.notification_callback {
+            callback(text);
+        }
+    }
+
+    fn negotiation(&mut self, negotiation: &Negotiation) {
+        if let Some(callback) = &mut self.negotiation_callback {
+            callback(negotiation);
+        }
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.clock.as_ref().map(|clock| clock())
+    }
+
+    fn unix_time(&self) -> Option<u64> {
+        match &self.unix_clock {
+            Some(unix_clock) => Some(unix_clock()),
+            None => std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok()
+                .map(|elapsed| elapsed.as_secs()),
+        }
+    }
+
     fn response_buffer_state(&mut self) -> &mut ResponseBufferState {
         &mut self.response_buffer_state
     }
@@ -72,6 +142,10 @@ pub struct StaticEnvironment<const N: usize = 1020> {
     response_buffer: [u8; N],
     response_buffer_state: ResponseBufferState,
     random_function: fn(&mut [u8]),
+    notification_function: Option<fn(&[u8])>,
+    negotiation_function: Option<fn(&Negotiation)>,
+    clock_function: Option<fn() -> u64>,
+    unix_time_function: Option<fn() -> u64>,
 }
 
 impl<const N: usize> StaticEnvironment<N> {
@@ -81,8 +155,40 @@ impl<const N: usize> StaticEnvironment<N> {
             response_buffer: [0; N],
             response_buffer_state: ResponseBufferState::default(),
             random_function,
+            notification_function: None,
+            negotiation_function: None,
+            clock_function: None,
+            unix_time_function: None,
         }
     }
+
+    /// `notification_function` receives the text of each Notification
+    /// Request
+    pub fn with_notification_function(mut self, notification_function: fn(&[u8])) -> Self {
+        self.notification_function = Some(notification_function);
+        self
+    }
+
+    /// `negotiation_function` receives the outcome of each method
+    /// negotiation, see [`EapEnvironment::negotiation`]
+    pub fn with_negotiation_function(mut self, negotiation_function: fn(&Negotiation)) -> Self {
+        self.negotiation_function = Some(negotiation_function);
+        self
+    }
+
+    /// `clock_function` returns milliseconds of a monotonic clock, see
+    /// [`EapEnvironment::now`]
+    pub fn with_clock_function(mut self, clock_function: fn() -> u64) -> Self {
+        self.clock_function = Some(clock_function);
+        self
+    }
+
+    /// `unix_time_function` returns seconds since the unix epoch, see
+    /// [`EapEnvironment::unix_time`]
+    pub fn with_unix_time_function(mut self, unix_time_function: fn() -> u64) -> Self {
+        self.unix_time_function = Some(unix_time_function);
+        self
+    }
 }
 
 impl<const N: usize> EapEnvironment for StaticEnvironment<N> {
@@ -103,6 +209,27 @@ impl<const N: usize> EapEnvironment for StaticEnvironment<N> {
         (self.random_function)(buf)
     }
 
+    fn notification(&mut self, text: &[u8]) {
+        if let Some(notification_function) = self.notification_function {
+            notification_function(text);
+        }
+    }
+
+    fn negotiation(&mut self, negotiation: &Negotiation) {
+        if let Some(negotiation_function) = self.negotiation_function {
+            negotiation_function(negotiation);
+        }
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.clock_function.map(|clock_function| clock_function())
+    }
+
+    fn unix_time(&self) -> Option<u64> {
+        self.unix_time_function
+            .map(|unix_time_function| unix_time_function())
+    }
+
     fn response_buffer_state(&mut self) -> &mut ResponseBufferState {
         &mut self.response_buffer_state
     }
@@ -1,7 +1,20 @@
 pub mod default_env;
 pub use default_env::*;
 
-use crate::message::MessageCode;
+use crate::{
+    layers::{method_type::EXPANDED_TYPE_LEN, Negotiation},
+    message::MessageCode,
+};
+
+/// Space reserved in front of a response for the EAP header and the type,
+/// which may be an Expanded Type
+const HEADROOM: usize = 4 + EXPANDED_TYPE_LEN;
+
+/// Bytes of type and data that fit in a response of `env`
+pub(crate) fn response_capacity(env: &dyn EapEnvironment) -> usize {
+    env.response_buffer().len().saturating_sub(HEADROOM)
+}
+
 pub trait EapEnvironment {
     fn set_name(&mut self, name: &[u8]); // <- Extract Somehow
 
@@ -19,8 +32,70 @@ pub trait EapEnvironment {
         10 // Some default value
     }
 
+    /// Milliseconds of a monotonic clock. With a clock the state machine
+    /// runs on deadlines, see [`EapLayer::next_deadline`](crate::layers::EapLayer::next_deadline),
+    /// without one every timeout event counts as an expired timer.
+    fn now(&self) -> Option<u64> {
+        None
+    }
+
+    /// Seconds since the unix epoch, the wall clock for time based one time
+    /// passwords. Unlike [`now`](Self::now) it may be set back.
+    fn unix_time(&self) -> Option<u64> {
+        None
+    }
+
+    /// Retransmission timeout in milliseconds before the first RTT
+    /// measurement
+    fn initial_retransmit_timeout(&self) -> u32 {
+        3_000 // RFC 2988
+    }
+
+    fn min_retransmit_timeout(&self) -> u32 {
+        200 // RFC 3748 4.3
+    }
+
+    /// Upper bound of the retransmission timeout, also with backoff
+    fn max_retransmit_timeout(&self) -> u32 {
+        60_000 // RFC 3748 4.3
+    }
+
+    /// ClientTimeout, milliseconds the peer waits for the next request
+    fn idle_timeout(&self) -> u32 {
+        60_000 // Suggested by RFC 4137
+    }
+
+    /// Requests answered on the peer, responses processed on the
+    /// authenticator
+    fn max_round_trips(&self) -> u16 {
+        100 // Same as wpa_supplicant
+    }
+
+    /// Round trips of a single method, including its fragments
+    fn max_method_round_trips(&self) -> u16 {
+        50 // Some default value
+    }
+
+    /// Bytes of all messages received, valid or not
+    fn max_bytes_received(&self) -> u32 {
+        256 * 1024 // Some default value
+    }
+
+    /// Milliseconds from the start, only enforced with a clock
+    fn max_session_duration(&self) -> u64 {
+        5 * 60_000 // Some default value
+    }
+
     fn fill_random(&self, buf: &mut [u8]);
 
+    /// Displays the text of a Notification Request to the user, RFC 3748 5.2.
+    /// `text` is the whole Type-Data of the Request, no limit applies here.
+    fn notification(&mut self, _text: &[u8]) {}
+
+    /// Reports the outcome of the method negotiation once it is settled,
+    /// i.e. a method other than Identity runs or none is left
+    fn negotiation(&mut self, _negotiation: &Negotiation) {}
+
     fn response_buffer_state(&mut self) -> &mut ResponseBufferState;
     fn response_buffer_mut(&mut self) -> &mut [u8];
     fn response_buffer(&self) -> &[u8];
@@ -64,7 +139,7 @@ impl<'a> EapEnvironmentResponse<'a> for &'a mut dyn EapEnvironment {
         *self.response_buffer_state() = ResponseBufferState::Dirty;
         MessageBuilder {
             env: EnvOrOwned::Env(self),
-            offset: 5,
+            offset: HEADROOM,
             length: 0,
         }
     }
@@ -211,7 +286,7 @@ impl<'a> MessageBuilder<'a> {
 impl<'a, 'b> From<&'a [u8]> for MessageBuilder<'b> {
     fn from(message: &'a [u8]) -> Self {
         // reserve space for the header
-        let offset = 5;
+        let offset = HEADROOM;
         let mut buffer = vec![0u8; offset];
         buffer.extend_from_slice(message);
 
@@ -0,0 +1,498 @@
+//! ERP, the EAP Re-authentication Protocol, see RFC 6696
+//!
+//! After a full authentication both sides derive the re-authentication root
+//! key rRK and the integrity key rIK from the EMSK. A later conversation then
+//! ends after a single round trip: the peer proves knowledge of rIK with
+//! EAP-Initiate/Re-auth, the server answers with EAP-Finish/Re-auth and both
+//! use the rMSK of the sequence number as MSK.
+//!
+//! The authenticator acts as a local ER server, it bootstraps the keys from
+//! the EMSK of its own EAP server. If re-authentication is not possible, both
+//! sides fall back to full EAP.
+
+mod peer;
+mod server;
+
+pub use peer::ErpPeer;
+pub use server::ErpServer;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{
+    layers::eap_layer::SessionKeys,
+    message::{Message, MessageCode},
+    util::{constant_time_eq, ByteReader, OwnedSlice},
+    EapEnvironment, EapEnvironmentResponse, MessageBuilder, ResponseMessage,
+};
+
+/// Types of EAP-Initiate and EAP-Finish
+pub(crate) const TYPE_REAUTH_START: u8 = 1;
+pub(crate) const TYPE_REAUTH: u8 = 2;
+
+/// Flags
+const FLAG_RESULT: u8 = 0x80;
+
+/// TV and TLV types
+const TLV_KEY_NAME_NAI: u8 = 1;
+const TV_RRK_LIFETIME: u8 = 2;
+const TV_RMSK_LIFETIME: u8 = 3;
+
+/// HMAC-SHA256-128, mandatory to implement
+const CRYPTOSUITE_HMAC_SHA256_128: u8 = 2;
+const TAG_LEN: usize = 16;
+
+const EMSK_NAME_LEN: usize = 8;
+const KEY_LEN: usize = 64;
+const MAX_KEY_NAME_NAI_LEN: usize = 253;
+
+const RRK_LABEL: &[u8] = b"EAP Re-authentication Root Key@ietf.org";
+const RIK_LABEL: &[u8] = b"Re-authentication Integrity Key@ietf.org";
+const RMSK_LABEL: &[u8] = b"Re-authentication Master Session Key@ietf.org";
+
+/// KDF of RFC 5295 with HMAC-SHA-256, S = label | "\0" | context
+fn kdf(key: &[u8], label: &[u8], context: &[u8], out: &mut [u8]) {
+    let mut previous = [0u8; 32];
+    for (i, chunk) in out.chunks_mut(previous.len()).enumerate() {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+        if i > 0 {
+            mac.update(&previous);
+        }
+        mac.update(label);
+        mac.update(&[0]);
+        mac.update(context);
+        mac.update(&[i as u8 + 1]);
+        previous = mac.finalize().into_bytes().into();
+        chunk.copy_from_slice(&previous[..chunk.len()]);
+    }
+}
+
+/// Sequence numbers accepted by the server. Numbers may arrive out of order
+/// within the window, but none is accepted twice.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ReplayWindow {
+    highest: Option<u16>,
+    /// Bit n is set if `highest - n` was accepted
+    seen: u32,
+}
+
+impl ReplayWindow {
+    fn accept(&mut self, seq: u16) -> bool {
+        match self.highest {
+            Some(highest) if seq <= highest => {
+                let offset = (highest - seq) as u32;
+                if offset >= u32::BITS || self.seen & (1 << offset) != 0 {
+                    return false;
+                }
+                self.seen |= 1 << offset;
+            }
+            Some(highest) => {
+                let shift = (seq - highest) as u32;
+                self.seen = self.seen.checked_shl(shift).unwrap_or(0) | 1;
+                self.highest = Some(seq);
+            }
+            None => {
+                self.seen = 1;
+                self.highest = Some(seq);
+            }
+        }
+        true
+    }
+}
+
+/// Keys of one bootstrapped peer, identified by its keyName-NAI
+#[derive(Clone)]
+pub struct ErpKeys {
+    key_name_nai: OwnedSlice<MAX_KEY_NAME_NAI_LEN>,
+    rrk: [u8; KEY_LEN],
+    rik: [u8; KEY_LEN],
+    /// Sequence number of the next EAP-Initiate/Re-auth of the peer
+    next_seq: u16,
+    /// Sequence numbers the server has accepted
+    window: ReplayWindow,
+}
+
+impl ErpKeys {
+    /// Derives rRK and rIK from the EMSK. The keyName-NAI consists of the
+    /// EMSKname and the ER domain, EMSKname = KDF(Session-ID, "EMSK", 8) of
+    /// RFC 5295 3.1. Keys without a Session-ID are not bootstrapped.
+    fn derive(keys: &SessionKeys, domain: &[u8]) -> Option<Self> {
+        let session_id = keys.session_id.as_ref();
+        if session_id.is_empty() {
+            return None;
+        }
+        let emsk = &keys.emsk;
+
+        let mut emsk_name = [0u8; EMSK_NAME_LEN];
+        kdf(
+            session_id,
+            b"EMSK",
+            &(EMSK_NAME_LEN as u16).to_be_bytes(),
+            &mut emsk_name,
+        );
+
+        let mut nai = [0u8; MAX_KEY_NAME_NAI_LEN];
+        let nai_len = 2 * EMSK_NAME_LEN + 1 + domain.len();
+        if nai_len > nai.len() {
+            return None;
+        }
+        for (i, byte) in emsk_name.iter().enumerate() {
+            const HEX: &[u8; 16] = b"0123456789abcdef";
+            nai[2 * i] = HEX[(byte >> 4) as usize];
+            nai[2 * i + 1] = HEX[(byte & 0x0f) as usize];
+        }
+        nai[2 * EMSK_NAME_LEN] = b'@';
+        nai[2 * EMSK_NAME_LEN + 1..nai_len].copy_from_slice(domain);
+
+        let mut rrk = [0u8; KEY_LEN];
+        kdf(emsk, RRK_LABEL, &(KEY_LEN as u16).to_be_bytes(), &mut rrk);
+        let mut rik = [0u8; KEY_LEN];
+        let [len_high, len_low] = (KEY_LEN as u16).to_be_bytes();
+        kdf(
+            &rrk,
+            RIK_LABEL,
+            &[CRYPTOSUITE_HMAC_SHA256_128, len_high, len_low],
+            &mut rik,
+        );
+
+        Some(Self {
+            key_name_nai: OwnedSlice::from(&nai[..nai_len]),
+            rrk,
+            rik,
+            next_seq: 0,
+            window: ReplayWindow::default(),
+        })
+    }
+
+    pub fn key_name_nai(&self) -> &[u8] {
+        self.key_name_nai.as_ref()
+    }
+
+    /// rMSK of the re-authentication with `seq`. ERP derives no EMSK, it is
+    /// left zero, and defines no Session-ID.
+    fn session_keys(&self, seq: u16) -> SessionKeys {
+        let mut msk = [0u8; KEY_LEN];
+        let mut context = [0u8; 4];
+        context[..2].copy_from_slice(&seq.to_be_bytes());
+        context[2..].copy_from_slice(&(KEY_LEN as u16).to_be_bytes());
+        kdf(&self.rrk, RMSK_LABEL, &context, &mut msk);
+        SessionKeys {
+            msk,
+            emsk: [0; KEY_LEN],
+            session_id: OwnedSlice::new(),
+        }
+    }
+
+    /// Authentication Tag over the whole packet up to the Cryptosuite
+    fn tag(&self, code: MessageCode, identifier: u8, data: &[u8]) -> [u8; TAG_LEN] {
+        let length = (4 + data.len() + TAG_LEN) as u16;
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.rik).unwrap();
+        mac.update(&[code as u8, identifier]);
+        mac.update(&length.to_be_bytes());
+        mac.update(data);
+        let mut tag = [0u8; TAG_LEN];
+        tag.copy_from_slice(&mac.finalize().into_bytes()[..TAG_LEN]);
+        tag
+    }
+
+    /// Writes EAP-Initiate/Re-auth or EAP-Finish/Re-auth
+    fn write<'a>(
+        &self,
+        code: MessageCode,
+        identifier: u8,
+        flags: u8,
+        seq: u16,
+        env: &'a mut dyn EapEnvironment,
+    ) -> ResponseMessage<'a> {
+        let builder = write_reauth(env, flags, seq, self.key_name_nai())
+            .write(&[CRYPTOSUITE_HMAC_SHA256_128]);
+        let tag = self.tag(code, identifier, builder.slice());
+        builder.write(&tag).build(code, identifier)
+    }
+
+    fn verify(&self, message: &Message, reauth: &Reauth) -> bool {
+        let Some(tag) = reauth.tag else {
+            return false;
+        };
+        let data = &message.body[..message.body.len() - TAG_LEN];
+        constant_time_eq(&self.tag(message.code, message.identifier, data), tag)
+    }
+}
+
+/// Re-auth fields up to the Cryptosuite
+fn write_reauth<'a>(
+    env: &'a mut dyn EapEnvironment,
+    flags: u8,
+    seq: u16,
+    key_name_nai: &[u8],
+) -> MessageBuilder<'a> {
+    env.respond()
+        .write(&[TYPE_REAUTH, flags])
+        .write(&seq.to_be_bytes())
+        .write(&[TLV_KEY_NAME_NAI, key_name_nai.len() as u8])
+        .write(key_name_nai)
+}
+
+/// Fields of EAP-Initiate/Re-auth or EAP-Finish/Re-auth
+struct Reauth<'a> {
+    flags: u8,
+    seq: u16,
+    key_name_nai: &'a [u8],
+    /// Only a failed EAP-Finish/Re-auth may come without a tag
+    tag: Option<&'a [u8]>,
+}
+
+impl<'a> Reauth<'a> {
+    fn parse(body: &'a [u8]) -> Option<Self> {
+        let mut reader = ByteReader::new(body);
+        if reader.u8()? != TYPE_REAUTH {
+            return None;
+        }
+        let flags = reader.u8()?;
+        let seq = reader.u16()?;
+
+        let mut key_name_nai = None;
+        let mut tag = None;
+        while !reader.is_empty() {
+            let remaining = reader.remaining();
+            if remaining.len() == 1 + TAG_LEN && remaining[0] == CRYPTOSUITE_HMAC_SHA256_128 {
+                tag = Some(&remaining[1..]);
+                break;
+            }
+            match reader.u8()? {
+                TV_RRK_LIFETIME | TV_RMSK_LIFETIME => {
+                    reader.u32()?;
+                }
+                TLV_KEY_NAME_NAI => {
+                    let len = reader.u8()?;
+                    key_name_nai = Some(reader.take(len as usize)?);
+                }
+                _ => {
+                    let len = reader.u8()?;
+                    reader.take(len as usize)?;
+                }
+            }
+        }
+
+        Some(Self {
+            flags,
+            seq,
+            key_name_nai: key_name_nai?,
+            tag,
+        })
+    }
+}
+
+/// Persistent storage of the peer, it holds the keys of its last full
+/// authentication
+pub trait ErpPeerStore {
+    fn load(&mut self) -> Option<ErpKeys>;
+    fn store(&mut self, keys: &ErpKeys);
+}
+
+/// Persistent storage of the ER server, keys are looked up by keyName-NAI
+pub trait ErpServerStore {
+    fn load(&mut self, key_name_nai: &[u8]) -> Option<ErpKeys>;
+    fn store(&mut self, keys: &ErpKeys);
+}
+
+/// Volatile storage, the keys are lost on restart
+impl ErpPeerStore for Option<ErpKeys> {
+    fn load(&mut self) -> Option<ErpKeys> {
+        self.clone()
+    }
+
+    fn store(&mut self, keys: &ErpKeys) {
+        *self = Some(keys.clone());
+    }
+}
+
+#[cfg(feature = "std")]
+impl ErpServerStore for std::collections::HashMap<Vec<u8>, ErpKeys> {
+    fn load(&mut self, key_name_nai: &[u8]) -> Option<ErpKeys> {
+        self.get(key_name_nai).cloned()
+    }
+
+    fn store(&mut self, keys: &ErpKeys) {
+        self.insert(keys.key_name_nai().to_vec(), keys.clone());
+    }
+}
+
+/// Answer of the ER server to EAP-Initiate/Re-auth
+pub enum ReauthResult<'a> {
+    /// EAP-Finish/Re-auth reporting success
+    Success(ResponseMessage<'a>),
+    /// EAP-Finish/Re-auth reporting failure, full EAP follows
+    Failure(ResponseMessage<'a>),
+    /// The message is not authentic
+    Discard(&'a mut dyn EapEnvironment),
+}
+
+/// Role of an [`EapLayer`](crate::layers::EapLayer) in ERP, `()` disables it
+pub trait Erp {
+    /// An authenticator with an ER server offers ERP with
+    /// EAP-Initiate/Re-auth-Start
+    fn is_server(&self) -> bool {
+        false
+    }
+
+    /// Derives the ERP keys after a full authentication
+    fn bootstrap(&mut self, _keys: &SessionKeys) {}
+
+    /// Peer: EAP-Initiate/Re-auth answering the Re-auth-Start with
+    /// `identifier`, fails without keys
+    fn reauth<'a>(
+        &mut self,
+        _identifier: u8,
+        env: &'a mut dyn EapEnvironment,
+    ) -> Result<ResponseMessage<'a>, &'a mut dyn EapEnvironment> {
+        Err(env)
+    }
+
+    /// Peer: whether EAP-Finish/Re-auth reports success, `None` if it is not
+    /// authentic
+    fn finish(&mut self, _finish: &Message) -> Option<bool> {
+        None
+    }
+
+    /// Server: answers EAP-Initiate/Re-auth
+    fn verify<'a>(
+        &mut self,
+        _reauth: &Message,
+        env: &'a mut dyn EapEnvironment,
+    ) -> ReauthResult<'a> {
+        ReauthResult::Discard(env)
+    }
+
+    /// Keys of a successful re-authentication
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        None
+    }
+}
+
+impl Erp for () {}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_window() {
+        let mut window = ReplayWindow::default();
+        assert!(window.accept(5));
+        assert!(!window.accept(5));
+        assert!(window.accept(7));
+        // Out of order within the window
+        assert!(window.accept(6));
+        assert!(!window.accept(6));
+        assert!(window.accept(3));
+
+        assert!(window.accept(100));
+        // Left the window
+        assert!(!window.accept(60));
+        assert!(window.accept(80));
+        assert!(!window.accept(80));
+    }
+
+    /// Keys of an EAP-PSK conversation with RAND_P of 0x01 and RAND_S of 0x02
+    fn session_keys(emsk: u8) -> SessionKeys {
+        SessionKeys {
+            msk: [0; KEY_LEN],
+            emsk: [emsk; KEY_LEN],
+            session_id: SessionKeys::session_id(47, &[&[1; 16], &[2; 16]]),
+        }
+    }
+
+    #[test]
+    fn key_derivation() {
+        let keys = ErpKeys::derive(&session_keys(0x11), b"example.com").unwrap();
+        // HMAC-SHA-256(Session-ID, "EMSK" | 0x00 | 0x00 0x08 | 0x01), cut to
+        // 8 bytes, computed with Python's hmac module
+        assert_eq!(keys.key_name_nai(), b"10a9352a7e4283bd@example.com");
+
+        // The EMSK does not change the name, only the keys
+        let other = ErpKeys::derive(&session_keys(0x22), b"example.com").unwrap();
+        assert_eq!(other.key_name_nai(), keys.key_name_nai());
+        assert!(other.rrk != keys.rrk);
+
+        // Each sequence number has its own rMSK
+        assert!(keys.session_keys(0).msk != keys.session_keys(1).msk);
+        assert!(keys.rrk != keys.rik);
+
+        let long_domain = [b'a'; MAX_KEY_NAME_NAI_LEN];
+        assert!(ErpKeys::derive(&session_keys(0x11), &long_domain).is_none());
+
+        let mut unnamed = session_keys(0x11);
+        unnamed.session_id = OwnedSlice::new();
+        assert!(ErpKeys::derive(&unnamed, b"example.com").is_none());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn failed_reauth_falls_back_to_full_eap() {
+        use crate::layers::{
+            auth::AuthIdentityMethod, peer::PeerIdentityMethod, AuthLayer, EapLayer, PeerLayer,
+        };
+        use std::collections::HashMap;
+
+        let mut auth_env = crate::DefaultEnvironment::new();
+        let mut peer_env = crate::DefaultEnvironment::new();
+        let keys = ErpKeys::derive(&session_keys(0x11), b"example.com").unwrap();
+
+        // The ER server does not know the keys of the peer
+        let mut auth = EapLayer::new(AuthLayer::new().with(AuthIdentityMethod::new()))
+            .with_erp(ErpServer::new(HashMap::new(), b"example.com"));
+        let mut peer = EapLayer::new(PeerLayer::new().with(PeerIdentityMethod::new(b"sensor")))
+            .with_erp(ErpPeer::new(Some(keys), b"example.com"));
+
+        let _ = peer.start(&mut peer_env);
+        let start = auth.start(&mut auth_env).message.unwrap().as_ref().to_vec();
+        let reauth = peer.receive(&start, &mut peer_env);
+        let reauth = reauth.message.unwrap().as_ref().to_vec();
+        assert_eq!(Message::parse(&reauth).unwrap().code, MessageCode::Initiate);
+
+        let finish = auth.receive(&reauth, &mut auth_env);
+        let finish = finish.message.unwrap().as_ref().to_vec();
+        assert_eq!(Message::parse(&finish).unwrap().code, MessageCode::Finish);
+        assert!(peer.receive(&finish, &mut peer_env).message.is_none());
+
+        // The same conversation continues with full EAP
+        let request = auth
+            .timeout(&mut auth_env)
+            .message
+            .unwrap()
+            .as_ref()
+            .to_vec();
+        let request = Message::parse(&request).unwrap();
+        assert_eq!(request.code, MessageCode::Request);
+        assert_eq!(request.body, [1]);
+
+        let response = peer.receive(&request.to_vec(), &mut peer_env);
+        let response = response.message.unwrap().as_ref().to_vec();
+        assert_eq!(
+            Message::parse(&response).unwrap(),
+            Message::new(MessageCode::Response, request.identifier, b"\x01sensor")
+        );
+    }
+
+    #[test]
+    fn message_roundtrip() {
+        let mut env = crate::DefaultEnvironment::new();
+        let keys = ErpKeys::derive(&session_keys(0x22), b"example.com").unwrap();
+
+        let message = keys
+            .write(MessageCode::Initiate, 7, 0, 42, &mut env)
+            .as_ref()
+            .to_vec();
+        let message = Message::parse(&message).unwrap();
+        let reauth = Reauth::parse(message.body).unwrap();
+        assert_eq!(reauth.seq, 42);
+        assert_eq!(reauth.key_name_nai, keys.key_name_nai());
+        assert!(keys.verify(&message, &reauth));
+
+        // The tag covers the header
+        let tampered = Message::new(MessageCode::Finish, 7, message.body);
+        assert!(!keys.verify(&tampered, &reauth));
+    }
+}
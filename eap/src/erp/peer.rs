@@ -0,0 +1,94 @@
+use crate::{
+    layers::eap_layer::SessionKeys,
+    message::{Message, MessageCode},
+    util::OwnedSlice,
+    EapEnvironment, ResponseMessage,
+};
+
+use super::*;
+
+/// Peer side of ERP.
+///
+/// The keys of the last full authentication are kept in `store`, they are
+/// used whenever the authenticator offers re-authentication.
+pub struct ErpPeer<S> {
+    store: S,
+    domain: OwnedSlice<MAX_KEY_NAME_NAI_LEN>,
+    /// Keys and sequence number of the outstanding EAP-Initiate/Re-auth
+    pending: Option<(ErpKeys, u16)>,
+    session_keys: Option<SessionKeys>,
+}
+
+impl<S> ErpPeer<S>
+where
+    S: ErpPeerStore,
+{
+    /// `domain` is the realm of the home ER server, it becomes part of the
+    /// keyName-NAI
+    pub fn new(store: S, domain: &[u8]) -> Self {
+        Self {
+            store,
+            domain: OwnedSlice::from(domain),
+            pending: None,
+            session_keys: None,
+        }
+    }
+}
+
+impl<S> Erp for ErpPeer<S>
+where
+    S: ErpPeerStore,
+{
+    fn bootstrap(&mut self, keys: &SessionKeys) {
+        if let Some(keys) = ErpKeys::derive(keys, self.domain.as_ref()) {
+            self.store.store(&keys);
+        }
+    }
+
+    fn reauth<'a>(
+        &mut self,
+        identifier: u8,
+        env: &'a mut dyn EapEnvironment,
+    ) -> Result<ResponseMessage<'a>, &'a mut dyn EapEnvironment> {
+        let Some(mut keys) = self.store.load() else {
+            return Err(env);
+        };
+        // Sequence numbers must not wrap, a full authentication renews the keys
+        let seq = keys.next_seq;
+        if seq == u16::MAX {
+            return Err(env);
+        }
+        keys.next_seq += 1;
+        self.store.store(&keys);
+
+        let message = keys.write(MessageCode::Initiate, identifier, 0, seq, env);
+        self.pending = Some((keys, seq));
+        self.session_keys = None;
+        Ok(message)
+    }
+
+    fn finish(&mut self, finish: &Message) -> Option<bool> {
+        let (keys, seq) = self.pending.as_ref()?;
+        let reauth = Reauth::parse(finish.body)?;
+        if reauth.key_name_nai != keys.key_name_nai() || reauth.seq != *seq {
+            return None;
+        }
+
+        // A failure only leads to full EAP, it does not need to be authentic
+        if reauth.flags & FLAG_RESULT != 0 {
+            self.pending = None;
+            return Some(false);
+        }
+        if !keys.verify(finish, &reauth) {
+            return None;
+        }
+
+        self.session_keys = Some(keys.session_keys(*seq));
+        self.pending = None;
+        Some(true)
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.session_keys.as_ref()
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{
+    layers::eap_layer::SessionKeys,
+    message::{Message, MessageCode},
+    util::OwnedSlice,
+    EapEnvironment,
+};
+
+use super::*;
+
+/// Local ER server of an authenticator.
+///
+/// Keys are bootstrapped from the full authentications of the EAP server in
+/// front of it and looked up in `store` by keyName-NAI.
+pub struct ErpServer<S> {
+    store: S,
+    domain: OwnedSlice<MAX_KEY_NAME_NAI_LEN>,
+    session_keys: Option<SessionKeys>,
+}
+
+impl<S> ErpServer<S>
+where
+    S: ErpServerStore,
+{
+    /// `domain` is the ER domain, peers must use the same one
+    pub fn new(store: S, domain: &[u8]) -> Self {
+        Self {
+            store,
+            domain: OwnedSlice::from(domain),
+            session_keys: None,
+        }
+    }
+}
+
+impl<S> Erp for ErpServer<S>
+where
+    S: ErpServerStore,
+{
+    fn is_server(&self) -> bool {
+        true
+    }
+
+    fn bootstrap(&mut self, keys: &SessionKeys) {
+        if let Some(keys) = ErpKeys::derive(keys, self.domain.as_ref()) {
+            self.store.store(&keys);
+        }
+    }
+
+    fn verify<'a>(
+        &mut self,
+        reauth: &Message,
+        env: &'a mut dyn EapEnvironment,
+    ) -> ReauthResult<'a> {
+        let Some(fields) = Reauth::parse(reauth.body) else {
+            return ReauthResult::Discard(env);
+        };
+
+        let Some(mut keys) = self.store.load(fields.key_name_nai) else {
+            // Unknown keys, the peer has to run full EAP
+            let message = write_reauth(env, FLAG_RESULT, fields.seq, fields.key_name_nai)
+                .build(MessageCode::Finish, reauth.identifier);
+            return ReauthResult::Failure(message);
+        };
+        if !keys.verify(reauth, &fields) || !keys.window.accept(fields.seq) {
+            return ReauthResult::Discard(env);
+        }
+        self.store.store(&keys);
+
+        self.session_keys = Some(keys.session_keys(fields.seq));
+        ReauthResult::Success(keys.write(
+            MessageCode::Finish,
+            reauth.identifier,
+            0,
+            fields.seq,
+            env,
+        ))
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.session_keys.as_ref()
+    }
+}
@@ -1,5 +1,4 @@
 use common::*;
-use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{Authenticator, Peer};
 
@@ -63,6 +62,15 @@ fn run<A: EapWrapper, B: EapWrapper>(
     panic!("Too many iterations");
 }
 
+/// The `own_vs_wpa_*` tests need the hostap submodule, without it they pass
+/// without running
+fn hostap_missing() -> bool {
+    if !wifieap::HOSTAP_AVAILABLE {
+        println!("hostap submodule is not checked out, skipping");
+    }
+    !wifieap::HOSTAP_AVAILABLE
+}
+
 fn hex_dump(label: &str, data: &[u8]) {
     println!("{label:10}");
 
@@ -127,87 +135,2131 @@ fn own_tls() {
 }
 
 #[test]
-fn own_vs_wpa_md5() {
-    println!("Own Peer vs WPA Authenticator");
-    let peer = Peer::new_password("hans", "1234");
-    let auth = wifieap::server::EapServer::new_password("hans", "1234");
+fn own_tls_notification() {
+    let mut peer = Peer::new_tls("hans", dummycert::TlsConfig::dummy_client_rsa());
+    let mut auth = Authenticator::new_tls(dummycert::TlsConfig::dummy_server_rsa());
+
+    let received = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    peer.set_notification_callback({
+        let received = received.clone();
+        move |text| received.borrow_mut().push(text.to_vec())
+    });
 
+    // Identity and the ClientHello
+    for _ in 0..2 {
+        let request = auth.step().response.map(|m| m.to_vec()).unwrap();
+        peer.receive(&request);
+        let response = peer.step().response.map(|m| m.to_vec()).unwrap();
+        auth.receive(&response);
+    }
+
+    // The handshake continues after the notification
+    auth.notify("Password expires soon").unwrap();
     assert_eq!(
         run(peer, auth, None),
         (EapStepStatus::Finished, EapStepStatus::Finished)
     );
+    assert_eq!(*received.borrow(), vec![b"Password expires soon".to_vec()]);
+}
 
-    // reverse role
-    println!("Own Authenticator vs WPA Peer");
-    let peer = wifieap::peer::EapPeer::new_password("hans", "1234");
+#[test]
+fn own_policy_refuses_downgrade() {
+    use crate::layers::{peer, PeerLayer, SecurityClaims};
+
+    // A rogue authenticator only offers MD5-Challenge to a peer that also
+    // has EAP-TLS configured
+    let peer = Peer::from_layer(
+        PeerLayer::new()
+            .with(peer::PeerIdentityMethod::new(b"hans"))
+            .with(peer::PeerMD5ChallengeMethod::new(b"1234"))
+            .with(crate::eap_rustls::PeerTlsMethod::new(
+                dummycert::TlsConfig::dummy_client_rsa(),
+            ))
+            .with_policy(peer::PeerPolicy::new().require(SecurityClaims {
+                mutual_auth: true,
+                key_derivation: true,
+                dictionary_attack_resistance: true,
+                ..SecurityClaims::NONE
+            })),
+    );
     let auth = Authenticator::new_password("1234");
 
     assert_eq!(
         run(peer, auth, None),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_gtc() {
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use crate::util::OwnedSlice;
+
+    let new_peer = |token: &'static [u8]| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"hans"))
+                .with(peer::PeerGtcMethod::new(move |_: &[u8]| {
+                    Some(OwnedSlice::from(token))
+                })),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            auth::AuthGtcMethod::new(
+                b"Token:",
+                auth::HotpVerifier::new(b"12345678901234567890", 0).unwrap(),
+            ),
+        ))
+    };
+
+    assert_eq!(
+        run(new_peer(b"755224"), new_auth(), None),
         (EapStepStatus::Finished, EapStepStatus::Finished)
     );
 
-    // Negative
-    println!("Own Peer vs WPA Authenticator; Negative");
-    let peer = Peer::new_password("hans", "1234");
-    let auth = wifieap::server::EapServer::new_password("hans", "not 1234");
+    assert_eq!(
+        run(new_peer(b"000000"), new_auth(), None),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_otp() {
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use crate::util::OwnedSlice;
+
+    let peer = Peer::from_layer(
+        PeerLayer::new()
+            .with(peer::PeerIdentityMethod::new(b"hans"))
+            .with(peer::PeerOtpMethod::new(|_: &[u8]| {
+                // RFC 6238 test secret at T = 59
+                Some(OwnedSlice::from(b"94287082"))
+            })),
+    );
+    let mut auth = Authenticator::from_layer(
+        AuthLayer::new()
+            .with(auth::AuthIdentityMethod::new())
+            .with(auth::AuthOtpMethod::new(
+                b"otp-totp",
+                auth::TotpVerifier::new(b"12345678901234567890")
+                    .unwrap()
+                    .with_digits(8),
+            )),
+    );
+    auth.set_unix_clock(|| 59 + 30);
 
     assert_eq!(
-        run(peer, auth, Some(ExtraOptions::wpa_does_not_give_up())),
+        run(peer, auth, None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+}
+
+#[test]
+fn own_psk() {
+    use crate::eap_psk::{AuthPskMethod, PeerPskMethod};
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+
+    let new_peer = |psk: [u8; 16]| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"sensor"))
+                .with(PeerPskMethod::new(b"sensor", psk)),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            AuthPskMethod::new(b"server", |id_p: &[u8]| {
+                (id_p == b"sensor").then_some([0x42; 16])
+            }),
+        ))
+    };
+
+    assert_eq!(
+        run(new_peer([0x42; 16]), new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    assert_eq!(
+        run(new_peer([0x23; 16]), new_auth(), None),
         (EapStepStatus::Error, EapStepStatus::Error)
     );
+}
 
-    // Negative everse role
-    println!("Own Authenticator vs WPA Peer; Negative");
-    let peer = wifieap::peer::EapPeer::new_password("hans", "1234");
-    let auth = Authenticator::new_password("not 1234");
+#[test]
+fn own_erp() {
+    use crate::eap_psk::{AuthPskMethod, PeerPskMethod};
+    use crate::erp::{ErpKeys, ErpPeer, ErpPeerStore, ErpServer, ErpServerStore};
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use std::{cell::Cell, cell::RefCell, collections::HashMap, rc::Rc};
+
+    #[derive(Clone, Default)]
+    struct Shared<T>(Rc<RefCell<T>>);
+
+    impl ErpPeerStore for Shared<Option<ErpKeys>> {
+        fn load(&mut self) -> Option<ErpKeys> {
+            self.0.borrow_mut().load()
+        }
+
+        fn store(&mut self, keys: &ErpKeys) {
+            self.0.borrow_mut().store(keys)
+        }
+    }
 
+    impl ErpServerStore for Shared<HashMap<Vec<u8>, ErpKeys>> {
+        fn load(&mut self, key_name_nai: &[u8]) -> Option<ErpKeys> {
+            self.0.borrow_mut().load(key_name_nai)
+        }
+
+        fn store(&mut self, keys: &ErpKeys) {
+            self.0.borrow_mut().store(keys)
+        }
+    }
+
+    let peer_store = Shared::<Option<ErpKeys>>::default();
+    let server_store = Shared::<HashMap<Vec<u8>, ErpKeys>>::default();
+    // Counts the full authentications
+    let psk_lookups = Rc::new(Cell::new(0));
+
+    let new_peer = || {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"sensor"))
+                .with(PeerPskMethod::new(b"sensor", [0x42; 16])),
+        )
+        .with_erp(ErpPeer::new(peer_store.clone(), b"example.com"))
+    };
+    let new_auth = || {
+        let psk_lookups = psk_lookups.clone();
+        Authenticator::from_layer(AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            AuthPskMethod::new(b"server", move |id_p: &[u8]| {
+                psk_lookups.set(psk_lookups.get() + 1);
+                (id_p == b"sensor").then_some([0x42; 16])
+            }),
+        ))
+        .with_erp(ErpServer::new(server_store.clone(), b"example.com"))
+    };
+
+    // Full authentication bootstraps both sides
     assert_eq!(
-        run(peer, auth, Some(ExtraOptions::wpa_does_not_give_up())),
+        run(new_peer(), new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+    assert_eq!(psk_lookups.get(), 1);
+    assert!(peer_store.0.borrow().is_some());
+    assert_eq!(server_store.0.borrow().len(), 1);
+
+    // Re-authentication
+    for _ in 0..2 {
+        assert_eq!(
+            run(new_peer(), new_auth(), None),
+            (EapStepStatus::Finished, EapStepStatus::Finished)
+        );
+        assert_eq!(psk_lookups.get(), 1);
+    }
+
+    // The ER server lost its keys, full EAP follows and bootstraps again
+    server_store.0.borrow_mut().clear();
+    assert_eq!(
+        run(new_peer(), new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+    assert_eq!(psk_lookups.get(), 2);
+    assert_eq!(server_store.0.borrow().len(), 1);
+
+    // A peer without ERP is authenticated in full
+    let peer = Peer::from_layer(
+        PeerLayer::new()
+            .with(peer::PeerIdentityMethod::new(b"sensor"))
+            .with(PeerPskMethod::new(b"sensor", [0x42; 16])),
+    );
+    assert_eq!(
+        run(peer, new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+    assert_eq!(psk_lookups.get(), 3);
+}
+
+#[test]
+fn own_gpsk() {
+    use crate::eap_gpsk::{AuthGpskMethod, PeerGpskMethod};
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use crate::util::OwnedSlice;
+
+    let new_peer = |psk: &[u8]| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"gateway"))
+                .with(PeerGpskMethod::new(b"gateway", psk)),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            AuthGpskMethod::new(b"server", |id_peer: &[u8]| {
+                (id_peer == b"gateway").then(|| OwnedSlice::from(b"0123456789abcdef"))
+            }),
+        ))
+    };
+
+    assert_eq!(
+        run(new_peer(b"0123456789abcdef"), new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    assert_eq!(
+        run(new_peer(b"fedcba9876543210"), new_auth(), None),
         (EapStepStatus::Error, EapStepStatus::Error)
     );
 }
 
 #[test]
-fn own_vs_wpa_tls() {
-    // Positive
-    println!("Own Peer vs WPA Authenticator");
-    let peer = Peer::new_tls("hans", dummycert::TlsConfig::dummy_client_rsa());
-    let auth = wifieap::server::EapServer::new_tls(dummycert::TlsConfig::dummy_server_rsa());
+fn own_eke() {
+    use crate::eap_eke::{AuthEkeMethod, PeerEkeMethod};
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use crate::util::OwnedSlice;
+
+    let new_peer = |id_p: &[u8], password: &[u8]| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(id_p))
+                .with(PeerEkeMethod::new(id_p, password)),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            AuthEkeMethod::new(b"server@example.org", |id_p: &[u8]| {
+                (id_p == b"alice@example.org").then(|| OwnedSlice::from(b"correct horse"))
+            }),
+        ))
+    };
 
     assert_eq!(
-        run(peer, auth, None),
+        run(
+            new_peer(b"alice@example.org", b"correct horse"),
+            new_auth(),
+            None
+        ),
         (EapStepStatus::Finished, EapStepStatus::Finished)
     );
 
-    // reverse
-    println!("Own Authenticator vs WPA Peer");
-    let peer = wifieap::peer::EapPeer::new_tls("hans", dummycert::TlsConfig::dummy_client_rsa());
-    let auth = Authenticator::new_tls(dummycert::TlsConfig::dummy_server_rsa());
+    assert_eq!(
+        run(
+            new_peer(b"alice@example.org", b"battery staple"),
+            new_auth(),
+            None
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
 
     assert_eq!(
-        run(peer, auth, None),
+        run(
+            new_peer(b"bob@example.org", b"correct horse"),
+            new_auth(),
+            None
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_edhoc() {
+    use crate::eap_edhoc::{AuthEdhocMethod, Credential, OwnCredential, PeerEdhocMethod};
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+
+    let server_signature = OwnCredential::signature(b"srv", "server.example.org", [1; 32]);
+    let server_static_dh = OwnCredential::static_dh(&[0x21], "server.example.org", [2; 32]);
+    let peer_signature = OwnCredential::signature(&[0x05], "sensor-1", [3; 32]);
+    let peer_static_dh = OwnCredential::static_dh(b"sensor-2", "sensor-2", [4; 32]);
+
+    let new_peer = |own: &OwnCredential, server: &OwnCredential| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"sensor@example.org"))
+                .with(PeerEdhocMethod::new(
+                    own.clone(),
+                    server.credential().clone(),
+                )),
+        )
+    };
+    let known: [Credential; 2] = [
+        peer_signature.credential().clone(),
+        peer_static_dh.credential().clone(),
+    ];
+    let new_auth = |own: &OwnCredential| {
+        let known = known.clone();
+        Authenticator::from_layer(AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            AuthEdhocMethod::new(own.clone(), move |kid: &[u8]| {
+                known
+                    .iter()
+                    .find(|credential| credential.kid() == kid)
+                    .cloned()
+            }),
+        ))
+    };
+
+    // All four EDHOC methods
+    for server in [&server_signature, &server_static_dh] {
+        for own in [&peer_signature, &peer_static_dh] {
+            assert_eq!(
+                run(new_peer(own, server), new_auth(server), None),
+                (EapStepStatus::Finished, EapStepStatus::Finished)
+            );
+        }
+    }
+
+    // The server doesn't know the peer
+    let unknown = OwnCredential::signature(b"sensor-3", "sensor-3", [5; 32]);
+    assert_eq!(
+        run(
+            new_peer(&unknown, &server_signature),
+            new_auth(&server_signature),
+            None
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+
+    // The peer expects another server key
+    let impostor = OwnCredential::signature(b"srv", "server.example.org", [6; 32]);
+    assert_eq!(
+        run(
+            new_peer(&peer_signature, &server_signature),
+            new_auth(&impostor),
+            None
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+
+    // A peer key that doesn't match its credential
+    let stolen = OwnCredential::new(peer_static_dh.credential().clone(), [7; 32]);
+    assert_eq!(
+        run(
+            new_peer(&stolen, &server_static_dh),
+            new_auth(&server_static_dh),
+            None
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_ikev2() {
+    use crate::eap_ikev2::{AuthIkev2Method, PeerIkev2Method};
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use crate::util::OwnedSlice;
+
+    let new_peer = |method: PeerIkev2Method| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"alice@example.org"))
+                .with(method),
+        )
+    };
+    let new_auth = |configure: fn(_) -> _| {
+        let method = AuthIkev2Method::new(b"server@example.org", |identity: &[u8]| {
+            (identity == b"alice@example.org").then(|| OwnedSlice::from(b"correct horse"))
+        });
+        Authenticator::from_layer(
+            AuthLayer::new()
+                .with(auth::AuthIdentityMethod::new())
+                .with(configure(method)),
+        )
+    };
+
+    let shared_secret = PeerIkev2Method::new(b"alice").with_shared_secret(b"correct horse");
+    assert_eq!(
+        run(new_peer(shared_secret), new_auth(|method| method), None),
         (EapStepStatus::Finished, EapStepStatus::Finished)
     );
 
-    // Negative
-    // These Cert use different CA's
-    println!("Own Peer vs WPA Authenticator; Negative");
-    let peer = Peer::new_tls("hans", dummycert::TlsConfig::dummy_client_rsa());
-    let auth = wifieap::server::EapServer::new_tls(dummycert::TlsConfig::dummy_server_ed25519());
+    let wrong_secret = PeerIkev2Method::new(b"alice").with_shared_secret(b"battery staple");
+    assert_eq!(
+        run(new_peer(wrong_secret), new_auth(|method| method), None),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+
+    #[cfg(feature = "tls")]
+    {
+        use dummycert::TlsConfig;
+
+        let certificate =
+            PeerIkev2Method::new(b"alice").with_certificate(TlsConfig::dummy_client());
+        assert_eq!(
+            run(
+                new_peer(certificate),
+                new_auth(|method| method.with_certificate(TlsConfig::dummy_server())),
+                None
+            ),
+            (EapStepStatus::Finished, EapStepStatus::Finished)
+        );
+
+        // RSA certificates don't fit a single message
+        let certificate = PeerIkev2Method::new(b"alice")
+            .with_certificate(TlsConfig::dummy_client_rsa())
+            .with_fragment_size(300);
+        assert_eq!(
+            run(
+                new_peer(certificate),
+                new_auth(|method| method.with_certificate(TlsConfig::dummy_server_rsa())),
+                None
+            ),
+            (EapStepStatus::Finished, EapStepStatus::Finished)
+        );
+
+        // The peer signs with a certificate the server's CA didn't issue, the
+        // shared secret doesn't help
+        let untrusted = PeerIkev2Method::new(b"alice")
+            .with_shared_secret(b"correct horse")
+            .with_certificate(TlsConfig::dummy_client_rsa());
+        assert_eq!(
+            run(
+                new_peer(untrusted),
+                new_auth(|method| method.with_certificate(TlsConfig::dummy_server())),
+                None
+            ),
+            (EapStepStatus::Error, EapStepStatus::Error)
+        );
+    }
+}
+
+#[test]
+fn own_noob() {
+    use crate::eap_noob::{
+        Association, AuthNoobMethod, NoobState, OobChannel, OobDirection, OobMessage,
+        PeerNoobMethod, PeerStore, ServerStore,
+    };
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    #[derive(Clone, Default)]
+    struct Shared<T>(Rc<RefCell<T>>);
+
+    impl PeerStore for Shared<Option<Association>> {
+        fn load(&mut self) -> Option<Association> {
+            self.0.borrow_mut().load()
+        }
+
+        fn store(&mut self, association: &Association) {
+            self.0.borrow_mut().store(association)
+        }
+    }
 
+    impl ServerStore for Shared<HashMap<Vec<u8>, Association>> {
+        fn load(&mut self, peer_id: &[u8]) -> Option<Association> {
+            self.0.borrow_mut().load(peer_id)
+        }
+
+        fn store(&mut self, association: &Association) {
+            self.0.borrow_mut().store(association)
+        }
+    }
+
+    /// The user transfers the displayed message by calling `transfer`
+    #[derive(Clone, Default)]
+    struct Oob {
+        displayed: Option<OobMessage>,
+        transferred: Option<OobMessage>,
+    }
+
+    impl Shared<Oob> {
+        fn transfer(&self) {
+            let mut oob = self.0.borrow_mut();
+            oob.transferred = oob.displayed.take();
+        }
+    }
+
+    impl OobChannel for Shared<Oob> {
+        fn send(&mut self, oob: &OobMessage) {
+            self.0.borrow_mut().displayed = Some(oob.clone());
+        }
+
+        fn receive(&mut self, peer_id: &[u8]) -> Option<OobMessage> {
+            let mut oob = self.0.borrow_mut();
+            match &oob.transferred {
+                Some(message) if message.peer_id.as_ref() == peer_id => oob.transferred.take(),
+                _ => None,
+            }
+        }
+    }
+
+    for direction in [OobDirection::PeerToServer, OobDirection::ServerToPeer] {
+        let peer_store = Shared::<Option<Association>>::default();
+        let server_store = Shared::<HashMap<Vec<u8>, Association>>::default();
+        let oob = Shared::<Oob>::default();
+
+        let new_peer = || {
+            Peer::from_layer(
+                PeerLayer::new()
+                    .with(peer::PeerIdentityMethod::new(crate::eap_noob::DEFAULT_NAI))
+                    .with(
+                        PeerNoobMethod::new(peer_store.clone(), oob.clone())
+                            .with_directions(direction)
+                            .with_peer_info(br#"{"Make":"Acme","Serial":"1234"}"#),
+                    ),
+            )
+        };
+        let new_auth = || {
+            Authenticator::from_layer(
+                AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+                    AuthNoobMethod::new(server_store.clone(), oob.clone())
+                        .with_server_info(br#"{"Name":"Example"}"#),
+                ),
+            )
+        };
+        let peer_state = || peer_store.0.borrow().as_ref().map(|a| a.state());
+
+        // Initial Exchange, always ends with a failure
+        assert_eq!(
+            run(new_peer(), new_auth(), None),
+            (EapStepStatus::Error, EapStepStatus::Error)
+        );
+        assert_eq!(peer_state(), Some(NoobState::WaitingForOob));
+        assert!(oob.0.borrow().displayed.is_some());
+
+        // Waiting Exchange, the user has not transferred the OOB message yet
+        assert_eq!(
+            run(new_peer(), new_auth(), None),
+            (EapStepStatus::Error, EapStepStatus::Error)
+        );
+
+        // Completion Exchange
+        oob.transfer();
+        assert_eq!(
+            run(new_peer(), new_auth(), None),
+            (EapStepStatus::Finished, EapStepStatus::Finished)
+        );
+        assert_eq!(peer_state(), Some(NoobState::Registered));
+
+        // Reconnect Exchange
+        assert_eq!(
+            run(new_peer(), new_auth(), None),
+            (EapStepStatus::Finished, EapStepStatus::Finished)
+        );
+
+        // Server lost its state, the peer refuses to register again on its own
+        server_store.0.borrow_mut().clear();
+        assert_eq!(
+            run(new_peer(), new_auth(), None),
+            (EapStepStatus::Error, EapStepStatus::Error)
+        );
+    }
+}
+
+#[test]
+fn own_teap() {
+    use crate::eap_teap::{AuthTeapMethod, IdentityType, PeerTeapMethod};
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use std::{cell::RefCell, rc::Rc};
+
+    let new_peer = |password: &[u8], certificate: Rc<RefCell<Option<Vec<u8>>>>| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"anonymous"))
+                .with(
+                    PeerTeapMethod::new(dummycert::TlsConfig::dummy_client())
+                        .with_inner_method(
+                            IdentityType::Machine,
+                            PeerLayer::new()
+                                .with(peer::PeerIdentityMethod::new(b"host/laptop"))
+                                .with(peer::PeerMD5ChallengeMethod::new(b"machine-secret")),
+                        )
+                        .with_basic_password(b"hans", password)
+                        .with_certificate_request(b"csr", move |pkcs7: &[u8]| {
+                            *certificate.borrow_mut() = Some(pkcs7.to_vec())
+                        }),
+                ),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(
+            AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+                AuthTeapMethod::new(dummycert::TlsConfig::dummy_server())
+                    .with_inner_method(
+                        Some(IdentityType::Machine),
+                        AuthLayer::new()
+                            .with(auth::AuthIdentityMethod::new())
+                            .with(auth::AuthMD5ChallengeMethod::new(b"machine-secret")),
+                    )
+                    .with_basic_password(Some(IdentityType::User), |username, password| {
+                        username == b"hans" && password == b"1234"
+                    })
+                    .with_certificate_issuer(|csr| (csr == b"csr").then(|| b"pkcs7".to_vec())),
+            ),
+        )
+    };
+
+    let certificate = Rc::new(RefCell::new(None));
     assert_eq!(
-        run(peer, auth, Some(ExtraOptions::wpa_does_not_give_up())),
+        run(new_peer(b"1234", certificate.clone()), new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+    assert_eq!(certificate.borrow().as_deref(), Some(&b"pkcs7"[..]));
+
+    let certificate = Rc::new(RefCell::new(None));
+    assert_eq!(
+        run(new_peer(b"4321", certificate.clone()), new_auth(), None),
         (EapStepStatus::Error, EapStepStatus::Error)
     );
+    assert!(certificate.borrow().is_none());
 
-    // reverse
-    println!("Own Authenticator vs WPA Peer; Negative");
-    let peer = wifieap::peer::EapPeer::new_tls("hans", dummycert::TlsConfig::dummy_client_rsa());
-    let auth = Authenticator::new_tls(dummycert::TlsConfig::dummy_server_ed25519());
+    // Without an inner authentication the server fails instead of panicking
+    let certificate = Rc::new(RefCell::new(None));
+    let auth = Authenticator::from_layer(
+        AuthLayer::new()
+            .with(auth::AuthIdentityMethod::new())
+            .with(AuthTeapMethod::new(dummycert::TlsConfig::dummy_server())),
+    );
+    assert_eq!(
+        run(new_peer(b"1234", certificate.clone()), auth, None),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+    assert!(certificate.borrow().is_none());
+}
+
+#[test]
+fn own_tnc() {
+    use crate::eap_teap::{AuthTeapMethod, IdentityType, PeerTeapMethod};
+    use crate::eap_tnc::{
+        AccessRecommendation, Attribute, AttributeWriter, Attributes, AuthTncMethod, PaSubtype,
+        PeerTncMethod, PostureCollector, PostureValidator,
+    };
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use std::{cell::Cell, rc::Rc};
+
+    /// Reports the firmware version, and the product when asked for it
+    struct Firmware {
+        version: &'static [u8],
+        result: Rc<Cell<Option<AccessRecommendation>>>,
+    }
+
+    impl PostureCollector for Firmware {
+        fn subtype(&self) -> PaSubtype {
+            PaSubtype::OPERATING_SYSTEM
+        }
+
+        fn begin_handshake(&mut self, out: &mut AttributeWriter) {
+            out.write(Attribute::ietf(Attribute::STRING_VERSION, self.version));
+        }
+
+        fn receive_message(&mut self, attributes: Attributes, out: &mut AttributeWriter) {
+            for attribute in attributes {
+                if attribute.attribute_type == Attribute::ATTRIBUTE_REQUEST {
+                    out.write(Attribute::ietf(
+                        Attribute::PRODUCT_INFORMATION,
+                        b"\x00\x00\x00\x00\x00sensor-os",
+                    ));
+                }
+            }
+        }
+
+        fn handshake_result(&mut self, recommendation: AccessRecommendation) {
+            self.result.set(Some(recommendation));
+        }
+    }
+
+    /// Asks for the product, then checks the firmware version
+    #[derive(Default)]
+    struct Policy {
+        version: Option<Vec<u8>>,
+        product: bool,
+    }
+
+    impl PostureValidator for Policy {
+        fn subtype(&self) -> PaSubtype {
+            PaSubtype::OPERATING_SYSTEM
+        }
+
+        fn begin_handshake(&mut self) {
+            *self = Self::default();
+        }
+
+        fn receive_message(&mut self, attributes: Attributes, out: &mut AttributeWriter) {
+            for attribute in attributes {
+                match attribute.attribute_type {
+                    Attribute::STRING_VERSION => self.version = Some(attribute.value.to_vec()),
+                    Attribute::PRODUCT_INFORMATION => self.product = true,
+                    _ => {}
+                }
+            }
+            if !self.product {
+                let request = [0, 0, 0, 0, 0, 0, 0, Attribute::PRODUCT_INFORMATION as u8];
+                out.write(Attribute::ietf(Attribute::ATTRIBUTE_REQUEST, &request));
+            }
+        }
+
+        fn recommendation(&mut self) -> Option<AccessRecommendation> {
+            if !self.product {
+                return None;
+            }
+            Some(match self.version.as_deref() {
+                Some(b"2.1") => AccessRecommendation::Allow,
+                Some(b"2.0") => AccessRecommendation::Isolate,
+                _ => AccessRecommendation::NoAccess,
+            })
+        }
+    }
+
+    let firmware = |version, result: &Rc<Cell<_>>| Firmware {
+        version,
+        result: result.clone(),
+    };
+    let new_peer = |version, result: &Rc<Cell<_>>| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"sensor-1"))
+                .with(PeerTncMethod::new(firmware(version, result))),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(
+            AuthLayer::new()
+                .with(auth::AuthIdentityMethod::new())
+                .with(AuthTncMethod::new(Policy::default())),
+        )
+    };
+
+    let result = Rc::new(Cell::new(None));
+    for (version, recommendation, status) in [
+        (b"2.1", AccessRecommendation::Allow, EapStepStatus::Finished),
+        (
+            b"2.0",
+            AccessRecommendation::Isolate,
+            EapStepStatus::Finished,
+        ),
+        (b"1.0", AccessRecommendation::NoAccess, EapStepStatus::Error),
+    ] {
+        assert_eq!(
+            run(new_peer(version, &result), new_auth(), None),
+            (status, status)
+        );
+        assert_eq!(result.take(), Some(recommendation));
+    }
 
+    // Posture assessment after the inner authentication of a tunnel
+    let teap_peer = Peer::from_layer(
+        PeerLayer::new()
+            .with(peer::PeerIdentityMethod::new(b"anonymous"))
+            .with(
+                PeerTeapMethod::new(dummycert::TlsConfig::dummy_client())
+                    .with_basic_password(b"hans", b"1234")
+                    .with_inner_method(
+                        IdentityType::Machine,
+                        PeerLayer::new()
+                            .with(peer::PeerIdentityMethod::new(b"host/sensor-1"))
+                            .with(PeerTncMethod::new(firmware(b"2.1", &result))),
+                    ),
+            ),
+    );
+    let teap_auth = Authenticator::from_layer(
+        AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            AuthTeapMethod::new(dummycert::TlsConfig::dummy_server())
+                .with_basic_password(Some(IdentityType::User), |username, password| {
+                    username == b"hans" && password == b"1234"
+                })
+                .with_inner_method(
+                    Some(IdentityType::Machine),
+                    AuthLayer::new()
+                        .with(auth::AuthIdentityMethod::new())
+                        .with(AuthTncMethod::new(Policy::default())),
+                ),
+        ),
+    );
     assert_eq!(
-        run(peer, auth, Some(ExtraOptions::wpa_does_not_give_up())),
+        run(teap_peer, teap_auth, None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+    assert_eq!(result.take(), Some(AccessRecommendation::Allow));
+}
+
+#[test]
+fn own_wsc() {
+    use crate::eap_wsc::{
+        AuthWscMethod, Credential, Device, DevicePassword, PeerWscMethod, IDENTITY_ENROLLEE,
+    };
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use std::{cell::RefCell, rc::Rc};
+
+    const ENROLLEE_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x22];
+
+    let network = Credential::new(
+        b"sensor-net",
+        Credential::AUTHENTICATION_WPA2_PSK,
+        Credential::ENCRYPTION_AES,
+        b"correct horse battery staple",
+    );
+    let new_peer = |password, received: &Rc<RefCell<Vec<Credential>>>| {
+        let received = received.clone();
+        let device = Device::new([0x22; 16], ENROLLEE_MAC, "sensor-1")
+            .with_manufacturer("sensors inc.")
+            .with_primary_device_type(Device::DEVICE_TYPE_COMPUTER);
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(IDENTITY_ENROLLEE))
+                .with(PeerWscMethod::new(device, password, move |credential| {
+                    received.borrow_mut().push(credential.clone())
+                })),
+        )
+    };
+    let new_auth = |password| {
+        let device = Device::new([0x11; 16], [0x02, 0, 0, 0, 0, 0x11], "access point")
+            .with_primary_device_type(Device::DEVICE_TYPE_ACCESS_POINT);
+        Authenticator::from_layer(
+            AuthLayer::new()
+                .with(auth::AuthIdentityMethod::new())
+                .with(AuthWscMethod::new(device, password, network.clone())),
+        )
+    };
+
+    // A registration always ends with EAP-Failure, the outcome is the credential
+    let received = Rc::new(RefCell::new(Vec::new()));
+    for password in [DevicePassword::pin("12345670"), DevicePassword::PushButton] {
+        assert_eq!(
+            run(
+                new_peer(password.clone(), &received),
+                new_auth(password),
+                None
+            ),
+            (EapStepStatus::Error, EapStepStatus::Error)
+        );
+        let credentials = received.take();
+        assert_eq!(credentials.len(), 1);
+        assert_eq!(credentials[0].ssid(), b"sensor-net");
+        assert_eq!(
+            credentials[0].network_key(),
+            b"correct horse battery staple"
+        );
+        assert_eq!(credentials[0].mac_address(), ENROLLEE_MAC);
+    }
+
+    // Wrong PIN, and a Registrar without PIN for the Enrollee answering M2D
+    for (peer_password, auth_password) in [
+        (
+            DevicePassword::pin("12345670"),
+            DevicePassword::pin("12345678"),
+        ),
+        (DevicePassword::pin("12345670"), DevicePassword::PushButton),
+    ] {
+        assert_eq!(
+            run(
+                new_peer(peer_password, &received),
+                new_auth(auth_password),
+                None
+            ),
+            (EapStepStatus::Error, EapStepStatus::Error)
+        );
+        assert!(received.take().is_empty());
+    }
+
+    // Only a PIN authenticates the two sides to each other
+    for (password, mutual_auth) in [
+        (DevicePassword::pin("12345670"), true),
+        (DevicePassword::PushButton, false),
+    ] {
+        let device = Device::new([0x11; 16], [0x02, 0, 0, 0, 0, 0x11], "access point");
+        let layer = AuthLayer::new().with(AuthWscMethod::new(
+            device.clone(),
+            password.clone(),
+            network.clone(),
+        ));
+        let (_, claims) = layer.security_claims().next().unwrap();
+        assert_eq!(claims.mutual_auth, mutual_auth);
+
+        let layer =
+            PeerLayer::new().with(PeerWscMethod::new(device, password, |_: &Credential| {}));
+        let (_, claims) = layer.security_claims().next().unwrap();
+        assert_eq!(claims, crate::layers::SecurityClaims::eap_wsc(!mutual_auth));
+    }
+}
+
+#[test]
+fn own_mschapv2() {
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use crate::util::OwnedSlice;
+
+    let new_peer = |password: &[u8]| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"hans"))
+                .with(peer::PeerMschapv2Method::new(b"hans", password)),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            auth::AuthMschapv2Method::new(b"server", |username: &[u8]| {
+                (username == b"hans").then(|| OwnedSlice::from(b"1234"))
+            }),
+        ))
+    };
+
+    assert_eq!(
+        run(new_peer(b"1234"), new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    assert_eq!(
+        run(new_peer(b"4321"), new_auth(), None),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_fido() {
+    use crate::eap_fido::{AuthFidoMethod, PeerFidoMethod, SoftwareAuthenticator};
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use std::collections::HashMap;
+
+    const RP_ID: &str = "dummy.example.com";
+
+    // Registers a credential of hans with a fresh authenticator
+    let register = |authenticator: SoftwareAuthenticator| {
+        let mut authenticator = authenticator;
+        let credential = authenticator.make_credential(RP_ID, b"hans");
+        let store = HashMap::from([(credential.credential_id.clone(), credential)]);
+        (authenticator, store)
+    };
+    let new_peer = |rp_id, identity: Option<&[u8]>, authenticator| {
+        let mut method =
+            PeerFidoMethod::new(dummycert::TlsConfig::dummy_client(), rp_id, authenticator);
+        if let Some(identity) = identity {
+            method = method.with_identity(identity);
+        }
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"anonymous"))
+                .with(method),
+        )
+    };
+    let new_auth = |user_verification, store| {
+        let mut method = AuthFidoMethod::new(dummycert::TlsConfig::dummy_server(), RP_ID, store);
+        if user_verification {
+            method = method.with_user_verification();
+        }
+        Authenticator::from_layer(
+            AuthLayer::new()
+                .with(auth::AuthIdentityMethod::new())
+                .with(method),
+        )
+    };
+
+    // Discoverable credential, and a named user with user verification
+    let (authenticator, store) = register(SoftwareAuthenticator::new());
+    assert_eq!(
+        run(
+            new_peer(RP_ID, None, authenticator),
+            new_auth(false, store),
+            None
+        ),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+    let (authenticator, store) = register(SoftwareAuthenticator::new().with_user_verification());
+    assert_eq!(
+        run(
+            new_peer(RP_ID, Some(b"hans"), authenticator),
+            new_auth(true, store),
+            None
+        ),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    // Missing user verification, unknown user, another relying party and a
+    // credential the server does not know
+    let (authenticator, store) = register(SoftwareAuthenticator::new());
+    assert_eq!(
+        run(
+            new_peer(RP_ID, None, authenticator),
+            new_auth(true, store),
+            None
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+    let (authenticator, store) = register(SoftwareAuthenticator::new());
+    assert_eq!(
+        run(
+            new_peer(RP_ID, Some(b"fritz"), authenticator),
+            new_auth(false, store),
+            None
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+    let (authenticator, store) = register(SoftwareAuthenticator::new());
+    assert_eq!(
+        run(
+            new_peer("other.example.com", None, authenticator),
+            new_auth(false, store),
+            None
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+    let (authenticator, _) = register(SoftwareAuthenticator::new());
+    let (_, store) = register(SoftwareAuthenticator::new());
+    assert_eq!(
+        run(
+            new_peer(RP_ID, None, authenticator),
+            new_auth(false, store),
+            None
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_aka() {
+    use crate::eap_aka::{
+        AuthAkaMethod, AuthenticationCentre, AuthenticationVector, IdentityStore,
+        MemoryIdentityStore, Milenage, PeerAkaMethod, PeerIdentities, PeerIdentityStore,
+        ReauthContext, SoftAuc, SoftUsim, Usim, UsimError, UsimResponse,
+    };
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use crate::util::OwnedSlice;
+    use std::{cell::RefCell, rc::Rc};
+
+    const IMSI: &[u8] = b"001010123456789";
+
+    #[derive(Clone)]
+    struct SharedUsim(Vec<u8>, Rc<RefCell<SoftUsim>>);
+
+    impl PeerIdentityStore for SharedUsim {
+        fn load_identities(&mut self) -> PeerIdentities {
+            self.1.borrow_mut().load_identities()
+        }
+
+        fn store_identities(&mut self, identities: &PeerIdentities) {
+            self.1.borrow_mut().store_identities(identities)
+        }
+    }
+
+    impl Usim for SharedUsim {
+        fn imsi(&self) -> &[u8] {
+            &self.0
+        }
+
+        fn authenticate(
+            &mut self,
+            rand: &[u8; 16],
+            autn: &[u8; 16],
+        ) -> Result<UsimResponse, UsimError> {
+            self.1.borrow_mut().authenticate(rand, autn)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Shared<T>(Rc<RefCell<T>>);
+
+    impl AuthenticationCentre for Shared<SoftAuc> {
+        fn vector(
+            &mut self,
+            imsi: &[u8],
+            rand: &[u8; 16],
+            amf: [u8; 2],
+        ) -> Option<AuthenticationVector> {
+            self.0.borrow_mut().vector(imsi, rand, amf)
+        }
+
+        fn resynchronize(&mut self, imsi: &[u8], rand: &[u8; 16], auts: &[u8; 14]) -> bool {
+            self.0.borrow_mut().resynchronize(imsi, rand, auts)
+        }
+    }
+
+    impl IdentityStore for Shared<MemoryIdentityStore> {
+        fn permanent_identity(&mut self, pseudonym: &[u8]) -> Option<OwnedSlice<128>> {
+            self.0.borrow_mut().permanent_identity(pseudonym)
+        }
+
+        fn store_pseudonym(&mut self, pseudonym: &[u8], permanent_identity: &[u8]) {
+            self.0
+                .borrow_mut()
+                .store_pseudonym(pseudonym, permanent_identity)
+        }
+
+        fn load_reauth(&mut self, identity: &[u8]) -> Option<ReauthContext> {
+            self.0.borrow_mut().load_reauth(identity)
+        }
+
+        fn store_reauth(&mut self, context: &ReauthContext) {
+            self.0.borrow_mut().store_reauth(context)
+        }
+
+        fn remove_reauth(&mut self, identity: &[u8]) {
+            self.0.borrow_mut().remove_reauth(identity)
+        }
+    }
+
+    let milenage = || Milenage::new(&[0x46; 16], &[0xcd; 16]);
+
+    for prime in [false, true] {
+        let usim = SharedUsim(
+            IMSI.to_vec(),
+            Rc::new(RefCell::new(SoftUsim::new(IMSI, milenage()))),
+        );
+        let auc = Shared(Rc::new(RefCell::new(
+            SoftAuc::new().with_subscriber(IMSI, milenage()),
+        )));
+        let store = Shared::<MemoryIdentityStore>::default();
+
+        let new_peer = |usim: &SharedUsim, network_name: &[u8]| {
+            let mut method = PeerAkaMethod::new(usim.clone());
+            if prime {
+                method = method.with_aka_prime().with_network_name(network_name);
+            }
+            Peer::from_layer(
+                PeerLayer::new()
+                    .with(peer::PeerIdentityMethod::new(b"anonymous@example.org"))
+                    .with(method),
+            )
+        };
+        let new_auth = || {
+            let mut method = AuthAkaMethod::new(auc.clone(), store.clone())
+                .with_pseudonyms()
+                .with_fast_reauth();
+            if prime {
+                method = method.with_aka_prime(b"WLAN");
+            }
+            Authenticator::from_layer(
+                AuthLayer::new()
+                    .with(auth::AuthIdentityMethod::new())
+                    .with(method),
+            )
+        };
+        let identities = || usim.1.borrow().clone().load_identities();
+
+        // Full authentication with the permanent identity
+        assert_eq!(
+            run(new_peer(&usim, b"WLAN"), new_auth(), None),
+            (EapStepStatus::Finished, EapStepStatus::Finished)
+        );
+        let first = identities();
+        let pseudonym = first.pseudonym.clone().unwrap();
+        assert_eq!(pseudonym.as_ref()[0], if prime { b'7' } else { b'2' });
+        assert_eq!(first.reauth.as_ref().unwrap().counter(), 0);
+
+        // Fast re-authentication
+        assert_eq!(
+            run(new_peer(&usim, b"WLAN"), new_auth(), None),
+            (EapStepStatus::Finished, EapStepStatus::Finished)
+        );
+        let second = identities();
+        assert_eq!(second.pseudonym, Some(pseudonym.clone()));
+        let reauth = second.reauth.unwrap();
+        assert_eq!(reauth.counter(), 1);
+        assert_ne!(reauth.identity(), first.reauth.unwrap().identity());
+
+        // The server lost the re-authentication identity, the pseudonym still works
+        store.0.borrow_mut().remove_reauth(reauth.identity());
+        assert_eq!(
+            run(new_peer(&usim, b"WLAN"), new_auth(), None),
+            (EapStepStatus::Finished, EapStepStatus::Finished)
+        );
+        assert_ne!(identities().pseudonym, Some(pseudonym));
+
+        // Unknown temporary identities, falls back to the permanent identity
+        store.0.borrow_mut().clear();
+        assert_eq!(
+            run(new_peer(&usim, b"WLAN"), new_auth(), None),
+            (EapStepStatus::Finished, EapStepStatus::Finished)
+        );
+
+        // The USIM is ahead of the authentication centre
+        let resync_usim = SharedUsim(
+            IMSI.to_vec(),
+            Rc::new(RefCell::new(SoftUsim::new(IMSI, milenage()).with_sqn(1000))),
+        );
+        assert_eq!(
+            run(new_peer(&resync_usim, b"WLAN"), new_auth(), None),
+            (EapStepStatus::Finished, EapStepStatus::Finished)
+        );
+        assert_eq!(resync_usim.1.borrow().sqn(), 1001);
+
+        // Another subscriber key
+        let wrong_usim = SharedUsim(
+            IMSI.to_vec(),
+            Rc::new(RefCell::new(SoftUsim::new(
+                IMSI,
+                Milenage::new(&[0x47; 16], &[0xcd; 16]),
+            ))),
+        );
+        assert_eq!(
+            run(new_peer(&wrong_usim, b"WLAN"), new_auth(), None),
+            (EapStepStatus::Error, EapStepStatus::Error)
+        );
+
+        if prime {
+            // Keys bound to another access network, the fresh USIM has no
+            // re-authentication identity
+            let other_usim = SharedUsim(
+                IMSI.to_vec(),
+                Rc::new(RefCell::new(SoftUsim::new(IMSI, milenage()))),
+            );
+            assert_eq!(
+                run(new_peer(&other_usim, b"other"), new_auth(), None),
+                (EapStepStatus::Error, EapStepStatus::Error)
+            );
+        }
+    }
+}
+
+#[test]
+fn own_sim() {
+    use crate::eap_aka::Milenage;
+    use crate::eap_sim::{
+        AuthSimMethod, IdentityStore, MemoryIdentityStore, PeerIdentities, PeerIdentityStore,
+        PeerSimMethod, ReauthContext, Sim, SoftSim, SoftTripletProvider, Triplet, TripletProvider,
+    };
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use crate::util::OwnedSlice;
+    use std::{cell::RefCell, rc::Rc};
+
+    const IMSI: &[u8] = b"001010123456789";
+
+    #[derive(Clone)]
+    struct SharedSim(Vec<u8>, Rc<RefCell<SoftSim>>);
+
+    impl PeerIdentityStore for SharedSim {
+        fn load_identities(&mut self) -> PeerIdentities {
+            self.1.borrow_mut().load_identities()
+        }
+
+        fn store_identities(&mut self, identities: &PeerIdentities) {
+            self.1.borrow_mut().store_identities(identities)
+        }
+    }
+
+    impl Sim for SharedSim {
+        fn imsi(&self) -> &[u8] {
+            &self.0
+        }
+
+        fn run_gsm_algorithm(&mut self, rand: &[u8; 16]) -> Triplet {
+            self.1.borrow_mut().run_gsm_algorithm(rand)
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Shared<T>(Rc<RefCell<T>>);
+
+    impl TripletProvider for Shared<SoftTripletProvider> {
+        fn triplet(&mut self, imsi: &[u8], rand: &[u8; 16]) -> Option<Triplet> {
+            self.0.borrow_mut().triplet(imsi, rand)
+        }
+    }
+
+    impl IdentityStore for Shared<MemoryIdentityStore> {
+        fn permanent_identity(&mut self, pseudonym: &[u8]) -> Option<OwnedSlice<128>> {
+            self.0.borrow_mut().permanent_identity(pseudonym)
+        }
+
+        fn store_pseudonym(&mut self, pseudonym: &[u8], permanent_identity: &[u8]) {
+            self.0
+                .borrow_mut()
+                .store_pseudonym(pseudonym, permanent_identity)
+        }
+
+        fn load_reauth(&mut self, identity: &[u8]) -> Option<ReauthContext> {
+            self.0.borrow_mut().load_reauth(identity)
+        }
+
+        fn store_reauth(&mut self, context: &ReauthContext) {
+            self.0.borrow_mut().store_reauth(context)
+        }
+
+        fn remove_reauth(&mut self, identity: &[u8]) {
+            self.0.borrow_mut().remove_reauth(identity)
+        }
+    }
+
+    let milenage = || Milenage::new(&[0x46; 16], &[0xcd; 16]);
+
+    let sim = SharedSim(
+        IMSI.to_vec(),
+        Rc::new(RefCell::new(SoftSim::new(IMSI, milenage()))),
+    );
+    let provider = Shared(Rc::new(RefCell::new(
+        SoftTripletProvider::new().with_subscriber(IMSI, milenage()),
+    )));
+    let store = Shared::<MemoryIdentityStore>::default();
+
+    let new_peer = |sim: &SharedSim| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"anonymous@example.org"))
+                .with(PeerSimMethod::new(sim.clone())),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(
+            AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+                AuthSimMethod::new(provider.clone(), store.clone())
+                    .with_pseudonyms()
+                    .with_fast_reauth(),
+            ),
+        )
+    };
+    let identities = || sim.1.borrow().clone().load_identities();
+
+    // Full authentication with the permanent identity
+    assert_eq!(
+        run(new_peer(&sim), new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+    let first = identities();
+    let pseudonym = first.pseudonym.clone().unwrap();
+    assert_eq!(pseudonym.as_ref()[0], b'3');
+    assert_eq!(first.reauth.as_ref().unwrap().counter(), 0);
+
+    // Fast re-authentication
+    assert_eq!(
+        run(new_peer(&sim), new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+    let second = identities();
+    assert_eq!(second.pseudonym, Some(pseudonym.clone()));
+    let reauth = second.reauth.unwrap();
+    assert_eq!(reauth.counter(), 1);
+    assert_ne!(reauth.identity(), first.reauth.unwrap().identity());
+
+    // The server lost the re-authentication identity, the pseudonym still works
+    store.0.borrow_mut().remove_reauth(reauth.identity());
+    assert_eq!(
+        run(new_peer(&sim), new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+    assert_ne!(identities().pseudonym, Some(pseudonym));
+
+    // Unknown temporary identities, falls back to the permanent identity
+    store.0.borrow_mut().clear();
+    assert_eq!(
+        run(new_peer(&sim), new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    // Another subscriber key
+    let wrong_sim = SharedSim(
+        IMSI.to_vec(),
+        Rc::new(RefCell::new(SoftSim::new(
+            IMSI,
+            Milenage::new(&[0x47; 16], &[0xcd; 16]),
+        ))),
+    );
+    assert_eq!(
+        run(new_peer(&wrong_sim), new_auth(), None),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_vs_wpa_md5() {
+    if hostap_missing() {
+        return;
+    }
+
+    println!("Own Peer vs WPA Authenticator");
+    let peer = Peer::new_password("hans", "1234");
+    let auth = wifieap::server::EapServer::new_password("hans", "1234");
+
+    assert_eq!(
+        run(peer, auth, None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    // reverse role
+    println!("Own Authenticator vs WPA Peer");
+    let peer = wifieap::peer::EapPeer::new_password("hans", "1234");
+    let auth = Authenticator::new_password("1234");
+
+    assert_eq!(
+        run(peer, auth, None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    // Negative
+    println!("Own Peer vs WPA Authenticator; Negative");
+    let peer = Peer::new_password("hans", "1234");
+    let auth = wifieap::server::EapServer::new_password("hans", "not 1234");
+
+    assert_eq!(
+        run(peer, auth, Some(ExtraOptions::wpa_does_not_give_up())),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+
+    // Negative everse role
+    println!("Own Authenticator vs WPA Peer; Negative");
+    let peer = wifieap::peer::EapPeer::new_password("hans", "1234");
+    let auth = Authenticator::new_password("not 1234");
+
+    assert_eq!(
+        run(peer, auth, Some(ExtraOptions::wpa_does_not_give_up())),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_vs_wpa_tls() {
+    if hostap_missing() {
+        return;
+    }
+
+    // Positive
+    println!("Own Peer vs WPA Authenticator");
+    let peer = Peer::new_tls("hans", dummycert::TlsConfig::dummy_client_rsa());
+    let auth = wifieap::server::EapServer::new_tls(dummycert::TlsConfig::dummy_server_rsa());
+
+    assert_eq!(
+        run(peer, auth, None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    // reverse
+    println!("Own Authenticator vs WPA Peer");
+    let peer = wifieap::peer::EapPeer::new_tls("hans", dummycert::TlsConfig::dummy_client_rsa());
+    let auth = Authenticator::new_tls(dummycert::TlsConfig::dummy_server_rsa());
+
+    assert_eq!(
+        run(peer, auth, None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    // Negative
+    // These Cert use different CA's
+    println!("Own Peer vs WPA Authenticator; Negative");
+    let peer = Peer::new_tls("hans", dummycert::TlsConfig::dummy_client_rsa());
+    let auth = wifieap::server::EapServer::new_tls(dummycert::TlsConfig::dummy_server_ed25519());
+
+    assert_eq!(
+        run(peer, auth, Some(ExtraOptions::wpa_does_not_give_up())),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+
+    // reverse
+    println!("Own Authenticator vs WPA Peer; Negative");
+    let peer = wifieap::peer::EapPeer::new_tls("hans", dummycert::TlsConfig::dummy_client_rsa());
+    let auth = Authenticator::new_tls(dummycert::TlsConfig::dummy_server_ed25519());
+
+    assert_eq!(
+        run(peer, auth, Some(ExtraOptions::wpa_does_not_give_up())),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_vs_wpa_wsc() {
+    if hostap_missing() {
+        return;
+    }
+
+    use crate::eap_wsc::{
+        AuthWscMethod, Credential, Device, DevicePassword, PeerWscMethod, IDENTITY_ENROLLEE,
+    };
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use std::{cell::RefCell, rc::Rc};
+
+    let new_peer = |password, received: &Rc<RefCell<Vec<Credential>>>| {
+        let received = received.clone();
+        let device = Device::new([0x22; 16], [0x02, 0, 0, 0, 0, 0x22], "sensor-1");
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(IDENTITY_ENROLLEE))
+                .with(PeerWscMethod::new(device, password, move |credential| {
+                    received.borrow_mut().push(credential.clone())
+                })),
+        )
+    };
+    let new_auth = |password| {
+        let device = Device::new([0x11; 16], [0x02, 0, 0, 0, 0, 0x11], "access point")
+            .with_primary_device_type(Device::DEVICE_TYPE_ACCESS_POINT);
+        let network = Credential::new(
+            b"sensor-net",
+            Credential::AUTHENTICATION_WPA2_PSK,
+            Credential::ENCRYPTION_AES,
+            b"12345678",
+        );
+        Authenticator::from_layer(
+            AuthLayer::new()
+                .with(auth::AuthIdentityMethod::new())
+                .with(AuthWscMethod::new(device, password, network)),
+        )
+    };
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    for pin in [Some("12345670"), None] {
+        let password = || pin.map_or(DevicePassword::PushButton, DevicePassword::pin);
+
+        println!("Own Peer vs WPA Authenticator");
+        let peer = new_peer(password(), &received);
+        let auth = wifieap::server::EapServer::new_wps(b"sensor-net", "12345678", pin);
+
+        assert_eq!(
+            run(peer, auth, None),
+            (EapStepStatus::Error, EapStepStatus::Error)
+        );
+        let credentials = received.take();
+        assert_eq!(credentials.len(), 1);
+        assert_eq!(credentials[0].ssid(), b"sensor-net");
+        assert_eq!(credentials[0].network_key(), b"12345678");
+
+        // reverse role
+        println!("Own Authenticator vs WPA Peer");
+        let wpa_received = received.clone();
+        let peer = wifieap::peer::EapPeer::new_wps(pin, move |credential| {
+            wpa_received.borrow_mut().push(Credential::new(
+                &credential.ssid,
+                Credential::AUTHENTICATION_WPA2_PSK,
+                Credential::ENCRYPTION_AES,
+                &credential.network_key,
+            ))
+        });
+        let auth = new_auth(password());
+
+        assert_eq!(
+            run(peer, auth, None),
+            (EapStepStatus::Error, EapStepStatus::Error)
+        );
+        let credentials = received.take();
+        assert_eq!(credentials.len(), 1);
+        assert_eq!(credentials[0].ssid(), b"sensor-net");
+        assert_eq!(credentials[0].network_key(), b"12345678");
+    }
+
+    // Negative
+    println!("Own Peer vs WPA Authenticator; Negative");
+    let peer = new_peer(DevicePassword::pin("12345670"), &received);
+    let auth = wifieap::server::EapServer::new_wps(b"sensor-net", "12345678", Some("12345678"));
+
+    assert_eq!(
+        run(peer, auth, Some(ExtraOptions::wpa_does_not_give_up())),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+    assert!(received.take().is_empty());
+}
+
+#[test]
+fn own_vs_wpa_gtc() {
+    if hostap_missing() {
+        return;
+    }
+
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use crate::util::OwnedSlice;
+
+    let new_peer = |token: &'static [u8]| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"hans"))
+                .with(peer::PeerGtcMethod::new(move |_: &[u8]| {
+                    Some(OwnedSlice::from(token))
+                })),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            auth::AuthGtcMethod::new(
+                b"Token:",
+                auth::HotpVerifier::new(b"12345678901234567890", 0).unwrap(),
+            ),
+        ))
+    };
+    let new_wpa_auth = |password| {
+        wifieap::server::EapServer::builder()
+            .set_password("hans", password)
+            .allow_gtc()
+            .build()
+    };
+
+    println!("Own Peer vs WPA Authenticator");
+    assert_eq!(
+        run(new_peer(b"755224"), new_wpa_auth("755224"), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    // reverse role, hostap answers with its password, the first HOTP value
+    println!("Own Authenticator vs WPA Peer");
+    let peer = wifieap::peer::EapPeer::new_password("hans", "755224");
+    assert_eq!(
+        run(peer, new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    println!("Own Peer vs WPA Authenticator; Negative");
+    assert_eq!(
+        run(
+            new_peer(b"000000"),
+            new_wpa_auth("755224"),
+            Some(ExtraOptions::wpa_does_not_give_up())
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+
+    println!("Own Authenticator vs WPA Peer; Negative");
+    let peer = wifieap::peer::EapPeer::new_password("hans", "000000");
+    assert_eq!(
+        run(peer, new_auth(), Some(ExtraOptions::wpa_does_not_give_up())),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_vs_wpa_psk() {
+    if hostap_missing() {
+        return;
+    }
+
+    use crate::eap_psk::{AuthPskMethod, PeerPskMethod};
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+
+    // hostap takes the PSK as a 16 character password
+    const PSK: &str = "0123456789abcdef";
+
+    let new_peer = |psk: &str| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"sensor"))
+                .with(PeerPskMethod::new(
+                    b"sensor",
+                    psk.as_bytes().try_into().unwrap(),
+                )),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            AuthPskMethod::new(b"server", |id_p: &[u8]| {
+                (id_p == b"sensor").then(|| PSK.as_bytes().try_into().unwrap())
+            }),
+        ))
+    };
+    let new_wpa_auth = || {
+        wifieap::server::EapServer::builder()
+            .set_password("sensor", PSK)
+            .allow_psk()
+            .build()
+    };
+
+    println!("Own Peer vs WPA Authenticator");
+    assert_eq!(
+        run(new_peer(PSK), new_wpa_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    println!("Own Authenticator vs WPA Peer");
+    let peer = wifieap::peer::EapPeer::new_password("sensor", PSK);
+    assert_eq!(
+        run(peer, new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    println!("Own Peer vs WPA Authenticator; Negative");
+    assert_eq!(
+        run(
+            new_peer("fedcba9876543210"),
+            new_wpa_auth(),
+            Some(ExtraOptions::wpa_does_not_give_up())
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+
+    println!("Own Authenticator vs WPA Peer; Negative");
+    let peer = wifieap::peer::EapPeer::new_password("sensor", "fedcba9876543210");
+    assert_eq!(
+        run(peer, new_auth(), Some(ExtraOptions::wpa_does_not_give_up())),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_vs_wpa_gpsk() {
+    if hostap_missing() {
+        return;
+    }
+
+    use crate::eap_gpsk::{AuthGpskMethod, PeerGpskMethod};
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use crate::util::OwnedSlice;
+
+    let new_peer = |psk: &str| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"gateway"))
+                .with(PeerGpskMethod::new(b"gateway", psk.as_bytes())),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            AuthGpskMethod::new(b"server", |id_peer: &[u8]| {
+                (id_peer == b"gateway").then(|| OwnedSlice::from(b"0123456789abcdef"))
+            }),
+        ))
+    };
+    let new_wpa_auth = || {
+        wifieap::server::EapServer::builder()
+            .set_password("gateway", "0123456789abcdef")
+            .allow_gpsk()
+            .build()
+    };
+
+    println!("Own Peer vs WPA Authenticator");
+    assert_eq!(
+        run(new_peer("0123456789abcdef"), new_wpa_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    println!("Own Authenticator vs WPA Peer");
+    let peer = wifieap::peer::EapPeer::new_password("gateway", "0123456789abcdef");
+    assert_eq!(
+        run(peer, new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    println!("Own Peer vs WPA Authenticator; Negative");
+    assert_eq!(
+        run(
+            new_peer("fedcba9876543210"),
+            new_wpa_auth(),
+            Some(ExtraOptions::wpa_does_not_give_up())
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+
+    println!("Own Authenticator vs WPA Peer; Negative");
+    let peer = wifieap::peer::EapPeer::new_password("gateway", "fedcba9876543210");
+    assert_eq!(
+        run(peer, new_auth(), Some(ExtraOptions::wpa_does_not_give_up())),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_vs_wpa_teap() {
+    if hostap_missing() {
+        return;
+    }
+
+    use crate::eap_teap::{AuthTeapMethod, IdentityType, PeerTeapMethod};
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use dummycert::TlsConfig;
+    use wifieap::EapMethod;
+
+    let new_peer = |password: &[u8]| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"anonymous"))
+                .with(
+                    PeerTeapMethod::new(TlsConfig::dummy_client_rsa()).with_inner_method(
+                        IdentityType::User,
+                        PeerLayer::new()
+                            .with(peer::PeerIdentityMethod::new(b"hans"))
+                            .with(peer::PeerMD5ChallengeMethod::new(password)),
+                    ),
+                ),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(
+            AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+                AuthTeapMethod::new(TlsConfig::dummy_server_rsa()).with_inner_method(
+                    None,
+                    AuthLayer::new()
+                        .with(auth::AuthIdentityMethod::new())
+                        .with(auth::AuthMD5ChallengeMethod::new(b"1234")),
+                ),
+            ),
+        )
+    };
+    let new_wpa_peer = |password| {
+        wifieap::peer::EapPeer::builder("hans")
+            .set_password(password)
+            .set_tls_config(TlsConfig::dummy_client_rsa())
+            .set_phase2("auth=MD5")
+            .build()
+    };
+    let new_wpa_auth = || {
+        wifieap::server::EapServer::builder()
+            .set_password("hans", "1234")
+            .set_tls_config(TlsConfig::dummy_server_rsa())
+            .allow_teap(EapMethod::MD5)
+            .build()
+    };
+
+    println!("Own Peer vs WPA Authenticator");
+    assert_eq!(
+        run(new_peer(b"1234"), new_wpa_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    println!("Own Authenticator vs WPA Peer");
+    assert_eq!(
+        run(new_wpa_peer("1234"), new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    println!("Own Peer vs WPA Authenticator; Negative");
+    assert_eq!(
+        run(
+            new_peer(b"4321"),
+            new_wpa_auth(),
+            Some(ExtraOptions::wpa_does_not_give_up())
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+
+    println!("Own Authenticator vs WPA Peer; Negative");
+    assert_eq!(
+        run(
+            new_wpa_peer("4321"),
+            new_auth(),
+            Some(ExtraOptions::wpa_does_not_give_up())
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_vs_wpa_mschapv2() {
+    if hostap_missing() {
+        return;
+    }
+
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use crate::util::OwnedSlice;
+
+    let new_peer = |password: &[u8]| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"hans"))
+                .with(peer::PeerMschapv2Method::new(b"hans", password)),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            auth::AuthMschapv2Method::new(b"server", |username: &[u8]| {
+                (username == b"hans").then(|| OwnedSlice::from(b"1234"))
+            }),
+        ))
+    };
+    let new_wpa_auth = || {
+        wifieap::server::EapServer::builder()
+            .set_password("hans", "1234")
+            .allow_mschapv2()
+            .build()
+    };
+
+    println!("Own Peer vs WPA Authenticator");
+    assert_eq!(
+        run(new_peer(b"1234"), new_wpa_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    println!("Own Authenticator vs WPA Peer");
+    let peer = wifieap::peer::EapPeer::new_password("hans", "1234");
+    assert_eq!(
+        run(peer, new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    println!("Own Peer vs WPA Authenticator; Negative");
+    assert_eq!(
+        run(
+            new_peer(b"4321"),
+            new_wpa_auth(),
+            Some(ExtraOptions::wpa_does_not_give_up())
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+
+    println!("Own Authenticator vs WPA Peer; Negative");
+    let peer = wifieap::peer::EapPeer::new_password("hans", "4321");
+    assert_eq!(
+        run(peer, new_auth(), Some(ExtraOptions::wpa_does_not_give_up())),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_vs_wpa_aka() {
+    if hostap_missing() {
+        return;
+    }
+
+    use crate::eap_aka::{AuthAkaMethod, MemoryIdentityStore, Milenage, SoftAuc};
+    use crate::layers::{auth, AuthLayer};
+
+    const IMSI: &[u8] = b"001010123456789";
+    // hostap simulates the USIM from K:OPc:SQN, hostapd needs an external
+    // HLR/AuC gateway for the server side, so only its peer is tested
+    const OPC: [u8; 16] = [0xcd; 16];
+    let usim = |k: &str| format!("{k}:{}:000000000000", "cd".repeat(16));
+
+    for prime in [false, true] {
+        let new_auth = || {
+            let auc = SoftAuc::new().with_subscriber(IMSI, Milenage::with_opc(&[0x46; 16], &OPC));
+            let mut method = AuthAkaMethod::new(auc, MemoryIdentityStore::default());
+            if prime {
+                method = method.with_aka_prime(b"WLAN");
+            }
+            Authenticator::from_layer(
+                AuthLayer::new()
+                    .with(auth::AuthIdentityMethod::new())
+                    .with(method),
+            )
+        };
+        let identity = if prime {
+            "6001010123456789"
+        } else {
+            "0001010123456789"
+        };
+
+        println!("Own Authenticator vs WPA Peer; AKA' {prime}");
+        let peer = wifieap::peer::EapPeer::new_password(identity, &usim(&"46".repeat(16)));
+        assert_eq!(
+            run(peer, new_auth(), None),
+            (EapStepStatus::Finished, EapStepStatus::Finished)
+        );
+
+        println!("Own Authenticator vs WPA Peer; AKA' {prime}; Negative");
+        let peer = wifieap::peer::EapPeer::new_password(identity, &usim(&"47".repeat(16)));
+        assert_eq!(
+            run(peer, new_auth(), Some(ExtraOptions::wpa_does_not_give_up())),
+            (EapStepStatus::Error, EapStepStatus::Error)
+        );
+    }
+}
+
+#[test]
+fn own_vs_wpa_sim() {
+    if hostap_missing() {
+        return;
+    }
+
+    use crate::eap_aka::Milenage;
+    use crate::eap_sim::{AuthSimMethod, MemoryIdentityStore, SoftTripletProvider};
+    use crate::layers::{auth, AuthLayer};
+
+    const IMSI: &[u8] = b"001010123456789";
+    // hostap simulates the SIM with GSM-Milenage from K:OPc, hostapd needs an
+    // external HLR/AuC gateway for the server side, so only its peer is tested
+    const OPC: [u8; 16] = [0xcd; 16];
+    let sim = |k: &str| format!("{k}:{}", "cd".repeat(16));
+
+    let new_auth = || {
+        let provider =
+            SoftTripletProvider::new().with_subscriber(IMSI, Milenage::with_opc(&[0x46; 16], &OPC));
+        Authenticator::from_layer(
+            AuthLayer::new()
+                .with(auth::AuthIdentityMethod::new())
+                .with(AuthSimMethod::new(provider, MemoryIdentityStore::default())),
+        )
+    };
+
+    println!("Own Authenticator vs WPA Peer");
+    let peer = wifieap::peer::EapPeer::new_password("1001010123456789", &sim(&"46".repeat(16)));
+    assert_eq!(
+        run(peer, new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    println!("Own Authenticator vs WPA Peer; Negative");
+    let peer = wifieap::peer::EapPeer::new_password("1001010123456789", &sim(&"47".repeat(16)));
+    assert_eq!(
+        run(peer, new_auth(), Some(ExtraOptions::wpa_does_not_give_up())),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_vs_wpa_eke() {
+    if hostap_missing() {
+        return;
+    }
+
+    use crate::eap_eke::{AuthEkeMethod, PeerEkeMethod};
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use crate::util::OwnedSlice;
+
+    let new_peer = |password: &[u8]| {
+        Peer::from_layer(
+            PeerLayer::new()
+                .with(peer::PeerIdentityMethod::new(b"alice@example.org"))
+                .with(PeerEkeMethod::new(b"alice@example.org", password)),
+        )
+    };
+    let new_auth = || {
+        Authenticator::from_layer(AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            AuthEkeMethod::new(b"server@example.org", |id_p: &[u8]| {
+                (id_p == b"alice@example.org").then(|| OwnedSlice::from(b"correct horse"))
+            }),
+        ))
+    };
+    let new_wpa_auth = || {
+        wifieap::server::EapServer::builder()
+            .set_password("alice@example.org", "correct horse")
+            .allow_eke()
+            .build()
+    };
+
+    println!("Own Peer vs WPA Authenticator");
+    assert_eq!(
+        run(new_peer(b"correct horse"), new_wpa_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    println!("Own Authenticator vs WPA Peer");
+    let peer = wifieap::peer::EapPeer::new_password("alice@example.org", "correct horse");
+    assert_eq!(
+        run(peer, new_auth(), None),
+        (EapStepStatus::Finished, EapStepStatus::Finished)
+    );
+
+    println!("Own Peer vs WPA Authenticator; Negative");
+    assert_eq!(
+        run(
+            new_peer(b"battery staple"),
+            new_wpa_auth(),
+            Some(ExtraOptions::wpa_does_not_give_up())
+        ),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+
+    println!("Own Authenticator vs WPA Peer; Negative");
+    let peer = wifieap::peer::EapPeer::new_password("alice@example.org", "battery staple");
+    assert_eq!(
+        run(peer, new_auth(), Some(ExtraOptions::wpa_does_not_give_up())),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+}
+
+#[test]
+fn own_vs_wpa_ikev2() {
+    if hostap_missing() {
+        return;
+    }
+
+    use crate::eap_ikev2::{AuthIkev2Method, PeerIkev2Method};
+    use crate::layers::{auth, peer, AuthLayer, PeerLayer};
+    use crate::util::OwnedSlice;
+
+    // hostap only offers MODP groups with HMAC-SHA1/MD5, we only X25519 with
+    // HMAC-SHA2-256. Without a common proposal both sides have to fail cleanly.
+    let peer = Peer::from_layer(
+        PeerLayer::new()
+            .with(peer::PeerIdentityMethod::new(b"alice@example.org"))
+            .with(PeerIkev2Method::new(b"alice").with_shared_secret(b"correct horse")),
+    );
+    let auth =
+        Authenticator::from_layer(AuthLayer::new().with(auth::AuthIdentityMethod::new()).with(
+            AuthIkev2Method::new(b"server@example.org", |identity: &[u8]| {
+                (identity == b"alice@example.org").then(|| OwnedSlice::from(b"correct horse"))
+            }),
+        ));
+
+    println!("Own Peer vs WPA Authenticator; No common proposal");
+    let wpa_auth = wifieap::server::EapServer::builder()
+        .set_password("alice@example.org", "correct horse")
+        .allow_ikev2()
+        .build();
+    assert_eq!(
+        run(peer, wpa_auth, Some(ExtraOptions::wpa_does_not_give_up())),
+        (EapStepStatus::Error, EapStepStatus::Error)
+    );
+
+    println!("Own Authenticator vs WPA Peer; No common proposal");
+    let wpa_peer = wifieap::peer::EapPeer::new_password("alice@example.org", "correct horse");
+    assert_eq!(
+        run(wpa_peer, auth, Some(ExtraOptions::wpa_does_not_give_up())),
         (EapStepStatus::Error, EapStepStatus::Error)
     );
 }
@@ -1,20 +1,56 @@
 use crate::{
-    layers::mux::{TupleAppend, TupleById, TupleElement},
+    environment::response_capacity,
+    layers::{
+        method_type::{EXPANDED_TYPE_LEN, TYPE_EXPANDED, TYPE_IDENTITY, TYPE_NOTIFICATION},
+        mux::{TupleAppend, TupleById, TupleElement},
+        MethodPreference, MethodType, Negotiation, PreferenceOrder, SecurityClaims,
+    },
     message::{Message, MessageCode},
-    EapEnvironment, MessageBuilder,
+    util::OwnedSlice,
+    EapEnvironment, EapEnvironmentResponse, MessageBuilder,
 };
 
 use crate::layers::eap_layer::{
-    PeerAuthLayer as ThisLayer, PeerAuthLayerResult as ThisLayerResult,
+    PeerAuthLayer as ThisLayer, PeerAuthLayerResult as ThisLayerResult, SessionKeys,
 };
 
 #[derive(Clone)]
 pub struct AuthLayer<I> {
-    peer_has_sent_nak: bool, // RFC allows only one NAK per session
-    next_layer: u8,
+    // Positions of the candidates the peer has refused with a Nak, the RFC
+    // allows one Nak per proposed method
+    refused: u64,
+    next_layer: MethodType,
     candidates: I,
+    notification: Option<OwnedSlice<NOTIFICATION_INLINE_LEN>>,
+    // Set while a Notification Request is outstanding
+    after_notification: Option<AfterNotification>,
+    held_request: Option<OwnedSlice<255>>,
+    preference: MethodPreference,
+    negotiation: Option<Negotiation>,
+    // Set once the peer answered the selected method and the negotiation
+    // was reported to the environment
+    negotiation_settled: bool,
 }
 
+/// What a Notification Request has interrupted
+#[derive(Clone, Copy)]
+enum AfterNotification {
+    StartMethod,
+    /// Sends the held Request of the active method
+    Request,
+    Finished,
+    Failed,
+}
+
+/// Longest Notification text [`AuthLayer::notify`] accepts without an
+/// allocator, with `std` or `alloc` the text is only bound by the MTU
+pub const NOTIFICATION_INLINE_LEN: usize = 64;
+
+/// The text of a Notification is longer than [`NOTIFICATION_INLINE_LEN`]
+/// without an allocator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotificationTooLong;
+
 pub const METHOD_CLIENT_PROPOSAL: u8 = 3;
 
 pub struct RecvMeta<'a> {
@@ -22,7 +58,7 @@ pub struct RecvMeta<'a> {
 }
 
 pub trait AuthMethodLayer {
-    fn method_identifier(&self) -> u8;
+    fn method_identifier(&self) -> MethodType;
     fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a>;
     fn recv<'a>(
         &mut self,
@@ -33,6 +69,21 @@ pub trait AuthMethodLayer {
     fn selectable_by_nak(&self) -> bool {
         true
     }
+
+    /// The method authenticates the Identifier of its next Request, which
+    /// then cannot be held back for a Notification
+    fn binds_identifier(&self) -> bool {
+        false
+    }
+
+    /// Claims of the method, RFC 3748 section 7.2
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::NONE
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        None
+    }
 }
 
 pub enum AuthMethodLayerResult<'a> {
@@ -51,8 +102,7 @@ where
     }
 
     fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> ThisLayerResult<'a> {
-        let res = self.current_layer().start(env);
-        self.process_result(res)
+        self.send_notification(AfterNotification::StartMethod, env)
     }
 
     fn recv<'a>(&mut self, msg: &Message, env: &'a mut dyn EapEnvironment) -> ThisLayerResult<'a> {
@@ -60,46 +110,115 @@ where
             return ThisLayerResult::Failed(env);
         }
 
-        if msg.body.is_empty() {
+        let Some((method_identifier, data)) = MethodType::parse(msg.body) else {
             // Message Too Short
             return ThisLayerResult::Failed(env);
+        };
+
+        if let Some(after) = self.after_notification.take() {
+            if method_identifier != MethodType::Legacy(TYPE_NOTIFICATION) {
+                return ThisLayerResult::Failed(env);
+            }
+            return self.resume(after, env);
         }
 
-        let method_identifier = msg.body[0];
-        if method_identifier == METHOD_CLIENT_PROPOSAL {
-            if self.peer_has_sent_nak {
+        let expanded_nak = match method_identifier {
+            MethodType::Legacy(METHOD_CLIENT_PROPOSAL) => Some(false),
+            MethodType::EXPANDED_NAK => Some(true),
+            _ => None,
+        };
+        if let Some(expanded_nak) = expanded_nak {
+            let Some(position) = self
+                .candidates
+                .iter()
+                .take(MAX_CANDIDATES)
+                .position(|c| c.method_identifier() == self.next_layer)
+            else {
+                // Nak to a method that was never proposed
+                return ThisLayerResult::Failed(env);
+            };
+            let refused = refused_bit(position);
+            if self.refused & refused != 0 {
                 // Protocol Violation
                 return ThisLayerResult::Failed(env);
             }
-            self.peer_has_sent_nak = true;
-
-            for candidate in self.candidates.iter() {
-                if candidate.selectable_by_nak()
-                    && msg.body[1..].contains(&candidate.method_identifier())
-                {
-                    self.next_layer = candidate.method_identifier();
-
-                    let res = self.current_layer().start(env);
-                    return self.process_result(res);
+            self.refused |= refused;
+
+            let order = self.preference.preference_order();
+            let selected = self
+                .candidates
+                .iter()
+                .take(MAX_CANDIDATES)
+                .enumerate()
+                .filter(|(position, _)| self.refused & refused_bit(*position) == 0)
+                .filter(|(_, c)| c.selectable_by_nak())
+                .filter_map(|(position, c)| {
+                    let method = c.method_identifier();
+                    let peer_rank = nak_position(data, expanded_nak, method)?;
+                    let rank = self.preference.rank(method, position);
+                    match order {
+                        PreferenceOrder::Authenticator => Some((rank, method)),
+                        PreferenceOrder::Peer => Some((peer_rank, method)),
+                    }
+                })
+                .min_by_key(|(rank, _)| *rank)
+                .map(|(_, method)| method);
+
+            let negotiation = Negotiation {
+                proposed: self.negotiation.map_or(self.next_layer, |n| n.proposed),
+                selected,
+                nak: true,
+                order,
+            };
+            self.negotiation = Some(negotiation);
+
+            return match selected {
+                Some(method) => {
+                    self.next_layer = method;
+                    self.send_notification(AfterNotification::StartMethod, env)
                 }
-            }
-
-            return ThisLayerResult::Failed(env); // <- no matching method
+                None => {
+                    env.negotiation(&negotiation);
+                    ThisLayerResult::Failed(env) // <- no matching method
+                }
+            };
         }
 
         if method_identifier != self.next_layer {
             return ThisLayerResult::Failed(env);
         }
 
+        if let Some(negotiation) = self.negotiation.filter(|_| !self.negotiation_settled) {
+            self.negotiation_settled = true;
+            env.negotiation(&negotiation);
+        }
+
         let res = self
             .current_layer()
-            .recv(&msg.body[1..], &RecvMeta { message: *msg }, env);
+            .recv(data, &RecvMeta { message: *msg }, env);
 
         self.process_result(res)
     }
 
-    fn can_succeed(&mut self) -> bool {
-        panic!("Assertion failed, Auth Layer instantiates EAP success")
+    fn reset(&mut self) {
+        self.refused = 0;
+        self.next_layer = self.candidates.first().method_identifier();
+        self.after_notification = None;
+        self.held_request = None;
+        self.negotiation = None;
+        self.negotiation_settled = false;
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.candidates
+            .get_by_id(self.next_layer)
+            .and_then(|layer| layer.session_keys())
+    }
+}
+
+impl Default for AuthLayer<()> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -107,9 +226,15 @@ impl AuthLayer<()> {
     #[allow(unused)]
     pub fn new() -> Self {
         Self {
-            peer_has_sent_nak: false,
-            next_layer: 0,
+            refused: 0,
+            next_layer: MethodType::Legacy(0),
             candidates: (),
+            notification: None,
+            after_notification: None,
+            held_request: None,
+            preference: MethodPreference::new(),
+            negotiation: None,
+            negotiation_settled: false,
         }
     }
 }
@@ -121,31 +246,98 @@ impl<I> AuthLayer<I> {
         P: TupleElement<Target = dyn AuthMethodLayer>,
     {
         AuthLayer {
-            peer_has_sent_nak: false,
+            refused: 0,
             next_layer: if self.candidates.len() == 0 {
                 candidate.id()
             } else {
                 self.next_layer
             },
             candidates: self.candidates.append(candidate),
+            notification: self.notification,
+            after_notification: None,
+            held_request: None,
+            preference: self.preference,
+            negotiation: None,
+            negotiation_settled: false,
         }
     }
+
+    /// Orders the methods after Identity and the choice from a Nak, see
+    /// [`MethodPreference`]
+    pub fn with_preference(self, preference: MethodPreference) -> Self {
+        AuthLayer { preference, ..self }
+    }
+
+    /// Result of the method negotiation, once a method other than Identity
+    /// was requested
+    pub fn negotiation(&self) -> Option<&Negotiation> {
+        self.negotiation.as_ref()
+    }
 }
 
 impl<I> AuthLayer<I>
 where
     I: TupleById<dyn AuthMethodLayer>,
 {
+    /// The first candidate is proposed first. A Nak can only select one of
+    /// the first 64 candidates.
     #[allow(unused)]
     pub fn from_layers(candidates: I) -> Self {
         let next_layer = candidates.first().method_identifier();
         Self {
             next_layer,
             candidates,
-            peer_has_sent_nak: false,
+            refused: 0,
+            notification: None,
+            after_notification: None,
+            held_request: None,
+            preference: MethodPreference::new(),
+            negotiation: None,
+            negotiation_settled: false,
         }
     }
 
+    /// Sends `text` to the peer in a Notification Request. It goes out with
+    /// the next Request: the Request of the active method is sent once the
+    /// peer has answered the Notification, the method does not notice. Methods
+    /// that [bind the Identifier](AuthMethodLayer::binds_identifier), and on
+    /// no_std Requests over 255 bytes, are not interrupted, the Notification
+    /// then waits for the next method or the end of the conversation.
+    ///
+    /// Replaces a notification that was not sent yet. Without `std` or
+    /// `alloc` the text is at most [`NOTIFICATION_INLINE_LEN`] bytes, a text
+    /// that does not fit in a response of the environment is not sent.
+    pub fn notify(&mut self, text: &[u8]) -> Result<(), NotificationTooLong> {
+        self.notification = Some(text.try_into().map_err(|_| NotificationTooLong)?);
+        Ok(())
+    }
+
+    /// Configured methods with their security claims, in order of preference
+    pub fn security_claims(&self) -> impl Iterator<Item = (MethodType, SecurityClaims)> + '_ {
+        self.candidates
+            .iter()
+            .map(|c| (c.method_identifier(), c.security_claims()))
+    }
+
+    /// The method ranked after the current one, wrapping around
+    fn next_by_preference(&self) -> MethodType {
+        let ranked = || {
+            self.candidates.iter().enumerate().map(|(position, c)| {
+                let method = c.method_identifier();
+                (self.preference.rank(method, position), method)
+            })
+        };
+        let current = ranked()
+            .find(|(_, method)| *method == self.next_layer)
+            .map_or(0, |(rank, _)| rank);
+
+        ranked()
+            .filter(|(rank, _)| *rank > current)
+            .min_by_key(|(rank, _)| *rank)
+            .or_else(|| ranked().min_by_key(|(rank, _)| *rank))
+            .map_or(self.next_layer, |(_, method)| method)
+    }
+
     fn current_layer(&mut self) -> &mut dyn AuthMethodLayer {
         // this is ensured by construction, see `new`
         self.candidates.get_by_id_mut(self.next_layer).unwrap()
@@ -157,29 +349,109 @@ where
     ) -> ThisLayerResult<'a> {
         match res {
             AuthMethodLayerResult::Send(msg) => {
-                let id = self.next_layer;
+                let msg = self.next_layer.prepend_to(msg);
+                let binds_identifier = self
+                    .candidates
+                    .get_by_id(self.next_layer)
+                    .is_some_and(|c| c.binds_identifier());
+                if self.notification.is_none() || binds_identifier {
+                    return ThisLayerResult::Send(msg);
+                }
 
-                ThisLayerResult::Send(msg.prepend(&[id]))
+                match OwnedSlice::try_from(msg.slice()) {
+                    Ok(request) => {
+                        self.held_request = Some(request);
+                        self.send_notification(AfterNotification::Request, msg.abort())
+                    }
+                    Err(()) => ThisLayerResult::Send(msg),
+                }
+            }
+            AuthMethodLayerResult::Finished(env) => {
+                self.send_notification(AfterNotification::Finished, env)
+            }
+            AuthMethodLayerResult::Failed(env) => {
+                self.send_notification(AfterNotification::Failed, env)
             }
-            AuthMethodLayerResult::Finished(env) => ThisLayerResult::Finished(env),
-            AuthMethodLayerResult::Failed(env) => ThisLayerResult::Failed(env),
             AuthMethodLayerResult::NextLayer(env) => {
-                let current_idx = self.candidates.id_to_idx(self.next_layer).unwrap();
-                self.next_layer = self
-                    .candidates
-                    .get_by_pos((current_idx + 1) % self.candidates.len())
-                    .unwrap()
-                    .method_identifier();
+                self.next_layer = self.next_by_preference();
 
                 if self.candidates.len() > 1 {
-                    let res = self.current_layer().start(env);
-                    self.process_result(res)
+                    self.send_notification(AfterNotification::StartMethod, env)
                 } else {
                     ThisLayerResult::Failed(env) // <- Internal Issue
                 }
             }
         }
     }
+
+    /// Sends the queued notification, `after` continues the conversation once
+    /// the peer has answered it
+    fn send_notification<'a>(
+        &mut self,
+        after: AfterNotification,
+        env: &'a mut dyn EapEnvironment,
+    ) -> ThisLayerResult<'a> {
+        match self.notification.take() {
+            // A text over the MTU is dropped rather than sent truncated
+            Some(text) if text.as_ref().len() < response_capacity(env) => {
+                self.after_notification = Some(after);
+                ThisLayerResult::Send(
+                    env.respond()
+                        .write(&[TYPE_NOTIFICATION])
+                        .write(text.as_ref()),
+                )
+            }
+            _ => self.resume(after, env),
+        }
+    }
+
+    fn resume<'a>(
+        &mut self,
+        after: AfterNotification,
+        env: &'a mut dyn EapEnvironment,
+    ) -> ThisLayerResult<'a> {
+        match after {
+            AfterNotification::StartMethod => {
+                if self.negotiation.is_none()
+                    && self.next_layer != MethodType::Legacy(TYPE_IDENTITY)
+                {
+                    let order = self.preference.preference_order();
+                    self.negotiation = Some(Negotiation::accepted(self.next_layer, order));
+                }
+                let res = self.current_layer().start(env);
+                self.process_result(res)
+            }
+            AfterNotification::Request => match self.held_request.take() {
+                Some(request) => ThisLayerResult::Send(env.respond().write(request.as_ref())),
+                None => ThisLayerResult::Failed(env), // <- Internal Issue
+            },
+            AfterNotification::Finished => ThisLayerResult::Finished(env),
+            AfterNotification::Failed => ThisLayerResult::Failed(env),
+        }
+    }
+}
+
+/// Candidates after this many are never chosen from a Nak, there is one bit
+/// per candidate in `AuthLayer::refused`
+const MAX_CANDIDATES: usize = u64::BITS as usize;
+
+/// Bit of the candidate at `position` in `AuthLayer::refused`, `position` is
+/// below [`MAX_CANDIDATES`]
+fn refused_bit(position: usize) -> u64 {
+    1 << position
+}
+
+/// Place of `method` in the Nak, the peer lists its favourite first. An
+/// Expanded Nak lists all types in expanded form, a legacy Nak lists 254 if
+/// the peer supports any Expanded Type.
+fn nak_position(proposals: &[u8], expanded_nak: bool, method: MethodType) -> Option<usize> {
+    match (expanded_nak, method) {
+        (true, _) => proposals
+            .chunks_exact(EXPANDED_TYPE_LEN)
+            .position(|entry| MethodType::parse_expanded_entry(entry) == Some(method)),
+        (false, MethodType::Legacy(method)) => proposals.iter().position(|&t| t == method),
+        (false, MethodType::Expanded { .. }) => proposals.iter().position(|&t| t == TYPE_EXPANDED),
+    }
 }
 
 #[cfg(feature = "std")]
@@ -200,14 +472,14 @@ mod tests {
 
     #[derive(Debug, Clone, PartialEq, Eq)]
     struct DummyProtocol {
-        method_identifier: u8,
+        method_identifier: MethodType,
         events: Vec<DummyEvent>,
     }
 
     impl DummyProtocol {
-        fn new(method_identifier: u8, events: &[DummyEvent]) -> Self {
+        fn new(method_identifier: impl Into<MethodType>, events: &[DummyEvent]) -> Self {
             DummyProtocol {
-                method_identifier,
+                method_identifier: method_identifier.into(),
                 events: events.to_vec(),
             }
         }
@@ -216,7 +488,7 @@ mod tests {
     impl TupleElement for DummyProtocol {
         type Target = dyn AuthMethodLayer;
 
-        fn id(&self) -> u8 {
+        fn id(&self) -> MethodType {
             self.method_identifier
         }
 
@@ -230,7 +502,7 @@ mod tests {
     }
 
     impl AuthMethodLayer for DummyProtocol {
-        fn method_identifier(&self) -> u8 {
+        fn method_identifier(&self) -> MethodType {
             self.method_identifier
         }
 
@@ -356,4 +628,373 @@ mod tests {
             ThisLayerResult::Finished(&mut DefaultEnvironment::new()),
         );
     }
+
+    #[test]
+    fn auth_layer_expanded() {
+        let mut env = DefaultEnvironment::new();
+        let vendor_method = MethodType::Expanded {
+            vendor_id: 0x1234,
+            vendor_type: 7,
+        };
+
+        let mut layer = AuthLayer::from_layers((
+            DummyProtocol::new(1, &[DummyEvent::NextLayer]),
+            DummyProtocol::new(
+                vendor_method,
+                &[DummyEvent::Send(b"vendor".to_vec()), DummyEvent::Finished],
+            ),
+            DummyProtocol::new(4, &[DummyEvent::Send(b"md5".to_vec())]),
+        ));
+
+        // Requests of an Expanded Type carry Vendor-Id and Vendor-Type
+        assert_eq!(
+            layer.start(&mut env),
+            ThisLayerResult::Send(MessageBuilder::from(
+                b"\xfe\x00\x12\x34\x00\x00\x00\x07vendor".as_slice()
+            ))
+        );
+
+        {
+            // Alternative Reality
+            let mut layer = layer.clone();
+            // Legacy NAK without 254 selects a legacy type
+            assert_eq!(
+                layer.recv(
+                    &Message::new(MessageCode::Response, 0, b"\x03\x04"),
+                    &mut env,
+                ),
+                ThisLayerResult::Send(MessageBuilder::from(b"\x04md5".as_slice())),
+            );
+        }
+
+        {
+            // Alternative Reality
+            let mut layer = layer.clone();
+            // Expanded NAK proposing MD5 in expanded form
+            assert_eq!(
+                layer.recv(
+                    &Message::new(
+                        MessageCode::Response,
+                        0,
+                        b"\xfe\x00\x00\x00\x00\x00\x00\x03\
+                          \xfe\x00\x00\x00\x00\x00\x00\x04"
+                    ),
+                    &mut env,
+                ),
+                ThisLayerResult::Send(MessageBuilder::from(b"\x04md5".as_slice())),
+            );
+        }
+
+        // Responses of another vendor are rejected
+        {
+            let mut layer = layer.clone();
+            assert_eq!(
+                layer.recv(
+                    &Message::new(
+                        MessageCode::Response,
+                        0,
+                        b"\xfe\x00\x12\x35\x00\x00\x00\x07"
+                    ),
+                    &mut env,
+                ),
+                ThisLayerResult::Failed(&mut DefaultEnvironment::new()),
+            );
+        }
+
+        assert_eq!(
+            layer.recv(
+                &Message::new(
+                    MessageCode::Response,
+                    0,
+                    b"\xfe\x00\x12\x34\x00\x00\x00\x07"
+                ),
+                &mut env,
+            ),
+            ThisLayerResult::Finished(&mut DefaultEnvironment::new()),
+        );
+    }
+
+    #[test]
+    fn auth_layer_nak_per_method() {
+        let mut env = DefaultEnvironment::new();
+        let reported = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        env.set_negotiation_callback({
+            let reported = reported.clone();
+            move |negotiation| reported.borrow_mut().push(*negotiation)
+        });
+        let vendor_method = MethodType::Expanded {
+            vendor_id: 0x1234,
+            vendor_type: 7,
+        };
+
+        let mut layer = AuthLayer::from_layers((
+            DummyProtocol::new(1, &[DummyEvent::NextLayer]),
+            DummyProtocol::new(5, &[DummyEvent::Send(b"otp".to_vec())]),
+            DummyProtocol::new(vendor_method, &[DummyEvent::Send(b"vendor".to_vec())]),
+            DummyProtocol::new(
+                4,
+                &[DummyEvent::Send(b"md5".to_vec()), DummyEvent::Finished],
+            ),
+        ));
+
+        assert_eq!(
+            layer.start(&mut env),
+            ThisLayerResult::Send(MessageBuilder::from(b"\x05otp".as_slice()))
+        );
+
+        // The peer only supports Expanded Types
+        assert_eq!(
+            layer.recv(
+                &Message::new(MessageCode::Response, 0, b"\x03\xfe"),
+                &mut env,
+            ),
+            ThisLayerResult::Send(MessageBuilder::from(
+                b"\xfe\x00\x12\x34\x00\x00\x00\x07vendor".as_slice()
+            )),
+        );
+
+        // But not this vendor's, it answers with an Expanded Nak (RFC 3748 5.3.2)
+        assert_eq!(
+            layer.recv(
+                &Message::new(
+                    MessageCode::Response,
+                    0,
+                    b"\xfe\x00\x00\x00\x00\x00\x00\x03\
+                      \xfe\x00\x00\x00\x00\x00\x00\x05\
+                      \xfe\x00\x00\x00\x00\x00\x00\x04"
+                ),
+                &mut env,
+            ),
+            // 5 was refused before
+            ThisLayerResult::Send(MessageBuilder::from(b"\x04md5".as_slice())),
+        );
+        assert_eq!(
+            layer.negotiation(),
+            Some(&Negotiation {
+                proposed: MethodType::Legacy(5),
+                selected: Some(MethodType::Legacy(4)),
+                nak: true,
+                order: PreferenceOrder::Authenticator,
+            })
+        );
+        assert!(reported.borrow().is_empty());
+
+        // Settled once the peer answers the selected method
+        assert_eq!(
+            layer.recv(&Message::new(MessageCode::Response, 0, b"\x04"), &mut env),
+            ThisLayerResult::Finished(&mut DefaultEnvironment::new()),
+        );
+        assert_eq!(*reported.borrow(), vec![*layer.negotiation().unwrap()]);
+    }
+
+    #[test]
+    fn auth_layer_security_claims() {
+        use crate::layers::auth::{AuthIdentityMethod, AuthMD5ChallengeMethod, AuthMschapv2Method};
+
+        let layer = AuthLayer::new()
+            .with(AuthIdentityMethod::new())
+            .with(AuthMD5ChallengeMethod::new(b"1234"))
+            .with(AuthMschapv2Method::new(b"server", |_: &[u8]| None));
+
+        let claims: Vec<_> = layer.security_claims().collect();
+        assert_eq!(claims[0], (MethodType::Legacy(1), SecurityClaims::NONE));
+        assert_eq!(claims[1], (MethodType::Legacy(4), SecurityClaims::NONE));
+
+        let (method, mschapv2) = claims[2];
+        assert_eq!(method, MethodType::Legacy(26));
+        assert!(mschapv2.mutual_auth && mschapv2.key_derivation);
+        assert!(!mschapv2.dictionary_attack_resistance);
+        assert!(!mschapv2.satisfies(&SecurityClaims {
+            key_strength: 128,
+            ..SecurityClaims::NONE
+        }));
+    }
+
+    #[test]
+    fn auth_layer_preference() {
+        let mut env = DefaultEnvironment::new();
+
+        let layer = AuthLayer::from_layers((
+            DummyProtocol::new(1, &[DummyEvent::NextLayer]),
+            DummyProtocol::new(4, &[DummyEvent::Send(b"md5".to_vec())]),
+            DummyProtocol::new(5, &[DummyEvent::Send(b"otp".to_vec())]),
+            DummyProtocol::new(6, &[DummyEvent::Send(b"gtc".to_vec())]),
+        ));
+
+        // Preferred methods are requested first, Identity stays in front
+        let mut preferring = layer
+            .clone()
+            .with_preference(MethodPreference::new().prefer(MethodType::Legacy(6)));
+        assert_eq!(
+            preferring.start(&mut env),
+            ThisLayerResult::Send(MessageBuilder::from(b"\x06gtc".as_slice()))
+        );
+        assert_eq!(
+            preferring.negotiation(),
+            Some(&Negotiation::accepted(
+                MethodType::Legacy(6),
+                PreferenceOrder::Authenticator
+            ))
+        );
+
+        {
+            // Alternative Reality
+            let mut layer = preferring.clone();
+            // The authenticator's order picks 4 over 5
+            assert_eq!(
+                layer.recv(
+                    &Message::new(MessageCode::Response, 0, b"\x03\x05\x04"),
+                    &mut env,
+                ),
+                ThisLayerResult::Send(MessageBuilder::from(b"\x04md5".as_slice())),
+            );
+            assert_eq!(
+                layer.negotiation(),
+                Some(&Negotiation {
+                    proposed: MethodType::Legacy(6),
+                    selected: Some(MethodType::Legacy(4)),
+                    nak: true,
+                    order: PreferenceOrder::Authenticator,
+                })
+            );
+        }
+
+        // The peer's order picks its first supported proposal
+        let mut layer = layer.with_preference(
+            MethodPreference::new()
+                .prefer(MethodType::Legacy(6))
+                .order(PreferenceOrder::Peer),
+        );
+        assert_eq!(
+            layer.start(&mut env),
+            ThisLayerResult::Send(MessageBuilder::from(b"\x06gtc".as_slice()))
+        );
+        assert_eq!(
+            layer.recv(
+                &Message::new(MessageCode::Response, 0, b"\x03\x07\x05\x04"),
+                &mut env,
+            ),
+            ThisLayerResult::Send(MessageBuilder::from(b"\x05otp".as_slice())),
+        );
+        assert_eq!(
+            layer.negotiation(),
+            Some(&Negotiation {
+                proposed: MethodType::Legacy(6),
+                selected: Some(MethodType::Legacy(5)),
+                nak: true,
+                order: PreferenceOrder::Peer,
+            })
+        );
+    }
+
+    #[test]
+    fn auth_layer_notification() {
+        let mut env = DefaultEnvironment::new();
+
+        let mut layer = AuthLayer::new().with(DummyProtocol::new(
+            4,
+            &[
+                DummyEvent::Send(b"challenge".to_vec()),
+                DummyEvent::Finished,
+            ],
+        ));
+        layer.notify(b"Welcome").unwrap();
+
+        // The notification precedes the first method
+        assert_eq!(
+            layer.start(&mut env),
+            ThisLayerResult::Send(MessageBuilder::from(b"\x02Welcome".as_slice()))
+        );
+        assert_eq!(
+            layer.recv(&Message::new(MessageCode::Response, 0, b"\x02"), &mut env),
+            ThisLayerResult::Send(MessageBuilder::from(b"\x04challenge".as_slice()))
+        );
+
+        // A notification before the last Response goes out at the end
+        layer.notify(b"Password expires soon").unwrap();
+        assert_eq!(
+            layer.recv(
+                &Message::new(MessageCode::Response, 0, b"\x04response"),
+                &mut env
+            ),
+            ThisLayerResult::Send(MessageBuilder::from(
+                b"\x02Password expires soon".as_slice()
+            ))
+        );
+
+        {
+            // Alternative Reality
+            let mut layer = layer.clone();
+            // Peer does not answer the notification
+            assert_eq!(
+                layer.recv(
+                    &Message::new(MessageCode::Response, 0, b"\x03\x04"),
+                    &mut env
+                ),
+                ThisLayerResult::Failed(&mut DefaultEnvironment::new()),
+            );
+        }
+
+        assert_eq!(
+            layer.recv(&Message::new(MessageCode::Response, 0, b"\x02"), &mut env),
+            ThisLayerResult::Finished(&mut DefaultEnvironment::new()),
+        );
+    }
+
+    #[test]
+    fn auth_layer_notification_over_mtu() {
+        let mut env = DefaultEnvironment::new_with_mtu(32);
+
+        let mut layer = AuthLayer::new().with(DummyProtocol::new(
+            4,
+            &[
+                DummyEvent::Send(b"challenge".to_vec()),
+                DummyEvent::Finished,
+            ],
+        ));
+        layer.notify(&[b'a'; 100]).unwrap();
+
+        // Not sent, the method starts right away
+        assert_eq!(
+            layer.start(&mut env),
+            ThisLayerResult::Send(MessageBuilder::from(b"\x04challenge".as_slice()))
+        );
+    }
+
+    #[test]
+    fn auth_layer_notification_between_requests() {
+        let mut env = DefaultEnvironment::new();
+
+        let mut layer = AuthLayer::new().with(DummyProtocol::new(
+            13,
+            &[
+                DummyEvent::Send(b"first".to_vec()),
+                DummyEvent::Send(b"second".to_vec()),
+                DummyEvent::Finished,
+            ],
+        ));
+
+        assert_eq!(
+            layer.start(&mut env),
+            ThisLayerResult::Send(MessageBuilder::from(b"\x0dfirst".as_slice()))
+        );
+
+        // The next Request of the method is held back for the notification
+        layer.notify(b"Maintenance at midnight").unwrap();
+        assert_eq!(
+            layer.recv(&Message::new(MessageCode::Response, 0, b"\x0d"), &mut env),
+            ThisLayerResult::Send(MessageBuilder::from(
+                b"\x02Maintenance at midnight".as_slice()
+            ))
+        );
+        assert_eq!(
+            layer.recv(&Message::new(MessageCode::Response, 0, b"\x02"), &mut env),
+            ThisLayerResult::Send(MessageBuilder::from(b"\x0dsecond".as_slice()))
+        );
+
+        assert_eq!(
+            layer.recv(&Message::new(MessageCode::Response, 0, b"\x0d"), &mut env),
+            ThisLayerResult::Finished(&mut DefaultEnvironment::new()),
+        );
+    }
 }
@@ -0,0 +1,41 @@
+use super::token::AuthTokenMethod;
+
+const METHOD_GTC: u8 = 6;
+
+/// EAP-GTC (RFC 3748 5.6), the prompt is shown to the user and
+/// the returned token is checked by the verifier.
+pub type AuthGtcMethod<V> = AuthTokenMethod<V, METHOD_GTC>;
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use crate::layers::auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta};
+    use crate::layers::auth::StaticTokenVerifier;
+    use crate::layers::MethodType;
+    use crate::{message::Message, DefaultEnvironment};
+
+    use super::*;
+
+    #[test]
+    fn auth_gtc_method() {
+        let mut env = DefaultEnvironment::new();
+        let mut method =
+            AuthGtcMethod::new(b"Token:", StaticTokenVerifier::new(b"123456").unwrap());
+        assert_eq!(method.method_identifier(), MethodType::Legacy(METHOD_GTC));
+
+        assert!(matches!(
+            method.start(&mut env),
+            AuthMethodLayerResult::Send(data) if data.slice() == b"Token:"
+        ));
+
+        let m = Message::new(crate::message::MessageCode::Response, 0, b"");
+        assert!(matches!(
+            method.recv(b"654321", &RecvMeta { message: m }, &mut env),
+            AuthMethodLayerResult::Failed(_)
+        ));
+        assert!(matches!(
+            method.recv(b"123456", &RecvMeta { message: m }, &mut env),
+            AuthMethodLayerResult::Finished(_)
+        ));
+    }
+}
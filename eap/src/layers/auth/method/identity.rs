@@ -1,4 +1,7 @@
-use crate::{layers::mux::TupleElement, EapEnvironment, EapEnvironmentResponse};
+use crate::{
+    layers::{mux::TupleElement, MethodType},
+    EapEnvironment, EapEnvironmentResponse,
+};
 
 use super::super::auth_layer::{
     AuthMethodLayer as ThisLayer, AuthMethodLayerResult as ThisLayerResult, RecvMeta,
@@ -18,7 +21,7 @@ impl AuthIdentityMethod {
 impl TupleElement for AuthIdentityMethod {
     type Target = dyn ThisLayer;
 
-    fn id(&self) -> u8 {
+    fn id(&self) -> MethodType {
         self.method_identifier()
     }
 
@@ -32,8 +35,8 @@ impl TupleElement for AuthIdentityMethod {
 }
 
 impl ThisLayer for AuthIdentityMethod {
-    fn method_identifier(&self) -> u8 {
-        METHOD_IDENTITY
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_IDENTITY)
     }
 
     fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> ThisLayerResult<'a> {
@@ -66,7 +69,10 @@ mod tests {
     fn auth_identity_method() {
         let mut env = crate::DefaultEnvironment::new();
         let mut method = AuthIdentityMethod::new();
-        assert_eq!(method.method_identifier(), METHOD_IDENTITY);
+        assert_eq!(
+            method.method_identifier(),
+            MethodType::Legacy(METHOD_IDENTITY)
+        );
 
         assert!(matches!(
             method.start(&mut env),
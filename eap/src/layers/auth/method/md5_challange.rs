@@ -1,5 +1,6 @@
 use crate::layers::auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult};
 use crate::layers::mux::TupleElement;
+use crate::layers::MethodType;
 use crate::util::OwnedSlice;
 use crate::{EapEnvironment, EapEnvironmentResponse};
 
@@ -17,7 +18,7 @@ pub struct AuthMD5ChallengeMethod {
 impl TupleElement for AuthMD5ChallengeMethod {
     type Target = dyn AuthMethodLayer;
 
-    fn id(&self) -> u8 {
+    fn id(&self) -> MethodType {
         self.method_identifier()
     }
 
@@ -46,8 +47,8 @@ impl AuthMD5ChallengeMethod {
 }
 
 impl AuthMethodLayer for AuthMD5ChallengeMethod {
-    fn method_identifier(&self) -> u8 {
-        METHOD_MD5_CHALLENGE
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_MD5_CHALLENGE)
     }
 
     fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
@@ -98,7 +99,10 @@ mod test {
     #[test]
     fn auth_md5_challenge_method() {
         let mut method = AuthMD5ChallengeMethod::new(b"42");
-        assert_eq!(method.method_identifier(), METHOD_MD5_CHALLENGE);
+        assert_eq!(
+            method.method_identifier(),
+            MethodType::Legacy(METHOD_MD5_CHALLENGE)
+        );
 
         let mut env = DefaultEnvironment::new();
 
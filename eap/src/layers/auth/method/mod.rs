@@ -1,2 +1,7 @@
+pub mod gtc;
 pub mod identity;
 pub mod md5_challange;
+pub mod mschapv2;
+pub mod otp;
+pub mod token;
+pub mod verifier;
@@ -0,0 +1,249 @@
+use crate::layers::auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult};
+use crate::layers::eap_layer::SessionKeys;
+use crate::layers::mux::TupleElement;
+use crate::layers::{MethodType, SecurityClaims};
+use crate::mschapv2::*;
+use crate::util::{constant_time_eq, OwnedSlice};
+use crate::{EapEnvironment, EapEnvironmentResponse};
+
+use super::super::auth_layer::RecvMeta;
+
+#[derive(Clone)]
+enum State {
+    Start,
+    WaitResponse,
+    WaitSuccessAck { keys: SessionKeys },
+    WaitFailureAck,
+    Done { keys: SessionKeys },
+}
+
+/// EAP-MSCHAPv2 (draft-kamath-pppext-eap-mschapv2), `password_lookup` returns
+/// the password of the user name sent by the peer.
+#[derive(Clone)]
+pub struct AuthMschapv2Method<F> {
+    name: OwnedSlice<64>,
+    password_lookup: F,
+    identifier: u8,
+    challenge: [u8; CHALLENGE_LEN],
+    state: State,
+}
+
+impl<F> TupleElement for AuthMschapv2Method<F>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<64>> + 'static,
+{
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<F> AuthMschapv2Method<F>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<64>>,
+{
+    pub fn new(name: &[u8], password_lookup: F) -> Self {
+        Self {
+            name: name.try_into().expect("name too long for nostd"),
+            password_lookup,
+            identifier: 0,
+            challenge: [0; CHALLENGE_LEN],
+            state: State::Start,
+        }
+    }
+
+    /// Checks the Response packet, returns the Authenticator Response and the keys
+    fn verify(
+        &mut self,
+        msg: &[u8],
+        env: &mut dyn EapEnvironment,
+    ) -> Option<([u8; AUTHENTICATOR_RESPONSE_LEN], SessionKeys)> {
+        if msg.len() < HEADER_LEN + 1 + RESPONSE_LEN
+            || msg[0] != OP_RESPONSE
+            || msg[1] != self.identifier
+            || msg[HEADER_LEN] as usize != RESPONSE_LEN
+        {
+            return None;
+        }
+
+        let response = &msg[HEADER_LEN + 1..HEADER_LEN + 1 + RESPONSE_LEN];
+        let username = &msg[HEADER_LEN + 1 + RESPONSE_LEN..];
+        let peer_challenge: [u8; CHALLENGE_LEN] = response[..CHALLENGE_LEN].try_into().unwrap();
+        let nt_response: [u8; NT_RESPONSE_LEN] = response[CHALLENGE_LEN + 8..][..NT_RESPONSE_LEN]
+            .try_into()
+            .unwrap();
+
+        env.set_name(username);
+        let password = (self.password_lookup)(username)?;
+
+        let expected = generate_nt_response(
+            &self.challenge,
+            &peer_challenge,
+            username,
+            password.as_ref(),
+        );
+        if !constant_time_eq(&expected, &nt_response) {
+            return None;
+        }
+
+        Some((
+            authenticator_response(
+                password.as_ref(),
+                &nt_response,
+                &peer_challenge,
+                &self.challenge,
+                username,
+            ),
+            session_keys(password.as_ref(), &nt_response),
+        ))
+    }
+}
+
+fn header(op_code: u8, identifier: u8, length: usize) -> [u8; HEADER_LEN] {
+    let length = (length as u16).to_be_bytes();
+    [op_code, identifier, length[0], length[1]]
+}
+
+impl<F> AuthMethodLayer for AuthMschapv2Method<F>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<64>>,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_MSCHAPV2)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_MSCHAPV2
+    }
+
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        env.fill_random(&mut self.challenge);
+        self.identifier = self.identifier.wrapping_add(1);
+        self.state = State::WaitResponse;
+
+        let name = self.name.as_ref();
+        let length = HEADER_LEN + 1 + CHALLENGE_LEN + name.len();
+        AuthMethodLayerResult::Send(
+            env.respond()
+                .write(&header(OP_CHALLENGE, self.identifier, length))
+                .write(&[CHALLENGE_LEN as u8])
+                .write(&self.challenge)
+                .write(name),
+        )
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        match self.state.clone() {
+            State::WaitResponse => match self.verify(msg, env) {
+                Some((authenticator_response, keys)) => {
+                    self.state = State::WaitSuccessAck { keys };
+
+                    let length = HEADER_LEN + AUTHENTICATOR_RESPONSE_LEN;
+                    AuthMethodLayerResult::Send(
+                        env.respond()
+                            .write(&header(OP_SUCCESS, self.identifier, length))
+                            .write(&authenticator_response),
+                    )
+                }
+                None => {
+                    self.state = State::WaitFailureAck;
+
+                    let message: &[u8] = b"E=691 R=0 V=3";
+                    let length = HEADER_LEN + message.len();
+                    AuthMethodLayerResult::Send(
+                        env.respond()
+                            .write(&header(OP_FAILURE, self.identifier, length))
+                            .write(message),
+                    )
+                }
+            },
+            State::WaitSuccessAck { keys } if msg == [OP_SUCCESS] => {
+                self.state = State::Done { keys };
+                AuthMethodLayerResult::Finished(env)
+            }
+            _ => AuthMethodLayerResult::Failed(env),
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        match &self.state {
+            State::Done { keys } => Some(keys),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use crate::layers::eap_layer::Decision;
+    use crate::layers::peer::peer_layer::{self, PeerMethodLayer, PeerMethodLayerResult};
+    use crate::layers::peer::PeerMschapv2Method;
+    use crate::message::{Message, MessageCode};
+    use crate::DefaultEnvironment;
+
+    use super::*;
+
+    fn exchange(password: &[u8]) -> (bool, Decision) {
+        let mut env = DefaultEnvironment::new();
+        let mut auth = AuthMschapv2Method::new(b"server", |username: &[u8]| {
+            (username == b"hans").then(|| OwnedSlice::from(b"1234"))
+        });
+        let mut peer = PeerMschapv2Method::new(b"hans", password);
+
+        let auth_meta = AuthMeta(Message::new(MessageCode::Response, 0, b""));
+        let peer_meta = peer_layer::RecvMeta {
+            message: Message::new(MessageCode::Request, 0, b""),
+        };
+
+        let mut request = match auth.start(&mut env) {
+            AuthMethodLayerResult::Send(data) => data.slice().to_vec(),
+            _ => panic!("expected challenge"),
+        };
+
+        loop {
+            let response = match peer.recv(&request, &peer_meta, &mut env) {
+                PeerMethodLayerResult::Send(data) => data.slice().to_vec(),
+                _ => panic!("peer has to respond"),
+            };
+
+            request = match auth.recv(&response, &auth_meta.meta(), &mut env) {
+                AuthMethodLayerResult::Send(data) => data.slice().to_vec(),
+                AuthMethodLayerResult::Finished(_) => {
+                    assert!(auth.session_keys() == peer.session_keys());
+                    return (true, peer.decision());
+                }
+                AuthMethodLayerResult::Failed(_) => return (false, peer.decision()),
+                AuthMethodLayerResult::NextLayer(_) => unreachable!(),
+            };
+        }
+    }
+
+    struct AuthMeta<'a>(Message<'a>);
+
+    impl<'a> AuthMeta<'a> {
+        fn meta(&self) -> RecvMeta<'a> {
+            RecvMeta { message: self.0 }
+        }
+    }
+
+    #[test]
+    fn mschapv2_exchange() {
+        assert_eq!(exchange(b"1234"), (true, Decision::CondSucc));
+        assert_eq!(exchange(b"4321"), (false, Decision::Fail));
+    }
+}
@@ -0,0 +1,44 @@
+use super::token::AuthTokenMethod;
+
+const METHOD_OTP: u8 = 5;
+
+/// EAP-OTP (RFC 3748 5.5), the challenge is sent as is and
+/// the one time password returned by the peer is checked by the verifier.
+pub type AuthOtpMethod<V> = AuthTokenMethod<V, METHOD_OTP>;
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use crate::layers::auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult, RecvMeta};
+    use crate::layers::auth::HotpVerifier;
+    use crate::layers::MethodType;
+    use crate::{message::Message, DefaultEnvironment};
+
+    use super::*;
+
+    #[test]
+    fn auth_otp_method() {
+        let mut env = DefaultEnvironment::new();
+        let verifier = HotpVerifier::new(b"12345678901234567890", 0).unwrap();
+        let mut method = AuthOtpMethod::new(b"otp-hotp 0", verifier);
+        assert_eq!(method.method_identifier(), MethodType::Legacy(METHOD_OTP));
+
+        assert!(matches!(
+            method.start(&mut env),
+            AuthMethodLayerResult::Send(data) if data.slice() == b"otp-hotp 0"
+        ));
+
+        let m = Message::new(crate::message::MessageCode::Response, 0, b"");
+        assert!(matches!(
+            method.recv(b"755224", &RecvMeta { message: m }, &mut env),
+            AuthMethodLayerResult::Finished(_)
+        ));
+
+        // Codes can't be reused
+        let _ = method.start(&mut env);
+        assert!(matches!(
+            method.recv(b"755224", &RecvMeta { message: m }, &mut env),
+            AuthMethodLayerResult::Failed(_)
+        ));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::layers::auth::auth_layer::{AuthMethodLayer, AuthMethodLayerResult};
+use crate::layers::mux::TupleElement;
+use crate::layers::MethodType;
+use crate::util::OwnedSlice;
+use crate::{EapEnvironment, EapEnvironmentResponse};
+
+use super::super::auth_layer::RecvMeta;
+use super::verifier::TokenVerifier;
+
+/// A method that sends a prompt and checks the single token the peer returns, the
+/// common part of EAP-OTP and EAP-GTC, which only differ in their type `METHOD`.
+#[derive(Clone)]
+pub struct AuthTokenMethod<V, const METHOD: u8> {
+    prompt: OwnedSlice<64>,
+    verifier: V,
+}
+
+impl<V: TokenVerifier + 'static, const METHOD: u8> TupleElement for AuthTokenMethod<V, METHOD> {
+    type Target = dyn AuthMethodLayer;
+
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<V: TokenVerifier, const METHOD: u8> AuthTokenMethod<V, METHOD> {
+    pub fn new(prompt: &[u8], verifier: V) -> Self {
+        Self {
+            prompt: prompt.try_into().expect("prompt too long for nostd"),
+            verifier,
+        }
+    }
+}
+
+impl<V: TokenVerifier, const METHOD: u8> AuthMethodLayer for AuthTokenMethod<V, METHOD> {
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD)
+    }
+
+    fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> AuthMethodLayerResult<'a> {
+        AuthMethodLayerResult::Send(env.respond().write(self.prompt.as_ref()))
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn EapEnvironment,
+    ) -> AuthMethodLayerResult<'a> {
+        if self.verifier.verify(env.name(), env.unix_time(), msg) {
+            AuthMethodLayerResult::Finished(env)
+        } else {
+            AuthMethodLayerResult::Failed(env)
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use hmac::{Hmac, Mac};
+
+use crate::util::{constant_time_eq, OwnedSlice};
+
+type HmacSha1 = Hmac<sha1::Sha1>;
+
+/// Checks the token a peer returned for EAP-GTC or EAP-OTP.
+pub trait TokenVerifier {
+    /// `identity` is the name the peer announced via the Identity method, if any,
+    /// `unix_time` is the wall clock of the environment, see
+    /// [`EapEnvironment::unix_time`](crate::EapEnvironment::unix_time).
+    fn verify(&mut self, identity: Option<&[u8]>, unix_time: Option<u64>, response: &[u8]) -> bool;
+}
+
+/// The secret or token does not fit, without `alloc` they are limited to 64 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecretTooLong;
+
+/// RFC 4226 HOTP value for the given counter.
+pub fn hotp(secret: &[u8], counter: u64, digits: u32) -> u32 {
+    let mut mac = HmacSha1::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation, see RFC 4226 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    binary % 10u32.pow(digits)
+}
+
+fn code_eq(a: u32, b: u32) -> bool {
+    constant_time_eq(&a.to_be_bytes(), &b.to_be_bytes())
+}
+
+fn parse_code(response: &[u8], digits: u32) -> Option<u32> {
+    if response.len() != digits as usize {
+        return None;
+    }
+
+    let mut value = 0u32;
+    for c in response {
+        if !c.is_ascii_digit() {
+            return None;
+        }
+        value = value * 10 + (c - b'0') as u32;
+    }
+
+    Some(value)
+}
+
+/// Counter based one time passwords (RFC 4226).
+///
+/// Codes within `window` counter values ahead of the expected one are accepted,
+/// the counter is resynchronized to the accepted code.
+#[derive(Clone)]
+pub struct HotpVerifier {
+    secret: OwnedSlice<64>,
+    counter: u64,
+    digits: u32,
+    window: u64,
+}
+
+impl HotpVerifier {
+    pub fn new(secret: &[u8], counter: u64) -> Result<Self, SecretTooLong> {
+        Ok(Self {
+            secret: secret.try_into().map_err(|_| SecretTooLong)?,
+            counter,
+            digits: 6,
+            window: 10,
+        })
+    }
+
+    pub fn with_digits(mut self, digits: u32) -> Self {
+        assert!((6..=9).contains(&digits));
+        self.digits = digits;
+        self
+    }
+
+    pub fn with_window(mut self, window: u64) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Next counter value the verifier expects.
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+}
+
+impl TokenVerifier for HotpVerifier {
+    fn verify(
+        &mut self,
+        _identity: Option<&[u8]>,
+        _unix_time: Option<u64>,
+        response: &[u8],
+    ) -> bool {
+        let code = match parse_code(response, self.digits) {
+            Some(code) => code,
+            None => return false,
+        };
+
+        for counter in self.counter..=self.counter.saturating_add(self.window) {
+            if code_eq(hotp(self.secret.as_ref(), counter, self.digits), code) {
+                // A code can only be used once
+                self.counter = counter + 1;
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+/// Time based one time passwords (RFC 6238).
+///
+/// The time is read from the wall clock of the environment, see
+/// [`EapEnvironment::unix_time`](crate::EapEnvironment::unix_time), without one every
+/// code is rejected. Codes up to `drift` time steps before or after the current one
+/// are accepted, a time step is only accepted once.
+#[derive(Clone)]
+pub struct TotpVerifier {
+    secret: OwnedSlice<64>,
+    digits: u32,
+    step: u64,
+    t0: u64,
+    drift: u64,
+    last_accepted_step: Option<u64>,
+}
+
+impl TotpVerifier {
+    pub fn new(secret: &[u8]) -> Result<Self, SecretTooLong> {
+        Ok(Self {
+            secret: secret.try_into().map_err(|_| SecretTooLong)?,
+            digits: 6,
+            step: 30,
+            t0: 0,
+            drift: 1,
+            last_accepted_step: None,
+        })
+    }
+
+    pub fn with_digits(mut self, digits: u32) -> Self {
+        assert!((6..=9).contains(&digits));
+        self.digits = digits;
+        self
+    }
+
+    pub fn with_step(mut self, step: u64, t0: u64) -> Self {
+        assert!(step > 0);
+        self.step = step;
+        self.t0 = t0;
+        self
+    }
+
+    pub fn with_drift(mut self, drift: u64) -> Self {
+        self.drift = drift;
+        self
+    }
+}
+
+impl TokenVerifier for TotpVerifier {
+    fn verify(
+        &mut self,
+        _identity: Option<&[u8]>,
+        unix_time: Option<u64>,
+        response: &[u8],
+    ) -> bool {
+        let code = match parse_code(response, self.digits) {
+            Some(code) => code,
+            None => return false,
+        };
+
+        let unix_time = match unix_time {
+            Some(unix_time) => unix_time,
+            None => return false,
+        };
+
+        let current = unix_time.saturating_sub(self.t0) / self.step;
+        let first = current.saturating_sub(self.drift);
+        let first = match self.last_accepted_step {
+            Some(last) => first.max(last + 1),
+            None => first,
+        };
+
+        for step in first..=current.saturating_add(self.drift) {
+            if code_eq(hotp(self.secret.as_ref(), step, self.digits), code) {
+                self.last_accepted_step = Some(step);
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+/// Accepts a fixed token, mostly useful for testing.
+#[derive(Clone)]
+pub struct StaticTokenVerifier {
+    token: OwnedSlice<64>,
+}
+
+impl StaticTokenVerifier {
+    pub fn new(token: &[u8]) -> Result<Self, SecretTooLong> {
+        Ok(Self {
+            token: token.try_into().map_err(|_| SecretTooLong)?,
+        })
+    }
+}
+
+impl TokenVerifier for StaticTokenVerifier {
+    fn verify(
+        &mut self,
+        _identity: Option<&[u8]>,
+        _unix_time: Option<u64>,
+        response: &[u8],
+    ) -> bool {
+        constant_time_eq(self.token.as_ref(), response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn hotp_rfc4226_vectors() {
+        // RFC 4226 Appendix D
+        let expected = [
+            755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489,
+        ];
+
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(SECRET, counter as u64, 6), *code);
+        }
+    }
+
+    #[test]
+    fn hotp_verifier_window() {
+        let mut verifier = HotpVerifier::new(SECRET, 0).unwrap().with_window(3);
+
+        assert!(!verifier.verify(None, None, b"338314")); // counter 4, outside of window
+        assert!(verifier.verify(None, None, b"969429")); // counter 3, resync
+        assert_eq!(verifier.counter(), 4);
+        assert!(!verifier.verify(None, None, b"969429")); // replay
+        assert!(verifier.verify(None, None, b"338314"));
+        assert!(!verifier.verify(None, None, b"33831"));
+        assert!(!verifier.verify(None, None, b"33831x"));
+    }
+
+    #[test]
+    fn totp_rfc6238_vectors() {
+        // RFC 6238 Appendix B, SHA1
+        let mut verifier = TotpVerifier::new(SECRET).unwrap().with_digits(8);
+        assert!(verifier.verify(None, Some(59), b"94287082"));
+
+        let mut verifier = TotpVerifier::new(SECRET).unwrap().with_digits(8);
+        assert!(verifier.verify(None, Some(1_111_111_109), b"07081804"));
+
+        let mut verifier = TotpVerifier::new(SECRET).unwrap().with_digits(8);
+        assert!(verifier.verify(None, Some(2_000_000_000), b"69279037"));
+    }
+
+    #[test]
+    fn totp_verifier_drift() {
+        // Code for T = 59 is 94287082, step 1
+        let mut verifier = TotpVerifier::new(SECRET).unwrap().with_digits(8);
+        assert!(verifier.verify(None, Some(59 + 30), b"94287082"));
+        // Replay of the same time step
+        assert!(!verifier.verify(None, Some(59 + 30), b"94287082"));
+
+        let mut verifier = TotpVerifier::new(SECRET).unwrap().with_digits(8);
+        assert!(!verifier.verify(None, Some(59 + 60), b"94287082"));
+
+        let mut verifier = TotpVerifier::new(SECRET)
+            .unwrap()
+            .with_digits(8)
+            .with_drift(2);
+        assert!(verifier.verify(None, Some(59 + 60), b"94287082"));
+    }
+
+    #[test]
+    fn totp_verifier_clock() {
+        // Without a wall clock there is no current time step
+        let mut verifier = TotpVerifier::new(SECRET).unwrap().with_digits(8);
+        assert!(!verifier.verify(None, None, b"94287082"));
+    }
+
+    #[test]
+    fn static_token_verifier() {
+        let mut verifier = StaticTokenVerifier::new(b"123456").unwrap();
+        assert!(verifier.verify(None, None, b"123456"));
+        assert!(!verifier.verify(None, None, b"123457"));
+        assert!(!verifier.verify(None, None, b"12345"));
+    }
+}
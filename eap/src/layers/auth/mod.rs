@@ -3,5 +3,12 @@ pub use auth_layer::{AuthLayer, AuthMethodLayer};
 
 pub mod method;
 
+pub use method::gtc::AuthGtcMethod;
 pub use method::identity::AuthIdentityMethod;
 pub use method::md5_challange::AuthMD5ChallengeMethod;
+pub use method::mschapv2::AuthMschapv2Method;
+pub use method::otp::AuthOtpMethod;
+pub use method::token::AuthTokenMethod;
+pub use method::verifier::{
+    HotpVerifier, SecretTooLong, StaticTokenVerifier, TokenVerifier, TotpVerifier,
+};
@@ -0,0 +1,135 @@
+/// Security claims of a method, RFC 3748 section 7.2.
+///
+/// Claims hold for the method as implemented here, e.g. a method that can
+/// run with a password is not claimed to resist dictionary attacks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SecurityClaims {
+    /// Both the peer and the server are authenticated
+    pub mutual_auth: bool,
+    /// An MSK and EMSK are exported
+    pub key_derivation: bool,
+    /// Effective key strength in bits against brute force, 0 without key
+    /// derivation
+    pub key_strength: u16,
+    /// An attacker cannot guess the credential offline from a conversation
+    pub dictionary_attack_resistance: bool,
+    /// Replayed method messages are detected
+    pub replay_protection: bool,
+    /// Method messages are encrypted, at least once keys are established
+    pub confidentiality: bool,
+    /// Lower layer parameters are verified with the server (RFC 6677)
+    pub channel_binding: bool,
+}
+
+impl SecurityClaims {
+    /// Methods that only authenticate the peer, e.g. MD5-Challenge
+    pub const NONE: SecurityClaims = SecurityClaims {
+        mutual_auth: false,
+        key_derivation: false,
+        key_strength: 0,
+        dictionary_attack_resistance: false,
+        replay_protection: false,
+        confidentiality: false,
+        channel_binding: false,
+    };
+
+    /// Every claim of `minimum` is made here as well
+    pub fn satisfies(&self, minimum: &SecurityClaims) -> bool {
+        (self.mutual_auth || !minimum.mutual_auth)
+            && (self.key_derivation || !minimum.key_derivation)
+            && self.key_strength >= minimum.key_strength
+            && (self.dictionary_attack_resistance || !minimum.dictionary_attack_resistance)
+            && (self.replay_protection || !minimum.replay_protection)
+            && (self.confidentiality || !minimum.confidentiality)
+            && (self.channel_binding || !minimum.channel_binding)
+    }
+}
+
+/// Claims of the methods in this crate, kept in one table so they can be
+/// compared side by side. Each value cites the section that states it, key
+/// strengths follow the weakest primitive the implementation negotiates.
+impl SecurityClaims {
+    /// Methods that authenticate both sides, derive keys and detect replays
+    const fn mutual(
+        key_strength: u16,
+        dictionary_attack_resistance: bool,
+        confidentiality: bool,
+    ) -> SecurityClaims {
+        SecurityClaims {
+            mutual_auth: true,
+            key_derivation: true,
+            key_strength,
+            dictionary_attack_resistance,
+            replay_protection: true,
+            confidentiality,
+            channel_binding: false,
+        }
+    }
+
+    /// EAP-TLS, RFC 5216 section 5.1. A client certificate is required and
+    /// AES-128 is the weakest cipher of the rustls default suites.
+    pub const EAP_TLS: SecurityClaims = Self::mutual(128, true, true);
+
+    /// TEAP, RFC 7170 section 8, with the cipher suites of EAP-TLS
+    pub const TEAP: SecurityClaims = Self::mutual(128, true, true);
+
+    /// EAP-FIDO (draft-ietf-emu-eap-fido), TLS 1.3 and a FIDO2 assertion
+    pub const EAP_FIDO: SecurityClaims = Self::mutual(128, true, true);
+
+    /// EAP-PSK, RFC 4764 section 8, AES-128 keys. The PSK may be derived
+    /// from a password, so no dictionary attack resistance is claimed.
+    pub const EAP_PSK: SecurityClaims = Self::mutual(128, false, true);
+
+    /// EAP-GPSK, RFC 5433 section 11, ciphersuite 1 (AES-CMAC-128). The PSK
+    /// may be a password as for EAP-PSK.
+    pub const EAP_GPSK: SecurityClaims = Self::mutual(128, false, true);
+
+    /// EAP-IKEv2, RFC 5106 section 8, X25519 and AES-128. A side that
+    /// accepts a shared secret, which may be a password, is open to
+    /// dictionary attacks, only certificates resist them.
+    pub const fn eap_ikev2(shared_secret: bool) -> SecurityClaims {
+        Self::mutual(128, !shared_secret, true)
+    }
+
+    /// EAP-EKE, RFC 6124 section 7. The only proposal uses DH group 14,
+    /// which RFC 3526 section 8 estimates at 110 to 160 bits, the lower
+    /// bound is claimed. Only the Confirm payloads are encrypted.
+    pub const EAP_EKE: SecurityClaims = Self::mutual(110, true, false);
+
+    /// EAP-SIM, RFC 4186 section 12, at least two 64-bit Kc values
+    pub const EAP_SIM: SecurityClaims = Self::mutual(128, true, true);
+
+    /// EAP-AKA and EAP-AKA', RFC 4187 section 12 and RFC 9048 section 6,
+    /// 128-bit CK and IK
+    pub const EAP_AKA: SecurityClaims = Self::mutual(128, true, true);
+
+    /// EAP-NOOB, RFC 9140 section 7, X25519. Messages are integrity
+    /// protected but not encrypted.
+    pub const EAP_NOOB: SecurityClaims = Self::mutual(128, true, false);
+
+    /// EAP-EDHOC (draft-ietf-emu-eap-edhoc), cipher suite 0 with AES-CCM-128
+    pub const EAP_EDHOC: SecurityClaims = Self::mutual(128, true, true);
+
+    /// EAP-MSCHAPv2, the password can be recovered from a conversation and
+    /// the NT-Response falls to a single DES key search (RFC 2759 section 8)
+    pub const EAP_MSCHAPV2: SecurityClaims = Self::mutual(56, false, false);
+
+    /// PT-EAP, RFC 7171 section 5, it relies on the tunnel around it
+    pub const PT_EAP: SecurityClaims = Self::NONE;
+
+    /// EAP-WSC, Wi-Fi Simple Configuration Technical Specification v2.0.
+    /// A PIN authenticates both sides but can be guessed, with the push
+    /// button method neither side is authenticated. No MSK is exported,
+    /// the Credential in M8 is encrypted.
+    pub const fn eap_wsc(push_button: bool) -> SecurityClaims {
+        SecurityClaims {
+            mutual_auth: !push_button,
+            key_derivation: false,
+            key_strength: 0,
+            dictionary_attack_resistance: false,
+            replay_protection: true,
+            confidentiality: true,
+            channel_binding: false,
+        }
+    }
+}
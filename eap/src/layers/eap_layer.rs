@@ -1,30 +1,118 @@
+use super::{
+    timers::{Clock, Rtt},
+    MethodType,
+};
 use crate::{
+    erp::{Erp, ReauthResult, TYPE_REAUTH, TYPE_REAUTH_START},
     message::{Message, MessageCode},
+    util::OwnedSlice,
     EapEnvironment, EapEnvironmentResponse, MessageBuilder, ResponseMessage,
 };
 
 #[cfg(not(feature = "std"))]
 use core as std;
 
-pub struct EapLayer<N> {
+/// EAP peer or authenticator state machine of RFC 4137, the role is that of
+/// the layer below.
+///
+/// Method selection, Identity, Notification and Nak are handled by
+/// [`PeerLayer`](crate::layers::PeerLayer) and
+/// [`AuthLayer`](crate::layers::AuthLayer), this layer covers the remaining
+/// transitions: duplicate detection, retransmission, discarding and the
+/// SUCCESS and FAILURE states.
+pub struct EapLayer<N, E = ()> {
     state: State,
+    interface: EapInterface,
     // Count of invalid messages received,
     // fail if it exceeds max_invalid_message_count
     invalid_message_count: u16,
     timed_out_count: u16,
-    next_id: u8,
+    /// lastId on the peer, currentId on the authenticator
+    current_id: Option<u8>,
+    retrans_count: u16,
     next_layer: N,
+    /// ERP role, `()` if re-authentication is disabled
+    erp: E,
+    // The authenticator offers re-authentication only once
+    reauth_offered: bool,
+    /// Read from the environment at the start of each call, `None` without
+    /// a clock
+    clock: Option<Clock>,
+    /// Expiry of retransWhile on the authenticator, idleWhile on the peer
+    deadline: Option<u64>,
+    /// Authenticator: time the current request was sent, `None` after a
+    /// retransmission as the response is ambiguous then (Karn's algorithm)
+    sent_at: Option<u64>,
+    rtt: Option<Rtt>,
+    usage: Usage,
+}
+
+/// Resources used by the session so far, limited by the environment
+#[derive(Default)]
+struct Usage {
+    round_trips: u16,
+    method: Option<MethodType>,
+    method_round_trips: u16,
+    bytes_received: u32,
+    /// Time of the first start, if there is a clock
+    started_at: Option<u64>,
 }
 
+/// Resting states of RFC 4137, the other states are passed within a single
+/// call
 enum State {
-    Start,
+    /// portEnabled is false
+    Disabled,
+    /// The peer waits for a request, the authenticator for the response to
+    /// currentId
     Idle,
-    MessagePending {
-        expected_id: u8,
-        retransmission_count: u16,
+    /// Authenticator: EAP-Initiate/Re-auth-Start was sent
+    ReauthStart,
+    /// Authenticator: EAP-Finish/Re-auth reported a failure, full EAP starts
+    /// with the next timeout
+    ReauthFailed,
+    /// Peer: EAP-Initiate/Re-auth was sent
+    ReauthPending {
+        id: u8,
     },
-    Finished,
-    Failed,
+    Success,
+    Failure,
+    /// Authenticator: the peer did not answer
+    TimeoutFailure,
+}
+
+/// Variables shared with the lower layer, RFC 4137 sections 4.1 and 6.1.
+///
+/// eapReqData, eapRespData and eapKeyData are passed directly: messages in
+/// [`EapOutput`], keys through [`EapLayer::session_keys`]. The signals are
+/// cleared at the start of each call.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EapInterface {
+    /// The lower layer is ready, set by [`EapLayer::start`]
+    pub port_enabled: bool,
+    /// Authenticator: the output holds a request
+    pub eap_req: bool,
+    /// Authenticator: there is no request to send
+    pub eap_no_req: bool,
+    /// Peer: the output holds a response
+    pub eap_resp: bool,
+    /// Peer: the request was discarded, there is no response
+    pub eap_no_resp: bool,
+    pub eap_success: bool,
+    pub eap_fail: bool,
+    /// Authenticator: the peer stopped answering
+    pub eap_timeout: bool,
+    /// Keys are available from [`EapLayer::session_keys`]
+    pub eap_key_available: bool,
+}
+
+impl EapInterface {
+    fn clear_signals(&mut self) {
+        self.eap_req = false;
+        self.eap_no_req = false;
+        self.eap_resp = false;
+        self.eap_no_resp = false;
+    }
 }
 
 pub trait PeerAuthLayer {
@@ -33,7 +121,23 @@ pub trait PeerAuthLayer {
         !self.is_peer()
     }
 
-    fn can_succeed(&mut self) -> bool;
+    /// methodState of the active peer method
+    fn method_state(&self) -> MethodState {
+        MethodState::Init
+    }
+
+    /// decision of the active peer method
+    fn decision(&self) -> Decision {
+        Decision::Fail
+    }
+
+    /// Back to the initial state, for eapRestart
+    fn reset(&mut self) {}
+
+    /// Keys exported by the method that is currently running
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        None
+    }
 
     fn step<'a>(
         &mut self,
@@ -55,6 +159,31 @@ pub trait PeerAuthLayer {
     ) -> PeerAuthLayerResult<'a>;
 }
 
+/// methodState of a peer method, RFC 4137 section 4.1.1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MethodState {
+    /// No request was processed yet
+    Init,
+    /// The method expects more requests, EAP-Success and EAP-Failure are
+    /// ignored
+    Cont,
+    /// The server may end the conversation or send more requests
+    MayCont,
+    /// The method has finished
+    Done,
+}
+
+/// decision of a peer method, RFC 4137 section 4.1.1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Only EAP-Failure ends the conversation
+    Fail,
+    /// EAP-Success is accepted if the server sends it
+    CondSucc,
+    /// The method has established success, EAP-Failure is ignored
+    UncondSucc,
+}
+
 #[allow(unused)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PeerAuthLayerInput<'a> {
@@ -94,6 +223,34 @@ impl<'a> std::fmt::Debug for PeerAuthLayerResult<'a> {
     }
 }
 
+/// Longest Session-ID of the methods here, the Type and the two longest
+/// EAP-IKEv2 nonces
+pub const MAX_SESSION_ID_LEN: usize = 1 + 2 * 64;
+
+/// Keying material exported by a key deriving method (RFC 5247)
+#[derive(Clone, PartialEq, Eq)]
+pub struct SessionKeys {
+    pub msk: [u8; 64],
+    pub emsk: [u8; 64],
+    /// Names the session the keys belong to: the method Type and data of the
+    /// conversation, RFC 5247 1.4 and Appendix A
+    pub session_id: OwnedSlice<MAX_SESSION_ID_LEN>,
+}
+
+impl SessionKeys {
+    /// Session-ID of the method `method_type` made of `parts`
+    pub(crate) fn session_id(method_type: u8, parts: &[&[u8]]) -> OwnedSlice<MAX_SESSION_ID_LEN> {
+        let mut id = [0u8; MAX_SESSION_ID_LEN];
+        id[0] = method_type;
+        let mut len = 1;
+        for part in parts {
+            id[len..len + part.len()].copy_from_slice(part);
+            len += part.len();
+        }
+        OwnedSlice::from(&id[..len])
+    }
+}
+
 #[derive(PartialEq, Eq)]
 pub struct EapOutput<'a> {
     pub status: EapStatus,
@@ -157,6 +314,8 @@ pub enum EapInput<'a> {
     Start,
     Receive(&'a [u8]),
     Timeout,
+    AltAccept,
+    AltReject,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd)]
@@ -164,283 +323,670 @@ pub enum StateError {
     InvalidMessage,
     EndOfConversation,
     Timeout,
+    /// A resource limit of the environment was reached
+    LimitExceeded(SessionLimit),
+}
+
+/// Per-session limits, see the `max_*` settings of [`EapEnvironment`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
+pub enum SessionLimit {
+    RoundTrips,
+    MethodRoundTrips,
+    BytesReceived,
+    Duration,
 }
 
 impl<N: PeerAuthLayer> EapLayer<N> {
     pub fn new(inner: N) -> Self {
         EapLayer {
-            next_id: 0, // gets initialized in start()
-            state: State::Start,
+            state: State::Disabled,
+            interface: EapInterface::default(),
+            current_id: None,
+            retrans_count: 0,
             next_layer: inner,
+            erp: (),
+            reauth_offered: false,
             invalid_message_count: 0,
             timed_out_count: 0,
+            clock: None,
+            deadline: None,
+            sent_at: None,
+            rtt: None,
+            usage: Usage::default(),
+        }
+    }
+}
+
+impl<N: PeerAuthLayer, E: Erp> EapLayer<N, E> {
+    /// Enables ERP (RFC 6696), use [`ErpPeer`](crate::erp::ErpPeer) on the
+    /// peer and [`ErpServer`](crate::erp::ErpServer) on the authenticator
+    pub fn with_erp<F: Erp>(self, erp: F) -> EapLayer<N, F> {
+        EapLayer {
+            state: self.state,
+            interface: self.interface,
+            invalid_message_count: self.invalid_message_count,
+            timed_out_count: self.timed_out_count,
+            current_id: self.current_id,
+            retrans_count: self.retrans_count,
+            next_layer: self.next_layer,
+            erp,
+            reauth_offered: self.reauth_offered,
+            clock: self.clock,
+            deadline: self.deadline,
+            sent_at: self.sent_at,
+            rtt: self.rtt,
+            usage: self.usage,
         }
     }
 
     #[allow(unused)]
     pub fn has_started(&self) -> bool {
-        !matches!(self.state, State::Start)
+        !matches!(self.state, State::Disabled)
     }
 
     #[allow(unused)]
     pub fn is_finished(&self) -> bool {
-        matches!(self.state, State::Finished)
+        matches!(self.state, State::Success)
     }
 
     #[allow(unused)]
     pub fn is_failed(&self) -> bool {
-        matches!(self.state, State::Failed)
+        matches!(self.state, State::Failure | State::TimeoutFailure)
+    }
+
+    /// Interface variables for the lower layer, see [`EapInterface`]
+    pub fn interface(&self) -> &EapInterface {
+        &self.interface
+    }
+
+    /// Time of [`EapEnvironment::now`] at which [`timeout`](Self::timeout)
+    /// is due, `None` without a clock or once the conversation has ended
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// The peer or authenticator layer below
+    pub fn layer(&self) -> &N {
+        &self.next_layer
+    }
+
+    /// The peer or authenticator layer below, mutable
+    pub fn layer_mut(&mut self) -> &mut N {
+        &mut self.next_layer
+    }
+
+    /// MSK and EMSK of a successful conversation, if the method derives keys.
+    /// After re-authentication the MSK is the rMSK.
+    pub fn session_keys(&self) -> Option<&SessionKeys> {
+        match self.state {
+            State::Success => self
+                .erp
+                .session_keys()
+                .or_else(|| self.next_layer.session_keys()),
+            _ => None,
+        }
     }
 
     #[allow(unused)]
-    /// Note: If there is no event to process after a certain amount of time, send a timeout event
-    /// to the state machine. This Timeout should be a few milliseconds. Too many Timeout will
-    /// cause the state machine to fail. This value can be adjusted in the environment.
+    /// Note: If the environment has a clock, send a timeout event once
+    /// [`next_deadline`](Self::next_deadline) has passed. Without a clock
+    /// each timeout event counts as an expired timer, too many of them cause
+    /// the state machine to fail. The limits can be adjusted in the
+    /// environment.
     pub fn step<'a>(&mut self, input: &EapInput, env: &'a mut dyn EapEnvironment) -> EapOutput<'a> {
         match input {
             EapInput::Start => self.start(env),
             EapInput::Receive(msg) => self.receive(msg, env),
             EapInput::Timeout => self.timeout(env),
+            EapInput::AltAccept => self.alt_accept(),
+            EapInput::AltReject => self.alt_reject(),
         }
     }
 
+    /// Enables the port (portEnabled), the state machine leaves DISABLED and
+    /// passes INITIALIZE. The authenticator sends its first request.
     pub fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> EapOutput<'a> {
-        if self.next_layer.is_auth() {
-            let next_id = &mut [0u8];
-            env.fill_random(&mut next_id[..]);
-            self.next_id = next_id[0];
-        }
+        self.interface.clear_signals();
+        self.clock = Clock::read(env);
 
-        match &self.state {
-            State::Start => {
-                self.state = State::Idle;
-                let res = self.next_layer.start(env);
-                self.process_result(res)
-            }
-            _ => EapOutput::internal_error(
-                "EAP state machine is not in the Start state, cannot start",
+        if !matches!(self.state, State::Disabled) {
+            return EapOutput::internal_error(
+                "EAP state machine is not in the DISABLED state, cannot start",
                 None,
-            ),
+            );
+        }
+        self.interface.port_enabled = true;
+        self.current_id = None;
+        self.state = State::Idle;
+        if self.usage.started_at.is_none() {
+            self.usage.started_at = self.clock.as_ref().map(|clock| clock.now);
+        }
+        self.arm_timer();
+
+        if self.next_layer.is_peer() {
+            let res = self.next_layer.start(env);
+            return self.process_result(res, 0);
+        }
+
+        let id = self.next_request_id(env);
+        if self.erp.is_server() && !self.reauth_offered {
+            self.reauth_offered = true;
+            self.current_id = Some(id);
+            self.retrans_count = 0;
+            self.state = State::ReauthStart;
+            self.arm_timer();
+            self.interface.eap_req = true;
+            return EapOutput::send(env.respond_with(
+                MessageCode::Initiate,
+                id,
+                &[TYPE_REAUTH_START, 0],
+            ));
         }
+
+        // SELECT_ACTION, PROPOSE_METHOD and METHOD_REQUEST
+        let res = self.next_layer.start(env);
+        self.process_result(res, id)
+    }
+
+    /// eapRestart, returns to INITIALIZE and starts a new conversation, e.g.
+    /// after an EAPOL-Start
+    pub fn restart<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> EapOutput<'a> {
+        self.interface = EapInterface::default();
+        self.state = State::Disabled;
+        self.retrans_count = 0;
+        self.invalid_message_count = 0;
+        self.timed_out_count = 0;
+        self.deadline = None;
+        self.sent_at = None;
+        self.usage = Usage::default();
+        self.next_layer.reset();
+        self.start(env)
     }
 
+    /// eapReq on the peer, eapResp on the authenticator
     pub fn receive<'a>(
         &mut self,
         message: &[u8],
         env: &'a mut dyn EapEnvironment,
     ) -> EapOutput<'a> {
+        self.interface.clear_signals();
+        self.clock = Clock::read(env);
         // Reset Timeout counter
         self.timed_out_count = 0;
 
-        match &self.state {
-            State::Start | State::Idle if self.next_layer.is_auth() => {
-                // Silently drop the message, no request has been sent yet
-                self.on_invalid_message(env)
+        match self.state {
+            State::Success => return EapOutput::success(None),
+            State::Failure => return EapOutput::failed(StateError::EndOfConversation, None),
+            State::TimeoutFailure => return EapOutput::failed(StateError::Timeout, None),
+            _ => {}
+        }
+
+        self.usage.bytes_received = (self.usage.bytes_received)
+            .saturating_add(message.len().try_into().unwrap_or(u32::MAX));
+        if let Some(limit) = self.exceeded_limit(env) {
+            return self.on_fail(StateError::LimitExceeded(limit), env);
+        }
+
+        let msg = match (&self.state, Message::parse(message)) {
+            // Silently drop the message, the port is disabled
+            (State::Disabled, _) if self.next_layer.is_auth() => {
+                return self.discard(env);
             }
-            State::Start | State::Idle => {
-                // if peer
-                match Message::parse(message) {
-                    Ok(msg) if msg.code == MessageCode::Request => {
-                        self.invalid_message_count = 0;
-                        self.next_id = msg.identifier;
-                        let res = self.next_layer.recv(&msg, env);
-                        self.process_result(res)
-                    }
-                    Ok(msg) if msg.code == MessageCode::Failure => {
-                        // TODO: Ask inner layer if it is ok to end the conversation
-                        self.state = State::Failed;
-                        EapOutput::failed(StateError::EndOfConversation, None)
-                    }
-                    _ => self.on_invalid_message(env),
+            // A request enables the port of the peer
+            (State::Disabled, Ok(msg)) => {
+                self.interface.port_enabled = true;
+                self.state = State::Idle;
+                if self.usage.started_at.is_none() {
+                    self.usage.started_at = self.clock.as_ref().map(|clock| clock.now);
                 }
+                msg
             }
-            State::MessagePending { expected_id, .. } => match Message::parse(message) {
-                // Duplicate messages are ignored rfc3748 3.1.5
-                Ok(msg) if self.next_layer.is_auth() => {
-                    // Check if the message is a response to the last request
-                    if msg.identifier != *expected_id {
-                        return self.on_invalid_message(env);
-                    }
+            (_, Ok(msg)) => msg,
+            (_, Err(_e)) => return self.discard(env),
+        };
 
-                    // Auth layer should only receive responses or failure messages
-                    if msg.code != MessageCode::Response {
-                        return self.on_invalid_message(env);
-                    }
+        match self.state {
+            State::ReauthStart => self.received_reauth(&msg, env),
+            State::ReauthFailed => self.discard(env),
+            State::ReauthPending { id } => self.received_finish(&msg, id, env),
+            _ if self.next_layer.is_auth() => self.auth_received(&msg, env),
+            _ => self.peer_received(&msg, env),
+        }
+    }
 
-                    if msg.code == MessageCode::Failure {
-                        self.state = State::Failed;
-                        return EapOutput::failed(StateError::EndOfConversation, None);
-                    }
+    /// Expiry of idleWhile on the peer and of retransWhile on the
+    /// authenticator
+    pub fn timeout<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> EapOutput<'a> {
+        self.interface.clear_signals();
+        self.clock = Clock::read(env);
+
+        match (&self.clock, self.deadline) {
+            (Some(clock), Some(deadline)) if clock.now < deadline => return EapOutput::noop(),
+            _ => {}
+        }
+
+        match self.state {
+            State::Success => EapOutput::success(None),
+            State::Failure => EapOutput::failed(StateError::EndOfConversation, None),
+            State::TimeoutFailure => EapOutput::failed(StateError::Timeout, None),
+            _ if self.exceeded_limit(env) == Some(SessionLimit::Duration) => {
+                self.on_fail(StateError::LimitExceeded(SessionLimit::Duration), env)
+            }
+            // The peer does not support ERP or could not re-authenticate,
+            // fall back to full EAP
+            State::ReauthStart | State::ReauthFailed => {
+                self.state = State::Idle;
+                let id = self.next_request_id(env);
+                let res = self.next_layer.start(env);
+                self.process_result(res, id)
+            }
+            State::Idle if self.next_layer.is_auth() => self.retransmit(env),
+            _ => self.on_timeout(env),
+        }
+    }
+
+    /// altAccept, the lower layer of the peer indicates success, e.g. a
+    /// completed 4-way handshake. Ends in SUCCESS without an EAP-Success if
+    /// the decision of the method allows it.
+    pub fn alt_accept<'a>(&mut self) -> EapOutput<'a> {
+        self.interface.clear_signals();
 
-                    let res = self.next_layer.recv(&msg, env);
-                    self.process_result(res)
+        match self.state {
+            State::Success => EapOutput::success(None),
+            State::Failure => EapOutput::failed(StateError::EndOfConversation, None),
+            State::Idle if self.next_layer.is_peer() => {
+                if self.next_layer.decision() != Decision::Fail {
+                    self.success();
+                    return EapOutput::success(None);
                 }
-                Ok(msg) if self.next_layer.is_peer() => {
-                    // Sucess messages have other next_id ...
-                    // because this code would be to easy otherwise
-                    if msg.code == MessageCode::Success
-                        && self.next_layer.can_succeed()
-                        && msg.identifier == self.next_id.wrapping_sub(1)
-                    {
-                        self.state = State::Finished;
-                        return EapOutput::success(None);
-                    }
+                // A method in CONT may still reach a decision
+                if self.next_layer.method_state() != MethodState::Cont {
+                    self.failure();
+                    return EapOutput::failed(StateError::EndOfConversation, None);
+                }
+                EapOutput::noop()
+            }
+            _ if self.next_layer.is_auth() => EapOutput::internal_error(
+                "Lower layer indications are only defined for the peer",
+                None,
+            ),
+            _ => EapOutput::noop(),
+        }
+    }
 
-                    if msg.identifier == *expected_id {
-                        // a.k.a expected_id is the last id sent by the peer
-                        // Auth layer expects a retranmission of the last request
-                        return self.retransmit(env);
-                    }
+    /// altReject, the lower layer of the peer indicates failure
+    pub fn alt_reject<'a>(&mut self) -> EapOutput<'a> {
+        self.interface.clear_signals();
 
-                    if msg.identifier != self.next_id {
-                        // Message id is not valid
-                        return self.on_invalid_message(env);
-                    }
+        match self.state {
+            State::Failure => EapOutput::failed(StateError::EndOfConversation, None),
+            State::Idle if self.next_layer.is_peer() => {
+                self.failure();
+                EapOutput::failed(StateError::EndOfConversation, None)
+            }
+            _ if self.next_layer.is_auth() => EapOutput::internal_error(
+                "Lower layer indications are only defined for the peer",
+                None,
+            ),
+            State::Success => EapOutput::success(None),
+            _ => EapOutput::noop(),
+        }
+    }
 
-                    // While Peer layer can receive everything but responses
-                    if msg.code == MessageCode::Response {
-                        return self.on_invalid_message(env);
+    /// RECEIVED of the peer state machine
+    fn peer_received<'a>(
+        &mut self,
+        msg: &Message,
+        env: &'a mut dyn EapEnvironment,
+    ) -> EapOutput<'a> {
+        let last_id = self.current_id == Some(msg.identifier);
+        let decision = self.next_layer.decision();
+        // A method in CONT ignores both EAP-Success and EAP-Failure
+        let may_end = last_id && self.next_layer.method_state() != MethodState::Cont;
+
+        match msg.code {
+            MessageCode::Request if last_id => self.retransmit(env),
+            MessageCode::Request => {
+                self.invalid_message_count = 0;
+                if let Some(limit) = self.count_round_trip(msg, env) {
+                    return self.on_fail(StateError::LimitExceeded(limit), env);
+                }
+                let res = self.next_layer.recv(msg, env);
+                self.process_result(res, msg.identifier)
+            }
+            MessageCode::Success if last_id && decision != Decision::Fail => {
+                self.success();
+                EapOutput::success(None)
+            }
+            // An EAP-Success the method has not agreed to ends the conversation
+            MessageCode::Success if may_end && decision == Decision::Fail => {
+                self.failure();
+                EapOutput::failed(StateError::EndOfConversation, None)
+            }
+            MessageCode::Failure if may_end && decision != Decision::UncondSucc => {
+                self.failure();
+                EapOutput::failed(StateError::EndOfConversation, None)
+            }
+            MessageCode::Initiate
+                if msg.body.first() == Some(&TYPE_REAUTH_START) && self.current_id.is_none() =>
+            {
+                match self.erp.reauth(msg.identifier, env) {
+                    Ok(reauth) => {
+                        self.retrans_count = 0;
+                        self.state = State::ReauthPending { id: msg.identifier };
+                        self.arm_timer();
+                        self.interface.eap_resp = true;
+                        EapOutput::send(reauth)
                     }
+                    // Without keys the peer waits for full EAP
+                    Err(env) => self.discard(env),
+                }
+            }
+            _ => self.discard(env),
+        }
+    }
 
-                    if msg.code == MessageCode::Failure {
-                        self.state = State::Failed;
-                        return EapOutput::failed(StateError::EndOfConversation, None);
-                    }
+    /// RECEIVED of the authenticator state machine, only the response to the
+    /// current request is processed
+    fn auth_received<'a>(
+        &mut self,
+        msg: &Message,
+        env: &'a mut dyn EapEnvironment,
+    ) -> EapOutput<'a> {
+        if msg.code != MessageCode::Response || Some(msg.identifier) != self.current_id {
+            return self.discard(env);
+        }
+        if let (Some(sent_at), Some(clock)) = (self.sent_at.take(), &self.clock) {
+            let sample = clock.now.saturating_sub(sent_at).min(u32::MAX as u64) as u32;
+            match &mut self.rtt {
+                Some(rtt) => rtt.update(sample),
+                None => self.rtt = Some(Rtt::new(sample)),
+            }
+        }
+        if let Some(limit) = self.count_round_trip(msg, env) {
+            return self.on_fail(StateError::LimitExceeded(limit), env);
+        }
+
+        let id = self.next_request_id(env);
+        let res = self.next_layer.recv(msg, env);
+        self.process_result(res, id)
+    }
+
+    /// Authenticator: answer to EAP-Initiate/Re-auth-Start
+    fn received_reauth<'a>(
+        &mut self,
+        msg: &Message,
+        env: &'a mut dyn EapEnvironment,
+    ) -> EapOutput<'a> {
+        if msg.code != MessageCode::Initiate
+            || Some(msg.identifier) != self.current_id
+            || msg.body.first() != Some(&TYPE_REAUTH)
+        {
+            return self.discard(env);
+        }
+
+        match self.erp.verify(msg, env) {
+            ReauthResult::Success(finish) => {
+                self.state = State::Success;
+                self.deadline = None;
+                self.interface.eap_success = true;
+                self.interface.eap_key_available = self.erp.session_keys().is_some();
+                EapOutput::success(Some(finish))
+            }
+            ReauthResult::Failure(finish) => {
+                // Full EAP follows in the same conversation, RFC 6696 5.3.3
+                self.state = State::ReauthFailed;
+                self.deadline = None;
+                self.interface.eap_req = true;
+                EapOutput::send(finish)
+            }
+            ReauthResult::Discard(env) => self.discard(env),
+        }
+    }
 
-                    let res = self.next_layer.recv(&msg, env);
-                    self.process_result(res)
+    /// Peer: answer to EAP-Initiate/Re-auth
+    fn received_finish<'a>(
+        &mut self,
+        msg: &Message,
+        id: u8,
+        env: &'a mut dyn EapEnvironment,
+    ) -> EapOutput<'a> {
+        match msg.code {
+            MessageCode::Finish if msg.identifier == id => match self.erp.finish(msg) {
+                Some(true) => {
+                    self.state = State::Success;
+                    self.deadline = None;
+                    self.interface.eap_success = true;
+                    self.interface.eap_key_available = self.erp.session_keys().is_some();
+                    EapOutput::success(None)
                 }
-                Ok(_) => {
-                    unreachable!(); // Inner layer must be either auth or peer
+                Some(false) => {
+                    self.state = State::Idle;
+                    self.interface.eap_no_resp = true;
+                    EapOutput::noop()
                 }
-                Err(_e) => self.on_invalid_message(env),
+                None => self.discard(env),
             },
-            State::Finished => EapOutput::success(None),
-            State::Failed => EapOutput::failed(StateError::EndOfConversation, None),
+            MessageCode::Initiate if msg.identifier == id => self.retransmit(env),
+            // The authenticator gave up on re-authentication
+            MessageCode::Request => {
+                self.state = State::Idle;
+                self.peer_received(msg, env)
+            }
+            _ => self.discard(env),
         }
     }
 
-    pub fn timeout<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> EapOutput<'a> {
-        match self.state {
-            State::MessagePending { .. } => {
-                if self.next_layer.is_auth() {
-                    self.retransmit(env)
-                } else {
-                    self.on_timeout(env)
-                }
+    /// Counts a request answered by the peer or a response processed by the
+    /// authenticator, the round trips of a method restart with each new type
+    fn count_round_trip(
+        &mut self,
+        msg: &Message,
+        env: &mut dyn EapEnvironment,
+    ) -> Option<SessionLimit> {
+        let usage = &mut self.usage;
+        usage.round_trips = usage.round_trips.saturating_add(1);
+
+        let method = MethodType::parse(msg.body).map(|(method, _)| method);
+        if method != usage.method {
+            usage.method = method;
+            usage.method_round_trips = 0;
+        }
+        usage.method_round_trips = usage.method_round_trips.saturating_add(1);
+
+        self.exceeded_limit(env)
+    }
+
+    fn exceeded_limit(&self, env: &dyn EapEnvironment) -> Option<SessionLimit> {
+        let usage = &self.usage;
+        let elapsed = match (&self.clock, usage.started_at) {
+            (Some(clock), Some(started_at)) => clock.now.saturating_sub(started_at),
+            _ => 0,
+        };
+
+        if usage.round_trips > env.max_round_trips() {
+            Some(SessionLimit::RoundTrips)
+        } else if usage.method_round_trips > env.max_method_round_trips() {
+            Some(SessionLimit::MethodRoundTrips)
+        } else if usage.bytes_received > env.max_bytes_received() {
+            Some(SessionLimit::BytesReceived)
+        } else if elapsed >= env.max_session_duration() {
+            Some(SessionLimit::Duration)
+        } else {
+            None
+        }
+    }
+
+    /// nextId(currentId), the first request gets a random identifier
+    fn next_request_id(&self, env: &mut dyn EapEnvironment) -> u8 {
+        match self.current_id {
+            Some(id) => id.wrapping_add(1),
+            None => {
+                let id = &mut [0u8];
+                env.fill_random(&mut id[..]);
+                id[0]
             }
-            State::Finished => EapOutput::success(None),
-            State::Failed => EapOutput::failed(StateError::EndOfConversation, None),
-            _ => self.on_timeout(env),
         }
     }
 
+    /// Idle timeout of the peer, an expired idleWhile or too many timeout
+    /// events lead to FAILURE. A method that decided on unconditional
+    /// success does not need the EAP-Success.
     fn on_timeout<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> EapOutput<'a> {
         self.timed_out_count += 1;
-        if self.timed_out_count >= env.max_timeout_count() {
-            self.state = State::Failed;
-            return EapOutput::failed(
-                StateError::Timeout,
-                Some(env.respond_with(MessageCode::Failure, self.next_id, &[])),
-            );
+        if self.clock.is_some() || self.timed_out_count >= env.max_timeout_count() {
+            if self.next_layer.is_peer() && self.next_layer.decision() == Decision::UncondSucc {
+                self.success();
+                return EapOutput::success(None);
+            }
+            self.failure();
+            return EapOutput::failed(StateError::Timeout, None);
         }
 
+        self.interface.eap_no_resp = true;
         EapOutput::noop()
     }
 
+    /// RETRANSMIT, the authenticator gives up in TIMEOUT_FAILURE
     fn retransmit<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> EapOutput<'a> {
-        match &mut self.state {
-            State::MessagePending {
-                retransmission_count,
-                ..
-            } => {
-                if *retransmission_count >= env.max_retransmit_count() {
-                    return self.on_fail(StateError::Timeout, env);
-                }
-
-                *retransmission_count += 1;
+        if self.retrans_count >= env.max_retransmit_count() {
+            if self.next_layer.is_auth() {
+                self.state = State::TimeoutFailure;
+                self.deadline = None;
+                self.interface.eap_timeout = true;
+                return EapOutput::failed(StateError::Timeout, None);
+            }
+            self.failure();
+            return EapOutput::failed(StateError::Timeout, None);
+        }
+        self.retrans_count += 1;
+        self.sent_at = None;
+        self.arm_timer();
 
-                match env.last_message() {
-                    Ok(msg) => EapOutput::send(msg),
-                    _ => {
-                        EapOutput::internal_error("Last message not found, can't retransmit", None)
-                    }
+        match env.last_message() {
+            Ok(msg) => {
+                if self.next_layer.is_auth() {
+                    self.interface.eap_req = true;
+                } else {
+                    self.interface.eap_resp = true;
                 }
+                EapOutput::send(msg)
             }
-            _ => self.on_invalid_message(env),
+            _ => EapOutput::internal_error("Last message not found, can't retransmit", None),
         }
     }
 
-    fn on_invalid_message<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> EapOutput<'a> {
+    /// DISCARD, too many invalid messages end the conversation
+    fn discard<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> EapOutput<'a> {
         self.invalid_message_count += 1;
         if self.invalid_message_count >= env.max_invalid_message_count() {
             return self.on_fail(StateError::InvalidMessage, env);
         }
 
+        if self.next_layer.is_auth() {
+            self.interface.eap_no_req = true;
+        } else {
+            self.interface.eap_no_resp = true;
+        }
         EapOutput::noop()
     }
 
+    /// Ends the conversation, only the authenticator sends an EAP-Failure
     fn on_fail<'a>(
         &mut self,
         reason: StateError,
         env: &'a mut dyn EapEnvironment,
     ) -> EapOutput<'a> {
-        self.state = State::Failed;
-        EapOutput::failed(
-            reason,
-            Some(env.respond_with(MessageCode::Failure, self.next_id, &[])),
-        )
+        self.failure();
+        let failure = match (self.next_layer.is_auth(), self.current_id) {
+            (true, Some(id)) => Some(env.respond_with(MessageCode::Failure, id, &[])),
+            _ => None,
+        };
+        EapOutput::failed(reason, failure)
     }
 
-    fn process_result<'a>(&mut self, res: PeerAuthLayerResult<'a>) -> EapOutput<'a> {
+    /// Result of the peer or authenticator layer, `id` is the identifier of
+    /// the request being answered on the peer and of the next request on the
+    /// authenticator
+    fn process_result<'a>(&mut self, res: PeerAuthLayerResult<'a>, id: u8) -> EapOutput<'a> {
         match res {
-            PeerAuthLayerResult::Noop(_) => EapOutput::noop(),
-            PeerAuthLayerResult::Send(msg) => self.send_message(msg),
+            PeerAuthLayerResult::Noop(_) => {
+                self.interface.eap_no_resp = self.next_layer.is_peer();
+                self.interface.eap_no_req = self.next_layer.is_auth();
+                EapOutput::noop()
+            }
+            PeerAuthLayerResult::Send(msg) => self.send_message(msg, id),
             PeerAuthLayerResult::Finished(env) => {
-                self.state = State::Finished;
+                self.success();
                 if self.next_layer.is_auth() {
-                    // Notify Client
                     // For success messages the identifier is the same as the last request.
-                    // TODO: refactor `next_id to be `last_id`
-                    self.next_id = self.next_id.wrapping_sub(1);
-
-                    EapOutput::success(Some(env.respond_with(
-                        MessageCode::Success,
-                        self.next_id,
-                        &[],
-                    )))
+                    let id = self.current_id.unwrap_or(id);
+                    EapOutput::success(Some(env.respond_with(MessageCode::Success, id, &[])))
                 } else {
                     EapOutput::success(None)
                 }
             }
-            PeerAuthLayerResult::Failed(env) => {
-                self.state = State::Failed;
-                EapOutput::failed(
-                    StateError::EndOfConversation,
-                    Some(env.respond_with(MessageCode::Failure, self.next_id, &[])),
-                )
-            }
+            PeerAuthLayerResult::Failed(env) => self.on_fail(StateError::EndOfConversation, env),
+        }
+    }
+
+    /// SUCCESS, the keys of a full authentication bootstrap ERP
+    fn success(&mut self) {
+        self.state = State::Success;
+        self.deadline = None;
+        self.interface.eap_success = true;
+        if let Some(keys) = self.next_layer.session_keys() {
+            self.interface.eap_key_available = true;
+            self.erp.bootstrap(keys);
         }
     }
 
-    fn send_message<'a>(&mut self, msg: MessageBuilder<'a>) -> EapOutput<'a> {
+    /// FAILURE
+    fn failure(&mut self) {
+        self.state = State::Failure;
+        self.deadline = None;
+        self.interface.eap_fail = true;
+    }
+
+    /// SEND_REQUEST on the authenticator, SEND_RESPONSE on the peer
+    fn send_message<'a>(&mut self, msg: MessageBuilder<'a>, id: u8) -> EapOutput<'a> {
         let code = if self.next_layer.is_auth() {
+            self.interface.eap_req = true;
             MessageCode::Request
         } else {
+            self.interface.eap_resp = true;
             MessageCode::Response
         };
 
-        let identifier = self.next_id;
-        self.next_id = self.next_id.wrapping_add(1);
+        self.current_id = Some(id);
+        self.retrans_count = 0;
+        self.state = State::Idle;
+        self.arm_timer();
 
-        let message = msg.build(code, identifier);
+        EapOutput::send(msg.build(code, id))
+    }
 
-        self.state = State::MessagePending {
-            expected_id: identifier,
-            retransmission_count: 0,
+    /// Starts retransWhile with calculateTimeout on the authenticator and
+    /// idleWhile with ClientTimeout on the peer
+    fn arm_timer(&mut self) {
+        let Some(clock) = &self.clock else {
+            self.deadline = None;
+            return;
+        };
+        let timeout = if self.next_layer.is_auth() {
+            if self.retrans_count == 0 {
+                self.sent_at = Some(clock.now);
+            }
+            clock.calculate_timeout(self.rtt.as_ref(), self.retrans_count)
+        } else {
+            clock.idle_timeout
+        };
+        let deadline = clock.now + timeout as u64;
+        // The session ends at the latest after max_session_duration
+        self.deadline = match self.usage.started_at {
+            Some(started_at) => Some(deadline.min(started_at + clock.max_session_duration)),
+            None => Some(deadline),
         };
-
-        EapOutput::send(message)
     }
 }
 
@@ -456,6 +1002,10 @@ mod tests {
         is_auth: bool,
         is_peer: bool,
         counter: u8,
+        method_state: MethodState,
+        decision: Decision,
+        // The authenticator succeeds after this many responses
+        finish_after: Option<u8>,
     }
 
     impl DummyInnerLayer {
@@ -464,6 +1014,9 @@ mod tests {
                 is_auth,
                 is_peer: !is_auth,
                 counter: 0,
+                method_state: MethodState::Done,
+                decision: Decision::CondSucc,
+                finish_after: None,
             }
         }
     }
@@ -489,6 +1042,9 @@ mod tests {
             }
             assert_eq!(msg.body.len(), 2);
             self.counter += 1;
+            if self.finish_after == Some(self.counter) {
+                return PeerAuthLayerResult::Finished(env);
+            }
             PeerAuthLayerResult::Send(env.respond().write([msg.body[0], self.counter].as_slice()))
         }
 
@@ -496,8 +1052,12 @@ mod tests {
             self.is_peer
         }
 
-        fn can_succeed(&mut self) -> bool {
-            true
+        fn method_state(&self) -> MethodState {
+            self.method_state
+        }
+
+        fn decision(&self) -> Decision {
+            self.decision
         }
     }
 
@@ -653,12 +1213,507 @@ mod tests {
             );
         }
 
+        // TIMEOUT_FAILURE sends nothing, the lower layer sees eapTimeout
         assert_output(
             layer.timeout(&mut env),
             EapOutput {
-                message: Some(Message::new(MessageCode::Failure, 43, &[]).into()),
+                message: None,
                 status: EapStatus::Failed(StateError::Timeout),
             },
         );
+        assert!(layer.interface().eap_timeout);
+        assert!(layer.is_failed());
+    }
+
+    fn request(id: u8, body: &[u8]) -> Vec<u8> {
+        Message::new(MessageCode::Request, id, body).to_vec()
+    }
+
+    #[test]
+    /// DISABLED -> INITIALIZE -> IDLE -> RECEIVED -> METHOD -> SEND_RESPONSE
+    /// and RETRANSMIT for a request with lastId
+    fn rfc4137_peer_method_and_retransmit() {
+        let mut env = DefaultEnvironment::new();
+        let mut layer = EapLayer::new(DummyInnerLayer::new(false));
+        assert!(!layer.interface().port_enabled);
+
+        let _ = layer.start(&mut env);
+        assert!(layer.interface().port_enabled);
+
+        let output = layer.receive(&request(7, &[1, 0]), &mut env);
+        assert_eq!(output.message.unwrap().as_ref(), &[2, 7, 0, 6, 1, 1]);
+        assert!(layer.interface().eap_resp);
+
+        // Same identifier, the last response is sent again
+        let output = layer.receive(&request(7, &[1, 0]), &mut env);
+        assert_eq!(output.message.unwrap().as_ref(), &[2, 7, 0, 6, 1, 1]);
+        assert!(layer.interface().eap_resp);
+
+        // Any other identifier is a new request
+        let output = layer.receive(&request(3, &[1, 0]), &mut env);
+        assert_eq!(output.message.unwrap().as_ref(), &[2, 3, 0, 6, 1, 2]);
+    }
+
+    #[test]
+    /// RECEIVED -> DISCARD for messages a peer does not accept
+    fn rfc4137_peer_discard() {
+        let mut env = DefaultEnvironment::new();
+        let mut layer = EapLayer::new(DummyInnerLayer::new(false));
+        let _ = layer.start(&mut env);
+
+        // No request was answered yet, lastId is NONE
+        for code in [MessageCode::Success, MessageCode::Failure] {
+            let output = layer.receive(&Message::new(code, 0, &[]).to_vec(), &mut env);
+            assert_output(output, EapOutput::noop());
+            assert!(layer.interface().eap_no_resp);
+        }
+
+        let _ = layer.receive(&request(5, &[1, 0]), &mut env);
+        for message in [
+            Message::new(MessageCode::Success, 6, &[]),
+            Message::new(MessageCode::Response, 5, &[1, 0]),
+        ] {
+            let output = layer.receive(&message.to_vec(), &mut env);
+            assert_output(output, EapOutput::noop());
+            assert!(layer.interface().eap_no_resp);
+        }
+        assert!(!layer.is_finished() && !layer.is_failed());
+    }
+
+    #[test]
+    /// RECEIVED -> SUCCESS for an EAP-Success with lastId
+    fn rfc4137_peer_success() {
+        let mut env = DefaultEnvironment::new();
+        let mut layer = EapLayer::new(DummyInnerLayer::new(false));
+        let _ = layer.start(&mut env);
+        let _ = layer.receive(&request(5, &[1, 0]), &mut env);
+
+        let output = layer.receive(
+            &Message::new(MessageCode::Success, 5, &[]).to_vec(),
+            &mut env,
+        );
+        assert_output(output, EapOutput::success(None));
+        assert!(layer.interface().eap_success);
+        assert!(!layer.interface().eap_key_available);
+    }
+
+    fn peer_after_request(
+        method_state: MethodState,
+        decision: Decision,
+    ) -> EapLayer<DummyInnerLayer> {
+        let mut layer = EapLayer::new(DummyInnerLayer::new(false));
+        let _ = layer.start(&mut DefaultEnvironment::new());
+        let _ = layer.receive(&request(5, &[1, 0]), &mut DefaultEnvironment::new());
+        layer.layer_mut().method_state = method_state;
+        layer.layer_mut().decision = decision;
+        layer
+    }
+
+    #[test]
+    /// RECEIVED -> FAILURE depends on methodState and decision
+    fn rfc4137_peer_failure() {
+        let mut env = DefaultEnvironment::new();
+        let failure = Message::new(MessageCode::Failure, 5, &[]).to_vec();
+
+        // The method has established success on its own
+        let mut layer = peer_after_request(MethodState::Done, Decision::UncondSucc);
+        assert_output(layer.receive(&failure, &mut env), EapOutput::noop());
+        assert!(!layer.interface().eap_fail);
+
+        // The method is not finished yet
+        let mut layer = peer_after_request(MethodState::Cont, Decision::Fail);
+        assert_output(layer.receive(&failure, &mut env), EapOutput::noop());
+
+        for method_state in [MethodState::MayCont, MethodState::Done] {
+            let mut layer = peer_after_request(method_state, Decision::CondSucc);
+            // The peer never sends an EAP-Failure itself
+            assert_output(
+                layer.receive(&failure, &mut env),
+                EapOutput::failed(StateError::EndOfConversation, None),
+            );
+            assert!(layer.interface().eap_fail);
+        }
+    }
+
+    #[test]
+    /// An EAP-Success before the method decided on success
+    fn rfc4137_peer_early_success() {
+        let mut env = DefaultEnvironment::new();
+        let success = Message::new(MessageCode::Success, 5, &[]).to_vec();
+
+        let mut layer = peer_after_request(MethodState::Cont, Decision::Fail);
+        assert_output(layer.receive(&success, &mut env), EapOutput::noop());
+        assert!(!layer.is_finished());
+
+        let mut layer = peer_after_request(MethodState::MayCont, Decision::Fail);
+        assert_output(
+            layer.receive(&success, &mut env),
+            EapOutput::failed(StateError::EndOfConversation, None),
+        );
+        assert!(!layer.interface().eap_success && layer.interface().eap_fail);
+    }
+
+    #[test]
+    /// IDLE -> SUCCESS without EAP-Success after unconditional success
+    fn rfc4137_peer_idle_timeout_success() {
+        let mut env = DefaultEnvironment::new();
+        let mut layer = peer_after_request(MethodState::Done, Decision::UncondSucc);
+
+        for _ in 1..env.max_timeout_count() {
+            assert_output(layer.timeout(&mut env), EapOutput::noop());
+        }
+        assert_output(layer.timeout(&mut env), EapOutput::success(None));
+        assert!(layer.interface().eap_success);
+    }
+
+    #[test]
+    /// IDLE -> SUCCESS and IDLE -> FAILURE on lower layer indications
+    fn rfc4137_peer_alt_accept_reject() {
+        let mut layer = peer_after_request(MethodState::Done, Decision::CondSucc);
+        assert_output(layer.alt_accept(), EapOutput::success(None));
+        assert!(layer.is_finished() && layer.interface().eap_success);
+
+        // The method has not decided yet
+        let mut layer = peer_after_request(MethodState::Cont, Decision::Fail);
+        assert_output(layer.alt_accept(), EapOutput::noop());
+        assert!(!layer.is_finished() && !layer.is_failed());
+
+        let mut layer = peer_after_request(MethodState::MayCont, Decision::Fail);
+        assert_output(
+            layer.alt_accept(),
+            EapOutput::failed(StateError::EndOfConversation, None),
+        );
+        assert!(layer.interface().eap_fail);
+
+        // altReject overrides even unconditional success
+        let mut layer = peer_after_request(MethodState::Done, Decision::UncondSucc);
+        assert_output(
+            layer.alt_reject(),
+            EapOutput::failed(StateError::EndOfConversation, None),
+        );
+        assert!(layer.is_failed());
+
+        let mut layer = EapLayer::new(DummyInnerLayer::new(true));
+        let _ = layer.start(&mut DefaultEnvironment::new());
+        assert!(matches!(
+            layer.alt_accept().status,
+            EapStatus::InternalError(_)
+        ));
+    }
+
+    #[test]
+    /// IDLE -> FAILURE once idleWhile expires too often
+    fn rfc4137_peer_idle_timeout() {
+        let mut env = DefaultEnvironment::new();
+        let mut layer = EapLayer::new(DummyInnerLayer::new(false));
+        let _ = layer.start(&mut env);
+
+        for _ in 1..env.max_timeout_count() {
+            assert_output(layer.timeout(&mut env), EapOutput::noop());
+        }
+        assert_output(
+            layer.timeout(&mut env),
+            EapOutput::failed(StateError::Timeout, None),
+        );
+        assert!(layer.interface().eap_fail);
+    }
+
+    #[test]
+    /// INITIALIZE -> SEND_REQUEST -> IDLE -> RECEIVED, DISCARD of responses
+    /// to other requests and SUCCESS with currentId
+    fn rfc4137_auth_dialog() {
+        let mut env = StaticEnvironment::<1020>::new(|buf| buf.fill(42));
+        let mut layer = EapLayer::new(DummyInnerLayer::new(true));
+        layer.layer_mut().finish_after = Some(2);
+
+        let output = layer.start(&mut env);
+        assert_eq!(output.message.unwrap().as_ref(), &[1, 42, 0, 6, 0, 0]);
+        assert!(layer.interface().eap_req);
+
+        // Not the current identifier
+        let response = |id, body: &[u8]| Message::new(MessageCode::Response, id, body).to_vec();
+        assert_output(
+            layer.receive(&response(41, &[0, 0]), &mut env),
+            EapOutput::noop(),
+        );
+        assert!(layer.interface().eap_no_req);
+        // The authenticator ignores requests
+        assert_output(
+            layer.receive(&request(42, &[0, 0]), &mut env),
+            EapOutput::noop(),
+        );
+
+        let output = layer.receive(&response(42, &[0, 0]), &mut env);
+        assert_eq!(output.message.unwrap().as_ref(), &[1, 43, 0, 6, 0, 1]);
+
+        let output = layer.receive(&response(43, &[0, 0]), &mut env);
+        assert_eq!(output.status, EapStatus::Success);
+        assert_eq!(output.message.unwrap().as_ref(), &[3, 43, 0, 4]);
+        assert!(layer.interface().eap_success);
+        assert!(layer.is_finished());
+    }
+
+    fn clocked_env() -> (DefaultEnvironment, std::rc::Rc<std::cell::Cell<u64>>) {
+        let now = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut env = DefaultEnvironment::new();
+        env.set_clock({
+            let now = now.clone();
+            move || now.get()
+        });
+        (env, now)
+    }
+
+    #[test]
+    /// retransWhile with exponential backoff until TIMEOUT_FAILURE
+    fn rfc4137_auth_retransmit_backoff() {
+        let (mut env, now) = clocked_env();
+        let mut layer = EapLayer::new(DummyInnerLayer::new(true));
+
+        let request = layer.start(&mut env).message.unwrap().as_ref().to_vec();
+        assert_eq!(layer.next_deadline(), Some(3_000));
+
+        now.set(2_999);
+        assert_output(layer.timeout(&mut env), EapOutput::noop());
+
+        for deadline in [9_000, 21_000, 45_000, 93_000] {
+            now.set(layer.next_deadline().unwrap());
+            let output = layer.timeout(&mut env);
+            assert_eq!(output.message.unwrap().as_ref(), request);
+            assert_eq!(layer.next_deadline(), Some(deadline));
+        }
+
+        now.set(93_000);
+        assert_output(
+            layer.timeout(&mut env),
+            EapOutput::failed(StateError::Timeout, None),
+        );
+        assert!(layer.interface().eap_timeout);
+        assert_eq!(layer.next_deadline(), None);
+    }
+
+    #[test]
+    /// calculateTimeout uses the measured round-trip time, but not that of a
+    /// retransmitted request
+    fn rfc4137_auth_rtt() {
+        let (mut env, now) = clocked_env();
+        let mut layer = EapLayer::new(DummyInnerLayer::new(true));
+        let response = |id| Message::new(MessageCode::Response, id, &[0, 0]).to_vec();
+
+        let id = layer.start(&mut env).message.unwrap().as_ref()[1];
+        now.set(100);
+        let _ = layer.receive(&response(id), &mut env);
+        // SRTT 100 ms and RTTVAR 50 ms
+        assert_eq!(layer.next_deadline(), Some(100 + 300));
+
+        now.set(400);
+        let _ = layer.timeout(&mut env);
+        assert_eq!(layer.next_deadline(), Some(400 + 600));
+
+        // Ambiguous after the retransmission, the estimate stays
+        now.set(900);
+        let _ = layer.receive(&response(id.wrapping_add(1)), &mut env);
+        assert_eq!(layer.next_deadline(), Some(900 + 300));
+    }
+
+    #[test]
+    /// idleWhile of the peer restarts with each response
+    fn rfc4137_peer_idle_while() {
+        let (mut env, now) = clocked_env();
+        let mut layer = EapLayer::new(DummyInnerLayer::new(false));
+
+        let _ = layer.start(&mut env);
+        assert_eq!(layer.next_deadline(), Some(60_000));
+
+        now.set(1_000);
+        let _ = layer.receive(&request(5, &[1, 0]), &mut env);
+        assert_eq!(layer.next_deadline(), Some(61_000));
+
+        now.set(60_999);
+        assert_output(layer.timeout(&mut env), EapOutput::noop());
+        assert!(!layer.is_failed());
+
+        // A single expiry ends the conversation
+        now.set(61_000);
+        assert_output(
+            layer.timeout(&mut env),
+            EapOutput::failed(StateError::Timeout, None),
+        );
+        assert!(layer.is_failed());
+        assert_eq!(layer.next_deadline(), None);
+    }
+
+    /// Environment with small session limits
+    struct LimitedEnvironment {
+        inner: DefaultEnvironment,
+        round_trips: u16,
+        method_round_trips: u16,
+        bytes_received: u32,
+        session_duration: u64,
+    }
+
+    impl LimitedEnvironment {
+        fn new() -> Self {
+            let inner = DefaultEnvironment::new();
+            LimitedEnvironment {
+                round_trips: inner.max_round_trips(),
+                method_round_trips: inner.max_method_round_trips(),
+                bytes_received: inner.max_bytes_received(),
+                session_duration: inner.max_session_duration(),
+                inner,
+            }
+        }
+    }
+
+    impl EapEnvironment for LimitedEnvironment {
+        fn set_name(&mut self, name: &[u8]) {
+            self.inner.set_name(name)
+        }
+
+        fn name(&self) -> Option<&[u8]> {
+            self.inner.name()
+        }
+
+        fn max_round_trips(&self) -> u16 {
+            self.round_trips
+        }
+
+        fn max_method_round_trips(&self) -> u16 {
+            self.method_round_trips
+        }
+
+        fn max_bytes_received(&self) -> u32 {
+            self.bytes_received
+        }
+
+        fn max_session_duration(&self) -> u64 {
+            self.session_duration
+        }
+
+        fn now(&self) -> Option<u64> {
+            self.inner.now()
+        }
+
+        fn fill_random(&self, buf: &mut [u8]) {
+            buf.fill(42)
+        }
+
+        fn response_buffer_state(&mut self) -> &mut crate::ResponseBufferState {
+            self.inner.response_buffer_state()
+        }
+
+        fn response_buffer_mut(&mut self) -> &mut [u8] {
+            self.inner.response_buffer_mut()
+        }
+
+        fn response_buffer(&self) -> &[u8] {
+            self.inner.response_buffer()
+        }
+    }
+
+    #[test]
+    /// The authenticator ends an endless conversation with an EAP-Failure
+    fn session_limit_round_trips() {
+        let mut env = LimitedEnvironment::new();
+        env.round_trips = 3;
+        let mut layer = EapLayer::new(DummyInnerLayer::new(true));
+        let response = |id| Message::new(MessageCode::Response, id, &[0, 0]).to_vec();
+
+        let _ = layer.start(&mut env);
+        for id in 42..45 {
+            let output = layer.receive(&response(id), &mut env);
+            assert_eq!(output.status, EapStatus::Ok);
+        }
+        assert_output(
+            layer.receive(&response(45), &mut env),
+            EapOutput::failed(
+                StateError::LimitExceeded(SessionLimit::RoundTrips),
+                Some(Message::new(MessageCode::Failure, 45, &[]).into()),
+            ),
+        );
+        assert!(layer.is_failed());
+    }
+
+    #[test]
+    /// Round trips per method restart with a new method, duplicates do not
+    /// count
+    fn session_limit_method_round_trips() {
+        let mut env = LimitedEnvironment::new();
+        env.method_round_trips = 2;
+        let mut layer = EapLayer::new(DummyInnerLayer::new(false));
+        let _ = layer.start(&mut env);
+
+        for (id, method) in [(1, 1), (1, 1), (2, 1), (3, 2), (4, 2)] {
+            let output = layer.receive(&request(id, &[method, 0]), &mut env);
+            assert_eq!(output.status, EapStatus::Ok);
+        }
+        assert_output(
+            layer.receive(&request(5, &[2, 0]), &mut env),
+            EapOutput::failed(
+                StateError::LimitExceeded(SessionLimit::MethodRoundTrips),
+                None,
+            ),
+        );
+    }
+
+    #[test]
+    /// Invalid messages count towards the bytes received
+    fn session_limit_bytes_received() {
+        let mut env = LimitedEnvironment::new();
+        env.bytes_received = 10;
+        let mut layer = EapLayer::new(DummyInnerLayer::new(false));
+        let _ = layer.start(&mut env);
+
+        assert_output(layer.receive(&[0; 8], &mut env), EapOutput::noop());
+        assert_output(
+            layer.receive(&request(1, &[1, 0]), &mut env),
+            EapOutput::failed(StateError::LimitExceeded(SessionLimit::BytesReceived), None),
+        );
+    }
+
+    #[test]
+    /// The deadline never lies beyond the end of the session
+    fn session_limit_duration() {
+        let (inner, now) = clocked_env();
+        let mut env = LimitedEnvironment::new();
+        env.inner = inner;
+        env.session_duration = 5_000;
+        let mut layer = EapLayer::new(DummyInnerLayer::new(true));
+
+        let _ = layer.start(&mut env);
+        assert_eq!(layer.next_deadline(), Some(3_000));
+
+        now.set(2_000);
+        let _ = layer.receive(
+            &Message::new(MessageCode::Response, 42, &[0, 0]).to_vec(),
+            &mut env,
+        );
+        assert_eq!(layer.next_deadline(), Some(5_000));
+
+        now.set(5_000);
+        assert_output(
+            layer.timeout(&mut env),
+            EapOutput::failed(
+                StateError::LimitExceeded(SessionLimit::Duration),
+                Some(Message::new(MessageCode::Failure, 43, &[]).into()),
+            ),
+        );
+    }
+
+    #[test]
+    /// eapRestart leads back to INITIALIZE
+    fn rfc4137_restart() {
+        let mut env = StaticEnvironment::<1020>::new(|buf| buf.fill(42));
+        let mut layer = EapLayer::new(DummyInnerLayer::new(true));
+        layer.layer_mut().finish_after = Some(1);
+
+        let _ = layer.start(&mut env);
+        let response = Message::new(MessageCode::Response, 42, &[0, 0]).to_vec();
+        let _ = layer.receive(&response, &mut env);
+        assert!(layer.interface().eap_success);
+
+        let output = layer.restart(&mut env);
+        assert_eq!(output.message.unwrap().as_ref(), &[1, 42, 0, 6, 0, 1]);
+        assert!(!layer.interface().eap_success);
+        assert!(layer.interface().port_enabled && layer.interface().eap_req);
     }
 }
@@ -0,0 +1,143 @@
+use crate::environment::MessageBuilder;
+
+pub const TYPE_IDENTITY: u8 = 1;
+pub const TYPE_NOTIFICATION: u8 = 2;
+pub const TYPE_NAK: u8 = 3;
+pub const TYPE_EXPANDED: u8 = 254;
+
+/// Length of an Expanded Type: the Type, a 3 byte Vendor-Id and a 4 byte
+/// Vendor-Type
+pub const EXPANDED_TYPE_LEN: usize = 8;
+
+/// Type field of an EAP Request or Response (RFC 3748 5.7).
+///
+/// Vendor-specific methods use Expanded Types, with the SMI Private
+/// Enterprise Code of the vendor as Vendor-Id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MethodType {
+    Legacy(u8),
+    Expanded { vendor_id: u32, vendor_type: u32 },
+}
+
+impl MethodType {
+    /// Expanded Nak, the answer to an Expanded Type the peer does not support
+    pub const EXPANDED_NAK: MethodType = MethodType::Expanded {
+        vendor_id: 0,
+        vendor_type: TYPE_NAK as u32,
+    };
+
+    pub fn is_expanded(&self) -> bool {
+        matches!(self, MethodType::Expanded { .. })
+    }
+
+    /// Splits the type off the data of a Request or Response
+    pub fn parse(data: &[u8]) -> Option<(MethodType, &[u8])> {
+        match *data.first()? {
+            TYPE_EXPANDED => {
+                let entry = data.get(..EXPANDED_TYPE_LEN)?;
+                let vendor_id = u32::from_be_bytes([0, entry[1], entry[2], entry[3]]);
+                let vendor_type = u32::from_be_bytes([entry[4], entry[5], entry[6], entry[7]]);
+                let method = MethodType::Expanded {
+                    vendor_id,
+                    vendor_type,
+                };
+                Some((method, &data[EXPANDED_TYPE_LEN..]))
+            }
+            legacy => Some((MethodType::Legacy(legacy), &data[1..])),
+        }
+    }
+
+    /// Reads an entry of an Expanded Nak, Vendor-Id 0 refers to the legacy
+    /// type space
+    pub fn parse_expanded_entry(entry: &[u8]) -> Option<MethodType> {
+        match MethodType::parse(entry)? {
+            (
+                MethodType::Expanded {
+                    vendor_id: 0,
+                    vendor_type,
+                },
+                _,
+            ) if vendor_type < TYPE_EXPANDED as u32 => Some(MethodType::Legacy(vendor_type as u8)),
+            (method @ MethodType::Expanded { .. }, _) => Some(method),
+            (MethodType::Legacy(_), _) => None,
+        }
+    }
+
+    /// The type in expanded form, as listed in an Expanded Nak
+    pub fn expanded_entry(&self) -> [u8; EXPANDED_TYPE_LEN] {
+        let (vendor_id, vendor_type) = match *self {
+            MethodType::Legacy(legacy) => (0, legacy as u32),
+            MethodType::Expanded {
+                vendor_id,
+                vendor_type,
+            } => (vendor_id, vendor_type),
+        };
+
+        let mut entry = [0u8; EXPANDED_TYPE_LEN];
+        entry[0] = TYPE_EXPANDED;
+        entry[1..4].copy_from_slice(&vendor_id.to_be_bytes()[1..]);
+        entry[4..].copy_from_slice(&vendor_type.to_be_bytes());
+        entry
+    }
+
+    /// Puts the type in front of the method data
+    pub fn prepend_to<'a>(&self, msg: MessageBuilder<'a>) -> MessageBuilder<'a> {
+        match *self {
+            MethodType::Legacy(legacy) => msg.prepend(&[legacy]),
+            MethodType::Expanded { .. } => msg.prepend(&self.expanded_entry()),
+        }
+    }
+}
+
+impl From<u8> for MethodType {
+    fn from(legacy: u8) -> Self {
+        MethodType::Legacy(legacy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            MethodType::parse(b"\x04abc"),
+            Some((MethodType::Legacy(4), &b"abc"[..]))
+        );
+        assert_eq!(
+            MethodType::parse(b"\xfe\x00\x37\x2a\x00\x00\x00\x01abc"),
+            Some((
+                MethodType::Expanded {
+                    vendor_id: 0x372a,
+                    vendor_type: 1
+                },
+                &b"abc"[..]
+            ))
+        );
+        assert_eq!(MethodType::parse(b"\xfe\x00\x37\x2a"), None);
+        assert_eq!(MethodType::parse(b""), None);
+    }
+
+    #[test]
+    fn test_expanded_entry() {
+        let wsc = MethodType::Expanded {
+            vendor_id: 0x372a,
+            vendor_type: 1,
+        };
+        assert_eq!(wsc.expanded_entry(), *b"\xfe\x00\x37\x2a\x00\x00\x00\x01");
+        assert_eq!(
+            MethodType::parse_expanded_entry(&wsc.expanded_entry()),
+            Some(wsc)
+        );
+
+        let md5 = MethodType::Legacy(4);
+        assert_eq!(md5.expanded_entry(), *b"\xfe\x00\x00\x00\x00\x00\x00\x04");
+        assert_eq!(
+            MethodType::parse_expanded_entry(&md5.expanded_entry()),
+            Some(md5)
+        );
+
+        assert_eq!(MethodType::parse_expanded_entry(b"\x04"), None);
+    }
+}
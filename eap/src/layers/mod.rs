@@ -4,4 +4,11 @@ pub mod auth;
 pub use auth::AuthLayer;
 pub mod peer;
 pub use peer::PeerLayer;
+pub mod claims;
+pub use claims::SecurityClaims;
+pub mod method_type;
 pub mod mux;
+pub mod negotiation;
+pub use negotiation::{MethodPreference, Negotiation, PreferenceOrder};
+mod timers;
+pub use method_type::MethodType;
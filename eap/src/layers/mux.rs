@@ -1,10 +1,15 @@
 #[cfg(not(feature = "std"))]
 use core as std;
 
+use super::MethodType;
+
 pub trait TupleAppend<X> {
     type Output;
     fn append(self, t: X) -> Self::Output;
     fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 impl<X> TupleAppend<X> for () {
@@ -21,19 +26,19 @@ impl<X> TupleAppend<X> for () {
 pub trait TupleElement {
     type Target: ?Sized;
 
-    fn id(&self) -> u8;
+    fn id(&self) -> MethodType;
     fn get(&self) -> &Self::Target;
     fn get_mut(&mut self) -> &mut Self::Target;
 }
 
 pub trait TupleById<Target: ?Sized> {
-    fn id_to_idx(&self, id: u8) -> Option<usize>;
+    fn id_to_idx(&self, id: MethodType) -> Option<usize>;
 
-    fn get_by_id(&self, id: u8) -> Option<&Target> {
+    fn get_by_id(&self, id: MethodType) -> Option<&Target> {
         self.id_to_idx(id).and_then(|idx| self.get_by_pos(idx))
     }
 
-    fn get_by_id_mut(&mut self, id: u8) -> Option<&mut Target> {
+    fn get_by_id_mut(&mut self, id: MethodType) -> Option<&mut Target> {
         self.id_to_idx(id).and_then(|idx| self.get_by_pos_mut(idx))
     }
 
@@ -42,12 +47,16 @@ pub trait TupleById<Target: ?Sized> {
     fn get_by_pos_mut(&mut self, idx: usize) -> Option<&mut Target>;
 
     // Note: This can not be done via standard iterator, because of target type.
-    fn iter(&self) -> TupleByIdIterator<Self, Target>;
+    fn iter(&self) -> TupleByIdIterator<'_, Self, Target>;
 
     fn len(&self) -> usize {
         self.iter().count()
     }
 
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     fn first(&self) -> &Target {
         self.get_by_pos(0).unwrap()
     }
@@ -104,7 +113,7 @@ macro_rules! tuple_impl {
         where
             $($n: TupleElement<Target = Target>,)+
         {
-            fn id_to_idx(&self, id: u8) -> Option<usize> {
+            fn id_to_idx(&self, id: MethodType) -> Option<usize> {
                 let ($($n),+,) = self;
                 let counter = 0;
                 $(
@@ -146,7 +155,7 @@ macro_rules! tuple_impl {
                 None
             }
 
-            fn iter(&self) -> TupleByIdIterator<Self, Target> {
+            fn iter(&self) -> TupleByIdIterator<'_, Self, Target> {
                 TupleByIdIterator {
                     idx: 0,
                     inner: self,
@@ -194,8 +203,8 @@ mod tests {
     impl TupleElement for A {
         type Target = dyn MyTrait;
 
-        fn id(&self) -> u8 {
-            10
+        fn id(&self) -> MethodType {
+            MethodType::Legacy(10)
         }
 
         fn get(&self) -> &Self::Target {
@@ -210,8 +219,11 @@ mod tests {
     impl TupleElement for B {
         type Target = dyn MyTrait;
 
-        fn id(&self) -> u8 {
-            20
+        fn id(&self) -> MethodType {
+            MethodType::Expanded {
+                vendor_id: 20,
+                vendor_type: 1,
+            }
         }
 
         fn get(&self) -> &Self::Target {
@@ -234,13 +246,18 @@ mod tests {
     fn test_tuple_by_id() {
         let mut t = (A, B);
 
-        let a = t.get_by_id_mut(10).unwrap();
+        let a = t.get_by_id_mut(MethodType::Legacy(10)).unwrap();
         assert_eq!(a.say_my_name(), "A");
 
-        let b = t.get_by_id_mut(20).unwrap();
+        let b = t
+            .get_by_id_mut(MethodType::Expanded {
+                vendor_id: 20,
+                vendor_type: 1,
+            })
+            .unwrap();
         assert_eq!(b.say_my_name(), "B");
 
-        let c = t.get_by_id_mut(0);
+        let c = t.get_by_id_mut(MethodType::Legacy(20));
         assert!(c.is_none());
     }
 
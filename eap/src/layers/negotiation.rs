@@ -0,0 +1,108 @@
+use super::{method_type::TYPE_IDENTITY, MethodType};
+
+/// Capacity of a [`MethodPreference`]
+pub const MAX_PREFERENCES: usize = 8;
+
+/// Whose order of preference decides between methods both sides support.
+/// Both sides should be configured alike.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PreferenceOrder {
+    /// The authenticator picks from a Nak by its own preference, the peer
+    /// accepts every supported method
+    #[default]
+    Authenticator,
+    /// The peer Naks the first method unless it is ranked at or above the
+    /// threshold of [`MethodPreference::nak_below`], the authenticator picks
+    /// the first supported method of the Nak
+    Peer,
+}
+
+/// Ordered list of preferred methods, see
+/// [`AuthLayer::with_preference`](super::AuthLayer::with_preference) and
+/// [`PeerLayer::with_preference`](super::PeerLayer::with_preference).
+///
+/// Listed methods come first, in the given order, followed by the other
+/// configured methods in the order they were added. Identity always comes
+/// first.
+#[derive(Debug, Clone, Default)]
+pub struct MethodPreference {
+    methods: [Option<MethodType>; MAX_PREFERENCES],
+    order: PreferenceOrder,
+    nak_below: Option<MethodType>,
+}
+
+impl MethodPreference {
+    /// Order of configuration, the authenticator's order wins
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `method`, it is preferred over all methods listed later
+    pub fn prefer(mut self, method: MethodType) -> Self {
+        let free = self
+            .methods
+            .iter_mut()
+            .find(|entry| entry.is_none())
+            .expect("too many preferred methods");
+        *free = Some(method);
+        self
+    }
+
+    pub fn order(mut self, order: PreferenceOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn preference_order(&self) -> PreferenceOrder {
+        self.order
+    }
+
+    /// With [`PreferenceOrder::Peer`], the peer accepts a first offer ranked
+    /// at or above `method` and Naks lower ranked ones. Without a threshold,
+    /// or if `method` is not a candidate, only the favourite is accepted.
+    pub fn nak_below(mut self, method: MethodType) -> Self {
+        self.nak_below = Some(method);
+        self
+    }
+
+    pub fn nak_threshold(&self) -> Option<MethodType> {
+        self.nak_below
+    }
+
+    /// Lower is better, `position` is the place of `method` in the
+    /// configuration
+    pub fn rank(&self, method: MethodType, position: usize) -> usize {
+        if method == MethodType::Legacy(TYPE_IDENTITY) {
+            return 0;
+        }
+
+        match self.methods.iter().position(|entry| *entry == Some(method)) {
+            Some(index) => 1 + index,
+            None => 1 + MAX_PREFERENCES + position,
+        }
+    }
+}
+
+/// Outcome of the method negotiation, for logging
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Negotiation {
+    /// First method the authenticator requested after Identity
+    pub proposed: MethodType,
+    /// Method that runs, `None` if the sides have no method in common
+    pub selected: Option<MethodType>,
+    /// The peer answered with a Nak
+    pub nak: bool,
+    /// Whose order decided
+    pub order: PreferenceOrder,
+}
+
+impl Negotiation {
+    pub(crate) fn accepted(method: MethodType, order: PreferenceOrder) -> Self {
+        Negotiation {
+            proposed: method,
+            selected: Some(method),
+            nak: false,
+            order,
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use super::token::PeerTokenMethod;
+
+const METHOD_GTC: u8 = 6;
+
+/// EAP-GTC (RFC 3748 5.6), the token is requested each time the
+/// authenticator sends a prompt.
+pub type PeerGtcMethod<F> = PeerTokenMethod<F, METHOD_GTC>;
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta};
+    use crate::layers::MethodType;
+    use crate::util::OwnedSlice;
+
+    #[test]
+    fn peer_gtc_method() {
+        let mut env = crate::DefaultEnvironment::new();
+        let mut method = PeerGtcMethod::new(|prompt: &[u8]| {
+            assert_eq!(prompt, b"Token:");
+            Some(OwnedSlice::from(b"123456"))
+        });
+        assert_eq!(method.method_identifier(), MethodType::Legacy(METHOD_GTC));
+
+        let m = crate::message::Message::new(crate::message::MessageCode::Request, 0, b"");
+        assert!(matches!(
+            method.recv(b"Token:", &RecvMeta { message: m }, &mut env),
+            PeerMethodLayerResult::Send(response) if response.slice() == b"123456",
+        ));
+    }
+
+    #[test]
+    fn peer_gtc_method_abort() {
+        let mut env = crate::DefaultEnvironment::new();
+        let mut method = PeerGtcMethod::new(|_: &[u8]| None);
+
+        let m = crate::message::Message::new(crate::message::MessageCode::Request, 0, b"");
+        assert!(matches!(
+            method.recv(b"Token:", &RecvMeta { message: m }, &mut env),
+            PeerMethodLayerResult::Failed(_),
+        ));
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{layers::mux::TupleElement, util::OwnedSlice, EapEnvironment, EapEnvironmentResponse};
+use crate::{
+    layers::{mux::TupleElement, MethodType},
+    util::OwnedSlice,
+    EapEnvironment, EapEnvironmentResponse,
+};
 
 use super::super::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta};
 
@@ -18,7 +22,7 @@ impl PeerIdentityMethod {
 
 impl TupleElement for PeerIdentityMethod {
     type Target = dyn PeerMethodLayer;
-    fn id(&self) -> u8 {
+    fn id(&self) -> MethodType {
         self.method_identifier()
     }
 
@@ -32,8 +36,8 @@ impl TupleElement for PeerIdentityMethod {
 }
 
 impl PeerMethodLayer for PeerIdentityMethod {
-    fn method_identifier(&self) -> u8 {
-        1
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(1)
     }
 
     fn recv<'a>(
@@ -66,7 +70,7 @@ mod tests {
         let mut env = crate::DefaultEnvironment::new();
 
         let mut method = PeerIdentityMethod::new(b"bob");
-        assert_eq!(method.method_identifier(), 1);
+        assert_eq!(method.method_identifier(), MethodType::Legacy(1));
 
         let m = Message::new(crate::message::MessageCode::Response, 0, b"");
 
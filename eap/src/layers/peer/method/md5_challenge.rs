@@ -1,4 +1,12 @@
-use crate::{layers::mux::TupleElement, util::OwnedSlice, EapEnvironmentResponse};
+use crate::{
+    layers::{
+        eap_layer::{Decision, MethodState},
+        mux::TupleElement,
+        MethodType,
+    },
+    util::OwnedSlice,
+    EapEnvironmentResponse,
+};
 
 use super::super::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta};
 
@@ -18,7 +26,7 @@ impl PeerMD5ChallengeMethod {
 
 impl TupleElement for PeerMD5ChallengeMethod {
     type Target = dyn PeerMethodLayer;
-    fn id(&self) -> u8 {
+    fn id(&self) -> MethodType {
         self.method_identifier()
     }
 
@@ -32,12 +40,17 @@ impl TupleElement for PeerMD5ChallengeMethod {
 }
 
 impl PeerMethodLayer for PeerMD5ChallengeMethod {
-    fn method_identifier(&self) -> u8 {
-        4
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(4)
     }
 
-    fn can_succeed(&self) -> Option<bool> {
-        Some(true)
+    /// The method ends with the single response
+    fn method_state(&self) -> MethodState {
+        MethodState::Done
+    }
+
+    fn decision(&self) -> Decision {
+        Decision::CondSucc
     }
 
     fn recv<'a>(
@@ -78,7 +91,7 @@ mod tests {
         let mut env = crate::DefaultEnvironment::new();
         let mut method = PeerMD5ChallengeMethod::new(&password);
 
-        assert_eq!(method.method_identifier(), 4);
+        assert_eq!(method.method_identifier(), MethodType::Legacy(4));
 
         let data = crate::util::hex_to_vec("10 c7 24 0a c7 c5 94 58 ee 8f 7a 98 35 6c 61 53 f0");
         let m = crate::message::Message::new(crate::message::MessageCode::Response, 0x69, &data);
@@ -1,2 +1,6 @@
+pub mod gtc;
 pub mod identity;
 pub mod md5_challenge;
+pub mod mschapv2;
+pub mod otp;
+pub mod token;
@@ -0,0 +1,160 @@
+use crate::{
+    layers::{
+        eap_layer::{Decision, MethodState, SessionKeys},
+        mux::TupleElement,
+        MethodType, SecurityClaims,
+    },
+    mschapv2::*,
+    util::{constant_time_eq, OwnedSlice},
+    EapEnvironmentResponse,
+};
+
+use super::super::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta};
+
+/// EAP-MSCHAPv2 (draft-kamath-pppext-eap-mschapv2). Success is only accepted once
+/// the Authenticator Response of the server has been verified.
+#[derive(Clone)]
+pub struct PeerMschapv2Method {
+    username: OwnedSlice<64>,
+    password: OwnedSlice<64>,
+    expected_response: Option<[u8; AUTHENTICATOR_RESPONSE_LEN]>,
+    keys: Option<SessionKeys>,
+    verified: bool,
+}
+
+impl PeerMschapv2Method {
+    pub fn new(username: &[u8], password: &[u8]) -> Self {
+        Self {
+            username: username.try_into().expect("username too long for nostd"),
+            password: password.try_into().expect("password too long for nostd"),
+            expected_response: None,
+            keys: None,
+            verified: false,
+        }
+    }
+}
+
+impl TupleElement for PeerMschapv2Method {
+    type Target = dyn PeerMethodLayer;
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl PeerMethodLayer for PeerMschapv2Method {
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD_MSCHAPV2)
+    }
+
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::EAP_MSCHAPV2
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn crate::EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        if msg.len() < HEADER_LEN {
+            return PeerMethodLayerResult::Failed(env);
+        }
+        let identifier = msg[1];
+
+        match msg[0] {
+            OP_CHALLENGE => {
+                if msg.len() < HEADER_LEN + 1 + CHALLENGE_LEN
+                    || msg[HEADER_LEN] as usize != CHALLENGE_LEN
+                {
+                    return PeerMethodLayerResult::Failed(env);
+                }
+                let challenge: [u8; CHALLENGE_LEN] =
+                    msg[HEADER_LEN + 1..][..CHALLENGE_LEN].try_into().unwrap();
+
+                let mut peer_challenge = [0u8; CHALLENGE_LEN];
+                env.fill_random(&mut peer_challenge);
+
+                let username = self.username.as_ref();
+                let password = self.password.as_ref();
+                let nt_response =
+                    generate_nt_response(&challenge, &peer_challenge, username, password);
+
+                self.expected_response = Some(authenticator_response(
+                    password,
+                    &nt_response,
+                    &peer_challenge,
+                    &challenge,
+                    username,
+                ));
+                self.keys = Some(session_keys(password, &nt_response));
+                self.verified = false;
+
+                let length =
+                    ((HEADER_LEN + 1 + RESPONSE_LEN + username.len()) as u16).to_be_bytes();
+                PeerMethodLayerResult::Send(
+                    env.respond()
+                        .write(&[OP_RESPONSE, identifier, length[0], length[1]])
+                        .write(&[RESPONSE_LEN as u8])
+                        .write(&peer_challenge)
+                        .write(&[0; 8])
+                        .write(&nt_response)
+                        .write(&[0])
+                        .write(username),
+                )
+            }
+            OP_SUCCESS => {
+                let message = &msg[HEADER_LEN..];
+                let authentic = self.expected_response.is_some_and(|expected| {
+                    message
+                        .get(..expected.len())
+                        .is_some_and(|received| constant_time_eq(received, &expected))
+                });
+                if authentic {
+                    self.verified = true;
+                    PeerMethodLayerResult::Send(env.respond().write(&[OP_SUCCESS]))
+                } else {
+                    PeerMethodLayerResult::Failed(env)
+                }
+            }
+            OP_FAILURE => {
+                self.verified = false;
+                PeerMethodLayerResult::Send(env.respond().write(&[OP_FAILURE]))
+            }
+            _ => PeerMethodLayerResult::Failed(env),
+        }
+    }
+
+    fn method_state(&self) -> MethodState {
+        if self.verified {
+            MethodState::Done
+        } else {
+            MethodState::MayCont
+        }
+    }
+
+    fn decision(&self) -> Decision {
+        if self.verified {
+            Decision::CondSucc
+        } else {
+            Decision::Fail
+        }
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.keys.as_ref().filter(|_| self.verified)
+    }
+
+    fn reset(&mut self) {
+        self.expected_response = None;
+        self.keys = None;
+        self.verified = false;
+    }
+}
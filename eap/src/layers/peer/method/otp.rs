@@ -0,0 +1,41 @@
+use super::token::PeerTokenMethod;
+
+const METHOD_OTP: u8 = 5;
+
+/// EAP-OTP (RFC 3748 5.5), the one time password is requested each
+/// time the authenticator sends a challenge.
+pub type PeerOtpMethod<F> = PeerTokenMethod<F, METHOD_OTP>;
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::peer::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta};
+    use crate::layers::MethodType;
+    use crate::util::OwnedSlice;
+
+    #[test]
+    fn peer_otp_method() {
+        let mut env = crate::DefaultEnvironment::new();
+        let mut counter = 0;
+        let mut method = PeerOtpMethod::new(move |_: &[u8]| {
+            counter += 1;
+            Some(OwnedSlice::from(if counter == 1 {
+                b"755224"
+            } else {
+                b"287082"
+            }))
+        });
+        assert_eq!(method.method_identifier(), MethodType::Legacy(METHOD_OTP));
+
+        let m = crate::message::Message::new(crate::message::MessageCode::Request, 0, b"");
+        assert!(matches!(
+            method.recv(b"otp-hotp 0", &RecvMeta { message: m }, &mut env),
+            PeerMethodLayerResult::Send(response) if response.slice() == b"755224",
+        ));
+        assert!(matches!(
+            method.recv(b"otp-hotp 1", &RecvMeta { message: m }, &mut env),
+            PeerMethodLayerResult::Send(response) if response.slice() == b"287082",
+        ));
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{
+    layers::{
+        eap_layer::{Decision, MethodState},
+        mux::TupleElement,
+        MethodType,
+    },
+    util::OwnedSlice,
+    EapEnvironmentResponse,
+};
+
+use super::super::peer_layer::{PeerMethodLayer, PeerMethodLayerResult, RecvMeta};
+
+/// A method that answers each prompt with a single token, the common part of
+/// EAP-OTP and EAP-GTC, which only differ in their type `METHOD`. The token is
+/// requested from `token_source`, returning `None` aborts the authentication.
+#[derive(Clone)]
+pub struct PeerTokenMethod<F, const METHOD: u8> {
+    token_source: F,
+}
+
+impl<F, const METHOD: u8> PeerTokenMethod<F, METHOD>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<64>>,
+{
+    pub fn new(token_source: F) -> Self {
+        Self { token_source }
+    }
+}
+
+impl<F, const METHOD: u8> TupleElement for PeerTokenMethod<F, METHOD>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<64>> + 'static,
+{
+    type Target = dyn PeerMethodLayer;
+    fn id(&self) -> MethodType {
+        self.method_identifier()
+    }
+
+    fn get(&self) -> &Self::Target {
+        self
+    }
+
+    fn get_mut(&mut self) -> &mut Self::Target {
+        self
+    }
+}
+
+impl<F, const METHOD: u8> PeerMethodLayer for PeerTokenMethod<F, METHOD>
+where
+    F: FnMut(&[u8]) -> Option<OwnedSlice<64>>,
+{
+    fn method_identifier(&self) -> MethodType {
+        MethodType::Legacy(METHOD)
+    }
+
+    /// The method ends with the single response
+    fn method_state(&self) -> MethodState {
+        MethodState::Done
+    }
+
+    fn decision(&self) -> Decision {
+        Decision::CondSucc
+    }
+
+    fn recv<'a>(
+        &mut self,
+        msg: &[u8],
+        _meta: &RecvMeta,
+        env: &'a mut dyn crate::EapEnvironment,
+    ) -> PeerMethodLayerResult<'a> {
+        match (self.token_source)(msg) {
+            Some(token) => PeerMethodLayerResult::Send(env.respond().write(token.as_ref())),
+            None => PeerMethodLayerResult::Failed(env),
+        }
+    }
+}
@@ -1,6 +1,12 @@
 pub mod peer_layer;
 pub use peer_layer::PeerLayer;
+pub mod policy;
+pub use policy::PeerPolicy;
 
 pub mod method;
+pub use method::gtc::PeerGtcMethod;
 pub use method::identity::PeerIdentityMethod;
 pub use method::md5_challenge::PeerMD5ChallengeMethod;
+pub use method::mschapv2::PeerMschapv2Method;
+pub use method::otp::PeerOtpMethod;
+pub use method::token::PeerTokenMethod;
@@ -1,20 +1,33 @@
 use crate::{
-    layers::mux::{TupleAppend, TupleById, TupleElement},
+    layers::{
+        method_type::{TYPE_EXPANDED, TYPE_IDENTITY, TYPE_NOTIFICATION},
+        mux::{TupleAppend, TupleById, TupleElement},
+        MethodPreference, MethodType, Negotiation, PreferenceOrder, SecurityClaims,
+    },
     message::Message,
     EapEnvironment, EapEnvironmentResponse, MessageBuilder,
 };
 
-use crate::layers::eap_layer::{PeerAuthLayer, PeerAuthLayerResult};
+use crate::layers::eap_layer::{
+    Decision, MethodState, PeerAuthLayer, PeerAuthLayerResult, SessionKeys,
+};
 
-//////
-///
-/// TODO: Missing Feature:
-/// Make Server via EAP TLS a requirement for this
+use super::PeerPolicy;
 
 #[derive(Clone)]
 pub struct PeerLayer<I> {
-    next_layer: Option<u8>,
+    next_layer: Option<MethodType>,
     candidates: I,
+    policy: PeerPolicy,
+    downgrade_attempt: Option<MethodType>,
+    preference: MethodPreference,
+    negotiation: Option<Negotiation>,
+}
+
+impl Default for PeerLayer<()> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PeerLayer<()> {
@@ -23,6 +36,10 @@ impl PeerLayer<()> {
         Self {
             next_layer: None,
             candidates: (),
+            policy: PeerPolicy::new(),
+            downgrade_attempt: None,
+            preference: MethodPreference::new(),
+            negotiation: None,
         }
     }
 }
@@ -36,8 +53,35 @@ impl<I> PeerLayer<I> {
         PeerLayer {
             next_layer: None,
             candidates: self.candidates.append(candidate),
+            policy: self.policy,
+            downgrade_attempt: None,
+            preference: self.preference,
+            negotiation: None,
         }
     }
+
+    /// Refuses methods the policy does not permit, they are answered with a
+    /// Nak as if they were not configured
+    pub fn with_policy(self, policy: PeerPolicy) -> Self {
+        PeerLayer { policy, ..self }
+    }
+
+    /// The last configured method the authenticator requested although the
+    /// policy refuses it
+    pub fn downgrade_attempt(&self) -> Option<MethodType> {
+        self.downgrade_attempt
+    }
+
+    /// Orders the Nak, see [`MethodPreference`]
+    pub fn with_preference(self, preference: MethodPreference) -> Self {
+        PeerLayer { preference, ..self }
+    }
+
+    /// Result of the method negotiation, once a method other than Identity
+    /// was requested
+    pub fn negotiation(&self) -> Option<&Negotiation> {
+        self.negotiation.as_ref()
+    }
 }
 
 pub struct RecvMeta<'a> {
@@ -46,7 +90,7 @@ pub struct RecvMeta<'a> {
 
 pub trait PeerMethodLayer {
     /* */
-    fn method_identifier(&self) -> u8;
+    fn method_identifier(&self) -> MethodType;
     fn recv<'a>(
         &mut self,
         msg: &[u8],
@@ -58,7 +102,23 @@ pub trait PeerMethodLayer {
         true
     }
 
-    fn can_succeed(&self) -> Option<bool> {
+    /// Claims of the method, RFC 3748 section 7.2. Checked against the
+    /// [`PeerPolicy`](super::PeerPolicy).
+    fn security_claims(&self) -> SecurityClaims {
+        SecurityClaims::NONE
+    }
+
+    /// methodState after the last request, see [`MethodState`]
+    fn method_state(&self) -> MethodState {
+        MethodState::MayCont
+    }
+
+    /// decision after the last request, see [`Decision`]
+    fn decision(&self) -> Decision {
+        Decision::Fail
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
         None
     }
 
@@ -94,14 +154,24 @@ where
         true
     }
 
-    fn can_succeed(&mut self) -> bool {
-        for c in self.candidates.iter() {
-            if let Some(val) = c.can_succeed() {
-                return val;
-            }
-        }
+    fn method_state(&self) -> MethodState {
+        self.active_layer()
+            .map_or(MethodState::Init, |layer| layer.method_state())
+    }
+
+    fn decision(&self) -> Decision {
+        self.active_layer()
+            .map_or(Decision::Fail, |layer| layer.decision())
+    }
+
+    fn session_keys(&self) -> Option<&SessionKeys> {
+        self.active_layer().and_then(|layer| layer.session_keys())
+    }
 
-        false
+    fn reset(&mut self) {
+        self.next_layer = None;
+        self.downgrade_attempt = None;
+        self.negotiation = None;
     }
 
     fn start<'a>(&mut self, env: &'a mut dyn EapEnvironment) -> PeerAuthLayerResult<'a> {
@@ -114,38 +184,50 @@ where
         msg: &Message,
         env: &'a mut dyn EapEnvironment,
     ) -> PeerAuthLayerResult<'a> {
-        if msg.body.is_empty() {
+        let Some((method_identifier, data)) = MethodType::parse(msg.body) else {
             // Message Too Short
             return PeerAuthLayerResult::Failed(env);
+        };
+
+        if method_identifier == MethodType::Legacy(TYPE_NOTIFICATION) {
+            // Notifications may arrive at any time, the active method is not
+            // involved
+            env.notification(data);
+            return PeerAuthLayerResult::Send(env.respond().write(&[TYPE_NOTIFICATION]));
         }
 
-        let method_identifier = msg.body[0];
         if Some(method_identifier) != self.next_layer {
+            // Offers below the threshold are refused in favour of the peer's
+            // favourite, it may only ask once
+            let rank = |method| self.proposals().position(|m| m == method);
+            let threshold = self.preference.nak_threshold().and_then(rank).unwrap_or(0);
+            let insist = self.preference.preference_order() == PreferenceOrder::Peer
+                && method_identifier != MethodType::Legacy(TYPE_IDENTITY)
+                && !self.negotiation.is_some_and(|n| n.nak)
+                && self.proposals().next().is_some()
+                && rank(method_identifier).is_none_or(|rank| rank > threshold);
+
             // Find a candidate
             match self.candidates.get_by_id_mut(method_identifier) {
+                Some(c) if !self.policy.permits(method_identifier, &c.security_claims()) => {
+                    self.downgrade_attempt = Some(method_identifier);
+                    self.refuse(method_identifier, env)
+                }
+                Some(_) if insist => self.refuse(method_identifier, env),
                 Some(c) => {
                     c.reset();
                     self.next_layer = Some(method_identifier);
-                    let res = c.recv(&msg.body[1..], &RecvMeta { message: *msg }, env);
+                    self.accepted(method_identifier, env);
+                    let c = self.candidates.get_by_id_mut(method_identifier).unwrap();
+                    let res = c.recv(data, &RecvMeta { message: *msg }, env);
                     self.process_result(res)
                 }
-                None => {
-                    let mut message_builder = env.respond();
-                    message_builder = message_builder.write(&[METHOD_CLIENT_PROPOSAL]);
-
-                    for c in self.candidates.iter() {
-                        if c.selectable_by_nak() {
-                            message_builder = message_builder.write(&[c.method_identifier()]);
-                        }
-                    }
-
-                    PeerAuthLayerResult::Send(message_builder)
-                }
+                None => self.refuse(method_identifier, env),
             }
         } else {
             match self.candidates.get_by_id_mut(method_identifier) {
                 Some(c) => {
-                    let res = c.recv(&msg.body[1..], &RecvMeta { message: *msg }, env);
+                    let res = c.recv(data, &RecvMeta { message: *msg }, env);
                     self.process_result(res)
                 }
                 None => {
@@ -171,12 +253,127 @@ where
     }
 }
 
+impl<I> PeerLayer<I>
+where
+    I: TupleById<dyn PeerMethodLayer>,
+{
+    /// Configured methods with their security claims
+    pub fn security_claims(&self) -> impl Iterator<Item = (MethodType, SecurityClaims)> + '_ {
+        self.candidates
+            .iter()
+            .map(|c| (c.method_identifier(), c.security_claims()))
+    }
+
+    /// The method of the last request, only its state counts
+    fn active_layer(&self) -> Option<&dyn PeerMethodLayer> {
+        self.next_layer.and_then(|id| self.candidates.get_by_id(id))
+    }
+
+    /// Candidates selectable by Nak and permitted by the policy, in order of
+    /// preference. Identity is not an authentication method and never
+    /// proposed (RFC 3748 5.3.1).
+    fn proposals(&self) -> impl Iterator<Item = MethodType> + '_ {
+        let ranked = || {
+            self.candidates
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| c.selectable_by_nak())
+                .filter(|(_, c)| {
+                    self.policy
+                        .permits(c.method_identifier(), &c.security_claims())
+                })
+                .map(|(position, c)| {
+                    let method = c.method_identifier();
+                    (self.preference.rank(method, position), method)
+                })
+        };
+
+        let mut last = None;
+        core::iter::from_fn(move || {
+            let (rank, method) = ranked()
+                .filter(|(rank, _)| last.is_none_or(|last| *rank > last))
+                .min_by_key(|(rank, _)| *rank)?;
+            last = Some(rank);
+            Some(method)
+        })
+    }
+
+    /// Answers a request for `method` with a Nak
+    fn refuse<'a>(
+        &mut self,
+        method: MethodType,
+        env: &'a mut dyn EapEnvironment,
+    ) -> PeerAuthLayerResult<'a> {
+        let order = self.preference.preference_order();
+        let negotiation = self.negotiation.get_or_insert(Negotiation {
+            proposed: method,
+            selected: None,
+            nak: true,
+            order,
+        });
+        negotiation.selected = None;
+        negotiation.nak = true;
+
+        PeerAuthLayerResult::Send(self.nak(method.is_expanded(), env))
+    }
+
+    /// Settles the negotiation on `method` unless it is Identity
+    fn accepted(&mut self, method: MethodType, env: &mut dyn EapEnvironment) {
+        if method == MethodType::Legacy(TYPE_IDENTITY) {
+            return;
+        }
+        let order = self.preference.preference_order();
+        let negotiation = self
+            .negotiation
+            .get_or_insert(Negotiation::accepted(method, order));
+        negotiation.selected = Some(method);
+        env.negotiation(negotiation);
+    }
+
+    /// Proposes the candidates in order of preference. A Request of an
+    /// Expanded Type is answered by an Expanded Nak, which lists all of them
+    /// in expanded form. A legacy Nak lists 254 in place of the first
+    /// Expanded Type.
+    fn nak<'a>(&self, expanded: bool, env: &'a mut dyn EapEnvironment) -> MessageBuilder<'a> {
+        let mut message_builder = env.respond();
+
+        if expanded {
+            message_builder = message_builder.write(&MethodType::EXPANDED_NAK.expanded_entry());
+            for method in self.proposals() {
+                message_builder = message_builder.write(&method.expanded_entry());
+            }
+        } else {
+            message_builder = message_builder.write(&[METHOD_CLIENT_PROPOSAL]);
+            let mut expanded_listed = false;
+            for method in self.proposals() {
+                match method {
+                    MethodType::Legacy(method) => {
+                        message_builder = message_builder.write(&[method]);
+                    }
+                    MethodType::Expanded { .. } if !expanded_listed => {
+                        expanded_listed = true;
+                        message_builder = message_builder.write(&[TYPE_EXPANDED]);
+                    }
+                    MethodType::Expanded { .. } => {}
+                }
+            }
+        }
+
+        // Type 0 stands for no alternative
+        match (self.proposals().next(), expanded) {
+            (Some(_), _) => message_builder,
+            (None, true) => message_builder.write(&MethodType::Legacy(0).expanded_entry()),
+            (None, false) => message_builder.write(&[0]),
+        }
+    }
+}
+
 impl<I> PeerLayer<I> {
     fn process_result<'a>(&mut self, res: PeerMethodLayerResult<'a>) -> PeerAuthLayerResult<'a> {
         match res {
             PeerMethodLayerResult::Noop(env) => PeerAuthLayerResult::Noop(env),
             PeerMethodLayerResult::Send(data) => {
-                PeerAuthLayerResult::Send(data.prepend(&[self.next_layer.unwrap()]))
+                PeerAuthLayerResult::Send(self.next_layer.unwrap().prepend_to(data))
             }
             PeerMethodLayerResult::Failed(env) => PeerAuthLayerResult::Failed(env),
         }
@@ -200,12 +397,12 @@ mod tests {
 
     #[derive(Clone)]
     struct DummyProtocol {
-        method_identifier: u8,
+        method_identifier: MethodType,
         events: Vec<DummyEvent>,
     }
 
     impl PeerMethodLayer for DummyProtocol {
-        fn method_identifier(&self) -> u8 {
+        fn method_identifier(&self) -> MethodType {
             self.method_identifier
         }
 
@@ -223,13 +420,28 @@ mod tests {
         }
 
         fn selectable_by_nak(&self) -> bool {
-            self.method_identifier != 1
+            self.method_identifier != MethodType::Legacy(1)
+        }
+
+        // Done once all events are used up
+        fn method_state(&self) -> MethodState {
+            match self.events.is_empty() {
+                true => MethodState::Done,
+                false => MethodState::MayCont,
+            }
+        }
+
+        fn decision(&self) -> Decision {
+            match self.events.is_empty() {
+                true => Decision::CondSucc,
+                false => Decision::Fail,
+            }
         }
     }
 
     impl TupleElement for DummyProtocol {
         type Target = dyn PeerMethodLayer;
-        fn id(&self) -> u8 {
+        fn id(&self) -> MethodType {
             self.method_identifier()
         }
 
@@ -245,7 +457,7 @@ mod tests {
     #[test]
     fn peer_is_peer() {
         let layer = PeerLayer::new().with(DummyProtocol {
-            method_identifier: 1,
+            method_identifier: MethodType::Legacy(1),
             events: vec![],
         });
         assert!(layer.is_peer());
@@ -258,15 +470,15 @@ mod tests {
 
         let mut layer = PeerLayer::new()
             .with(DummyProtocol {
-                method_identifier: 1,
+                method_identifier: MethodType::Legacy(1),
                 events: vec![DummyEvent::Send(b"Bob".to_vec()), DummyEvent::Failed],
             })
             .with(DummyProtocol {
-                method_identifier: 4,
+                method_identifier: MethodType::Legacy(4),
                 events: vec![DummyEvent::Send(b"Ok".to_vec())],
             })
             .with(DummyProtocol {
-                method_identifier: 2,
+                method_identifier: MethodType::Legacy(5),
                 events: vec![DummyEvent::Failed],
             });
 
@@ -285,15 +497,15 @@ mod tests {
         // request non existent protocol 6
         assert_eq!(
             layer.recv(&Message::new(MessageCode::Request, 0, b"\x06"), &mut env),
-            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x03\x04\x02".as_slice()))
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x03\x04\x05".as_slice()))
         );
 
         // Alternative Reality
         {
             let mut layer = layer.clone();
-            // Request protocol 2
+            // Request protocol 5
             assert_eq!(
-                layer.recv(&Message::new(MessageCode::Request, 0, b"\x02"), &mut env),
+                layer.recv(&Message::new(MessageCode::Request, 0, b"\x05"), &mut env),
                 PeerAuthLayerResult::Failed(&mut DefaultEnvironment::new())
             );
         }
@@ -304,4 +516,271 @@ mod tests {
             PeerAuthLayerResult::Send(MessageBuilder::from(b"\x04Ok".as_slice()))
         );
     }
+
+    #[test]
+    fn test_decision_of_active_method() {
+        let mut env = DefaultEnvironment::new();
+
+        let mut layer = PeerLayer::new()
+            .with(DummyProtocol {
+                method_identifier: MethodType::Legacy(4),
+                events: vec![],
+            })
+            .with(DummyProtocol {
+                method_identifier: MethodType::Legacy(5),
+                events: vec![
+                    DummyEvent::Send(b"1".to_vec()),
+                    DummyEvent::Send(b"2".to_vec()),
+                ],
+            });
+        assert_eq!(layer.method_state(), MethodState::Init);
+        assert_eq!(layer.decision(), Decision::Fail);
+
+        // Method 4 would agree to success, but it never ran
+        let _ = layer.recv(&Message::new(MessageCode::Request, 0, b"\x05"), &mut env);
+        assert_eq!(layer.method_state(), MethodState::MayCont);
+        assert_eq!(layer.decision(), Decision::Fail);
+
+        let _ = layer.recv(&Message::new(MessageCode::Request, 1, b"\x05"), &mut env);
+        assert_eq!(layer.method_state(), MethodState::Done);
+        assert_eq!(layer.decision(), Decision::CondSucc);
+    }
+
+    #[test]
+    fn test_expanded() {
+        let mut env = DefaultEnvironment::new();
+        let vendor_method = MethodType::Expanded {
+            vendor_id: 0x1234,
+            vendor_type: 7,
+        };
+
+        let mut layer = PeerLayer::new()
+            .with(DummyProtocol {
+                method_identifier: MethodType::Legacy(1),
+                events: vec![],
+            })
+            .with(DummyProtocol {
+                method_identifier: MethodType::Legacy(4),
+                events: vec![],
+            })
+            .with(DummyProtocol {
+                method_identifier: vendor_method,
+                events: vec![DummyEvent::Send(b"Ok".to_vec())],
+            });
+
+        // A legacy NAK signals support for Expanded Types
+        assert_eq!(
+            layer.recv(&Message::new(MessageCode::Request, 0, b"\x06"), &mut env),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x03\x04\xfe".as_slice()))
+        );
+
+        // An unknown Expanded Type is answered by an Expanded NAK
+        assert_eq!(
+            layer.recv(
+                &Message::new(MessageCode::Request, 0, b"\xfe\x00\x12\x34\x00\x00\x00\x08"),
+                &mut env
+            ),
+            PeerAuthLayerResult::Send(MessageBuilder::from(
+                b"\xfe\x00\x00\x00\x00\x00\x00\x03\
+                  \xfe\x00\x00\x00\x00\x00\x00\x04\
+                  \xfe\x00\x12\x34\x00\x00\x00\x07"
+                    .as_slice()
+            ))
+        );
+
+        // The response repeats the Expanded Type
+        assert_eq!(
+            layer.recv(
+                &Message::new(MessageCode::Request, 0, b"\xfe\x00\x12\x34\x00\x00\x00\x07"),
+                &mut env
+            ),
+            PeerAuthLayerResult::Send(MessageBuilder::from(
+                b"\xfe\x00\x12\x34\x00\x00\x00\x07Ok".as_slice()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_no_alternative() {
+        let mut env = DefaultEnvironment::new();
+        let mut layer = PeerLayer::new().with(DummyProtocol {
+            method_identifier: MethodType::Legacy(1),
+            events: vec![],
+        });
+
+        assert_eq!(
+            layer.recv(&Message::new(MessageCode::Request, 0, b"\x06"), &mut env),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x03\x00".as_slice()))
+        );
+    }
+
+    #[test]
+    fn test_policy() {
+        use crate::layers::peer::{
+            PeerIdentityMethod, PeerMD5ChallengeMethod, PeerMschapv2Method, PeerPolicy,
+        };
+
+        let mut env = DefaultEnvironment::new();
+        let layer = PeerLayer::new()
+            .with(PeerIdentityMethod::new(b"hans"))
+            .with(PeerMD5ChallengeMethod::new(b"1234"))
+            .with(PeerMschapv2Method::new(b"hans", b"1234"));
+
+        // MD5-Challenge does not authenticate the server
+        let mut strict = layer
+            .clone()
+            .with_policy(PeerPolicy::new().require(SecurityClaims {
+                mutual_auth: true,
+                ..SecurityClaims::NONE
+            }));
+        assert_eq!(
+            strict.recv(&Message::new(MessageCode::Request, 0, b"\x01"), &mut env),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x01hans".as_slice()))
+        );
+        assert_eq!(
+            strict.recv(
+                &Message::new(MessageCode::Request, 1, b"\x04\x01\x00"),
+                &mut env
+            ),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x03\x1a".as_slice()))
+        );
+        assert_eq!(strict.downgrade_attempt(), Some(MethodType::Legacy(4)));
+        assert_eq!(strict.decision(), Decision::Fail);
+
+        // Only the listed methods run
+        let mut listed = layer.with_policy(PeerPolicy::new().allow(MethodType::Legacy(4)));
+        assert_eq!(
+            listed.recv(&Message::new(MessageCode::Request, 0, b"\x1a"), &mut env),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x03\x04".as_slice()))
+        );
+        assert_eq!(listed.downgrade_attempt(), Some(MethodType::Legacy(26)));
+    }
+
+    #[test]
+    fn test_preference() {
+        let mut env = DefaultEnvironment::new();
+        let dummy = |method_type: u8| DummyProtocol {
+            method_identifier: MethodType::Legacy(method_type),
+            events: vec![DummyEvent::Send(b"response".to_vec())],
+        };
+        let layer = PeerLayer::new()
+            .with(dummy(1))
+            .with(dummy(4))
+            .with(dummy(5))
+            .with(dummy(6));
+        let preference = MethodPreference::new()
+            .prefer(MethodType::Legacy(6))
+            .prefer(MethodType::Legacy(4));
+
+        // The Nak lists preferred methods first
+        let mut nak = layer.clone().with_preference(preference.clone());
+        assert_eq!(
+            nak.recv(&Message::new(MessageCode::Request, 0, b"\x07"), &mut env),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x03\x06\x04\x05".as_slice()))
+        );
+
+        // The authenticator's order wins, any supported method is accepted
+        let mut accepting = layer.clone().with_preference(preference.clone());
+        assert_eq!(
+            accepting.recv(&Message::new(MessageCode::Request, 0, b"\x05"), &mut env),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x05response".as_slice()))
+        );
+        assert_eq!(
+            accepting.negotiation(),
+            Some(&Negotiation::accepted(
+                MethodType::Legacy(5),
+                PreferenceOrder::Authenticator
+            ))
+        );
+
+        // The peer's order wins, it asks once for its favourite
+        let reported = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        env.set_negotiation_callback({
+            let reported = reported.clone();
+            move |negotiation| reported.borrow_mut().push(*negotiation)
+        });
+        let preference = preference.order(PreferenceOrder::Peer);
+        let mut insisting = layer.clone().with_preference(preference.clone());
+        assert_eq!(
+            insisting.recv(&Message::new(MessageCode::Request, 0, b"\x01"), &mut env),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x01response".as_slice()))
+        );
+        assert_eq!(insisting.negotiation(), None);
+        assert_eq!(
+            insisting.recv(&Message::new(MessageCode::Request, 1, b"\x05"), &mut env),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x03\x06\x04\x05".as_slice()))
+        );
+        assert_eq!(
+            insisting.recv(&Message::new(MessageCode::Request, 2, b"\x04"), &mut env),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x04response".as_slice()))
+        );
+        assert_eq!(
+            insisting.negotiation(),
+            Some(&Negotiation {
+                proposed: MethodType::Legacy(5),
+                selected: Some(MethodType::Legacy(4)),
+                nak: true,
+                order: PreferenceOrder::Peer,
+            })
+        );
+        assert_eq!(*reported.borrow(), vec![*insisting.negotiation().unwrap()]);
+
+        // Methods ranked at or above the threshold are accepted at once
+        let mut tolerant = layer.with_preference(preference.nak_below(MethodType::Legacy(4)));
+        assert_eq!(
+            tolerant.recv(&Message::new(MessageCode::Request, 0, b"\x05"), &mut env),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x03\x06\x04\x05".as_slice()))
+        );
+        tolerant.reset();
+        assert_eq!(
+            tolerant.recv(&Message::new(MessageCode::Request, 0, b"\x04"), &mut env),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x04response".as_slice()))
+        );
+        assert_eq!(
+            tolerant.negotiation(),
+            Some(&Negotiation::accepted(
+                MethodType::Legacy(4),
+                PreferenceOrder::Peer
+            ))
+        );
+    }
+
+    #[test]
+    fn test_notification() {
+        let mut env = DefaultEnvironment::new();
+        let received = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        env.set_notification_callback({
+            let received = received.clone();
+            move |text| received.borrow_mut().push(text.to_vec())
+        });
+
+        let mut layer = PeerLayer::new().with(DummyProtocol {
+            method_identifier: MethodType::Legacy(4),
+            events: vec![
+                DummyEvent::Send(b"first".to_vec()),
+                DummyEvent::Send(b"second".to_vec()),
+            ],
+        });
+
+        assert_eq!(
+            layer.recv(&Message::new(MessageCode::Request, 0, b"\x04"), &mut env),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x04first".as_slice()))
+        );
+
+        // Answered with an empty Notification Response
+        assert_eq!(
+            layer.recv(
+                &Message::new(MessageCode::Request, 0, b"\x02Password expires soon"),
+                &mut env
+            ),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x02".as_slice()))
+        );
+        assert_eq!(*received.borrow(), vec![b"Password expires soon".to_vec()]);
+
+        // The method continues where it was
+        assert_eq!(
+            layer.recv(&Message::new(MessageCode::Request, 0, b"\x04"), &mut env),
+            PeerAuthLayerResult::Send(MessageBuilder::from(b"\x04second".as_slice()))
+        );
+    }
 }
@@ -0,0 +1,52 @@
+use crate::layers::{method_type::TYPE_IDENTITY, MethodType, SecurityClaims};
+
+/// Capacity of the allow list of a [`PeerPolicy`]
+pub const MAX_ALLOWED_METHODS: usize = 8;
+
+/// Methods the peer accepts in a network, see
+/// [`PeerLayer::with_policy`](super::PeerLayer::with_policy).
+///
+/// A method runs if it is on the allow list, or no list was given, and makes
+/// every required claim. Identity is not an authentication method and always
+/// passes.
+#[derive(Debug, Clone, Default)]
+pub struct PeerPolicy {
+    allowed: [Option<MethodType>; MAX_ALLOWED_METHODS],
+    minimum: SecurityClaims,
+}
+
+impl PeerPolicy {
+    /// Accepts every configured method
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `method` to the allow list, the first call restricts the peer
+    /// to the listed methods
+    pub fn allow(mut self, method: MethodType) -> Self {
+        let free = self
+            .allowed
+            .iter_mut()
+            .find(|entry| entry.is_none())
+            .expect("too many allowed methods");
+        *free = Some(method);
+        self
+    }
+
+    /// Claims every method has to make, e.g. mutual authentication so that
+    /// a rogue authenticator cannot fall back to MD5-Challenge
+    pub fn require(mut self, minimum: SecurityClaims) -> Self {
+        self.minimum = minimum;
+        self
+    }
+
+    pub fn permits(&self, method: MethodType, claims: &SecurityClaims) -> bool {
+        if method == MethodType::Legacy(TYPE_IDENTITY) {
+            return true;
+        }
+
+        let listed =
+            self.allowed.iter().all(Option::is_none) || self.allowed.contains(&Some(method));
+        listed && claims.satisfies(&self.minimum)
+    }
+}
@@ -0,0 +1,112 @@
+use crate::EapEnvironment;
+
+/// Time and timer settings of the environment, read at the start of each
+/// call into [`EapLayer`](super::EapLayer)
+pub(crate) struct Clock {
+    pub now: u64,
+    initial_rto: u32,
+    min_rto: u32,
+    max_rto: u32,
+    pub idle_timeout: u32,
+    pub max_session_duration: u64,
+}
+
+impl Clock {
+    /// `None` if the environment has no clock
+    pub fn read(env: &dyn EapEnvironment) -> Option<Self> {
+        Some(Clock {
+            now: env.now()?,
+            initial_rto: env.initial_retransmit_timeout(),
+            min_rto: env.min_retransmit_timeout(),
+            max_rto: env.max_retransmit_timeout(),
+            idle_timeout: env.idle_timeout(),
+            max_session_duration: env.max_session_duration(),
+        })
+    }
+
+    /// calculateTimeout of RFC 4137: the RTO of RFC 2988, doubled with each
+    /// retransmission
+    pub fn calculate_timeout(&self, rtt: Option<&Rtt>, retrans_count: u16) -> u32 {
+        let rto = match rtt {
+            // The clock granularity is one millisecond
+            Some(rtt) => rtt.srtt.saturating_add((4 * rtt.rttvar).max(1)),
+            None => self.initial_rto,
+        };
+        let rto = rto.clamp(self.min_rto, self.max_rto) as u64;
+        let backoff = rto << retrans_count.min(16);
+        backoff.min(self.max_rto as u64) as u32
+    }
+}
+
+/// Smoothed round-trip time and its variation (eapSRTT and eapRTTVAR) in
+/// milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Rtt {
+    srtt: u32,
+    rttvar: u32,
+}
+
+impl Rtt {
+    pub fn new(sample: u32) -> Self {
+        Rtt {
+            srtt: sample,
+            rttvar: sample / 2,
+        }
+    }
+
+    /// RFC 2988 2.3 with alpha = 1/8 and beta = 1/4
+    pub fn update(&mut self, sample: u32) {
+        self.rttvar = ((3 * self.rttvar as u64 + self.srtt.abs_diff(sample) as u64) / 4) as u32;
+        self.srtt = ((7 * self.srtt as u64 + sample as u64) / 8) as u32;
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DefaultEnvironment;
+
+    fn clock() -> Clock {
+        let mut env = DefaultEnvironment::new();
+        env.set_clock(|| 0);
+        Clock::read(&env).unwrap()
+    }
+
+    #[test]
+    fn test_calculate_timeout() {
+        let clock = clock();
+        assert_eq!(clock.calculate_timeout(None, 0), 3_000);
+        assert_eq!(clock.calculate_timeout(None, 1), 6_000);
+        assert_eq!(clock.calculate_timeout(None, 5), 60_000);
+        assert_eq!(clock.calculate_timeout(None, u16::MAX), 60_000);
+
+        // SRTT + 4 * RTTVAR, bounded below
+        let rtt = Rtt::new(100);
+        assert_eq!(clock.calculate_timeout(Some(&rtt), 0), 300);
+        assert_eq!(clock.calculate_timeout(Some(&rtt), 2), 1_200);
+        assert_eq!(clock.calculate_timeout(Some(&Rtt::new(10)), 0), 200);
+    }
+
+    #[test]
+    fn test_rtt_update() {
+        let mut rtt = Rtt::new(100);
+        rtt.update(100);
+        assert_eq!(
+            rtt,
+            Rtt {
+                srtt: 100,
+                rttvar: 37
+            }
+        );
+
+        rtt.update(900);
+        assert_eq!(
+            rtt,
+            Rtt {
+                srtt: 200,
+                rttvar: 227
+            }
+        );
+    }
+}
@@ -3,8 +3,25 @@
 #[cfg(feature = "tls")]
 pub mod eap_rustls;
 
+pub mod eap_aka;
+pub mod eap_edhoc;
+pub mod eap_eke;
+#[cfg(feature = "tls")]
+pub mod eap_fido;
+pub mod eap_gpsk;
+pub mod eap_ikev2;
+pub mod eap_noob;
+pub mod eap_psk;
+pub mod eap_sim;
+#[cfg(feature = "tls")]
+pub mod eap_teap;
+pub mod eap_tnc;
+pub mod eap_wsc;
+pub mod erp;
 pub mod layers;
 mod message;
+mod mschapv2;
+mod sim_aka;
 pub mod util;
 
 pub use common;
@@ -22,3 +39,5 @@ pub use dummycert::TlsConfig;
 
 #[cfg(test)]
 mod integration_tests;
+#[cfg(test)]
+mod test_util;
@@ -24,6 +24,9 @@ pub enum MessageCode {
     Response = 2,
     Success = 3,
     Failure = 4,
+    /// EAP-Initiate and EAP-Finish of ERP, RFC 6696
+    Initiate = 5,
+    Finish = 6,
 }
 
 #[cfg(feature = "std")]
@@ -49,6 +52,8 @@ impl<'a> Message<'a> {
             2 => MessageCode::Response,
             3 => MessageCode::Success,
             4 => MessageCode::Failure,
+            5 => MessageCode::Initiate,
+            6 => MessageCode::Finish,
             _ => {
                 return Err(MessageParseError::InvalidCode);
             }
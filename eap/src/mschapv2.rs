@@ -0,0 +1,255 @@
+//! MS-CHAPv2 primitives, see https://www.rfc-editor.org/rfc/rfc2759
+//! and the key derivation of https://www.rfc-editor.org/rfc/rfc3079
+//!
+//! Used by the EAP-MSCHAPv2 methods (draft-kamath-pppext-eap-mschapv2).
+
+use des::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Des,
+};
+use md4::{Digest, Md4};
+use sha1::Sha1;
+
+use crate::{layers::eap_layer::SessionKeys, util::OwnedSlice};
+
+pub(crate) const METHOD_MSCHAPV2: u8 = 26;
+
+pub(crate) const OP_CHALLENGE: u8 = 1;
+pub(crate) const OP_RESPONSE: u8 = 2;
+pub(crate) const OP_SUCCESS: u8 = 3;
+pub(crate) const OP_FAILURE: u8 = 4;
+
+pub(crate) const CHALLENGE_LEN: usize = 16;
+pub(crate) const NT_RESPONSE_LEN: usize = 24;
+/// Peer-Challenge, 8 reserved bytes, NT-Response and Flags
+pub(crate) const RESPONSE_LEN: usize = CHALLENGE_LEN + 8 + NT_RESPONSE_LEN + 1;
+/// "S=" followed by 40 hex digits
+pub(crate) const AUTHENTICATOR_RESPONSE_LEN: usize = 42;
+
+/// OpCode, MS-CHAPv2-ID and MS-Length
+pub(crate) const HEADER_LEN: usize = 4;
+
+const MAGIC_SERVER_SIGNING: &[u8] = b"Magic server to client signing constant";
+const MAGIC_ITERATION: &[u8] = b"Pad to make it do more than one iteration";
+const MAGIC_MASTER_KEY: &[u8] = b"This is the MPPE Master Key";
+const MAGIC_CLIENT_SEND: &[u8] =
+    b"On the client side, this is the send key; on the server side, it is the receive key.";
+const MAGIC_CLIENT_RECEIVE: &[u8] =
+    b"On the client side, this is the receive key; on the server side, it is the send key.";
+
+/// NtPasswordHash, MD4 over the UTF-16LE password. Passwords that are not
+/// valid UTF-8 are taken as Latin-1.
+pub(crate) fn nt_password_hash(password: &[u8]) -> [u8; 16] {
+    let mut md4 = Md4::new();
+    match core::str::from_utf8(password) {
+        Ok(password) => {
+            for unit in password.encode_utf16() {
+                md4.update(unit.to_le_bytes());
+            }
+        }
+        Err(_) => {
+            for byte in password {
+                md4.update([*byte, 0]);
+            }
+        }
+    }
+    md4.finalize().into()
+}
+
+fn hash_nt_password_hash(password_hash: &[u8; 16]) -> [u8; 16] {
+    Md4::digest(password_hash).into()
+}
+
+fn challenge_hash(
+    peer_challenge: &[u8; CHALLENGE_LEN],
+    authenticator_challenge: &[u8; CHALLENGE_LEN],
+    username: &[u8],
+) -> [u8; 8] {
+    let mut sha1 = Sha1::new();
+    sha1.update(peer_challenge);
+    sha1.update(authenticator_challenge);
+    sha1.update(username);
+
+    let mut result = [0u8; 8];
+    result.copy_from_slice(&sha1.finalize()[..8]);
+    result
+}
+
+/// Expands 56 key bits into a DES key, the parity bits are ignored
+fn des_key(key: &[u8]) -> [u8; 8] {
+    let mut result = [0u8; 8];
+    for (i, byte) in result.iter_mut().enumerate() {
+        let bit = i * 7;
+        let value = (u16::from(key[bit / 8]) << 8) | u16::from(*key.get(bit / 8 + 1).unwrap_or(&0));
+        *byte = (((value >> (9 - bit % 8)) & 0x7f) as u8) << 1;
+    }
+    result
+}
+
+fn challenge_response(challenge: &[u8; 8], password_hash: &[u8; 16]) -> [u8; NT_RESPONSE_LEN] {
+    let mut z_password_hash = [0u8; 21];
+    z_password_hash[..16].copy_from_slice(password_hash);
+
+    let mut response = [0u8; NT_RESPONSE_LEN];
+    for (key, block) in z_password_hash.chunks(7).zip(response.chunks_mut(8)) {
+        let cipher = Des::new(GenericArray::from_slice(&des_key(key)));
+        block.copy_from_slice(challenge);
+        cipher.encrypt_block(GenericArray::from_mut_slice(block));
+    }
+    response
+}
+
+/// GenerateNTResponse, RFC 2759 8.1
+pub(crate) fn generate_nt_response(
+    authenticator_challenge: &[u8; CHALLENGE_LEN],
+    peer_challenge: &[u8; CHALLENGE_LEN],
+    username: &[u8],
+    password: &[u8],
+) -> [u8; NT_RESPONSE_LEN] {
+    let challenge = challenge_hash(peer_challenge, authenticator_challenge, username);
+    challenge_response(&challenge, &nt_password_hash(password))
+}
+
+/// GenerateAuthenticatorResponse, RFC 2759 8.7
+pub(crate) fn authenticator_response(
+    password: &[u8],
+    nt_response: &[u8; NT_RESPONSE_LEN],
+    peer_challenge: &[u8; CHALLENGE_LEN],
+    authenticator_challenge: &[u8; CHALLENGE_LEN],
+    username: &[u8],
+) -> [u8; AUTHENTICATOR_RESPONSE_LEN] {
+    let password_hash_hash = hash_nt_password_hash(&nt_password_hash(password));
+
+    let mut sha1 = Sha1::new();
+    sha1.update(password_hash_hash);
+    sha1.update(nt_response);
+    sha1.update(MAGIC_SERVER_SIGNING);
+    let digest = sha1.finalize();
+
+    let mut sha1 = Sha1::new();
+    sha1.update(digest);
+    sha1.update(challenge_hash(
+        peer_challenge,
+        authenticator_challenge,
+        username,
+    ));
+    sha1.update(MAGIC_ITERATION);
+    let digest = sha1.finalize();
+
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    let mut result = [0u8; AUTHENTICATOR_RESPONSE_LEN];
+    result[..2].copy_from_slice(b"S=");
+    for (i, byte) in digest.iter().enumerate() {
+        result[2 + 2 * i] = HEX[(byte >> 4) as usize];
+        result[3 + 2 * i] = HEX[(byte & 0x0f) as usize];
+    }
+    result
+}
+
+fn master_key(password: &[u8], nt_response: &[u8; NT_RESPONSE_LEN]) -> [u8; 16] {
+    let password_hash_hash = hash_nt_password_hash(&nt_password_hash(password));
+
+    let mut sha1 = Sha1::new();
+    sha1.update(password_hash_hash);
+    sha1.update(nt_response);
+    sha1.update(MAGIC_MASTER_KEY);
+
+    let mut result = [0u8; 16];
+    result.copy_from_slice(&sha1.finalize()[..16]);
+    result
+}
+
+fn asymmetric_start_key(master_key: &[u8; 16], magic: &[u8]) -> [u8; 16] {
+    let mut sha1 = Sha1::new();
+    sha1.update(master_key);
+    sha1.update([0u8; 40]);
+    sha1.update(magic);
+    sha1.update([0xf2u8; 40]);
+
+    let mut result = [0u8; 16];
+    result.copy_from_slice(&sha1.finalize()[..16]);
+    result
+}
+
+/// MSK as used by tunnel methods: the client send key followed by the client
+/// receive key. There is no EMSK, it is left zero, and no Session-ID.
+pub(crate) fn session_keys(password: &[u8], nt_response: &[u8; NT_RESPONSE_LEN]) -> SessionKeys {
+    let master_key = master_key(password, nt_response);
+
+    let mut keys = SessionKeys {
+        msk: [0; 64],
+        emsk: [0; 64],
+        session_id: OwnedSlice::new(),
+    };
+    keys.msk[..16].copy_from_slice(&asymmetric_start_key(&master_key, MAGIC_CLIENT_SEND));
+    keys.msk[16..32].copy_from_slice(&asymmetric_start_key(&master_key, MAGIC_CLIENT_RECEIVE));
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex_to_vec;
+
+    // RFC 2759 9.2 and RFC 3079 3.5.3
+    const USERNAME: &[u8] = b"User";
+    const PASSWORD: &[u8] = b"clientPass";
+
+    fn array<const N: usize>(hex: &str) -> [u8; N] {
+        hex_to_vec(hex).try_into().unwrap()
+    }
+
+    #[test]
+    fn rfc2759_test_vectors() {
+        let authenticator_challenge = array("5B 5D 7C 7D 7B 3F 2F 3E 3C 2C 60 21 32 26 26 28");
+        let peer_challenge = array("21 40 23 24 25 5E 26 2A 28 29 5F 2B 3A 33 7C 7E");
+
+        assert_eq!(
+            nt_password_hash(PASSWORD),
+            array("44 EB BA 8D 53 12 B8 D6 11 47 44 11 F5 69 89 AE")
+        );
+        assert_eq!(
+            challenge_hash(&peer_challenge, &authenticator_challenge, USERNAME),
+            array("D0 2E 43 86 BC E9 12 26")
+        );
+
+        let nt_response = generate_nt_response(
+            &authenticator_challenge,
+            &peer_challenge,
+            USERNAME,
+            PASSWORD,
+        );
+        assert_eq!(
+            nt_response,
+            array("82 30 9E CD 8D 70 8B 5E A0 8F AA 39 81 CD 83 54 42 33 11 4A 3D 85 D6 DF")
+        );
+
+        assert_eq!(
+            &authenticator_response(
+                PASSWORD,
+                &nt_response,
+                &peer_challenge,
+                &authenticator_challenge,
+                USERNAME
+            ),
+            b"S=407A5589115FD0D6209F510FE9C04566932CDA56"
+        );
+    }
+
+    #[test]
+    fn rfc3079_test_vectors() {
+        let nt_response =
+            array("82 30 9E CD 8D 70 8B 5E A0 8F AA 39 81 CD 83 54 42 33 11 4A 3D 85 D6 DF");
+
+        let master_key = master_key(PASSWORD, &nt_response);
+        assert_eq!(
+            master_key,
+            array("FD EC E3 71 7A 8C 83 8C B3 88 E5 27 AE 3C DD 31")
+        );
+        assert_eq!(
+            // Send key of the server
+            asymmetric_start_key(&master_key, MAGIC_CLIENT_RECEIVE),
+            array("8B 7C DC 14 9B 99 3A 1B A1 18 CB 15 3F 56 DC CB")
+        );
+    }
+}
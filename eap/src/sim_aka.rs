@@ -0,0 +1,760 @@
+//! Shared parts of EAP-SIM and EAP-AKA: the attribute format, message
+//! authentication, encrypted attributes and the identity privacy support,
+//! see https://www.rfc-editor.org/rfc/rfc4186 and https://www.rfc-editor.org/rfc/rfc4187
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes128,
+};
+use hmac::{Hmac, Mac};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::{
+    layers::eap_layer::{SessionKeys, MAX_SESSION_ID_LEN},
+    message::{Message, MessageCode},
+    util::{constant_time_eq, ByteReader, OwnedSlice},
+};
+
+pub(crate) const SUBTYPE_REAUTHENTICATION: u8 = 13;
+pub(crate) const SUBTYPE_CLIENT_ERROR: u8 = 14;
+
+pub(crate) const AT_RAND: u8 = 1;
+pub(crate) const AT_AUTN: u8 = 2;
+pub(crate) const AT_RES: u8 = 3;
+pub(crate) const AT_AUTS: u8 = 4;
+pub(crate) const AT_PADDING: u8 = 6;
+pub(crate) const AT_NONCE_MT: u8 = 7;
+pub(crate) const AT_PERMANENT_ID_REQ: u8 = 10;
+pub(crate) const AT_MAC: u8 = 11;
+pub(crate) const AT_ANY_ID_REQ: u8 = 13;
+pub(crate) const AT_IDENTITY: u8 = 14;
+pub(crate) const AT_VERSION_LIST: u8 = 15;
+pub(crate) const AT_SELECTED_VERSION: u8 = 16;
+pub(crate) const AT_FULLAUTH_ID_REQ: u8 = 17;
+pub(crate) const AT_COUNTER: u8 = 19;
+pub(crate) const AT_COUNTER_TOO_SMALL: u8 = 20;
+pub(crate) const AT_NONCE_S: u8 = 21;
+pub(crate) const AT_CLIENT_ERROR_CODE: u8 = 22;
+pub(crate) const AT_KDF_INPUT: u8 = 23;
+pub(crate) const AT_KDF: u8 = 24;
+pub(crate) const AT_IV: u8 = 129;
+pub(crate) const AT_ENCR_DATA: u8 = 130;
+pub(crate) const AT_NEXT_PSEUDONYM: u8 = 132;
+pub(crate) const AT_NEXT_REAUTH_ID: u8 = 133;
+pub(crate) const AT_CHECKCODE: u8 = 134;
+
+/// Attributes from 128 on may be ignored if they are not recognized
+const SKIPPABLE: u8 = 128;
+
+/// AT_CLIENT_ERROR_CODE "unable to process packet"
+pub(crate) const CLIENT_ERROR_UNABLE_TO_PROCESS: u16 = 0;
+/// AT_CLIENT_ERROR_CODE "unsupported version" of EAP-SIM
+pub(crate) const CLIENT_ERROR_UNSUPPORTED_VERSION: u16 = 1;
+/// AT_CLIENT_ERROR_CODE "insufficient number of challenges" of EAP-SIM
+pub(crate) const CLIENT_ERROR_INSUFFICIENT_CHALLENGES: u16 = 2;
+/// AT_CLIENT_ERROR_CODE "RANDs are not fresh" of EAP-SIM
+pub(crate) const CLIENT_ERROR_RANDS_NOT_FRESH: u16 = 3;
+
+pub(crate) const MAC_LEN: usize = 16;
+pub(crate) const NONCE_LEN: usize = 16;
+pub(crate) const MK_LEN: usize = 20;
+pub(crate) const K_AUT_MAX_LEN: usize = 32;
+
+/// Longest identity accepted from the peer
+pub const MAX_IDENTITY_LEN: usize = 128;
+
+/// Room for the method data of any message, below the response buffer size
+const MAX_DATA_LEN: usize = 1000;
+const MAX_ENCR_DATA_LEN: usize = 512;
+
+pub(crate) type Block = [u8; 16];
+pub(crate) type Identity = OwnedSlice<MAX_IDENTITY_LEN>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MacAlgorithm {
+    /// HMAC-SHA1-128 of EAP-SIM and EAP-AKA
+    HmacSha1,
+    /// HMAC-SHA-256-128 of EAP-AKA'
+    HmacSha256,
+}
+
+impl MacAlgorithm {
+    pub fn key_len(self) -> usize {
+        match self {
+            Self::HmacSha1 => 16,
+            Self::HmacSha256 => 32,
+        }
+    }
+
+    fn compute(self, k_aut: &[u8; K_AUT_MAX_LEN], parts: &[&[u8]]) -> [u8; MAC_LEN] {
+        let key = &k_aut[..self.key_len()];
+        let mut out = [0u8; MAC_LEN];
+        match self {
+            Self::HmacSha1 => {
+                let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(key).unwrap();
+                parts.iter().for_each(|part| mac.update(part));
+                out.copy_from_slice(&mac.finalize().into_bytes()[..MAC_LEN]);
+            }
+            Self::HmacSha256 => {
+                let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+                parts.iter().for_each(|part| mac.update(part));
+                out.copy_from_slice(&mac.finalize().into_bytes()[..MAC_LEN]);
+            }
+        }
+        out
+    }
+
+    /// Checks AT_MAC of a received packet. The MAC covers the whole EAP packet with
+    /// the MAC value zeroed, followed by `extra`.
+    pub fn verify(
+        self,
+        k_aut: &[u8; K_AUT_MAX_LEN],
+        message: &Message,
+        attributes: &Attributes,
+        extra: &[u8],
+    ) -> bool {
+        let Some(offset) = attributes.mac else {
+            return false;
+        };
+        // The method data starts after the type byte of the body
+        let offset = offset + 1;
+        let body = message.body;
+
+        let header = packet_header(message.code as u8, message.identifier, body.len());
+        let expected = self.compute(
+            k_aut,
+            &[
+                &header,
+                &body[..offset],
+                &[0; MAC_LEN],
+                &body[offset + MAC_LEN..],
+                extra,
+            ],
+        );
+        constant_time_eq(&expected, &body[offset..offset + MAC_LEN])
+    }
+}
+
+/// Session-ID of a fast re-authentication: the Type, NONCE_S and AT_MAC of
+/// the Re-authentication Request, RFC 5247 Appendix A
+pub(crate) fn reauth_session_id(
+    method_type: u8,
+    nonce_s: &[u8; NONCE_LEN],
+    mac: &[u8; MAC_LEN],
+) -> OwnedSlice<MAX_SESSION_ID_LEN> {
+    SessionKeys::session_id(method_type, &[nonce_s, mac])
+}
+
+/// Code, identifier and length of an EAP packet with the given body (type and data)
+pub(crate) fn packet_header(code: u8, identifier: u8, body_len: usize) -> [u8; 4] {
+    let length = (4 + body_len) as u16;
+    let [high, low] = length.to_be_bytes();
+    [code, identifier, high, low]
+}
+
+/// Parsed attributes of a message, see RFC 4187 10.
+///
+/// Unknown attributes below 128 make the whole message invalid, others are
+/// skipped.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Attributes<'a> {
+    pub rand: Option<&'a [u8]>,
+    pub autn: Option<&'a Block>,
+    pub res: Option<&'a [u8]>,
+    pub nonce_mt: Option<&'a [u8; NONCE_LEN]>,
+    /// Versions of AT_VERSION_LIST, two bytes each
+    pub version_list: Option<&'a [u8]>,
+    pub selected_version: Option<u16>,
+    pub auts: Option<&'a [u8; 14]>,
+    /// Type of the AT_*_ID_REQ attribute
+    pub id_req: Option<u8>,
+    pub identity: Option<&'a [u8]>,
+    /// Offset of the MAC value in the method data
+    pub mac: Option<usize>,
+    pub counter: Option<u16>,
+    pub counter_too_small: bool,
+    pub nonce_s: Option<&'a [u8; NONCE_LEN]>,
+    pub client_error_code: Option<u16>,
+    /// Only the first, most preferred KDF is kept
+    pub kdf: Option<u16>,
+    pub kdf_input: Option<&'a [u8]>,
+    pub iv: Option<&'a Block>,
+    pub encr_data: Option<&'a [u8]>,
+    pub next_pseudonym: Option<&'a [u8]>,
+    pub next_reauth_id: Option<&'a [u8]>,
+    pub checkcode: Option<&'a [u8]>,
+}
+
+/// Value after two reserved bytes
+fn reserved(value: &[u8]) -> Option<&[u8]> {
+    value.get(2..)
+}
+
+/// Value after a two byte length in bytes, the rest is padding
+fn prefixed(value: &[u8], len: usize) -> Option<&[u8]> {
+    value.get(2..2 + len)
+}
+
+fn u16_value(value: &[u8]) -> Option<u16> {
+    (value.len() == 2).then(|| u16::from_be_bytes([value[0], value[1]]))
+}
+
+impl<'a> Attributes<'a> {
+    /// Value of AT_MAC in `message`, the packet the attributes were parsed
+    /// from
+    pub fn mac_value(&self, message: &Message) -> Option<[u8; MAC_LEN]> {
+        // The method data starts after the type byte of the body
+        let offset = self.mac? + 1;
+        message.body.get(offset..offset + MAC_LEN)?.try_into().ok()
+    }
+
+    /// Parses the attributes in `data`, `offset` is the position of `data` in the
+    /// method data.
+    pub fn parse(data: &'a [u8], offset: usize) -> Option<Self> {
+        let mut reader = ByteReader::new(data);
+        let mut attributes = Self::default();
+
+        while !reader.is_empty() {
+            let position = offset + data.len() - reader.remaining().len();
+            let attribute = reader.u8()?;
+            let len = reader.u8()? as usize * 4;
+            if len == 0 {
+                return None;
+            }
+            let value = reader.take(len - 2)?;
+            let length_field = || u16_value(value.get(..2)?);
+
+            match attribute {
+                AT_RAND => attributes.rand = Some(reserved(value)?),
+                AT_AUTN => attributes.autn = Some(reserved(value)?.try_into().ok()?),
+                AT_RES => {
+                    let bits = length_field()? as usize;
+                    if !bits.is_multiple_of(8) {
+                        return None;
+                    }
+                    attributes.res = Some(prefixed(value, bits / 8)?);
+                }
+                AT_AUTS => attributes.auts = Some(value.try_into().ok()?),
+                AT_NONCE_MT => attributes.nonce_mt = Some(reserved(value)?.try_into().ok()?),
+                AT_VERSION_LIST => {
+                    let list = prefixed(value, length_field()? as usize)?;
+                    if list.is_empty() || !list.len().is_multiple_of(2) {
+                        return None;
+                    }
+                    attributes.version_list = Some(list);
+                }
+                AT_SELECTED_VERSION => attributes.selected_version = Some(u16_value(value)?),
+                AT_PADDING => {
+                    if value.iter().any(|&b| b != 0) {
+                        return None;
+                    }
+                }
+                AT_PERMANENT_ID_REQ | AT_ANY_ID_REQ | AT_FULLAUTH_ID_REQ => {
+                    if attributes.id_req.replace(attribute).is_some() {
+                        return None;
+                    }
+                }
+                AT_MAC => {
+                    if reserved(value)?.len() != MAC_LEN {
+                        return None;
+                    }
+                    attributes.mac = Some(position + 4);
+                }
+                AT_IDENTITY => {
+                    attributes.identity = Some(prefixed(value, length_field()? as usize)?)
+                }
+                AT_COUNTER => attributes.counter = Some(u16_value(value)?),
+                AT_COUNTER_TOO_SMALL => attributes.counter_too_small = true,
+                AT_NONCE_S => attributes.nonce_s = Some(reserved(value)?.try_into().ok()?),
+                AT_CLIENT_ERROR_CODE => attributes.client_error_code = Some(u16_value(value)?),
+                AT_KDF_INPUT => {
+                    attributes.kdf_input = Some(prefixed(value, length_field()? as usize)?)
+                }
+                AT_KDF => {
+                    let kdf = u16_value(value)?;
+                    attributes.kdf.get_or_insert(kdf);
+                }
+                AT_IV => attributes.iv = Some(reserved(value)?.try_into().ok()?),
+                AT_ENCR_DATA => attributes.encr_data = Some(reserved(value)?),
+                AT_NEXT_PSEUDONYM => {
+                    attributes.next_pseudonym = Some(prefixed(value, length_field()? as usize)?)
+                }
+                AT_NEXT_REAUTH_ID => {
+                    attributes.next_reauth_id = Some(prefixed(value, length_field()? as usize)?)
+                }
+                AT_CHECKCODE => attributes.checkcode = Some(reserved(value)?),
+                SKIPPABLE.. => {}
+                _ => return None,
+            }
+        }
+
+        Some(attributes)
+    }
+}
+
+/// Decrypted content of AT_ENCR_DATA
+pub(crate) struct Decrypted {
+    buf: [u8; MAX_ENCR_DATA_LEN],
+    len: usize,
+}
+
+impl Decrypted {
+    /// Decrypts AT_ENCR_DATA with the IV of AT_IV, fails if either is missing
+    pub fn new(k_encr: &Block, attributes: &Attributes) -> Option<Self> {
+        let (iv, data) = (attributes.iv?, attributes.encr_data?);
+        if data.is_empty() || !data.len().is_multiple_of(16) || data.len() > MAX_ENCR_DATA_LEN {
+            return None;
+        }
+
+        let mut decrypted = Self {
+            buf: [0; MAX_ENCR_DATA_LEN],
+            len: data.len(),
+        };
+        decrypted.buf[..data.len()].copy_from_slice(data);
+        decrypt_cbc(k_encr, iv, &mut decrypted.buf[..data.len()]);
+        Some(decrypted)
+    }
+
+    pub fn attributes(&self) -> Option<Attributes<'_>> {
+        Attributes::parse(&self.buf[..self.len], 0)
+    }
+}
+
+/// Builds the method data of a message: subtype, two reserved bytes and
+/// attributes padded to multiples of four bytes.
+pub(crate) struct Writer {
+    buf: [u8; MAX_DATA_LEN],
+    len: usize,
+    mac: Option<usize>,
+}
+
+impl Writer {
+    pub fn new(subtype: u8) -> Self {
+        let mut writer = Self::nested();
+        writer.write(&[subtype, 0, 0]);
+        writer
+    }
+
+    /// Attributes without the subtype, e.g. the plaintext of AT_ENCR_DATA
+    pub fn nested() -> Self {
+        Self {
+            buf: [0; MAX_DATA_LEN],
+            len: 0,
+            mac: None,
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        self.buf[self.len..self.len + data.len()].copy_from_slice(data);
+        self.len += data.len();
+    }
+
+    pub fn attribute(&mut self, attribute: u8, parts: &[&[u8]]) -> &mut Self {
+        let len = 2 + parts.iter().map(|part| part.len()).sum::<usize>();
+        let padded = len.div_ceil(4) * 4;
+        assert!(padded <= u8::MAX as usize * 4);
+
+        self.write(&[attribute, (padded / 4) as u8]);
+        for part in parts {
+            self.write(part);
+        }
+        self.write(&[0; 3][..padded - len]);
+        self
+    }
+
+    /// Attribute with two reserved bytes in front of the value
+    pub fn reserved(&mut self, attribute: u8, value: &[u8]) -> &mut Self {
+        self.attribute(attribute, &[&[0, 0], value])
+    }
+
+    /// Attribute with the length of the value in front of it
+    pub fn prefixed(&mut self, attribute: u8, value: &[u8]) -> &mut Self {
+        self.attribute(attribute, &[&(value.len() as u16).to_be_bytes(), value])
+    }
+
+    pub fn u16(&mut self, attribute: u8, value: u16) -> &mut Self {
+        self.attribute(attribute, &[&value.to_be_bytes()])
+    }
+
+    /// AT_IV and AT_ENCR_DATA with the attributes of `plaintext`
+    pub fn encrypted(&mut self, k_encr: &Block, iv: &Block, mut plaintext: Writer) -> &mut Self {
+        let padding = (16 - plaintext.len % 16) % 16;
+        if padding != 0 {
+            plaintext.attribute(AT_PADDING, &[&[0; 10][..padding - 2]]);
+        }
+
+        let data = &mut plaintext.buf[..plaintext.len];
+        encrypt_cbc(k_encr, iv, data);
+        self.reserved(AT_IV, iv).reserved(AT_ENCR_DATA, data)
+    }
+
+    /// AT_MAC, its value is filled in by `sign`
+    pub fn mac(&mut self) -> &mut Self {
+        self.reserved(AT_MAC, &[0; MAC_LEN]);
+        self.mac = Some(self.len - MAC_LEN);
+        self
+    }
+
+    /// Computes AT_MAC over the packet this data is sent in
+    pub fn sign(
+        &mut self,
+        algorithm: MacAlgorithm,
+        k_aut: &[u8; K_AUT_MAX_LEN],
+        code: MessageCode,
+        identifier: u8,
+        method_type: u8,
+        extra: &[u8],
+    ) -> &mut Self {
+        let offset = self.mac.expect("AT_MAC is written before signing");
+        let header = packet_header(code as u8, identifier, 1 + self.len);
+        let mac = algorithm.compute(k_aut, &[&header, &[method_type], self.as_slice(), extra]);
+        self.buf[offset..offset + MAC_LEN].copy_from_slice(&mac);
+        self
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Value of AT_MAC, once signed
+    pub fn mac_value(&self) -> [u8; MAC_LEN] {
+        let offset = self.mac.expect("AT_MAC is written before signing");
+        self.buf[offset..offset + MAC_LEN].try_into().unwrap()
+    }
+}
+
+fn encrypt_cbc(key: &Block, iv: &Block, data: &mut [u8]) {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    let mut previous = *iv;
+    for block in data.chunks_exact_mut(16) {
+        block.iter_mut().zip(previous).for_each(|(b, p)| *b ^= p);
+        cipher.encrypt_block(GenericArray::from_mut_slice(block));
+        previous.copy_from_slice(block);
+    }
+}
+
+fn decrypt_cbc(key: &Block, iv: &Block, data: &mut [u8]) {
+    let cipher = Aes128::new(GenericArray::from_slice(key));
+    let mut previous = *iv;
+    for block in data.chunks_exact_mut(16) {
+        let ciphertext: Block = (*block).try_into().unwrap();
+        cipher.decrypt_block(GenericArray::from_mut_slice(block));
+        block.iter_mut().zip(previous).for_each(|(b, p)| *b ^= p);
+        previous = ciphertext;
+    }
+}
+
+/// Pseudo-random function of FIPS 186-2 change notice 1 with SHA-1 as G and
+/// without the optional user input, RFC 4186 Appendix B.
+pub(crate) fn fips186_2_prf(key: &[u8; MK_LEN], out: &mut [u8]) {
+    const SHA1_INIT: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut xkey = *key;
+    for chunk in out.chunks_mut(MK_LEN) {
+        // G is the compression function applied to XKEY padded with zeros
+        let mut block = [0u8; 64];
+        block[..MK_LEN].copy_from_slice(&xkey);
+        let mut state = SHA1_INIT;
+        sha1::compress(&mut state, &[GenericArray::clone_from_slice(&block)]);
+
+        let mut w = [0u8; MK_LEN];
+        for (bytes, word) in w.chunks_mut(4).zip(state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+
+        // XKEY = (1 + XKEY + w) mod 2^160
+        let mut carry = 1u16;
+        for (x, w) in xkey.iter_mut().zip(w).rev() {
+            let sum = *x as u16 + w as u16 + carry;
+            *x = sum as u8;
+            carry = sum >> 8;
+        }
+
+        chunk.copy_from_slice(&w[..chunk.len()]);
+    }
+}
+
+/// Keys of a full authentication
+#[derive(Clone)]
+pub(crate) struct Keys {
+    pub k_encr: Block,
+    pub k_aut: [u8; K_AUT_MAX_LEN],
+    /// The key fast re-authentication derives from: MK, or K_re of EAP-AKA'
+    pub reauth_key: [u8; 32],
+    pub session_keys: SessionKeys,
+}
+
+impl Keys {
+    /// Keys derived from the master key of EAP-SIM and EAP-AKA, RFC 4187 7
+    pub fn from_master_key(mk: &[u8; MK_LEN], session_id: OwnedSlice<MAX_SESSION_ID_LEN>) -> Self {
+        let mut out = [0u8; 160];
+        fips186_2_prf(mk, &mut out);
+
+        let mut keys = Self {
+            k_encr: out[..16].try_into().unwrap(),
+            k_aut: [0; K_AUT_MAX_LEN],
+            reauth_key: [0; 32],
+            session_keys: SessionKeys {
+                msk: out[32..96].try_into().unwrap(),
+                emsk: out[96..160].try_into().unwrap(),
+                session_id,
+            },
+        };
+        keys.k_aut[..16].copy_from_slice(&out[16..32]);
+        keys.reauth_key[..MK_LEN].copy_from_slice(mk);
+        keys
+    }
+}
+
+/// MSK and EMSK of a fast re-authentication of EAP-SIM and EAP-AKA
+pub(crate) fn reauth_session_keys(
+    identity: &[u8],
+    counter: u16,
+    nonce_s: &[u8; NONCE_LEN],
+    mk: &[u8; MK_LEN],
+    session_id: OwnedSlice<MAX_SESSION_ID_LEN>,
+) -> SessionKeys {
+    let xkey: [u8; MK_LEN] = Sha1::new()
+        .chain_update(identity)
+        .chain_update(counter.to_be_bytes())
+        .chain_update(nonce_s)
+        .chain_update(mk)
+        .finalize()
+        .into();
+
+    let mut out = [0u8; 160];
+    fips186_2_prf(&xkey, &mut out);
+    SessionKeys {
+        msk: out[..64].try_into().unwrap(),
+        emsk: out[64..128].try_into().unwrap(),
+        session_id,
+    }
+}
+
+/// Leading characters that tell the kinds of identities apart, usernames of
+/// pseudonyms and re-authentication identities are random otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct IdentityPrefixes {
+    pub permanent: u8,
+    pub pseudonym: u8,
+    pub reauth: u8,
+}
+
+/// Username part of an NAI
+pub(crate) fn username(identity: &[u8]) -> &[u8] {
+    identity.split(|&b| b == b'@').next().unwrap()
+}
+
+/// A new pseudonym or re-authentication identity in the realm of `identity`
+pub(crate) fn temporary_identity(prefix: u8, identity: &[u8], random: &[u8; 8]) -> Identity {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    let mut buf = [0u8; MAX_IDENTITY_LEN];
+    buf[0] = prefix;
+    for (i, b) in random.iter().enumerate() {
+        buf[1 + 2 * i] = HEX[(b >> 4) as usize];
+        buf[2 + 2 * i] = HEX[(b & 0xf) as usize];
+    }
+
+    let mut len = 1 + 2 * random.len();
+    let realm = &identity[username(identity).len()..];
+    if len + realm.len() <= MAX_IDENTITY_LEN {
+        buf[len..len + realm.len()].copy_from_slice(realm);
+        len += realm.len();
+    }
+    Identity::from(&buf[..len])
+}
+
+/// State of fast re-authentication, kept by both sides between conversations
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReauthContext {
+    pub(crate) identity: Identity,
+    pub(crate) permanent_identity: Identity,
+    pub(crate) counter: u16,
+    pub(crate) k_encr: Block,
+    pub(crate) k_aut: [u8; K_AUT_MAX_LEN],
+    pub(crate) reauth_key: [u8; 32],
+}
+
+impl ReauthContext {
+    pub(crate) fn new(identity: &[u8], permanent_identity: &[u8], keys: &Keys) -> Self {
+        Self {
+            identity: Identity::from(identity),
+            permanent_identity: Identity::from(permanent_identity),
+            counter: 0,
+            k_encr: keys.k_encr,
+            k_aut: keys.k_aut,
+            reauth_key: keys.reauth_key,
+        }
+    }
+
+    /// The re-authentication identity the context is stored under
+    pub fn identity(&self) -> &[u8] {
+        self.identity.as_ref()
+    }
+
+    /// Number of fast re-authentications since the last full authentication
+    pub fn counter(&self) -> u16 {
+        self.counter
+    }
+}
+
+/// Temporary identities of the peer, usually stored on the (U)SIM
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerIdentities {
+    pub pseudonym: Option<Identity>,
+    pub reauth: Option<ReauthContext>,
+}
+
+/// Persistent storage of the peer for its temporary identities
+pub trait PeerIdentityStore {
+    fn load_identities(&mut self) -> PeerIdentities;
+    fn store_identities(&mut self, identities: &PeerIdentities);
+}
+
+/// Persistent storage of the server for pseudonyms and fast re-authentication
+pub trait IdentityStore {
+    /// Permanent identity a pseudonym was issued for
+    fn permanent_identity(&mut self, pseudonym: &[u8]) -> Option<Identity>;
+    fn store_pseudonym(&mut self, pseudonym: &[u8], permanent_identity: &[u8]);
+
+    fn load_reauth(&mut self, identity: &[u8]) -> Option<ReauthContext>;
+    /// Stores the context under its re-authentication identity
+    fn store_reauth(&mut self, context: &ReauthContext);
+    fn remove_reauth(&mut self, identity: &[u8]);
+}
+
+/// Stores nothing, for servers issuing neither pseudonyms nor re-authentication
+/// identities
+impl IdentityStore for () {
+    fn permanent_identity(&mut self, _pseudonym: &[u8]) -> Option<Identity> {
+        None
+    }
+
+    fn store_pseudonym(&mut self, _pseudonym: &[u8], _permanent_identity: &[u8]) {}
+
+    fn load_reauth(&mut self, _identity: &[u8]) -> Option<ReauthContext> {
+        None
+    }
+
+    fn store_reauth(&mut self, _context: &ReauthContext) {}
+
+    fn remove_reauth(&mut self, _identity: &[u8]) {}
+}
+
+/// Volatile storage, all identities are lost on restart
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+pub struct MemoryIdentityStore {
+    pseudonyms: std::collections::HashMap<Vec<u8>, Identity>,
+    reauth: std::collections::HashMap<Vec<u8>, ReauthContext>,
+}
+
+#[cfg(feature = "std")]
+impl MemoryIdentityStore {
+    pub fn clear(&mut self) {
+        self.pseudonyms.clear();
+        self.reauth.clear();
+    }
+}
+
+#[cfg(feature = "std")]
+impl IdentityStore for MemoryIdentityStore {
+    fn permanent_identity(&mut self, pseudonym: &[u8]) -> Option<Identity> {
+        self.pseudonyms.get(pseudonym).cloned()
+    }
+
+    fn store_pseudonym(&mut self, pseudonym: &[u8], permanent_identity: &[u8]) {
+        self.pseudonyms
+            .insert(pseudonym.to_vec(), Identity::from(permanent_identity));
+    }
+
+    fn load_reauth(&mut self, identity: &[u8]) -> Option<ReauthContext> {
+        self.reauth.get(identity).cloned()
+    }
+
+    fn store_reauth(&mut self, context: &ReauthContext) {
+        self.reauth
+            .insert(context.identity().to_vec(), context.clone());
+    }
+
+    fn remove_reauth(&mut self, identity: &[u8]) {
+        self.reauth.remove(identity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::hex_to_vec;
+
+    #[test]
+    fn fips186_2_prf_vector() {
+        // FIPS 186-2 change notice 1, Appendix 3.1 example without XSEED
+        let key: [u8; MK_LEN] =
+            hex_to_vec("bd 02 9b be 7f 51 96 0b cf 9e db 2b 61 f0 6f 0f eb 5a 38 b6")
+                .try_into()
+                .unwrap();
+        let mut out = [0u8; 40];
+        fips186_2_prf(&key, &mut out);
+        assert_eq!(
+            out.to_vec(),
+            hex_to_vec(
+                "20 70 b3 22 3d ba 37 2f de 1c 0f fc 7b 2e 3b 49 8b 26 06 14 \
+                 3c 6c 18 ba cb 0f 6c 55 ba bb 13 78 8e 20 d7 37 a3 27 51 16"
+            )
+        );
+    }
+
+    #[test]
+    fn attributes() {
+        let mut writer = Writer::new(1);
+        writer
+            .reserved(AT_RAND, &[1; 16])
+            .prefixed(AT_IDENTITY, b"0123")
+            .u16(AT_COUNTER, 7)
+            .reserved(200, b"skipped")
+            .mac();
+        let data = writer.as_slice();
+        assert_eq!(data.len() % 4, 3);
+
+        let attributes = Attributes::parse(&data[3..], 3).unwrap();
+        assert_eq!(attributes.rand, Some(&[1; 16][..]));
+        assert_eq!(attributes.identity, Some(&b"0123"[..]));
+        assert_eq!(attributes.counter, Some(7));
+        assert_eq!(attributes.mac, Some(data.len() - MAC_LEN));
+
+        // Unknown attribute that must not be skipped
+        assert!(Attributes::parse(&[100, 1, 0, 0], 0).is_none());
+        // Zero length
+        assert!(Attributes::parse(&[AT_RAND, 0, 0, 0], 0).is_none());
+    }
+
+    #[test]
+    fn encrypted_attributes() {
+        let k_encr = [3; 16];
+        let iv = [4; 16];
+        let mut plaintext = Writer::nested();
+        plaintext
+            .prefixed(AT_NEXT_PSEUDONYM, b"2pseudonym")
+            .u16(AT_COUNTER, 1);
+
+        let mut writer = Writer::new(1);
+        writer.encrypted(&k_encr, &iv, plaintext);
+        let attributes = Attributes::parse(&writer.as_slice()[3..], 3).unwrap();
+        assert_eq!(attributes.encr_data.unwrap().len(), 32);
+
+        let decrypted = Decrypted::new(&k_encr, &attributes).unwrap();
+        let inner = decrypted.attributes().unwrap();
+        assert_eq!(inner.next_pseudonym, Some(&b"2pseudonym"[..]));
+        assert_eq!(inner.counter, Some(1));
+    }
+
+    #[test]
+    fn temporary_identities() {
+        let identity = temporary_identity(b'2', b"0123@example.org", &[0xab; 8]);
+        assert_eq!(identity.as_ref(), b"2abababababababab@example.org");
+        assert_eq!(username(identity.as_ref()), b"2abababababababab");
+    }
+}
@@ -0,0 +1,71 @@
+//! Helpers shared by the unit tests of several modules
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::{
+    layers::{
+        eap_layer::{EapStatus, PeerAuthLayer},
+        EapLayer,
+    },
+    StaticEnvironment,
+};
+
+/// Runs a conversation of `peer` and `auth`, each in a `StaticEnvironment`,
+/// for at most `max_messages` messages after the first Request. Both have to
+/// finish with the same keys.
+pub(crate) fn assert_static_conversation<P, A>(
+    mut peer: EapLayer<P>,
+    mut auth: EapLayer<A>,
+    max_messages: usize,
+) where
+    P: PeerAuthLayer,
+    A: PeerAuthLayer,
+{
+    fn counting_random(buf: &mut [u8]) {
+        // Deterministic, good enough for a test
+        static COUNTER: AtomicU8 = AtomicU8::new(0);
+        for b in buf.iter_mut() {
+            *b = COUNTER.fetch_add(29, Ordering::Relaxed);
+        }
+    }
+
+    let mut peer_env = StaticEnvironment::<1020>::new(counting_random);
+    let mut auth_env = StaticEnvironment::<1020>::new(counting_random);
+
+    let _ = peer.start(&mut peer_env);
+    let mut buffer = [0u8; 1020];
+    let mut len = {
+        let out = auth.start(&mut auth_env);
+        let msg = out.message.unwrap();
+        buffer[..msg.as_ref().len()].copy_from_slice(msg.as_ref());
+        msg.as_ref().len()
+    };
+
+    let mut to_peer = true;
+    for _ in 0..max_messages {
+        let out = if to_peer {
+            peer.receive(&buffer[..len], &mut peer_env)
+        } else {
+            auth.receive(&buffer[..len], &mut auth_env)
+        };
+        to_peer = !to_peer;
+
+        match out.message {
+            Some(msg) => {
+                len = msg.as_ref().len();
+                buffer[..len].copy_from_slice(msg.as_ref());
+            }
+            None => break,
+        }
+    }
+
+    assert_eq!(
+        auth.session_keys().map(|k| (k.msk, k.session_id.clone())),
+        peer.session_keys().map(|k| (k.msk, k.session_id.clone()))
+    );
+    assert!(auth
+        .session_keys()
+        .is_some_and(|k| !k.session_id.as_ref().is_empty()));
+    assert!(peer.is_finished());
+    assert_eq!(auth.receive(&[], &mut auth_env).status, EapStatus::Success);
+}
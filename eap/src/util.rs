@@ -25,6 +25,12 @@ impl<const N: usize> OwnedSlice<N> {
     }
 }
 
+impl<const N: usize> Default for OwnedSlice<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<const N: usize> TryFrom<&[u8]> for OwnedSlice<N> {
     type Error = ();
 
@@ -60,3 +66,78 @@ impl<const N: usize> AsRef<[u8]> for OwnedSlice<N> {
         }
     }
 }
+
+/// Cursor over a received message, all reads fail instead of panicking
+/// if the message is too short.
+#[derive(Clone, Copy, Debug)]
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Some(head)
+    }
+
+    pub fn take_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N).map(|data| data.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.take_array::<1>().map(|[b]| b)
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        self.take_array().map(u16::from_be_bytes)
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        self.take_array().map(u32::from_be_bytes)
+    }
+
+    /// Reads a field prefixed with a two byte length
+    pub fn u16_prefixed(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()? as usize;
+        self.take(len)
+    }
+}
+
+/// Compares MACs and other secrets, the time taken does not depend on where
+/// they differ
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_reader() {
+        let mut reader = ByteReader::new(&[1, 0, 2, 3, 4, 5]);
+        assert_eq!(reader.u8(), Some(1));
+        assert_eq!(reader.u16_prefixed(), Some(&[3, 4][..]));
+        assert_eq!(reader.u16(), None);
+        assert_eq!(reader.remaining(), &[5]);
+        assert_eq!(reader.take(1), Some(&[5][..]));
+        assert!(reader.is_empty());
+    }
+}
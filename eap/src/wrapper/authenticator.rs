@@ -1,9 +1,14 @@
 use crate::{
+    environment::response_capacity,
+    erp::Erp,
     layers::{
         self,
-        auth::{AuthIdentityMethod, AuthMD5ChallengeMethod, AuthMethodLayer},
+        auth::{
+            auth_layer::NotificationTooLong, AuthIdentityMethod, AuthMD5ChallengeMethod,
+            AuthMethodLayer,
+        },
         mux::TupleById,
-        AuthLayer, EapLayer,
+        AuthLayer, EapLayer, Negotiation,
     },
     DefaultEnvironment,
 };
@@ -12,13 +17,77 @@ pub use common::EapStepResult as AuthenticatorStepResult;
 pub use common::EapStepStatus as AuthenticatorStepStatus;
 pub use common::EapWrapper;
 
-
-pub struct Authenticator<I> {
+pub struct Authenticator<I, E = ()> {
     env: DefaultEnvironment,
-    inner: EapLayer<AuthLayer<I>>,
+    inner: EapLayer<AuthLayer<I>, E>,
     buffer: Vec<u8>,
 }
 
+impl<I> Authenticator<I>
+where
+    I: TupleById<dyn AuthMethodLayer>,
+{
+    pub fn from_layer(layer: AuthLayer<I>) -> Self {
+        Self {
+            inner: EapLayer::new(layer),
+            env: DefaultEnvironment::new(),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<I, E> Authenticator<I, E>
+where
+    I: TupleById<dyn AuthMethodLayer>,
+    E: Erp,
+{
+    /// Offers re-authentication, see [`EapLayer::with_erp`]
+    pub fn with_erp<F: Erp>(self, erp: F) -> Authenticator<I, F> {
+        Authenticator {
+            env: self.env,
+            inner: self.inner.with_erp(erp),
+            buffer: self.buffer,
+        }
+    }
+
+    /// Queues a Notification Request, see [`AuthLayer::notify`]. The text
+    /// has to fit in one Request of the MTU of the environment.
+    pub fn notify(&mut self, text: &str) -> Result<(), NotificationTooLong> {
+        if text.len() >= response_capacity(&self.env) {
+            return Err(NotificationTooLong);
+        }
+        self.inner.layer_mut().notify(text.as_bytes())
+    }
+
+    /// `clock` returns milliseconds of a monotonic clock, steps without a
+    /// message are then timeouts only once [`Self::next_deadline`] passed
+    pub fn set_clock(&mut self, clock: impl Fn() -> u64 + 'static) {
+        self.env.set_clock(clock);
+    }
+
+    /// `unix_clock` returns seconds since the unix epoch, for time based one
+    /// time passwords, see [`EapEnvironment::unix_time`](crate::EapEnvironment::unix_time)
+    pub fn set_unix_clock(&mut self, unix_clock: impl Fn() -> u64 + 'static) {
+        self.env.set_unix_clock(unix_clock);
+    }
+
+    /// `callback` receives the outcome of the method negotiation, see
+    /// [`EapEnvironment::negotiation`](crate::EapEnvironment::negotiation)
+    pub fn set_negotiation_callback(&mut self, callback: impl FnMut(&Negotiation) + 'static) {
+        self.env.set_negotiation_callback(callback);
+    }
+
+    /// See [`EapLayer::next_deadline`]
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.inner.next_deadline()
+    }
+
+    /// See [`AuthLayer::negotiation`]
+    pub fn negotiation(&self) -> Option<&Negotiation> {
+        self.inner.layer().negotiation()
+    }
+}
+
 pub type MD5Authenticator = Authenticator<(AuthIdentityMethod, AuthMD5ChallengeMethod)>;
 impl Authenticator<(AuthIdentityMethod, AuthMD5ChallengeMethod)> {
     pub fn new_password(password: &str) -> Self {
@@ -53,9 +122,10 @@ impl Authenticator<(AuthIdentityMethod, crate::eap_rustls::AuthTlsMethod)> {
     }
 }
 
-impl<I> EapWrapper for Authenticator<I>
+impl<I, E> EapWrapper for Authenticator<I, E>
 where
     I: TupleById<dyn AuthMethodLayer>,
+    E: Erp,
 {
     fn receive(&mut self, data: &[u8]) {
         self.buffer = data.to_vec();
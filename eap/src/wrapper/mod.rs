@@ -44,9 +44,13 @@ mod tests {
             } = auth.step();
             let auth_response = auth_response.map(|m| m.to_vec());
 
+            let mut peer_status = peer_status;
             if let Some(response) = auth_response {
                 if rng.gen::<f32>() > package_drop_rate {
                     peer.receive(&response);
+                } else if auth_status == AuthenticatorStepStatus::Finished {
+                    // The EAP-Success was lost, the lower layer still signals success
+                    peer_status = peer.lower_layer_success();
                 }
             }
 
@@ -80,6 +84,25 @@ mod tests {
         assert_eq!(auth_res, AuthenticatorStepStatus::Error);
     }
 
+    #[test]
+    fn test_wrapper_notification() {
+        let mut peer = Peer::new_password("testuser", "pasword123");
+        let mut auth = Authenticator::new_password("pasword123");
+
+        let received = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        peer.set_notification_callback({
+            let received = received.clone();
+            move |text| received.borrow_mut().push(text.to_vec())
+        });
+        auth.notify("Password expires soon").unwrap();
+
+        let (peer_res, auth_res) = run(&mut peer, &mut auth, None);
+
+        assert_eq!(peer_res, PeerStepStatus::Finished);
+        assert_eq!(auth_res, AuthenticatorStepStatus::Finished);
+        assert_eq!(*received.borrow(), vec![b"Password expires soon".to_vec()]);
+    }
+
     #[test]
     fn test_wrapper_package_loss() {
         let mut peer = Peer::new_password("testuser", "pasword123");
@@ -105,9 +128,6 @@ mod tests {
             match (peer_res, auth_res) {
                 (PeerStepStatus::Finished, AuthenticatorStepStatus::Finished) => success += 1,
                 (PeerStepStatus::Error, AuthenticatorStepStatus::Error) => {}
-                (PeerStepStatus::Error, AuthenticatorStepStatus::Finished) => {
-                    // Success packet was lost
-                }
                 _ => {
                     panic!("Unexpected result: ({peer_res:?}, {auth_res:?})");
                 }
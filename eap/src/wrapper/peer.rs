@@ -2,18 +2,19 @@
 use dummycert::TlsConfig;
 
 use crate::{
+    erp::Erp,
     layers::{
         eap_layer::EapStatus,
         mux::TupleById,
         peer::{peer_layer::PeerMethodLayer, PeerIdentityMethod, PeerMD5ChallengeMethod},
-        EapLayer, PeerLayer,
+        EapLayer, MethodType, Negotiation, PeerLayer,
     },
     DefaultEnvironment,
 };
 
-pub struct Peer<I> {
+pub struct Peer<I, E = ()> {
     env: DefaultEnvironment,
-    inner: EapLayer<PeerLayer<I>>,
+    inner: EapLayer<PeerLayer<I>, E>,
     buffer: Vec<u8>,
 }
 
@@ -21,6 +22,87 @@ pub use common::EapStepResult as PeerStepResult;
 pub use common::EapStepStatus as PeerStepStatus;
 pub use common::EapWrapper;
 
+impl<I> Peer<I>
+where
+    I: TupleById<dyn PeerMethodLayer>,
+{
+    pub fn from_layer(layer: PeerLayer<I>) -> Self {
+        Self {
+            inner: EapLayer::new(layer),
+            env: DefaultEnvironment::new(),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<I, E> Peer<I, E>
+where
+    I: TupleById<dyn PeerMethodLayer>,
+    E: Erp,
+{
+    /// Re-authenticates when offered, see [`EapLayer::with_erp`]
+    pub fn with_erp<F: Erp>(self, erp: F) -> Peer<I, F> {
+        Peer {
+            env: self.env,
+            inner: self.inner.with_erp(erp),
+            buffer: self.buffer,
+        }
+    }
+
+    /// `callback` receives the text of each Notification Request
+    pub fn set_notification_callback(&mut self, callback: impl FnMut(&[u8]) + 'static) {
+        self.env.set_notification_callback(callback);
+    }
+
+    /// `callback` receives the outcome of the method negotiation, see
+    /// [`EapEnvironment::negotiation`](crate::EapEnvironment::negotiation)
+    pub fn set_negotiation_callback(&mut self, callback: impl FnMut(&Negotiation) + 'static) {
+        self.env.set_negotiation_callback(callback);
+    }
+
+    /// `clock` returns milliseconds of a monotonic clock, steps without a
+    /// message are then timeouts only once [`Self::next_deadline`] passed
+    pub fn set_clock(&mut self, clock: impl Fn() -> u64 + 'static) {
+        self.env.set_clock(clock);
+    }
+
+    /// See [`EapLayer::next_deadline`]
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.inner.next_deadline()
+    }
+
+    /// See [`PeerLayer::downgrade_attempt`]
+    pub fn downgrade_attempt(&self) -> Option<MethodType> {
+        self.inner.layer().downgrade_attempt()
+    }
+
+    /// See [`PeerLayer::negotiation`]
+    pub fn negotiation(&self) -> Option<&Negotiation> {
+        self.inner.layer().negotiation()
+    }
+
+    /// Success indication of the lower layer, e.g. keys confirmed by the
+    /// 4-way handshake. Finishes without EAP-Success if the method allows
+    /// it, see [`EapLayer::alt_accept`]
+    pub fn lower_layer_success(&mut self) -> PeerStepStatus {
+        status(&self.inner.alt_accept().status)
+    }
+
+    /// Failure indication of the lower layer, see [`EapLayer::alt_reject`]
+    pub fn lower_layer_failure(&mut self) -> PeerStepStatus {
+        status(&self.inner.alt_reject().status)
+    }
+}
+
+fn status(status: &EapStatus) -> PeerStepStatus {
+    match status {
+        EapStatus::Ok => PeerStepStatus::Ok,
+        EapStatus::Success => PeerStepStatus::Finished,
+        EapStatus::Failed(_) => PeerStepStatus::Error,
+        EapStatus::InternalError(_) => PeerStepStatus::Error,
+    }
+}
+
 pub type MD5Peer = Peer<(PeerIdentityMethod, PeerMD5ChallengeMethod)>;
 impl Peer<(PeerIdentityMethod, PeerMD5ChallengeMethod)> {
     pub fn new_password(identity: &str, password: &str) -> Self {
@@ -43,7 +125,6 @@ impl Peer<(PeerIdentityMethod, PeerMD5ChallengeMethod)> {
 #[cfg(feature = "tls")]
 pub type TlsPeer = Peer<(PeerIdentityMethod, crate::eap_rustls::PeerTlsMethod)>;
 
-
 #[cfg(feature = "tls")]
 impl Peer<(PeerIdentityMethod, crate::eap_rustls::PeerTlsMethod)> {
     pub fn new_tls(identity: &str, config: TlsConfig) -> Self {
@@ -61,9 +142,10 @@ impl Peer<(PeerIdentityMethod, crate::eap_rustls::PeerTlsMethod)> {
     }
 }
 
-impl<I> EapWrapper for Peer<I>
+impl<I, E> EapWrapper for Peer<I, E>
 where
     I: TupleById<dyn PeerMethodLayer>,
+    E: Erp,
 {
     fn receive(&mut self, data: &[u8]) {
         self.buffer = data.to_vec();
@@ -94,12 +176,7 @@ where
         };
 
         PeerStepResult {
-            status: match res.status {
-                EapStatus::Ok => PeerStepStatus::Ok,
-                EapStatus::Success => PeerStepStatus::Finished,
-                EapStatus::Failed(_) => PeerStepStatus::Error,
-                EapStatus::InternalError(_) => PeerStepStatus::Error,
-            },
+            status: status(&res.status),
             response: res.message.map(|m| m.into_slice()),
         }
     }
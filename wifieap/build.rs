@@ -24,17 +24,44 @@ const SOURCE_LIBS: &[&str] = &[
 
 // This needs (tag) hostap_2_9  (ca8c2bd28), later versions seem to fail.
 
+// The sublibs are built with these, EAP_AKA_PRIME adds the AKA' key derivation
+// to eap_common/eap_sim_common.c
+const MAKE_CFLAGS: &str = "-MMD -O2 -Wall -g -DEAP_AKA_PRIME";
+
 // Adapted from Makefile
 const PEER_OBJECTS: &[&str] = &[
     "eap_peer/eap_tls.c",
+    "eap_peer/eap_aka.c",
+    "eap_peer/eap_sim.c",
     "eap_peer/eap_md5.c",
+    "eap_peer/eap_eke.c",
+    "eap_peer/eap_ikev2.c",
+    "eap_peer/ikev2.c",
+    "eap_peer/eap_mschapv2.c",
+    "eap_peer/mschapv2.c",
+    "eap_peer/eap_gtc.c",
+    "eap_peer/eap_psk.c",
+    "eap_peer/eap_gpsk.c",
+    "eap_peer/eap_teap.c",
+    "eap_peer/eap_teap_pac.c",
+    "eap_common/eap_teap_common.c",
     "eap_peer/eap_tls_common.c",
+    "eap_peer/eap_wsc.c",
 ];
 
 const SERVER_OBJECTS: &[&str] = &[
     "eap_server/eap_server_tls.c",
     "eap_server/eap_server_md5.c",
+    "eap_server/eap_server_eke.c",
+    "eap_server/eap_server_ikev2.c",
+    "eap_server/ikev2.c",
+    "eap_server/eap_server_mschapv2.c",
+    "eap_server/eap_server_gtc.c",
+    "eap_server/eap_server_psk.c",
+    "eap_server/eap_server_gpsk.c",
+    "eap_server/eap_server_teap.c",
     "eap_server/eap_server_tls_common.c",
+    "eap_server/eap_server_wsc.c",
 ];
 
 // adapted from hostapd Makefile
@@ -44,7 +71,32 @@ const OPENSSL_OBJECTS: &[&str] = &[
     "crypto/random.c",         //
 ];
 
+// Crypto of the methods that crypto_openssl.c lacks, adapted from the
+// wpa_supplicant Makefile. Linked after the wrapper, so these only fill gaps.
+const CRYPTO_OBJECTS: &[&str] = &[
+    "crypto/aes-ctr.c",
+    "crypto/aes-eax.c",
+    "crypto/aes-encblock.c",
+    "crypto/aes-omac1.c",
+    "crypto/dh_groups.c",
+    "crypto/fips_prf_openssl.c",
+    "crypto/milenage.c",
+    "crypto/ms_funcs.c",
+    "crypto/sha256-tlsprf.c",
+    "crypto/sha384-tlsprf.c",
+];
+
 fn main() {
+    println!("cargo:rustc-check-cfg=cfg(hostap_missing)");
+    println!("cargo:rerun-if-changed={SOURCE_DIR}Makefile");
+
+    // Without the submodule only stand-ins are built, see `HOSTAP_AVAILABLE`
+    if !PathBuf::from(SOURCE_DIR).join("Makefile").exists() {
+        println!("cargo:warning=hostap submodule is not checked out, interop tests are skipped");
+        println!("cargo:rustc-cfg=hostap_missing");
+        return;
+    }
+
     build_hostap();
     bindgen_hostap();
 }
@@ -54,6 +106,7 @@ fn build_hostap() {
 
     Command::new("make")
         .arg("CONFIG_TLSV12=y")
+        .env("CFLAGS", MAKE_CFLAGS)
         .current_dir(SOURCE_DIR)
         .status()
         .expect("Failed running make to build");
@@ -74,6 +127,7 @@ fn build_hostap() {
     lib_from_objects("methods_peer", PEER_OBJECTS);
     lib_from_objects("methods_server", SERVER_OBJECTS);
     lib_from_objects("openssl_wrapper", OPENSSL_OBJECTS);
+    lib_from_objects("crypto_extra", CRYPTO_OBJECTS);
 }
 
 fn patch_dh_openssl_issue() {
@@ -117,6 +171,7 @@ fn patch_makefile(sublib: &str) {
         .expect("Failed running make to clean");
 
     Command::new("make")
+        .env("CFLAGS", MAKE_CFLAGS)
         .current_dir(&lib_path)
         .status()
         .expect("Failed running make");
@@ -127,6 +182,10 @@ fn lib_from_objects(label: &str, files: &[&str]) {
     build.warnings(false);
     build.flag("-w");
     build.flag("-DTLS_DEFAULT_CIPHERS=\"DEFAULT\"");
+    build.flag("-DCONFIG_SHA384"); // TEAP cipher suites with SHA-384
+    build.flag("-DEAP_AKA_PRIME");
+    build.flag("-DCONFIG_USIM_SIMULATOR"); // Milenage with K:OPc:SQN as password
+    build.flag("-DCONFIG_SIM_SIMULATOR"); // GSM-Milenage with K:OPc as password
 
     for f in files {
         build.file(PathBuf::from(SOURCE_DIR).join(f).canonicalize().unwrap());
@@ -5,4 +5,6 @@
 #include "eap_peer/eap.h"
 #include "eap_peer/eap_config.h"
 #include "crypto/tls.h"
-#include "wpabuf.h"
\ No newline at end of file
+#include "wpabuf.h"
+#include "wps/wps.h"
+#include "utils/eloop.h"
\ No newline at end of file
@@ -4,4 +4,6 @@
 #include "common.h"
 #include "eap_server/eap.h"
 #include "crypto/tls.h"
-#include "wpabuf.h"
\ No newline at end of file
+#include "wpabuf.h"
+#include "wps/wps.h"
+#include "utils/eloop.h"
\ No newline at end of file
@@ -1,3 +1,4 @@
+#[cfg(not(hostap_missing))]
 #[allow(
     non_camel_case_types,
     non_upper_case_globals,
@@ -7,6 +8,7 @@
 )] // improper_ctypes for u128 values
 pub(crate) mod bindings_peer;
 
+#[cfg(not(hostap_missing))]
 #[allow(
     non_camel_case_types,
     non_upper_case_globals,
@@ -18,15 +20,35 @@ pub(crate) mod bindings_server;
 
 pub mod peer;
 pub mod server;
+#[cfg(not(hostap_missing))]
 pub mod util;
 //
-#[cfg(test)]
+#[cfg(all(test, not(hostap_missing)))]
 mod test;
 
+/// Whether hostap was built. Without the submodule, `EapPeer` and `EapServer`
+/// panic when they are built and the interop tests skip themselves.
+pub const HOSTAP_AVAILABLE: bool = !cfg!(hostap_missing);
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum EapMethod {
     TLS,
     MD5,
+    WSC,
+    GTC,
+    PSK,
+    GPSK,
+    TEAP,
+    MSCHAPV2,
+    EKE,
+    IKEV2,
+}
+
+/// Network credential a WPS Enrollee received from the Registrar
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WpsCredential {
+    pub ssid: Vec<u8>,
+    pub network_key: Vec<u8>,
 }
 
 pub use dummycert::TlsConfig;
@@ -1,12 +1,22 @@
-use common::{EapStepResult, EapStepStatus, EapWrapper};
+#[cfg(not(hostap_missing))]
+use common::EapStepStatus;
+use common::{EapStepResult, EapWrapper};
+#[cfg(not(hostap_missing))]
 use std::{collections::HashMap, ffi::c_void, sync::Once};
-use tempfile::NamedTempFile;
+#[cfg(not(hostap_missing))]
+use tempfile::{NamedTempFile, TempDir};
 
+#[cfg(not(hostap_missing))]
 pub use crate::bindings_peer::*;
-use crate::TlsConfig;
+use crate::{TlsConfig, WpsCredential};
 
+/// Identity a WPS Enrollee has to use
+const WSC_ID_ENROLLEE: &str = "WFA-SimpleConfig-Enrollee-1-0";
+
+#[cfg(not(hostap_missing))]
 static PEER_INIT: Once = Once::new();
 
+#[cfg(not(hostap_missing))]
 pub struct EapPeer {
     callbacks: Box<eapol_callbacks>,
     config: Box<eap_config>,
@@ -18,15 +28,32 @@ pub struct EapPeer {
     state_bool: HashMap<eapol_bool_var, bool>,
     state_int: HashMap<eapol_int_var, u32>,
     _temp_files: Vec<NamedTempFile>,
+    _pac_dir: Option<TempDir>,
+    _wps_state: Option<Box<EapPeerWpsState>>,
 
     response_buffer: Vec<u8>,
     final_status: Option<EapStepStatus>,
 }
 
+/// Stand-in without hostap, see [`crate::HOSTAP_AVAILABLE`]
+#[cfg(hostap_missing)]
+pub struct EapPeer;
+
+// This is keep around, hostap points into it
+#[cfg(not(hostap_missing))]
+struct EapPeerWpsState {
+    context: Box<wps_context>,
+    on_credential: Box<dyn FnMut(WpsCredential)>,
+}
+
+#[cfg_attr(hostap_missing, allow(dead_code))]
 pub struct EapPeerBuilder {
     identity: String,
     password: Option<String>,
     tls_config: Option<TlsConfig>,
+    phase2: Option<String>,
+    wps_pin: Option<Option<String>>,
+    on_wps_credential: Option<Box<dyn FnMut(WpsCredential)>>,
 }
 
 impl EapPeerBuilder {
@@ -35,6 +62,9 @@ impl EapPeerBuilder {
             identity: identity.to_string(),
             password: None,
             tls_config: None,
+            phase2: None,
+            wps_pin: None,
+            on_wps_credential: None,
         }
     }
 
@@ -48,6 +78,24 @@ impl EapPeerBuilder {
         self
     }
 
+    /// Inner methods of a tunnel method, e.g. `auth=MD5`. The identity and
+    /// password are used inside the tunnel.
+    pub fn set_phase2(&mut self, phase2: &str) -> &mut Self {
+        self.phase2 = Some(phase2.to_string());
+        self
+    }
+
+    /// Enrollee with a PIN, or push button if `pin` is `None`
+    pub fn set_wps(
+        &mut self,
+        pin: Option<&str>,
+        on_credential: impl FnMut(WpsCredential) + 'static,
+    ) -> &mut Self {
+        self.wps_pin = Some(pin.map(str::to_string));
+        self.on_wps_credential = Some(Box::new(on_credential));
+        self
+    }
+
     pub fn build(&mut self) -> Box<EapPeer> {
         EapPeer::new(self)
     }
@@ -70,15 +118,51 @@ impl EapPeer {
         builder.build()
     }
 
-    fn new(builder: &EapPeerBuilder) -> Box<Self> {
-        PEER_INIT.call_once(|| {
-            unsafe {
-                wpa_debug_level = 0;
+    pub fn new_wps(
+        pin: Option<&str>,
+        on_credential: impl FnMut(WpsCredential) + 'static,
+    ) -> Box<EapPeer> {
+        let mut builder = EapPeerBuilder::new(WSC_ID_ENROLLEE);
+        builder.set_wps(pin, on_credential);
+        builder.build()
+    }
+}
 
-                //assert!(eap_peer_mschapv2_register() == 0);
-                assert!(eap_peer_md5_register() == 0);
-                assert!(eap_peer_tls_register() == 0);
-            }
+#[cfg(hostap_missing)]
+impl EapPeer {
+    fn new(_builder: &mut EapPeerBuilder) -> Box<Self> {
+        panic!("hostap submodule is not checked out")
+    }
+}
+
+#[cfg(hostap_missing)]
+impl EapWrapper for Box<EapPeer> {
+    fn receive(&mut self, _msg: &[u8]) {}
+
+    fn step(&mut self) -> EapStepResult<'_> {
+        unreachable!()
+    }
+}
+
+#[cfg(not(hostap_missing))]
+impl EapPeer {
+    fn new(builder: &mut EapPeerBuilder) -> Box<Self> {
+        PEER_INIT.call_once(|| unsafe {
+            wpa_debug_level = 0;
+
+            assert!(eap_peer_mschapv2_register() == 0);
+            assert!(eap_peer_md5_register() == 0);
+            assert!(eap_peer_eke_register() == 0);
+            assert!(eap_peer_ikev2_register() == 0);
+            assert!(eap_peer_gtc_register() == 0);
+            assert!(eap_peer_psk_register() == 0);
+            assert!(eap_peer_gpsk_register() == 0);
+            assert!(eap_peer_teap_register() == 0);
+            assert!(eap_peer_tls_register() == 0);
+            assert!(eap_peer_aka_register() == 0);
+            assert!(eap_peer_aka_prime_register() == 0);
+            assert!(eap_peer_sim_register() == 0);
+            assert!(eap_peer_wsc_register() == 0);
         });
 
         // ! BOX, should not be moved
@@ -101,7 +185,7 @@ impl EapPeer {
 
         let mut peer_config: Box<eap_peer_config> = Box::new(unsafe { std::mem::zeroed() });
         peer_config.fragment_size = 1400; // <- needs to be set, otherwise it get stuck sending 0 sized fragments.
-        let config: Box<eap_config> = Box::new(unsafe { std::mem::zeroed() });
+        let mut config: Box<eap_config> = Box::new(unsafe { std::mem::zeroed() });
 
         // Identity
         unsafe {
@@ -130,6 +214,57 @@ impl EapPeer {
             vec![]
         };
 
+        // Tunnel, TEAP needs a PAC file. One that doesn't exist yet holds no
+        // PAC, an empty one is rejected.
+        let pac_dir = if let Some(phase2) = &builder.phase2 {
+            let pac_dir = TempDir::new().unwrap();
+            let pac_file = pac_dir.path().join("pac");
+            unsafe {
+                peer_config.phase2 = crate::util::malloc_str(phase2).0 as _;
+                peer_config.pac_file = crate::util::malloc_str(pac_file.to_str().unwrap()).0 as _;
+            }
+
+            Some(pac_dir)
+        } else {
+            None
+        };
+
+        // WPS
+        let wps_state = if let Some(pin) = &builder.wps_pin {
+            crate::util::init_eloop();
+
+            let mut context: Box<wps_context> = Box::new(unsafe { std::mem::zeroed() });
+            context.uuid = [0x22; 16];
+            context.dev.mac_addr = [0x02, 0, 0, 0, 0, 0x22];
+            context.dev.pri_dev_type = [0x00, 0x01, 0x00, 0x50, 0xf2, 0x04, 0x00, 0x01];
+            context.dev.rf_bands = WPS_RF_24GHZ as _;
+            context.config_methods = (WPS_CONFIG_DISPLAY | WPS_CONFIG_VIRT_PUSHBUTTON) as _;
+            unsafe {
+                context.dev.device_name = crate::util::malloc_str("wpa_supplicant").0 as _;
+                context.dev.manufacturer = crate::util::malloc_str("hostap").0 as _;
+            }
+
+            let mut state = Box::new(EapPeerWpsState {
+                context,
+                on_credential: builder.on_wps_credential.take().unwrap(),
+            });
+            state.context.cred_cb = Some(Self::wps_credential);
+            state.context.cb_ctx = state.as_mut() as *mut EapPeerWpsState as *mut c_void;
+            config.wps = state.context.as_mut();
+
+            let phase1 = match pin {
+                Some(pin) => format!("pin={pin}"),
+                None => "pbc=1".to_string(),
+            };
+            unsafe {
+                peer_config.phase1 = crate::util::malloc_str(&phase1).0 as _;
+            }
+
+            Some(state)
+        } else {
+            None
+        };
+
         let wpabuf: *mut wpabuf = unsafe { wpabuf_alloc(0) };
         assert!(!wpabuf.is_null());
 
@@ -142,6 +277,8 @@ impl EapPeer {
             state_bool: HashMap::new(),
             state_int: HashMap::new(),
             _temp_files: temp_files,
+            _pac_dir: pac_dir,
+            _wps_state: wps_state,
             response_buffer: vec![],
             final_status: None,
         });
@@ -207,8 +344,19 @@ impl EapPeer {
     unsafe extern "C" fn notify_pending(_ctx: *mut c_void) {
         // NOP
     }
+
+    unsafe extern "C" fn wps_credential(ctx: *mut c_void, cred: *const wps_credential) -> i32 {
+        let state = &mut *(ctx as *mut EapPeerWpsState);
+        let cred = &*cred;
+        (state.on_credential)(WpsCredential {
+            ssid: cred.ssid[..cred.ssid_len].to_vec(),
+            network_key: cred.key[..cred.key_len].to_vec(),
+        });
+        0
+    }
 }
 
+#[cfg(not(hostap_missing))]
 impl EapWrapper for Box<EapPeer> {
     fn receive(&mut self, msg: &[u8]) {
         self.state_bool.insert(eapol_bool_var_EAPOL_eapReq, true);
@@ -284,6 +432,7 @@ impl EapWrapper for Box<EapPeer> {
     }
 }
 
+#[cfg(not(hostap_missing))]
 impl Drop for EapPeer {
     fn drop(&mut self) {
         unsafe {
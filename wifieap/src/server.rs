@@ -1,22 +1,42 @@
-use common::{EapStepResult, EapStepStatus, EapWrapper};
+#[cfg(not(hostap_missing))]
+use common::EapStepStatus;
+use common::{EapStepResult, EapWrapper};
 
+#[cfg(not(hostap_missing))]
 pub use crate::bindings_server::*;
+#[cfg(not(hostap_missing))]
 use crate::util;
 use crate::{EapMethod, TlsConfig};
 
+use std::collections::HashMap;
+#[cfg(not(hostap_missing))]
 use std::{
-    collections::HashMap,
     ffi::{c_int, c_void},
     sync::Once,
 };
 
+#[cfg(not(hostap_missing))]
 static SERVER_INIT: Once = Once::new();
 
+/// Identity of the server in methods that exchange one
+pub const SERVER_ID: &str = "hostapd";
+
 #[derive(Default, Clone)]
+#[cfg_attr(hostap_missing, allow(dead_code))]
 pub struct EapServerBuilder {
     passwords: HashMap<String, String>,
     tls_config: Option<TlsConfig>,
+    wps_config: Option<WpsConfig>,
     method_priorities: Vec<EapMethod>,
+    phase2_methods: Vec<EapMethod>,
+}
+
+#[derive(Clone)]
+#[cfg_attr(hostap_missing, allow(dead_code))]
+struct WpsConfig {
+    ssid: Vec<u8>,
+    network_key: String,
+    pin: Option<String>,
 }
 
 impl EapServerBuilder {
@@ -35,6 +55,17 @@ impl EapServerBuilder {
         self
     }
 
+    /// Registrar handing out a WPA2-PSK network, to Enrollees with the PIN or
+    /// by push button if `pin` is `None`
+    pub fn set_wps(&mut self, ssid: &[u8], network_key: &str, pin: Option<&str>) -> &mut Self {
+        self.wps_config = Some(WpsConfig {
+            ssid: ssid.to_vec(),
+            network_key: network_key.to_string(),
+            pin: pin.map(str::to_string),
+        });
+        self
+    }
+
     pub fn allow_md5(&mut self) -> &mut Self {
         self.allow_method(EapMethod::MD5)
     }
@@ -43,6 +74,40 @@ impl EapServerBuilder {
         self.allow_method(EapMethod::TLS)
     }
 
+    pub fn allow_wsc(&mut self) -> &mut Self {
+        self.allow_method(EapMethod::WSC)
+    }
+
+    pub fn allow_gtc(&mut self) -> &mut Self {
+        self.allow_method(EapMethod::GTC)
+    }
+
+    pub fn allow_psk(&mut self) -> &mut Self {
+        self.allow_method(EapMethod::PSK)
+    }
+
+    pub fn allow_gpsk(&mut self) -> &mut Self {
+        self.allow_method(EapMethod::GPSK)
+    }
+
+    pub fn allow_mschapv2(&mut self) -> &mut Self {
+        self.allow_method(EapMethod::MSCHAPV2)
+    }
+
+    pub fn allow_eke(&mut self) -> &mut Self {
+        self.allow_method(EapMethod::EKE)
+    }
+
+    pub fn allow_ikev2(&mut self) -> &mut Self {
+        self.allow_method(EapMethod::IKEV2)
+    }
+
+    /// TEAP with `inner` inside the tunnel, needs a TLS config
+    pub fn allow_teap(&mut self, inner: EapMethod) -> &mut Self {
+        self.phase2_methods = vec![inner];
+        self.allow_method(EapMethod::TEAP)
+    }
+
     fn allow_method(&mut self, method: EapMethod) -> &mut Self {
         if !self.method_priorities.contains(&method) {
             self.method_priorities.push(method);
@@ -55,19 +120,27 @@ impl EapServerBuilder {
     }
 }
 
+#[cfg(not(hostap_missing))]
 pub struct EapServer {
     interface: *mut eap_eapol_interface,
     callbacks: eapol_callbacks,
     eap_config: eap_config,
     state: *mut eap_sm,
     _tls_state: Option<EapServerTlsState>,
+    _wps_state: Option<EapServerWpsState>,
     users: HashMap<String, String>,
     method_priorities: Vec<EapMethod>,
+    phase2_methods: Vec<EapMethod>,
     response_buffer: Vec<u8>,
     final_status: Option<EapStepStatus>,
 }
 
+/// Stand-in without hostap, see [`crate::HOSTAP_AVAILABLE`]
+#[cfg(hostap_missing)]
+pub struct EapServer;
+
 // This is keep around to prevent the memory from being freed
+#[cfg(not(hostap_missing))]
 struct EapServerTlsState {
     tls_ctx: *mut c_void,
     _tls_params: Box<tls_connection_params>,
@@ -76,6 +149,7 @@ struct EapServerTlsState {
     _temp_files: Vec<tempfile::NamedTempFile>,
 }
 
+#[cfg(not(hostap_missing))]
 impl Drop for EapServerTlsState {
     fn drop(&mut self) {
         unsafe {
@@ -84,6 +158,21 @@ impl Drop for EapServerTlsState {
     }
 }
 
+// This is keep around, hostap points into it
+#[cfg(not(hostap_missing))]
+struct EapServerWpsState {
+    context: Box<wps_context>,
+}
+
+#[cfg(not(hostap_missing))]
+impl Drop for EapServerWpsState {
+    fn drop(&mut self) {
+        unsafe {
+            wps_registrar_deinit(self.context.registrar);
+        }
+    }
+}
+
 impl EapServer {
     pub fn builder() -> EapServerBuilder {
         EapServerBuilder::new()
@@ -103,13 +192,47 @@ impl EapServer {
         builder.build()
     }
 
+    pub fn new_wps(ssid: &[u8], network_key: &str, pin: Option<&str>) -> Box<EapServer> {
+        let mut builder = EapServerBuilder::new();
+        builder.set_wps(ssid, network_key, pin);
+        builder.allow_wsc();
+        builder.build()
+    }
+}
+
+#[cfg(hostap_missing)]
+impl EapServer {
+    fn init(_builder: EapServerBuilder) -> Box<Self> {
+        panic!("hostap submodule is not checked out")
+    }
+}
+
+#[cfg(hostap_missing)]
+impl EapWrapper for Box<EapServer> {
+    fn receive(&mut self, _buffer: &[u8]) {}
+
+    fn step(&mut self) -> EapStepResult<'_> {
+        unreachable!()
+    }
+}
+
+#[cfg(not(hostap_missing))]
+impl EapServer {
     fn init(builder: EapServerBuilder) -> Box<Self> {
         SERVER_INIT.call_once(|| unsafe {
             wpa_debug_level = 0;
 
             assert!(eap_server_identity_register() == 0);
             assert!(eap_server_md5_register() == 0);
+            assert!(eap_server_mschapv2_register() == 0);
+            assert!(eap_server_eke_register() == 0);
+            assert!(eap_server_ikev2_register() == 0);
             assert!(eap_server_tls_register() == 0);
+            assert!(eap_server_wsc_register() == 0);
+            assert!(eap_server_gtc_register() == 0);
+            assert!(eap_server_psk_register() == 0);
+            assert!(eap_server_gpsk_register() == 0);
+            assert!(eap_server_teap_register() == 0);
         });
 
         let callbacks: eapol_callbacks = eapol_callbacks {
@@ -125,6 +248,11 @@ impl EapServer {
         let mut eap_config: eap_config = unsafe { std::mem::zeroed() };
         eap_config.eap_server = 1;
 
+        // ID_S of the methods that name the server, e.g. EAP-PSK
+        unsafe {
+            (eap_config.server_id, eap_config.server_id_len) = util::malloc_str(SERVER_ID);
+        }
+
         // Init Tls
         // Note: Cannot free builder.tls_config as it used by tls config.
         let tls_state = if let Some(tls) = builder.tls_config {
@@ -163,14 +291,81 @@ impl EapServer {
             None
         };
 
+        // TEAP refuses to start without a PAC-Opaque key and an A-ID, even
+        // though no PAC is provisioned
+        if builder.method_priorities.contains(&EapMethod::TEAP) {
+            unsafe {
+                eap_config.pac_opaque_encr_key = util::malloc_str("0123456789abcdef").0;
+                (eap_config.eap_fast_a_id, eap_config.eap_fast_a_id_len) =
+                    util::malloc_str(SERVER_ID);
+                eap_config.eap_fast_a_id_info = util::malloc_str(SERVER_ID).0 as _;
+            }
+        }
+
+        // Init WPS, as configured access point with internal registrar
+        let wps_state = if let Some(wps) = builder.wps_config {
+            util::init_eloop();
+
+            let mut context: Box<wps_context> = Box::new(unsafe { std::mem::zeroed() });
+            context.ap = 1;
+            context.wps_state = 2; // WPS_STATE_CONFIGURED
+            context.uuid = [0x11; 16];
+            context.dev.mac_addr = [0x02, 0, 0, 0, 0, 0x11];
+            context.dev.pri_dev_type = [0x00, 0x06, 0x00, 0x50, 0xf2, 0x04, 0x00, 0x01];
+            context.dev.rf_bands = WPS_RF_24GHZ as _;
+            context.config_methods = (WPS_CONFIG_DISPLAY | WPS_CONFIG_VIRT_PUSHBUTTON) as _;
+            context.ssid[..wps.ssid.len()].copy_from_slice(&wps.ssid);
+            context.ssid_len = wps.ssid.len();
+            context.auth_types = WPS_AUTH_WPA2PSK as _;
+            context.encr_types = WPS_ENCR_AES as _;
+            context.encr_types_rsn = WPS_ENCR_AES as _;
+            unsafe {
+                context.dev.device_name = util::malloc_str("hostapd").0 as _;
+                context.dev.manufacturer = util::malloc_str("hostap").0 as _;
+                (context.network_key, context.network_key_len) = util::malloc_str(&wps.network_key);
+            }
+
+            let registrar_config: wps_registrar_config = unsafe { std::mem::zeroed() };
+            unsafe {
+                context.registrar = wps_registrar_init(context.as_mut(), &registrar_config);
+                assert!(!context.registrar.is_null());
+
+                match &wps.pin {
+                    Some(pin) => assert_eq!(
+                        wps_registrar_add_pin(
+                            context.registrar,
+                            std::ptr::null(),
+                            std::ptr::null(),
+                            pin.as_ptr(),
+                            pin.len(),
+                            0,
+                        ),
+                        0
+                    ),
+                    None => assert_eq!(
+                        wps_registrar_button_pushed(context.registrar, std::ptr::null()),
+                        0
+                    ),
+                }
+            }
+
+            eap_config.wps = context.as_mut();
+
+            Some(EapServerWpsState { context })
+        } else {
+            None
+        };
+
         let mut me = Box::new(Self {
             interface: std::ptr::null_mut(),
             callbacks,
             eap_config,
             state: std::ptr::null_mut(),
             _tls_state: tls_state,
+            _wps_state: wps_state,
             users: builder.passwords,
             method_priorities: builder.method_priorities,
+            phase2_methods: builder.phase2_methods,
             response_buffer: vec![],
             final_status: None,
         });
@@ -195,7 +390,7 @@ impl EapServer {
         ctx: *mut c_void,
         identity: *const u8,
         identity_len: usize,
-        _phase2: c_int,
+        phase2: c_int,
         user: *mut eap_user,
     ) -> i32 {
         let me = &mut *(ctx as *mut Self);
@@ -209,23 +404,36 @@ impl EapServer {
         let identity = String::from_utf8_lossy(identity);
 
         let password = me.users.get(&identity.to_string());
-        for (i, meth) in me.method_priorities.iter().enumerate() {
+        let methods = match phase2 {
+            0 => &me.method_priorities,
+            _ => &me.phase2_methods,
+        };
+        let methods = methods.iter().filter_map(|meth| match meth {
+            EapMethod::TLS => Some((EAP_VENDOR_IETF, EapType_EAP_TYPE_TLS)),
+            EapMethod::TEAP => Some((EAP_VENDOR_IETF, EapType_EAP_TYPE_TEAP)),
+            EapMethod::WSC => Some((EAP_VENDOR_WFA, 1)), // EAP_VENDOR_TYPE_WSC
+            // Password based methods are only offered to known users
+            _ if password.is_none() => None,
+            EapMethod::MD5 => Some((EAP_VENDOR_IETF, EapType_EAP_TYPE_MD5)),
+            EapMethod::GTC => Some((EAP_VENDOR_IETF, EapType_EAP_TYPE_GTC)),
+            EapMethod::PSK => Some((EAP_VENDOR_IETF, EapType_EAP_TYPE_PSK)),
+            EapMethod::GPSK => Some((EAP_VENDOR_IETF, EapType_EAP_TYPE_GPSK)),
+            EapMethod::MSCHAPV2 => Some((EAP_VENDOR_IETF, EapType_EAP_TYPE_MSCHAPV2)),
+            EapMethod::EKE => Some((EAP_VENDOR_IETF, EapType_EAP_TYPE_EKE)),
+            EapMethod::IKEV2 => Some((EAP_VENDOR_IETF, EapType_EAP_TYPE_IKEV2)),
+        });
+        for (i, (vendor, method)) in methods.enumerate() {
             assert!(i < 8); // max 8 methods, else out of bounds
 
-            match meth {
-                EapMethod::MD5 => {
-                    if let Some(password) = password {
-                        unsafe {
-                            (*user).methods[i].vendor = EAP_VENDOR_IETF as _;
-                            (*user).methods[i].method = EapType_EAP_TYPE_MD5 as _;
-                            ((*user).password, (*user).password_len) = util::malloc_str(password);
-                        }
-                    }
-                }
-                EapMethod::TLS => unsafe {
-                    (*user).methods[i].vendor = EAP_VENDOR_IETF as _;
-                    (*user).methods[i].method = EapType_EAP_TYPE_TLS as _;
-                },
+            unsafe {
+                (*user).methods[i].vendor = vendor as _;
+                (*user).methods[i].method = method as _;
+            }
+        }
+
+        if let Some(password) = password {
+            unsafe {
+                ((*user).password, (*user).password_len) = util::malloc_str(password);
             }
         }
 
@@ -238,6 +446,7 @@ impl EapServer {
     }
 }
 
+#[cfg(not(hostap_missing))]
 impl EapWrapper for Box<EapServer> {
     fn receive(&mut self, buffer: &[u8]) {
         unsafe {
@@ -306,6 +515,7 @@ impl EapWrapper for Box<EapServer> {
     }
 }
 
+#[cfg(not(hostap_missing))]
 impl Drop for EapServer {
     fn drop(&mut self) {
         unsafe {
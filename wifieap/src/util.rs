@@ -1,8 +1,10 @@
-use std::{ffi::c_void, io::Write};
+use std::{ffi::c_void, io::Write, sync::Once};
 
 use tempfile::NamedTempFile;
 
-use crate::peer::{malloc, memccpy};
+use crate::peer::{eloop_init, malloc, memccpy};
+
+static ELOOP_INIT: Once = Once::new();
 
 /// Converts a str into a null-terminated C string on the heap.
 /// The returned length does not include the null terminator.
@@ -27,3 +29,11 @@ pub fn create_tempfile(contents: &[u8], registry: &mut Vec<NamedTempFile>) -> *m
     registry.push(file);
     ptr as _
 }
+
+/// WPS registers timeouts (e.g. the PBC walk time), so the event loop has to
+/// be initialized once for peers and servers, even though it is never run.
+pub fn init_eloop() {
+    ELOOP_INIT.call_once(|| unsafe {
+        assert!(eloop_init() == 0);
+    });
+}